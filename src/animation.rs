@@ -0,0 +1,233 @@
+use glam::{ Mat4, Quat, Vec3 };
+use std::sync::Arc;
+
+/// Local transform of one joint relative to its parent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JointPose {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl JointPose {
+    pub const IDENTITY: JointPose = JointPose { translation: Vec3::ZERO, rotation: Quat::IDENTITY, scale: Vec3::ONE };
+
+    pub fn matrix(&self) -> Mat4 { Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation) }
+
+    /// Rotations take the shorter way round.
+    pub fn lerp(&self, other: &JointPose, t: f32) -> JointPose {
+        JointPose { translation: self.translation.lerp(other.translation, t), rotation: self.rotation.slerp(other.rotation, t),
+                    scale: self.scale.lerp(other.scale, t) }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Joint {
+    pub name: String,
+    pub parent: Option<usize>,
+    /// Where the joint sits when nothing animates it.
+    pub rest: JointPose,
+}
+
+/// Joint hierarchy of a skin. `inverse_bind` takes model space into each joint's space as it
+/// was when the mesh was bound, so a joint in its bind pose leaves its vertices where they are.
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+    pub inverse_bind: Vec<Mat4>,
+    /// Parents before children.
+    order: Vec<usize>,
+}
+
+impl Skeleton {
+    pub fn new(joints: Vec<Joint>, inverse_bind: Vec<Mat4>) -> Self {
+        assert_eq!(joints.len(), inverse_bind.len(), "one inverse bind matrix per joint");
+        let mut order = Vec::with_capacity(joints.len());
+        let mut placed = vec![false; joints.len()];
+        //repeated sweeps, hierarchies are shallow and this runs once per skeleton
+        while order.len() < joints.len() {
+            let before = order.len();
+            for (i, joint) in joints.iter().enumerate() {
+                if !placed[i] && joint.parent.map_or(true, |p| placed[p]) {
+                    placed[i] = true;
+                    order.push(i);
+                }
+            }
+            assert!(order.len() > before, "skeleton has a cycle");
+        }
+        Skeleton { joints, inverse_bind, order }
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> { self.joints.iter().position(|j| j.name == name) }
+
+    pub fn rest_pose(&self) -> Pose { Pose { joints: self.joints.iter().map(|j| j.rest).collect(), weights: Vec::new() } }
+
+    /// Model space transform of every joint in `pose`.
+    pub fn model_space(&self, pose: &Pose) -> Vec<Mat4> {
+        let mut model = vec![Mat4::IDENTITY; self.joints.len()];
+        for &i in &self.order {
+            let local = pose.joints[i].matrix();
+            model[i] = match self.joints[i].parent { Some(p) => model[p] * local, None => local };
+        }
+        model
+    }
+
+    /// What the vertex shader skins with: each joint's model space transform after its
+    /// inverse bind matrix.
+    pub fn palette(&self, pose: &Pose) -> Vec<Mat4> {
+        self.model_space(pose).into_iter().zip(&self.inverse_bind).map(|(m, inverse_bind)| m * *inverse_bind).collect()
+    }
+}
+
+/// Local transforms for every joint of a skeleton, in its order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pose {
+    pub joints: Vec<JointPose>,
+    /// Morph target weights by channel, see `Keys::Weights`. Channels nothing animated are empty.
+    pub weights: Vec<Vec<f32>>,
+}
+
+impl Pose {
+    /// Moves `weight` of the way towards `other`.
+    pub fn blend(&mut self, other: &Pose, weight: f32) {
+        for (a, b) in self.joints.iter_mut().zip(&other.joints) { *a = a.lerp(b, weight); }
+        self.blend_weights(other, weight);
+    }
+
+    /// Just the morph target weights of `blend`; missing ones count as 0.
+    pub fn blend_weights(&mut self, other: &Pose, weight: f32) {
+        if self.weights.len() < other.weights.len() { self.weights.resize(other.weights.len(), Vec::new()); }
+        for (a, b) in self.weights.iter_mut().zip(&other.weights) {
+            if a.len() < b.len() { a.resize(b.len(), 0.0); }
+            for (i, a) in a.iter_mut().enumerate() { *a += (b.get(i).copied().unwrap_or(0.0) - *a) * weight; }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation { Step, Linear }
+
+#[derive(Clone, Debug)]
+pub enum Keys {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+    /// Every morph target's weight per key, for the channel in the track's `joint`.
+    Weights(Vec<Vec<f32>>),
+}
+
+/// Keyframes for one property of one joint, or for a set of morph target weights.
+#[derive(Clone, Debug)]
+pub struct Track {
+    /// The joint, or the morph channel for `Keys::Weights`.
+    pub joint: usize,
+    /// Seconds, ascending, one per key.
+    pub times: Vec<f32>,
+    pub keys: Keys,
+    pub interpolation: Interpolation,
+}
+
+impl Track {
+    /// The two keys around `time` and how far between them it is; clamped at either end.
+    fn segment(&self, time: f32) -> (usize, usize, f32) {
+        let last = self.times.len() - 1;
+        let next = self.times.partition_point(|&t| t <= time);
+        if next == 0 { return (0, 0, 0.0); }
+        if next > last { return (last, last, 0.0); }
+        let (t0, t1) = (self.times[next - 1], self.times[next]);
+        let t = match self.interpolation { Interpolation::Step => 0.0, Interpolation::Linear => (time - t0) / (t1 - t0).max(1e-6) };
+        (next - 1, next, t)
+    }
+
+    fn apply(&self, time: f32, pose: &mut Pose) {
+        if self.times.is_empty() { return; }
+        let (a, b, t) = self.segment(time);
+        if let Keys::Weights(k) = &self.keys {
+            if pose.weights.len() <= self.joint { pose.weights.resize(self.joint + 1, Vec::new()); }
+            pose.weights[self.joint] = k[a].iter().zip(&k[b]).map(|(a, b)| a + (b - a) * t).collect();
+            return;
+        }
+        let joint = match pose.joints.get_mut(self.joint) { Some(j) => j, None => return };
+        match &self.keys {
+            Keys::Translation(k) => joint.translation = k[a].lerp(k[b], t),
+            Keys::Rotation(k) => joint.rotation = k[a].slerp(k[b], t).normalize(),
+            Keys::Scale(k) => joint.scale = k[a].lerp(k[b], t),
+            Keys::Weights(_) => {}
+        }
+    }
+}
+
+/// Tracks for some of a skeleton's joints; the others keep whatever pose they're sampled over.
+#[derive(Clone, Debug)]
+pub struct Clip {
+    pub name: String,
+    /// Seconds.
+    pub duration: f32,
+    pub tracks: Vec<Track>,
+}
+
+impl Clip {
+    /// Overwrites the animated properties of `pose` with their values at `time`.
+    pub fn sample(&self, time: f32, pose: &mut Pose) {
+        for track in &self.tracks { track.apply(time, pose); }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PlayingClip {
+    pub clip: Arc<Clip>,
+    /// Seconds into the clip.
+    pub time: f32,
+    /// 1 is as authored, negative plays backwards.
+    pub speed: f32,
+    /// Relative to the other clips playing; the weights needn't add up to 1.
+    pub weight: f32,
+    pub looping: bool,
+}
+
+impl PlayingClip {
+    pub fn new(clip: Arc<Clip>) -> Self { PlayingClip { clip, time: 0.0, speed: 1.0, weight: 1.0, looping: true } }
+
+    pub fn finished(&self) -> bool {
+        !self.looping && if self.speed < 0.0 { self.time <= 0.0 } else { self.time >= self.clip.duration }
+    }
+}
+
+/// Plays clips on a skeleton and blends them by weight, each sampled over the rest pose.
+#[derive(Clone, Debug, Default)]
+pub struct AnimationPlayer {
+    pub clips: Vec<PlayingClip>,
+}
+
+impl AnimationPlayer {
+    /// Replaces whatever was playing.
+    pub fn play(&mut self, clip: Arc<Clip>) { self.clips = vec![PlayingClip::new(clip)]; }
+
+    /// Plays `clip` alongside the others, blended in at `weight`.
+    pub fn add(&mut self, clip: Arc<Clip>, weight: f32) { self.clips.push(PlayingClip { weight, ..PlayingClip::new(clip) }); }
+
+    pub fn is_playing(&self) -> bool { !self.clips.is_empty() }
+
+    /// Advances every clip by `dt` seconds. Looping clips wrap, the others stop at their end.
+    pub fn update(&mut self, dt: f32) {
+        for c in &mut self.clips {
+            c.time += dt * c.speed;
+            c.time = if c.looping && c.clip.duration > 0.0 { c.time.rem_euclid(c.clip.duration) } else { c.time.clamp(0.0, c.clip.duration) };
+        }
+    }
+
+    /// The weighted average of every playing clip; the rest pose if none are.
+    pub fn pose(&self, skeleton: &Skeleton) -> Pose { self.sample(skeleton.rest_pose()) }
+
+    /// `pose` over any rest pose; an empty one for meshes animating only morph targets.
+    pub fn sample(&self, rest: Pose) -> Pose {
+        let mut pose = rest.clone();
+        let mut total = 0.0;
+        for c in self.clips.iter().filter(|c| c.weight > 0.0) {
+            let mut sampled = rest.clone();
+            c.clip.sample(c.time, &mut sampled);
+            total += c.weight;
+            pose.blend(&sampled, c.weight / total);
+        }
+        pose
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::{ HashMap, HashSet };
+use std::sync::Arc;
+use crate::animation::{ Clip, Pose, Skeleton };
+
+/// Values the game sets and transitions test, by name. Unset values read as 0.
+#[derive(Clone, Debug, Default)]
+pub struct Parameters {
+    values: HashMap<String, f32>,
+    /// Set until a transition that tests them fires.
+    triggers: HashSet<String>,
+}
+
+impl Parameters {
+    pub fn set(&mut self, name: &str, value: f32) { self.values.insert(name.to_string(), value); }
+    pub fn set_bool(&mut self, name: &str, value: bool) { self.set(name, value as u32 as f32); }
+    pub fn get(&self, name: &str) -> f32 { self.values.get(name).copied().unwrap_or(0.0) }
+    pub fn trigger(&mut self, name: &str) { self.triggers.insert(name.to_string()); }
+}
+
+/// How clips combine into a state's pose.
+#[derive(Clone, Debug)]
+pub enum BlendTree {
+    Clip(Arc<Clip>),
+    /// Children placed along a parameter at ascending thresholds; the two either side of its
+    /// value blend, walk into run by speed for instance.
+    Blend1d { parameter: String, children: Vec<(f32, BlendTree)> },
+}
+
+impl BlendTree {
+    /// Weight of every clip under the tree for `params`; zero weights left out.
+    fn weights(&self, params: &Parameters, scale: f32, out: &mut Vec<(Arc<Clip>, f32)>) {
+        match self {
+            BlendTree::Clip(clip) => if scale > 0.0 { out.push((clip.clone(), scale)) },
+            BlendTree::Blend1d { parameter, children } => {
+                if children.is_empty() { return; }
+                let x = params.get(parameter);
+                let next = children.partition_point(|(t, _)| *t <= x);
+                if next == 0 { return children[0].1.weights(params, scale, out); }
+                if next == children.len() { return children[next - 1].1.weights(params, scale, out); }
+                let ((t0, a), (t1, b)) = (&children[next - 1], &children[next]);
+                let t = (x - t0) / (t1 - t0).max(1e-6);
+                a.weights(params, scale * (1.0 - t), out);
+                b.weights(params, scale * t, out);
+            }
+        }
+    }
+}
+
+/// One state's clips, advanced together: they share a phase, so a walk and a run of different
+/// lengths blended together keep their feet in step.
+fn sample(weights: &[(Arc<Clip>, f32)], phase: f32, skeleton: &Skeleton) -> Pose {
+    let rest = skeleton.rest_pose();
+    let mut pose = rest.clone();
+    let mut total = 0.0;
+    for (clip, weight) in weights {
+        let mut sampled = rest.clone();
+        clip.sample(phase * clip.duration, &mut sampled);
+        total += weight;
+        pose.blend(&sampled, weight / total);
+    }
+    pose
+}
+
+/// Seconds one pass through the state takes, the weighted clip lengths.
+fn duration(weights: &[(Arc<Clip>, f32)]) -> f32 {
+    let total: f32 = weights.iter().map(|(_, w)| w).sum();
+    weights.iter().map(|(c, w)| c.duration * w).sum::<f32>() / total.max(1e-6)
+}
+
+#[derive(Clone, Debug)]
+pub struct State {
+    pub name: String,
+    pub tree: BlendTree,
+    pub speed: f32,
+    /// Holds on the last frame instead of wrapping.
+    pub once: bool,
+}
+
+impl State {
+    pub fn new(name: &str, tree: BlendTree) -> Self { State { name: name.to_string(), tree, speed: 1.0, once: false } }
+}
+
+#[derive(Clone, Debug)]
+pub enum Condition {
+    Greater(String, f32),
+    Less(String, f32),
+    /// The parameter is above 0.5, as `Parameters::set_bool` leaves it.
+    True(String),
+    False(String),
+    /// Consumed when the transition fires.
+    Trigger(String),
+    /// The current state made it through once; for states that play `once`, or to leave a
+    /// looping one at its end.
+    Finished,
+}
+
+#[derive(Clone, Debug)]
+pub struct Transition {
+    /// None goes from any state but the target itself.
+    pub from: Option<usize>,
+    pub to: usize,
+    /// Every one has to hold.
+    pub conditions: Vec<Condition>,
+    /// Seconds of crossfade.
+    pub duration: f32,
+}
+
+struct Fade {
+    state: usize,
+    phase: f32,
+    elapsed: f32,
+    duration: f32,
+}
+
+/// States with transitions between them, checked in order each update; the first whose
+/// conditions hold crossfades into its target.
+pub struct StateMachine {
+    pub states: Vec<State>,
+    pub transitions: Vec<Transition>,
+    current: usize,
+    /// 0..1 through the current state, see `advance` for after it.
+    phase: f32,
+    /// The state being faded out of, which keeps playing meanwhile.
+    fade: Option<Fade>,
+}
+
+impl StateMachine {
+    /// Starts in the first state added.
+    pub fn new() -> Self { StateMachine { states: Vec::new(), transitions: Vec::new(), current: 0, phase: 0.0, fade: None } }
+
+    pub fn add_state(&mut self, state: State) -> usize { self.states.push(state); self.states.len() - 1 }
+
+    pub fn add_transition(&mut self, from: Option<usize>, to: usize, conditions: Vec<Condition>, duration: f32) {
+        self.transitions.push(Transition { from, to, conditions, duration });
+    }
+
+    pub fn current(&self) -> &State { &self.states[self.current] }
+
+    /// Jumps straight to a state, without a fade.
+    pub fn set_state(&mut self, state: usize) {
+        self.current = state;
+        self.phase = 0.0;
+        self.fade = None;
+    }
+
+    fn holds(&self, condition: &Condition, params: &Parameters) -> bool {
+        match condition {
+            Condition::Greater(p, v) => params.get(p) > *v,
+            Condition::Less(p, v) => params.get(p) < *v,
+            Condition::True(p) => params.get(p) > 0.5,
+            Condition::False(p) => params.get(p) <= 0.5,
+            Condition::Trigger(t) => params.triggers.contains(t),
+            Condition::Finished => self.phase >= 1.0,
+        }
+    }
+
+    fn advance(&self, state: usize, phase: f32, dt: f32, params: &Parameters) -> f32 {
+        let state = &self.states[state];
+        let mut weights = Vec::new();
+        state.tree.weights(params, 1.0, &mut weights);
+        let phase = phase + dt * state.speed / duration(&weights).max(1e-3);
+        //looping states stay in 1..2 once they wrapped, finished from then on; sampled by the fraction
+        if state.once { phase.min(1.0) } else if phase >= 1.0 { phase.fract() + 1.0 } else { phase }
+    }
+
+    pub fn update(&mut self, dt: f32, params: &mut Parameters) {
+        if self.states.is_empty() { return; }
+        let fired = self.transitions.iter().position(|t| t.from.map_or(t.to != self.current, |f| f == self.current)
+                                                       && t.conditions.iter().all(|c| self.holds(c, params)));
+        if let Some(i) = fired {
+            let t = self.transitions[i].clone();
+            for c in &t.conditions {
+                if let Condition::Trigger(name) = c { params.triggers.remove(name); }
+            }
+            self.fade = (t.duration > 0.0).then(|| Fade { state: self.current, phase: self.phase, elapsed: 0.0, duration: t.duration });
+            self.current = t.to;
+            self.phase = 0.0;
+        }
+        self.phase = self.advance(self.current, self.phase, dt, params);
+        if let Some(mut fade) = self.fade.take() {
+            fade.phase = self.advance(fade.state, fade.phase, dt, params);
+            fade.elapsed += dt;
+            if fade.elapsed < fade.duration { self.fade = Some(fade); }
+        }
+    }
+
+    fn state_pose(&self, state: usize, phase: f32, params: &Parameters, skeleton: &Skeleton) -> Pose {
+        let mut weights = Vec::new();
+        self.states[state].tree.weights(params, 1.0, &mut weights);
+        let phase = if self.states[state].once { phase.min(1.0) } else if phase >= 1.0 { phase.fract() } else { phase };
+        sample(&weights, phase, skeleton)
+    }
+
+    pub fn pose(&self, params: &Parameters, skeleton: &Skeleton) -> Pose {
+        if self.states.is_empty() { return skeleton.rest_pose(); }
+        let mut pose = self.state_pose(self.current, self.phase, params, skeleton);
+        if let Some(fade) = &self.fade {
+            let mut from = self.state_pose(fade.state, fade.phase, params, skeleton);
+            from.blend(&pose, fade.elapsed / fade.duration);
+            pose = from;
+        }
+        pose
+    }
+}
+
+/// Per joint weights a layer applies with.
+#[derive(Clone, Debug)]
+pub struct Mask {
+    pub weights: Vec<f32>,
+}
+
+impl Mask {
+    /// `joint` and everything below it, the upper body from the spine for instance.
+    pub fn from_joint(skeleton: &Skeleton, joint: usize) -> Self {
+        let weights = (0..skeleton.joints.len()).map(|mut j| {
+            loop {
+                if j == joint { return 1.0; }
+                match skeleton.joints[j].parent { Some(p) => j = p, None => return 0.0 }
+            }
+        }).collect();
+        Mask { weights }
+    }
+}
+
+pub struct Layer {
+    pub machine: StateMachine,
+    pub weight: f32,
+    /// None covers every joint.
+    pub mask: Option<Mask>,
+}
+
+/// State machines layered over each other, each later one blended onto what's below by its
+/// weight and mask; shooting on the upper body while the legs keep running. Set on a `Skin`,
+/// it drives the pose in place of the skin's player.
+pub struct AnimationGraph {
+    pub layers: Vec<Layer>,
+    pub parameters: Parameters,
+}
+
+impl AnimationGraph {
+    /// The base layer, applied fully to every joint.
+    pub fn new(base: StateMachine) -> Self {
+        AnimationGraph { layers: vec![Layer { machine: base, weight: 1.0, mask: None }], parameters: Parameters::default() }
+    }
+
+    pub fn add_layer(&mut self, machine: StateMachine, weight: f32, mask: Option<Mask>) -> usize {
+        self.layers.push(Layer { machine, weight, mask });
+        self.layers.len() - 1
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for layer in &mut self.layers { layer.machine.update(dt, &mut self.parameters); }
+    }
+
+    pub fn pose(&self, skeleton: &Skeleton) -> Pose {
+        let mut pose = skeleton.rest_pose();
+        for layer in self.layers.iter().filter(|l| l.weight > 0.0) {
+            let top = layer.machine.pose(&self.parameters, skeleton);
+            for (i, (a, b)) in pose.joints.iter_mut().zip(&top.joints).enumerate() {
+                let w = layer.weight * layer.mask.as_ref().map_or(1.0, |m| m.weights.get(i).copied().unwrap_or(0.0));
+                *a = a.lerp(b, w);
+            }
+            //masks are per joint, morph weights just take the layer's
+            pose.blend_weights(&top, layer.weight);
+        }
+        pose
+    }
+}
@@ -0,0 +1,369 @@
+use rodio::{ OutputStream, OutputStreamHandle, Sink, Decoder, Source };
+use glam::Vec3;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicU32, Ordering };
+use std::time::Duration;
+use crate::time::Time;
+use crate::camera::Camera;
+use crate::scene::{ Scene, EntityId };
+use crate::bvh::Ray;
+
+/// Encoded sound file kept in memory. Decoded afresh for every playback, so one sound can
+/// play any number of times at once.
+pub struct Sound {
+    data: Arc<[u8]>,
+}
+
+impl Sound {
+    /// Anything rodio decodes: wav, ogg vorbis, flac, mp3.
+    #[profiling::function]
+    pub fn load(path: impl AsRef<Path>) -> Arc<Sound> {
+        let data = std::fs::read(path.as_ref()).unwrap_or_else(|e| panic!("failed loading {:?}: {}", path.as_ref(), e));
+        Self::from_bytes(data)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Arc<Sound> {
+        //fail here rather than on the first play
+        Decoder::new(Cursor::new(data.clone())).expect("unsupported sound format");
+        Arc::new(Sound { data: data.into() })
+    }
+
+    fn decoder(&self) -> Decoder<Cursor<Arc<[u8]>>> { Decoder::new(Cursor::new(self.data.clone())).unwrap() }
+}
+
+pub type BusId = usize;
+/// Every other bus feeds into it.
+pub const MASTER: BusId = 0;
+
+/// A group of sounds mixed at one volume: music, effects, dialogue.
+pub struct Bus {
+    pub name: String,
+    pub volume: f32,
+    pub muted: bool,
+}
+
+/// How to play a sound.
+#[derive(Clone, Copy, Debug)]
+pub struct PlaySettings {
+    pub volume: f32,
+    /// Playback rate; also shifts the pitch.
+    pub speed: f32,
+    pub looping: bool,
+    pub bus: BusId,
+}
+
+impl Default for PlaySettings {
+    fn default() -> Self { PlaySettings { volume: 1.0, speed: 1.0, looping: false, bus: MASTER } }
+}
+
+/// Refers to one playback. Goes stale once the sound finishes or is stopped; calls through a
+/// stale handle do nothing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SoundHandle(u64);
+
+struct Playing {
+    sink: Sink,
+    volume: f32,
+    bus: BusId,
+    /// By `pause`, as opposed to by the clock.
+    paused: bool,
+}
+
+/// Sound component: plays from the entity's position once it is spawned with one, until the
+/// entity or the component goes away.
+#[derive(Clone)]
+pub struct AudioEmitter {
+    pub sound: Arc<Sound>,
+    pub volume: f32,
+    pub looping: bool,
+    pub bus: BusId,
+    /// Full volume up to here, then falls off inversely with distance.
+    pub min_distance: f32,
+    /// Silent from here on.
+    pub max_distance: f32,
+    /// How fast it falls off past `min_distance`; 1 is physical.
+    pub rolloff: f32,
+    /// Scales the pitch shift from relative motion; 0 turns it off.
+    pub doppler: f32,
+    /// Muffled while scene geometry is between it and the listener. One ray a frame.
+    pub occlusion: bool,
+}
+
+impl AudioEmitter {
+    pub fn new(sound: Arc<Sound>) -> Self {
+        AudioEmitter { sound, volume: 1.0, looping: true, bus: MASTER, min_distance: 1.0, max_distance: 50.0, rolloff: 1.0, doppler: 1.0, occlusion: false }
+    }
+
+    fn attenuation(&self, distance: f32) -> f32 {
+        if distance >= self.max_distance { return 0.0; }
+        let min = self.min_distance.max(1e-3);
+        min / (min + self.rolloff * (distance.max(min) - min))
+    }
+}
+
+const SPEED_OF_SOUND: f32 = 343.0;
+/// Low pass cutoff in hz with nothing in the way, and fully occluded.
+const OPEN_CUTOFF: f32 = 20000.0;
+const OCCLUDED_CUTOFF: f32 = 1000.0;
+/// Occluded emitters also lose this much volume.
+const OCCLUDED_GAIN: f32 = 0.5;
+/// Per second, so walking behind a wall muffles rather than clicks.
+const OCCLUSION_RATE: f32 = 8.0;
+
+/// Parameters a spatial source picks up while it plays, written from `update`.
+struct SpatialParams {
+    left: AtomicU32,
+    right: AtomicU32,
+    cutoff: AtomicU32,
+}
+
+impl SpatialParams {
+    fn new() -> Arc<Self> { Arc::new(SpatialParams { left: AtomicU32::new(0), right: AtomicU32::new(0), cutoff: AtomicU32::new(OPEN_CUTOFF.to_bits()) }) }
+    fn get(a: &AtomicU32) -> f32 { f32::from_bits(a.load(Ordering::Relaxed)) }
+    fn set(a: &AtomicU32, v: f32) { a.store(v.to_bits(), Ordering::Relaxed) }
+}
+
+/// Downmixes to mono, then pans to stereo through a one pole low pass.
+struct Spatial<S: Source<Item = f32>> {
+    input: S,
+    params: Arc<SpatialParams>,
+    frame: [f32; 2],
+    next: usize,
+    low: [f32; 2],
+    cutoff: f32,
+    alpha: f32,
+}
+
+impl<S: Source<Item = f32>> Spatial<S> {
+    fn new(input: S, params: Arc<SpatialParams>) -> Self { Spatial { input, params, frame: [0.0; 2], next: 2, low: [0.0; 2], cutoff: 0.0, alpha: 1.0 } }
+}
+
+impl<S: Source<Item = f32>> Iterator for Spatial<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.next == 2 {
+            let channels = self.input.channels().max(1);
+            let mut mono = 0.0;
+            for _ in 0..channels { mono += self.input.next()?; }
+            mono /= channels as f32;
+            let cutoff = SpatialParams::get(&self.params.cutoff);
+            if cutoff != self.cutoff {
+                self.cutoff = cutoff;
+                self.alpha = 1.0 - (-std::f32::consts::TAU * cutoff / self.input.sample_rate() as f32).exp();
+            }
+            let gains = [SpatialParams::get(&self.params.left), SpatialParams::get(&self.params.right)];
+            for ear in 0..2 {
+                self.low[ear] += self.alpha * (mono * gains[ear] - self.low[ear]);
+                self.frame[ear] = self.low[ear];
+            }
+            self.next = 0;
+        }
+        self.next += 1;
+        Some(self.frame[self.next - 1])
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Spatial<S> {
+    fn current_frame_len(&self) -> Option<usize> { None }
+    fn channels(&self) -> u16 { 2 }
+    fn sample_rate(&self) -> u32 { self.input.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
+
+/// An emitter's playback and what `update` remembers about it between frames.
+struct Emitting {
+    handle: SoundHandle,
+    params: Arc<SpatialParams>,
+    position: Vec3,
+    occlusion: f32,
+}
+
+/// Where sound is heard from, taken from the camera every `update`.
+struct Listener {
+    position: Vec3,
+    velocity: Vec3,
+    right: Vec3,
+}
+
+/// Sound output on the default device. Without one everything still works and stays silent,
+/// so headless machines and CI don't need audio. `update` once a frame drops finished sounds;
+/// dropping the `Audio` stops everything.
+pub struct Audio {
+    //the stream has to outlive every sink
+    _stream: Option<OutputStream>,
+    output: Option<OutputStreamHandle>,
+    buses: Vec<Bus>,
+    playing: HashMap<SoundHandle, Playing>,
+    next: u64,
+    /// Emitters that started, kept after a one shot ends so it doesn't start over.
+    emitters: HashMap<EntityId, Emitting>,
+    listener: Option<Listener>,
+}
+
+impl Audio {
+    pub fn new() -> Self {
+        let (stream, output) = match OutputStream::try_default() {
+            Ok((stream, output)) => (Some(stream), Some(output)),
+            Err(e) => { println!("No audio output, sound is off: {}", e); (None, None) }
+        };
+        let master = Bus { name: "master".into(), volume: 1.0, muted: false };
+        Audio { _stream: stream, output, buses: vec![master], playing: HashMap::new(), next: 1, emitters: HashMap::new(), listener: None }
+    }
+
+    pub fn add_bus(&mut self, name: &str) -> BusId {
+        self.buses.push(Bus { name: name.into(), volume: 1.0, muted: false });
+        self.buses.len() - 1
+    }
+
+    pub fn bus(&self, name: &str) -> Option<BusId> { self.buses.iter().position(|b| b.name == name) }
+
+    /// Changes apply to playing sounds at the next `update`. None for ids this `Audio` didn't hand out.
+    pub fn bus_mut(&mut self, id: BusId) -> Option<&mut Bus> { self.buses.get_mut(id) }
+
+    /// Sounds on unknown buses only go through the master.
+    fn bus_volume(&self, id: BusId) -> f32 {
+        let gain = |b: &Bus| if b.muted { 0.0 } else { b.volume };
+        let bus = if id == MASTER { 1.0 } else { self.buses.get(id).map_or(1.0, gain) };
+        gain(&self.buses[MASTER]) * bus
+    }
+
+    /// Plays `sound` and returns a handle to control it with.
+    pub fn play(&mut self, sound: &Sound, settings: PlaySettings) -> SoundHandle {
+        self.start(settings, |sink| match settings.looping {
+            true => sink.append(sound.decoder().repeat_infinite()),
+            false => sink.append(sound.decoder()),
+        })
+    }
+
+    fn start(&mut self, settings: PlaySettings, append: impl FnOnce(&Sink)) -> SoundHandle {
+        let handle = SoundHandle(self.next);
+        self.next += 1;
+        let output = match &self.output { Some(o) => o, None => return handle };
+        //the device can go away under a running stream; the handle is stale from the start then
+        let sink = match Sink::try_new(output) {
+            Ok(sink) => sink,
+            Err(e) => { println!("Failed to play a sound: {}", e); return handle; }
+        };
+        sink.set_volume(settings.volume * self.bus_volume(settings.bus));
+        sink.set_speed(settings.speed);
+        append(&sink);
+        self.playing.insert(handle, Playing { sink, volume: settings.volume, bus: settings.bus, paused: false });
+        handle
+    }
+
+    fn play_spatial(&mut self, emitter: &AudioEmitter, params: Arc<SpatialParams>) -> SoundHandle {
+        let settings = PlaySettings { volume: emitter.volume, speed: 1.0, looping: emitter.looping, bus: emitter.bus };
+        self.start(settings, |sink| match emitter.looping {
+            true => sink.append(Spatial::new(emitter.sound.decoder().repeat_infinite().convert_samples(), params)),
+            false => sink.append(Spatial::new(emitter.sound.decoder().convert_samples(), params)),
+        })
+    }
+
+    /// Fire and forget, for one shots nobody needs to stop.
+    pub fn play_once(&mut self, sound: &Sound, bus: BusId) {
+        self.play(sound, PlaySettings { bus, ..Default::default() });
+    }
+
+    pub fn stop(&mut self, handle: SoundHandle) {
+        if let Some(p) = self.playing.remove(&handle) { p.sink.stop(); }
+    }
+
+    pub fn pause(&mut self, handle: SoundHandle) {
+        if let Some(p) = self.playing.get_mut(&handle) { p.paused = true; p.sink.pause(); }
+    }
+
+    pub fn resume(&mut self, handle: SoundHandle) {
+        if let Some(p) = self.playing.get_mut(&handle) { p.paused = false; p.sink.play(); }
+    }
+
+    pub fn set_volume(&mut self, handle: SoundHandle, volume: f32) {
+        let bus_volume = match self.playing.get(&handle) { Some(p) => self.bus_volume(p.bus), None => return };
+        let p = self.playing.get_mut(&handle).unwrap();
+        p.volume = volume;
+        p.sink.set_volume(volume * bus_volume);
+    }
+
+    pub fn set_speed(&self, handle: SoundHandle, speed: f32) {
+        if let Some(p) = self.playing.get(&handle) { p.sink.set_speed(speed); }
+    }
+
+    pub fn is_playing(&self, handle: SoundHandle) -> bool { self.playing.contains_key(&handle) }
+
+    /// Once a frame, after the scene moved: forgets finished sounds, applies bus volumes, and
+    /// starts, stops and spatializes the scene's emitters as heard from `camera`. Pausing the
+    /// clock (`Time::scale` 0) pauses every sound with it.
+    pub fn update(&mut self, time: &Time, scene: &Scene, camera: &Camera) {
+        self.playing.retain(|_, p| !p.sink.empty());
+        self.update_emitters(time, scene, camera);
+        let clock_paused = time.scale == 0.0;
+        for p in self.playing.values() {
+            p.sink.set_volume(p.volume * self.bus_volume(p.bus));
+            let paused = clock_paused || p.paused;
+            if paused != p.sink.is_paused() {
+                if paused { p.sink.pause() } else { p.sink.play() }
+            }
+        }
+    }
+
+    fn update_emitters(&mut self, time: &Time, scene: &Scene, camera: &Camera) {
+        let gone: Vec<EntityId> = self.emitters.keys().copied().filter(|&id| scene.get(id).map_or(true, |e| e.emitter.is_none())).collect();
+        for id in gone {
+            let handle = self.emitters.remove(&id).unwrap().handle;
+            self.stop(handle);
+        }
+
+        let position = camera.position;
+        let forward = (camera.target - camera.position).normalize_or_zero();
+        let right = forward.cross(camera.up).normalize_or_zero();
+        let velocity = match &self.listener { Some(l) if time.delta > 0.0 => (position - l.position) / time.delta, _ => Vec3::ZERO };
+        self.listener = Some(Listener { position, velocity, right });
+        let listener = self.listener.as_ref().unwrap();
+
+        let mut started = Vec::new();
+        let mut updates = Vec::new();
+        for e in scene.entities.iter() {
+            let emitter = match &e.emitter { Some(em) => em, None => continue };
+            let position = e.position();
+            let state = match self.emitters.get_mut(&e.id) {
+                Some(s) => s,
+                None => { started.push((e.id, emitter.clone(), position)); continue }
+            };
+            if !self.playing.contains_key(&state.handle) { continue; }
+            let velocity = if time.delta > 0.0 { (position - state.position) / time.delta } else { Vec3::ZERO };
+            state.position = position;
+
+            let to = position - listener.position;
+            let distance = to.length();
+            let dir = if distance > 1e-4 { to / distance } else { Vec3::ZERO };
+            let blocked = emitter.occlusion && distance > 1e-4 && scene.raycast(&Ray { origin: listener.position, dir })
+                .map_or(false, |hit| hit.entity != e.id && hit.distance < distance);
+            let target = if blocked { 1.0 } else { 0.0 };
+            state.occlusion += (target - state.occlusion) * (1.0 - (-OCCLUSION_RATE * time.delta).exp());
+
+            //equal power pan on the listener's left-right axis
+            let pan = dir.dot(listener.right);
+            let angle = (pan + 1.0) * std::f32::consts::FRAC_PI_4;
+            let gain = emitter.attenuation(distance) * (1.0 - state.occlusion * (1.0 - OCCLUDED_GAIN));
+            SpatialParams::set(&state.params.left, angle.cos() * gain);
+            SpatialParams::set(&state.params.right, angle.sin() * gain);
+            SpatialParams::set(&state.params.cutoff, OPEN_CUTOFF + (OCCLUDED_CUTOFF - OPEN_CUTOFF) * state.occlusion);
+
+            //positive when closing in, from either end
+            let closing_listener = listener.velocity.dot(dir) * emitter.doppler;
+            let closing_emitter = -velocity.dot(dir) * emitter.doppler;
+            let pitch = ((SPEED_OF_SOUND + closing_listener) / (SPEED_OF_SOUND - closing_emitter).max(1.0)).clamp(0.5, 2.0);
+            updates.push((state.handle, pitch));
+        }
+        for (handle, pitch) in updates { self.set_speed(handle, pitch); }
+
+        for (id, emitter, position) in started {
+            let params = SpatialParams::new();
+            let handle = self.play_spatial(&emitter, params.clone());
+            self.emitters.insert(id, Emitting { handle, params, position, occlusion: 0.0 });
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use glam::Vec3;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::time::Instant;
+use crate::bvh::Aabb;
+use crate::camera::Camera;
+use crate::camera_path::{ CameraPath, Spline };
+use crate::scene::Scene;
+use crate::tween::Curve;
+
+/// Seconds of scene time per benchmark frame, whatever the frame really took, so every run
+/// renders the same frames.
+pub const STEP: f32 = 1.0 / 60.0;
+
+#[derive(Clone, Copy, Debug)]
+struct FrameSample {
+    /// The `GpuTimer`'s number for it.
+    frame: u64,
+    /// Wall clock to the next frame's `update`; None until then.
+    frame_ms: Option<f32>,
+    cpu_ms: f32,
+    gpu_ms: Option<f32>,
+}
+
+/// Flies the camera along a fixed path at a fixed step and times every frame, for comparing
+/// renderer performance between builds. Plays the scene's first camera path once, or circles
+/// the scene when it has none. Frame times are wall clock from one frame's `update` to the
+/// next; cpu times from `update` to `recorded`, the simulation and command recording; gpu times
+/// come from the `GpuTimer` a few frames late, matched up by frame number, and are missing
+/// where it has no timestamps or the run ended before they came in.
+pub struct Benchmark {
+    /// Frames rendered before recording, for pipelines and caches to settle.
+    pub warmup: u32,
+    /// Report stem; the .csv and .json go next to each other.
+    pub output: PathBuf,
+    path: CameraPath,
+    length: f32,
+    distance: f32,
+    frame: u32,
+    /// When this frame's `update` was.
+    start: Instant,
+    samples: Vec<FrameSample>,
+}
+
+/// Eight points around the scene's bounds, a little above, looking at the middle.
+fn orbit(scene: &Scene) -> CameraPath {
+    let bounds = scene.entities.iter().map(|e| e.world_bounds()).reduce(|a, b| a.union(&b))
+        .unwrap_or(Aabb { min: Vec3::splat(-5.0), max: Vec3::splat(5.0) });
+    let (center, radius) = (bounds.center(), (bounds.extent().length() * 0.5).max(1.0));
+    let mut path = CameraPath::new("benchmark orbit", Spline::CatmullRom);
+    path.points = (0..8).map(|i| {
+        let a = i as f32 / 8.0 * std::f32::consts::TAU;
+        center + Vec3::new(a.cos() * radius, radius * 0.4, a.sin() * radius)
+    }).collect();
+    path.looping = true;
+    path.look_at = Some(Curve::new(center));
+    path.speed = Curve::new(radius * std::f32::consts::TAU / 20.0);
+    path
+}
+
+impl Benchmark {
+    /// Fails on paths it would never get to the end of, or that have no length to time.
+    pub fn new(scene: &Scene, output: PathBuf) -> Result<Self, String> {
+        let path = scene.camera_paths.first().cloned().unwrap_or_else(|| orbit(scene));
+        let length = path.length();
+        if length <= 0.0 { return Err(format!("benchmark path {:?} has no length", path.name)); }
+        //the keys and between them, eases can dip below their keys
+        let stalls = path.speed.keys.iter().map(|k| k.value).chain((0..=256).map(|i| path.speed.sample(length * i as f32 / 256.0))).any(|s| s <= 0.0);
+        if stalls { return Err(format!("benchmark path {:?} stops along the way, its speed has to stay above 0", path.name)); }
+        Ok(Benchmark { warmup: 60, output, length, path, distance: 0.0, frame: 0, start: Instant::now(), samples: Vec::new() })
+    }
+
+    pub fn finished(&self) -> bool { self.distance >= self.length }
+
+    /// Once a frame before drawing: places the camera, ends the frame before's wall clock time
+    /// and takes the `GpuTimer`'s `last` for whichever frame it belongs to.
+    pub fn update(&mut self, camera: &mut Camera, gpu: Option<(u64, f32)>) {
+        let now = Instant::now();
+        if let Some(s) = self.samples.last_mut().filter(|s| s.frame_ms.is_none()) {
+            s.frame_ms = Some(now.duration_since(self.start).as_secs_f32() * 1000.0);
+        }
+        self.start = now;
+        //the same result comes back until a newer one is in, matching by frame keeps it once
+        if let Some((frame, ms)) = gpu {
+            if let Some(s) = self.samples.iter_mut().rev().find(|s| s.frame == frame) { s.gpu_ms = Some(ms); }
+        }
+        self.frame += 1;
+        //the camera holds at the start through the warmup
+        if self.frame > self.warmup { self.distance += self.path.speed.sample(self.distance).max(0.0) * STEP; }
+        let sample = self.path.sample(self.distance.min(self.length));
+        camera.position = sample.position;
+        camera.target = sample.target;
+    }
+
+    /// Once the frame's commands are recorded, with the `GpuTimer`'s `frame` for it.
+    pub fn recorded(&mut self, frame: u64) {
+        if self.frame <= self.warmup { return; }
+        self.samples.push(FrameSample { frame, frame_ms: None, cpu_ms: self.start.elapsed().as_secs_f32() * 1000.0, gpu_ms: None });
+    }
+
+    /// Writes the report and prints the summary. The last frame has no frame time yet and is left out.
+    pub fn write_report(&self) -> std::io::Result<()> {
+        let samples: Vec<&FrameSample> = self.samples.iter().filter(|s| s.frame_ms.is_some()).collect();
+        let mut csv = String::from("frame,frame_ms,cpu_ms,gpu_ms\n");
+        for (i, s) in samples.iter().enumerate() {
+            writeln!(csv, "{},{:.4},{:.4},{}", i, s.frame_ms.unwrap(), s.cpu_ms, s.gpu_ms.map_or(String::new(), |g| format!("{:.4}", g))).unwrap();
+        }
+        std::fs::write(self.output.with_extension("csv"), csv)?;
+
+        let frame = Stats::of(samples.iter().filter_map(|s| s.frame_ms).collect());
+        let cpu = Stats::of(samples.iter().map(|s| s.cpu_ms).collect());
+        let gpu = Stats::of(samples.iter().filter_map(|s| s.gpu_ms).collect());
+        let json = format!("{{\n  \"path\": {:?},\n  \"frames\": {},\n  \"step\": {},\n  \"frame_ms\": {},\n  \"cpu_ms\": {},\n  \"gpu_ms\": {}\n}}\n",
+                           self.path.name, samples.len(), STEP, Stats::json(&frame), Stats::json(&cpu), Stats::json(&gpu));
+        std::fs::write(self.output.with_extension("json"), json)?;
+        println!("Benchmark, {} frames: frame {}, cpu {}, gpu {}", samples.len(), Stats::summary(&frame), Stats::summary(&cpu), Stats::summary(&gpu));
+        Ok(())
+    }
+}
+
+/// Milliseconds over the recorded frames.
+struct Stats { mean: f32, min: f32, max: f32, median: f32, p95: f32, p99: f32 }
+
+impl Stats {
+    fn of(mut ms: Vec<f32>) -> Option<Stats> {
+        if ms.is_empty() { return None; }
+        ms.sort_by(f32::total_cmp);
+        let at = |p: f32| ms[((ms.len() - 1) as f32 * p).round() as usize];
+        Some(Stats { mean: ms.iter().sum::<f32>() / ms.len() as f32, min: ms[0], max: ms[ms.len() - 1], median: at(0.5), p95: at(0.95), p99: at(0.99) })
+    }
+
+    fn json(stats: &Option<Stats>) -> String {
+        match stats {
+            Some(s) => format!("{{ \"mean\": {:.4}, \"min\": {:.4}, \"max\": {:.4}, \"median\": {:.4}, \"p95\": {:.4}, \"p99\": {:.4} }}",
+                               s.mean, s.min, s.max, s.median, s.p95, s.p99),
+            None => "null".to_string(),
+        }
+    }
+
+    fn summary(stats: &Option<Stats>) -> String {
+        match stats {
+            Some(s) => format!("mean {:.2}ms median {:.2}ms p99 {:.2}ms", s.mean, s.median, s.p99),
+            None => "n/a".to_string(),
+        }
+    }
+}
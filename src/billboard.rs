@@ -0,0 +1,414 @@
+use vulkano::{ device::Device,
+               buffer::{ BufferUsage, CpuBufferPool },
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               image::{ AttachmentImage, view::ImageView },
+               render_pass::Subpass,
+               sampler::{ Sampler, SamplerCreateInfo },
+               pipeline::{ GraphicsPipeline, Pipeline, PipelineBindPoint, graphics::{ input_assembly::InputAssemblyState, vertex_input::BuffersDefinition,
+                                                                                      viewport::ViewportState, color_blend::ColorBlendState } },
+               impl_vertex };
+use bytemuck::{ Pod, Zeroable };
+use glam::Vec3;
+use std::sync::Arc;
+use crate::texture::Texture;
+use crate::camera::View;
+use crate::light2d::{ GpuLight2d, Lighting2d };
+use crate::sprite::SpriteAnimator;
+use crate::renderer::DrawStats;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BillboardMode {
+    Spherical,   //faces the camera fully
+    Cylindrical, //only rotates around world up, for trees and such
+}
+
+/// How billboards in one sorting layer order among themselves.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpriteSort {
+    /// Far to near from the camera, for billboards out in a 3d scene.
+    Depth,
+    /// Higher bottom edges first, so whatever stands lower on screen goes in front; top down
+    /// and isometric scenes.
+    Y,
+    /// `Billboard::sort_key`, lowest first.
+    Key,
+}
+
+/// Billboards of a layer draw over every layer before it, whatever their depth.
+#[derive(Clone, Debug)]
+pub struct SortingLayer {
+    pub name: String,
+    pub sort: SpriteSort,
+    /// Shaded by the scene's `Lighting2d` rather than drawn at full color.
+    pub lit: bool,
+}
+
+impl SortingLayer {
+    pub fn new(name: &str, sort: SpriteSort) -> Self { SortingLayer { name: name.to_string(), sort, lit: false } }
+}
+
+#[derive(Clone)]
+pub struct Billboard {
+    pub texture: Arc<Texture>,
+    pub position: Vec3,
+    pub size: [f32; 2],
+    pub color: [f32; 4],
+    pub uv: [f32; 4], //min.xy, max.xy
+    pub mode: BillboardMode,
+    /// Keeps `uv` on the current frame of a sprite sheet, see `sprite::animate`.
+    pub animation: Option<SpriteAnimator>,
+    /// Index into `Scene::sorting_layers`; ones past the end sort by depth after all of them.
+    pub layer: usize,
+    pub sort_key: f32,
+    /// Tangent space, x along the billboard's right and y up, for lit layers; flat without.
+    /// Load it with `Texture::load_linear`.
+    pub normal_map: Option<Arc<Texture>>,
+}
+
+impl Billboard {
+    pub fn new(texture: Arc<Texture>, position: Vec3, size: f32) -> Self {
+        Billboard { texture, position, size: [size, size], color: [1.0; 4], uv: [0.0, 0.0, 1.0, 1.0], mode: BillboardMode::Spherical, animation: None, layer: 0, sort_key: 0.0, normal_map: None }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SpriteVertex {
+    pub position: Vec3,
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+}
+
+/// Textured triangles drawn with the billboards and sorted among them, for sprites a quad
+/// can't shape: cutout skeletons, deformed parts. World space.
+#[derive(Clone)]
+pub struct SpriteMesh {
+    pub texture: Arc<Texture>,
+    /// Three a triangle, in draw order.
+    pub vertices: Vec<SpriteVertex>,
+    /// Where it sorts for `SpriteSort::Depth` and `SpriteSort::Y`.
+    pub position: Vec3,
+    /// The mesh's own right and up, that its normal map's x and y point along.
+    pub right: Vec3,
+    pub up: Vec3,
+    pub layer: usize,
+    pub sort_key: f32,
+    pub normal_map: Option<Arc<Texture>>,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+struct MeshVertex { m_position: [f32; 3], m_uv: [f32; 2], m_color: [f32; 4], m_right: [f32; 3], m_up: [f32; 3],
+                    m_light_mask: u32, m_normal_mapped: u32, m_ambient: [f32; 3], }
+impl_vertex!(MeshVertex, m_position, m_uv, m_color, m_right, m_up, m_light_mask, m_normal_mapped, m_ambient);
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+struct Instance { i_position: [f32; 3], i_mode: u32, i_size: [f32; 2], i_color: [f32; 4], i_uv: [f32; 4],
+                  i_light_mask: u32, i_normal_mapped: u32, i_ambient: [f32; 3], }
+impl_vertex!(Instance, i_position, i_mode, i_size, i_color, i_uv, i_light_mask, i_normal_mapped, i_ambient);
+
+mod vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			layout(location = 0) in vec3 i_position;
+			layout(location = 1) in uint i_mode;
+			layout(location = 2) in vec2 i_size;
+			layout(location = 3) in vec4 i_color;
+			layout(location = 4) in vec4 i_uv;
+			layout(location = 5) in uint i_light_mask; //its layer's bit, 0 unlit
+			layout(location = 6) in uint i_normal_mapped;
+			layout(location = 7) in vec3 i_ambient;
+
+			layout(location = 0) out vec2 v_uv;
+			layout(location = 1) out vec4 v_color;
+			layout(location = 2) flat out vec3 v_fade; //near, far, fade distance
+			layout(location = 3) out vec3 v_world;
+			layout(location = 4) flat out vec3 v_right;
+			layout(location = 5) flat out vec3 v_up;
+			layout(location = 6) flat out uvec2 v_lighting; //mask, normal mapped
+			layout(location = 7) flat out vec3 v_ambient;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 view_proj;
+				vec4 cam_right;
+				vec4 cam_up;
+				vec4 cam_pos;  //w is the soft fade distance
+				vec2 near_far;
+			} pc;
+
+			const vec2 corners[6] = vec2[](vec2(-0.5, -0.5), vec2(0.5, -0.5), vec2(0.5, 0.5),
+			                               vec2(-0.5, -0.5), vec2(0.5, 0.5), vec2(-0.5, 0.5));
+
+			void main() {
+				vec2 c = corners[gl_VertexIndex];
+				vec3 right = pc.cam_right.xyz;
+				vec3 up = pc.cam_up.xyz;
+				if (i_mode == 1) {
+					up = vec3(0.0, 1.0, 0.0);
+					vec3 to_cam = pc.cam_pos.xyz - i_position;
+					right = normalize(cross(up, to_cam));
+				}
+				vec3 p = i_position + right * c.x * i_size.x + up * c.y * i_size.y;
+				gl_Position = pc.view_proj * vec4(p, 1.0);
+				v_uv = mix(i_uv.xy, i_uv.zw, vec2(c.x + 0.5, 0.5 - c.y));
+				v_color = i_color;
+				v_fade = vec3(pc.near_far, pc.cam_pos.w);
+				v_world = p;
+				v_right = right;
+				v_up = up;
+				v_lighting = uvec2(i_light_mask, i_normal_mapped);
+				v_ambient = i_ambient;
+			}"
+    }
+}
+mod mesh_vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			layout(location = 0) in vec3 m_position;
+			layout(location = 1) in vec2 m_uv;
+			layout(location = 2) in vec4 m_color;
+			layout(location = 3) in vec3 m_right;
+			layout(location = 4) in vec3 m_up;
+			layout(location = 5) in uint m_light_mask;
+			layout(location = 6) in uint m_normal_mapped;
+			layout(location = 7) in vec3 m_ambient;
+
+			layout(location = 0) out vec2 v_uv;
+			layout(location = 1) out vec4 v_color;
+			layout(location = 2) flat out vec3 v_fade;
+			layout(location = 3) out vec3 v_world;
+			layout(location = 4) flat out vec3 v_right;
+			layout(location = 5) flat out vec3 v_up;
+			layout(location = 6) flat out uvec2 v_lighting;
+			layout(location = 7) flat out vec3 v_ambient;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 view_proj;
+				vec4 fade; //near, far, soft fade distance
+			} pc;
+
+			//the billboards' fragment shader, with the mesh's own axes
+			void main() {
+				gl_Position = pc.view_proj * vec4(m_position, 1.0);
+				v_uv = m_uv;
+				v_color = m_color;
+				v_fade = pc.fade.xyz;
+				v_world = m_position;
+				v_right = m_right;
+				v_up = m_up;
+				v_lighting = uvec2(m_light_mask, m_normal_mapped);
+				v_ambient = m_ambient;
+			}"
+    }
+}
+mod fs {
+    vulkano_shaders::shader! { ty: "fragment",
+    src: "#version 450
+
+			layout(input_attachment_index = 0, set = 0, binding = 0) uniform subpassInput u_depth;
+			layout(set = 0, binding = 1) uniform sampler2D u_texture;
+			layout(set = 0, binding = 2) uniform sampler2D u_normal; //the color texture when there's none
+			struct Light2d { vec4 position; vec4 color; vec4 direction; uvec4 mask; };
+			layout(set = 0, binding = 3) readonly buffer Lights2d { Light2d lights[]; };
+			layout(set = 0, binding = 4) readonly buffer Shadows2d { float shadow_maps[]; };
+			const uint SHADOW_RESOLUTION = 360u; //light2d::SHADOW_RESOLUTION
+
+			layout(location = 0) in vec2 v_uv;
+			layout(location = 1) in vec4 v_color;
+			layout(location = 2) flat in vec3 v_fade;
+			layout(location = 3) in vec3 v_world;
+			layout(location = 4) flat in vec3 v_right;
+			layout(location = 5) flat in vec3 v_up;
+			layout(location = 6) flat in uvec2 v_lighting;
+			layout(location = 7) flat in vec3 v_ambient;
+			layout(location = 0) out vec4 f_color;
+
+			float linear_depth(float d) { return v_fade.x * v_fade.y / (v_fade.y - d * (v_fade.y - v_fade.x)); }
+
+			//1 where the light reaches, three taps wide across the angles
+			float shadow(Light2d l) {
+				if (l.mask.y == 0u) return 1.0;
+				vec2 to = v_world.xy - l.position.xy;
+				float d = length(to);
+				float a = fract(atan(to.y, to.x) / 6.28318530718) * float(SHADOW_RESOLUTION) - 0.5;
+				float lit = 0.0;
+				for (int k = -1; k <= 1; k++) {
+					uint i = uint(mod(floor(a) + float(k), float(SHADOW_RESOLUTION)));
+					lit += step(d, shadow_maps[l.mask.y - 1u + i] + 0.02);
+				}
+				return lit / 3.0;
+			}
+
+			vec3 lighting() {
+				vec3 n = v_lighting.y != 0u ? texture(u_normal, v_uv).xyz * 2.0 - 1.0 : vec3(0.0, 0.0, 1.0);
+				vec3 normal = normalize(v_right * n.x + v_up * n.y + normalize(cross(v_right, v_up)) * n.z);
+				vec3 light = v_ambient;
+				for (int i = 0; i < lights.length(); i++) {
+					Light2d l = lights[i];
+					if ((l.mask.x & v_lighting.x) == 0u) continue;
+					vec3 to_light = l.position.xyz - v_world;
+					float d = length(to_light);
+					if (d >= l.position.w) continue;
+					vec3 dir = to_light / max(d, 1e-4);
+					float falloff = 1.0 - d / l.position.w;
+					float cone = l.direction.w < -1.5 ? 1.0 : smoothstep(l.direction.w, l.color.w, dot(-dir, l.direction.xyz));
+					light += l.color.rgb * falloff * falloff * cone * max(dot(normal, dir), 0.0) * shadow(l);
+				}
+				return light;
+			}
+
+			void main() {
+				float scene = linear_depth(subpassLoad(u_depth).r);
+				float frag = linear_depth(gl_FragCoord.z);
+				float fade = clamp((scene - frag) / v_fade.z, 0.0, 1.0);
+				f_color = texture(u_texture, v_uv) * v_color;
+				if (v_lighting.x != 0u) f_color.rgb *= lighting();
+				f_color.a *= fade;
+			}"
+    }
+}
+
+/// One draw's worth of consecutive sprites with the same textures.
+enum Batch {
+    Quads(Vec<Instance>),
+    Mesh(Vec<MeshVertex>),
+}
+
+/// Draws every billboard and sprite mesh in the scene in the transparent subpass, in layer and
+/// sort order, one draw per run of the same texture; sprites sharing an atlas batch best. There
+/// is no depth test; occlusion comes from the soft fade against the scene depth input.
+pub struct Billboards {
+    pipeline: Arc<GraphicsPipeline>,
+    mesh_pipeline: Arc<GraphicsPipeline>,
+    pool: CpuBufferPool<Instance>,
+    mesh_pool: CpuBufferPool<MeshVertex>,
+    light_pool: CpuBufferPool<GpuLight2d>,
+    shadow_pool: CpuBufferPool<f32>,
+    sampler: Arc<Sampler>,
+    pub fade_distance: f32,
+}
+
+impl Billboards {
+    pub fn new(dev: Arc<Device>, subpass: Subpass) -> Self {
+        let vs = vs::load(dev.clone()).unwrap();
+        let fs = fs::load(dev.clone()).unwrap();
+        let pipeline = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new().instance::<Instance>())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .color_blend_state(ColorBlendState::new(subpass.num_color_attachments()).blend_alpha())
+            .render_pass(subpass.clone())
+            .build(dev.clone()).unwrap();
+        let mesh_pipeline = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new().vertex::<MeshVertex>())
+            .vertex_shader(mesh_vs::load(dev.clone()).unwrap().entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .color_blend_state(ColorBlendState::new(subpass.num_color_attachments()).blend_alpha())
+            .render_pass(subpass)
+            .build(dev.clone()).unwrap();
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo::simple_repeat_linear_no_mipmap()).unwrap();
+        Billboards { pipeline, mesh_pipeline, pool: CpuBufferPool::vertex_buffer(dev.clone()), mesh_pool: CpuBufferPool::vertex_buffer(dev.clone()), light_pool: CpuBufferPool::new(dev.clone(), BufferUsage::storage_buffer()),
+                     shadow_pool: CpuBufferPool::new(dev, BufferUsage::storage_buffer()), sampler, fade_distance: 0.5 }
+    }
+
+    #[profiling::function]
+    pub fn draw(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, billboards: &[Billboard], meshes: &[SpriteMesh],
+                layers: &[SortingLayer], lighting: &Lighting2d, depth: Arc<ImageView<AttachmentImage>>, view: &View) -> DrawStats {
+        let mut stats = DrawStats::default();
+        if billboards.is_empty() && meshes.is_empty() { return stats; }
+        let inv = view.view.inverse();
+        let eye = inv.w_axis.truncate();
+
+        //layer by layer, back to front within one so alpha blending works, ties by texture to batch
+        let key = |layer: usize, position: Vec3, bottom: f32, sort_key: f32| match layers.get(layer).map_or(SpriteSort::Depth, |l| l.sort) {
+            SpriteSort::Depth => -position.distance_squared(eye),
+            SpriteSort::Y => -bottom,
+            SpriteSort::Key => sort_key,
+        };
+        //billboards as Ok, meshes as Err
+        let mut sorted: Vec<(usize, f32, Result<&Billboard, &SpriteMesh>)> = billboards.iter()
+            .map(|b| (b.layer.min(layers.len()), key(b.layer, b.position, b.position.y - b.size[1] * 0.5, b.sort_key), Ok(b)))
+            .chain(meshes.iter().map(|m| (m.layer.min(layers.len()), key(m.layer, m.position, m.position.y, m.sort_key), Err(m))))
+            .collect();
+        let texture_of = |s: &Result<&Billboard, &SpriteMesh>| match s { Ok(b) => b.texture.clone(), Err(m) => m.texture.clone() };
+        let normal_of = |s: &Result<&Billboard, &SpriteMesh>| match s { Ok(b) => b.normal_map.clone(), Err(m) => m.normal_map.clone() };
+        sorted.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)).then_with(|| Arc::as_ptr(&texture_of(&a.2)).cmp(&Arc::as_ptr(&texture_of(&b.2)))));
+        //only neighbours batch, merging further apart would draw out of order
+        let same = |a: &Option<Arc<Texture>>, b: &Option<Arc<Texture>>| match (a, b) { (Some(a), Some(b)) => Arc::ptr_eq(a, b), (a, b) => a.is_none() && b.is_none() };
+        let mut batches: Vec<(Arc<Texture>, Option<Arc<Texture>>, Batch)> = Vec::new();
+        for (layer, _, sprite) in sorted {
+            let lit = layers.get(layer).map_or(false, |l| l.lit);
+            let light_mask = if lit { 1 << layer.min(31) } else { 0 };
+            let (texture, normal) = (texture_of(&sprite), normal_of(&sprite));
+            let last = batches.last_mut().filter(|(t, n, _)| Arc::ptr_eq(t, &texture) && same(n, &normal));
+            match sprite {
+                Ok(b) => {
+                    let instance = Instance { i_position: b.position.into(), i_mode: (b.mode == BillboardMode::Cylindrical) as u32,
+                                              i_size: b.size, i_color: b.color, i_uv: b.uv,
+                                              i_light_mask: light_mask, i_normal_mapped: b.normal_map.is_some() as u32,
+                                              i_ambient: lighting.ambient };
+                    match last {
+                        Some((_, _, Batch::Quads(instances))) => instances.push(instance),
+                        _ => batches.push((texture, normal, Batch::Quads(vec![instance]))),
+                    }
+                }
+                Err(m) => {
+                    let vertices = m.vertices.iter().map(|v| MeshVertex { m_position: v.position.into(), m_uv: v.uv, m_color: v.color,
+                                                                          m_right: m.right.into(), m_up: m.up.into(), m_light_mask: light_mask,
+                                                                          m_normal_mapped: m.normal_map.is_some() as u32, m_ambient: lighting.ambient });
+                    match last {
+                        Some((_, _, Batch::Mesh(batch))) => batch.extend(vertices),
+                        _ => batches.push((texture, normal, Batch::Mesh(vertices.collect()))),
+                    }
+                }
+            }
+        }
+        let (lights, shadow_maps) = lighting.gpu();
+        let (lights, shadow_maps) = (self.light_pool.chunk(lights).unwrap(), self.shadow_pool.chunk(shadow_maps).unwrap());
+
+        let pc = vs::ty::PushConstants {
+            view_proj: view.view_proj().to_cols_array_2d(),
+            cam_right: inv.x_axis.into(), cam_up: inv.y_axis.into(),
+            cam_pos: eye.extend(self.fade_distance).into(),
+            near_far: [view.near, view.far],
+        };
+        let mesh_pc = mesh_vs::ty::PushConstants { view_proj: pc.view_proj, fade: [view.near, view.far, self.fade_distance, 0.0] };
+        for (texture, normal_map, batch) in batches {
+            let pipeline = match batch { Batch::Quads(_) => &self.pipeline, Batch::Mesh(_) => &self.mesh_pipeline };
+            let normal = normal_map.as_ref().unwrap_or(&texture);
+            let set = PersistentDescriptorSet::new(pipeline.layout().set_layouts().get(0).unwrap().clone(), [
+                WriteDescriptorSet::image_view(0, depth.clone()),
+                WriteDescriptorSet::image_view_sampler(1, texture.view.clone(), self.sampler.clone()),
+                WriteDescriptorSet::image_view_sampler(2, normal.view.clone(), self.sampler.clone()),
+                WriteDescriptorSet::buffer(3, lights.clone()),
+                WriteDescriptorSet::buffer(4, shadow_maps.clone()),
+            ]).unwrap();
+            builder.bind_pipeline_graphics(pipeline.clone())
+                .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 0, set);
+            match batch {
+                Batch::Quads(instances) => {
+                    let count = instances.len() as u32;
+                    builder.push_constants(pipeline.layout().clone(), 0, pc)
+                        .bind_vertex_buffers(0, self.pool.chunk(instances).unwrap())
+                        .draw(6, count, 0, 0).unwrap();
+                    stats.add(1, count as u64 * 2);
+                }
+                Batch::Mesh(vertices) => {
+                    let count = vertices.len() as u32;
+                    builder.push_constants(pipeline.layout().clone(), 0, mesh_pc)
+                        .bind_vertex_buffers(0, self.mesh_pool.chunk(vertices).unwrap())
+                        .draw(count, 1, 0, 0).unwrap();
+                    stats.add(1, count as u64 / 3);
+                }
+            }
+        }
+        stats
+    }
+}
@@ -0,0 +1,130 @@
+use glam::{ Vec3, Mat4 };
+use crate::scene::EntityId;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ray { pub origin: Vec3, pub dir: Vec3, }
+
+impl Ray {
+    pub fn at(&self, t: f32) -> Vec3 { self.origin + self.dir * t }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb { pub min: Vec3, pub max: Vec3, }
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb { min: Vec3::splat(f32::INFINITY), max: Vec3::splat(f32::NEG_INFINITY) };
+
+    pub fn from_points(points: impl Iterator<Item = Vec3>) -> Aabb {
+        points.fold(Aabb::EMPTY, |b, p| Aabb { min: b.min.min(p), max: b.max.max(p) })
+    }
+    pub fn union(&self, o: &Aabb) -> Aabb { Aabb { min: self.min.min(o.min), max: self.max.max(o.max) } }
+    pub fn center(&self) -> Vec3 { (self.min + self.max) * 0.5 }
+    pub fn extent(&self) -> Vec3 { self.max - self.min }
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (a, b) = (self.min, self.max);
+        [ Vec3::new(a.x, a.y, a.z), Vec3::new(b.x, a.y, a.z), Vec3::new(a.x, b.y, a.z), Vec3::new(b.x, b.y, a.z),
+          Vec3::new(a.x, a.y, b.z), Vec3::new(b.x, a.y, b.z), Vec3::new(a.x, b.y, b.z), Vec3::new(b.x, b.y, b.z) ]
+    }
+    /// Box enclosing this one after `m` is applied; not tight for rotations, but conservative.
+    pub fn transformed(&self, m: &Mat4) -> Aabb { Aabb::from_points(self.corners().iter().map(|&c| m.transform_point3(c))) }
+}
+
+/// Slab test, returns the entry distance along the ray.
+pub fn ray_aabb(ray: &Ray, b: &Aabb) -> Option<f32> {
+    let inv = ray.dir.recip();
+    let t0 = (b.min - ray.origin) * inv;
+    let t1 = (b.max - ray.origin) * inv;
+    let near = t0.min(t1).max_element().max(0.0);
+    let far = t0.max(t1).min_element();
+    if near <= far { Some(near) } else { None }
+}
+
+/// Moller-Trumbore, two sided.
+pub fn ray_triangle(ray: &Ray, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+    let (e1, e2) = (b - a, c - a);
+    let p = ray.dir.cross(e2);
+    let det = e1.dot(p);
+    if det.abs() < f32::EPSILON { return None; }
+    let inv_det = 1.0 / det;
+    let s = ray.origin - a;
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) { return None; }
+    let q = s.cross(e1);
+    let v = ray.dir.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 { return None; }
+    let t = e2.dot(q) * inv_det;
+    if t > 0.0 { Some(t) } else { None }
+}
+
+enum Node {
+    Leaf { bounds: Aabb, item: usize },
+    Branch { bounds: Aabb, left: usize, right: usize },
+}
+
+impl Node {
+    fn bounds(&self) -> &Aabb { match self { Node::Leaf { bounds, .. } | Node::Branch { bounds, .. } => bounds } }
+}
+
+/// Bounding volume hierarchy over entity world bounds, split at the median of the longest axis.
+#[derive(Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    items: Vec<(EntityId, Aabb)>,
+}
+
+impl Bvh {
+    pub fn build(items: Vec<(EntityId, Aabb)>) -> Bvh {
+        let mut bvh = Bvh { nodes: Vec::with_capacity(items.len() * 2), items };
+        let mut order: Vec<usize> = (0..bvh.items.len()).collect();
+        if !order.is_empty() { bvh.build_node(&mut order); }
+        bvh
+    }
+
+    fn build_node(&mut self, order: &mut [usize]) -> usize {
+        let bounds = order.iter().fold(Aabb::EMPTY, |b, &i| b.union(&self.items[i].1));
+        if order.len() == 1 {
+            self.nodes.push(Node::Leaf { bounds, item: order[0] });
+            return self.nodes.len() - 1;
+        }
+        let axis = bounds.extent().to_array().iter().enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1)).map(|(i, _)| i).unwrap();
+        order.sort_by(|&a, &b| self.items[a].1.center()[axis].total_cmp(&self.items[b].1.center()[axis]));
+        let (l, r) = order.split_at_mut(order.len() / 2);
+        let left = self.build_node(l);
+        let right = self.build_node(r);
+        self.nodes.push(Node::Branch { bounds, left, right });
+        self.nodes.len() - 1
+    }
+
+    fn root(&self) -> Option<usize> { self.nodes.len().checked_sub(1) }
+
+    /// Entities whose bounds the ray enters, with their entry distance. Unsorted.
+    pub fn query_ray(&self, ray: &Ray) -> Vec<(EntityId, f32)> {
+        let mut out = Vec::new();
+        let mut stack: Vec<usize> = self.root().into_iter().collect();
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            if ray_aabb(ray, node.bounds()).is_none() { continue; }
+            match *node {
+                Node::Leaf { item, .. } => out.push((self.items[item].0, ray_aabb(ray, &self.items[item].1).unwrap())),
+                Node::Branch { left, right, .. } => { stack.push(left); stack.push(right); }
+            }
+        }
+        out
+    }
+
+    /// Entities whose bounds satisfy `visit` for every node on the way down.
+    pub fn query(&self, mut visit: impl FnMut(&Aabb) -> bool) -> Vec<EntityId> {
+        let mut out = Vec::new();
+        let mut stack: Vec<usize> = self.root().into_iter().collect();
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            if !visit(node.bounds()) { continue; }
+            match *node {
+                Node::Leaf { item, .. } => out.push(self.items[item].0),
+                Node::Branch { left, right, .. } => { stack.push(left); stack.push(right); }
+            }
+        }
+        out
+    }
+}
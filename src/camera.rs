@@ -0,0 +1,235 @@
+use glam::{ Vec2, Vec3, Vec4, Mat4 };
+use crate::bvh::Ray;
+use crate::postfx::{ dof::DepthOfField, lens::{ Vignette, ChromaticAberration } };
+
+/// Matrices a pass renders with. Usually from the `Camera`, but mirrors and portals make their own.
+#[derive(Clone, Copy, Debug)]
+pub struct View {
+    pub view: Mat4,
+    pub proj: Mat4,
+    pub near: f32,
+    pub far: f32,
+    /// Clip space offset already in `proj`, for temporal anti-aliasing.
+    pub jitter: Vec2,
+}
+
+impl View {
+    pub fn view_proj(&self) -> Mat4 { self.proj * self.view }
+    /// Shifts the projection by `jitter` in clip space; geometry moves by a fraction of a pixel.
+    pub fn jittered(self, jitter: Vec2) -> View {
+        View { proj: Mat4::from_translation(jitter.extend(0.0)) * self.proj, jitter: self.jitter + jitter, ..self }
+    }
+    /// `view_proj` as it would be without jitter, for reprojecting between frames.
+    pub fn unjittered_view_proj(&self) -> Mat4 { Mat4::from_translation(-self.jitter.extend(0.0)) * self.view_proj() }
+    pub fn eye(&self) -> Vec3 { self.view.inverse().w_axis.truncate() }
+    /// Unit direction the view looks along.
+    pub fn forward(&self) -> Vec3 { -self.view.inverse().z_axis.truncate().normalize() }
+    /// An orthographic projection keeps w at 1.
+    pub fn is_orthographic(&self) -> bool { self.proj.w_axis.w == 1.0 }
+    /// World space ray through a cursor position given in window pixels; parallel rays for
+    /// orthographic views, like the 2D camera's.
+    pub fn screen_ray(&self, cursor: [f32; 2], extent: [f32; 2]) -> Ray {
+        let ndc = [cursor[0] / extent[0] * 2.0 - 1.0, cursor[1] / extent[1] * 2.0 - 1.0];
+        let inv = self.view_proj().inverse();
+        let unproject = |z: f32| { let p = inv * Vec4::new(ndc[0], ndc[1], z, 1.0); p.truncate() / p.w };
+        let (near, far) = (unproject(0.0), unproject(1.0));
+        Ray { origin: near, dir: (far - near).normalize() }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    /// `fov_y` in radians.
+    Perspective { fov_y: f32 },
+    /// `height` of the view volume in world units; the width follows the aspect ratio. The
+    /// lit passes handle it, post effects that turn depth back into distance still assume
+    /// perspective.
+    Orthographic { height: f32 },
+}
+
+impl Projection {
+    /// Right handed, looking down -z, with y flipped and depth in 0..1 to match vulkan clip
+    /// space. `reversed_z` maps near to 1 and far to 0, which spreads float depth precision
+    /// evenly; the renderer's own passes test and clear for forward depth, so it's for passes
+    /// of your own that test greater and clear to 0.
+    pub fn matrix(&self, aspect: f32, near: f32, far: f32, reversed_z: bool) -> Mat4 {
+        let mut proj = match *self {
+            Projection::Perspective { fov_y } => Mat4::perspective_rh(fov_y, aspect, near, far),
+            Projection::Orthographic { height } => {
+                let (x, y) = (height * aspect * 0.5, height * 0.5);
+                Mat4::orthographic_rh(-x, x, -y, y, near, far)
+            }
+        };
+        proj.y_axis.y *= -1.0;
+        if reversed_z {
+            //z' = w - z
+            proj = Mat4::from_cols(Vec4::X, Vec4::Y, Vec4::new(0.0, 0.0, -1.0, 0.0), Vec4::new(0.0, 0.0, 1.0, 1.0)) * proj;
+        }
+        proj
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SensorFit {
+    /// The sensor's width spans the image's, as most DCC tools do for landscape images.
+    Horizontal,
+    Vertical,
+}
+
+/// A real camera's settings, in the units its spec sheet uses. Set on a `Camera`, they take
+/// over its field of view, the depth of field's aperture and lens, the motion blur's shutter
+/// and, while auto exposure is off, the tonemapper's exposure. The exposure is physical, so it
+/// only looks right with lights in physical units: a sunny day wants f/16, 1/100s, ISO 100
+/// under a sun of around 100000 lux.
+#[derive(Clone, Copy, Debug)]
+pub struct PhysicalCamera {
+    /// Millimeters.
+    pub focal_length: f32,
+    /// Width and height in millimeters; 36 by 24 is full frame.
+    pub sensor: [f32; 2],
+    pub fit: SensorFit,
+    /// f-number.
+    pub aperture: f32,
+    /// Seconds the shutter is open.
+    pub shutter: f32,
+    pub iso: f32,
+}
+
+impl Default for PhysicalCamera {
+    fn default() -> Self { PhysicalCamera { focal_length: 35.0, sensor: [36.0, 24.0], fit: SensorFit::Horizontal, aperture: 2.8, shutter: 1.0 / 60.0, iso: 100.0 } }
+}
+
+impl PhysicalCamera {
+    /// Millimeters of sensor the image's height covers.
+    pub fn sensor_height(&self, aspect: f32) -> f32 {
+        match self.fit { SensorFit::Horizontal => self.sensor[0] / aspect.max(1e-4), SensorFit::Vertical => self.sensor[1] }
+    }
+
+    pub fn fov_y(&self, aspect: f32) -> f32 { 2.0 * (self.sensor_height(aspect) * 0.5 / self.focal_length.max(1e-3)).atan() }
+
+    /// Exposure value at ISO 100 of these settings.
+    pub fn ev100(&self) -> f32 { (self.aperture * self.aperture / self.shutter * 100.0 / self.iso).log2() }
+
+    /// What scene luminance in nits is multiplied by to land in 0..1 before tonemapping; the
+    /// brightest unclipped value comes out at 1, with the 1.2 of the saturation based
+    /// sensitivity standard. Lagarde and de Rousiers, "Moving Frostbite to PBR".
+    pub fn exposure(&self) -> f32 { 1.0 / (1.2 * self.ev100().exp2()) }
+
+    /// How much of a frame `frame_time` seconds long the shutter stays open, for motion blur.
+    pub fn shutter_fraction(&self, frame_time: f32) -> f32 { (self.shutter / frame_time.max(1e-4)).min(1.0) }
+}
+
+pub struct Camera {
+    pub position: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    /// The field of view is the physical camera's while that's set, see `lens`.
+    pub projection: Projection,
+    pub aspect: f32,
+    pub near: f32,
+    pub far: f32,
+    /// Applied by the post stack to this camera's image.
+    pub depth_of_field: Option<DepthOfField>,
+    pub vignette: Option<Vignette>,
+    pub chromatic_aberration: Option<ChromaticAberration>,
+    pub physical: Option<PhysicalCamera>,
+}
+
+impl Camera {
+    pub fn new(position: Vec3, target: Vec3) -> Self {
+        Camera { position, target, up: Vec3::Y, projection: Projection::Perspective { fov_y: 60f32.to_radians() }, aspect: 1.0, near: 0.1, far: 1000.0, depth_of_field: None, vignette: None, chromatic_aberration: None, physical: None }
+    }
+
+    pub fn view(&self) -> Mat4 { Mat4::look_at_rh(self.position, self.target, self.up) }
+
+    /// `projection` with the physical camera's field of view, when it has one and is perspective.
+    pub fn lens(&self) -> Projection {
+        match (self.projection, self.physical) {
+            (Projection::Perspective { .. }, Some(p)) => Projection::Perspective { fov_y: p.fov_y(self.aspect) },
+            (projection, _) => projection,
+        }
+    }
+
+    /// 0..1 depth, y flipped to match vulkan clip space.
+    pub fn projection(&self) -> Mat4 { self.lens().matrix(self.aspect, self.near, self.far, false) }
+
+    pub fn view_proj(&self) -> Mat4 { self.projection() * self.view() }
+
+    pub fn as_view(&self) -> View { View { view: self.view(), proj: self.projection(), near: self.near, far: self.far, jitter: Vec2::ZERO } }
+}
+
+/// Orthographic camera for 2D work, y up, measured in pixels of the target it draws to rather
+/// than a vertical extent. Sprites at z 0 sit halfway through its depth range; anything
+/// between -`DEPTH_2D` / 2 and `DEPTH_2D` / 2 is visible, higher z in front.
+#[derive(Clone, Copy, Debug)]
+pub struct Camera2d {
+    /// World point at the center of the screen.
+    pub position: Vec2,
+    /// Screen pixels per world unit.
+    pub zoom: f32,
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    /// Keeps `zoom` to whole numbers and world units on pixel boundaries, so pixel art stays
+    /// crisp instead of shimmering as the camera moves.
+    pub pixel_perfect: bool,
+}
+
+pub const DEPTH_2D: f32 = 2000.0;
+
+impl Camera2d {
+    pub fn new(position: Vec2, zoom: f32) -> Self {
+        Camera2d { position, zoom, min_zoom: 0.01, max_zoom: 1000.0, pixel_perfect: false }
+    }
+
+    fn effective_zoom(&self) -> f32 {
+        if self.pixel_perfect { self.zoom.round().max(1.0) } else { self.zoom }
+    }
+
+    /// `position` snapped so texel edges land on pixel edges; odd extents center on a pixel's
+    /// middle rather than its edge.
+    fn effective_position(&self, extent: [f32; 2]) -> Vec2 {
+        if !self.pixel_perfect { return self.position; }
+        let zoom = self.effective_zoom();
+        let half = Vec2::new(extent[0] % 2.0, extent[1] % 2.0) * 0.5;
+        ((self.position * zoom - half).round() + half) / zoom
+    }
+
+    pub fn as_view(&self, extent: [f32; 2]) -> View {
+        let zoom = self.effective_zoom();
+        let position = self.effective_position(extent);
+        let height = extent[1] / zoom;
+        let proj = Projection::Orthographic { height }.matrix(extent[0] / extent[1].max(1.0), 0.0, DEPTH_2D, false);
+        let view = Mat4::from_translation(-position.extend(DEPTH_2D * 0.5));
+        View { view, proj, near: 0.0, far: DEPTH_2D, jitter: Vec2::ZERO }
+    }
+
+    /// Window pixels, origin top left, of a world point.
+    pub fn world_to_screen(&self, world: Vec2, extent: [f32; 2]) -> Vec2 {
+        let p = (world - self.effective_position(extent)) * self.effective_zoom();
+        Vec2::new(p.x, -p.y) + Vec2::from(extent) * 0.5
+    }
+
+    pub fn screen_to_world(&self, screen: Vec2, extent: [f32; 2]) -> Vec2 {
+        let p = screen - Vec2::from(extent) * 0.5;
+        self.effective_position(extent) + Vec2::new(p.x, -p.y) / self.effective_zoom()
+    }
+
+    /// Moves by a cursor drag in pixels, so what was under the cursor stays there.
+    pub fn pan(&mut self, delta: Vec2) {
+        self.position -= Vec2::new(delta.x, -delta.y) / self.effective_zoom();
+    }
+
+    /// Zooms by `factor` keeping the world point under `cursor` in place. Pixel perfect
+    /// cameras go a whole step at a time.
+    pub fn zoom_at(&mut self, cursor: Vec2, factor: f32, extent: [f32; 2]) {
+        let anchor = self.screen_to_world(cursor, extent);
+        self.zoom = if self.pixel_perfect {
+            let step = if factor > 1.0 { 1.0 } else if factor < 1.0 { -1.0 } else { 0.0 };
+            self.effective_zoom() + step
+        } else {
+            self.zoom * factor
+        }.clamp(self.min_zoom, self.max_zoom);
+        let p = cursor - Vec2::from(extent) * 0.5;
+        self.position = anchor - Vec2::new(p.x, -p.y) / self.effective_zoom();
+    }
+}
@@ -0,0 +1,242 @@
+use glam::Vec3;
+use std::fmt::Write as _;
+use std::path::Path;
+use crate::camera::{ Camera, Projection };
+use crate::scene::Scene;
+use crate::tween::{ add_key, write_curve, Curve, Ease };
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Spline {
+    /// Goes through every point.
+    CatmullRom,
+    /// Points come as point, handle, handle, point, handle, ... and it goes through every third.
+    Bezier,
+}
+
+/// Samples per spline segment for measuring along it.
+const STEPS: usize = 32;
+
+/// A camera move for cutscenes and flythroughs. Everything but the points is keyed by meters
+/// along the path, so retiming the speed doesn't shift where the camera looks.
+#[derive(Clone, Debug)]
+pub struct CameraPath {
+    pub name: String,
+    pub spline: Spline,
+    pub points: Vec<Vec3>,
+    /// Where the camera looks; along the path when None.
+    pub look_at: Option<Curve<Vec3>>,
+    /// Meters per second.
+    pub speed: Curve<f32>,
+    /// Vertical field of view in radians; leaves the camera's be when None.
+    pub fov: Option<Curve<f32>>,
+    /// Closes back to the first point and plays round and round.
+    pub looping: bool,
+}
+
+/// Where a path puts the camera.
+#[derive(Clone, Copy, Debug)]
+pub struct PathSample {
+    pub position: Vec3,
+    pub target: Vec3,
+    pub fov_y: Option<f32>,
+}
+
+impl CameraPath {
+    pub fn new(name: &str, spline: Spline) -> Self {
+        CameraPath { name: name.to_string(), spline, points: Vec::new(), look_at: None, speed: Curve::new(2.0), fov: None, looping: false }
+    }
+
+    fn segments(&self) -> usize {
+        let n = self.points.len();
+        match self.spline {
+            Spline::CatmullRom if n < 2 => 0,
+            Spline::CatmullRom => if self.looping { n } else { n - 1 },
+            Spline::Bezier => n.saturating_sub(1) / 3,
+        }
+    }
+
+    /// Position `t` of the way through `segment`.
+    fn point(&self, segment: usize, t: f32) -> Vec3 {
+        let p = &self.points;
+        match self.spline {
+            Spline::CatmullRom => {
+                let n = p.len() as isize;
+                //ends repeat their point, loops wrap
+                let at = |i: isize| p[if self.looping { i.rem_euclid(n) } else { i.clamp(0, n - 1) } as usize];
+                let i = segment as isize;
+                let (p0, p1, p2, p3) = (at(i - 1), at(i), at(i + 1), at(i + 2));
+                0.5 * (2.0 * p1 + (p2 - p0) * t + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t * t + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t * t * t)
+            }
+            Spline::Bezier => {
+                let [a, b, c, d] = [0, 1, 2, 3].map(|k| p[segment * 3 + k]);
+                let u = 1.0 - t;
+                a * u * u * u + b * 3.0 * u * u * t + c * 3.0 * u * t * t + d * t * t * t
+            }
+        }
+    }
+
+    /// Distance along the path at every sample point, with the segment and t it's at.
+    fn measure(&self) -> Vec<(f32, usize, f32)> {
+        let mut table = vec![(0.0, 0, 0.0)];
+        let mut previous = match self.points.first() { Some(&p) => p, None => return table };
+        let mut distance = 0.0;
+        for segment in 0..self.segments() {
+            for step in 1..=STEPS {
+                let t = step as f32 / STEPS as f32;
+                let p = self.point(segment, t);
+                distance += p.distance(previous);
+                previous = p;
+                table.push((distance, segment, t));
+            }
+        }
+        table
+    }
+
+    /// Meters from start to end.
+    pub fn length(&self) -> f32 { self.measure().last().map_or(0.0, |m| m.0) }
+
+    fn position_in(&self, table: &[(f32, usize, f32)], distance: f32) -> Vec3 {
+        if self.segments() == 0 { return self.points.first().copied().unwrap_or(Vec3::ZERO); }
+        let next = table.partition_point(|m| m.0 <= distance).clamp(1, table.len() - 1);
+        let ((d0, s0, t0), (d1, s1, t1)) = (table[next - 1], table[next]);
+        let f = ((distance - d0) / (d1 - d0).max(1e-6)).clamp(0.0, 1.0);
+        //the step that crosses into a new segment starts at its t = 0
+        let t0 = if s0 != s1 { 0.0 } else { t0 };
+        self.point(s1, t0 + (t1 - t0) * f)
+    }
+
+    pub fn position(&self, distance: f32) -> Vec3 { self.position_in(&self.measure(), distance) }
+
+    /// The camera `distance` meters along.
+    pub fn sample(&self, distance: f32) -> PathSample {
+        let table = self.measure();
+        let position = self.position_in(&table, distance);
+        let target = match &self.look_at {
+            Some(curve) => curve.sample(distance),
+            None => {
+                //a little way ahead, or behind past the end
+                let length = table.last().map_or(0.0, |m| m.0);
+                let ahead = self.position_in(&table, (distance + 0.5).min(length));
+                if ahead.distance(position) > 1e-4 { ahead } else { position + (position - self.position_in(&table, distance - 0.5)) }
+            }
+        };
+        PathSample { position, target, fov_y: self.fov.as_ref().map(|f| f.sample(distance)) }
+    }
+
+    /// Appends a point looking at `target`, for building a path from where the camera has been.
+    /// Bezier paths get handles a third of the way along either side.
+    pub fn add_point(&mut self, position: Vec3, target: Vec3) {
+        if let (Spline::Bezier, Some(&last)) = (self.spline, self.points.last()) {
+            self.points.extend([last.lerp(position, 1.0 / 3.0), last.lerp(position, 2.0 / 3.0)]);
+        }
+        self.points.push(position);
+        let distance = self.length();
+        self.look_at = Some(match self.look_at.take() {
+            Some(curve) => curve.key(distance, target, Ease::SineInOut),
+            None => Curve::new(target),
+        });
+    }
+}
+
+/// Plays one of the scene's paths on a camera, as a cutscene or an editor flythrough.
+pub struct PathPlayer {
+    /// Index into `Scene::camera_paths`.
+    pub path: usize,
+    /// Meters along it.
+    pub distance: f32,
+    pub playing: bool,
+}
+
+impl PathPlayer {
+    pub fn new() -> Self { PathPlayer { path: 0, distance: 0.0, playing: false } }
+
+    /// From the start of `path`.
+    pub fn play(&mut self, path: usize) {
+        self.path = path;
+        self.distance = 0.0;
+        self.playing = true;
+    }
+
+    pub fn stop(&mut self) { self.playing = false; }
+
+    /// Moves along and places `camera`, which is the path's while it plays. Stops at the end of
+    /// paths that don't loop.
+    pub fn update(&mut self, dt: f32, scene: &Scene, camera: &mut Camera) {
+        if !self.playing { return; }
+        let path = match scene.camera_paths.get(self.path) { Some(p) => p, None => return self.stop() };
+        let length = path.length();
+        self.distance += path.speed.sample(self.distance).max(0.0) * dt;
+        if self.distance >= length {
+            if path.looping && length > 0.0 { self.distance %= length; } else { self.distance = length; self.playing = false; }
+        }
+        let sample = path.sample(self.distance);
+        camera.position = sample.position;
+        camera.target = sample.target;
+        if let (Some(fov), Projection::Perspective { fov_y }) = (sample.fov_y, &mut camera.projection) { *fov_y = fov; }
+    }
+}
+
+/// Paths as text, one statement a line:
+///
+/// path <name>, then for it: spline catmull_rom|bezier, looping true|false, point x y z,
+/// look_at meters x y z ease, speed meters value ease, fov meters radians ease
+///
+/// Eases by name, see `Ease`. Blank lines and lines starting with # are skipped.
+pub fn to_text(paths: &[CameraPath]) -> String {
+    let mut out = String::new();
+    for path in paths {
+        writeln!(out, "path {}", path.name).unwrap();
+        writeln!(out, "spline {}", match path.spline { Spline::CatmullRom => "catmull_rom", Spline::Bezier => "bezier" }).unwrap();
+        writeln!(out, "looping {}", path.looping).unwrap();
+        for p in &path.points { writeln!(out, "point {} {} {}", p.x, p.y, p.z).unwrap(); }
+        if let Some(look_at) = &path.look_at { write_curve(&mut out, "look_at", look_at, |v| v.to_array()); }
+        write_curve(&mut out, "speed", &path.speed, |v| [*v]);
+        if let Some(fov) = &path.fov { write_curve(&mut out, "fov", fov, |v| [*v]); }
+        out.push('\n');
+    }
+    out
+}
+
+pub fn parse(text: &str) -> Result<Vec<CameraPath>, String> {
+    let mut paths: Vec<CameraPath> = Vec::new();
+    //speed keys replace the default one
+    let mut speed: Option<Curve<f32>> = None;
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue; }
+        let err = |what: &str| format!("line {}: {}", n + 1, what);
+        let (word, rest) = line.split_once(' ').unwrap_or((line, ""));
+        if word == "path" {
+            if let (Some(p), Some(s)) = (paths.last_mut(), speed.take()) { p.speed = s; }
+            paths.push(CameraPath::new(rest.trim(), Spline::CatmullRom));
+            continue;
+        }
+        let path = paths.last_mut().ok_or_else(|| err("expected a path first"))?;
+        let fields: Vec<&str> = rest.split_whitespace().collect();
+        let numbers = |count: usize| -> Result<Vec<f32>, String> {
+            if fields.len() < count { return Err(err("too few values")); }
+            fields[..count].iter().map(|f| f.parse::<f32>().map_err(|_| err(&format!("bad number {:?}", f)))).collect()
+        };
+        let ease = |at: usize| fields.get(at).map_or(Ok(Ease::Linear), |e| Ease::from_name(e).ok_or_else(|| err(&format!("unknown ease {:?}", e))));
+        match word {
+            "spline" => path.spline = match rest.trim() { "catmull_rom" => Spline::CatmullRom, "bezier" => Spline::Bezier, s => return Err(err(&format!("unknown spline {:?}", s))) },
+            "looping" => path.looping = rest.trim() == "true",
+            "point" => { let v = numbers(3)?; path.points.push(Vec3::new(v[0], v[1], v[2])); }
+            "look_at" => { let v = numbers(4)?; add_key(&mut path.look_at, v[0], Vec3::new(v[1], v[2], v[3]), ease(4)?); }
+            "speed" => { let v = numbers(2)?; add_key(&mut speed, v[0], v[1], ease(2)?); }
+            "fov" => { let v = numbers(2)?; add_key(&mut path.fov, v[0], v[1], ease(2)?); }
+            _ => return Err(err(&format!("unknown statement {:?}", word))),
+        }
+    }
+    if let (Some(p), Some(s)) = (paths.last_mut(), speed) { p.speed = s; }
+    Ok(paths)
+}
+
+/// A file `save` wrote, or one written by hand in the same statements. A bad statement panics
+/// with its line number.
+pub fn load(path: impl AsRef<Path>) -> Vec<CameraPath> {
+    let text = std::fs::read_to_string(path.as_ref()).unwrap_or_else(|e| panic!("failed loading {:?}: {}", path.as_ref(), e));
+    parse(&text).unwrap_or_else(|e| panic!("{:?} {}", path.as_ref(), e))
+}
+
+pub fn save(paths: &[CameraPath], path: impl AsRef<Path>) -> std::io::Result<()> { std::fs::write(path, to_text(paths)) }
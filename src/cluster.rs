@@ -0,0 +1,57 @@
+use vulkano::{ device::Device,
+               buffer::{ BufferAccess, BufferUsage, DeviceLocalBuffer },
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint } };
+use std::sync::Arc;
+use crate::camera::View;
+
+mod cull {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/cluster_cull.comp", include: ["src/shaders"] }
+}
+
+/// Froxels across, down and deep. Keep in step with cluster.glsl.
+pub const CLUSTER_GRID: [u32; 3] = [16, 9, 24];
+/// Lights one cluster can hold.
+pub const CLUSTER_LIGHTS: u32 = 128;
+const CLUSTER_COUNT: u32 = CLUSTER_GRID[0] * CLUSTER_GRID[1] * CLUSTER_GRID[2];
+
+/// Forward+ light culling. Splits the view frustum into froxels and lists the point and spot
+/// lights touching each one, so a fragment only loops over the lights near it instead of every
+/// light in the frame.
+pub struct LightClusters {
+    pipeline: Arc<ComputePipeline>,
+    /// Per cluster, a light count then `CLUSTER_LIGHTS` slots of indices into the light buffer.
+    pub clusters: Arc<DeviceLocalBuffer<[u32]>>,
+}
+
+impl LightClusters {
+    pub fn new(dev: Arc<Device>) -> Self {
+        let shader = cull::load(dev.clone()).unwrap();
+        let pipeline = ComputePipeline::new(dev.clone(), shader.entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let clusters = DeviceLocalBuffer::array(dev.clone(), (CLUSTER_COUNT * (CLUSTER_LIGHTS + 1)) as u64,
+            BufferUsage { storage_buffer: true, ..BufferUsage::none() }, dev.active_queue_families()).unwrap();
+        LightClusters { pipeline, clusters }
+    }
+
+    /// Rebins `count` lights from `lights` for `view`. Compute can't run inside a render pass, so
+    /// record this before the pass whose draws read `clusters`.
+    pub fn cull(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, lights: Arc<dyn BufferAccess>, count: u32, view: &View) {
+        let set = PersistentDescriptorSet::new(self.pipeline.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::buffer(0, lights),
+            WriteDescriptorSet::buffer(1, self.clusters.clone()),
+        ]).unwrap();
+        //froxel corners unproject from the projection's scale alone, mirrored views included; for
+        //orthographic views the scale is the half extent itself
+        let pc = cull::ty::PushConstants {
+            view: view.view.to_cols_array_2d(),
+            proj: [1.0 / view.proj.x_axis.x, 1.0 / view.proj.y_axis.y, view.near, view.far],
+            count,
+            orthographic: view.is_orthographic() as u32,
+        };
+        builder.bind_pipeline_compute(self.pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.pipeline.layout().clone(), 0, set)
+            .push_constants(self.pipeline.layout().clone(), 0, pc)
+            .dispatch([(CLUSTER_COUNT + 63) / 64, 1, 1]).unwrap();
+    }
+}
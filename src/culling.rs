@@ -0,0 +1,71 @@
+use glam::{ Vec3, Vec4, Mat4 };
+use std::collections::HashSet;
+use crate::bvh::Aabb;
+use crate::scene::{ Scene, EntityId };
+use crate::debug_draw::DebugDraw;
+
+pub struct Frustum { planes: [Vec4; 6], corners: [Vec3; 8], }
+
+impl Frustum {
+    /// Planes point inwards; near plane is z = 0 since vulkan depth is 0..1.
+    pub fn from_view_proj(m: &Mat4) -> Self {
+        let (r0, r1, r2, r3) = (m.row(0), m.row(1), m.row(2), m.row(3));
+        let planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2].map(|p| p / p.truncate().length());
+        let inv = m.inverse();
+        let corners = Aabb { min: Vec3::new(-1.0, -1.0, 0.0), max: Vec3::ONE }.corners().map(|c| inv.project_point3(c));
+        Frustum { planes, corners }
+    }
+
+    pub fn intersects_aabb(&self, b: &Aabb) -> bool {
+        self.planes.iter().all(|p| {
+            let n = p.truncate();
+            let positive = Vec3::select(n.cmpge(Vec3::ZERO), b.max, b.min);
+            n.dot(positive) + p.w >= 0.0
+        })
+    }
+
+    pub fn corners(&self) -> &[Vec3; 8] { &self.corners }
+}
+
+pub struct Culling {
+    pub frozen: Option<Mat4>, //cull against a stale camera to inspect results from outside
+    pub show_bounds: bool,
+    pub visible: HashSet<EntityId>,
+    frustum: Option<Frustum>,
+}
+
+const VISIBLE: [f32; 4] = [0.2, 1.0, 0.2, 1.0];
+const CULLED: [f32; 4] = [1.0, 0.2, 0.2, 0.6];
+const SPHERE: [f32; 4] = [0.3, 0.7, 1.0, 0.6];
+const FRUSTUM: [f32; 4] = [1.0, 1.0, 0.2, 1.0];
+
+impl Culling {
+    pub fn new() -> Self { Culling { frozen: None, show_bounds: false, visible: HashSet::new(), frustum: None } }
+
+    pub fn toggle_freeze(&mut self, view_proj: Mat4) {
+        self.frozen = match self.frozen { Some(_) => None, None => Some(view_proj) };
+    }
+
+    /// Expects `scene.update_bounds` to have been called this frame.
+    #[profiling::function]
+    pub fn update(&mut self, scene: &Scene, view_proj: Mat4) {
+        let frustum = Frustum::from_view_proj(&self.frozen.unwrap_or(view_proj));
+        self.visible = scene.bvh.query(|b| frustum.intersects_aabb(b)).into_iter()
+            .filter(|&id| frustum.intersects_aabb(&scene.get(id).unwrap().world_bounds()))
+            .collect();
+        self.frustum = Some(frustum);
+    }
+
+    pub fn is_visible(&self, id: EntityId) -> bool { self.visible.contains(&id) }
+
+    pub fn debug_draw(&self, scene: &Scene, dbg: &mut DebugDraw) {
+        if !self.show_bounds { return; }
+        for e in scene.entities.iter().filter(|e| e.mesh.is_some()) {
+            dbg.aabb(&e.world_bounds(), if self.is_visible(e.id) { VISIBLE } else { CULLED });
+            let (center, radius) = e.world_sphere();
+            dbg.sphere(center, radius, SPHERE);
+        }
+        //the live frustum is only visible from outside, ie. when frozen
+        if let (Some(_), Some(f)) = (self.frozen, &self.frustum) { dbg.corners(f.corners(), FRUSTUM); }
+    }
+}
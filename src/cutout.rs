@@ -0,0 +1,516 @@
+use glam::{ Affine2, Mat4, Vec2, Vec3 };
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{ Path, PathBuf };
+use std::sync::Arc;
+use crate::billboard::{ SpriteMesh, SpriteVertex };
+use crate::texture::Texture;
+use crate::time::Time;
+use crate::tween::{ Curve, Ease, Lerp };
+
+/// A bone or attachment placed in its parent's space. Degrees, like the files.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoneLocal {
+    pub position: Vec2,
+    pub rotation: f32,
+    pub scale: Vec2,
+}
+
+impl BoneLocal {
+    pub fn affine(&self) -> Affine2 { Affine2::from_scale_angle_translation(self.scale, self.rotation.to_radians(), self.position) }
+
+    /// Rotation the shorter way round.
+    pub fn lerp(&self, other: &BoneLocal, t: f32) -> BoneLocal {
+        let turn = (other.rotation - self.rotation + 180.0).rem_euclid(360.0) - 180.0;
+        BoneLocal { position: self.position.lerp(other.position, t), rotation: self.rotation + turn * t, scale: self.scale.lerp(other.scale, t) }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Bone2d {
+    pub name: String,
+    /// Always before the bone.
+    pub parent: Option<usize>,
+    pub length: f32,
+    pub setup: BoneLocal,
+}
+
+/// Where an attachment draws, in draw order.
+#[derive(Clone, Debug)]
+pub struct Slot {
+    pub name: String,
+    pub bone: usize,
+    pub color: [f32; 4],
+    /// Shown in the setup pose.
+    pub attachment: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Attachment {
+    /// A rectangle of the atlas centered on `offset` in its slot's bone.
+    Region { region: String, offset: BoneLocal, size: Vec2 },
+    /// Triangles of an atlas region, `uvs` 0..1 across it. Each vertex follows one or more
+    /// bones: a position in that bone's space and its weight.
+    Mesh { region: String, uvs: Vec<Vec2>, triangles: Vec<usize>, vertices: Vec<Vec<(usize, Vec2, f32)>> },
+}
+
+impl Attachment {
+    pub fn region(&self) -> &str { match self { Attachment::Region { region, .. } | Attachment::Mesh { region, .. } => region } }
+}
+
+/// A rectangle of the atlas page, in pixels as unrotated.
+#[derive(Clone, Copy, Debug)]
+pub struct AtlasRegion {
+    pub position: Vec2,
+    pub size: Vec2,
+    /// Packed turned 90 degrees clockwise.
+    pub rotated: bool,
+}
+
+impl AtlasRegion {
+    /// `local` 0..1 across the region, y down, to the page's uv.
+    pub fn uv(&self, local: Vec2, page: Vec2) -> [f32; 2] {
+        let p = match self.rotated {
+            true => self.position + Vec2::new(local.y * self.size.y, (1.0 - local.x) * self.size.x),
+            false => self.position + local * self.size,
+        };
+        (p / page).to_array()
+    }
+}
+
+/// One page of a libgdx style texture atlas, as Spine exports them. Whitespace stripping isn't
+/// undone; pack without it.
+#[derive(Clone, Debug)]
+pub struct Atlas {
+    pub image: PathBuf,
+    pub size: Vec2,
+    pub regions: HashMap<String, AtlasRegion>,
+}
+
+impl Atlas {
+    /// Spine 3 (`xy` and `size`) and 4 (`bounds`) atlases.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut image: Option<PathBuf> = None;
+        let mut size = Vec2::ZERO;
+        let mut regions = HashMap::new();
+        let mut region: Option<(String, AtlasRegion)> = None;
+        let mut page_next = true;
+        for (n, line) in text.lines().enumerate() {
+            let err = |what: &str| format!("line {}: {}", n + 1, what);
+            let trimmed = line.trim();
+            if trimmed.is_empty() { page_next = true; continue; }
+            let (key, value) = match trimmed.split_once(':') {
+                Some((k, v)) => (k.trim(), v.trim()),
+                None => {
+                    if let Some((name, r)) = region.take() { regions.insert(name, r); }
+                    if page_next && image.is_some() { return Err(err("only single page atlases are supported")); }
+                    match page_next {
+                        true => image = Some(PathBuf::from(trimmed)),
+                        false => region = Some((trimmed.to_string(), AtlasRegion { position: Vec2::ZERO, size: Vec2::ZERO, rotated: false })),
+                    }
+                    page_next = false;
+                    continue;
+                }
+            };
+            let numbers = || value.split(',').map(|v| v.trim().parse::<f32>().map_err(|_| err(&format!("bad number {:?}", v)))).collect::<Result<Vec<f32>, String>>();
+            let pair = |v: Vec<f32>| if v.len() >= 2 { Ok(Vec2::new(v[0], v[1])) } else { Err(err("expected two values")) };
+            match (&mut region, key) {
+                (None, "size") => size = pair(numbers()?)?,
+                (Some((_, r)), "xy") => r.position = pair(numbers()?)?,
+                (Some((_, r)), "size") => r.size = pair(numbers()?)?,
+                (Some((_, r)), "bounds") => {
+                    let v = numbers()?;
+                    if v.len() < 4 { return Err(err("expected four values")); }
+                    (r.position, r.size) = (Vec2::new(v[0], v[1]), Vec2::new(v[2], v[3]));
+                }
+                (Some((_, r)), "rotate") => r.rotated = matches!(value, "true" | "90"),
+                _ => {}
+            }
+        }
+        if let Some((name, r)) = region { regions.insert(name, r); }
+        let image = image.ok_or("missing the page image")?;
+        if size == Vec2::ZERO { return Err("missing the page size".into()); }
+        Ok(Atlas { image, size, regions })
+    }
+}
+
+#[derive(Clone, Debug)]
+struct BoneTimeline {
+    bone: usize,
+    /// Degrees added to the setup rotation.
+    rotate: Option<Curve<f32>>,
+    /// Added to the setup position.
+    translate: Option<Curve<Vec2>>,
+    /// Multiplies the setup scale.
+    scale: Option<Curve<Vec2>>,
+}
+
+#[derive(Clone, Debug)]
+struct SlotTimeline {
+    slot: usize,
+    /// Seconds and what shows from then.
+    attachment: Vec<(f32, Option<String>)>,
+    color: Option<Curve<[f32; 4]>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CutoutAnimation {
+    pub name: String,
+    pub duration: f32,
+    bones: Vec<BoneTimeline>,
+    slots: Vec<SlotTimeline>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlotPose {
+    pub attachment: Option<String>,
+    pub color: [f32; 4],
+}
+
+/// Every bone's local transform and every slot's attachment and color.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CutoutPose {
+    pub bones: Vec<BoneLocal>,
+    pub slots: Vec<SlotPose>,
+}
+
+impl CutoutPose {
+    /// Moves `weight` of the way towards `other`. Attachments switch halfway.
+    pub fn blend(&mut self, other: &CutoutPose, weight: f32) {
+        for (a, b) in self.bones.iter_mut().zip(&other.bones) { *a = a.lerp(b, weight); }
+        for (a, b) in self.slots.iter_mut().zip(&other.slots) {
+            if weight >= 0.5 { a.attachment.clone_from(&b.attachment); }
+            a.color = std::array::from_fn(|i| a.color[i] + (b.color[i] - a.color[i]) * weight);
+        }
+    }
+}
+
+/// A cutout character: bones, the slots hanging sprite parts off them, and the animations
+/// moving them, loaded from Spine's json export and its atlas. Skeleton space is the file's,
+/// y up in pixels. Bezier curves play linear, and ik, paths, deform and draw order timelines
+/// aren't read.
+#[derive(Clone, Debug)]
+pub struct CutoutSkeleton {
+    pub bones: Vec<Bone2d>,
+    pub slots: Vec<Slot>,
+    /// The default skin, by slot and attachment name.
+    pub attachments: HashMap<(usize, String), Attachment>,
+    pub animations: Vec<CutoutAnimation>,
+    pub atlas: Atlas,
+}
+
+fn number(v: &Value, default: f32) -> f32 { v.as_f64().map_or(default, |n| n as f32) }
+
+/// RRGGBB or RRGGBBAA.
+fn color(v: &Value) -> Result<[f32; 4], String> {
+    let hex = match v.as_str() { Some(h) => h, None => return Ok([1.0; 4]) };
+    let bits = u32::from_str_radix(hex, 16).map_err(|_| format!("bad color {:?}", hex))?;
+    let bits = if hex.len() == 6 { bits << 8 | 0xff } else { bits };
+    Ok([24, 16, 8, 0].map(|s| ((bits >> s) & 0xff) as f32 / 255.0))
+}
+
+/// Keys of a timeline; the curve on one key leads to the next, so a stepped key steps into
+/// the one after it.
+fn keys<T: Lerp>(timeline: &Value, value: impl Fn(&Value) -> T) -> Option<Curve<T>> {
+    let keys = timeline.as_array().filter(|k| !k.is_empty())?;
+    let mut curve = Curve::new(value(&keys[0]));
+    curve.keys[0].time = number(&keys[0]["time"], 0.0);
+    for pair in keys.windows(2) {
+        let ease = if pair[0]["curve"].as_str() == Some("stepped") { Ease::Step } else { Ease::Linear };
+        curve = curve.key(number(&pair[1]["time"], 0.0), value(&pair[1]), ease);
+    }
+    Some(curve)
+}
+
+impl CutoutSkeleton {
+    pub fn parse(json: &str, atlas: Atlas) -> Result<Self, String> {
+        let json: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let mut bones: Vec<Bone2d> = Vec::new();
+        for b in json["bones"].as_array().ok_or("missing bones")? {
+            let name = b["name"].as_str().ok_or("bone without a name")?.to_string();
+            let parent = match b["parent"].as_str() {
+                Some(p) => Some(bones.iter().position(|b| b.name == p).ok_or_else(|| format!("bone {:?} comes before its parent {:?}", name, p))?),
+                None => None,
+            };
+            let setup = BoneLocal { position: Vec2::new(number(&b["x"], 0.0), number(&b["y"], 0.0)), rotation: number(&b["rotation"], 0.0),
+                                    scale: Vec2::new(number(&b["scaleX"], 1.0), number(&b["scaleY"], 1.0)) };
+            bones.push(Bone2d { name, parent, length: number(&b["length"], 0.0), setup });
+        }
+        let bone = |name: &str| bones.iter().position(|b| b.name == name).ok_or_else(|| format!("unknown bone {:?}", name));
+        let slots = json["slots"].as_array().map_or(&[][..], |s| s).iter().map(|s| Ok(Slot {
+            name: s["name"].as_str().ok_or("slot without a name")?.to_string(),
+            bone: bone(s["bone"].as_str().unwrap_or(""))?,
+            color: color(&s["color"])?,
+            attachment: s["attachment"].as_str().map(String::from),
+        })).collect::<Result<Vec<_>, String>>()?;
+        let slot = |name: &str| slots.iter().position(|s| s.name == name).ok_or_else(|| format!("unknown slot {:?}", name));
+
+        //3.8 and on list skins, older ones map them by name
+        let skin = match &json["skins"] {
+            Value::Array(skins) => skins.iter().find(|s| s["name"] == "default").map(|s| &s["attachments"]),
+            skins => skins.get("default"),
+        };
+        let mut attachments = HashMap::new();
+        for (slot_name, named) in skin.and_then(|s| s.as_object()).into_iter().flatten() {
+            let s = slot(slot_name)?;
+            for (name, a) in named.as_object().into_iter().flatten() {
+                let region = a["path"].as_str().or_else(|| a["name"].as_str()).unwrap_or(name).to_string();
+                let floats = |v: &Value| v.as_array().map_or(Vec::new(), |v| v.iter().map(|n| number(n, 0.0)).collect::<Vec<f32>>());
+                let attachment = match a["type"].as_str().unwrap_or("region") {
+                    "region" => Attachment::Region {
+                        region,
+                        offset: BoneLocal { position: Vec2::new(number(&a["x"], 0.0), number(&a["y"], 0.0)), rotation: number(&a["rotation"], 0.0),
+                                            scale: Vec2::new(number(&a["scaleX"], 1.0), number(&a["scaleY"], 1.0)) },
+                        size: Vec2::new(number(&a["width"], 0.0), number(&a["height"], 0.0)),
+                    },
+                    "mesh" => {
+                        let uvs: Vec<Vec2> = floats(&a["uvs"]).chunks_exact(2).map(|p| Vec2::new(p[0], p[1])).collect();
+                        let raw = floats(&a["vertices"]);
+                        //plain meshes give a position per uv, weighted ones a bone count then bone, x, y, weight each
+                        let vertices = if raw.len() == uvs.len() * 2 {
+                            raw.chunks_exact(2).map(|p| vec![(slots[s].bone, Vec2::new(p[0], p[1]), 1.0)]).collect()
+                        } else {
+                            let mut vertices = Vec::new();
+                            let mut i = 0;
+                            while i < raw.len() {
+                                let count = raw[i] as usize;
+                                let weights = raw.get(i + 1..i + 1 + count * 4).ok_or_else(|| format!("mesh {:?} has short weights", name))?;
+                                vertices.push(weights.chunks_exact(4).map(|w| (w[0] as usize, Vec2::new(w[1], w[2]), w[3])).collect());
+                                i += 1 + count * 4;
+                            }
+                            vertices
+                        };
+                        let triangles: Vec<usize> = floats(&a["triangles"]).into_iter().map(|t| t as usize).collect();
+                        if vertices.len() != uvs.len() || triangles.iter().any(|&t| t >= uvs.len()) || vertices.iter().flatten().any(|w| w.0 >= bones.len()) {
+                            return Err(format!("mesh {:?} doesn't add up", name));
+                        }
+                        Attachment::Mesh { region, uvs, triangles, vertices }
+                    }
+                    //bounding boxes, clipping, points and paths don't draw
+                    _ => continue,
+                };
+                attachments.insert((s, name.clone()), attachment);
+            }
+        }
+
+        let mut animations = Vec::new();
+        for (name, a) in json["animations"].as_object().into_iter().flatten() {
+            let mut duration: f32 = 0.0;
+            let mut end = |timeline: &Value| for k in timeline.as_array().into_iter().flatten() { duration = duration.max(number(&k["time"], 0.0)); };
+            let mut bone_timelines = Vec::new();
+            for (bone_name, t) in a["bones"].as_object().into_iter().flatten() {
+                for k in ["rotate", "translate", "scale"] { end(&t[k]); }
+                //3.x keys rotation as angle, 4 as value
+                bone_timelines.push(BoneTimeline {
+                    bone: bone(bone_name)?,
+                    rotate: keys(&t["rotate"], |k| number(if k["angle"].is_null() { &k["value"] } else { &k["angle"] }, 0.0)),
+                    translate: keys(&t["translate"], |k| Vec2::new(number(&k["x"], 0.0), number(&k["y"], 0.0))),
+                    scale: keys(&t["scale"], |k| Vec2::new(number(&k["x"], 1.0), number(&k["y"], 1.0))),
+                });
+            }
+            let mut slot_timelines = Vec::new();
+            for (slot_name, t) in a["slots"].as_object().into_iter().flatten() {
+                //3.x names color timelines color, 4 rgba
+                let colors = if t["color"].is_null() { &t["rgba"] } else { &t["color"] };
+                end(&t["attachment"]);
+                end(colors);
+                for k in colors.as_array().into_iter().flatten() { color(&k["color"])?; }
+                slot_timelines.push(SlotTimeline {
+                    slot: slot(slot_name)?,
+                    attachment: t["attachment"].as_array().into_iter().flatten().map(|k| (number(&k["time"], 0.0), k["name"].as_str().map(String::from))).collect(),
+                    color: keys(colors, |k| color(&k["color"]).unwrap()),
+                });
+            }
+            animations.push(CutoutAnimation { name: name.clone(), duration, bones: bone_timelines, slots: slot_timelines });
+        }
+        Ok(CutoutSkeleton { bones, slots, attachments, animations, atlas })
+    }
+
+    /// Spine's json export with its .atlas of the same name next to it, Spine 3 or 4; the atlas
+    /// image comes back relative to where the atlas is.
+    #[profiling::function]
+    pub fn load(path: impl AsRef<Path>) -> Arc<Self> {
+        let path = path.as_ref();
+        let read = |p: &Path| std::fs::read_to_string(p).unwrap_or_else(|e| panic!("failed loading {:?}: {}", p, e));
+        let atlas_path = path.with_extension("atlas");
+        let mut atlas = Atlas::parse(&read(&atlas_path)).unwrap_or_else(|e| panic!("{:?} {}", atlas_path, e));
+        atlas.image = atlas_path.parent().map_or(atlas.image.clone(), |dir| dir.join(&atlas.image));
+        Arc::new(Self::parse(&read(path), atlas).unwrap_or_else(|e| panic!("{:?} {}", path, e)))
+    }
+
+    pub fn animation(&self, name: &str) -> Option<usize> { self.animations.iter().position(|a| a.name == name) }
+
+    pub fn setup_pose(&self) -> CutoutPose {
+        CutoutPose { bones: self.bones.iter().map(|b| b.setup).collect(),
+                     slots: self.slots.iter().map(|s| SlotPose { attachment: s.attachment.clone(), color: s.color }).collect() }
+    }
+
+    /// Sets what `animation` moves to how it is `time` seconds in, over the setup pose.
+    pub fn sample(&self, animation: usize, time: f32, pose: &mut CutoutPose) {
+        let a = &self.animations[animation];
+        for t in &a.bones {
+            let (setup, bone) = (self.bones[t.bone].setup, &mut pose.bones[t.bone]);
+            if let Some(c) = &t.rotate { bone.rotation = setup.rotation + c.sample(time); }
+            if let Some(c) = &t.translate { bone.position = setup.position + c.sample(time); }
+            if let Some(c) = &t.scale { bone.scale = setup.scale * c.sample(time); }
+        }
+        for t in &a.slots {
+            let slot = &mut pose.slots[t.slot];
+            if let Some(c) = &t.color { slot.color = c.sample(time); }
+            if let Some((_, name)) = t.attachment.iter().rev().find(|(at, _)| *at <= time) { slot.attachment.clone_from(name); }
+        }
+    }
+
+    /// Every bone to skeleton space.
+    pub fn world(&self, pose: &CutoutPose) -> Vec<Affine2> {
+        let mut world: Vec<Affine2> = Vec::with_capacity(self.bones.len());
+        for (b, local) in self.bones.iter().zip(&pose.bones) {
+            let parent = b.parent.map_or(Affine2::IDENTITY, |p| world[p]);
+            world.push(parent * local.affine());
+        }
+        world
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PlayingCutout {
+    /// Index into `CutoutSkeleton::animations`.
+    pub animation: usize,
+    pub time: f32,
+    pub speed: f32,
+    /// Relative to the other animations playing; the weights needn't add up to 1.
+    pub weight: f32,
+    pub looping: bool,
+    /// Weight gained per second, negative fading out; it's dropped once faded out.
+    pub fade: f32,
+}
+
+impl PlayingCutout {
+    pub fn new(animation: usize) -> Self { PlayingCutout { animation, time: 0.0, speed: 1.0, weight: 1.0, looping: true, fade: 0.0 } }
+}
+
+/// Plays a cutout skeleton's animations and blends them by weight, like `AnimationPlayer` does
+/// for 3d skeletons.
+#[derive(Clone, Debug, Default)]
+pub struct CutoutPlayer {
+    pub tracks: Vec<PlayingCutout>,
+}
+
+impl CutoutPlayer {
+    /// Replaces whatever was playing.
+    pub fn play(&mut self, animation: usize) { self.tracks = vec![PlayingCutout::new(animation)]; }
+
+    /// Plays `animation` alongside the others, blended in at `weight`.
+    pub fn add(&mut self, animation: usize, weight: f32) { self.tracks.push(PlayingCutout { weight, ..PlayingCutout::new(animation) }); }
+
+    /// Fades `animation` in over `seconds` while everything else fades out.
+    pub fn crossfade(&mut self, animation: usize, seconds: f32) {
+        if seconds <= 0.0 { return self.play(animation); }
+        for t in &mut self.tracks { t.fade = -t.weight / seconds; }
+        self.tracks.push(PlayingCutout { weight: 0.0, fade: 1.0 / seconds, ..PlayingCutout::new(animation) });
+    }
+
+    pub fn is_playing(&self) -> bool { !self.tracks.is_empty() }
+
+    /// Advances every track by `dt` seconds. Looping ones wrap, the others stop at their end.
+    pub fn update(&mut self, skeleton: &CutoutSkeleton, dt: f32) {
+        for t in &mut self.tracks {
+            let duration = skeleton.animations.get(t.animation).map_or(0.0, |a| a.duration);
+            t.time += dt * t.speed;
+            t.time = if t.looping && duration > 0.0 { t.time.rem_euclid(duration) } else { t.time.clamp(0.0, duration) };
+            if t.fade != 0.0 {
+                t.weight = (t.weight + t.fade * dt).clamp(0.0, 1.0);
+                if t.weight >= 1.0 { t.fade = 0.0; }
+            }
+        }
+        self.tracks.retain(|t| t.fade >= 0.0 || t.weight > 0.0);
+    }
+
+    /// The weighted average of every track; the setup pose if none are playing.
+    pub fn sample(&self, skeleton: &CutoutSkeleton) -> CutoutPose {
+        let setup = skeleton.setup_pose();
+        let mut pose = setup.clone();
+        let mut total = 0.0;
+        for t in self.tracks.iter().filter(|t| t.weight > 0.0 && t.animation < skeleton.animations.len()) {
+            let mut sampled = setup.clone();
+            skeleton.sample(t.animation, t.time, &mut sampled);
+            total += t.weight;
+            pose.blend(&sampled, t.weight / total);
+        }
+        pose
+    }
+}
+
+/// A posed cutout skeleton in the scene, drawn as one sprite mesh with the billboards.
+pub struct Cutout {
+    pub skeleton: Arc<CutoutSkeleton>,
+    /// The atlas page.
+    pub texture: Arc<Texture>,
+    pub normal_map: Option<Arc<Texture>>,
+    /// Skeleton space to world; by default a hundred pixels to the meter, facing +z.
+    pub transform: Mat4,
+    pub player: CutoutPlayer,
+    /// Multiplies every slot's.
+    pub color: [f32; 4],
+    pub layer: usize,
+    pub sort_key: f32,
+    /// Set by `update` while the player plays; set it directly for poses from elsewhere.
+    pub pose: CutoutPose,
+}
+
+impl Cutout {
+    pub fn new(skeleton: Arc<CutoutSkeleton>, texture: Arc<Texture>) -> Self {
+        Cutout { pose: skeleton.setup_pose(), skeleton, texture, normal_map: None, transform: Mat4::from_scale(Vec3::splat(0.01)),
+                 player: CutoutPlayer::default(), color: [1.0; 4], layer: 0, sort_key: 0.0 }
+    }
+
+    /// Plays the animation by name, crossfading from the last over `fade` seconds. False if
+    /// there's none by that name.
+    pub fn play(&mut self, name: &str, fade: f32) -> bool {
+        let animation = match self.skeleton.animation(name) { Some(a) => a, None => return false };
+        self.player.crossfade(animation, fade);
+        true
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if !self.player.is_playing() { return; }
+        self.player.update(&self.skeleton, dt);
+        self.pose = self.player.sample(&self.skeleton);
+    }
+
+    /// The posed attachments as triangles in slot order.
+    pub fn mesh(&self) -> SpriteMesh {
+        let (skeleton, atlas) = (&self.skeleton, &self.skeleton.atlas);
+        let world = skeleton.world(&self.pose);
+        let to_world = |p: Vec2| self.transform.transform_point3(p.extend(0.0));
+        let mut vertices = Vec::new();
+        for (i, (slot, state)) in skeleton.slots.iter().zip(&self.pose.slots).enumerate() {
+            let attachment = match state.attachment.as_ref().and_then(|name| skeleton.attachments.get(&(i, name.clone()))) { Some(a) => a, None => continue };
+            let region = match atlas.regions.get(attachment.region()) { Some(r) => r, None => continue };
+            let color = std::array::from_fn(|c| self.color[c] * state.color[c]);
+            match attachment {
+                Attachment::Region { offset, size, .. } => {
+                    let m = world[slot.bone] * offset.affine();
+                    let corner = |x: f32, y: f32| SpriteVertex { position: to_world(m.transform_point2(Vec2::new(x, y) * *size)),
+                                                                 uv: region.uv(Vec2::new(x + 0.5, 0.5 - y), atlas.size), color };
+                    let [a, b, c, d] = [corner(-0.5, -0.5), corner(0.5, -0.5), corner(0.5, 0.5), corner(-0.5, 0.5)];
+                    vertices.extend([a, b, c, a, c, d]);
+                }
+                Attachment::Mesh { uvs, triangles, vertices: bound, .. } => {
+                    let points: Vec<Vec3> = bound.iter()
+                        .map(|weights| to_world(weights.iter().fold(Vec2::ZERO, |p, &(bone, at, w)| p + world[bone].transform_point2(at) * w)))
+                        .collect();
+                    vertices.extend(triangles.iter().map(|&t| SpriteVertex { position: points[t], uv: region.uv(uvs[t], atlas.size), color }));
+                }
+            }
+        }
+        SpriteMesh { texture: self.texture.clone(), vertices, position: self.transform.w_axis.truncate(),
+                     right: self.transform.x_axis.truncate().normalize_or_zero(), up: self.transform.y_axis.truncate().normalize_or_zero(),
+                     layer: self.layer, sort_key: self.sort_key, normal_map: self.normal_map.clone() }
+    }
+}
+
+/// Steps every cutout's animations. Once a frame, like `sprite::animate`.
+pub fn animate(time: &Time, cutouts: &mut [Cutout]) {
+    for c in cutouts { c.update(time.delta); }
+}
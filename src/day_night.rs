@@ -0,0 +1,92 @@
+use glam::{ Mat4, Quat, Vec3 };
+use crate::scene::{ Scene, EntityId };
+use crate::renderer::Renderer;
+use crate::sky::ProceduralSky;
+use crate::skybox::{ Skybox, SkyboxSource };
+use crate::time::Time;
+
+/// Approximate blackbody color, normalized so the brightest channel is 1. Good from about
+/// 1000K to 40000K.
+pub fn kelvin(k: f32) -> Vec3 {
+    let t = k.clamp(1000.0, 40000.0) / 100.0;
+    let r = if t <= 66.0 { 255.0 } else { 329.7 * (t - 60.0).powf(-0.1332) };
+    let g = if t <= 66.0 { 99.47 * t.ln() - 161.12 } else { 288.12 * (t - 60.0).powf(-0.0755) };
+    let b = if t >= 66.0 { 255.0 } else if t <= 19.0 { 0.0 } else { 138.52 * (t - 10.0).ln() - 305.04 };
+    let c = Vec3::new(r, g, b).clamp(Vec3::ZERO, Vec3::splat(255.0)) / 255.0;
+    c / c.max_element()
+}
+
+/// Time of day. Moves the sun and moon lights, tints them, and rebakes the procedural sky and
+/// scene environment every `rebake_minutes` of game time, marking reflection probes stale so
+/// they recapture over the next frames within their budget. Light probe grids and lightmaps
+/// are left alone, they are too slow to keep up.
+pub struct DayNight {
+    /// Hours, 0..24, noon at 12.
+    pub hour: f32,
+    /// Real seconds for a whole day at time scale 1.
+    pub day_length: f32,
+    /// Tilts the sun's path away from straight overhead, radians.
+    pub tilt: f32,
+    /// Directional light entities that get moved. The moon is optional.
+    pub sun: EntityId,
+    pub moon: Option<EntityId>,
+    pub sun_intensity: f32,
+    pub moon_intensity: f32,
+    /// Moonlight color temperature; the sun's comes from the atmosphere.
+    pub moon_kelvin: f32,
+    pub sky: ProceduralSky,
+    pub sky_resolution: u32,
+    pub rebake_minutes: f32,
+    baked_hour: Option<f32>,
+}
+
+impl DayNight {
+    pub fn new(sun: EntityId, hour: f32) -> Self {
+        DayNight { hour, day_length: 600.0, tilt: 0.5, sun, moon: None, sun_intensity: 3.0, moon_intensity: 0.15, moon_kelvin: 4100.0,
+                   sky: ProceduralSky::default(), sky_resolution: 128, rebake_minutes: 10.0, baked_hour: None }
+    }
+
+    /// Towards the sun at the current hour.
+    pub fn sun_direction(&self) -> Vec3 {
+        let angle = (self.hour - 12.0) / 24.0 * std::f32::consts::TAU;
+        Quat::from_rotation_x(-self.tilt) * Vec3::new(angle.sin(), angle.cos(), 0.0)
+    }
+
+    pub fn update(&mut self, time: &Time, renderer: &Renderer, scene: &mut Scene) {
+        self.hour = (self.hour + time.delta * 24.0 / self.day_length).rem_euclid(24.0);
+        let to_sun = self.sun_direction();
+        self.sky.sun_direction = to_sun;
+
+        let sun_color = self.sky.sun_color();
+        place(scene, self.sun, to_sun, sun_color, self.sun_intensity);
+        if let Some(moon) = self.moon {
+            //fades in as the sun sets so there is always something casting shadows
+            let night = (-to_sun.y * 4.0).clamp(0.0, 1.0);
+            place(scene, moon, -to_sun, kelvin(self.moon_kelvin), self.moon_intensity * night);
+        }
+
+        let due = self.baked_hour.map_or(true, |h| {
+            let minutes = (self.hour - h).rem_euclid(24.0) * 60.0;
+            minutes >= self.rebake_minutes
+        });
+        if !due { return; }
+        self.baked_hour = Some(self.hour);
+        let environment = renderer.ibl.bake_sky(&self.sky, self.sky_resolution);
+        scene.skybox = Some(Skybox::new(SkyboxSource::Cube(environment.cube.clone())));
+        scene.environment = Some(environment);
+        for e in scene.entities.iter_mut() {
+            if let Some(p) = &mut e.probe { p.mark_stale(); }
+        }
+    }
+}
+
+/// Points a directional light entity along -`to_light` and sets its color, keeping its position.
+fn place(scene: &mut Scene, id: EntityId, to_light: Vec3, color: Vec3, intensity: f32) {
+    let e = match scene.get_mut(id) { Some(e) => e, None => return };
+    let up = if to_light.y.abs() > 0.99 { Vec3::Z } else { Vec3::Y };
+    e.transform = Mat4::look_at_rh(e.position(), e.position() - to_light, up).inverse();
+    if let Some(light) = &mut e.light {
+        light.color = color.into();
+        light.intensity = intensity;
+    }
+}
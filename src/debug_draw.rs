@@ -0,0 +1,130 @@
+use glam::{ Vec3, Mat4 };
+use crate::bvh::Aabb;
+use crate::overlay::LineVertex;
+
+/// Immediate mode line drawing. Everything pushed between `begin_frame` calls ends up in one
+/// vertex buffer drawn by the overlay pass.
+pub struct DebugDraw {
+    lines: Vec<LineVertex>,
+    right: Vec3,
+    up: Vec3,
+    pub enabled: bool,
+}
+
+const SPHERE_SEGMENTS: usize = 32;
+const BOX_EDGES: [(usize, usize); 12] = [(0, 1), (2, 3), (4, 5), (6, 7), (0, 2), (1, 3), (4, 6), (5, 7), (0, 4), (1, 5), (2, 6), (3, 7)];
+
+/* Sixteen segment stroke font on a 1x2 cell, y up:
+   0,1 top halves, 2,3 right, 4,5 bottom halves, 6,7 left, 8,9 middle halves,
+   10,11 center vertical, 12..15 diagonals from the corners to the center. */
+const SEGMENTS: [[f32; 4]; 16] = [
+    [0.0, 2.0, 0.5, 2.0], [0.5, 2.0, 1.0, 2.0], [1.0, 2.0, 1.0, 1.0], [1.0, 1.0, 1.0, 0.0],
+    [1.0, 0.0, 0.5, 0.0], [0.5, 0.0, 0.0, 0.0], [0.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 2.0],
+    [0.0, 1.0, 0.5, 1.0], [0.5, 1.0, 1.0, 1.0], [0.5, 2.0, 0.5, 1.0], [0.5, 1.0, 0.5, 0.0],
+    [0.0, 2.0, 0.5, 1.0], [1.0, 2.0, 0.5, 1.0], [0.0, 0.0, 0.5, 1.0], [1.0, 0.0, 0.5, 1.0],
+];
+
+const fn seg(bits: &[u32]) -> u16 {
+    let mut mask = 0u16;
+    let mut i = 0;
+    while i < bits.len() { mask |= 1 << bits[i]; i += 1; }
+    mask
+}
+
+fn glyph(c: char) -> u16 {
+    match c.to_ascii_uppercase() {
+        '0' => seg(&[0, 1, 2, 3, 4, 5, 6, 7, 13, 14]), '1' => seg(&[2, 3, 13]),
+        '2' => seg(&[0, 1, 2, 9, 8, 6, 5, 4]), '3' => seg(&[0, 1, 2, 3, 4, 5, 9]),
+        '4' => seg(&[7, 8, 9, 2, 3]), '5' => seg(&[0, 1, 7, 8, 9, 3, 4, 5]),
+        '6' => seg(&[0, 1, 7, 6, 5, 4, 3, 9, 8]), '7' => seg(&[0, 1, 2, 3]),
+        '8' => seg(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]), '9' => seg(&[0, 1, 2, 3, 4, 5, 7, 8, 9]),
+        'A' => seg(&[0, 1, 2, 3, 6, 7, 8, 9]), 'B' => seg(&[0, 1, 2, 3, 4, 5, 9, 10, 11]),
+        'C' => seg(&[0, 1, 7, 6, 5, 4]), 'D' => seg(&[0, 1, 2, 3, 4, 5, 10, 11]),
+        'E' => seg(&[0, 1, 7, 6, 5, 4, 8]), 'F' => seg(&[0, 1, 7, 6, 8]),
+        'G' => seg(&[0, 1, 7, 6, 5, 4, 3, 9]), 'H' => seg(&[7, 6, 2, 3, 8, 9]),
+        'I' => seg(&[0, 1, 10, 11, 4, 5]), 'J' => seg(&[2, 3, 4, 5, 6]),
+        'K' => seg(&[7, 6, 8, 13, 15]), 'L' => seg(&[7, 6, 5, 4]),
+        'M' => seg(&[7, 6, 2, 3, 12, 13]), 'N' => seg(&[7, 6, 2, 3, 12, 15]),
+        'O' => seg(&[0, 1, 2, 3, 4, 5, 6, 7]), 'P' => seg(&[0, 1, 2, 7, 6, 8, 9]),
+        'Q' => seg(&[0, 1, 2, 3, 4, 5, 6, 7, 15]), 'R' => seg(&[0, 1, 2, 7, 6, 8, 9, 15]),
+        'S' => seg(&[0, 1, 7, 8, 9, 3, 4, 5]), 'T' => seg(&[0, 1, 10, 11]),
+        'U' => seg(&[7, 6, 5, 4, 3, 2]), 'V' => seg(&[7, 6, 14, 13]),
+        'W' => seg(&[7, 6, 2, 3, 14, 15]), 'X' => seg(&[12, 13, 14, 15]),
+        'Y' => seg(&[12, 13, 11]), 'Z' => seg(&[0, 1, 13, 14, 5, 4]),
+        '-' => seg(&[8, 9]), '+' => seg(&[8, 9, 10, 11]), '=' => seg(&[8, 9, 4, 5]),
+        '_' => seg(&[4, 5]), '/' => seg(&[13, 14]), '(' | '<' => seg(&[13, 15]), ')' | '>' => seg(&[12, 14]),
+        '.' | ',' => seg(&[5]), ':' => seg(&[10]),
+        _ => 0,
+    }
+}
+
+impl DebugDraw {
+    pub fn new() -> Self { DebugDraw { lines: Vec::new(), right: Vec3::X, up: Vec3::Y, enabled: true } }
+
+    /// Clears last frame's batch and grabs the camera basis text3d billboards against.
+    pub fn begin_frame(&mut self, view: &Mat4) {
+        self.lines.clear();
+        let inv = view.inverse();
+        self.right = inv.x_axis.truncate();
+        self.up = inv.y_axis.truncate();
+    }
+
+    pub fn lines(&self) -> &[LineVertex] { if self.enabled { &self.lines } else { &[] } }
+
+    pub fn line(&mut self, a: Vec3, b: Vec3, color: [f32; 4]) {
+        self.lines.push(LineVertex::new(a, color));
+        self.lines.push(LineVertex::new(b, color));
+    }
+
+    /// Segment from `origin` to `origin + dir`, with a small arrow head.
+    pub fn ray(&mut self, origin: Vec3, dir: Vec3, color: [f32; 4]) {
+        let tip = origin + dir;
+        self.line(origin, tip, color);
+        let len = dir.length();
+        if len <= f32::EPSILON { return; }
+        let (u, v) = (dir / len).any_orthonormal_pair();
+        let back = tip - dir * 0.1;
+        for side in [u, -u, v, -v] { self.line(tip, back + side * len * 0.05, color); }
+    }
+
+    pub fn circle(&mut self, center: Vec3, normal: Vec3, radius: f32, color: [f32; 4]) {
+        let (u, v) = normal.normalize().any_orthonormal_pair();
+        let point = |j: usize| {
+            let a = j as f32 / SPHERE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + (u * a.cos() + v * a.sin()) * radius
+        };
+        for j in 0..SPHERE_SEGMENTS { self.line(point(j), point(j + 1), color); }
+    }
+
+    /// Three axis aligned great circles.
+    pub fn sphere(&mut self, center: Vec3, radius: f32, color: [f32; 4]) {
+        for n in [Vec3::X, Vec3::Y, Vec3::Z] { self.circle(center, n, radius, color); }
+    }
+
+    /// Edges of a box given its corners in `Aabb::corners` order, for frusta and other skewed boxes.
+    pub fn corners(&mut self, c: &[Vec3; 8], color: [f32; 4]) {
+        for (a, b) in BOX_EDGES { self.line(c[a], c[b], color); }
+    }
+
+    pub fn aabb(&mut self, b: &Aabb, color: [f32; 4]) { self.corners(&b.corners(), color); }
+
+    /// Unit cube centered on the origin, transformed by `m`.
+    pub fn obb(&mut self, m: &Mat4, color: [f32; 4]) {
+        let unit = Aabb { min: Vec3::splat(-0.5), max: Vec3::splat(0.5) };
+        self.corners(&unit.corners().map(|c| m.transform_point3(c)), color);
+    }
+
+    /// Camera facing stroke text, `size` is the glyph height in world units. Left aligned at `pos`.
+    pub fn text3d(&mut self, pos: Vec3, text: &str, size: f32, color: [f32; 4]) {
+        let scale = size * 0.5;
+        let (right, up) = (self.right * scale, self.up * scale);
+        for (i, c) in text.chars().enumerate() {
+            let origin = pos + right * (i as f32 * 1.5);
+            let mask = glyph(c);
+            for (bit, s) in SEGMENTS.iter().enumerate() {
+                if mask & (1 << bit) == 0 { continue; }
+                self.line(origin + right * s[0] + up * s[1], origin + right * s[2] + up * s[3], color);
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet, layout::DescriptorSetLayout },
+               image::{ AttachmentImage, ImageUsage, view::ImageView },
+               render_pass::{ RenderPass, Subpass },
+               sampler::{ Sampler, SamplerCreateInfo },
+               pipeline::{ ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint,
+                           graphics::{ input_assembly::InputAssemblyState, vertex_input::BuffersDefinition, viewport::ViewportState,
+                                       color_blend::{ ColorBlendState, AttachmentBlend, BlendOp, BlendFactor } } },
+               shader::ShaderModule,
+               format::Format };
+use std::sync::Arc;
+use crate::picking;
+use crate::camera::View;
+use crate::renderer::{ self, Target };
+
+/// Deferred needs world positions back from depth, so more precision than the forward pass.
+pub const DEPTH_FORMAT: Format = Format::D32_SFLOAT;
+/// albedo + metallic, normal + roughness, geometric normal + shininess, baked diffuse.
+pub const GBUFFER_FORMATS: [Format; 4] = [Format::R8G8B8A8_SRGB, Format::R16G16B16A16_SFLOAT, Format::R16G16B16A16_SFLOAT, Format::R16G16B16A16_SFLOAT];
+/// What the tiled resolve writes: lit and fogged rgb, and the fog transmittance in alpha for
+/// the emissive already in color.
+pub const LIT_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+/// Pixels per side of a light culling tile. Keep in step with tiled.comp.
+pub const TILE_SIZE: u32 = 16;
+
+mod gbuffer_fs {
+    vulkano_shaders::shader! { ty: "fragment", path: "src/shaders/gbuffer.frag", include: ["src/shaders"] }
+}
+mod tiled {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/tiled.comp", include: ["src/shaders"] }
+}
+mod composite_vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			void main() {
+				gl_Position = vec4(vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0, 0.0, 1.0);
+			}"
+    }
+}
+mod composite_fs {
+    vulkano_shaders::shader! { ty: "fragment",
+    src: "#version 450
+
+			layout(set = 0, binding = 0) uniform sampler2D u_lit;
+
+			layout(location = 0) out vec4 f_color;
+
+			void main() {
+				f_color = texelFetch(u_lit, ivec2(gl_FragCoord.xy), 0);
+			}"
+    }
+}
+
+/// Same attachments as the forward pass plus the g-buffer, all stored for the tiled resolve.
+/// Opaque geometry fills the g-buffer; the skybox and portals draw straight to color.
+pub fn render_pass(dev: Arc<Device>, color_format: Format) -> Arc<RenderPass> {
+    vulkano::ordered_passes_renderpass!( dev,
+        attachments: { color: { load: Clear, store: Store, format: color_format, samples: 1,},
+                       id: { load: Clear, store: Store, format: picking::ID_FORMAT, samples: 1,},
+                       velocity: { load: Clear, store: Store, format: renderer::VELOCITY_FORMAT, samples: 1,},
+                       depth: { load: Clear, store: Store, format: DEPTH_FORMAT, samples: 1,},
+                       albedo: { load: Clear, store: Store, format: GBUFFER_FORMATS[0], samples: 1,},
+                       normal: { load: Clear, store: Store, format: GBUFFER_FORMATS[1], samples: 1,},
+                       material: { load: Clear, store: Store, format: GBUFFER_FORMATS[2], samples: 1,},
+                       baked: { load: Clear, store: Store, format: GBUFFER_FORMATS[3], samples: 1,}},
+        passes: [ { color: [color, id, velocity, albedo, normal, material, baked], depth_stencil: {depth}, input: [] } ]
+        ).unwrap()
+}
+
+/// After the tiled resolve: blends the lit image onto color, then billboards go on top as in forward.
+pub fn composite_pass(dev: Arc<Device>, color_format: Format) -> Arc<RenderPass> {
+    vulkano::ordered_passes_renderpass!( dev,
+        attachments: { color: { load: Load, store: Store, format: color_format, samples: 1,},
+                       depth: { load: Load, store: Store, format: DEPTH_FORMAT, samples: 1,}},
+        passes: [ { color: [color], depth_stencil: {}, input: [depth] } ]
+        ).unwrap()
+}
+
+/// Replaces standard.frag when the renderer is deferred.
+pub fn gbuffer_shader(dev: Arc<Device>) -> Arc<ShaderModule> { gbuffer_fs::load(dev).unwrap() }
+
+/// Sampled by the tiled resolve and read as an input attachment by billboards.
+pub fn depth_view(dev: &Arc<Device>, extent: [u32; 2]) -> Arc<ImageView<AttachmentImage>> {
+    ImageView::new_default(AttachmentImage::with_usage(dev.clone(), extent, DEPTH_FORMAT,
+        ImageUsage { depth_stencil_attachment: true, input_attachment: true, sampled: true, ..ImageUsage::none() }).unwrap()).unwrap()
+}
+
+pub fn gbuffer_views(dev: &Arc<Device>, extent: [u32; 2]) -> Vec<Arc<ImageView<AttachmentImage>>> {
+    GBUFFER_FORMATS.iter().map(|&f| ImageView::new_default(AttachmentImage::with_usage(dev.clone(), extent, f,
+        ImageUsage { color_attachment: true, sampled: true, ..ImageUsage::none() }).unwrap()).unwrap()).collect()
+}
+
+pub fn lit_view(dev: &Arc<Device>, extent: [u32; 2]) -> Arc<ImageView<AttachmentImage>> {
+    ImageView::new_default(AttachmentImage::with_usage(dev.clone(), extent, LIT_FORMAT,
+        ImageUsage { storage: true, sampled: true, ..ImageUsage::none() }).unwrap()).unwrap()
+}
+
+/// Tiled lighting over the g-buffer. Each 16x16 tile finds its depth range, culls the frame's
+/// point lights against that slice of the frustum once, then shades its pixels with only the
+/// lights that survived.
+pub struct Resolve {
+    tiled: Arc<ComputePipeline>,
+    composite: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+}
+
+impl Resolve {
+    /// `subpass` is the composite pass, where the lit image gets added.
+    pub fn new(dev: Arc<Device>, subpass: Subpass) -> Self {
+        let cs = tiled::load(dev.clone()).unwrap();
+        let tiled = ComputePipeline::new(dev.clone(), cs.entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let vs = composite_vs::load(dev.clone()).unwrap();
+        let fs = composite_fs::load(dev.clone()).unwrap();
+        let composite = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            //color * fog transmittance + lit, so emissive gets fogged along with the rest
+            .color_blend_state(ColorBlendState::new(1).blend(AttachmentBlend {
+                color_op: BlendOp::Add, color_source: BlendFactor::One, color_destination: BlendFactor::SrcAlpha,
+                alpha_op: BlendOp::Add, alpha_source: BlendFactor::Zero, alpha_destination: BlendFactor::One }))
+            .render_pass(subpass)
+            .build(dev.clone()).unwrap();
+        let sampler = Sampler::new(dev, SamplerCreateInfo::default()).unwrap();
+        Resolve { tiled, composite, sampler }
+    }
+
+    /// Layout of the frame set (set 0) the resolve shades with.
+    pub fn frame_layout(&self) -> Arc<DescriptorSetLayout> { self.tiled.layout().set_layouts().get(0).unwrap().clone() }
+
+    /// Lights the g-buffer into `target.lit`. Record between the g-buffer and composite passes,
+    /// after the ssao, ssr and ray traced passes.
+    pub fn light(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, frame_set: Arc<PersistentDescriptorSet>, target: &Target, view: &View) {
+        let lit = target.lit.clone().unwrap();
+        let layout = self.tiled.layout().set_layouts().get(1).unwrap();
+        let mut writes = vec![WriteDescriptorSet::image_view_sampler(0, target.depth.clone(), self.sampler.clone())];
+        writes.extend(target.gbuffer.iter().enumerate().map(|(i, g)| WriteDescriptorSet::image_view_sampler(i as u32 + 1, g.clone(), self.sampler.clone())));
+        writes.push(WriteDescriptorSet::image_view(5, lit));
+        writes.push(WriteDescriptorSet::image_view_sampler(6, target.ao[0].clone(), self.sampler.clone()));
+        writes.push(WriteDescriptorSet::image_view_sampler(7, target.reflections[0].clone(), self.sampler.clone()));
+        writes.push(WriteDescriptorSet::image_view_sampler(8, target.rt_shadow.clone().unwrap(), self.sampler.clone()));
+        let gbuffer_set = PersistentDescriptorSet::new(layout.clone(), writes).unwrap();
+        let pc = tiled::ty::PushConstants { view: view.view.to_cols_array_2d(), inv_proj: view.proj.inverse().to_cols_array_2d() };
+        let groups = [(target.extent[0] + TILE_SIZE - 1) / TILE_SIZE, (target.extent[1] + TILE_SIZE - 1) / TILE_SIZE, 1];
+        builder.bind_pipeline_compute(self.tiled.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.tiled.layout().clone(), 0, vec![frame_set, gbuffer_set])
+            .push_constants(self.tiled.layout().clone(), 0, pc)
+            .dispatch(groups).unwrap();
+    }
+
+    /// Blends the lit image onto color. First thing in the composite pass.
+    pub fn composite(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, target: &Target) {
+        let layout = self.composite.layout().set_layouts().get(0).unwrap();
+        let set = PersistentDescriptorSet::new(layout.clone(), [
+            WriteDescriptorSet::image_view_sampler(0, target.lit.clone().unwrap(), self.sampler.clone())]).unwrap();
+        builder.bind_pipeline_graphics(self.composite.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, self.composite.layout().clone(), 0, set)
+            .draw(3, 1, 0, 0).unwrap();
+    }
+}
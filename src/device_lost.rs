@@ -0,0 +1,170 @@
+use vulkano::{ buffer::{ BufferUsage, sys::{ UnsafeBuffer, UnsafeBufferCreateInfo } },
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, CommandBufferUsage },
+               device::{ Device, Queue },
+               memory::{ DeviceMemory, MappedDeviceMemory, MemoryAllocateInfo },
+               sync::GpuFuture,
+               VulkanObject, DeviceSize };
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::sync::Arc;
+use std::time::{ Duration, Instant };
+use crate::gpu_memory::{ GpuMemory, MemoryCategory };
+use crate::raw_commands::RawCommands;
+
+/// Frames of breadcrumbs kept, the one lost and a couple before it.
+const FRAMES: usize = 3;
+/// Marks a frame can leave; later ones aren't written.
+const MAX_MARKS: usize = 32;
+/// Devices lost in a row before the engine gives up and panics.
+const MAX_RESTARTS: u32 = 3;
+/// Losing it again sooner than this after starting over counts as in a row.
+const IN_A_ROW: Duration = Duration::from_secs(60);
+/// Appended to in the working directory, a report per lost device.
+pub const LOG: &str = "device_lost.log";
+
+/// Sets one frame's count, recorded once for every frame that gets that far. Its buffer is raw
+/// memory vulkano doesn't track, so there's nothing in it for vulkano to lock.
+fn crumb(queue: &Arc<Queue>, buffer: &UnsafeBuffer, offset: DeviceSize, count: u32) -> Arc<RawCommands> {
+    let marker = queue.device().enabled_extensions().amd_buffer_marker;
+    Arc::new(RawCommands::record(queue, CommandBufferUsage::SimultaneousUse, |fns, cb| unsafe {
+        if marker {
+            //written once everything before it in the queue is done
+            (fns.amd_buffer_marker.cmd_write_buffer_marker_amd)(cb, ash::vk::PipelineStageFlags::BOTTOM_OF_PIPE, buffer.internal_object(), offset, count);
+        } else {
+            //without the marker a fill has to wait on everything before it itself
+            let barrier = ash::vk::MemoryBarrier { src_access_mask: ash::vk::AccessFlags::MEMORY_WRITE, dst_access_mask: ash::vk::AccessFlags::TRANSFER_WRITE, ..Default::default() };
+            (fns.v1_0.cmd_pipeline_barrier)(cb, ash::vk::PipelineStageFlags::ALL_COMMANDS, ash::vk::PipelineStageFlags::TRANSFER, ash::vk::DependencyFlags::empty(),
+                                            1, &barrier, 0, std::ptr::null(), 0, std::ptr::null());
+            (fns.v1_0.cmd_fill_buffer)(cb, buffer.internal_object(), offset, 4, count);
+        }
+    }))
+}
+
+struct Frame {
+    /// `crumbs[n]` sets the frame's count to n.
+    crumbs: Vec<Arc<RawCommands>>,
+    names: Vec<&'static str>,
+    number: u64,
+}
+
+/// Where the gpu got in the last few frames, for the report when the device is lost. Each
+/// frame has a count in host coherent memory that stays mapped, so it can still be read once
+/// the device is gone; behind every pass it's set to the passes done, after everything before
+/// it in the queue finished: by `VK_AMD_buffer_marker` where the device has it, or by a fill
+/// behind a pipeline barrier.
+pub struct Breadcrumbs {
+    frames: Vec<Frame>,
+    current: usize,
+    number: u64,
+    memory: MappedDeviceMemory,
+    //the crumbs write to it, kept for as long as they are
+    _buffer: Arc<UnsafeBuffer>,
+}
+
+impl Breadcrumbs {
+    pub fn new(queue: &Arc<Queue>) -> Self {
+        let dev = queue.device();
+        let size = (FRAMES * 4) as DeviceSize;
+        let buffer = UnsafeBuffer::new(dev.clone(), UnsafeBufferCreateInfo { size, usage: BufferUsage::transfer_destination(), ..Default::default() }).unwrap();
+        let requirements = buffer.memory_requirements();
+        let memory_type = dev.physical_device().memory_types()
+            .find(|t| requirements.memory_type_bits & (1 << t.id()) != 0 && t.is_host_visible() && t.is_host_coherent())
+            .expect("no host coherent memory for the breadcrumbs");
+        let memory = DeviceMemory::allocate(dev.clone(), MemoryAllocateInfo { allocation_size: requirements.size, memory_type_index: memory_type.id(), ..Default::default() }).unwrap();
+        unsafe { buffer.bind_memory(&memory, 0).unwrap(); }
+        let memory = MappedDeviceMemory::new(memory, 0..requirements.size).unwrap();
+        unsafe { memory.write(0..size).unwrap().fill(0); }
+        let frames = (0..FRAMES).map(|i| Frame {
+            crumbs: (0..=MAX_MARKS as u32).map(|count| crumb(queue, &buffer, (i * 4) as DeviceSize, count)).collect(),
+            names: Vec::new(), number: 0 }).collect();
+        Breadcrumbs { frames, current: 0, number: 0, memory, _buffer: buffer }
+    }
+
+    /// Outside a render pass, before the frame's first pass.
+    pub fn begin(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        self.current = (self.current + 1) % FRAMES;
+        self.number += 1;
+        let frame = &mut self.frames[self.current];
+        frame.names.clear();
+        frame.number = self.number;
+        builder.execute_commands(frame.crumbs[0].clone()).unwrap();
+    }
+
+    /// After the pass called `name`, outside a render pass.
+    pub fn mark(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, name: &'static str) {
+        let frame = &mut self.frames[self.current];
+        if frame.names.len() >= MAX_MARKS { return; }
+        frame.names.push(name);
+        builder.execute_commands(frame.crumbs[frame.names.len()].clone()).unwrap();
+    }
+
+    /// A line per frame, oldest first, of the passes it got through and the one it was in.
+    pub fn report(&self) -> String {
+        let counts = unsafe { self.memory.read(0..(FRAMES * 4) as DeviceSize).unwrap().to_vec() };
+        let mut frames: Vec<(usize, &Frame)> = self.frames.iter().enumerate().filter(|(_, f)| f.number > 0).collect();
+        frames.sort_by_key(|(_, f)| f.number);
+        let mut out = String::new();
+        for (i, f) in frames {
+            let count = u32::from_ne_bytes(counts[i * 4..i * 4 + 4].try_into().unwrap()) as usize;
+            let done = &f.names[..count.min(f.names.len())];
+            let at = f.names.get(count).copied().unwrap_or("after the last pass");
+            writeln!(out, "frame {}: through {}; at {}", f.number, if done.is_empty() { "nothing".to_string() } else { done.join(", ") }, at).unwrap();
+        }
+        out
+    }
+}
+
+/// What was going on when the device went, for the log.
+pub fn report(dev: &Device, breadcrumbs: &Breadcrumbs, memory: &GpuMemory, error: &dyn std::fmt::Debug) -> String {
+    let properties = dev.physical_device().properties();
+    let mut out = String::new();
+    let since_epoch = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    writeln!(out, "device lost at {} s since the epoch: {:?}", since_epoch, error).unwrap();
+    writeln!(out, "{} ({:?}), driver {}, vulkan {:?}", properties.device_name, properties.device_type, properties.driver_version, properties.api_version).unwrap();
+    out.push_str(&breadcrumbs.report());
+    let (usage, budget) = memory.device_local();
+    write!(out, "memory: {} of {} MB device local;", usage >> 20, budget >> 20).unwrap();
+    for c in MemoryCategory::ALL { write!(out, " {} {} MB", c.name(), memory.categories[c as usize] >> 20).unwrap(); }
+    out.push('\n');
+    out
+}
+
+/// What a frame hands back when its device is lost.
+pub struct Lost {
+    pub report: String,
+    /// The frame's future still on the lost device, if it had one.
+    pub future: Option<Box<dyn GpuFuture>>,
+}
+
+/// Counts lost devices across the engine starting over on new ones.
+pub struct Restarts {
+    count: u32,
+    started: Instant,
+    /// The lost devices' futures, each holding its device and whatever was on it. Dropping one
+    /// waits on its lost queue and panics, so they stay until the process goes. One per lost
+    /// device; `MAX_RESTARTS` in a row panics before they pile up.
+    graveyard: Vec<Box<dyn GpuFuture>>,
+}
+
+impl Restarts {
+    pub fn new() -> Self { Restarts { count: 0, started: Instant::now(), graveyard: Vec::new() } }
+
+    /// Logs the report to stdout and `LOG` before the engine starts over. Panics with it instead
+    /// after `MAX_RESTARTS` lost soon after starting, a driver that keeps losing it won't get better.
+    pub fn lost(&mut self, lost: Lost) {
+        self.graveyard.extend(lost.future);
+        let report = &lost.report;
+        println!("{}", report);
+        if let Err(e) = std::fs::OpenOptions::new().create(true).append(true).open(LOG).and_then(|mut f| writeln!(f, "{}", report)) {
+            println!("failed writing {}: {}", LOG, e);
+        }
+        self.count = if self.started.elapsed() < IN_A_ROW { self.count + 1 } else { 1 };
+        if self.count > MAX_RESTARTS { panic!("device lost {} times in a row\n{}", self.count, report); }
+        self.started = Instant::now();
+    }
+}
+
+impl Drop for Restarts {
+    //at exit, the lost devices go with the process rather than through their futures
+    fn drop(&mut self) { std::mem::forget(std::mem::take(&mut self.graveyard)); }
+}
@@ -0,0 +1,285 @@
+use vulkano::{ device::{ Device, Queue },
+               buffer::{ BufferUsage, CpuBufferPool },
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, SubpassContents },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               image::{ SwapchainImage, view::ImageView },
+               sampler::{ Sampler, SamplerCreateInfo },
+               render_pass::{ Framebuffer, FramebufferCreateInfo, RenderPass, Subpass },
+               pipeline::{ GraphicsPipeline, Pipeline, PipelineBindPoint, graphics::{ input_assembly::InputAssemblyState, vertex_input::BuffersDefinition,
+                                                                   viewport::{ Scissor, Viewport, ViewportState },
+                                                                   color_blend::{ ColorBlendState, AttachmentBlend, BlendOp, BlendFactor } } },
+               format::{ ClearValue, Format },
+               impl_vertex };
+use bytemuck::{ Pod, Zeroable };
+use egui::{ epaint::{ ImageData, Primitive }, ClippedPrimitive, Context, Modifiers, PointerButton, Pos2, RawInput, Rect, TextureId, Vec2 };
+use winit::{ event::{ ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent }, window::{ CursorIcon, Window } };
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use crate::texture::Texture;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+struct EguiVertex { position: [f32; 2], uv: [f32; 2], color: [f32; 4], }
+impl_vertex!(EguiVertex, position, uv, color);
+
+mod vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			layout(location = 0) in vec2 position;
+			layout(location = 1) in vec2 uv;
+			layout(location = 2) in vec4 color;
+			layout(location = 0) out vec2 v_uv;
+			layout(location = 1) out vec4 v_color;
+
+			layout(push_constant) uniform PushConstants { vec2 screen; } pc; //points
+
+			//egui's colors are srgb, premultiplied; the target is linear
+			vec3 linear(vec3 srgb) { return mix(srgb / 12.92, pow((srgb + 0.055) / 1.055, vec3(2.4)), step(0.04045, srgb)); }
+
+			void main() {
+				gl_Position = vec4(position / pc.screen * 2.0 - 1.0, 0.0, 1.0);
+				v_uv = uv;
+				v_color = vec4(linear(color.rgb), color.a);
+			}"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! { ty: "fragment",
+    src: "#version 450
+
+			layout(set = 0, binding = 0) uniform sampler2D u_texture;
+			layout(location = 0) in vec2 v_uv;
+			layout(location = 1) in vec4 v_color;
+			layout(location = 0) out vec4 f_color;
+
+			void main() { f_color = v_color * texture(u_texture, v_uv); }"
+    }
+}
+
+/// One of egui's own textures, kept on the cpu too so partial updates can patch it.
+struct Managed {
+    size: [usize; 2],
+    pixels: Vec<u8>,
+    texture: Arc<Texture>,
+}
+
+/// Immediate mode tooling UI: feeds winit events to an egui `Context`, uploads the textures it
+/// asks for and draws its meshes in a pass of their own onto the swapchain image, over the
+/// overlay. No clipboard; copy and paste stay inside egui.
+pub struct EguiPass {
+    pub ctx: Context,
+    input: RawInput,
+    start: Instant,
+    pixels_per_point: f32,
+    pointer: Pos2,
+    modifiers: Modifiers,
+    queue: Arc<Queue>,
+    textures: HashMap<TextureId, Managed>,
+    /// Engine textures shown in egui, see `register`.
+    user: HashMap<u64, Arc<Texture>>,
+    /// Freed after the frame that last used them has drawn.
+    free: Vec<TextureId>,
+    primitives: Vec<ClippedPrimitive>,
+    render_pass: Arc<RenderPass>,
+    pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    vertex_pool: CpuBufferPool<EguiVertex>,
+    index_pool: CpuBufferPool<u32>,
+    framebuffers: Vec<Arc<Framebuffer>>,
+}
+
+impl EguiPass {
+    pub fn new(dev: Arc<Device>, queue: Arc<Queue>, format: Format, window: &Window) -> Self {
+        let render_pass = vulkano::single_pass_renderpass!( dev.clone(),
+                                                            attachments: { color: { load: Load, store: Store, format: format, samples: 1,}},
+                                                            pass: { color: [color], depth_stencil: {} }).unwrap();
+        let vs = vs::load(dev.clone()).unwrap();
+        let fs = fs::load(dev.clone()).unwrap();
+        let pipeline = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new().vertex::<EguiVertex>())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_dynamic(1))
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            //premultiplied
+            .color_blend_state(ColorBlendState::new(1).blend(AttachmentBlend {
+                color_op: BlendOp::Add, color_source: BlendFactor::One, color_destination: BlendFactor::OneMinusSrcAlpha,
+                alpha_op: BlendOp::Add, alpha_source: BlendFactor::OneMinusDstAlpha, alpha_destination: BlendFactor::One }))
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(dev.clone()).unwrap();
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo::simple_repeat_linear_no_mipmap()).unwrap();
+        EguiPass { ctx: Context::default(), input: RawInput::default(), start: Instant::now(), pixels_per_point: window.scale_factor() as f32,
+                   pointer: Pos2::ZERO, modifiers: Modifiers::default(), queue, textures: HashMap::new(), user: HashMap::new(), free: Vec::new(),
+                   primitives: Vec::new(), render_pass, pipeline, sampler, vertex_pool: CpuBufferPool::vertex_buffer(dev.clone()),
+                   index_pool: CpuBufferPool::new(dev, BufferUsage::index_buffer()), framebuffers: Vec::new() }
+    }
+
+    pub fn resize(&mut self, images: &[Arc<SwapchainImage<Window>>]) {
+        self.framebuffers = images.iter().map(|image| {
+            Framebuffer::new(self.render_pass.clone(), FramebufferCreateInfo {
+                attachments: vec![ImageView::new_default(image.clone()).unwrap()], ..Default::default() }).unwrap()
+        }).collect();
+    }
+
+    /// Makes an engine texture, a view's image say, showable with `egui::Image`.
+    pub fn register(&mut self, texture: Arc<Texture>) -> TextureId {
+        let id = self.user.len() as u64;
+        self.user.insert(id, texture);
+        TextureId::User(id)
+    }
+
+    /// Queues `event` for the next `run`. True when egui wants it to itself, the pointer over
+    /// one of its windows or a text field focused, and the engine should leave it be.
+    pub fn on_event(&mut self, event: &WindowEvent) -> bool {
+        let ppp = self.pixels_per_point;
+        let (pointer, keyboard) = (self.ctx.wants_pointer_input(), self.ctx.wants_keyboard_input());
+        match event {
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => { self.pixels_per_point = *scale_factor as f32; false }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.pointer = Pos2::new(position.x as f32 / ppp, position.y as f32 / ppp);
+                self.input.events.push(egui::Event::PointerMoved(self.pointer));
+                pointer
+            }
+            WindowEvent::CursorLeft { .. } => { self.input.events.push(egui::Event::PointerGone); false }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button { MouseButton::Left => PointerButton::Primary, MouseButton::Right => PointerButton::Secondary,
+                                            MouseButton::Middle => PointerButton::Middle, MouseButton::Other(_) => return false };
+                self.input.events.push(egui::Event::PointerButton { pos: self.pointer, button, pressed: *state == ElementState::Pressed, modifiers: self.modifiers });
+                pointer
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                //lines are about 50 points
+                let delta = match delta { MouseScrollDelta::LineDelta(x, y) => Vec2::new(*x, *y) * 50.0,
+                                          MouseScrollDelta::PixelDelta(p) => Vec2::new(p.x as f32, p.y as f32) / ppp };
+                self.input.events.push(egui::Event::Scroll(delta));
+                pointer
+            }
+            WindowEvent::ModifiersChanged(m) => {
+                self.modifiers = Modifiers { alt: m.alt(), ctrl: m.ctrl(), shift: m.shift(), mac_cmd: cfg!(target_os = "macos") && m.logo(),
+                                             command: if cfg!(target_os = "macos") { m.logo() } else { m.ctrl() } };
+                false
+            }
+            WindowEvent::ReceivedCharacter(c) => {
+                if !c.is_control() { self.input.events.push(egui::Event::Text(c.to_string())); }
+                keyboard
+            }
+            WindowEvent::KeyboardInput { input, .. } => {
+                if let Some(key) = input.virtual_keycode.and_then(key) {
+                    self.input.events.push(egui::Event::Key { key, pressed: input.state == ElementState::Pressed, repeat: false, modifiers: self.modifiers });
+                }
+                keyboard
+            }
+            _ => false,
+        }
+    }
+
+    /// Runs `ui` on the frame's input and tessellates what it drew. Once a frame, before `draw`.
+    pub fn run(&mut self, window: &Window, ui: impl FnOnce(&Context)) {
+        for id in self.free.drain(..) { self.textures.remove(&id); }
+        let size = window.inner_size();
+        let mut input = std::mem::take(&mut self.input);
+        input.screen_rect = Some(Rect::from_min_size(Pos2::ZERO, Vec2::new(size.width as f32, size.height as f32) / self.pixels_per_point));
+        input.pixels_per_point = Some(self.pixels_per_point);
+        input.time = Some(self.start.elapsed().as_secs_f64());
+        input.modifiers = self.modifiers;
+        let output = self.ctx.run(input, ui);
+        for (id, delta) in output.textures_delta.set {
+            let (size, pixels): ([usize; 2], Vec<u8>) = match &delta.image {
+                ImageData::Color(image) => (image.size, image.pixels.iter().flat_map(|c| c.to_array()).collect()),
+                ImageData::Font(image) => (image.size, image.srgba_pixels(None).flat_map(|c| c.to_array()).collect()),
+            };
+            let (full, pixels) = match (delta.pos, self.textures.remove(&id)) {
+                (Some([x, y]), Some(mut managed)) => {
+                    for row in 0..size[1] {
+                        let at = ((y + row) * managed.size[0] + x) * 4;
+                        managed.pixels[at..at + size[0] * 4].copy_from_slice(&pixels[row * size[0] * 4..(row + 1) * size[0] * 4]);
+                    }
+                    (managed.size, managed.pixels)
+                }
+                _ => (size, pixels),
+            };
+            //whole, like the glyph atlas; egui's updates are few and small after the first frames
+            let texture = Texture::from_rgba(self.queue.clone(), [full[0] as u32, full[1] as u32], pixels.clone());
+            self.textures.insert(id, Managed { size: full, pixels, texture });
+        }
+        self.free = output.textures_delta.free;
+        window.set_cursor_visible(output.platform_output.cursor_icon != egui::CursorIcon::None);
+        window.set_cursor_icon(cursor(output.platform_output.cursor_icon));
+        self.primitives = self.ctx.tessellate(output.shapes);
+    }
+
+    #[profiling::function]
+    pub fn draw(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, image_num: usize, viewport: &Viewport) {
+        let meshes: Vec<_> = self.primitives.iter().filter_map(|p| match &p.primitive {
+            Primitive::Mesh(mesh) if !mesh.indices.is_empty() => Some((p.clip_rect, mesh)),
+            _ => None,
+        }).collect();
+        if meshes.is_empty() { return; }
+        //every mesh in one buffer, drawn in order with its clip rect as the scissor
+        let vertices: Vec<EguiVertex> = meshes.iter().flat_map(|(_, m)| m.vertices.iter().map(|v| EguiVertex {
+            position: [v.pos.x, v.pos.y], uv: [v.uv.x, v.uv.y], color: v.color.to_array().map(|c| c as f32 / 255.0) })).collect();
+        let indices: Vec<u32> = meshes.iter().flat_map(|(_, m)| m.indices.iter().copied()).collect();
+        let ppp = self.pixels_per_point;
+        let layout = self.pipeline.layout().set_layouts().get(0).unwrap();
+        builder.begin_render_pass(self.framebuffers[image_num].clone(), SubpassContents::Inline, vec![ClearValue::None]).unwrap()
+            .set_viewport(0, [viewport.clone()])
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .push_constants(self.pipeline.layout().clone(), 0, vs::ty::PushConstants { screen: viewport.dimensions.map(|d| d / ppp) })
+            .bind_vertex_buffers(0, self.vertex_pool.chunk(vertices).unwrap())
+            .bind_index_buffer(self.index_pool.chunk(indices).unwrap());
+        let (mut first_index, mut vertex_offset) = (0, 0);
+        for (clip, mesh) in meshes {
+            let (count, vertex_count) = (mesh.indices.len() as u32, mesh.vertices.len() as i32);
+            let texture = match mesh.texture_id {
+                TextureId::Managed(_) => self.textures.get(&mesh.texture_id).map(|m| &m.texture),
+                TextureId::User(id) => self.user.get(&id),
+            };
+            let min = [(clip.min.x * ppp).max(0.0), (clip.min.y * ppp).max(0.0)];
+            let max = [(clip.max.x * ppp).min(viewport.dimensions[0]), (clip.max.y * ppp).min(viewport.dimensions[1])];
+            if let (Some(texture), true) = (texture, max[0] > min[0] && max[1] > min[1]) {
+                let set = PersistentDescriptorSet::new(layout.clone(), [WriteDescriptorSet::image_view_sampler(0, texture.view.clone(), self.sampler.clone())]).unwrap();
+                let scissor = Scissor { origin: min.map(|v| v as u32), dimensions: [(max[0] - min[0]).ceil() as u32, (max[1] - min[1]).ceil() as u32] };
+                builder.set_scissor(0, [scissor])
+                    .bind_descriptor_sets(PipelineBindPoint::Graphics, self.pipeline.layout().clone(), 0, set)
+                    .draw_indexed(count, 1, first_index, vertex_offset, 0).unwrap();
+            }
+            first_index += count;
+            vertex_offset += vertex_count;
+        }
+        builder.end_render_pass().unwrap();
+    }
+}
+
+fn key(key: VirtualKeyCode) -> Option<egui::Key> {
+    use egui::Key;
+    use VirtualKeyCode as V;
+    Some(match key {
+        V::Down => Key::ArrowDown, V::Left => Key::ArrowLeft, V::Right => Key::ArrowRight, V::Up => Key::ArrowUp,
+        V::Escape => Key::Escape, V::Tab => Key::Tab, V::Back => Key::Backspace, V::Return | V::NumpadEnter => Key::Enter, V::Space => Key::Space,
+        V::Insert => Key::Insert, V::Delete => Key::Delete, V::Home => Key::Home, V::End => Key::End, V::PageUp => Key::PageUp, V::PageDown => Key::PageDown,
+        V::A => Key::A, V::C => Key::C, V::V => Key::V, V::X => Key::X, V::Y => Key::Y, V::Z => Key::Z,
+        _ => return None,
+    })
+}
+
+fn cursor(icon: egui::CursorIcon) -> CursorIcon {
+    use egui::CursorIcon as E;
+    match icon {
+        E::PointingHand => CursorIcon::Hand,
+        E::Text => CursorIcon::Text,
+        E::ResizeHorizontal | E::ResizeColumn => CursorIcon::EwResize,
+        E::ResizeVertical | E::ResizeRow => CursorIcon::NsResize,
+        E::ResizeNwSe => CursorIcon::NwseResize,
+        E::ResizeNeSw => CursorIcon::NeswResize,
+        E::Grab => CursorIcon::Grab,
+        E::Grabbing => CursorIcon::Grabbing,
+        E::Move | E::AllScroll => CursorIcon::Move,
+        E::NotAllowed | E::NoDrop => CursorIcon::NotAllowed,
+        E::Wait => CursorIcon::Wait,
+        E::Crosshair => CursorIcon::Crosshair,
+        _ => CursorIcon::Default,
+    }
+}
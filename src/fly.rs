@@ -0,0 +1,97 @@
+use winit::event::VirtualKeyCode;
+use glam::{ Quat, Vec2, Vec3 };
+use crate::camera::Camera;
+
+/// First person camera moves: mouse look plus WASD, Q and E down and up, shift to sprint.
+/// Only does anything between `begin` and `end`, so the keys stay free for other shortcuts
+/// the rest of the time; the caller grabs and hides the cursor meanwhile. Like the orbit
+/// controller it works on whatever camera it's handed, keeping the target at its distance in
+/// front so orbiting afterwards goes around what was being looked at.
+pub struct FlyController {
+    /// Meters per second.
+    pub speed: f32,
+    pub sprint_multiplier: f32,
+    /// Radians per pixel of mouse motion.
+    pub look_speed: f32,
+    /// Seconds for movement and look to close most of the gap to where input puts them; 0 snaps.
+    pub smoothing: f32,
+    active: bool,
+    sprint: bool,
+    /// Forward, right, up, each -1..1 from the keys held.
+    held: [[bool; 2]; 3],
+    velocity: Vec3,
+    /// Mouse motion not yet turned into rotation.
+    look: Vec2,
+}
+
+/// Closest the view gets to straight up or down, in radians.
+const POLE_MARGIN: f32 = 0.01;
+
+impl FlyController {
+    pub fn new() -> Self {
+        FlyController { speed: 3.0, sprint_multiplier: 4.0, look_speed: 0.003, smoothing: 0.08,
+                        active: false, sprint: false, held: [[false; 2]; 3], velocity: Vec3::ZERO, look: Vec2::ZERO }
+    }
+
+    pub fn active(&self) -> bool { self.active }
+
+    pub fn begin(&mut self) { self.active = true; }
+
+    /// Lets go of every key too; their releases may never arrive once focus is elsewhere.
+    pub fn end(&mut self) {
+        self.active = false;
+        self.held = [[false; 2]; 3];
+        self.look = Vec2::ZERO;
+    }
+
+    /// True if the key is one of the controller's and it's active.
+    pub fn key(&mut self, key: VirtualKeyCode, pressed: bool) -> bool {
+        if !self.active { return false; }
+        let (axis, side) = match key {
+            VirtualKeyCode::W => (0, 1), VirtualKeyCode::S => (0, 0),
+            VirtualKeyCode::D => (1, 1), VirtualKeyCode::A => (1, 0),
+            VirtualKeyCode::E => (2, 1), VirtualKeyCode::Q => (2, 0),
+            _ => return false,
+        };
+        self.held[axis][side] = pressed;
+        true
+    }
+
+    pub fn set_sprint(&mut self, sprint: bool) { self.sprint = sprint; }
+
+    /// Raw mouse motion in pixels, as the device reports it; cursor positions stop at the
+    /// window's edge.
+    pub fn look(&mut self, delta: [f64; 2]) {
+        if self.active { self.look += Vec2::new(delta[0] as f32, delta[1] as f32); }
+    }
+
+    /// Scroll while flying changes the speed rather than zooming.
+    pub fn adjust_speed(&mut self, steps: f32) { self.speed = (self.speed * 1.2f32.powf(steps)).clamp(0.05, 500.0); }
+
+    /// `dt` in real seconds, so flying around still works while the scene is paused.
+    pub fn update(&mut self, camera: &mut Camera, dt: f32) {
+        let blend = if self.smoothing > 0.0 { 1.0 - (-dt / self.smoothing).exp() } else { 1.0 };
+        let turn = self.look * blend;
+        self.look -= turn;
+
+        let up = camera.up.normalize();
+        let offset = camera.target - camera.position;
+        let distance = offset.length().max(0.1);
+        let forward = offset / distance;
+        //yaw around `up`, pitch clamped short of the poles
+        let pitch = forward.dot(up).clamp(-1.0, 1.0).asin();
+        let limit = std::f32::consts::FRAC_PI_2 - POLE_MARGIN;
+        let pitch_delta = (pitch - turn.y * self.look_speed).clamp(-limit, limit) - pitch;
+        let right = forward.cross(up).normalize_or_zero();
+        let rotation = Quat::from_axis_angle(up, -turn.x * self.look_speed) * Quat::from_axis_angle(right, pitch_delta);
+        let forward = rotation * forward;
+        let right = forward.cross(up).normalize_or_zero();
+
+        let axis = |[back, front]: [bool; 2]| front as i32 as f32 - back as i32 as f32;
+        let input = forward * axis(self.held[0]) + right * axis(self.held[1]) + up * axis(self.held[2]);
+        let speed = self.speed * if self.sprint { self.sprint_multiplier } else { 1.0 };
+        self.velocity += (input.normalize_or_zero() * speed - self.velocity) * blend;
+        camera.position += self.velocity * dt;
+        camera.target = camera.position + forward * distance;
+    }
+}
@@ -0,0 +1,23 @@
+use glam::Vec3;
+
+/// Cheap analytic fog, computed per pixel in the lighting shader: exponential in distance, plus
+/// a layer that thins out with height. For where `VolumetricFog` costs too much.
+#[derive(Clone, Copy, Debug)]
+pub struct Fog {
+    pub color: Vec3,
+    /// Extinction per meter everywhere.
+    pub density: f32,
+    /// Extinction per meter of the height layer at `base_height`.
+    pub height_density: f32,
+    /// How fast the height layer thins out going up, per meter.
+    pub height_falloff: f32,
+    pub base_height: f32,
+    /// Meters from the camera before any fog starts.
+    pub start: f32,
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Fog { color: Vec3::new(0.5, 0.6, 0.7), density: 0.01, height_density: 0.05, height_falloff: 0.5, base_height: 0.0, start: 0.0 }
+    }
+}
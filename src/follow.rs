@@ -0,0 +1,91 @@
+use glam::{ Quat, Vec3 };
+use crate::bvh::Ray;
+use crate::camera::Camera;
+use crate::scene::{ Scene, Entity, EntityId };
+
+/// Third person camera trailing an entity on a spring arm: it eases after the entity's
+/// position and heading, and pulls in where the scene gets between them so walls never end up
+/// in front of the target. Works on whatever camera it's handed, like the other controllers.
+pub struct FollowController {
+    pub target: EntityId,
+    /// Where the camera sits from the target, in the frame of its heading; +z is behind an
+    /// entity facing -z.
+    pub offset: Vec3,
+    /// The point on the target the camera aims at, and the arm pivots on, in the same frame.
+    pub look_offset: Vec3,
+    /// Turn with the target's heading; a fixed world space offset otherwise.
+    pub follow_rotation: bool,
+    /// Seconds for the camera to close most of the gap to the target's position and heading;
+    /// 0 snaps.
+    pub position_damping: f32,
+    pub rotation_damping: f32,
+    /// Going in around an obstruction is instant, going back out takes this.
+    pub recover_damping: f32,
+    /// Meters kept clear between the camera and whatever is in the way.
+    pub collision_margin: f32,
+    /// Spread of the probe rays around the arm, about the camera's near plane in size.
+    pub probe_radius: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// Entities the arm passes through besides the target, its weapon for instance.
+    pub ignore: Vec<EntityId>,
+    pivot: Option<Vec3>,
+    heading: Quat,
+    arm: Option<f32>,
+}
+
+fn blend(dt: f32, damping: f32) -> f32 { if damping > 0.0 { 1.0 - (-dt / damping).exp() } else { 1.0 } }
+
+impl FollowController {
+    pub fn new(target: EntityId) -> Self {
+        FollowController { target, offset: Vec3::new(0.0, 1.5, 4.0), look_offset: Vec3::new(0.0, 1.0, 0.0), follow_rotation: true,
+                           position_damping: 0.1, rotation_damping: 0.3, recover_damping: 0.4, collision_margin: 0.2, probe_radius: 0.1,
+                           min_distance: 0.5, max_distance: 20.0, ignore: Vec::new(), pivot: None, heading: Quat::IDENTITY, arm: None }
+    }
+
+    /// Positive `steps` pull the camera in along its offset.
+    pub fn zoom(&mut self, steps: f32) {
+        let length = (self.offset.length() * 0.9f32.powf(steps)).clamp(self.min_distance, self.max_distance);
+        self.offset = self.offset.normalize_or_zero() * length;
+    }
+
+    /// Meters the arm can reach from `from` towards `dir` before the scene gets in the way.
+    fn reach(&self, scene: &Scene, from: Vec3, dir: Vec3, length: f32) -> f32 {
+        let (u, v) = dir.any_orthonormal_pair();
+        let keep = |e: &Entity| e.id != self.target && !self.ignore.contains(&e.id);
+        [Vec3::ZERO, u, -u, v, -v].iter().filter_map(|&side| {
+            scene.raycast_filtered(&Ray { origin: from + side * self.probe_radius, dir }, keep).map(|h| h.distance)
+        }).fold(length, f32::min)
+    }
+
+    /// `dt` in scaled seconds, so it settles along with a paused scene. Does nothing once the
+    /// target is gone.
+    pub fn update(&mut self, scene: &Scene, camera: &mut Camera, dt: f32) {
+        let entity = match scene.get(self.target) { Some(e) => e, None => return };
+        let (_, rotation, position) = entity.transform.to_scale_rotation_translation();
+        let heading = if self.follow_rotation {
+            let f = rotation * Vec3::Z;
+            Quat::from_rotation_y(f.x.atan2(f.z))
+        } else { Quat::IDENTITY };
+        //the first update starts where the target is
+        let pivot = match self.pivot {
+            Some(p) => p.lerp(position, blend(dt, self.position_damping)),
+            None => { self.heading = heading; position }
+        };
+        self.pivot = Some(pivot);
+        self.heading = self.heading.slerp(heading, blend(dt, self.rotation_damping)).normalize();
+
+        let look = pivot + self.heading * self.look_offset;
+        let arm = pivot + self.heading * self.offset - look;
+        let (length, dir) = (arm.length(), arm.normalize_or_zero());
+        let allowed = (self.reach(scene, look, dir, length + self.collision_margin) - self.collision_margin).clamp(self.min_distance.min(length), length);
+        let current = match self.arm {
+            Some(a) if a > allowed => allowed,
+            Some(a) => a + (allowed - a) * blend(dt, self.recover_damping),
+            None => allowed,
+        };
+        self.arm = Some(current);
+        camera.position = look + dir * current;
+        camera.target = look;
+    }
+}
@@ -0,0 +1,157 @@
+use glam::{ Vec3, Quat, Mat4 };
+use crate::bvh::{ Ray, Aabb };
+use crate::debug_draw::DebugDraw;
+use crate::scene::EntityId;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GizmoMode { Translate, Rotate, Scale }
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GizmoSpace { Local, World }
+
+/// Emitted while dragging; `before` is the transform at the start of the drag so edits can be undone.
+#[derive(Clone, Copy, Debug)]
+pub struct TransformEdit { pub entity: EntityId, pub before: Mat4, pub after: Mat4 }
+
+struct Drag { axis: usize, start: Mat4, start_param: f32, start_dir: Vec3 }
+
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    pub space: GizmoSpace,
+    pub snapping: bool,
+    pub translate_snap: f32,
+    pub rotate_snap: f32, //radians
+    pub scale_snap: f32,
+    hovered: Option<usize>,
+    drag: Option<Drag>,
+}
+
+const COLORS: [[f32; 4]; 3] = [[1.0, 0.2, 0.2, 1.0], [0.2, 1.0, 0.2, 1.0], [0.2, 0.4, 1.0, 1.0]];
+const ACTIVE: [f32; 4] = [1.0, 1.0, 0.2, 1.0];
+
+fn snap(v: f32, step: f32) -> f32 { (v / step).round() * step }
+
+/// Closest approach between a ray and an infinite line, as (param along line, distance between them).
+fn ray_line(ray: &Ray, origin: Vec3, dir: Vec3) -> Option<(f32, f32)> {
+    let w = ray.origin - origin;
+    let b = ray.dir.dot(dir);
+    let denom = 1.0 - b * b;
+    if denom.abs() < 1e-6 { return None; }
+    let (d, e) = (ray.dir.dot(w), dir.dot(w));
+    let s = (b * e - d) / denom; //along ray
+    let t = (e - b * d) / denom; //along line
+    if s < 0.0 { return None; }
+    Some((t, (ray.at(s) - (origin + dir * t)).length()))
+}
+
+fn ray_plane(ray: &Ray, origin: Vec3, normal: Vec3) -> Option<Vec3> {
+    let denom = ray.dir.dot(normal);
+    if denom.abs() < 1e-6 { return None; }
+    let t = (origin - ray.origin).dot(normal) / denom;
+    if t < 0.0 { None } else { Some(ray.at(t)) }
+}
+
+impl Gizmo {
+    pub fn new() -> Self {
+        Gizmo { mode: GizmoMode::Translate, space: GizmoSpace::World, snapping: false,
+                translate_snap: 0.25, rotate_snap: 15f32.to_radians(), scale_snap: 0.1, hovered: None, drag: None }
+    }
+
+    pub fn dragging(&self) -> bool { self.drag.is_some() }
+
+    fn axes(&self, transform: &Mat4) -> [Vec3; 3] {
+        let (_, rot, _) = transform.to_scale_rotation_translation();
+        match self.space {
+            GizmoSpace::World => [Vec3::X, Vec3::Y, Vec3::Z],
+            GizmoSpace::Local => [rot * Vec3::X, rot * Vec3::Y, rot * Vec3::Z],
+        }
+    }
+
+    /// Handle length, scaled with camera distance so the gizmo keeps a constant on-screen size.
+    fn size(transform: &Mat4, eye: Vec3) -> f32 { (transform.w_axis.truncate() - eye).length() * 0.2 }
+
+    fn hit_axis(&self, ray: &Ray, transform: &Mat4) -> Option<(usize, f32, Vec3)> {
+        let origin = transform.w_axis.truncate();
+        let size = Self::size(transform, ray.origin);
+        let tolerance = size * 0.08;
+        let axes = self.axes(transform);
+        (0..3).filter_map(|i| {
+            match self.mode {
+                GizmoMode::Translate | GizmoMode::Scale => {
+                    let (t, d) = ray_line(ray, origin, axes[i])?;
+                    if d < tolerance && (0.0..=size).contains(&t) { Some((i, d, t, Vec3::ZERO)) } else { None }
+                }
+                GizmoMode::Rotate => {
+                    let p = ray_plane(ray, origin, axes[i])?;
+                    let d = ((p - origin).length() - size).abs();
+                    if d < tolerance { Some((i, d, 0.0, (p - origin).normalize())) } else { None }
+                }
+            }
+        }).min_by(|a, b| a.1.total_cmp(&b.1)).map(|(i, _, t, dir)| (i, t, dir))
+    }
+
+    pub fn hover(&mut self, ray: &Ray, transform: &Mat4) {
+        if self.drag.is_none() { self.hovered = self.hit_axis(ray, transform).map(|h| h.0); }
+    }
+
+    /// Starts a drag if the ray hits a handle; returns false so the caller can fall back to picking.
+    pub fn begin(&mut self, ray: &Ray, transform: &Mat4) -> bool {
+        match self.hit_axis(ray, transform) {
+            Some((axis, start_param, start_dir)) => {
+                self.drag = Some(Drag { axis, start: *transform, start_param, start_dir });
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn end(&mut self) { self.drag = None; }
+
+    pub fn update(&self, ray: &Ray, entity: EntityId) -> Option<TransformEdit> {
+        let drag = self.drag.as_ref()?;
+        let (scale, rot, trans) = drag.start.to_scale_rotation_translation();
+        let axis = self.axes(&drag.start)[drag.axis];
+        let after = match self.mode {
+            GizmoMode::Translate => {
+                let (t, _) = ray_line(ray, trans, axis)?;
+                let mut delta = t - drag.start_param;
+                if self.snapping { delta = snap(delta, self.translate_snap); }
+                Mat4::from_scale_rotation_translation(scale, rot, trans + axis * delta)
+            }
+            GizmoMode::Rotate => {
+                let dir = (ray_plane(ray, trans, axis)? - trans).normalize();
+                let mut angle = drag.start_dir.cross(dir).dot(axis).atan2(drag.start_dir.dot(dir));
+                if self.snapping { angle = snap(angle, self.rotate_snap); }
+                Mat4::from_scale_rotation_translation(scale, Quat::from_axis_angle(axis, angle) * rot, trans)
+            }
+            GizmoMode::Scale => {
+                let (t, _) = ray_line(ray, trans, axis)?;
+                let mut factor = (t / drag.start_param.max(1e-3)).max(0.01);
+                if self.snapping { factor = snap(factor, self.scale_snap).max(self.scale_snap); }
+                let mut s = scale;
+                s[drag.axis] *= factor;
+                Mat4::from_scale_rotation_translation(s, rot, trans)
+            }
+        };
+        Some(TransformEdit { entity, before: drag.start, after })
+    }
+
+    pub fn draw(&self, transform: &Mat4, eye: Vec3, dbg: &mut DebugDraw) {
+        let origin = transform.w_axis.truncate();
+        let size = Self::size(transform, eye);
+        let axes = self.axes(transform);
+        let active = self.drag.as_ref().map(|d| d.axis).or(self.hovered);
+        for (i, &axis) in axes.iter().enumerate() {
+            let color = if active == Some(i) { ACTIVE } else { COLORS[i] };
+            match self.mode {
+                GizmoMode::Translate => dbg.ray(origin, axis * size, color),
+                GizmoMode::Scale => {
+                    let tip = origin + axis * size;
+                    dbg.line(origin, tip, color);
+                    dbg.aabb(&Aabb { min: tip - Vec3::splat(size * 0.04), max: tip + Vec3::splat(size * 0.04) }, color);
+                }
+                GizmoMode::Rotate => dbg.circle(origin, axis, size, color),
+            }
+        }
+    }
+}
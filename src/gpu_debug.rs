@@ -0,0 +1,123 @@
+use vulkano::{ VulkanObject,
+               device::DeviceOwned,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               instance::{ Instance, layers_list,
+                           debug::{ DebugUtilsLabel, DebugUtilsMessenger, DebugUtilsMessengerCreateInfo, DebugUtilsMessageSeverity, DebugUtilsMessageType, Message } } };
+use std::sync::Arc;
+
+/// Names and labels show up in RenderDoc, Nsight and the validation layers' messages. Both do
+/// nothing unless the instance has VK_EXT_debug_utils, which main turns on when it's there.
+fn enabled(owned: &impl DeviceOwned) -> bool { owned.device().instance().enabled_extensions().ext_debug_utils }
+
+/// Names a buffer, image, pipeline or render pass.
+pub fn name<T: VulkanObject + DeviceOwned>(object: &T, name: &str) {
+    if enabled(object) { object.device().set_debug_utils_object_name(object, Some(name)).unwrap(); }
+}
+
+/// Records `record` between a begin and end label called `name`, so it shows as one region.
+pub fn labeled<R>(builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, name: &str,
+                  record: impl FnOnce(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> R) -> R {
+    if !enabled(builder) { return record(builder); }
+    builder.begin_debug_utils_label(DebugUtilsLabel { label_name: name.to_string(), ..Default::default() }).unwrap();
+    let result = record(builder);
+    //paired with the begin above, in the same command buffer
+    unsafe { builder.end_debug_utils_label().unwrap(); }
+    result
+}
+
+/// RenderDoc's in-application API when the app was started from RenderDoc, to capture frames
+/// from a key or from code. Has to be made before the vulkan instance so the capture layer is
+/// loaded first; without the renderdoc feature it's never available.
+pub struct RenderDocCapture {
+    #[cfg(feature = "renderdoc")]
+    api: Option<renderdoc::RenderDoc<renderdoc::V141>>,
+}
+
+impl RenderDocCapture {
+    pub fn new() -> Self {
+        RenderDocCapture {
+            #[cfg(feature = "renderdoc")]
+            api: renderdoc::RenderDoc::new().ok(),
+        }
+    }
+
+    pub fn available(&self) -> bool {
+        #[cfg(feature = "renderdoc")]
+        return self.api.is_some();
+        #[cfg(not(feature = "renderdoc"))]
+        false
+    }
+
+    /// Captures the next frame presented; RenderDoc's ui picks it up from there.
+    pub fn capture(&mut self) {
+        #[cfg(feature = "renderdoc")]
+        if let Some(api) = &mut self.api { api.trigger_capture(); }
+    }
+}
+
+const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
+/// Opt in Khronos validation, its messages printed with the rest of the engine's. On with
+/// `--validation` or ARSE_VALIDATION=1; `--validation-severity` or ARSE_VALIDATION_SEVERITY
+/// is the least severe printed, error, warning (the default), info or verbose; and
+/// `--break-on-error` or ARSE_VALIDATION_BREAK=1 aborts on the first error, which stops a
+/// debugger on the call that made it.
+#[derive(Clone, Copy, Debug)]
+pub struct Validation {
+    pub enabled: bool,
+    pub severity: DebugUtilsMessageSeverity,
+    pub break_on_error: bool,
+}
+
+impl Validation {
+    pub fn from_args(args: &[String]) -> Self {
+        let flag = |name: &str, var: &str| args.iter().any(|a| a == name) || std::env::var(var).map_or(false, |v| v == "1");
+        let severity = args.iter().find_map(|a| a.strip_prefix("--validation-severity=").map(str::to_string))
+            .or_else(|| std::env::var("ARSE_VALIDATION_SEVERITY").ok());
+        let severity = match severity.as_deref() {
+            Some("error") => DebugUtilsMessageSeverity::errors(),
+            Some("info") => DebugUtilsMessageSeverity::errors_and_warnings_and_information(),
+            Some("verbose") => DebugUtilsMessageSeverity::all(),
+            Some("warning") | None => DebugUtilsMessageSeverity::errors_and_warnings(),
+            Some(s) => { println!("unknown validation severity {:?}, using warning", s); DebugUtilsMessageSeverity::errors_and_warnings() }
+        };
+        Validation { enabled: flag("--validation", "ARSE_VALIDATION"), severity, break_on_error: flag("--break-on-error", "ARSE_VALIDATION_BREAK") }
+    }
+
+    /// The layer for the instance, none if it's off or not installed.
+    pub fn layers(&self) -> Vec<String> {
+        if !self.enabled { return Vec::new(); }
+        let installed = layers_list().map_or(false, |mut l| l.any(|l| l.name() == VALIDATION_LAYER));
+        if !installed { println!("{} isn't installed, running without validation", VALIDATION_LAYER); return Vec::new(); }
+        vec![VALIDATION_LAYER.to_string()]
+    }
+
+    /// Prints the messages for as long as it's kept. Needs VK_EXT_debug_utils on `instance`.
+    pub fn messenger(&self, instance: Arc<Instance>) -> Option<DebugUtilsMessenger> {
+        if !self.enabled || !instance.enabled_extensions().ext_debug_utils { return None; }
+        let break_on_error = self.break_on_error;
+        let callback = Arc::new(move |message: &Message| {
+            let severity = match message.severity {
+                s if s.error => "error",
+                s if s.warning => "warning",
+                s if s.information => "info",
+                _ => "verbose",
+            };
+            let ty = match message.ty {
+                t if t.validation => "validation",
+                t if t.performance => "performance",
+                _ => "general",
+            };
+            println!("vulkan {} {}: {}", ty, severity, message.description);
+            if break_on_error && message.severity.error {
+                println!("{}", std::backtrace::Backtrace::force_capture());
+                //can't unwind out of the driver's call
+                std::process::abort();
+            }
+        });
+        let info = DebugUtilsMessengerCreateInfo { message_severity: self.severity, message_type: DebugUtilsMessageType::all(),
+                                                   ..DebugUtilsMessengerCreateInfo::user_callback(callback) };
+        //the callback only prints, or aborts, and never calls back into vulkan
+        unsafe { DebugUtilsMessenger::new(instance, info) }.map_err(|e| println!("no validation messages: {:?}", e)).ok()
+    }
+}
@@ -0,0 +1,115 @@
+use vulkano::{ VulkanObject, Version, device::Device, format::Format };
+use ash::vk;
+use std::any::Any;
+use std::sync::{ Arc, Mutex, Weak };
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryCategory {
+    Textures,
+    Meshes,
+    /// Uniform and storage buffers the engine keeps around; per frame pools aren't counted.
+    Uniforms,
+    RenderTargets,
+}
+
+impl MemoryCategory {
+    pub const ALL: [MemoryCategory; 4] = [MemoryCategory::Textures, MemoryCategory::Meshes, MemoryCategory::Uniforms, MemoryCategory::RenderTargets];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MemoryCategory::Textures => "textures",
+            MemoryCategory::Meshes => "meshes",
+            MemoryCategory::Uniforms => "uniforms",
+            MemoryCategory::RenderTargets => "targets",
+        }
+    }
+}
+
+struct Allocation {
+    category: MemoryCategory,
+    bytes: u64,
+    owner: Weak<dyn Any + Send + Sync>,
+}
+
+static ALLOCATIONS: Mutex<Vec<Allocation>> = Mutex::new(Vec::new());
+
+/// Counts `bytes` under `category` for as long as `owner` lives. Sizes are what the data takes,
+/// not what the driver rounded the allocation up to.
+pub fn track<T: Any + Send + Sync>(category: MemoryCategory, owner: &Arc<T>, bytes: u64) {
+    let owner: Weak<dyn Any + Send + Sync> = Arc::downgrade(owner);
+    ALLOCATIONS.lock().unwrap().push(Allocation { category, bytes, owner });
+}
+
+/// Bytes of a single layer `extent` image of `format`, without mips.
+pub fn image_bytes(format: Format, extent: [u32; 2]) -> u64 { format.block_size().unwrap_or(4) * extent[0] as u64 * extent[1] as u64 }
+
+/// Fraction of a device local heap's budget past which `GpuMemory` warns.
+pub const WARN_FRACTION: f32 = 0.9;
+
+#[derive(Clone, Copy, Debug)]
+pub struct HeapBudget {
+    pub size: u64,
+    /// What this process uses of it; 0 when unknown.
+    pub usage: u64,
+    /// How much it can use before allocations fail or start paging; the heap's size when unknown.
+    pub budget: u64,
+    pub device_local: bool,
+}
+
+/// Engine allocations by category and every memory heap's usage against its budget, the latter
+/// from VK_EXT_memory_budget when the device has it on. `update` once in a while, every frame
+/// is cheap enough.
+#[derive(Clone, Debug, Default)]
+pub struct GpuMemory {
+    /// Bytes, in `MemoryCategory::ALL` order.
+    pub categories: [u64; 4],
+    pub heaps: Vec<HeapBudget>,
+    /// Whether the driver reports usage and budgets.
+    pub has_budget: bool,
+    warned: bool,
+}
+
+impl GpuMemory {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn update(&mut self, device: &Device) {
+        let mut allocations = ALLOCATIONS.lock().unwrap();
+        allocations.retain(|a| a.owner.strong_count() > 0);
+        self.categories = [0; 4];
+        for a in allocations.iter() { self.categories[a.category as usize] += a.bytes; }
+        drop(allocations);
+
+        let physical = device.physical_device();
+        self.heaps = physical.memory_heaps().map(|h| HeapBudget { size: h.size(), usage: 0, budget: h.size(), device_local: h.is_device_local() }).collect();
+        self.has_budget = device.enabled_extensions().ext_memory_budget && physical.api_version() >= Version::V1_1
+            && device.instance().api_version() >= Version::V1_1;
+        if self.has_budget {
+            let mut budget = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+            let mut properties = vk::PhysicalDeviceMemoryProperties2 { p_next: &mut budget as *mut _ as *mut _, ..Default::default() };
+            //budget is chained in and outlives the call
+            unsafe { (device.instance().fns().v1_1.get_physical_device_memory_properties2)(physical.internal_object(), &mut properties); }
+            for (i, heap) in self.heaps.iter_mut().enumerate() {
+                heap.usage = budget.heap_usage[i];
+                heap.budget = budget.heap_budget[i];
+            }
+        }
+
+        //once per crossing, not every frame it stays there
+        let near = self.near_budget();
+        if near && !self.warned {
+            let (usage, budget) = self.device_local();
+            println!("gpu memory near budget: {} of {} MB", usage >> 20, budget >> 20);
+        }
+        self.warned = near;
+    }
+
+    /// Usage and budget summed over the device local heaps.
+    pub fn device_local(&self) -> (u64, u64) {
+        self.heaps.iter().filter(|h| h.device_local).fold((0, 0), |(u, b), h| (u + h.usage, b + h.budget))
+    }
+
+    /// Any device local heap past `WARN_FRACTION` of its budget. Never without budgets.
+    pub fn near_budget(&self) -> bool {
+        self.has_budget && self.heaps.iter().any(|h| h.device_local && h.usage as f32 > h.budget as f32 * WARN_FRACTION)
+    }
+}
@@ -0,0 +1,226 @@
+use vulkano::{ device::Device,
+               buffer::{ BufferUsage, CpuAccessibleBuffer, CpuBufferPool, DeviceLocalBuffer },
+               command_buffer::{ AutoCommandBufferBuilder, DispatchIndirectCommand, DrawIndirectCommand, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               image::{ AttachmentImage, view::{ ImageView, ImageViewAbstract } },
+               pipeline::{ ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint,
+                           graphics::{ color_blend::ColorBlendState, input_assembly::InputAssemblyState, vertex_input::BuffersDefinition,
+                                       viewport::ViewportState } },
+               render_pass::Subpass,
+               sampler::{ Filter, Sampler, SamplerAddressMode, SamplerCreateInfo } };
+use bytemuck::{ Pod, Zeroable };
+use glam::{ Mat4, Vec3 };
+use std::sync::Arc;
+use crate::camera::View;
+use crate::renderer::Target;
+use crate::particles::{ Emission, EmitterShape, ParticleCollision, ParticleEffect };
+use crate::scene::Scene;
+use crate::texture::Texture;
+use crate::time::Time;
+use crate::gpu_memory::{ self, MemoryCategory };
+
+mod cs {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/particles.comp", include: ["src/shaders"] }
+}
+mod vs {
+    vulkano_shaders::shader! { ty: "vertex", path: "src/shaders/gpu_particle.vert", include: ["src/shaders"] }
+}
+mod fs {
+    vulkano_shaders::shader! { ty: "fragment", path: "src/shaders/gpu_particle.frag", include: ["src/shaders"] }
+}
+
+/// Samples each over life curve is baked into. Keep in step with particle.glsl.
+pub const CURVE_SAMPLES: usize = 64;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct GpuParticle { pub position: [f32; 3], pub age: f32, pub velocity: [f32; 3], pub lifetime: f32 }
+
+/// Plays a particle effect from an entity on the gpu, for effects with too many particles to
+/// simulate on the cpu: fire, smoke, magic. The living particles are in one of two buffers;
+/// each frame the survivors and the newly emitted are appended to the other, which packs out
+/// the dead, and the count they leave sizes the next dispatch and the draw indirectly, so the
+/// cpu never reads it back.
+pub struct GpuEmitter {
+    /// Its over life curves are baked in `new`; a changed effect takes a new emitter.
+    pub effect: Arc<ParticleEffect>,
+    pub texture: Arc<Texture>,
+    /// Emitting; particles already out live on either way.
+    pub playing: bool,
+    emission: Emission,
+    particles: [Arc<DeviceLocalBuffer<[GpuParticle]>>; 2],
+    /// Which of `particles` holds the living.
+    current: usize,
+    /// Living particles, then the next list's count while it's being built.
+    counters: Arc<CpuAccessibleBuffer<[u32]>>,
+    dispatch: Arc<CpuAccessibleBuffer<[DispatchIndirectCommand]>>,
+    draw: Arc<CpuAccessibleBuffer<[DrawIndirectCommand]>>,
+    curves: Arc<CpuAccessibleBuffer<[[f32; 4]]>>,
+    seed: u32,
+}
+
+impl GpuEmitter {
+    /// Buffers for `effect.max_particles`, playing from the first update.
+    pub fn new(dev: Arc<Device>, effect: Arc<ParticleEffect>, texture: Arc<Texture>) -> Self {
+        let capacity = effect.max_particles.max(1) as u64;
+        let storage = BufferUsage { storage_buffer: true, ..BufferUsage::none() };
+        let indirect = BufferUsage { storage_buffer: true, indirect_buffer: true, ..BufferUsage::none() };
+        let buffer = || DeviceLocalBuffer::array(dev.clone(), capacity, storage, dev.active_queue_families()).unwrap();
+        let particles = [buffer(), buffer()];
+        let counters = CpuAccessibleBuffer::from_iter(dev.clone(), storage, false, [0u32; 2]).unwrap();
+        let dispatch = CpuAccessibleBuffer::from_iter(dev.clone(), indirect, false, [DispatchIndirectCommand { x: 0, y: 1, z: 1 }]).unwrap();
+        let draw = CpuAccessibleBuffer::from_iter(dev.clone(), indirect, false,
+            [DrawIndirectCommand { vertex_count: 6, instance_count: 0, first_vertex: 0, first_instance: 0 }]).unwrap();
+        let curves = CpuAccessibleBuffer::from_iter(dev, storage, false, bake(&effect)).unwrap();
+        let bytes = capacity * 2 * std::mem::size_of::<GpuParticle>() as u64 + (CURVE_SAMPLES * 2 * 16) as u64;
+        gpu_memory::track(MemoryCategory::Uniforms, &curves, bytes);
+        GpuEmitter { effect, texture, playing: true, emission: Emission::default(), particles, current: 0, counters, dispatch, draw, curves, seed: 0x2545_f491 }
+    }
+
+    /// From the start again, burst and all.
+    pub fn play(&mut self) {
+        self.playing = true;
+        self.emission = Emission::default();
+    }
+
+    pub fn stop(&mut self) { self.playing = false; }
+}
+
+/// Color over life, then size and speed over life, sampled evenly across a particle's life.
+fn bake(effect: &ParticleEffect) -> Vec<[f32; 4]> {
+    let t = |i: usize| i as f32 / (CURVE_SAMPLES - 1) as f32;
+    let colors = (0..CURVE_SAMPLES).map(|i| effect.color_over_life.sample(t(i)));
+    colors.chain((0..CURVE_SAMPLES).map(|i| [effect.size_over_life.sample(t(i)), effect.speed_over_life.sample(t(i)), 0.0, 0.0])).collect()
+}
+
+/// Simulates and draws every entity's `GpuEmitter`.
+pub struct GpuParticles {
+    compute: Arc<ComputePipeline>,
+    pipeline: Arc<GraphicsPipeline>,
+    uniforms: CpuBufferPool<cs::ty::Emitter>,
+    sampler: Arc<Sampler>,
+    depth_sampler: Arc<Sampler>,
+    /// Bound for depth until the target has been drawn once.
+    no_depth: Arc<Texture>,
+    /// The target's depth and the view about to draw it, which is what it holds next update.
+    last: Option<(Arc<ImageView<AttachmentImage>>, View)>,
+    pub fade_distance: f32,
+    /// Meters behind the depth buffer a particle still counts as hitting it; further back, it
+    /// went behind something instead.
+    pub collision_thickness: f32,
+}
+
+impl GpuParticles {
+    pub fn new(dev: Arc<Device>, subpass: Subpass, no_depth: Arc<Texture>) -> Self {
+        let compute = ComputePipeline::new(dev.clone(), cs::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let vs = vs::load(dev.clone()).unwrap();
+        let fs = fs::load(dev.clone()).unwrap();
+        let pipeline = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .color_blend_state(ColorBlendState::new(subpass.num_color_attachments()).blend_alpha())
+            .render_pass(subpass)
+            .build(dev.clone()).unwrap();
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo::simple_repeat_linear_no_mipmap()).unwrap();
+        let depth_sampler = Sampler::new(dev.clone(), SamplerCreateInfo { mag_filter: Filter::Nearest, min_filter: Filter::Nearest,
+                                                                          address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        GpuParticles { compute, pipeline, uniforms: CpuBufferPool::uniform_buffer(dev), sampler, depth_sampler, no_depth, last: None, fade_distance: 0.5, collision_thickness: 0.5 }
+    }
+
+    /// Steps every emitter by the frame's delta: survivors move, new ones are emitted from the
+    /// entity. Records compute, so once a frame before any `draw`, outside a render pass.
+    /// Colliding effects hit what `target` held from last frame, which `view` then draws over.
+    #[profiling::function]
+    pub fn update(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, time: &Time, scene: &mut Scene, target: &Target, view: &View) {
+        let dt = time.delta;
+        //a resized target hasn't been drawn yet
+        let depth = self.last.as_ref().filter(|(d, _)| Arc::ptr_eq(d, &target.depth));
+        let (depth_image, depth_view_proj) = match depth {
+            Some((d, v)) => (d.clone() as Arc<dyn ImageViewAbstract>, Some(v.view_proj())),
+            None => (self.no_depth.view.clone(), None),
+        };
+        let eye = depth.map_or(Vec3::ZERO, |(_, v)| v.eye());
+        for entity in &mut scene.entities {
+            let emitter = match &mut entity.gpu_particles { Some(e) => e, None => continue };
+            let effect = emitter.effect.clone();
+            let count = emitter.emission.step(&effect, dt, &mut emitter.playing).min(effect.max_particles) as u32;
+            emitter.seed = emitter.seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            let (kind, params) = match effect.shape {
+                EmitterShape::Point => (0.0, [0.0; 3]),
+                EmitterShape::Sphere { radius } => (1.0, [radius, 0.0, 0.0]),
+                EmitterShape::Cone { angle } => (2.0, [angle, 0.0, 0.0]),
+                EmitterShape::Box { half_extents } => (3.0, half_extents.to_array()),
+            };
+            let collision = match (effect.collision, depth_view_proj) {
+                (_, None) | (ParticleCollision::None, _) => [0.0; 4],
+                (ParticleCollision::Bounce { restitution, friction }, _) => [1.0, restitution, friction, 0.0],
+                (ParticleCollision::Kill, _) => [2.0, 0.0, 0.0, 0.0],
+            };
+            let depth_view_proj = depth_view_proj.unwrap_or(Mat4::IDENTITY);
+            let uniforms = self.uniforms.next(cs::ty::Emitter {
+                transform: entity.transform.to_cols_array_2d(),
+                depth_view_proj: depth_view_proj.to_cols_array_2d(),
+                inv_depth_view_proj: depth_view_proj.inverse().to_cols_array_2d(),
+                eye: eye.extend(self.collision_thickness).into(),
+                collision,
+                gravity: effect.gravity.extend(effect.drag).into(),
+                shape: [kind, params[0], params[1], params[2]],
+                ranges: [effect.lifetime[0], effect.lifetime[1], effect.speed[0], effect.speed[1]],
+                emit: count,
+                capacity: effect.max_particles as u32,
+                seed: emitter.seed,
+                dt,
+            }).unwrap();
+            let (source, destination) = (emitter.particles[emitter.current].clone(), emitter.particles[1 - emitter.current].clone());
+            let set = PersistentDescriptorSet::new(self.compute.layout().set_layouts().get(0).unwrap().clone(), [
+                WriteDescriptorSet::buffer(0, source),
+                WriteDescriptorSet::buffer(1, destination),
+                WriteDescriptorSet::buffer(2, emitter.counters.clone()),
+                WriteDescriptorSet::buffer(3, emitter.dispatch.clone()),
+                WriteDescriptorSet::buffer(4, emitter.draw.clone()),
+                WriteDescriptorSet::buffer(5, emitter.curves.clone()),
+                WriteDescriptorSet::buffer(6, uniforms),
+                WriteDescriptorSet::image_view_sampler(7, depth_image.clone(), self.depth_sampler.clone()),
+            ]).unwrap();
+            let layout = self.compute.layout().clone();
+            builder.bind_pipeline_compute(self.compute.clone())
+                .bind_descriptor_sets(PipelineBindPoint::Compute, layout.clone(), 0, set)
+                .push_constants(layout.clone(), 0, cs::ty::PushConstants { mode: 0 })
+                .dispatch_indirect(emitter.dispatch.clone()).unwrap()
+                .push_constants(layout.clone(), 0, cs::ty::PushConstants { mode: 1 })
+                .dispatch([(count + 63) / 64, 1, 1]).unwrap()
+                .push_constants(layout, 0, cs::ty::PushConstants { mode: 2 })
+                .dispatch([1, 1, 1]).unwrap();
+            emitter.current = 1 - emitter.current;
+        }
+        self.last = Some((target.depth.clone(), *view));
+    }
+
+    /// Draws the living particles of every emitter in the transparent subpass, unsorted; additive
+    /// looking effects hide that best.
+    #[profiling::function]
+    pub fn draw(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, scene: &Scene, depth: Arc<ImageView<AttachmentImage>>, view: &View) {
+        let inv = view.view.inverse();
+        let pc = vs::ty::PushConstants {
+            view_proj: view.view_proj().to_cols_array_2d(),
+            cam_right: inv.x_axis.into(), cam_up: inv.y_axis.into(),
+            near_far: [view.near, view.far, self.fade_distance, 0.0],
+        };
+        let layout = self.pipeline.layout().set_layouts().get(0).unwrap();
+        builder.bind_pipeline_graphics(self.pipeline.clone())
+            .push_constants(self.pipeline.layout().clone(), 0, pc);
+        for emitter in scene.entities.iter().filter_map(|e| e.gpu_particles.as_ref()) {
+            let set = PersistentDescriptorSet::new(layout.clone(), [
+                WriteDescriptorSet::image_view(0, depth.clone()),
+                WriteDescriptorSet::image_view_sampler(1, emitter.texture.view.clone(), self.sampler.clone()),
+                WriteDescriptorSet::buffer(2, emitter.particles[emitter.current].clone()),
+                WriteDescriptorSet::buffer(3, emitter.curves.clone()),
+            ]).unwrap();
+            builder.bind_descriptor_sets(PipelineBindPoint::Graphics, self.pipeline.layout().clone(), 0, set)
+                .draw_indirect(emitter.draw.clone()).unwrap();
+        }
+    }
+}
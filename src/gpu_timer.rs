@@ -0,0 +1,118 @@
+use vulkano::{ device::Queue,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               query::{ QueryPool, QueryPoolCreateInfo, QueryType, QueryResultFlags },
+               sync::PipelineStage };
+use std::sync::Arc;
+use crate::device_lost::Breadcrumbs;
+
+/// Frames of timestamps in flight; a frame's are read back when its pool comes round again.
+const FRAMES: usize = 3;
+/// Timestamps a frame can write: its start, a mark per pass and its end.
+const MAX_QUERIES: u32 = 32;
+
+struct FrameQueries {
+    pool: Arc<QueryPool>,
+    /// Of the passes ending at each mark, in order.
+    names: Vec<&'static str>,
+    written: u32,
+    /// Of the frame that wrote them, see `GpuTimer::frame`.
+    number: u64,
+}
+
+/// GPU time of a frame's command buffer and the passes in it, from timestamps at either end
+/// and at every `mark` between. Results are read back a few frames late without waiting, so
+/// `last_ms` and `passes` belong to an earlier finished frame, `last` says which. Times nothing on queues without
+/// timestamps, but the marks still leave breadcrumbs for when the device is lost.
+pub struct GpuTimer {
+    /// Empty without timestamps.
+    frames: Vec<FrameQueries>,
+    current: usize,
+    /// Nanoseconds per tick.
+    period: f32,
+    /// Ticks wrap at this many bits.
+    bits: u32,
+    /// Frames begun so far.
+    frame: u64,
+    last: Option<(u64, f32)>,
+    passes: Vec<(&'static str, f32)>,
+    breadcrumbs: Breadcrumbs,
+}
+
+impl GpuTimer {
+    pub fn new(queue: &Arc<Queue>) -> Self {
+        let dev = queue.device();
+        let bits = queue.family().timestamp_valid_bits().unwrap_or(0);
+        let frames = if bits > 0 {
+            (0..FRAMES).map(|_| FrameQueries {
+                pool: QueryPool::new(dev.clone(), QueryPoolCreateInfo { query_count: MAX_QUERIES, ..QueryPoolCreateInfo::query_type(QueryType::Timestamp) }).unwrap(),
+                names: Vec::new(), written: 0, number: 0 }).collect()
+        } else { Vec::new() };
+        GpuTimer { frames, current: 0, period: dev.physical_device().properties().timestamp_period, bits, frame: 0, last: None, passes: Vec::new(),
+                   breadcrumbs: Breadcrumbs::new(queue) }
+    }
+
+    /// First thing in the frame's command buffer, outside any render pass. Picks up the results
+    /// of the frame that last used this frame's queries if they are in.
+    pub fn begin(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        self.breadcrumbs.begin(builder);
+        self.frame += 1;
+        if self.frames.is_empty() { return; }
+        self.current = (self.current + 1) % FRAMES;
+        let frame = &mut self.frames[self.current];
+        if frame.written >= 2 {
+            let mut ticks = vec![0u64; frame.written as usize];
+            let flags = QueryResultFlags { wait: false, with_availability: false, partial: false };
+            if let Ok(true) = frame.pool.queries_range(0..frame.written).unwrap().get_results(&mut ticks, flags) {
+                let mask = if self.bits >= 64 { u64::MAX } else { (1u64 << self.bits) - 1 };
+                let ms = |from: u64, to: u64| (to.wrapping_sub(from) & mask) as f32 * self.period * 1e-6;
+                self.last = Some((frame.number, ms(ticks[0], ticks[ticks.len() - 1])));
+                self.passes = frame.names.iter().enumerate().map(|(i, &name)| (name, ms(ticks[i], ticks[i + 1]))).collect();
+            }
+        }
+        frame.names.clear();
+        frame.written = 1;
+        frame.number = self.frame;
+        //the queries are only ever written by the command buffer that reset them
+        unsafe {
+            builder.reset_query_pool(frame.pool.clone(), 0..MAX_QUERIES).unwrap()
+                .write_timestamp(frame.pool.clone(), 0, PipelineStage::TopOfPipe).unwrap();
+        }
+    }
+
+    /// Ends the pass called `name`, which began at the previous mark or at `begin`. Outside a
+    /// render pass. Marks past the pool's size are dropped.
+    pub fn mark(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, name: &'static str) {
+        self.breadcrumbs.mark(builder, name);
+        let frame = match self.frames.get_mut(self.current) { Some(f) => f, None => return };
+        //one left for end
+        if frame.written == 0 || frame.written + 1 >= MAX_QUERIES { return; }
+        unsafe { builder.write_timestamp(frame.pool.clone(), frame.written, PipelineStage::BottomOfPipe).unwrap(); }
+        frame.names.push(name);
+        frame.written += 1;
+    }
+
+    /// Last thing before the command buffer is built. Whatever ran after the last mark is in
+    /// the total but no pass.
+    pub fn end(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        let frame = match self.frames.get_mut(self.current) { Some(f) => f, None => return };
+        if frame.written == 0 { return; }
+        unsafe { builder.write_timestamp(frame.pool.clone(), frame.written, PipelineStage::BottomOfPipe).unwrap(); }
+        frame.written += 1;
+    }
+
+    /// Milliseconds, None until a frame was timed.
+    pub fn last_ms(&self) -> Option<f32> { self.last.map(|(_, ms)| ms) }
+
+    /// The frame `last_ms` belongs to, with it. The same one comes back until a newer frame's
+    /// results are in.
+    pub fn last(&self) -> Option<(u64, f32)> { self.last }
+
+    /// Number of the frame last begun, counting from 1.
+    pub fn frame(&self) -> u64 { self.frame }
+
+    /// Milliseconds of every marked pass of the same frame as `last_ms`, in order.
+    pub fn passes(&self) -> &[(&'static str, f32)] { &self.passes }
+
+    /// Where the gpu got in the last few frames.
+    pub fn breadcrumbs(&self) -> &Breadcrumbs { &self.breadcrumbs }
+}
@@ -0,0 +1,197 @@
+use vulkano::{ device::{ Device, Queue },
+               command_buffer::{ AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer, PrimaryCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               image::{ ImmutableImage, AttachmentImage, ImageDimensions, ImageUsage, ImageCreateFlags, ImageLayout, ImageAspects, ImageSubresourceRange, MipmapsCount,
+                        immutable::ImmutableImageInitialization, view::{ ImageView, ImageViewAbstract, ImageViewCreateInfo, ImageViewType } },
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode, SamplerMipmapMode, Filter },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint },
+               shader::ShaderModule,
+               format::Format,
+               sync::GpuFuture };
+use std::sync::Arc;
+use crate::texture::Texture;
+use crate::sky::ProceduralSky;
+
+mod equirect {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/equirect.comp", include: ["src/shaders"] }
+}
+mod irradiance {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/irradiance.comp", include: ["src/shaders"] }
+}
+mod prefilter {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/prefilter.comp", include: ["src/shaders"] }
+}
+mod faces_to_cube {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/faces_to_cube.comp", include: ["src/shaders"] }
+}
+mod sky {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/sky.comp", include: ["src/shaders"] }
+}
+mod brdf_lut {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/brdf_lut.comp", include: ["src/shaders"] }
+}
+
+const CUBE_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+const IRRADIANCE_SIZE: u32 = 32;
+const PREFILTER_SIZE: u32 = 128;
+const LUT_SIZE: u32 = 128;
+
+/// Prefiltered environment for image based lighting. `cube` is the unfiltered source, kept for
+/// drawing it as a sky.
+pub struct Environment {
+    pub cube: Arc<ImageView<ImmutableImage>>,
+    pub irradiance: Arc<ImageView<ImmutableImage>>,
+    pub prefiltered: Arc<ImageView<ImmutableImage>>,
+    /// Prefiltered mips, roughness 0 at mip 0 to roughness 1 at the last.
+    pub mip_levels: u32,
+}
+
+fn cube_image(queue: &Arc<Queue>, size: u32, mip_levels: u32) -> (Arc<ImmutableImage>, Arc<ImmutableImageInitialization>) {
+    ImmutableImage::uninitialized(queue.device().clone(), ImageDimensions::Dim2d { width: size, height: size, array_layers: 6 },
+        CUBE_FORMAT, MipmapsCount::Specific(mip_levels), ImageUsage { storage: true, sampled: true, ..ImageUsage::none() },
+        ImageCreateFlags { cube_compatible: true, ..ImageCreateFlags::none() }, ImageLayout::General,
+        queue.device().active_queue_families()).expect("failed creating cubemap")
+}
+
+/// The six faces of one mip as a 2d array, for compute shaders to write.
+fn face_view(init: &Arc<ImmutableImageInitialization>, mip: u32) -> Arc<ImageView<ImmutableImageInitialization>> {
+    ImageView::new(init.clone(), ImageViewCreateInfo {
+        view_type: ImageViewType::Dim2dArray,
+        subresource_range: ImageSubresourceRange { aspects: ImageAspects { color: true, ..ImageAspects::none() }, mip_levels: mip..mip + 1, array_layers: 0..6 },
+        ..ImageViewCreateInfo::from_image(init) }).unwrap()
+}
+
+fn cube_view(image: &Arc<ImmutableImage>) -> Arc<ImageView<ImmutableImage>> {
+    ImageView::new(image.clone(), ImageViewCreateInfo { view_type: ImageViewType::Cube, ..ImageViewCreateInfo::from_image(image) }).unwrap()
+}
+
+fn compute(dev: &Arc<Device>, module: Arc<ShaderModule>) -> Arc<ComputePipeline> {
+    ComputePipeline::new(dev.clone(), module.entry_point("main").unwrap(), &(), None, |_| {}).unwrap()
+}
+
+fn groups(size: u32, layers: u32) -> [u32; 3] { [(size + 7) / 8, (size + 7) / 8, layers] }
+
+/// Compute pipelines that turn an equirect panorama into an `Environment`, plus the brdf lut
+/// every environment shares.
+pub struct IblBaker {
+    queue: Arc<Queue>,
+    equirect: Arc<ComputePipeline>,
+    faces: Arc<ComputePipeline>,
+    irradiance: Arc<ComputePipeline>,
+    prefilter: Arc<ComputePipeline>,
+    sky: Arc<ComputePipeline>,
+    /// Trilinear clamp with the full lod range, for sampling the results.
+    pub sampler: Arc<Sampler>,
+    pub brdf_lut: Arc<ImageView<ImmutableImage>>,
+}
+
+impl IblBaker {
+    pub fn new(dev: Arc<Device>, queue: Arc<Queue>) -> Self {
+        let equirect = compute(&dev, equirect::load(dev.clone()).unwrap());
+        let faces = compute(&dev, faces_to_cube::load(dev.clone()).unwrap());
+        let irradiance = compute(&dev, irradiance::load(dev.clone()).unwrap());
+        let prefilter = compute(&dev, prefilter::load(dev.clone()).unwrap());
+        let sky = compute(&dev, sky::load(dev.clone()).unwrap());
+        let lut = compute(&dev, brdf_lut::load(dev.clone()).unwrap());
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo {
+            mag_filter: Filter::Linear, min_filter: Filter::Linear, mipmap_mode: SamplerMipmapMode::Linear,
+            address_mode: [SamplerAddressMode::ClampToEdge; 3], lod: 0.0..=1000.0, ..Default::default() }).unwrap();
+
+        let (image, init) = ImmutableImage::uninitialized(dev.clone(), ImageDimensions::Dim2d { width: LUT_SIZE, height: LUT_SIZE, array_layers: 1 },
+            Format::R16G16_SFLOAT, MipmapsCount::One, ImageUsage { storage: true, sampled: true, ..ImageUsage::none() },
+            ImageCreateFlags::none(), ImageLayout::General, dev.active_queue_families()).expect("failed creating brdf lut");
+        let mut builder = AutoCommandBufferBuilder::primary(dev.clone(), queue.family(), CommandBufferUsage::OneTimeSubmit).unwrap();
+        let set = PersistentDescriptorSet::new(lut.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view(0, ImageView::new_default(init).unwrap())]).unwrap();
+        builder.bind_pipeline_compute(lut.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, lut.layout().clone(), 0, set)
+            .dispatch(groups(LUT_SIZE, 1)).unwrap();
+        Self::submit(&queue, builder);
+        IblBaker { queue, equirect, faces, irradiance, prefilter, sky, sampler, brdf_lut: ImageView::new_default(image).unwrap() }
+    }
+
+    fn submit(queue: &Arc<Queue>, builder: AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        builder.build().unwrap().execute(queue.clone()).unwrap()
+            .then_signal_fence_and_flush().unwrap().wait(None).unwrap();
+    }
+
+    fn dispatch(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, pipeline: &Arc<ComputePipeline>,
+                source: WriteDescriptorSet, target: Arc<ImageView<ImmutableImageInitialization>>, size: u32, roughness: Option<f32>) {
+        let set = PersistentDescriptorSet::new(pipeline.layout().set_layouts().get(0).unwrap().clone(), [
+            source, WriteDescriptorSet::image_view(1, target)]).unwrap();
+        builder.bind_pipeline_compute(pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline.layout().clone(), 0, set);
+        if let Some(roughness) = roughness {
+            builder.push_constants(pipeline.layout().clone(), 0, prefilter::ty::PushConstants { roughness });
+        }
+        builder.dispatch(groups(size, 6)).unwrap();
+    }
+
+    /// Converts an equirect panorama to a cubemap and prefilters it. Blocks until done, fine for load time.
+    pub fn bake(&self, equirect: &Texture) -> Arc<Environment> {
+        //a cube face spans 90 degrees, the panorama's height 180
+        let size = (equirect.extent[1] / 2).next_power_of_two().clamp(8, 1024);
+        let equirect_sampler = Sampler::new(self.queue.device().clone(), SamplerCreateInfo::simple_repeat_linear_no_mipmap()).unwrap();
+        self.bake_from(&self.equirect, WriteDescriptorSet::image_view_sampler(0, equirect.view.clone(), equirect_sampler), size)
+    }
+
+    /// Same for six square renders in cube face order, as `ReflectionProbes` captures them.
+    pub fn bake_faces(&self, faces: &[Arc<ImageView<AttachmentImage>>; 6], size: u32) -> Arc<Environment> {
+        let faces = faces.iter().map(|f| (f.clone() as Arc<dyn ImageViewAbstract>, self.sampler.clone()));
+        self.bake_from(&self.faces, WriteDescriptorSet::image_view_sampler_array(0, 0, faces), size)
+    }
+
+    /// Renders a `ProceduralSky` into a `size` cube and prefilters it.
+    pub fn bake_sky(&self, sky: &ProceduralSky, size: u32) -> Arc<Environment> {
+        let (source, source_init) = cube_image(&self.queue, size, 1);
+        let set = PersistentDescriptorSet::new(self.sky.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view(0, face_view(&source_init, 0))]).unwrap();
+        let pc = sky::ty::PushConstants {
+            sun_direction: sky.sun_direction.normalize().extend(sky.sun_intensity).into(),
+            params: [sky.turbidity, sky.exposure, sky.mie_g, 0.0],
+            ground: sky.ground_albedo.extend(0.0).into(),
+        };
+        let mut builder = AutoCommandBufferBuilder::primary(self.queue.device().clone(), self.queue.family(), CommandBufferUsage::OneTimeSubmit).unwrap();
+        builder.bind_pipeline_compute(self.sky.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.sky.layout().clone(), 0, set)
+            .push_constants(self.sky.layout().clone(), 0, pc)
+            .dispatch(groups(size, 6)).unwrap();
+        Self::submit(&self.queue, builder);
+        self.filter(cube_view(&source), size)
+    }
+
+    /// `pipeline` fills the source cube from whatever `source` binds, then it gets filtered.
+    fn bake_from(&self, pipeline: &Arc<ComputePipeline>, source: WriteDescriptorSet, size: u32) -> Arc<Environment> {
+        let (image, init) = cube_image(&self.queue, size, 1);
+        let mut builder = AutoCommandBufferBuilder::primary(self.queue.device().clone(), self.queue.family(), CommandBufferUsage::OneTimeSubmit).unwrap();
+        self.dispatch(&mut builder, pipeline, source, face_view(&init, 0), size, None);
+        Self::submit(&self.queue, builder);
+        self.filter(cube_view(&image), size)
+    }
+
+    /// Irradiance and prefiltered mips of a finished `size` cube.
+    fn filter(&self, cube: Arc<ImageView<ImmutableImage>>, size: u32) -> Arc<Environment> {
+        let dev = self.queue.device().clone();
+        let prefilter_size = PREFILTER_SIZE.min(size);
+        let mip_levels = (prefilter_size.trailing_zeros() - 2).max(1); //stop at 8x8
+        let (irradiance, irradiance_init) = cube_image(&self.queue, IRRADIANCE_SIZE, 1);
+        let (prefiltered, prefiltered_init) = cube_image(&self.queue, prefilter_size, mip_levels);
+        let mut builder = AutoCommandBufferBuilder::primary(dev.clone(), self.queue.family(), CommandBufferUsage::OneTimeSubmit).unwrap();
+        self.dispatch(&mut builder, &self.irradiance, WriteDescriptorSet::image_view_sampler(0, cube.clone(), self.sampler.clone()),
+                      face_view(&irradiance_init, 0), IRRADIANCE_SIZE, None);
+        for mip in 0..mip_levels {
+            let roughness = if mip_levels > 1 { mip as f32 / (mip_levels - 1) as f32 } else { 0.0 };
+            self.dispatch(&mut builder, &self.prefilter, WriteDescriptorSet::image_view_sampler(0, cube.clone(), self.sampler.clone()),
+                          face_view(&prefiltered_init, mip), prefilter_size >> mip, Some(roughness));
+        }
+        Self::submit(&self.queue, builder);
+        Arc::new(Environment { cube, irradiance: cube_view(&irradiance), prefiltered: cube_view(&prefiltered), mip_levels })
+    }
+
+    /// Environment of a single color, for binding when a scene has none.
+    pub fn uniform(&self, color: [f32; 3]) -> Arc<Environment> {
+        let texel: Vec<u8> = bytemuck::cast_slice(&[color[0], color[1], color[2], 1.0f32]).to_vec();
+        let data = texel.iter().cycle().take(texel.len() * 32 * 16).cloned().collect();
+        self.bake(&Texture::from_rgba_format(self.queue.clone(), [32, 16], data, Format::R32G32B32A32_SFLOAT))
+    }
+}
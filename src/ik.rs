@@ -0,0 +1,115 @@
+use glam::{ Mat4, Quat, Vec3 };
+use crate::animation::{ Pose, Skeleton };
+
+#[derive(Clone, Debug)]
+pub enum IkSolver {
+    /// Hip, knee and ankle or shoulder, elbow and wrist: `end` reaches the target, `middle`
+    /// bending towards the pole. The end joint keeps its local rotation.
+    TwoBone { root: usize, middle: usize, end: usize },
+    /// Any number of joints, each the parent of the next or below it, the last reaching the
+    /// target. Iterates until within `tolerance` meters or out of `iterations`.
+    Fabrik { chain: Vec<usize>, iterations: u32, tolerance: f32 },
+    /// Turns the joint so its local `axis` points at the target; heads and eyes.
+    LookAt { joint: usize, axis: Vec3 },
+}
+
+/// A solver and what it reaches for, run over the animated pose.
+#[derive(Clone, Debug)]
+pub struct IkConstraint {
+    pub solver: IkSolver,
+    /// World space. None leaves the animation alone.
+    pub target: Option<Vec3>,
+    /// World space point the two bone middle joint bends towards, in front of the knee for a
+    /// leg. Without one it keeps the side it's bent to.
+    pub pole: Option<Vec3>,
+    /// 0 leaves the animation alone, 1 reaches fully.
+    pub weight: f32,
+}
+
+impl IkConstraint {
+    pub fn new(solver: IkSolver) -> Self { IkConstraint { solver, target: None, pole: None, weight: 1.0 } }
+}
+
+fn position(m: &Mat4) -> Vec3 { m.w_axis.truncate() }
+
+fn rotation(m: &Mat4) -> Quat { m.to_scale_rotation_translation().1 }
+
+/// Turns `joint` by `delta` in model space, taking its children along.
+fn rotate(skeleton: &Skeleton, pose: &mut Pose, model: &mut Vec<Mat4>, joint: usize, delta: Quat) {
+    let parent = skeleton.joints[joint].parent.map_or(Quat::IDENTITY, |p| rotation(&model[p]));
+    let local = &mut pose.joints[joint];
+    local.rotation = (parent.inverse() * delta * parent * local.rotation).normalize();
+    *model = skeleton.model_space(pose);
+}
+
+/// Turns `joint` so the direction from it to `from` points to `to` instead.
+fn aim(skeleton: &Skeleton, pose: &mut Pose, model: &mut Vec<Mat4>, joint: usize, from: Vec3, to: Vec3) {
+    let origin = position(&model[joint]);
+    let (from, to) = ((from - origin).normalize_or_zero(), (to - origin).normalize_or_zero());
+    if from == Vec3::ZERO || to == Vec3::ZERO { return; }
+    rotate(skeleton, pose, model, joint, Quat::from_rotation_arc(from, to));
+}
+
+fn two_bone(skeleton: &Skeleton, pose: &mut Pose, model: &mut Vec<Mat4>, [root, middle, end]: [usize; 3], target: Vec3, pole: Option<Vec3>) {
+    let (a, b, c) = (position(&model[root]), position(&model[middle]), position(&model[end]));
+    let (upper, lower) = (a.distance(b), b.distance(c));
+    let dir = (target - a).normalize_or_zero();
+    if dir == Vec3::ZERO || upper * lower == 0.0 { return; }
+    //out of reach straightens the limb, too close folds it as far as it goes
+    let reach = a.distance(target).clamp((upper - lower).abs() + 1e-4, upper + lower - 1e-4);
+    let along = (upper * upper - lower * lower + reach * reach) / (2.0 * reach);
+    let height = (upper * upper - along * along).max(0.0).sqrt();
+    let bend = pole.unwrap_or(b) - a;
+    let side = (bend - dir * bend.dot(dir)).try_normalize().unwrap_or_else(|| dir.any_orthonormal_vector());
+    let knee = a + dir * along + side * height;
+    aim(skeleton, pose, model, root, b, knee);
+    let c = position(&model[end]);
+    aim(skeleton, pose, model, middle, c, a + dir * reach);
+}
+
+fn fabrik(skeleton: &Skeleton, pose: &mut Pose, model: &mut Vec<Mat4>, chain: &[usize], iterations: u32, tolerance: f32, target: Vec3) {
+    if chain.len() < 2 { return; }
+    let mut points: Vec<Vec3> = chain.iter().map(|&j| position(&model[j])).collect();
+    let lengths: Vec<f32> = points.windows(2).map(|p| p[0].distance(p[1])).collect();
+    let root = points[0];
+    let last = points.len() - 1;
+    if root.distance(target) >= lengths.iter().sum::<f32>() {
+        let dir = (target - root).normalize_or_zero();
+        for i in 1..points.len() { points[i] = points[i - 1] + dir * lengths[i - 1]; }
+    } else {
+        for _ in 0..iterations {
+            if points[last].distance(target) <= tolerance { break; }
+            //end to the target and back, then the root back where it was
+            points[last] = target;
+            for i in (0..last).rev() { points[i] = points[i + 1] + (points[i] - points[i + 1]).normalize_or_zero() * lengths[i]; }
+            points[0] = root;
+            for i in 1..points.len() { points[i] = points[i - 1] + (points[i] - points[i - 1]).normalize_or_zero() * lengths[i - 1]; }
+        }
+    }
+    for i in 0..last {
+        let next = position(&model[chain[i + 1]]);
+        aim(skeleton, pose, model, chain[i], next, points[i + 1]);
+    }
+}
+
+/// `pose` with every constraint applied in order, each seeing the ones before it. `to_model`
+/// takes world space targets into the skeleton's.
+pub fn solve(skeleton: &Skeleton, pose: &Pose, constraints: &[IkConstraint], to_model: Mat4) -> Pose {
+    let mut pose = pose.clone();
+    for constraint in constraints.iter().filter(|c| c.weight > 0.0) {
+        let target = match constraint.target { Some(t) => to_model.transform_point3(t), None => continue };
+        let pole = constraint.pole.map(|p| to_model.transform_point3(p));
+        let mut solved = pose.clone();
+        let mut model = skeleton.model_space(&solved);
+        match &constraint.solver {
+            IkSolver::TwoBone { root, middle, end } => two_bone(skeleton, &mut solved, &mut model, [*root, *middle, *end], target, pole),
+            IkSolver::Fabrik { chain, iterations, tolerance } => fabrik(skeleton, &mut solved, &mut model, chain, *iterations, *tolerance, target),
+            IkSolver::LookAt { joint, axis } => {
+                let from = position(&model[*joint]) + rotation(&model[*joint]) * *axis;
+                aim(skeleton, &mut solved, &mut model, *joint, from, target);
+            }
+        }
+        pose.blend(&solved, constraint.weight.min(1.0));
+    }
+    pose
+}
@@ -0,0 +1,260 @@
+use vulkano::{ device::{ Device, Queue },
+               buffer::{ BufferUsage, CpuBufferPool },
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, SubpassContents },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               image::{ SwapchainImage, view::ImageView },
+               sampler::{ Sampler, SamplerCreateInfo },
+               render_pass::{ Framebuffer, FramebufferCreateInfo, RenderPass, Subpass },
+               pipeline::{ GraphicsPipeline, Pipeline, PipelineBindPoint, graphics::{ input_assembly::InputAssemblyState, vertex_input::BuffersDefinition,
+                                                                   viewport::{ Scissor, Viewport, ViewportState }, color_blend::ColorBlendState } },
+               format::{ ClearValue, Format },
+               impl_vertex };
+use bytemuck::{ Pod, Zeroable };
+use imgui::{ Context, DrawCmd, DrawCmdParams, FontConfig, FontSource, Key, MouseCursor, TextureId, Textures };
+use winit::{ event::{ ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent }, window::{ CursorIcon, Window } };
+use std::sync::Arc;
+use crate::texture::Texture;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+struct ImguiVertex { position: [f32; 2], uv: [f32; 2], color: [f32; 4], }
+impl_vertex!(ImguiVertex, position, uv, color);
+
+mod vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			layout(location = 0) in vec2 position;
+			layout(location = 1) in vec2 uv;
+			layout(location = 2) in vec4 color;
+			layout(location = 0) out vec2 v_uv;
+			layout(location = 1) out vec4 v_color;
+
+			layout(push_constant) uniform PushConstants { vec2 screen; } pc; //points
+
+			//imgui's colors are srgb; the target is linear
+			vec3 linear(vec3 srgb) { return mix(srgb / 12.92, pow((srgb + 0.055) / 1.055, vec3(2.4)), step(0.04045, srgb)); }
+
+			void main() {
+				gl_Position = vec4(position / pc.screen * 2.0 - 1.0, 0.0, 1.0);
+				v_uv = uv;
+				v_color = vec4(linear(color.rgb), color.a);
+			}"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! { ty: "fragment",
+    src: "#version 450
+
+			layout(set = 0, binding = 0) uniform sampler2D u_texture;
+			layout(location = 0) in vec2 v_uv;
+			layout(location = 1) in vec4 v_color;
+			layout(location = 0) out vec4 f_color;
+
+			void main() { f_color = v_color * texture(u_texture, v_uv); }"
+    }
+}
+
+/// A run of indices drawn with one texture and clip rect.
+struct Draw {
+    texture: TextureId,
+    /// min.xy, max.xy in pixels.
+    clip: [f32; 4],
+    count: u32,
+    first_index: u32,
+    vertex_offset: i32,
+}
+
+/// Dear ImGui through imgui-rs, for tooling already written against it; `EguiPass` otherwise.
+/// Feeds winit events to the `Context` and draws its frame in a pass of its own onto the
+/// swapchain image, over the overlay. No clipboard.
+pub struct ImguiPass {
+    pub ctx: Context,
+    pixels_per_point: f32,
+    textures: Textures<Arc<Texture>>,
+    vertices: Vec<ImguiVertex>,
+    indices: Vec<u16>,
+    draws: Vec<Draw>,
+    render_pass: Arc<RenderPass>,
+    pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    vertex_pool: CpuBufferPool<ImguiVertex>,
+    index_pool: CpuBufferPool<u16>,
+    framebuffers: Vec<Arc<Framebuffer>>,
+}
+
+impl ImguiPass {
+    pub fn new(dev: Arc<Device>, queue: Arc<Queue>, format: Format, window: &Window) -> Self {
+        let render_pass = vulkano::single_pass_renderpass!( dev.clone(),
+                                                            attachments: { color: { load: Load, store: Store, format: format, samples: 1,}},
+                                                            pass: { color: [color], depth_stencil: {} }).unwrap();
+        let vs = vs::load(dev.clone()).unwrap();
+        let fs = fs::load(dev.clone()).unwrap();
+        let pipeline = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new().vertex::<ImguiVertex>())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_dynamic(1))
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .color_blend_state(ColorBlendState::new(1).blend_alpha())
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(dev.clone()).unwrap();
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo::simple_repeat_linear_no_mipmap()).unwrap();
+        let pixels_per_point = window.scale_factor() as f32;
+        let mut ctx = Context::create();
+        ctx.set_ini_filename(None);
+        //rasterized at the screen's density and scaled back, so text stays sharp
+        ctx.fonts().add_font(&[FontSource::DefaultFontData { config: Some(FontConfig { size_pixels: 13.0 * pixels_per_point, ..FontConfig::default() }) }]);
+        ctx.io_mut().font_global_scale = 1.0 / pixels_per_point;
+        let mut textures = Textures::new();
+        let atlas = ctx.fonts().build_rgba32_texture();
+        let font = Texture::from_rgba(queue, [atlas.width, atlas.height], atlas.data.to_vec());
+        ctx.fonts().tex_id = textures.insert(font);
+        ImguiPass { ctx, pixels_per_point, textures, vertices: Vec::new(), indices: Vec::new(), draws: Vec::new(), render_pass, pipeline, sampler,
+                    vertex_pool: CpuBufferPool::vertex_buffer(dev.clone()), index_pool: CpuBufferPool::new(dev, BufferUsage::index_buffer()),
+                    framebuffers: Vec::new() }
+    }
+
+    pub fn resize(&mut self, images: &[Arc<SwapchainImage<Window>>]) {
+        self.framebuffers = images.iter().map(|image| {
+            Framebuffer::new(self.render_pass.clone(), FramebufferCreateInfo {
+                attachments: vec![ImageView::new_default(image.clone()).unwrap()], ..Default::default() }).unwrap()
+        }).collect();
+    }
+
+    /// Makes an engine texture showable with `imgui::Image`.
+    pub fn register(&mut self, texture: Arc<Texture>) -> TextureId { self.textures.insert(texture) }
+
+    /// Passes `event` on to imgui. True when it wants it to itself and the engine should leave
+    /// it be.
+    pub fn on_event(&mut self, event: &WindowEvent) -> bool {
+        let ppp = self.pixels_per_point;
+        let io = self.ctx.io_mut();
+        let (mouse, keyboard) = (io.want_capture_mouse, io.want_capture_keyboard);
+        match event {
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                //the font atlas stays rasterized for the old density
+                self.pixels_per_point = *scale_factor as f32;
+                false
+            }
+            WindowEvent::CursorMoved { position, .. } => { io.add_mouse_pos_event([position.x as f32 / ppp, position.y as f32 / ppp]); mouse }
+            WindowEvent::CursorLeft { .. } => { io.add_mouse_pos_event([f32::MAX, f32::MAX]); false }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button { MouseButton::Left => imgui::MouseButton::Left, MouseButton::Right => imgui::MouseButton::Right,
+                                            MouseButton::Middle => imgui::MouseButton::Middle, MouseButton::Other(_) => return false };
+                io.add_mouse_button_event(button, *state == ElementState::Pressed);
+                mouse
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                //in lines
+                let [x, y] = match delta { MouseScrollDelta::LineDelta(x, y) => [*x, *y],
+                                           MouseScrollDelta::PixelDelta(p) => [p.x as f32 / 50.0, p.y as f32 / 50.0] };
+                io.add_mouse_wheel_event([x, y]);
+                mouse
+            }
+            WindowEvent::ModifiersChanged(m) => {
+                io.add_key_event(Key::ModCtrl, m.ctrl());
+                io.add_key_event(Key::ModShift, m.shift());
+                io.add_key_event(Key::ModAlt, m.alt());
+                io.add_key_event(Key::ModSuper, m.logo());
+                false
+            }
+            WindowEvent::ReceivedCharacter(c) => {
+                if !c.is_control() { io.add_input_character(*c); }
+                keyboard
+            }
+            WindowEvent::KeyboardInput { input, .. } => {
+                if let Some(key) = input.virtual_keycode.and_then(key) { io.add_key_event(key, input.state == ElementState::Pressed); }
+                keyboard
+            }
+            _ => false,
+        }
+    }
+
+    /// Runs `ui` as the frame's imgui frame and keeps what it drew. Once a frame, before `draw`.
+    pub fn run(&mut self, window: &Window, dt: f32, ui: impl FnOnce(&imgui::Ui)) {
+        let ppp = self.pixels_per_point;
+        let size = window.inner_size();
+        let io = self.ctx.io_mut();
+        io.display_size = [size.width as f32 / ppp, size.height as f32 / ppp];
+        io.display_framebuffer_scale = [ppp, ppp];
+        //imgui asserts on a zero delta, which the first frame has
+        io.delta_time = dt.max(1e-4);
+        let frame = self.ctx.new_frame();
+        ui(frame);
+        match frame.mouse_cursor() {
+            Some(c) => { window.set_cursor_visible(true); window.set_cursor_icon(cursor(c)); }
+            None => window.set_cursor_visible(false),
+        }
+        let data = self.ctx.render();
+        self.vertices.clear();
+        self.indices.clear();
+        self.draws.clear();
+        //clip rects come in display coordinates, offset and in points
+        let (origin, scale) = (data.display_pos, data.framebuffer_scale);
+        for list in data.draw_lists() {
+            let (first_vertex, first_index) = (self.vertices.len() as i32, self.indices.len() as u32);
+            self.vertices.extend(list.vtx_buffer().iter().map(|v| ImguiVertex { position: v.pos, uv: v.uv, color: v.col.map(|c| c as f32 / 255.0) }));
+            self.indices.extend_from_slice(list.idx_buffer());
+            for command in list.commands() {
+                if let DrawCmd::Elements { count, cmd_params: DrawCmdParams { clip_rect, texture_id, vtx_offset, idx_offset } } = command {
+                    let clip = [(clip_rect[0] - origin[0]) * scale[0], (clip_rect[1] - origin[1]) * scale[1],
+                                (clip_rect[2] - origin[0]) * scale[0], (clip_rect[3] - origin[1]) * scale[1]];
+                    self.draws.push(Draw { texture: texture_id, clip, count: count as u32, first_index: first_index + idx_offset as u32,
+                                           vertex_offset: first_vertex + vtx_offset as i32 });
+                }
+            }
+        }
+    }
+
+    pub fn draw(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, image_num: usize, viewport: &Viewport) {
+        if self.draws.is_empty() { return; }
+        let ppp = self.pixels_per_point;
+        let layout = self.pipeline.layout().set_layouts().get(0).unwrap();
+        builder.begin_render_pass(self.framebuffers[image_num].clone(), SubpassContents::Inline, vec![ClearValue::None]).unwrap()
+            .set_viewport(0, [viewport.clone()])
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .push_constants(self.pipeline.layout().clone(), 0, vs::ty::PushConstants { screen: viewport.dimensions.map(|d| d / ppp) })
+            .bind_vertex_buffers(0, self.vertex_pool.chunk(self.vertices.iter().cloned()).unwrap())
+            .bind_index_buffer(self.index_pool.chunk(self.indices.iter().cloned()).unwrap());
+        for d in &self.draws {
+            let min = [d.clip[0].max(0.0), d.clip[1].max(0.0)];
+            let max = [d.clip[2].min(viewport.dimensions[0]), d.clip[3].min(viewport.dimensions[1])];
+            let texture = match self.textures.get(d.texture) { Some(t) => t, None => continue };
+            if max[0] <= min[0] || max[1] <= min[1] { continue; }
+            let set = PersistentDescriptorSet::new(layout.clone(), [WriteDescriptorSet::image_view_sampler(0, texture.view.clone(), self.sampler.clone())]).unwrap();
+            let scissor = Scissor { origin: min.map(|v| v as u32), dimensions: [(max[0] - min[0]).ceil() as u32, (max[1] - min[1]).ceil() as u32] };
+            builder.set_scissor(0, [scissor])
+                .bind_descriptor_sets(PipelineBindPoint::Graphics, self.pipeline.layout().clone(), 0, set)
+                .draw_indexed(d.count, 1, d.first_index, d.vertex_offset, 0).unwrap();
+        }
+        builder.end_render_pass().unwrap();
+    }
+}
+
+fn key(key: VirtualKeyCode) -> Option<Key> {
+    use VirtualKeyCode as V;
+    Some(match key {
+        V::Down => Key::DownArrow, V::Left => Key::LeftArrow, V::Right => Key::RightArrow, V::Up => Key::UpArrow,
+        V::Escape => Key::Escape, V::Tab => Key::Tab, V::Back => Key::Backspace, V::Return => Key::Enter, V::NumpadEnter => Key::KeypadEnter,
+        V::Space => Key::Space, V::Insert => Key::Insert, V::Delete => Key::Delete, V::Home => Key::Home, V::End => Key::End,
+        V::PageUp => Key::PageUp, V::PageDown => Key::PageDown,
+        V::A => Key::A, V::C => Key::C, V::V => Key::V, V::X => Key::X, V::Y => Key::Y, V::Z => Key::Z,
+        _ => return None,
+    })
+}
+
+fn cursor(cursor: MouseCursor) -> CursorIcon {
+    match cursor {
+        MouseCursor::Arrow => CursorIcon::Default,
+        MouseCursor::TextInput => CursorIcon::Text,
+        MouseCursor::ResizeAll => CursorIcon::Move,
+        MouseCursor::ResizeNS => CursorIcon::NsResize,
+        MouseCursor::ResizeEW => CursorIcon::EwResize,
+        MouseCursor::ResizeNESW => CursorIcon::NeswResize,
+        MouseCursor::ResizeNWSE => CursorIcon::NwseResize,
+        MouseCursor::Hand => CursorIcon::Hand,
+        MouseCursor::NotAllowed => CursorIcon::NotAllowed,
+    }
+}
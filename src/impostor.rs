@@ -0,0 +1,114 @@
+use vulkano::{ device::{ Device, Queue },
+               command_buffer::{ AutoCommandBufferBuilder, CommandBufferUsage, SubpassContents },
+               image::{ AttachmentImage, ImageUsage, view::ImageView },
+               render_pass::{ Framebuffer, FramebufferCreateInfo, Subpass },
+               pipeline::{ GraphicsPipeline, Pipeline, graphics::{ input_assembly::InputAssemblyState, vertex_input::BuffersDefinition,
+                                                                   viewport::{ Viewport, ViewportState }, depth_stencil::DepthStencilState } },
+               format::Format,
+               sync::{ self, GpuFuture } };
+use glam::{ Vec3, Mat4 };
+use std::sync::Arc;
+use std::f32::consts::TAU;
+use crate::mesh::{ Mesh, Vertex };
+use crate::texture::Texture;
+use crate::billboard::{ Billboard, BillboardMode };
+
+mod vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			layout(location = 0) in vec3 position;
+			layout(push_constant) uniform PushConstants { mat4 transform; } pc;
+
+			void main() { gl_Position = pc.transform * vec4(position, 1.0); }"
+    }
+}
+mod fs {
+    vulkano_shaders::shader! { ty: "fragment",
+    src: "#version 450
+
+			layout(location = 0) out vec4 f_color;
+
+			void main() { f_color = vec4(1.0, 0.0, 0.0, 1.0); }"
+    }
+}
+
+/// Atlas of a mesh rendered from `directions` evenly spaced yaw angles, laid out left to right.
+/// Used as the last lod: past `distance` the entity is drawn as a cylindrical billboard showing
+/// whichever frame was captured closest to the current view direction.
+pub struct Impostor {
+    pub texture: Arc<Texture>,
+    pub directions: u32,
+    pub radius: f32,
+    pub distance: f32,
+}
+
+impl Impostor {
+    pub fn bake(dev: Arc<Device>, queue: Arc<Queue>, mesh: &Mesh, directions: u32, frame_size: u32, distance: f32) -> Arc<Impostor> {
+        let extent = [frame_size * directions, frame_size];
+        let color = AttachmentImage::with_usage(dev.clone(), extent, Format::R8G8B8A8_SRGB,
+            ImageUsage { color_attachment: true, sampled: true, ..ImageUsage::none() }).unwrap();
+        let depth = AttachmentImage::transient(dev.clone(), extent, Format::D16_UNORM).unwrap();
+        let render_pass = vulkano::single_pass_renderpass!( dev.clone(),
+                                                            attachments: { color: { load: Clear, store: Store, format: Format::R8G8B8A8_SRGB, samples: 1,},
+                                                                           depth: { load: Clear, store: DontCare, format: Format::D16_UNORM, samples: 1,}},
+                                                            pass: { color: [color], depth_stencil: {depth} }).unwrap();
+        let vs = vs::load(dev.clone()).unwrap();
+        let fs = fs::load(dev.clone()).unwrap();
+        let pipeline = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .depth_stencil_state(DepthStencilState::simple_depth_test())
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(dev.clone()).unwrap();
+        let color_view = ImageView::new_default(color).unwrap();
+        let framebuffer = Framebuffer::new(render_pass, FramebufferCreateInfo {
+            attachments: vec![color_view.clone(), ImageView::new_default(depth).unwrap()], ..Default::default() }).unwrap();
+
+        //orthographic capture fitted to the bounding sphere
+        let center = mesh.aabb.center();
+        let radius = mesh.aabb.extent().length() * 0.5;
+        let mut proj = Mat4::orthographic_rh(-radius, radius, -radius, radius, 0.0, radius * 2.0);
+        proj.y_axis.y *= -1.0;
+
+        let mut builder = AutoCommandBufferBuilder::primary(dev.clone(), queue.family(), CommandBufferUsage::OneTimeSubmit).unwrap();
+        builder.begin_render_pass(framebuffer, SubpassContents::Inline, vec![[0.0, 0.0, 0.0, 0.0].into(), 1f32.into()]).unwrap()
+            .bind_pipeline_graphics(pipeline.clone())
+            .bind_vertex_buffers(0, mesh.vertex_buffer.clone());
+        for i in 0..directions {
+            let eye = center + Self::direction(i, directions) * radius;
+            let pc = vs::ty::PushConstants { transform: (proj * Mat4::look_at_rh(eye, center, Vec3::Y)).to_cols_array_2d() };
+            let viewport = Viewport { origin: [(i * frame_size) as f32, 0.0], dimensions: [frame_size as f32; 2], depth_range: 0.0..1.0 };
+            builder.set_viewport(0, [viewport])
+                .push_constants(pipeline.layout().clone(), 0, pc)
+                .draw(mesh.vertices.len() as u32, 1, 0, 0).unwrap();
+        }
+        builder.end_render_pass().unwrap();
+        sync::now(dev).then_execute(queue, builder.build().unwrap()).unwrap()
+            .then_signal_fence_and_flush().unwrap().wait(None).unwrap();
+
+        Arc::new(Impostor { texture: Arc::new(Texture { view: color_view, extent }), directions, radius, distance })
+    }
+
+    fn direction(i: u32, directions: u32) -> Vec3 {
+        let a = i as f32 / directions as f32 * TAU;
+        Vec3::new(a.sin(), 0.0, a.cos())
+    }
+
+    /// Billboard standing in for an entity with world transform `transform` seen from `eye`.
+    pub fn billboard(&self, transform: &Mat4, mesh: &Mesh, eye: Vec3) -> Billboard {
+        let (scale, rot, _) = transform.to_scale_rotation_translation();
+        let position = transform.transform_point3(mesh.aabb.center());
+        //frame index from the view direction in the entity's local space
+        let local = rot.inverse() * (eye - position);
+        let angle = local.x.atan2(local.z).rem_euclid(TAU);
+        let frame = (angle / TAU * self.directions as f32).round() as u32 % self.directions;
+        let w = 1.0 / self.directions as f32;
+        let size = self.radius * 2.0 * scale.max_element();
+        Billboard { uv: [frame as f32 * w, 0.0, (frame + 1) as f32 * w, 1.0], mode: BillboardMode::Cylindrical,
+                    ..Billboard::new(self.texture.clone(), position, size) }
+    }
+}
@@ -0,0 +1,126 @@
+use glam::Vec3;
+use crate::scene::{ Scene, EntityId };
+
+pub const MAX_POINT_LIGHTS: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    /// Shines along the entity's -z axis, position ignored.
+    Directional,
+    Point { range: f32 },
+    /// Cone along the entity's -z axis. Angles are radians from the axis; falloff between them.
+    Spot { range: f32, inner: f32, outer: f32 },
+}
+
+/// Per light shadow tuning. Acne wants more bias, peter-panning (shadows detached from their
+/// casters) wants less; `cull_front` casts from back faces, which suits closed meshes.
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowBias {
+    /// Depth bias applied while casting, in units of the smallest depth step.
+    pub constant: f32,
+    /// Depth bias scaled by the caster's depth slope.
+    pub slope: f32,
+    /// Receiver pushed along its normal before the lookup, in shadow map texels.
+    pub normal_offset: f32,
+    pub cull_front: bool,
+}
+
+impl Default for ShadowBias {
+    fn default() -> Self { ShadowBias { constant: 1.0, slope: 1.5, normal_offset: 1.0, cull_front: false } }
+}
+
+/// Scene wide tuning for the lights that have `contact_shadows` on. Screen space, so deferred only.
+#[derive(Clone, Copy, Debug)]
+pub struct ContactShadows {
+    /// Meters marched towards the light.
+    pub length: f32,
+    /// Meters behind the depth buffer a step still counts as blocked.
+    pub thickness: f32,
+    pub steps: u32,
+}
+
+impl Default for ContactShadows {
+    fn default() -> Self { ContactShadows { length: 0.3, thickness: 0.1, steps: 12 } }
+}
+
+/// Light component. Placement comes from the owning entity's transform.
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Casts shadows. On by default for directional lights, opt in for local ones.
+    pub shadows: bool,
+    pub bias: ShadowBias,
+    /// Short screen space shadows where the shadow map is too coarse to ground things.
+    pub contact_shadows: bool,
+}
+
+impl Light {
+    pub fn directional(color: [f32; 3], intensity: f32) -> Self {
+        Light { kind: LightKind::Directional, color, intensity, shadows: true, bias: ShadowBias::default(), contact_shadows: false }
+    }
+    pub fn point(color: [f32; 3], intensity: f32, range: f32) -> Self {
+        Light { kind: LightKind::Point { range }, color, intensity, shadows: false, bias: ShadowBias::default(), contact_shadows: false }
+    }
+    pub fn spot(color: [f32; 3], intensity: f32, range: f32, inner: f32, outer: f32) -> Self {
+        Light { kind: LightKind::Spot { range, inner, outer }, color, intensity, shadows: false, bias: ShadowBias::default(), contact_shadows: false }
+    }
+    pub fn with_shadows(mut self, shadows: bool) -> Self { self.shadows = shadows; self }
+    pub fn with_bias(mut self, bias: ShadowBias) -> Self { self.bias = bias; self }
+    pub fn with_contact_shadows(mut self, contact_shadows: bool) -> Self { self.contact_shadows = contact_shadows; self }
+    /// Bias to cast with, or None if the light casts no shadows.
+    pub fn shadow(&self) -> Option<ShadowBias> { if self.shadows { Some(self.bias) } else { None } }
+    pub fn radiance(&self) -> Vec3 { Vec3::from(self.color) * self.intensity }
+}
+
+/// Point or spot light; `cone` is (inner, outer) for spots.
+pub struct PointLightData {
+    pub entity: EntityId,
+    pub position: Vec3,
+    pub range: f32,
+    pub radiance: Vec3,
+    pub direction: Vec3,
+    pub cone: Option<(f32, f32)>,
+    pub shadow: Option<ShadowBias>,
+    pub contact_shadows: bool,
+}
+
+/// What the lit shader needs this frame: the brightest directional light, and the point lights
+/// nearest `eye` first, up to `MAX_POINT_LIGHTS`. See cluster.rs for how they are culled.
+pub struct SceneLights {
+    pub ambient: Vec3,
+    pub sun: Option<(Vec3, Vec3)>, //direction, radiance
+    pub sun_shadow: Option<ShadowBias>,
+    pub sun_contact_shadows: bool,
+    pub points: Vec<PointLightData>,
+}
+
+impl SceneLights {
+    pub fn gather(scene: &Scene, eye: Vec3) -> Self {
+        let mut sun = None;
+        let mut sun_shadow = None;
+        let mut sun_contact_shadows = false;
+        let mut points = Vec::new();
+        for e in &scene.entities {
+            let light = match &e.light { Some(l) => l, None => continue };
+            let direction = e.transform.transform_vector3(-Vec3::Z).normalize();
+            let (range, cone) = match light.kind {
+                LightKind::Directional if sun.map_or(true, |(_, r): (Vec3, Vec3)| light.radiance().length_squared() > r.length_squared()) => {
+                    sun = Some((direction, light.radiance()));
+                    sun_shadow = light.shadow();
+                    sun_contact_shadows = light.contact_shadows;
+                    continue;
+                }
+                LightKind::Directional => continue,
+                LightKind::Point { range } => (range, None),
+                LightKind::Spot { range, inner, outer } => (range, Some((inner, outer))),
+            };
+            points.push(PointLightData { entity: e.id, position: e.position(), range, radiance: light.radiance(), direction, cone, shadow: light.shadow(),
+                                         contact_shadows: light.contact_shadows });
+        }
+        points.sort_by(|a, b| a.position.distance_squared(eye).total_cmp(&b.position.distance_squared(eye)));
+        points.truncate(MAX_POINT_LIGHTS);
+        SceneLights { ambient: scene.ambient, sun, sun_shadow, sun_contact_shadows, points }
+    }
+}
@@ -0,0 +1,158 @@
+use bytemuck::{ Pod, Zeroable };
+use glam::{ Vec2, Vec3 };
+use std::f32::consts::TAU;
+
+/// Narrows a `Light2d` to a spotlight.
+#[derive(Clone, Copy, Debug)]
+pub struct Cone {
+    pub direction: Vec3,
+    /// Half angles in radians: full light inside `inner`, none past `outer`.
+    pub inner: f32,
+    pub outer: f32,
+}
+
+/// A light for billboards on lit sorting layers, shading them by their normal maps. Sprites
+/// face the camera, so put it a little towards the camera from them for the bumps to show.
+#[derive(Clone, Copy, Debug)]
+pub struct Light2d {
+    pub position: Vec3,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Falls off to nothing here.
+    pub radius: f32,
+    pub cone: Option<Cone>,
+    /// Bit per sorting layer it lights, all by default.
+    pub layers: u32,
+    /// Blocked by the scene's occluders.
+    pub shadows: bool,
+}
+
+impl Light2d {
+    pub fn point(color: [f32; 3], intensity: f32, radius: f32, position: Vec3) -> Self {
+        Light2d { position, color, intensity, radius, cone: None, layers: u32::MAX, shadows: false }
+    }
+
+    pub fn cone(color: [f32; 3], intensity: f32, radius: f32, position: Vec3, direction: Vec3, inner: f32, outer: f32) -> Self {
+        Light2d { cone: Some(Cone { direction: direction.normalize(), inner, outer }), ..Self::point(color, intensity, radius, position) }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct GpuLight2d {
+    /// w is the radius.
+    pub position: [f32; 4],
+    /// Premultiplied by intensity; w is the cosine of the inner angle.
+    pub color: [f32; 4],
+    /// w is the cosine of the outer angle, -2 for point lights.
+    pub direction: [f32; 4],
+    /// x is the layer mask, y where its shadow map starts plus one, 0 without.
+    pub mask: [u32; 4],
+}
+
+/// Angles in a light's shadow map.
+pub const SHADOW_RESOLUTION: usize = 360;
+
+#[derive(Clone, Debug)]
+pub enum OccluderShape {
+    /// A wall along the points, round back to the first if `closed`.
+    Polygon { points: Vec<Vec2>, closed: bool },
+    Circle { center: Vec2, radius: f32 },
+}
+
+/// Something 2d lights don't get past, in the xy plane 2d scenes lie in.
+#[derive(Clone, Debug)]
+pub struct Occluder2d {
+    pub shape: OccluderShape,
+    pub enabled: bool,
+}
+
+impl Occluder2d {
+    pub fn new(shape: OccluderShape) -> Self { Occluder2d { shape, enabled: true } }
+
+    /// An axis aligned box, for walls and crates.
+    pub fn rect(center: Vec2, half_extents: Vec2) -> Self {
+        let (min, max) = (center - half_extents, center + half_extents);
+        Self::new(OccluderShape::Polygon { points: vec![min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)], closed: true })
+    }
+
+    /// Distance along `dir`, unit length, from `origin` to where it first blocks.
+    pub fn raycast(&self, origin: Vec2, dir: Vec2) -> Option<f32> {
+        match &self.shape {
+            OccluderShape::Polygon { points, closed } => {
+                let wrap = if *closed && points.len() > 2 { Some([points[points.len() - 1], points[0]]) } else { None };
+                points.windows(2).map(|w| [w[0], w[1]]).chain(wrap).filter_map(|[a, b]| {
+                    let edge = b - a;
+                    let denom = dir.perp_dot(edge);
+                    if denom.abs() < 1e-8 { return None; }
+                    let t = (a - origin).perp_dot(edge) / denom;
+                    let s = (a - origin).perp_dot(dir) / denom;
+                    (t >= 0.0 && (0.0..=1.0).contains(&s)).then(|| t)
+                }).reduce(f32::min)
+            }
+            OccluderShape::Circle { center, radius } => {
+                let to = origin - *center;
+                let b = to.dot(dir);
+                let disc = b * b - (to.length_squared() - radius * radius);
+                if disc < 0.0 { return None; }
+                let (near, far) = (-b - disc.sqrt(), -b + disc.sqrt());
+                //from inside, the far side
+                if near >= 0.0 { Some(near) } else if far >= 0.0 { Some(far) } else { None }
+            }
+        }
+    }
+}
+
+/// The scene's 2d lights, what lit layers get without them, and what casts their shadows.
+#[derive(Clone, Debug)]
+pub struct Lighting2d {
+    pub ambient: [f32; 3],
+    pub lights: Vec<Light2d>,
+    pub occluders: Vec<Occluder2d>,
+}
+
+impl Default for Lighting2d {
+    fn default() -> Self { Lighting2d { ambient: [0.15; 3], lights: Vec::new(), occluders: Vec::new() } }
+}
+
+impl Lighting2d {
+    /// Distance to the nearest occluder along `dir` from `origin`, out to `max`.
+    pub fn raycast(&self, origin: Vec2, dir: Vec2, max: f32) -> f32 {
+        self.occluders.iter().filter(|o| o.enabled).filter_map(|o| o.raycast(origin, dir)).fold(max, f32::min)
+    }
+
+    /// Nothing in the way between the two, for line of sight and fog of war.
+    pub fn visible(&self, from: Vec2, to: Vec2) -> bool {
+        let d = from.distance(to);
+        d < 1e-6 || self.raycast(from, (to - from) / d, d) >= d
+    }
+
+    /// How far the light gets around `center` in every direction, counterclockwise from +x.
+    pub fn shadow_map(&self, center: Vec2, radius: f32) -> Vec<f32> {
+        (0..SHADOW_RESOLUTION).map(|i| {
+            let angle = (i as f32 + 0.5) / SHADOW_RESOLUTION as f32 * TAU;
+            self.raycast(center, Vec2::new(angle.cos(), angle.sin()), radius)
+        }).collect()
+    }
+
+    /// Lights and shadow maps as the billboard shader reads them, built on the cpu every
+    /// frame. There's always one of each, storage buffers can't be empty.
+    pub fn gpu(&self) -> (Vec<GpuLight2d>, Vec<f32>) {
+        let mut shadows = Vec::new();
+        let mut lights: Vec<GpuLight2d> = self.lights.iter().map(|l| {
+            let (direction, inner, outer) = match l.cone {
+                Some(c) => (c.direction, c.inner.cos(), c.outer.cos()),
+                None => (Vec3::Z, -1.0, -2.0),
+            };
+            let shadow = if l.shadows && !self.occluders.is_empty() {
+                shadows.extend(self.shadow_map(l.position.truncate(), l.radius));
+                (shadows.len() - SHADOW_RESOLUTION + 1) as u32
+            } else { 0 };
+            GpuLight2d { position: l.position.extend(l.radius).into(), color: [l.color[0] * l.intensity, l.color[1] * l.intensity, l.color[2] * l.intensity, inner],
+                         direction: direction.extend(outer).into(), mask: [l.layers, shadow, 0, 0] }
+        }).collect();
+        if lights.is_empty() { lights.push(GpuLight2d::default()); }
+        if shadows.is_empty() { shadows.push(0.0); }
+        (lights, shadows)
+    }
+}
@@ -0,0 +1,114 @@
+use vulkano::{ device::Queue,
+               buffer::{ BufferUsage, CpuAccessibleBuffer },
+               command_buffer::{ AutoCommandBufferBuilder, CommandBufferUsage, PrimaryCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               image::view::ImageViewAbstract,
+               sampler::{ Sampler, SamplerCreateInfo },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint },
+               sync::GpuFuture };
+use glam::{ Vec3, UVec3 };
+use std::sync::Arc;
+use crate::scene::Scene;
+use crate::renderer::Renderer;
+use crate::probe::ReflectionProbes;
+
+mod cs {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/sh_project.comp", include: ["src/shaders"] }
+}
+
+/// Order 3 (l = 0..2) spherical harmonics of incoming radiance, rgb per coefficient.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sh9 { pub coeffs: [Vec3; 9], }
+
+impl Sh9 {
+    pub fn scaled(&self, s: f32) -> Sh9 { Sh9 { coeffs: self.coeffs.map(|c| c * s) } }
+
+    pub fn add(&self, o: &Sh9) -> Sh9 {
+        let mut coeffs = self.coeffs;
+        for (c, oc) in coeffs.iter_mut().zip(o.coeffs) { *c += oc; }
+        Sh9 { coeffs }
+    }
+}
+
+/// Regular grid of diffuse light probes, probe (x, y, z) at `origin + spacing * (x, y, z)`.
+/// Objects get the trilinear blend of the 8 probes around their bounds center.
+pub struct LightProbeGrid {
+    pub origin: Vec3,
+    pub spacing: f32,
+    pub counts: UVec3,
+    /// x fastest, then y, then z. Empty until baked.
+    pub probes: Vec<Sh9>,
+}
+
+impl LightProbeGrid {
+    pub fn new(origin: Vec3, spacing: f32, counts: UVec3) -> Self { LightProbeGrid { origin, spacing, counts, probes: Vec::new() } }
+
+    pub fn position(&self, p: UVec3) -> Vec3 { self.origin + p.as_vec3() * self.spacing }
+
+    fn index(&self, p: UVec3) -> usize { (p.x + self.counts.x * (p.y + self.counts.y * p.z)) as usize }
+
+    /// Interpolated probe at `pos`, clamped to the grid. None before baking.
+    pub fn sample(&self, pos: Vec3) -> Option<Sh9> {
+        if self.probes.is_empty() { return None; }
+        let max = (self.counts.max(UVec3::ONE) - UVec3::ONE).as_vec3();
+        let g = ((pos - self.origin) / self.spacing).clamp(Vec3::ZERO, max);
+        let base = g.floor().min((max - Vec3::ONE).max(Vec3::ZERO));
+        let f = g - base;
+        let base = base.as_uvec3();
+        let mut out = Sh9::default();
+        for corner in 0..8u32 {
+            let offset = UVec3::new(corner & 1, (corner >> 1) & 1, corner >> 2);
+            let p = (base + offset).min(self.counts - UVec3::ONE);
+            let w = |axis: usize| if offset[axis] == 1 { f[axis] } else { 1.0 - f[axis] };
+            out = out.add(&self.probes[self.index(p)].scaled(w(0) * w(1) * w(2)));
+        }
+        Some(out)
+    }
+}
+
+/// Bakes `LightProbeGrid`s: a cube capture at every grid point projected to SH on the gpu.
+pub struct LightProbeBaker {
+    queue: Arc<Queue>,
+    pipeline: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+}
+
+impl LightProbeBaker {
+    pub fn new(queue: Arc<Queue>) -> Self {
+        let dev = queue.device().clone();
+        let cs = cs::load(dev.clone()).unwrap();
+        let pipeline = ComputePipeline::new(dev.clone(), cs.entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let sampler = Sampler::new(dev, SamplerCreateInfo::simple_repeat_linear_no_mipmap()).unwrap();
+        LightProbeBaker { queue, pipeline, sampler }
+    }
+
+    /// Blocks until every probe is done, one capture and one readback at a time. Meant for load
+    /// time or an editor button, not every frame.
+    pub fn bake(&self, renderer: &Renderer, captures: &mut ReflectionProbes, scene: &Scene, grid: &mut LightProbeGrid) {
+        let dev = self.queue.device().clone();
+        let result = CpuAccessibleBuffer::from_iter(dev.clone(), BufferUsage { storage_buffer: true, ..BufferUsage::none() }, true,
+                                                    (0..9).map(|_| [0f32; 4])).unwrap();
+        let layout = self.pipeline.layout().set_layouts().get(0).unwrap();
+        let mut probes = Vec::new();
+        for z in 0..grid.counts.z {
+            for y in 0..grid.counts.y {
+                for x in 0..grid.counts.x {
+                    let faces = captures.capture_faces(renderer, scene, grid.position(UVec3::new(x, y, z)));
+                    let set = PersistentDescriptorSet::new(layout.clone(), [
+                        WriteDescriptorSet::image_view_sampler_array(0, 0, faces.iter().map(|f| (f.clone() as Arc<dyn ImageViewAbstract>, self.sampler.clone()))),
+                        WriteDescriptorSet::buffer(1, result.clone()),
+                    ]).unwrap();
+                    let mut builder = AutoCommandBufferBuilder::primary(dev.clone(), self.queue.family(), CommandBufferUsage::OneTimeSubmit).unwrap();
+                    builder.bind_pipeline_compute(self.pipeline.clone())
+                        .bind_descriptor_sets(PipelineBindPoint::Compute, self.pipeline.layout().clone(), 0, set)
+                        .dispatch([1, 1, 1]).unwrap();
+                    builder.build().unwrap().execute(self.queue.clone()).unwrap()
+                        .then_signal_fence_and_flush().unwrap().wait(None).unwrap();
+                    let coeffs = result.read().unwrap();
+                    probes.push(Sh9 { coeffs: [0, 1, 2, 3, 4, 5, 6, 7, 8].map(|i| Vec3::new(coeffs[i][0], coeffs[i][1], coeffs[i][2])) });
+                }
+            }
+        }
+        grid.probes = probes;
+    }
+}
@@ -0,0 +1,307 @@
+use vulkano::{ device::Queue, format::Format };
+use glam::{ Vec2, Vec3 };
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicU32, Ordering };
+use std::thread::JoinHandle;
+use crate::scene::{ Scene, EntityId };
+use crate::bvh::{ Aabb, Bvh, Ray, ray_triangle };
+use crate::light::LightKind;
+use crate::texture::Texture;
+
+/// Lightmap component: diffuse lighting, direct and indirect, baked over the mesh's lightmap
+/// uvs. Lightmapped entities skip dynamic lights entirely, so keep it to static geometry.
+#[derive(Clone)]
+pub struct Lightmap {
+    /// Texels per side.
+    pub resolution: u32,
+    /// None until a bake finished.
+    pub texture: Option<Arc<Texture>>,
+}
+
+impl Lightmap {
+    pub fn new(resolution: u32) -> Self { Lightmap { resolution, texture: None } }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BakeSettings {
+    /// Hemisphere samples per texel.
+    pub samples: u32,
+    /// Indirect bounces, 0 for direct light only.
+    pub bounces: u32,
+}
+
+impl Default for BakeSettings {
+    fn default() -> Self { BakeSettings { samples: 64, bounces: 2 } }
+}
+
+/// Offsets ray origins off the surface they start on.
+const EPSILON: f32 = 1e-3;
+
+struct Object {
+    entity: EntityId,
+    positions: Vec<[Vec3; 3]>,
+    normals: Vec<[Vec3; 3]>,
+    lightmap_uvs: Vec<[Vec2; 3]>,
+    albedo: Vec3,
+    emissive: Vec3,
+    resolution: Option<u32>,
+}
+
+enum BakeLight {
+    Sun { direction: Vec3, radiance: Vec3 },
+    Point { position: Vec3, range: f32, radiance: Vec3, direction: Vec3, cone: Option<(f32, f32)> },
+}
+
+/// World space copy of everything the baker reads, so it can run on another thread while the
+/// scene keeps changing. Materials are reduced to their factors; textures are not sampled.
+struct BakeScene {
+    objects: Vec<Object>,
+    bvh: Bvh,
+    lights: Vec<BakeLight>,
+    sky: Vec3,
+    settings: BakeSettings,
+}
+
+struct Baked {
+    entity: EntityId,
+    resolution: u32,
+    texels: Vec<[f32; 4]>,
+}
+
+fn smoothstep(e0: f32, e1: f32, x: f32) -> f32 {
+    let t = ((x - e0) / (e1 - e0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Cosine weighted direction around `n` from two numbers in 0..1.
+fn cosine_sample(n: Vec3, u: f32, v: f32) -> Vec3 {
+    let (t, b) = n.any_orthonormal_pair();
+    let r = u.sqrt();
+    let phi = 2.0 * PI * v;
+    (t * r * phi.cos() + b * r * phi.sin() + n * (1.0 - u).max(0.0).sqrt()).normalize()
+}
+
+/// xorshift, plenty for decorrelating sample patterns between texels.
+struct Rng(u32);
+
+impl Rng {
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
+
+impl BakeScene {
+    fn snapshot(scene: &Scene, settings: BakeSettings) -> Self {
+        let mut objects = Vec::new();
+        for e in scene.entities.iter().filter(|e| e.portal.is_none()) {
+            let mesh = match &e.mesh { Some(m) => m, None => continue };
+            let normal_matrix = e.transform.inverse().transpose();
+            let t = |v: &crate::mesh::Vertex| e.transform.transform_point3(v.position.into());
+            let n = |v: &crate::mesh::Vertex| normal_matrix.transform_vector3(v.normal.into()).normalize_or_zero();
+            let m = &e.material;
+            objects.push(Object {
+                entity: e.id,
+                positions: mesh.vertices.chunks_exact(3).map(|v| [t(&v[0]), t(&v[1]), t(&v[2])]).collect(),
+                normals: mesh.vertices.chunks_exact(3).map(|v| [n(&v[0]), n(&v[1]), n(&v[2])]).collect(),
+                lightmap_uvs: mesh.vertices.chunks_exact(3).map(|v| [v[0].lightmap_uv.into(), v[1].lightmap_uv.into(), v[2].lightmap_uv.into()]).collect(),
+                albedo: Vec3::new(m.base_color[0], m.base_color[1], m.base_color[2]),
+                emissive: m.emissive.into(),
+                resolution: e.lightmap.as_ref().map(|l| l.resolution),
+            });
+        }
+        //keyed by object index rather than entity id
+        let bvh = Bvh::build(objects.iter().enumerate()
+            .map(|(i, o)| (i as EntityId, Aabb::from_points(o.positions.iter().flatten().cloned()))).collect());
+        let lights = scene.entities.iter().filter_map(|e| {
+            let light = e.light.as_ref()?;
+            let direction = e.transform.transform_vector3(-Vec3::Z).normalize();
+            Some(match light.kind {
+                LightKind::Directional => BakeLight::Sun { direction, radiance: light.radiance() },
+                LightKind::Point { range } => BakeLight::Point { position: e.position(), range, radiance: light.radiance(), direction, cone: None },
+                LightKind::Spot { range, inner, outer } =>
+                    BakeLight::Point { position: e.position(), range, radiance: light.radiance(), direction, cone: Some((inner.cos(), outer.cos())) },
+            })
+        }).collect();
+        BakeScene { objects, bvh, lights, sky: scene.ambient, settings }
+    }
+
+    /// Closest hit as (object, triangle, distance).
+    fn trace(&self, ray: &Ray) -> Option<(usize, usize, f32)> {
+        let mut candidates = self.bvh.query_ray(ray);
+        candidates.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let mut best: Option<(usize, usize, f32)> = None;
+        for (i, entry) in candidates {
+            if best.map_or(false, |b| b.2 < entry) { break; }
+            for (t, p) in self.objects[i as usize].positions.iter().enumerate() {
+                if let Some(d) = ray_triangle(ray, p[0], p[1], p[2]) {
+                    if best.map_or(true, |b| d < b.2) { best = Some((i as usize, t, d)); }
+                }
+            }
+        }
+        best
+    }
+
+    fn occluded(&self, ray: &Ray, max: f32) -> bool { self.trace(ray).map_or(false, |h| h.2 < max) }
+
+    /// Irradiance / pi from the lights, the same falloff the standard shader uses.
+    fn direct(&self, p: Vec3, n: Vec3) -> Vec3 {
+        let origin = p + n * EPSILON;
+        let mut sum = Vec3::ZERO;
+        for light in &self.lights {
+            match *light {
+                BakeLight::Sun { direction, radiance } => {
+                    let ndl = n.dot(-direction);
+                    if ndl <= 0.0 || self.occluded(&Ray { origin, dir: -direction }, f32::INFINITY) { continue; }
+                    sum += radiance * ndl;
+                }
+                BakeLight::Point { position, range, radiance, direction, cone } => {
+                    let d = position - p;
+                    let dist = d.length();
+                    let l = d / dist;
+                    let ndl = n.dot(l);
+                    let window = (1.0 - (dist / range).powi(4)).clamp(0.0, 1.0).powi(2);
+                    let cone = cone.map_or(1.0, |(inner, outer)| smoothstep(outer, inner, (-l).dot(direction)));
+                    if ndl <= 0.0 || window * cone <= 0.0 || self.occluded(&Ray { origin, dir: l }, dist) { continue; }
+                    sum += radiance * ndl * window * cone / (dist * dist + 1.0);
+                }
+            }
+        }
+        sum / PI
+    }
+
+    /// Radiance arriving along `ray`, following `depth` more bounces off diffuse surfaces.
+    fn radiance(&self, ray: &Ray, depth: u32, rng: &mut Rng) -> Vec3 {
+        let (o, t, d) = match self.trace(ray) { Some(h) => h, None => return self.sky };
+        let object = &self.objects[o];
+        let p = object.positions[t];
+        let mut n = (p[1] - p[0]).cross(p[2] - p[0]).normalize_or_zero();
+        if n.dot(ray.dir) > 0.0 { n = -n; }
+        let hit = ray.at(d);
+        let mut irradiance = self.direct(hit, n);
+        if depth > 1 {
+            irradiance += self.radiance(&Ray { origin: hit + n * EPSILON, dir: cosine_sample(n, rng.next(), rng.next()) }, depth - 1, rng);
+        }
+        object.emissive + object.albedo * irradiance
+    }
+
+    /// Irradiance / pi at a surface point, what the lightmap stores.
+    fn irradiance(&self, p: Vec3, n: Vec3, rng: &mut Rng) -> Vec3 {
+        let mut sum = self.direct(p, n);
+        if self.settings.bounces > 0 && self.settings.samples > 0 {
+            let count = self.settings.samples;
+            let mut indirect = Vec3::ZERO;
+            let (ju, jv) = (rng.next(), rng.next());
+            for i in 0..count {
+                //stratified in u, jittered per texel
+                let u = ((i as f32 + ju) / count as f32).fract();
+                let v = (i as f32 * 0.618034 + jv).fract();
+                indirect += self.radiance(&Ray { origin: p + n * EPSILON, dir: cosine_sample(n, u, v) }, self.settings.bounces, rng);
+            }
+            sum += indirect / count as f32;
+        }
+        sum
+    }
+
+    fn bake_object(&self, object: &Object, resolution: u32, progress: &AtomicU32) -> Baked {
+        let size = resolution as usize;
+        let mut texels = vec![[0.0f32; 4]; size * size];
+        for t in 0..object.positions.len() {
+            let uv = object.lightmap_uvs[t].map(|c| c * resolution as f32);
+            let lo = uv[0].min(uv[1]).min(uv[2]).floor().max(Vec2::ZERO);
+            let hi = uv[0].max(uv[1]).max(uv[2]).ceil().min(Vec2::splat(resolution as f32));
+            let area = (uv[1] - uv[0]).perp_dot(uv[2] - uv[0]);
+            if area.abs() < 1e-8 { continue; }
+            for y in lo.y as usize..hi.y as usize {
+                for x in lo.x as usize..hi.x as usize {
+                    //barycentrics of the texel center, a little slack so edge texels count
+                    let c = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                    let b1 = (c - uv[0]).perp_dot(uv[2] - uv[0]) / area;
+                    let b2 = (uv[1] - uv[0]).perp_dot(c - uv[0]) / area;
+                    let b0 = 1.0 - b1 - b2;
+                    if b0 < -0.05 || b1 < -0.05 || b2 < -0.05 { continue; }
+                    let p = object.positions[t];
+                    let n = object.normals[t];
+                    let position = p[0] * b0 + p[1] * b1 + p[2] * b2;
+                    let normal = (n[0] * b0 + n[1] * b1 + n[2] * b2).normalize_or_zero();
+                    let mut rng = Rng((y * size + x) as u32 * 9781 + t as u32 * 6271 + 1);
+                    texels[y * size + x] = self.irradiance(position, normal, &mut rng).extend(1.0).into();
+                }
+            }
+            progress.fetch_add(1, Ordering::Relaxed);
+        }
+        dilate(&mut texels, size, 2);
+        Baked { entity: object.entity, resolution, texels }
+    }
+}
+
+/// Grows covered texels (alpha 1) into their empty neighbours so filtering at chart edges does
+/// not pull in black.
+fn dilate(texels: &mut [[f32; 4]], size: usize, passes: u32) {
+    for _ in 0..passes {
+        let src = texels.to_vec();
+        for y in 0..size {
+            for x in 0..size {
+                if src[y * size + x][3] > 0.0 { continue; }
+                let mut sum = [0.0; 4];
+                let mut count = 0.0;
+                for (dx, dy) in [(-1i32, 0i32), (1, 0), (0, -1), (0, 1)] {
+                    let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                    if nx < 0 || ny < 0 || nx >= size as i32 || ny >= size as i32 { continue; }
+                    let n = src[ny as usize * size + nx as usize];
+                    if n[3] <= 0.0 { continue; }
+                    for i in 0..3 { sum[i] += n[i]; }
+                    count += 1.0;
+                }
+                if count > 0.0 { texels[y * size + x] = [sum[0] / count, sum[1] / count, sum[2] / count, 1.0]; }
+            }
+        }
+    }
+}
+
+/// Lightmap bake running on a background thread. Poll it once a frame; the results land in the
+/// scene's `Lightmap` components when it finishes.
+pub struct LightmapBake {
+    handle: Option<JoinHandle<Vec<Baked>>>,
+    progress: Arc<AtomicU32>,
+    total: u32,
+}
+
+impl LightmapBake {
+    /// Bakes every entity with a `Lightmap` component, lit by the scene as it is right now.
+    pub fn start(scene: &Scene, settings: BakeSettings) -> Self {
+        let bake = BakeScene::snapshot(scene, settings);
+        let total = bake.objects.iter().filter(|o| o.resolution.is_some()).map(|o| o.positions.len() as u32).sum();
+        let progress = Arc::new(AtomicU32::new(0));
+        let counter = progress.clone();
+        let handle = std::thread::spawn(move || {
+            bake.objects.iter().filter_map(|o| Some(bake.bake_object(o, o.resolution?, &counter))).collect()
+        });
+        LightmapBake { handle: Some(handle), progress, total }
+    }
+
+    /// 0..1, by triangles done.
+    pub fn progress(&self) -> f32 {
+        if self.total == 0 { return 1.0; }
+        self.progress.load(Ordering::Relaxed) as f32 / self.total as f32
+    }
+
+    pub fn is_done(&self) -> bool { self.handle.is_none() }
+
+    /// Uploads the lightmaps once the thread is finished. Entities removed since `start` are
+    /// skipped. Returns true on the call that applied them.
+    pub fn poll(&mut self, queue: &Arc<Queue>, scene: &mut Scene) -> bool {
+        if !self.handle.as_ref().map_or(false, |h| h.is_finished()) { return false; }
+        let baked = self.handle.take().unwrap().join().expect("lightmap bake panicked");
+        for b in baked {
+            let lightmap = match scene.get_mut(b.entity).and_then(|e| e.lightmap.as_mut()) { Some(l) => l, None => continue };
+            //half floats, linear filtering of 32 bit float formats is optional
+            let data = b.texels.iter().flatten().flat_map(|&c| half::f16::from_f32(c).to_le_bytes()).collect();
+            lightmap.texture = Some(Texture::from_rgba_format(queue.clone(), [b.resolution, b.resolution], data, Format::R16G16B16A16_SFLOAT));
+        }
+        true
+    }
+}
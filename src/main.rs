@@ -1,36 +1,200 @@
 use winit:: { event_loop::{ControlFlow, EventLoop},
-              window::{ WindowBuilder, Window },
+              window::{ Window, WindowBuilder },
               event::* };
-use vulkano::{ instance::{ Instance, InstanceCreateInfo },
+use vulkano::{ instance::{ Instance, InstanceCreateInfo, InstanceExtensions },
                device:: { physical::PhysicalDevice, physical::PhysicalDeviceType, DeviceExtensions, DeviceCreateInfo, QueueCreateInfo, Device },
-               buffer::{ BufferUsage, CpuAccessibleBuffer, TypedBufferAccess },
-               command_buffer::{ AutoCommandBufferBuilder, CommandBufferUsage, SubpassContents },
+               command_buffer::{ AutoCommandBufferBuilder, CommandBufferUsage },
                swapchain::{ Swapchain, SwapchainCreateInfo, SwapchainCreationError, acquire_next_image, AcquireError },
-               image::{ ImageUsage, SwapchainImage, view::ImageView, ImageAccess },
-               render_pass::{ Framebuffer, FramebufferCreateInfo, RenderPass, Subpass },
-               pipeline::{ GraphicsPipeline, graphics::{ input_assembly::InputAssemblyState, vertex_input::BuffersDefinition, viewport::{ Viewport, ViewportState} } },
-               sync::{ self, FlushError, GpuFuture },
-               impl_vertex};
-use bytemuck::{Pod, Zeroable};
+               image::{ ImageUsage, ImageAccess },
+               pipeline::graphics::viewport::Viewport,
+               sync::{ FlushError, GpuFuture } };
+use glam::Mat4;
 use std::sync::Arc;
-use vulkano_win::VkSurfaceBuild;
-use vulkano::sync::now;
+
+mod mesh;
+mod scene;
+mod picking;
+mod bvh;
+mod camera;
+mod overlay;
+mod gizmo;
+mod culling;
+mod debug_draw;
+mod texture;
+mod billboard;
+mod impostor;
+mod renderer;
+mod portal;
+mod views;
+mod light;
+mod material;
+mod shadow;
+mod ibl;
+mod probe;
+mod light_probe;
+mod lightmap;
+mod skybox;
+mod deferred;
+mod cluster;
+mod volumetric;
+mod fog;
+mod sky;
+mod time;
+mod day_night;
+mod selection;
+mod sdf;
+mod raytracing;
+mod raw_commands;
+mod rt_lighting;
+mod rtao;
+mod voxel_gi;
+mod ssao;
+mod ssr;
+mod postfx;
+mod gpu_timer;
+mod resolution;
+#[cfg(feature = "physics")]
+mod physics;
+mod audio;
+mod terrain;
+mod water;
+mod stages;
+mod orbit;
+mod fly;
+mod follow;
+mod animation;
+mod animation_graph;
+mod ik;
+mod skin;
+mod morph;
+mod model;
+mod tween;
+mod camera_path;
+mod benchmark;
+mod sprite;
+mod tiled;
+mod tilemap;
+mod ui;
+mod light2d;
+mod text;
+mod text_layout;
+mod particles;
+mod gpu_particles;
+mod cutout;
+mod vector;
+mod stats;
+mod gpu_debug;
+mod gpu_memory;
+mod device_lost;
+#[cfg(feature = "egui")]
+mod egui_pass;
+#[cfg(feature = "imgui")]
+mod imgui_pass;
+
+use mesh::Mesh;
+use scene::{ Scene, EntityId };
+use picking::Picker;
+use camera::{ Camera, Camera2d, Projection };
+use overlay::Overlay;
+use selection::SelectionOutline;
+use sdf::SdfScene;
+use raytracing::RayTracingSupport;
+use stages::ShaderStageSupport;
+use orbit::{ OrbitController, OrbitDrag };
+use fly::FlyController;
+use follow::FollowController;
+use tween::Tweener;
+use camera_path::{ CameraPath, PathPlayer, Spline };
+use benchmark::Benchmark;
+use skin::Skinning;
+use morph::Morphing;
+use voxel_gi::{ VoxelGi, GiQuality };
+use ssao::Ssao;
+use ssr::Ssr;
+use rt_lighting::RtLighting;
+use rtao::Rtao;
+use audio::Audio;
+use terrain::{ Terrain, Heightmap, TerrainSettings };
+use water::{ Water, WaterPass, PlanarReflection };
+use gpu_timer::GpuTimer;
+use resolution::DynamicResolution;
+#[cfg(feature = "physics")]
+use physics::Physics;
+use postfx::{ PostStack, PostContext, tonemap::{ Tonemap, TonemapPass }, exposure::AutoExposure, bloom::{ Bloom, BloomPass }, dof::DepthOfFieldPass,
+              motion_blur::{ MotionBlur, MotionBlurPass }, fxaa::FxaaPass, taa::{ self, Taa, TaaPass }, grading::GradingPass, lens::LensPass, cas::CasPass, fsr::FsrPass, transition::TransitionPass,
+              hdr::{ self, DisplayEncoding } };
+use gizmo::{ Gizmo, GizmoMode, GizmoSpace };
+use culling::Culling;
+use debug_draw::DebugDraw;
+use texture::Texture;
+use billboard::Billboard;
+use impostor::Impostor;
+use renderer::{ Renderer, RenderPath };
+use portal::{ Portal, PortalTargets };
+use views::{ Views, ViewPasses };
+use light::{ Light, ContactShadows };
+use probe::{ ReflectionProbe, ReflectionProbes, ProbeShape };
+use light_probe::{ LightProbeGrid, LightProbeBaker };
+use lightmap::{ Lightmap, LightmapBake, BakeSettings };
+use skybox::{ Skybox, SkyboxSource };
+use time::Time;
+use day_night::DayNight;
+use volumetric::{ VolumetricFog, FogVolume };
 
 fn main() {
+    //scopes go nowhere until a profiler's feature is on, see Cargo.toml
+    #[cfg(feature = "tracy")]
+    profiling::tracy_client::Client::start();
+    #[cfg(feature = "puffin")]
+    profiling::puffin::set_scopes_on(true);
+
+    //before the instance, so RenderDoc's layer is in when the app runs under it; F10 captures
+    let mut renderdoc = gpu_debug::RenderDocCapture::new();
+
     //vulkan instance setup
-    let req_ext = vulkano_win::required_extensions();
-    let  dev_ext = DeviceExtensions {
-        khr_swapchain: true, ..DeviceExtensions::none() };
-    let vkinst = Instance::new(InstanceCreateInfo { enabled_extensions: req_ext, ..Default::default() })
+    //hdr color spaces only show up in the surface's formats with this
+    let colorspace_ext = InstanceExtensions::supported_by_core().map_or(false, |e| e.ext_swapchain_colorspace);
+    //object names and pass labels for graphics debuggers
+    let debug_utils = InstanceExtensions::supported_by_core().map_or(false, |e| e.ext_debug_utils);
+    let req_ext = InstanceExtensions { ext_swapchain_colorspace: colorspace_ext, ext_debug_utils: debug_utils, ..vulkano_win::required_extensions() };
+    //--validation, see gpu_debug::Validation for the rest of its flags
+    let validation = gpu_debug::Validation::from_args(&std::env::args().collect::<Vec<_>>());
+    let vkinst = Instance::new(InstanceCreateInfo { enabled_extensions: req_ext, enabled_layers: validation.layers(), ..Default::default() })
         .expect("vkinst failed creation");
+    //never dropped, main doesn't return
+    let _messenger = validation.messenger(vkinst.clone());
     
-    //winit setup
+    //winit setup; the window outlives the devices, each gets its own surface on it
     let event_loop = EventLoop::new();
-    let builder = WindowBuilder::new();
-    let window = builder.build_vk_surface(&event_loop, vkinst.clone()).unwrap();
+    let window = Arc::new(WindowBuilder::new().build(&event_loop).unwrap());
+
+    let mut frame = Some(start(&vkinst, &window));
+    let mut restarts = device_lost::Restarts::new();
+    //winit loop
+    event_loop.run(move | event, _, control_flow |  {
+        let mut lost = None;
+        (frame.as_mut().unwrap())(event, control_flow, &mut lost);
+        if let Some(l) = lost {
+            restarts.lost(l);
+            //everything on the old device but its last future goes before the new one is made
+            frame.take();
+            frame = Some(start(&vkinst, &window));
+        }
+    });
+}
+
+/// The window's events for everything on one device; the last argument is set when the device
+/// is lost, and the closure should go for `start` to make a new one.
+type Frame = Box<dyn FnMut(Event<'_, ()>, &mut ControlFlow, &mut Option<device_lost::Lost>)>;
+
+/// Sets up a device, swapchain and the scene and everything else on them for `window`.
+fn start(vkinst: &Arc<Instance>, window: &Arc<Window>) -> Frame {
+    let window = vulkano_win::create_surface_from_winit(window.clone(), vkinst.clone()).unwrap();
+    let  dev_ext = DeviceExtensions {
+        khr_swapchain: true, ..DeviceExtensions::none() };
 
     //vulkan device setup
-    let (physical, queue_fam) = PhysicalDevice::enumerate(&vkinst)
+    let (physical, queue_fam) = PhysicalDevice::enumerate(vkinst)
         .filter(|&p| { p.supported_extensions().is_superset_of(&dev_ext) })
         .filter_map( |p|  {
             p.queue_families()
@@ -39,18 +203,27 @@ fn main() {
                 })
                 .map(|q|  (p, q))
         })
+        //with ray tracing asked for, one that can beats a faster one that can't
         .min_by_key(|(p, _)| {
-            match p.properties().device_type {
+            (RayTracingSupport::requested() && !RayTracingSupport::detect(*p).any(),
+             match p.properties().device_type {
                 PhysicalDeviceType::DiscreteGpu => 0,
                 PhysicalDeviceType::IntegratedGpu => 1,
                 PhysicalDeviceType::VirtualGpu => 2,
                 PhysicalDeviceType::Cpu => 3,
                 PhysicalDeviceType::Other => 4,
-            }
+            })
         }).unwrap();
     
+    let ray_tracing = RayTracingSupport::detect(physical);
+    if RayTracingSupport::requested() { println!("Ray tracing: {:?}", ray_tracing); }
+    let stages = ShaderStageSupport::detect(physical);
     let (dev, mut queues) = Device::new( physical, DeviceCreateInfo {
-        enabled_extensions: physical.required_extensions().union(&dev_ext),
+        //usage and budgets for gpu_memory when the driver has them, ordered breadcrumbs for device_lost
+        enabled_extensions: DeviceExtensions { ext_memory_budget: physical.supported_extensions().ext_memory_budget,
+                                               amd_buffer_marker: physical.supported_extensions().amd_buffer_marker, ..physical.required_extensions().union(&dev_ext) }
+            .union(&ray_tracing.extensions()),
+        enabled_features: stages.enable(ray_tracing.features()),
         queue_create_infos: vec![QueueCreateInfo::family(queue_fam)], ..Default::default() } )
         .expect("failed dev creation");
     let queue = queues.next().unwrap();
@@ -59,79 +232,370 @@ fn main() {
     let (mut swapchain, images) = {
         let surface_cap = physical.surface_capabilities(&window, Default::default())
             .unwrap();
-        let image_format = Some(physical.surface_formats(&window, Default::default())
-                                .unwrap()[0].0, );        
+        let hdr = match std::env::var("ARSE_HDR").as_deref() {
+            Ok("hdr10") => Some(DisplayEncoding::Hdr10),
+            Ok("scrgb") => Some(DisplayEncoding::ScRgb),
+            _ => None,
+        };
+        let (image_format, image_color_space) = hdr::swapchain_format(&physical.surface_formats(&window, Default::default()).unwrap(), hdr);
         Swapchain::new(dev.clone(), window.clone(), SwapchainCreateInfo {
             min_image_count: surface_cap.min_image_count,
-            image_format,
+            image_format: Some(image_format),
+            image_color_space,
             image_extent: window.window().inner_size().into(),
             image_usage: ImageUsage::color_attachment(),
             composite_alpha: surface_cap.supported_composite_alpha.iter().next().unwrap(), ..Default::default() }, ).unwrap()
     };
 
     /* To be removed! This is test data for the triangle. */
-    #[repr(C)]
-    #[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
-    struct Vertex { position: [f32; 2], }
-    impl_vertex!(Vertex, position);
-
-    let vertices = [ Vertex { position: [-0.5, -0.25] }, Vertex { position: [0.0, 0.5] }, Vertex { position: [0.25, -0.1] },];
-    let vertex_buffer = CpuAccessibleBuffer::from_iter(dev.clone(), BufferUsage::all(), false, vertices).unwrap();
-
-    mod vs { //vertex shader
-        vulkano_shaders::shader! { ty: "vertex",
-        src: "#version 450
-
-				layout(location = 0) in vec2 position;
-
-				void main() {
-					gl_Position = vec4(position, 0.0, 1.0);
-				}"
+    let triangle = Mesh::from_positions(dev.clone(), &[ [-0.5, -0.25, 0.0], [0.0, 0.5, 0.0], [0.25, -0.1, 0.0] ]);
+    let mut scene = Scene::new();
+    scene.spawn(triangle.clone(), Mat4::IDENTITY);
+    let far_triangle = scene.spawn(triangle.clone(), Mat4::from_translation(glam::vec3(0.3, 0.2, -0.5)));
+    scene.get_mut(far_triangle).unwrap().impostor = Some(Impostor::bake(dev.clone(), queue.clone(), &triangle, 8, 128, 5.0));
+    let mut camera = Camera::new(glam::vec3(0.0, 0.0, 2.0), glam::Vec3::ZERO);
+    let white = Texture::white(queue.clone());
+    scene.billboards.push(Billboard { color: [1.0, 0.8, 0.2, 0.8], ..Billboard::new(white, glam::vec3(-0.3, 0.0, -0.2), 0.4) });
+    //an aseprite json export to play next to it, its first tag or all of it
+    if let Ok(path) = std::env::var("ARSE_SPRITE") {
+        let sheet = sprite::SpriteSheet::load(path);
+        let texture = Texture::load(queue.clone(), sheet.image.as_ref().expect("sprite sheet without an image"));
+        let mut animation = sprite::SpriteAnimator::new(sheet.clone());
+        if let Some(clip) = sheet.clips.first() { animation.play(&clip.name); }
+        let mut sprite = Billboard { animation: Some(animation), ..Billboard::new(texture, glam::vec3(0.6, 0.0, -0.2), 0.5) };
+        //with its normal map, it goes on a lit layer with a warm light off to its left
+        if let Ok(normals) = std::env::var("ARSE_SPRITE_NORMALS") {
+            sprite.normal_map = Some(Texture::load_linear(queue.clone(), normals));
+            sprite.layer = scene.sorting_layers.len();
+            scene.sorting_layers.push(billboard::SortingLayer { lit: true, ..billboard::SortingLayer::new("lit sprites", billboard::SpriteSort::Depth) });
+            //with a post between them throwing a shadow across the sprite's lower half
+            scene.lighting_2d.lights.push(light2d::Light2d { shadows: true, ..light2d::Light2d::point([1.0, 0.8, 0.5], 2.0, 1.5, glam::vec3(0.2, 0.2, 0.1)) });
+            scene.lighting_2d.occluders.push(light2d::Occluder2d::rect(glam::vec2(0.4, 0.05), glam::vec2(0.03, 0.08)));
         }
+        scene.billboards.push(sprite);
     }
-    mod fs {
-        vulkano_shaders::shader!{ ty: "fragment",
-        src: "#version 450
-
-				layout(location = 0) out vec4 f_color;
-
-				void main() {
-					f_color = vec4(1.0, 0.0, 0.0, 1.0);
-				}"
+    //every effect in a particle effect file, side by side in front of the scene; a missing file
+    //is written with the default effect to start editing from
+    if let Ok(path) = std::env::var("ARSE_PARTICLES") {
+        if !std::path::Path::new(&path).exists() { particles::save(&[particles::ParticleEffect::new("default")], &path).unwrap(); }
+        for (i, effect) in particles::load(path).into_iter().enumerate() {
+            let texture = effect.texture.as_ref().map_or_else(|| Texture::white(queue.clone()), |t| Texture::load(queue.clone(), t));
+            let id = scene.spawn_empty(Mat4::from_translation(glam::vec3(-0.6 + i as f32 * 0.6, -0.4, 0.2)), None);
+            scene.get_mut(id).unwrap().particles = Some(particles::ParticleEmitter::new(std::sync::Arc::new(effect), texture));
+        }
+    }
+    //the same, simulated on the gpu, behind the scene where there's room for a lot of them
+    if let Ok(path) = std::env::var("ARSE_GPU_PARTICLES") {
+        for (i, effect) in particles::load(path).into_iter().enumerate() {
+            let texture = effect.texture.as_ref().map_or_else(|| Texture::white(queue.clone()), |t| Texture::load(queue.clone(), t));
+            let id = scene.spawn_empty(Mat4::from_translation(glam::vec3(-2.0 + i as f32 * 2.0, -0.5, -4.0)), None);
+            scene.get_mut(id).unwrap().gpu_particles = Some(gpu_particles::GpuEmitter::new(dev.clone(), std::sync::Arc::new(effect), texture));
         }
     }
-    let vs = vs::load(dev.clone()).unwrap();
-    let fs = fs::load(dev.clone()).unwrap();
+    //a Spine export, its json with the atlas and page next to it, playing its first animation
+    if let Ok(path) = std::env::var("ARSE_CUTOUT") {
+        let skeleton = cutout::CutoutSkeleton::load(path);
+        let mut character = cutout::Cutout::new(skeleton.clone(), Texture::load(queue.clone(), &skeleton.atlas.image));
+        character.transform = Mat4::from_translation(glam::vec3(-1.0, -0.5, -0.5)) * Mat4::from_scale(glam::Vec3::splat(0.003));
+        if let Some(first) = skeleton.animations.first() { character.play(&first.name, 0.0); }
+        scene.cutouts.push(character);
+    }
+    //a Tiled map standing behind the scene, its top left corner up and to the left
+    if let Ok(path) = std::env::var("ARSE_TILEMAP") {
+        let mut map = tilemap::Tilemap::new(dev.clone(), queue.clone(), tiled::load_tmx(path));
+        map.transform = Mat4::from_translation(glam::vec3(-4.0, 3.0, -3.0)) * map.transform;
+        scene.tilemaps.push(map);
+    }
 
+    let mirror = scene.spawn(Mesh::quad(dev.clone()), Mat4::from_translation(glam::vec3(0.0, 0.0, -1.5)) * Mat4::from_scale(glam::Vec3::splat(2.0)));
+    scene.get_mut(mirror).unwrap().portal = Some(Portal::Mirror);
+    let floor = scene.spawn(Mesh::quad(dev.clone()), Mat4::from_translation(glam::vec3(0.0, -0.5, 0.0)) * Mat4::from_rotation_x(-std::f32::consts::FRAC_PI_2) * Mat4::from_scale(glam::Vec3::splat(3.0)));
+    scene.get_mut(floor).unwrap().lightmap = Some(Lightmap::new(64));
+    let sun = scene.spawn_light(Light::directional([1.0, 0.95, 0.9], 3.0).with_contact_shadows(true), Mat4::look_at_rh(glam::Vec3::ZERO, glam::vec3(-0.3, -1.0, -0.5), glam::Vec3::Y).inverse());
+    let moon = scene.spawn_light(Light::directional([0.6, 0.7, 1.0], 0.0), Mat4::IDENTITY);
+    scene.spawn_light(Light::point([0.2, 0.5, 1.0], 2.0, 3.0).with_shadows(true), Mat4::from_translation(glam::vec3(0.5, 0.5, 0.5)));
+    let hills = Heightmap::from_fn([129, 129], |x, y| ((x as f32 * 0.1).sin() * (y as f32 * 0.07).cos()) * 0.5 + 0.5);
+    let mut terrain = Terrain::new(dev.clone(), &mut scene, hills, TerrainSettings { size: 64.0, height: 4.0, chunk_cells: 16, levels: 4, ..Default::default() },
+                                   material::Material::default(), glam::vec3(0.0, -4.0, 0.0));
+    //round bumps pushed out of a flat quad, flat where tessellation isn't supported
+    let bumps = (0..64 * 64).flat_map(|i| {
+        let (x, y) = ((i % 64) as f32 / 8.0 * std::f32::consts::TAU, (i / 64) as f32 / 8.0 * std::f32::consts::TAU);
+        let h = x.sin() * y.sin() * 0.5 + 0.5;
+        [(h * 255.0) as u8, 0, 0, 255]
+    }).collect();
+    let bumpy = scene.spawn(Mesh::quad(dev.clone()), Mat4::from_translation(glam::vec3(2.5, -0.49, 0.0)) * Mat4::from_rotation_x(-std::f32::consts::FRAC_PI_2));
+    scene.get_mut(bumpy).unwrap().material.displacement = Some(material::Displacement {
+        texture: Texture::from_rgba_format(queue.clone(), [64, 64], bumps, vulkano::format::Format::R8G8B8A8_UNORM), scale: 0.1, midlevel: 0.0, edge_pixels: 8.0 });
     /* End of remove block. */
 
-    //render pass setup
-    let render_pass = vulkano::single_pass_renderpass!( dev.clone(),
-                                                        attachments: { color: { load: Clear, store: Store, format: swapchain.image_format(), samples: 1,}},
-                                                        pass: { color: [color], depth_stencil: {} }).unwrap();
-    let pipeline = GraphicsPipeline::start().vertex_input_state(
-        BuffersDefinition::new().vertex::<Vertex>())
-        .vertex_shader(vs.entry_point("main").unwrap(), ())
-        .input_assembly_state(InputAssemblyState::new())
-        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
-        .fragment_shader(fs.entry_point("main").unwrap(), ())
-        .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
-        .build(dev.clone()).unwrap();
-
-    let mut viewport = Viewport { origin: [0.0, 0.0], dimensions: [0.0, 0.0], depth_range: 0.0..1.0};
-    let mut framebuffers = window_size_dependent_setup(&images, render_pass.clone(), &mut viewport);
+    let path = match std::env::var("ARSE_RENDER_PATH").as_deref() {
+        Ok("deferred") => RenderPath::Deferred,
+        _ => RenderPath::Forward,
+    };
+    let mut renderer = Renderer::new(dev.clone(), queue.clone(), postfx::SCENE_FORMAT, path);
+    let mut portal_targets = PortalTargets::new();
+    let mut water_reflection = PlanarReflection::new();
+    let mut views = Views::new();
+    let mut minimap = Camera::new(glam::vec3(0.0, 6.0, 0.0), glam::Vec3::ZERO);
+    minimap.up = -glam::Vec3::Z;
+    minimap.projection = Projection::Orthographic { height: 8.0 };
+    views.add(&renderer, minimap, [0.75, 0.02, 0.23, 0.23], [256, 256]);
+    let mut probes = ReflectionProbes::new(queue.clone());
+    let probe = scene.spawn_empty(Mat4::from_translation(glam::vec3(0.0, 0.0, 0.5)), None);
+    scene.get_mut(probe).unwrap().probe = Some(ReflectionProbe::new(ProbeShape::Box { half_extent: glam::vec3(2.0, 2.0, 2.5) }));
+    //an hdri stays put, the procedural sky runs through the day
+    let mut day_night = None;
+    match std::env::var("ARSE_ENVIRONMENT") {
+        Ok(path) => {
+            let environment = renderer.ibl.bake(&Texture::load_hdr(queue.clone(), path));
+            scene.skybox = Some(Skybox::new(SkyboxSource::Cube(environment.cube.clone())));
+            scene.environment = Some(environment);
+        }
+        Err(_) => {
+            let mut cycle = DayNight::new(sun, 10.0);
+            cycle.moon = Some(moon);
+            cycle.update(&Time::new(), &renderer, &mut scene);
+            day_night = Some(cycle);
+        }
+    }
+    //a gltf to look at, playing its first animation
+    let skinning = Skinning::new(dev.clone());
+    let morphing = Morphing::new(dev.clone());
+    if let Ok(path) = std::env::var("ARSE_MODEL") {
+        let model = model::load_gltf(dev.clone(), path);
+        for id in model.spawn(dev.clone(), &mut scene, Mat4::from_translation(glam::vec3(-2.0, -0.5, 0.0))) {
+            let (entity, clip) = (scene.get_mut(id).unwrap(), match model.clips.first() { Some(c) => c, None => break });
+            if let Some(skin) = &mut entity.skin { skin.player.play(clip.clone()); } else if let Some(morph) = &mut entity.morph { morph.player.play(clip.clone()); }
+        }
+    }
+    scene.volumetric_fog = Some(VolumetricFog::default());
+    scene.sdf = Some(SdfScene::default());
+    scene.voxel_gi = Some(VoxelGi::default());
+    scene.ssao = Some(Ssao::default());
+    scene.rtao = Some(Rtao::default());
+    scene.ssr = Some(Ssr::default());
+    scene.rt_lighting = Some(RtLighting::default());
+    scene.water = Some(Water { level: -2.5, ..Default::default() });
+    scene.contact_shadows = Some(ContactShadows::default());
+    scene.motion_blur = Some(MotionBlur::default());
+    scene.bloom = Some(Bloom::default());
+    scene.taa = Some(Taa::default());
+    scene.tonemap = Some(Tonemap { auto_exposure: Some(AutoExposure::default()), ..Default::default() });
+    renderer.voxels.set_quality(Some(GiQuality::Medium));
+    let mut fog_volume = FogVolume::new(dev.clone(), renderer.transparent_subpass());
+    let mut time = Time::new();
+    let mut audio = Audio::new();
+    #[cfg(feature = "physics")]
+    let mut physics = Physics::new();
+    //a card swinging from a fixed point beside the scene and another dropped on the floor; G throws
+    //the selected body away from the camera, H knocks it up
+    #[cfg(feature = "physics")]
+    if std::env::var("ARSE_PHYSICS").is_ok() {
+        use physics::{ BodyKind, ColliderShape, PhysicsBody };
+        let card = || PhysicsBody::new(BodyKind::Dynamic, ColliderShape::Cuboid { half_extents: glam::vec3(0.2, 0.2, 0.01) });
+        physics.insert(scene.get(floor).unwrap(), PhysicsBody::new(BodyKind::Fixed, ColliderShape::Cuboid { half_extents: glam::vec3(1.5, 1.5, 0.01) }));
+        let anchor = scene.spawn_empty(Mat4::from_translation(glam::vec3(-1.0, 1.0, 0.5)), None);
+        physics.insert(scene.get(anchor).unwrap(), PhysicsBody::new(BodyKind::Fixed, ColliderShape::Ball { radius: 0.05 }));
+        let swinging = scene.spawn(Mesh::quad(dev.clone()), Mat4::from_translation(glam::vec3(-0.4, 1.0, 0.5)) * Mat4::from_scale(glam::Vec3::splat(0.4)));
+        physics.insert(scene.get(swinging).unwrap(), card());
+        //the anchor is 0.6 to the card's left
+        physics.add_joint(anchor, swinging, rapier3d::prelude::SphericalJointBuilder::new().local_anchor2(rapier3d::na::Point3::new(-0.6, 0.0, 0.0)));
+        let dropped = scene.spawn(Mesh::quad(dev.clone()), Mat4::from_translation(glam::vec3(0.6, 1.5, 0.3)) * Mat4::from_rotation_x(-1.0) * Mat4::from_scale(glam::Vec3::splat(0.4)));
+        physics.insert(scene.get(dropped).unwrap(), card());
+    }
+    let mut light_probes = LightProbeGrid::new(glam::vec3(-1.0, -1.0, -1.0), 1.0, glam::UVec3::splat(3));
+    LightProbeBaker::new(queue.clone()).bake(&renderer, &mut probes, &scene, &mut light_probes);
+    scene.light_probes = Some(light_probes);
+    let mut lightmap_bake = LightmapBake::start(&scene, BakeSettings::default());
+
+    //the scene renders at its own resolution, the post stack brings it to the display's
+    let mut display = images[0].dimensions().width_height();
+    //a second player's camera on the right half, looking back at the scene
+    if std::env::var("ARSE_SPLIT_SCREEN").is_ok() {
+        let id = views.add_split(&renderer, Camera::new(glam::vec3(-4.0, 2.0, -4.0), glam::Vec3::ZERO), [0.5, 0.0, 0.5, 1.0], display);
+        views.get_mut(id).passes = ViewPasses { shadows: true, ..Default::default() };
+    }
+    let mut viewport = Viewport { origin: [0.0, 0.0], dimensions: [display[0] as f32, display[1] as f32], depth_range: 0.0..1.0 };
+    let mut gpu_timer = GpuTimer::new(&queue);
+    let mut dynamic_resolution = DynamicResolution::new(1000.0 / 60.0);
+    let mut picker = Picker::new(dev.clone(), postfx::render_extent(&scene, display, dynamic_resolution.scale()));
+    let (mut target, mut scene_color) = renderer.scene_target(&picker);
+    let mut post = PostStack::new(dev.clone(), swapchain.image_format(), swapchain.image_color_space());
+    println!("Presenting as {:?}", post.encoding());
+    post.resize(&images);
+    post.push(Box::new(WaterPass::new(dev.clone(), renderer.ibl.uniform([0.0; 3]))));
+    post.push(Box::new(TaaPass::new(dev.clone())));
+    post.push(Box::new(DepthOfFieldPass::new(dev.clone())));
+    post.push(Box::new(MotionBlurPass::new(dev.clone())));
+    post.push(Box::new(BloomPass::new(dev.clone())));
+    post.push(Box::new(TonemapPass::new(dev.clone())));
+    post.push(Box::new(GradingPass::new(queue.clone())));
+    post.push(Box::new(FxaaPass::new(dev.clone())));
+    post.push(Box::new(FsrPass::new(dev.clone())));
+    post.push(Box::new(LensPass::new(dev.clone())));
+    post.push(Box::new(CasPass::new(dev.clone())));
+    post.push(Box::new(TransitionPass::new(dev.clone())));
+    let mut overlay = Overlay::new(dev.clone(), swapchain.image_format());
+    overlay.resize(&images);
+    #[cfg(feature = "egui")]
+    let mut gui = egui_pass::EguiPass::new(dev.clone(), queue.clone(), swapchain.image_format(), window.window());
+    #[cfg(feature = "egui")]
+    gui.resize(&images);
+    //a tools window with the frame time and a few settings
+    #[cfg(feature = "egui")]
+    let tools = std::env::var("ARSE_EGUI").is_ok();
+    #[cfg(feature = "imgui")]
+    let mut dear_imgui = imgui_pass::ImguiPass::new(dev.clone(), queue.clone(), swapchain.image_format(), window.window());
+    #[cfg(feature = "imgui")]
+    dear_imgui.resize(&images);
+    //the same with imgui
+    #[cfg(feature = "imgui")]
+    let imgui_tools = std::env::var("ARSE_IMGUI").is_ok();
+    let mut outline = SelectionOutline::new(dev.clone(), swapchain.image_format());
+    outline.resize(&images);
+    let mut cursor = [0u32; 2];
+    let mut selected: Option<EntityId> = None;
+    let mut hovered: Option<EntityId> = None;
+    let mut gizmo = Gizmo::new();
+    let mut orbit = OrbitController::new();
+    let mut fly = FlyController::new();
+    //T follows the selected entity, and stops
+    let mut follow: Option<FollowController> = None;
+    let mut tweens = Tweener::new();
+    //C plays the first camera path, K adds the camera's spot to it; saved back when loaded from a file
+    let paths_file = std::env::var("ARSE_CAMERA_PATHS").ok();
+    if let Some(file) = paths_file.as_ref().filter(|f| std::path::Path::new(f).exists()) { scene.camera_paths = camera_path::load(file); }
+    let mut path_player = PathPlayer::new();
+    //--benchmark [report stem] flies a fixed path at a fixed step, then writes timings and quits
+    let args: Vec<String> = std::env::args().collect();
+    let mut benchmark = args.iter().position(|a| a == "--benchmark").and_then(|i| {
+        let output = args.get(i + 1).filter(|a| !a.starts_with("--")).map_or("benchmark", |a| a.as_str());
+        Benchmark::new(&scene, output.into()).map_err(|e| println!("No benchmark: {}", e)).ok()
+    });
+    //timings have to compare between runs
+    if benchmark.is_some() { dynamic_resolution.enabled = false; }
+    let mut culling = Culling::new();
+    let mut dbg = DebugDraw::new();
+    let mut ui = ui::Ui::new();
+    let mut stats = stats::StatsOverlay::new();
+    let mut gpu_memory = gpu_memory::GpuMemory::new();
+    //a panel skin with 8 pixel borders, stretched over the top left corner
+    let panel = std::env::var("ARSE_PANEL").ok().map(|path| ui::NineSlice::new(Texture::load(queue.clone(), path), [8; 4]));
+    //for the stats overlay in the top right, F3 toggles it
+    let mut font = std::env::var("ARSE_FONT").ok().map(|path| text::Font::load(queue.clone(), path));
+    //an msdf-atlas-gen json, its png next to it, for an outlined title along the bottom
+    let title_font = std::env::var("ARSE_SDF_FONT").ok().map(|path| text::SdfFont::load(queue.clone(), &path, std::path::Path::new(&path).with_extension("png")));
+    //a graph of the last 120 frame times in the bottom left, 33 ms at the top
+    let mut frame_graph = std::env::var("ARSE_VECTOR").is_ok().then(Vec::<f32>::new);
+    //ARSE_2D looks at the sprites and the tilemap through a 2D camera instead, ARSE_2D=pixel
+    //pixel perfect; the wheel zooms at the cursor and a middle drag moves it
+    let mut camera_2d = std::env::var("ARSE_2D").ok().map(|mode| Camera2d { pixel_perfect: mode == "pixel", ..Camera2d::new(glam::Vec2::ZERO, 32.0) });
+    let mut pan_2d: Option<glam::Vec2> = None;
 
     let mut recreate_swapchain = false;
     let mut previous_frame_end = Some(vulkano::sync::now(dev.clone()).boxed());
     
-    //winit loop
-    event_loop.run(move | event, _, control_flow |  {
+    Box::new(move |event: Event<'_, ()>, control_flow: &mut ControlFlow, lost: &mut Option<device_lost::Lost>| {
         *control_flow = ControlFlow::Poll;
         //*control_flow = ControlFlow::Wait;
         match event {
-            Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => { println!("Close button pressed."); *control_flow = ControlFlow::Exit },
+            Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => {
+                println!("Close button pressed.");
+                if let Some(b) = &benchmark { if let Err(e) = b.write_report() { println!("Failed writing the benchmark report: {}", e); } }
+                *control_flow = ControlFlow::Exit
+            }
+            //whatever egui is using doesn't reach the camera or the gizmo
+            #[cfg(feature = "egui")]
+            Event::WindowEvent { ref event, .. } if gui.on_event(event) => (),
+            #[cfg(feature = "imgui")]
+            Event::WindowEvent { ref event, .. } if dear_imgui.on_event(event) => (),
             Event::WindowEvent { event: WindowEvent::Resized(_), .. } => { recreate_swapchain = true; }
+            Event::WindowEvent { event: WindowEvent::CursorMoved { position, .. }, .. } => {
+                cursor = [position.x as u32, position.y as u32];
+                let at = glam::vec2(position.x as f32, position.y as f32);
+                if let (Some(c), Some(last)) = (&mut camera_2d, &mut pan_2d) { c.pan(at - *last); *last = at; }
+                hovered = picker.pick_window(cursor, display);
+                if camera_2d.is_none() { orbit.update(&mut camera, [position.x as f32, position.y as f32], viewport.dimensions); }
+                let view = match &camera_2d { Some(c) => c.as_view(viewport.dimensions), None => camera.as_view() };
+                let ray = view.screen_ray([position.x as f32, position.y as f32], viewport.dimensions);
+                if let Some(entity) = selected.and_then(|id| scene.get_mut(id)) {
+                    match gizmo.update(&ray, entity.id) {
+                        Some(edit) => entity.transform = edit.after,
+                        None => gizmo.hover(&ray, &entity.transform),
+                    }
+                }
+            }
+            Event::WindowEvent { event: WindowEvent::MouseInput { state: ElementState::Pressed, button: MouseButton::Left, .. }, .. } => {
+                let view = match &camera_2d { Some(c) => c.as_view(viewport.dimensions), None => camera.as_view() };
+                let ray = view.screen_ray([cursor[0] as f32, cursor[1] as f32], viewport.dimensions);
+                let on_gizmo = selected.and_then(|id| scene.get(id)).map_or(false, |e| gizmo.begin(&ray, &e.transform));
+                if !on_gizmo {
+                    selected = picker.pick_window(cursor, display);
+                    //bodies without a mesh, like the pendulum's anchor, aren't in the id buffer but
+                    //their colliders are; a small ball swept along the ray catches the thin ones it misses
+                    #[cfg(feature = "physics")]
+                    if selected.is_none() {
+                        selected = physics.raycast(&ray, 100.0)
+                            .or_else(|| physics.shape_cast(&physics::ColliderShape::Ball { radius: 0.05 }, ray.origin, ray.dir, 100.0)).map(|h| h.entity);
+                    }
+                    //dragging from empty space orbits, from an entity only selects it; the 2D camera pans instead
+                    if selected.is_none() && camera_2d.is_none() { orbit.begin(OrbitDrag::Rotate, [cursor[0] as f32, cursor[1] as f32]); }
+                }
+            }
+            Event::WindowEvent { event: WindowEvent::MouseInput { state: ElementState::Released, button: MouseButton::Left, .. }, .. } => { gizmo.end(); orbit.end(); }
+            Event::WindowEvent { event: WindowEvent::MouseInput { state, button: MouseButton::Middle, .. }, .. } => match (state, camera_2d.is_some()) {
+                (ElementState::Pressed, true) => pan_2d = Some(glam::vec2(cursor[0] as f32, cursor[1] as f32)),
+                (ElementState::Pressed, false) => orbit.begin(OrbitDrag::Pan, [cursor[0] as f32, cursor[1] as f32]),
+                (ElementState::Released, _) => { orbit.end(); pan_2d = None; }
+            },
+            Event::WindowEvent { event: WindowEvent::MouseWheel { delta, .. }, .. } => {
+                let steps = match delta { MouseScrollDelta::LineDelta(_, y) => y, MouseScrollDelta::PixelDelta(p) => p.y as f32 / 50.0 };
+                if let Some(c) = &mut camera_2d { c.zoom_at(glam::vec2(cursor[0] as f32, cursor[1] as f32), 1.1f32.powf(steps), viewport.dimensions); }
+                else if fly.active() { fly.adjust_speed(steps); } else if let Some(f) = &mut follow { f.zoom(steps); } else { orbit.zoom(&mut camera, steps); }
+            }
+            //hold the right button to fly, in 3D
+            Event::WindowEvent { event: WindowEvent::MouseInput { state, button: MouseButton::Right, .. }, .. } if camera_2d.is_none() => {
+                let flying = state == ElementState::Pressed;
+                if flying { fly.begin(); } else { fly.end(); }
+                window.window().set_cursor_grab(flying).ok();
+                window.window().set_cursor_visible(!flying);
+            }
+            Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta }, .. } => fly.look([delta.0, delta.1]),
+            Event::WindowEvent { event: WindowEvent::KeyboardInput { input: KeyboardInput { state, virtual_keycode: Some(key), .. }, .. }, .. }
+                if fly.key(key, state == ElementState::Pressed) => (),
+            Event::WindowEvent { event: WindowEvent::ModifiersChanged(m), .. } => { gizmo.snapping = m.ctrl(); fly.set_sprint(m.shift()); }
+            Event::WindowEvent { event: WindowEvent::KeyboardInput { input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(key), .. }, .. }, .. } => {
+                match key {
+                    VirtualKeyCode::W => gizmo.mode = GizmoMode::Translate,
+                    VirtualKeyCode::E => gizmo.mode = GizmoMode::Rotate,
+                    VirtualKeyCode::R => gizmo.mode = GizmoMode::Scale,
+                    VirtualKeyCode::Q => gizmo.space = if gizmo.space == GizmoSpace::World { GizmoSpace::Local } else { GizmoSpace::World },
+                    VirtualKeyCode::B => culling.show_bounds = !culling.show_bounds,
+                    VirtualKeyCode::F3 => stats.visible = !stats.visible,
+                    VirtualKeyCode::F10 => renderdoc.capture(),
+                    VirtualKeyCode::F => culling.toggle_freeze(camera.view_proj()),
+                    VirtualKeyCode::T => follow = if follow.is_some() { None } else { selected.map(FollowController::new) },
+                    VirtualKeyCode::C => if path_player.playing { path_player.stop() } else { path_player.play(0) },
+                    VirtualKeyCode::K => {
+                        if scene.camera_paths.is_empty() { scene.camera_paths.push(CameraPath::new("flythrough", Spline::CatmullRom)); }
+                        scene.camera_paths[0].add_point(camera.position, camera.target);
+                        if let Some(file) = &paths_file { camera_path::save(&scene.camera_paths, file).unwrap(); }
+                    }
+                    #[cfg(feature = "physics")]
+                    VirtualKeyCode::P => physics.show_debug = !physics.show_debug,
+                    #[cfg(feature = "physics")]
+                    VirtualKeyCode::G => if let Some(id) = selected.filter(|&id| physics.body(id).is_some()) {
+                        physics.set_velocity(id, (camera.target - camera.position).normalize() * 4.0);
+                    },
+                    #[cfg(feature = "physics")]
+                    VirtualKeyCode::H => if let Some(id) = selected {
+                        physics.apply_impulse(id, glam::Vec3::Y * 0.5);
+                    },
+                    _ => ()
+                }
+            }
             Event::MainEventsCleared => {
+                profiling::scope!("frame");
                 previous_frame_end.as_mut().unwrap().cleanup_finished();
                 if recreate_swapchain {
                     let (new_swapchain, new_images)  =
@@ -141,12 +605,33 @@ fn main() {
                             }) {
                             Ok(r) => r,
                             Err(SwapchainCreationError::ImageExtentNotSupported {..}) => return,
+                            Err(SwapchainCreationError::DeviceLost) => {
+                                *lost = Some(device_lost::Lost { report: device_lost::report(&dev, gpu_timer.breadcrumbs(), &gpu_memory, &SwapchainCreationError::DeviceLost),
+                                                                 future: previous_frame_end.take() });
+                                return;
+                            }
                             Err(e) => panic!("Failed to recreate swapchain: {:?}", e),
                         };
                     swapchain = new_swapchain;
-                    framebuffers = window_size_dependent_setup(&new_images, render_pass.clone(), &mut viewport);
+                    display = new_images[0].dimensions().width_height();
+                    views.resize(&renderer, display);
+                    viewport.dimensions = [display[0] as f32, display[1] as f32];
+                    post.resize(&new_images);
+                    overlay.resize(&new_images);
+                    #[cfg(feature = "egui")]
+                    gui.resize(&new_images);
+                    #[cfg(feature = "imgui")]
+                    dear_imgui.resize(&new_images);
+                    outline.resize(&new_images);
                     recreate_swapchain = false;
                 }
+                //follows the display, the upscaler setting and the gpu's load; the swapchain doesn't care
+                dynamic_resolution.update(gpu_timer.last_ms());
+                let render = postfx::render_extent(&scene, display, dynamic_resolution.scale());
+                if render != target.extent {
+                    picker = Picker::new(dev.clone(), render);
+                    (target, scene_color) = renderer.scene_target(&picker);
+                }
                 
                 let (image_num, suboptimal, acquire_future) =
                     match acquire_next_image(swapchain.clone(), None) {
@@ -155,54 +640,176 @@ fn main() {
                             recreate_swapchain = true;
                             return;
                         }
+                        Err(AcquireError::DeviceLost) => {
+                            *lost = Some(device_lost::Lost { report: device_lost::report(&dev, gpu_timer.breadcrumbs(), &gpu_memory, &AcquireError::DeviceLost),
+                                                             future: previous_frame_end.take() });
+                            return;
+                        }
                         Err(e) => panic!("Failed to acquire next image: {:?}", e),
                     };
                 
                 if suboptimal { recreate_swapchain = true; }
-                let clear_values = vec![ [0.0, 0.0, 1.0, 1.0].into() ];
+                if benchmark.is_some() { time.tick_fixed(benchmark::STEP); } else { time.tick(); }
+                fly.update(&mut camera, time.real_delta);
+                tweens.update(time.delta, &mut scene, &mut camera);
+                path_player.update(time.real_delta, &scene, &mut camera);
+                if let Some(f) = &mut follow { f.update(&scene, &mut camera, time.delta); }
+                if let Some(b) = &mut benchmark {
+                    b.update(&mut camera, gpu_timer.last());
+                    if b.finished() {
+                        if let Err(e) = b.write_report() { println!("Failed writing the benchmark report: {}", e); }
+                        benchmark = None;
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
+                if let Some(sdf) = &mut scene.sdf { sdf.time = time.elapsed as f32; }
+                if let Some(cycle) = &mut day_night { cycle.update(&time, &renderer, &mut scene); }
+                #[cfg(feature = "physics")]
+                physics.update(&time, &mut scene);
+                terrain.update(&mut scene, camera.position);
+                skinning.update(&time, &mut scene);
+                morphing.update(&time, &mut scene);
+                sprite::animate(&time, &mut scene.billboards);
+                cutout::animate(&time, &mut scene.cutouts);
+                particles::update(&time, &mut scene);
+                for map in &mut scene.tilemaps { map.update(&time); }
+                scene.update_bounds();
+                audio.update(&time, &scene, &camera);
+                probes.update(&renderer, &mut scene);
+                if !lightmap_bake.is_done() { lightmap_bake.poll(&queue, &mut scene); }
+                camera.aspect = viewport.dimensions[0] / viewport.dimensions[1];
+                let view = match &camera_2d { Some(c) => c.as_view(viewport.dimensions), None => camera.as_view() };
+                let view_proj = view.view_proj();
+                culling.update(&scene, view_proj);
+                //everything from here renders jittered; culling and the overlay stay steady
+                let view = match scene.taa { Some(_) => view.jittered(taa::jitter(time.frame, target.extent)), None => view };
+                dbg.begin_frame(&view.view);
+                ui.begin_frame();
+                if let Some(p) = &panel { ui.nine_slice(p, [16.0, 16.0, 240.0, 96.0], [1.0; 4]); }
+                gpu_memory.update(&dev);
+                stats.update(&time, gpu_timer.last_ms(), gpu_timer.passes(), renderer.take_stats(), &gpu_memory);
+                if let Some(font) = &mut font { stats.draw(&mut ui, font, &swapchain, viewport.dimensions, window.window().scale_factor() as f32); }
+                if let Some(font) = &title_font {
+                    let effects = ui::SdfEffects { outline: [0.0, 0.0, 0.0, 1.0], outline_width: 2.0, glow: [1.0, 0.6, 0.2, 0.8], glow_width: 8.0 };
+                    let style = text::TextStyle { align: text::Align::Center, effects, ..text::TextStyle::new(48.0) };
+                    ui.sdf_text(font, "arse", [viewport.dimensions[0] * 0.5, viewport.dimensions[1] - 80.0], &style);
+                }
+                //the 2D camera's world origin, to see pans and zooms against
+                if let Some(c) = &camera_2d {
+                    let origin = c.world_to_screen(glam::Vec2::ZERO, viewport.dimensions);
+                    ui.stroke(&vector::circle([0.0, 0.0], 6.0), &vector::Stroke::new(1.5), origin.into(), 1.0, [1.0, 0.3, 0.3, 1.0]);
+                }
+                if let Some(history) = &mut frame_graph {
+                    if history.len() == 120 { history.remove(0); }
+                    history.push(time.real_delta * 1000.0);
+                    //laid out in points, so it's the same size on dense screens
+                    let dpi = window.window().scale_factor() as f32;
+                    let at = [16.0 * dpi, viewport.dimensions[1] - 116.0 * dpi];
+                    ui.fill(&vector::rounded_rect([0.0, 0.0, 240.0, 100.0], 8.0), at, dpi, [0.0, 0.0, 0.0, 0.6]);
+                    let points: Vec<[f32; 2]> = history.iter().enumerate().map(|(i, ms)| [8.0 + i as f32 * 224.0 / 119.0, 92.0 - (ms / 33.3).min(1.0) * 84.0]).collect();
+                    ui.stroke(&vector::polyline(&points, false), &vector::Stroke::new(1.5), at, dpi, [0.4, 1.0, 0.4, 1.0]);
+                }
+                #[cfg(feature = "egui")]
+                gui.run(window.window(), |ctx| {
+                    #[cfg(feature = "puffin")]
+                    puffin_egui::profiler_window(ctx);
+                    if !tools { return; }
+                    egui::Window::new("arse").show(ctx, |ui| {
+                        ui.label(format!("{:.2} ms frame", time.real_delta * 1000.0));
+                        ui.add(egui::Slider::new(&mut time.scale, 0.0..=2.0).text("time scale"));
+                        ui.checkbox(&mut dynamic_resolution.enabled, "dynamic resolution");
+                        ui.add(egui::Slider::new(&mut overlay.line_width, 1.0..=8.0).text("line width"));
+                        if let Some(id) = selected {
+                            ui.label(format!("selected {}", id));
+                            #[cfg(feature = "physics")]
+                            if physics.body(id).is_some() { ui.label(format!("velocity {:.2?}", physics.velocity(id))); }
+                        }
+                    });
+                });
+                #[cfg(feature = "imgui")]
+                dear_imgui.run(window.window(), time.real_delta, |ui| {
+                    if !imgui_tools { return; }
+                    ui.window("arse").build(|| {
+                        ui.text(format!("{:.2} ms frame", time.real_delta * 1000.0));
+                        ui.slider("time scale", 0.0, 2.0, &mut time.scale);
+                        ui.checkbox("dynamic resolution", &mut dynamic_resolution.enabled);
+                        ui.slider("line width", 1.0, 8.0, &mut overlay.line_width);
+                        if let Some(id) = selected {
+                            ui.text(format!("selected {}", id));
+                            #[cfg(feature = "physics")]
+                            if physics.body(id).is_some() { ui.text(format!("velocity {:.2?}", physics.velocity(id))); }
+                        }
+                    });
+                });
+                culling.debug_draw(&scene, &mut dbg);
+                #[cfg(feature = "physics")]
+                physics.debug_draw(&mut dbg);
+                if let Some(e) = selected.and_then(|id| scene.get(id)) { gizmo.draw(&e.transform, camera.position, &mut dbg); }
 
                 let mut builder = AutoCommandBufferBuilder::primary(dev.clone(), queue.family(), CommandBufferUsage::OneTimeSubmit).unwrap();
-                builder.begin_render_pass(framebuffers[image_num].clone(), SubpassContents::Inline, clear_values).unwrap()
-                    .set_viewport(0, [viewport.clone()])
-                    .bind_pipeline_graphics(pipeline.clone())
-                    .bind_vertex_buffers(0, vertex_buffer.clone())
-                    .draw(vertex_buffer.len() as u32, 1, 0, 0).unwrap()
-                    .end_render_pass().unwrap();
+                gpu_timer.begin(&mut builder);
+                //particles collide with the depth the main view left last frame, before it draws again
+                gpu_debug::labeled(&mut builder, "particles", |b| renderer.gpu_particles.update(b, &time, &mut scene, &target, &view));
+                gpu_timer.mark(&mut builder, "particles");
+                gpu_debug::labeled(&mut builder, "acceleration structures", |b| renderer.build_acceleration_structures(b, &scene));
+                gpu_timer.mark(&mut builder, "acceleration structures");
+                //the views first, their own shadows and gi would overwrite the main camera's
+                gpu_debug::labeled(&mut builder, "views", |b| views.render(&mut renderer, b, &scene));
+                gpu_timer.mark(&mut builder, "views");
+                gpu_debug::labeled(&mut builder, "shadows", |b| renderer.shadows.render(b, &scene, &view));
+                gpu_timer.mark(&mut builder, "shadows");
+                gpu_debug::labeled(&mut builder, "voxel gi", |b| renderer.voxelize(b, &scene, &view));
+                gpu_timer.mark(&mut builder, "voxel gi");
+                let portal_views = gpu_debug::labeled(&mut builder, "portals", |b| portal_targets.render(&renderer, b, &scene, &view, target.extent, &|id| culling.is_visible(id)));
+                gpu_timer.mark(&mut builder, "portals");
+                gpu_debug::labeled(&mut builder, "scene", |b| renderer.draw(b, &target, &scene, &view, &|e| culling.is_visible(e.id), &portal_views, Some(&mut fog_volume)));
+                gpu_timer.mark(&mut builder, "scene");
+                let reflection = gpu_debug::labeled(&mut builder, "water", |b| water_reflection.render(&renderer, b, &scene, &view, target.extent));
+                gpu_timer.mark(&mut builder, "water");
+                gpu_debug::labeled(&mut builder, "picking", |b| picker.record(b));
+                gpu_timer.mark(&mut builder, "picking");
+                gpu_debug::labeled(&mut builder, "post", |b| post.record(b, image_num, &PostContext { scene: &scene, camera: &camera, view: &view, target: &target, time: &time, headroom: post.headroom(&scene), water_reflection: reflection }, scene_color.clone()));
+                gpu_timer.mark(&mut builder, "post");
+                gpu_debug::labeled(&mut builder, "ui", |b| {
+                    outline.draw(b, image_num, &viewport, &picker, selected, hovered);
+                    overlay.draw(b, image_num, &viewport, view_proj, dbg.lines(), &views.composites(), ui.shapes(), ui.quads());
+                    #[cfg(feature = "egui")]
+                    gui.draw(b, image_num, &viewport);
+                    #[cfg(feature = "imgui")]
+                    dear_imgui.draw(b, image_num, &viewport);
+                });
+                gpu_timer.mark(&mut builder, "ui");
+                scene.end_frame();
+                gpu_timer.end(&mut builder);
 
+                profiling::scope!("submit");
                 let command_buffer = builder.build().unwrap();
+                if let Some(b) = &mut benchmark { b.recorded(gpu_timer.frame()); }
                 let future = previous_frame_end.take().unwrap()
                     .join(acquire_future)
                     .then_execute(queue.clone(), command_buffer).unwrap()
                     .then_swapchain_present(queue.clone(), swapchain.clone(), image_num).then_signal_fence_and_flush();
 
                 match future {
-                    Ok(future) => { let _ = future.wait(None); previous_frame_end = Some(future.boxed()); }
+                    Ok(future) => {
+                        if let Err(FlushError::DeviceLost) = future.wait(None) {
+                            *lost = Some(device_lost::Lost { report: device_lost::report(&dev, gpu_timer.breadcrumbs(), &gpu_memory, &FlushError::DeviceLost),
+                                                             future: Some(future.boxed()) });
+                            return;
+                        }
+                        previous_frame_end = Some(future.boxed());
+                    }
+                    //a crash in what was submitted, or the driver timing it out
+                    Err(FlushError::DeviceLost) => {
+                        *lost = Some(device_lost::Lost { report: device_lost::report(&dev, gpu_timer.breadcrumbs(), &gpu_memory, &FlushError::DeviceLost), future: None });
+                        return;
+                    }
                     Err(FlushError::OutOfDate) => {recreate_swapchain = true; previous_frame_end = Some(vulkano::sync::now(dev.clone()).boxed()); }
                     Err(e) => { println!("Failed to flush future: {:?}", e); previous_frame_end = Some(vulkano::sync::now(dev.clone()).boxed()); }
                 }
+                profiling::finish_frame!();
             }
             _ => ()
         }
-    });
-}
-
- /// This method is called once during initialization, then again whenever the window is resized
-fn window_size_dependent_setup(
-    images: &[Arc<SwapchainImage<Window>>],
-    render_pass: Arc<RenderPass>,
-    viewport: &mut Viewport, ) -> Vec<Arc<Framebuffer>> {
-    
-    let dimensions = images[0].dimensions().width_height();
-    viewport.dimensions = [dimensions[0] as f32, dimensions[1] as f32];
-
-    images.iter().map(|image| {
-            let view = ImageView::new_default(image.clone()).unwrap();
-            Framebuffer::new(
-                render_pass.clone(),
-                FramebufferCreateInfo {
-                    attachments: vec![view],
-                    ..Default::default()
-                },
-            ) .unwrap()
-        }) .collect::<Vec<_>>()
+    })
 }
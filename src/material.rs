@@ -0,0 +1,99 @@
+use std::sync::Arc;
+use crate::texture::Texture;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShadingModel {
+    /// Cook-Torrance GGX, metallic-roughness like glTF.
+    Pbr,
+    BlinnPhong,
+    /// Cel shading: diffuse in `bands` flat steps, a hard highlight shrinking with roughness, and
+    /// `shadow_tint` for the unlit side. In deferred it takes no light probe or lightmap diffuse.
+    Toon { bands: u32, shadow_tint: [f32; 3] },
+}
+
+/// Inverted hull outline: the mesh drawn again, pushed out along its normals with front faces
+/// culled, so only a rim shows around the silhouette. Needs closed meshes with smooth normals.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Outline {
+    pub color: [f32; 4],
+    /// Pixels.
+    pub width: f32,
+}
+
+/// Surface parameters for the standard shader. Factors multiply their textures, and the
+/// metallic-roughness texture uses glTF's channels (g roughness, b metallic), so glTF
+/// materials map over one to one.
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub shading: ShadingModel,
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: [f32; 3],
+    pub shininess: f32, //blinn-phong only
+    /// Srgb, `Texture::load`.
+    pub base_color_texture: Option<Arc<Texture>>,
+    /// Linear, `Texture::load_linear`.
+    pub metallic_roughness_texture: Option<Arc<Texture>>,
+    /// Tangent space, +y up (glTF convention). Load with `Texture::load_linear`.
+    pub normal_texture: Option<Arc<Texture>>,
+    pub normal_scale: f32,
+    pub outline: Option<Outline>,
+    /// Replaces the base color texture with blended layers, for terrain.
+    pub splat: Option<Splat>,
+    pub displacement: Option<Displacement>,
+}
+
+/// Moves the surface itself along its normals by a height texture, tessellating the mesh finer
+/// the larger it is on screen. Needs tessellation shaders; devices without them draw the mesh
+/// as it is. Shadows, outlines and picking see the undisplaced mesh too. Wants smooth normals,
+/// flat ones tear the surface apart at the edges.
+#[derive(Clone, Debug)]
+pub struct Displacement {
+    /// Height in r. Load with `Texture::load_linear`.
+    pub texture: Arc<Texture>,
+    /// Object space distance between heights 0 and 1.
+    pub scale: f32,
+    /// Height that stays where the mesh is; 0 only pushes out.
+    pub midlevel: f32,
+    /// Screen pixels each tessellated edge aims for, smaller is denser.
+    pub edge_pixels: f32,
+}
+
+/// Up to four base color layers mixed by a control texture's rgba weights. The control map is
+/// read with the mesh uvs as they are, the layers with them times `tiling`, so one control map
+/// spans a whole terrain while the layers repeat across it. Missing layers are white.
+#[derive(Clone, Debug)]
+pub struct Splat {
+    /// Weights per layer in r, g, b, a, normalized in the shader. Load with `Texture::load_linear`.
+    pub control: Arc<Texture>,
+    pub layers: [Option<Arc<Texture>>; 4],
+    pub tiling: f32,
+}
+
+pub const FLAG_NORMAL_MAP: u32 = 1;
+/// Not a material property, the renderer sets it for entities with a baked lightmap.
+pub const FLAG_LIGHTMAP: u32 = 2;
+pub const FLAG_SPLAT: u32 = 4;
+/// Set by the renderer for entities with a skin, like `FLAG_LIGHTMAP`.
+pub const FLAG_SKINNED: u32 = 8;
+/// Set by the renderer for entities with morph targets.
+pub const FLAG_MORPHED: u32 = 16;
+
+impl Material {
+    /// Feature bits for the standard shader, see standard.glsl.
+    pub fn flags(&self) -> u32 {
+        let mut flags = 0;
+        if self.normal_texture.is_some() { flags |= FLAG_NORMAL_MAP; }
+        if self.splat.is_some() { flags |= FLAG_SPLAT; }
+        flags
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material { shading: ShadingModel::Pbr, base_color: [0.8, 0.8, 0.8, 1.0], metallic: 0.0, roughness: 0.5, emissive: [0.0; 3],
+                   shininess: 32.0, base_color_texture: None, metallic_roughness_texture: None,
+                   normal_texture: None, normal_scale: 1.0, outline: None, splat: None, displacement: None }
+    }
+}
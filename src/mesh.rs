@@ -0,0 +1,98 @@
+use vulkano::{ device::Device,
+               buffer::{ BufferUsage, CpuAccessibleBuffer },
+               impl_vertex };
+use bytemuck::{ Pod, Zeroable };
+use std::sync::Arc;
+use glam::Vec3;
+use crate::bvh::Aabb;
+use crate::gpu_memory::{ self, MemoryCategory };
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct Vertex { pub position: [f32; 3], pub normal: [f32; 3], pub uv: [f32; 2], pub tangent: [f32; 4], pub lightmap_uv: [f32; 2], }
+impl_vertex!(Vertex, position, normal, uv, tangent, lightmap_uv);
+
+pub struct Mesh {
+    pub vertex_buffer: Arc<CpuAccessibleBuffer<[Vertex]>>,
+    pub vertices: Vec<Vertex>, //cpu side copy, kept around for picking/culling
+    pub aabb: Aabb,
+}
+
+/// Per triangle tangents from the uv layout, w holding the bitangent sign. Fine for our
+/// non-indexed meshes; falls back to an arbitrary tangent where the uvs are degenerate.
+fn generate_tangents(vertices: &mut [Vertex]) {
+    for t in vertices.chunks_exact_mut(3) {
+        let p = [Vec3::from(t[0].position), Vec3::from(t[1].position), Vec3::from(t[2].position)];
+        let (e1, e2) = (p[1] - p[0], p[2] - p[0]);
+        let (du1, dv1) = (t[1].uv[0] - t[0].uv[0], t[1].uv[1] - t[0].uv[1]);
+        let (du2, dv2) = (t[2].uv[0] - t[0].uv[0], t[2].uv[1] - t[0].uv[1]);
+        let det = du1 * dv2 - du2 * dv1;
+        for v in t.iter_mut() {
+            let n = Vec3::from(v.normal);
+            if det.abs() < 1e-8 {
+                v.tangent = n.any_orthonormal_vector().extend(1.0).into();
+                continue;
+            }
+            let tangent = (e1 * dv2 - e2 * dv1) / det;
+            let bitangent = (e2 * du1 - e1 * du2) / det;
+            let tangent = (tangent - n * n.dot(tangent)).normalize_or_zero(); //gram-schmidt
+            let w = if n.cross(tangent).dot(bitangent) < 0.0 { -1.0 } else { 1.0 };
+            v.tangent = tangent.extend(w).into();
+        }
+    }
+}
+
+/// Second uv set for lightmaps: every pair of triangles gets one cell of a square grid, split
+/// along the diagonal and inset so bilinear lookups stay inside their own triangle. Wasteful
+/// next to a real unwrap, but never overlaps, which is all the baker needs.
+fn generate_lightmap_uvs(vertices: &mut [Vertex]) {
+    const INSET: f32 = 0.1;
+    let triangles = vertices.len() / 3;
+    let cells = (triangles + 1) / 2;
+    let side = (cells as f32).sqrt().ceil().max(1.0) as usize;
+    let corners = [[[INSET, INSET], [1.0 - 2.0 * INSET, INSET], [INSET, 1.0 - 2.0 * INSET]],
+                   [[1.0 - INSET, 1.0 - INSET], [2.0 * INSET, 1.0 - INSET], [1.0 - INSET, 2.0 * INSET]]];
+    for (i, t) in vertices.chunks_exact_mut(3).enumerate() {
+        let cell = i / 2;
+        let origin = [(cell % side) as f32, (cell / side) as f32];
+        for (v, c) in t.iter_mut().zip(corners[i % 2]) {
+            v.lightmap_uv = [(origin[0] + c[0]) / side as f32, (origin[1] + c[1]) / side as f32];
+        }
+    }
+}
+
+impl Mesh {
+    /// Tangents are generated here, whatever the caller put in them is overwritten. Lightmap uvs
+    /// are too, unless the caller has some that aren't all zero.
+    pub fn new(dev: Arc<Device>, mut vertices: Vec<Vertex>) -> Arc<Mesh> {
+        generate_tangents(&mut vertices);
+        if vertices.iter().all(|v| v.lightmap_uv == [0.0; 2]) { generate_lightmap_uvs(&mut vertices); }
+        //storage too, voxel gi reads the triangles in compute
+        let vertex_buffer = CpuAccessibleBuffer::from_iter(dev, BufferUsage { vertex_buffer: true, storage_buffer: true, ..BufferUsage::none() }, false, vertices.iter().cloned())
+            .expect("failed mesh upload");
+        gpu_memory::track(MemoryCategory::Meshes, &vertex_buffer, (vertices.len() * std::mem::size_of::<Vertex>()) as u64);
+        let aabb = Aabb::from_points(vertices.iter().map(|v| Vec3::from(v.position)));
+        Arc::new(Mesh { vertex_buffer, vertices, aabb })
+    }
+
+    /// Triangle list with flat normals generated from the winding, uvs left at zero.
+    pub fn from_positions(dev: Arc<Device>, positions: &[[f32; 3]]) -> Arc<Mesh> {
+        let vertices = positions.chunks_exact(3).flat_map(|t| {
+            let (a, b, c) = (Vec3::from(t[0]), Vec3::from(t[1]), Vec3::from(t[2]));
+            let normal = (b - a).cross(c - a).normalize_or_zero().into();
+            t.iter().map(move |&position| Vertex { position, normal, uv: [0.0; 2], tangent: [0.0; 4], lightmap_uv: [0.0; 2] })
+        }).collect();
+        Mesh::new(dev, vertices)
+    }
+
+    /// Unit quad in the xy plane facing +z.
+    pub fn quad(dev: Arc<Device>) -> Arc<Mesh> {
+        let v = |x: f32, y: f32| Vertex { position: [x, y, 0.0], normal: [0.0, 0.0, 1.0], uv: [x + 0.5, 0.5 - y], tangent: [0.0; 4], lightmap_uv: [0.0; 2] };
+        Mesh::new(dev, vec![v(-0.5, -0.5), v(0.5, -0.5), v(0.5, 0.5), v(-0.5, -0.5), v(0.5, 0.5), v(-0.5, 0.5)])
+    }
+
+    /// Triangles of a non-indexed triangle list.
+    pub fn triangles(&self) -> impl Iterator<Item = [Vec3; 3]> + '_ {
+        self.vertices.chunks_exact(3).map(|t| [t[0].position.into(), t[1].position.into(), t[2].position.into()])
+    }
+}
@@ -0,0 +1,184 @@
+use vulkano::device::Device;
+use gltf::animation::{ Interpolation as GltfInterpolation, util::ReadOutputs };
+use glam::{ Mat4, Quat, Vec3 };
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use crate::gpu_debug;
+use crate::mesh::{ Mesh, Vertex };
+use crate::animation::{ Clip, Interpolation, Joint, JointPose, Keys, Skeleton, Track };
+use crate::skin::{ Skin, SkinVertex };
+use crate::morph::{ Morph, MorphTargets, MorphVertex };
+use crate::scene::{ Scene, EntityId };
+
+/// One triangle primitive of the file, unindexed.
+pub struct ModelMesh {
+    pub mesh: Arc<Mesh>,
+    /// Per vertex of `mesh` when the primitive is bound to the model's skeleton.
+    pub skin: Option<Vec<SkinVertex>>,
+    pub morph: Option<Arc<MorphTargets>>,
+    /// Index of the node holding it, the morph channel its weight tracks animate.
+    pub node: usize,
+    /// Where the node holding it sits in the model; identity for skinned meshes, which the
+    /// joints place instead.
+    pub transform: Mat4,
+}
+
+/// Geometry, skeleton and animations of a glTF file. Materials aren't imported, the meshes get
+/// the default one.
+pub struct Model {
+    pub meshes: Vec<ModelMesh>,
+    /// The file's first skin; meshes bound to any other come in unskinned.
+    pub skeleton: Option<Arc<Skeleton>>,
+    /// Only the channels that animate the skeleton's joints, or morph weights by node.
+    pub clips: Vec<Arc<Clip>>,
+}
+
+/// Cubic spline keys come as in tangent, value, out tangent; only the values are kept, and
+/// played back linearly.
+fn values<T>(v: impl Iterator<Item = T>, cubic: bool) -> Vec<T> {
+    if cubic { v.skip(1).step_by(3).collect() } else { v.collect() }
+}
+
+/// Loads a .gltf or .glb along with its buffers. Panics on files it can't read, like the
+/// texture loaders.
+#[profiling::function]
+pub fn load_gltf(dev: Arc<Device>, path: impl AsRef<Path>) -> Model {
+    let (doc, buffers, _) = gltf::import(path.as_ref()).unwrap_or_else(|e| panic!("failed loading {:?}: {}", path.as_ref(), e));
+    let data = |b: gltf::Buffer| Some(buffers[b.index()].0.as_slice());
+
+    let skin = doc.skins().next();
+    let mut joint_of = HashMap::new();
+    let skeleton = skin.as_ref().map(|skin| {
+        let nodes: Vec<_> = skin.joints().collect();
+        for (i, n) in nodes.iter().enumerate() { joint_of.insert(n.index(), i); }
+        let mut joints: Vec<Joint> = nodes.iter().map(|n| {
+            let (translation, rotation, scale) = n.transform().decomposed();
+            Joint { name: n.name().unwrap_or_default().to_string(), parent: None,
+                    rest: JointPose { translation: translation.into(), rotation: Quat::from_array(rotation), scale: scale.into() } }
+        }).collect();
+        for (i, n) in nodes.iter().enumerate() {
+            for child in n.children() {
+                if let Some(&c) = joint_of.get(&child.index()) { joints[c].parent = Some(i); }
+            }
+        }
+        let inverse_bind = skin.reader(data).read_inverse_bind_matrices()
+            .map_or_else(|| vec![Mat4::IDENTITY; joints.len()], |m| m.map(|m| Mat4::from_cols_array_2d(&m)).collect());
+        Arc::new(Skeleton::new(joints, inverse_bind))
+    });
+
+    let mut meshes = Vec::new();
+    let mut stack: Vec<_> = doc.default_scene().or_else(|| doc.scenes().next()).into_iter()
+        .flat_map(|s| s.nodes().map(|n| (n, Mat4::IDENTITY)).collect::<Vec<_>>()).collect();
+    while let Some((node, parent)) = stack.pop() {
+        let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+        stack.extend(node.children().map(|c| (c, transform)));
+        let mesh = match node.mesh() { Some(m) => m, None => continue };
+        let skinned = skeleton.is_some() && node.skin().map(|s| s.index()) == skin.as_ref().map(|s| s.index());
+        for primitive in mesh.primitives().filter(|p| p.mode() == gltf::mesh::Mode::Triangles) {
+            let reader = primitive.reader(data);
+            let positions: Vec<[f32; 3]> = match reader.read_positions() { Some(p) => p.collect(), None => continue };
+            let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|n| n.collect());
+            let uvs: Option<Vec<[f32; 2]>> = reader.read_tex_coords(0).map(|t| t.into_f32().collect());
+            //an authored second set is the lightmap's, Mesh::new unwraps one otherwise
+            let lightmap_uvs: Option<Vec<[f32; 2]>> = reader.read_tex_coords(1).map(|t| t.into_f32().collect());
+            let joints: Option<Vec<[u16; 4]>> = reader.read_joints(0).map(|j| j.into_u16().collect());
+            let weights: Option<Vec<[f32; 4]>> = reader.read_weights(0).map(|w| w.into_f32().collect());
+            let indices: Vec<u32> = reader.read_indices().map_or_else(|| (0..positions.len() as u32).collect(), |i| i.into_u32().collect());
+
+            let mut vertices: Vec<Vertex> = indices.iter().map(|&i| {
+                let i = i as usize;
+                Vertex { position: positions[i], normal: normals.as_ref().map_or([0.0; 3], |n| n[i]), uv: uvs.as_ref().map_or([0.0; 2], |t| t[i]),
+                         tangent: [0.0; 4], lightmap_uv: lightmap_uvs.as_ref().map_or([0.0; 2], |t| t[i]) }
+            }).collect();
+            //flat normals from the winding when the file has none
+            if normals.is_none() {
+                for t in vertices.chunks_exact_mut(3) {
+                    let [a, b, c] = [0, 1, 2].map(|k| Vec3::from(t[k].position));
+                    let n = (b - a).cross(c - a).normalize_or_zero().into();
+                    for v in t.iter_mut() { v.normal = n; }
+                }
+            }
+            let skin = match (skinned, &joints, &weights) {
+                (true, Some(j), Some(w)) => Some(indices.iter().map(|&i| {
+                    let (j, w) = (j[i as usize], w[i as usize]);
+                    let sum = w.iter().sum::<f32>().max(1e-6);
+                    SkinVertex { joints: j.map(|j| j as u32), weights: w.map(|w| w / sum) }
+                }).collect()),
+                _ => None,
+            };
+            //deltas expanded by the indices like the vertices, target after target
+            let mut deltas = Vec::new();
+            for (positions, normals, _) in reader.read_morph_targets() {
+                let positions: Option<Vec<[f32; 3]>> = positions.map(|p| p.collect());
+                let normals: Option<Vec<[f32; 3]>> = normals.map(|n| n.collect());
+                deltas.extend(indices.iter().map(|&i| {
+                    let i = i as usize;
+                    let [px, py, pz] = positions.as_ref().map_or([0.0; 3], |p| p[i]);
+                    let [nx, ny, nz] = normals.as_ref().map_or([0.0; 3], |n| n[i]);
+                    MorphVertex { position: [px, py, pz, 0.0], normal: [nx, ny, nz, 0.0] }
+                }));
+            }
+            let targets = primitive.morph_targets().len();
+            let morph = (targets > 0).then(|| {
+                let mut defaults = mesh.weights().map_or_else(Vec::new, |w| w.to_vec());
+                defaults.resize(targets, 0.0);
+                MorphTargets::new(dev.clone(), &deltas, defaults)
+            });
+            let transform = if skin.is_some() { Mat4::IDENTITY } else { transform };
+            let vertices = Mesh::new(dev.clone(), vertices);
+            gpu_debug::name(&**vertices.vertex_buffer.inner().buffer, mesh.name().unwrap_or("mesh"));
+            meshes.push(ModelMesh { mesh: vertices, skin, morph, node: node.index(), transform });
+        }
+    }
+
+    let clips = doc.animations().map(|anim| {
+        let mut tracks = Vec::new();
+        for channel in anim.channels() {
+            let node = channel.target().node();
+            let morphs = node.mesh().and_then(|m| m.primitives().next()).map_or(0, |p| p.morph_targets().len());
+            let joint = match joint_of.get(&node.index()) {
+                Some(&j) => j,
+                None if morphs > 0 => node.index(),
+                None => continue,
+            };
+            let reader = channel.reader(data);
+            let (times, outputs) = match (reader.read_inputs(), reader.read_outputs()) { (Some(t), Some(o)) => (t.collect::<Vec<_>>(), o), _ => continue };
+            let cubic = channel.sampler().interpolation() == GltfInterpolation::CubicSpline;
+            let keys = match outputs {
+                ReadOutputs::Translations(t) => Keys::Translation(values(t.map(Vec3::from), cubic)),
+                ReadOutputs::Rotations(r) => Keys::Rotation(values(r.into_f32().map(Quat::from_array), cubic)),
+                ReadOutputs::Scales(s) => Keys::Scale(values(s.map(Vec3::from), cubic)),
+                //a key is every target's weight in a row
+                ReadOutputs::MorphTargetWeights(w) => {
+                    let w: Vec<f32> = w.into_f32().collect();
+                    Keys::Weights(values(w.chunks(morphs.max(1)).map(|k| k.to_vec()), cubic))
+                }
+            };
+            let interpolation = match channel.sampler().interpolation() { GltfInterpolation::Step => Interpolation::Step, _ => Interpolation::Linear };
+            tracks.push(Track { joint, times, keys, interpolation });
+        }
+        let duration = tracks.iter().filter_map(|t| t.times.last().copied()).fold(0.0, f32::max);
+        Arc::new(Clip { name: anim.name().unwrap_or_default().to_string(), duration, tracks })
+    }).collect();
+
+    Model { meshes, skeleton, clips }
+}
+
+impl Model {
+    pub fn clip(&self, name: &str) -> Option<Arc<Clip>> { self.clips.iter().find(|c| c.name == name).cloned() }
+
+    /// An entity per mesh, placed by `transform`; skinned ones get a `Skin` at the rest pose,
+    /// morphed ones a `Morph` at the file's weights.
+    pub fn spawn(&self, dev: Arc<Device>, scene: &mut Scene, transform: Mat4) -> Vec<EntityId> {
+        self.meshes.iter().map(|m| {
+            let id = scene.spawn(m.mesh.clone(), transform * m.transform);
+            let entity = scene.get_mut(id).unwrap();
+            if let (Some(weights), Some(skeleton)) = (&m.skin, &self.skeleton) {
+                entity.skin = Some(Skin::new(dev.clone(), skeleton.clone(), weights));
+            }
+            entity.morph = m.morph.as_ref().map(|t| Morph::new(t.clone(), m.node));
+            id
+        }).collect()
+    }
+}
@@ -0,0 +1,106 @@
+use vulkano::{ device::Device,
+               buffer::{ BufferAccess, BufferUsage, CpuAccessibleBuffer, CpuBufferPool } };
+use bytemuck::{ Pod, Zeroable };
+use std::sync::Arc;
+use crate::animation::{ AnimationPlayer, Pose };
+use crate::scene::Scene;
+use crate::time::Time;
+
+/// How far one morph target moves one vertex at weight 1. Tangents aren't morphed.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct MorphVertex { pub position: [f32; 4], pub normal: [f32; 4] }
+
+/// Blend shapes of one mesh: every target's deltas for every vertex, target after target, in
+/// the mesh's vertex order. Shared between entities using the mesh.
+pub struct MorphTargets {
+    pub deltas: Arc<CpuAccessibleBuffer<[MorphVertex]>>,
+    pub count: usize,
+    /// Weights the mesh shows unanimated.
+    pub defaults: Vec<f32>,
+}
+
+impl MorphTargets {
+    pub fn new(dev: Arc<Device>, deltas: &[MorphVertex], defaults: Vec<f32>) -> Arc<Self> {
+        assert!(!defaults.is_empty() && deltas.len() % defaults.len() == 0, "deltas for every vertex of every target");
+        let count = defaults.len();
+        let deltas = CpuAccessibleBuffer::from_iter(dev, BufferUsage::storage_buffer(), false, deltas.iter().cloned()).expect("failed morph upload");
+        Arc::new(MorphTargets { deltas, count, defaults })
+    }
+}
+
+/// Morph target weights of one entity, blended in the vertex shader before skinning.
+pub struct Morph {
+    pub targets: Arc<MorphTargets>,
+    /// One per target; set them directly, or let animation do it.
+    pub weights: Vec<f32>,
+    /// The `Keys::Weights` channel whose tracks drive `weights`.
+    pub channel: usize,
+    /// Drives `weights` for entities without a skin, when its clips have the channel; skinned
+    /// ones take them from the skin's pose instead, so one graph animates both.
+    pub player: AnimationPlayer,
+    previous: Vec<f32>,
+    /// This frame's weights followed by last frame's, for motion vectors.
+    buffer: Option<Arc<dyn BufferAccess>>,
+}
+
+impl Morph {
+    pub fn new(targets: Arc<MorphTargets>, channel: usize) -> Self {
+        Morph { weights: targets.defaults.clone(), targets, channel, player: AnimationPlayer::default(), previous: Vec::new(), buffer: None }
+    }
+
+    /// Deltas and weights to bind, once `Morphing::update` has run.
+    pub fn buffers(&self) -> Option<(Arc<dyn BufferAccess>, Arc<dyn BufferAccess>)> {
+        Some((self.targets.deltas.clone(), self.buffer.clone()?))
+    }
+}
+
+/// Bound in place of a morph's buffers for meshes without one, like `NoSkin`.
+pub struct NoMorph {
+    pub deltas: Arc<dyn BufferAccess>,
+    pub weights: Arc<dyn BufferAccess>,
+}
+
+impl NoMorph {
+    pub fn new(dev: Arc<Device>) -> Self {
+        let deltas = CpuAccessibleBuffer::from_iter(dev.clone(), BufferUsage::storage_buffer(), false, [MorphVertex::default()]).unwrap();
+        let weights = CpuAccessibleBuffer::from_iter(dev, BufferUsage::storage_buffer(), false, [0.0f32; 2]).unwrap();
+        NoMorph { deltas, weights }
+    }
+
+    /// `morph`'s buffers, or these.
+    pub fn or(&self, morph: Option<&Morph>) -> (Arc<dyn BufferAccess>, Arc<dyn BufferAccess>) {
+        morph.and_then(|m| m.buffers()).unwrap_or_else(|| (self.deltas.clone(), self.weights.clone()))
+    }
+}
+
+/// Takes every morph's weights from animation and uploads them. Once a frame, after `Skinning`.
+pub struct Morphing {
+    pool: CpuBufferPool<f32>,
+}
+
+impl Morphing {
+    pub fn new(dev: Arc<Device>) -> Self { Morphing { pool: CpuBufferPool::new(dev, BufferUsage::storage_buffer()) } }
+
+    #[profiling::function]
+    pub fn update(&self, time: &Time, scene: &mut Scene) {
+        for entity in &mut scene.entities {
+            let morph = match &mut entity.morph { Some(m) => m, None => continue };
+            let animated = match &entity.skin {
+                Some(skin) => skin.pose.weights.get(morph.channel).cloned(),
+                None if morph.player.is_playing() => {
+                    morph.player.update(time.delta);
+                    morph.player.sample(Pose::default()).weights.get(morph.channel).cloned()
+                }
+                None => None,
+            };
+            if let Some(w) = animated.filter(|w| !w.is_empty()) { morph.weights = w; }
+            morph.weights.resize(morph.targets.count, 0.0);
+            //the first frame has nothing to move from
+            if morph.previous.len() != morph.weights.len() { morph.previous = morph.weights.clone(); }
+            let buffer = self.pool.chunk(morph.weights.iter().chain(&morph.previous).copied()).unwrap();
+            morph.buffer = Some(buffer);
+            morph.previous.clone_from(&morph.weights);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use glam::{ Quat, Vec2 };
+use crate::camera::{ Camera, Projection };
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OrbitDrag { Rotate, Pan }
+
+/// Turns mouse drags and scrolling into camera moves around `Camera::target`. Keeps no camera
+/// state of its own, so it can drive whichever camera it's handed, and one that was moved some
+/// other way in between picks up from where that left it.
+pub struct OrbitController {
+    /// Radians per pixel dragged.
+    pub rotate_speed: f32,
+    /// Fraction of the distance to the target per scroll step.
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    drag: Option<(OrbitDrag, Vec2)>,
+}
+
+/// Closest the view direction gets to straight up or down, in radians, so `up` stays usable.
+const POLE_MARGIN: f32 = 0.01;
+
+impl OrbitController {
+    pub fn new() -> Self {
+        OrbitController { rotate_speed: 0.01, zoom_speed: 0.1, min_distance: 0.1, max_distance: 500.0, drag: None }
+    }
+
+    pub fn begin(&mut self, drag: OrbitDrag, cursor: [f32; 2]) { self.drag = Some((drag, cursor.into())); }
+
+    pub fn end(&mut self) { self.drag = None; }
+
+    /// Follows the cursor, in window pixels, while a drag is going; `extent` is the window's.
+    pub fn update(&mut self, camera: &mut Camera, cursor: [f32; 2], extent: [f32; 2]) {
+        let (drag, last) = match &mut self.drag { Some(d) => d, None => return };
+        let delta = Vec2::from(cursor) - *last;
+        *last = cursor.into();
+        let offset = camera.position - camera.target;
+        let up = camera.up.normalize();
+        let right = offset.cross(up).normalize_or_zero() * -1.0;
+        match drag {
+            OrbitDrag::Rotate => {
+                //pitch is clamped against the poles, yaw goes around `up`
+                let pitch = offset.normalize_or_zero().dot(up).clamp(-1.0, 1.0).asin();
+                let limit = std::f32::consts::FRAC_PI_2 - POLE_MARGIN;
+                let pitch_delta = (pitch + delta.y * self.rotate_speed).clamp(-limit, limit) - pitch;
+                let rotation = Quat::from_axis_angle(up, -delta.x * self.rotate_speed) * Quat::from_axis_angle(right, -pitch_delta);
+                camera.position = camera.target + rotation * offset;
+            }
+            OrbitDrag::Pan => {
+                //the target stays under the cursor
+                let world_height = match camera.lens() {
+                    Projection::Perspective { fov_y } => 2.0 * offset.length() * (fov_y * 0.5).tan(),
+                    Projection::Orthographic { height } => height,
+                };
+                let camera_up = right.cross(offset).normalize_or_zero() * -1.0;
+                let shift = (right * -delta.x + camera_up * delta.y) * world_height / extent[1].max(1.0);
+                camera.position += shift;
+                camera.target += shift;
+            }
+        }
+    }
+
+    /// Positive `steps` move in. Orthographic cameras shrink their view instead, since moving
+    /// one closer changes nothing on screen.
+    pub fn zoom(&self, camera: &mut Camera, steps: f32) {
+        let factor = (1.0 - self.zoom_speed).powf(steps);
+        match &mut camera.projection {
+            Projection::Perspective { .. } => {
+                let offset = camera.position - camera.target;
+                let distance = (offset.length() * factor).clamp(self.min_distance, self.max_distance);
+                camera.position = camera.target + offset.normalize_or_zero() * distance;
+            }
+            Projection::Orthographic { height } => *height = (*height * factor).clamp(self.min_distance, self.max_distance),
+        }
+    }
+}
@@ -0,0 +1,384 @@
+use vulkano::{ device::Device,
+               buffer::CpuBufferPool,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, SubpassContents },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               image::{ SwapchainImage, view::{ ImageView, ImageViewAbstract } },
+               sampler::{ Sampler, SamplerCreateInfo },
+               render_pass::{ Framebuffer, FramebufferCreateInfo, RenderPass, Subpass },
+               pipeline::{ GraphicsPipeline, Pipeline, PipelineBindPoint, graphics::{ input_assembly::{ InputAssemblyState, PrimitiveTopology }, vertex_input::BuffersDefinition,
+                                                                   viewport::{ Viewport, ViewportState }, color_blend::ColorBlendState } },
+               format::Format,
+               format::ClearValue,
+               impl_vertex };
+use bytemuck::{ Pod, Zeroable };
+use winit::window::Window;
+use glam::{ Vec3, Mat4 };
+use std::sync::Arc;
+use crate::texture::Texture;
+use crate::ui::UiQuad;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct LineVertex { pub position: [f32; 3], pub color: [f32; 4], }
+impl_vertex!(LineVertex, position, color);
+
+impl LineVertex {
+    pub fn new(p: Vec3, color: [f32; 4]) -> Self { LineVertex { position: p.into(), color } }
+}
+
+/// A vertex of `Ui` shapes, in pixels from the top left.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct ShapeVertex { pub position: [f32; 2], pub color: [f32; 4], }
+impl_vertex!(ShapeVertex, position, color);
+
+mod vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			layout(location = 0) in vec3 position;
+			layout(location = 1) in vec4 color;
+			layout(location = 0) out vec4 v_color;
+
+			layout(push_constant) uniform PushConstants { mat4 view_proj; } pc;
+
+			void main() {
+				gl_Position = pc.view_proj * vec4(position, 1.0);
+				v_color = color;
+			}"
+    }
+}
+mod fs {
+    vulkano_shaders::shader! { ty: "fragment",
+    src: "#version 450
+
+			layout(location = 0) in vec4 v_color;
+			layout(location = 0) out vec4 f_color;
+
+			void main() { f_color = v_color; }"
+    }
+}
+
+mod wide_vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			layout(location = 0) in vec3 position;
+			layout(location = 1) in vec4 color;
+			layout(location = 0) out vec4 v_color;
+
+			layout(push_constant) uniform PushConstants { mat4 view_proj; vec2 half_width; } pc;
+
+			void main() {
+				gl_Position = pc.view_proj * vec4(position, 1.0);
+				v_color = color;
+			}"
+    }
+}
+mod wide_gs {
+    vulkano_shaders::shader! { ty: "geometry",
+    src: "#version 450
+
+			layout(lines) in;
+			layout(triangle_strip, max_vertices = 4) out;
+			layout(location = 0) in vec4 v_color[];
+			layout(location = 0) out vec4 g_color;
+
+			layout(push_constant) uniform PushConstants { mat4 view_proj; vec2 half_width; } pc; //half_width: in ndc, per axis
+
+			const float NEAR = 1e-3;
+
+			// the segment clipped to the front of the camera, then widened across its direction on screen
+			void main() {
+				vec4 a = gl_in[0].gl_Position, b = gl_in[1].gl_Position;
+				vec4 ca = v_color[0], cb = v_color[1];
+				if (a.w < NEAR && b.w < NEAR) return;
+				if (a.w < NEAR) { float t = (NEAR - a.w) / (b.w - a.w); a = mix(a, b, t); ca = mix(ca, cb, t); }
+				if (b.w < NEAR) { float t = (NEAR - b.w) / (a.w - b.w); b = mix(b, a, t); cb = mix(cb, ca, t); }
+				vec2 dir = (b.xy / b.w - a.xy / a.w) / pc.half_width;
+				dir = length(dir) > 1e-6 ? normalize(dir) : vec2(1.0, 0.0);
+				vec2 side = vec2(-dir.y, dir.x) * pc.half_width;
+				g_color = ca; gl_Position = a - vec4(side * a.w, 0.0, 0.0); EmitVertex();
+				g_color = ca; gl_Position = a + vec4(side * a.w, 0.0, 0.0); EmitVertex();
+				g_color = cb; gl_Position = b - vec4(side * b.w, 0.0, 0.0); EmitVertex();
+				g_color = cb; gl_Position = b + vec4(side * b.w, 0.0, 0.0); EmitVertex();
+				EndPrimitive();
+			}"
+    }
+}
+
+mod rect_vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			layout(location = 0) out vec2 v_uv;
+			layout(push_constant) uniform PushConstants { vec4 rect; } pc; //x, y, w, h in 0..1, top left origin
+
+			const vec2 corners[6] = vec2[](vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 1.0));
+
+			void main() {
+				v_uv = corners[gl_VertexIndex];
+				gl_Position = vec4((pc.rect.xy + v_uv * pc.rect.zw) * 2.0 - 1.0, 0.0, 1.0);
+			}"
+    }
+}
+mod rect_fs {
+    vulkano_shaders::shader! { ty: "fragment",
+    src: "#version 450
+
+			layout(set = 0, binding = 0) uniform sampler2D u_texture;
+			layout(location = 0) in vec2 v_uv;
+			layout(location = 0) out vec4 f_color;
+
+			void main() { f_color = texture(u_texture, v_uv); }"
+    }
+}
+
+mod shape_vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			layout(location = 0) in vec2 position;
+			layout(location = 1) in vec4 color;
+			layout(location = 0) out vec4 v_color;
+
+			layout(push_constant) uniform PushConstants { vec2 screen; } pc; //pixels
+
+			void main() {
+				gl_Position = vec4(position / pc.screen * 2.0 - 1.0, 0.0, 1.0);
+				v_color = color;
+			}"
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+struct QuadInstance { i_rect: [f32; 4], i_uv: [f32; 4], i_color: [f32; 4], i_sdf: [f32; 4], i_outline: [f32; 4], i_glow: [f32; 4], }
+impl_vertex!(QuadInstance, i_rect, i_uv, i_color, i_sdf, i_outline, i_glow);
+
+mod quad_vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			layout(location = 0) in vec4 i_rect;
+			layout(location = 1) in vec4 i_uv;
+			layout(location = 2) in vec4 i_color;
+			layout(location = 3) in vec4 i_sdf; //pixel range, outline width, glow width; range 0 for plain images
+			layout(location = 4) in vec4 i_outline;
+			layout(location = 5) in vec4 i_glow;
+
+			layout(location = 0) out vec2 v_uv;
+			layout(location = 1) out vec4 v_color;
+			layout(location = 2) flat out vec4 v_sdf;
+			layout(location = 3) flat out vec4 v_outline;
+			layout(location = 4) flat out vec4 v_glow;
+
+			layout(push_constant) uniform PushConstants { vec2 screen; } pc; //pixels
+
+			const vec2 corners[6] = vec2[](vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 1.0));
+
+			void main() {
+				vec2 c = corners[gl_VertexIndex];
+				gl_Position = vec4((i_rect.xy + c * i_rect.zw) / pc.screen * 2.0 - 1.0, 0.0, 1.0);
+				v_uv = mix(i_uv.xy, i_uv.zw, c);
+				v_color = i_color;
+				v_sdf = i_sdf;
+				v_outline = i_outline;
+				v_glow = i_glow;
+			}"
+    }
+}
+mod quad_fs {
+    vulkano_shaders::shader! { ty: "fragment",
+    src: "#version 450
+
+			layout(set = 0, binding = 0) uniform sampler2D u_texture;
+			layout(location = 0) in vec2 v_uv;
+			layout(location = 1) in vec4 v_color;
+			layout(location = 2) flat in vec4 v_sdf;
+			layout(location = 3) flat in vec4 v_outline;
+			layout(location = 4) flat in vec4 v_glow;
+			layout(location = 0) out vec4 f_color;
+
+			vec4 over(vec4 top, vec4 bottom) {
+				float a = top.a + bottom.a * (1.0 - top.a);
+				return vec4((top.rgb * top.a + bottom.rgb * bottom.a * (1.0 - top.a)) / max(a, 1e-5), a);
+			}
+
+			void main() {
+				vec4 t = texture(u_texture, v_uv);
+				if (v_sdf.x <= 0.0) { f_color = t * v_color; return; }
+				//screen pixels inside the glyph's edge, negative outside
+				float d = (max(min(t.r, t.g), min(max(t.r, t.g), t.b)) - 0.5) * v_sdf.x;
+				float fill = clamp(d + 0.5, 0.0, 1.0);
+				float outline = v_sdf.y > 0.0 ? clamp(d + v_sdf.y + 0.5, 0.0, 1.0) : 0.0;
+				float glow = v_sdf.z > 0.0 ? clamp(1.0 + (d + v_sdf.y) / v_sdf.z, 0.0, 1.0) : 0.0;
+				vec4 under = over(vec4(v_outline.rgb, v_outline.a * outline), vec4(v_glow.rgb, v_glow.a * glow * glow));
+				f_color = over(vec4(v_color.rgb, v_color.a * fill), under);
+			}"
+    }
+}
+
+/// Pass drawn straight onto the swapchain image after the scene, without depth, for editor
+/// widgets and `DebugDraw` output that must stay visible through geometry. Also composites
+/// textured screen rects, such as secondary camera views, underneath the lines, and `Ui`
+/// shapes then quads between the two.
+pub struct Overlay {
+    /// In pixels. Lines stay one pixel wide on devices without geometry shaders.
+    pub line_width: f32,
+    render_pass: Arc<RenderPass>,
+    pipeline: Arc<GraphicsPipeline>,
+    /// Lines expanded to quads `line_width` across, when the device has geometry shaders.
+    wide_pipeline: Option<Arc<GraphicsPipeline>>,
+    rect_pipeline: Arc<GraphicsPipeline>,
+    shape_pipeline: Arc<GraphicsPipeline>,
+    quad_pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    pool: CpuBufferPool<LineVertex>,
+    shape_pool: CpuBufferPool<ShapeVertex>,
+    quad_pool: CpuBufferPool<QuadInstance>,
+    framebuffers: Vec<Arc<Framebuffer>>,
+}
+
+impl Overlay {
+    pub fn new(dev: Arc<Device>, format: Format) -> Self {
+        let render_pass = vulkano::single_pass_renderpass!( dev.clone(),
+                                                            attachments: { color: { load: Load, store: Store, format: format, samples: 1,}},
+                                                            pass: { color: [color], depth_stencil: {} }).unwrap();
+        let vs = vs::load(dev.clone()).unwrap();
+        let fs = fs::load(dev.clone()).unwrap();
+        let pipeline = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new().vertex::<LineVertex>())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new().topology(PrimitiveTopology::LineList))
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .color_blend_state(ColorBlendState::new(1).blend_alpha())
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(dev.clone()).unwrap();
+        let wide_pipeline = dev.enabled_features().geometry_shader.then(|| {
+            let vs = wide_vs::load(dev.clone()).unwrap();
+            let gs = wide_gs::load(dev.clone()).unwrap();
+            GraphicsPipeline::start()
+                .vertex_input_state(BuffersDefinition::new().vertex::<LineVertex>())
+                .vertex_shader(vs.entry_point("main").unwrap(), ())
+                .input_assembly_state(InputAssemblyState::new().topology(PrimitiveTopology::LineList))
+                .geometry_shader(gs.entry_point("main").unwrap(), ())
+                .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+                .fragment_shader(fs.entry_point("main").unwrap(), ())
+                .color_blend_state(ColorBlendState::new(1).blend_alpha())
+                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                .build(dev.clone()).unwrap()
+        });
+        let rect_vs = rect_vs::load(dev.clone()).unwrap();
+        let rect_fs = rect_fs::load(dev.clone()).unwrap();
+        let rect_pipeline = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new())
+            .vertex_shader(rect_vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(rect_fs.entry_point("main").unwrap(), ())
+            .color_blend_state(ColorBlendState::new(1).blend_alpha())
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(dev.clone()).unwrap();
+        let shape_vs = shape_vs::load(dev.clone()).unwrap();
+        let shape_pipeline = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new().vertex::<ShapeVertex>())
+            .vertex_shader(shape_vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .color_blend_state(ColorBlendState::new(1).blend_alpha())
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(dev.clone()).unwrap();
+        let quad_vs = quad_vs::load(dev.clone()).unwrap();
+        let quad_fs = quad_fs::load(dev.clone()).unwrap();
+        let quad_pipeline = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new().instance::<QuadInstance>())
+            .vertex_shader(quad_vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(quad_fs.entry_point("main").unwrap(), ())
+            .color_blend_state(ColorBlendState::new(1).blend_alpha())
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(dev.clone()).unwrap();
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo::simple_repeat_linear_no_mipmap()).unwrap();
+        Overlay { line_width: 2.0, render_pass, pipeline, wide_pipeline, rect_pipeline, shape_pipeline, quad_pipeline, sampler, pool: CpuBufferPool::vertex_buffer(dev.clone()),
+                  shape_pool: CpuBufferPool::vertex_buffer(dev.clone()), quad_pool: CpuBufferPool::vertex_buffer(dev), framebuffers: Vec::new() }
+    }
+
+    pub fn resize(&mut self, images: &[Arc<SwapchainImage<Window>>]) {
+        self.framebuffers = images.iter().map(|image| {
+            Framebuffer::new(self.render_pass.clone(), FramebufferCreateInfo {
+                attachments: vec![ImageView::new_default(image.clone()).unwrap()], ..Default::default() }).unwrap()
+        }).collect();
+    }
+
+    #[profiling::function]
+    pub fn draw(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, image_num: usize,
+                viewport: &Viewport, view_proj: Mat4, lines: &[LineVertex], rects: &[(Arc<dyn ImageViewAbstract>, [f32; 4])], shapes: &[ShapeVertex], quads: &[UiQuad]) {
+        if lines.is_empty() && rects.is_empty() && shapes.is_empty() && quads.is_empty() { return; }
+        builder.begin_render_pass(self.framebuffers[image_num].clone(), SubpassContents::Inline, vec![ClearValue::None]).unwrap()
+            .set_viewport(0, [viewport.clone()]);
+        if !rects.is_empty() {
+            let layout = self.rect_pipeline.layout().set_layouts().get(0).unwrap();
+            builder.bind_pipeline_graphics(self.rect_pipeline.clone());
+            for (texture, rect) in rects {
+                let set = PersistentDescriptorSet::new(layout.clone(), [WriteDescriptorSet::image_view_sampler(0, texture.clone(), self.sampler.clone())]).unwrap();
+                builder.bind_descriptor_sets(PipelineBindPoint::Graphics, self.rect_pipeline.layout().clone(), 0, set)
+                    .push_constants(self.rect_pipeline.layout().clone(), 0, rect_vs::ty::PushConstants { rect: *rect })
+                    .draw(6, 1, 0, 0).unwrap();
+            }
+        }
+        if !shapes.is_empty() {
+            builder.bind_pipeline_graphics(self.shape_pipeline.clone())
+                .push_constants(self.shape_pipeline.layout().clone(), 0, shape_vs::ty::PushConstants { screen: viewport.dimensions })
+                .bind_vertex_buffers(0, self.shape_pool.chunk(shapes.iter().cloned()).unwrap())
+                .draw(shapes.len() as u32, 1, 0, 0).unwrap();
+        }
+        if !quads.is_empty() {
+            //in order, one draw per run of the same texture
+            let mut batches: Vec<(Arc<Texture>, Vec<QuadInstance>)> = Vec::new();
+            for q in quads {
+                let (i_sdf, i_outline, i_glow) = match q.sdf {
+                    Some(s) => ([s.px_range.max(1e-3), s.effects.outline_width, s.effects.glow_width, 0.0], s.effects.outline, s.effects.glow),
+                    None => ([0.0; 4], [0.0; 4], [0.0; 4]),
+                };
+                let instance = QuadInstance { i_rect: q.rect, i_uv: q.uv, i_color: q.color, i_sdf, i_outline, i_glow };
+                match batches.last_mut() {
+                    Some((t, instances)) if Arc::ptr_eq(t, &q.texture) => instances.push(instance),
+                    _ => batches.push((q.texture.clone(), vec![instance])),
+                }
+            }
+            let layout = self.quad_pipeline.layout().set_layouts().get(0).unwrap();
+            builder.bind_pipeline_graphics(self.quad_pipeline.clone())
+                .push_constants(self.quad_pipeline.layout().clone(), 0, quad_vs::ty::PushConstants { screen: viewport.dimensions });
+            for (texture, instances) in batches {
+                let set = PersistentDescriptorSet::new(layout.clone(), [WriteDescriptorSet::image_view_sampler(0, texture.view.clone(), self.sampler.clone())]).unwrap();
+                let count = instances.len() as u32;
+                builder.bind_descriptor_sets(PipelineBindPoint::Graphics, self.quad_pipeline.layout().clone(), 0, set)
+                    .bind_vertex_buffers(0, self.quad_pool.chunk(instances).unwrap())
+                    .draw(6, count, 0, 0).unwrap();
+            }
+        }
+        if !lines.is_empty() {
+            let vertices = self.pool.chunk(lines.iter().cloned()).unwrap();
+            match &self.wide_pipeline {
+                Some(wide) => {
+                    let half_width = [self.line_width / viewport.dimensions[0], self.line_width / viewport.dimensions[1]];
+                    let pc = wide_vs::ty::PushConstants { view_proj: view_proj.to_cols_array_2d(), half_width };
+                    builder.bind_pipeline_graphics(wide.clone())
+                        .push_constants(wide.layout().clone(), 0, pc);
+                }
+                None => {
+                    let pc = vs::ty::PushConstants { view_proj: view_proj.to_cols_array_2d() };
+                    builder.bind_pipeline_graphics(self.pipeline.clone())
+                        .push_constants(self.pipeline.layout().clone(), 0, pc);
+                }
+            }
+            builder.bind_vertex_buffers(0, vertices)
+                .draw(lines.len() as u32, 1, 0, 0).unwrap();
+        }
+        builder.end_render_pass().unwrap();
+    }
+}
@@ -0,0 +1,352 @@
+use glam::{ Mat4, Vec3 };
+use serde_json::{ json, Value };
+use std::path::{ Path, PathBuf };
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicU32, Ordering };
+use crate::billboard::Billboard;
+use crate::scene::Scene;
+use crate::texture::Texture;
+use crate::time::Time;
+use crate::tween::{ add_key, Curve, Ease, Lerp };
+
+/// Emitters with at least this many particles simulate spread over threads.
+const PARALLEL_PARTICLES: usize = 4096;
+
+/// Where particles start and which way they go, in the entity's space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EmitterShape {
+    /// From the origin, every way.
+    Point,
+    /// From inside the sphere, outwards.
+    Sphere { radius: f32 },
+    /// From the origin, within `angle` radians of +y.
+    Cone { angle: f32 },
+    /// From inside the box, every way.
+    Box { half_extents: Vec3 },
+}
+
+/// What gpu particles do where they go behind the depth buffer, the visible scene's surface.
+/// The cpu ones don't see it and pass through.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParticleCollision {
+    None,
+    /// Off the surface, keeping `restitution` of the speed into it and losing `friction` of
+    /// the speed along it.
+    Bounce { restitution: f32, friction: f32 },
+    /// Die where they hit; sparks and rain.
+    Kill,
+}
+
+/// What an emitter emits, shared between the emitters playing it. The over life curves are
+/// keyed by age as a fraction of lifetime, 0 when born and 1 when it dies.
+#[derive(Clone, Debug)]
+pub struct ParticleEffect {
+    pub name: String,
+    /// Particles per second.
+    pub rate: f32,
+    /// Particles all at once when it starts playing.
+    pub burst: u32,
+    /// It stops emitting while this many are alive. Sizes a gpu emitter's buffers.
+    pub max_particles: usize,
+    /// Seconds it emits for; forever when None.
+    pub duration: Option<f32>,
+    /// Seconds, each particle's picked between the two.
+    pub lifetime: [f32; 2],
+    /// Meters per second at birth, picked between the two.
+    pub speed: [f32; 2],
+    pub shape: EmitterShape,
+    /// Meters per second squared, world space.
+    pub gravity: Vec3,
+    /// Fraction of velocity lost per second.
+    pub drag: f32,
+    pub collision: ParticleCollision,
+    /// Multiplies the velocity.
+    pub speed_over_life: Curve<f32>,
+    /// Meters across.
+    pub size_over_life: Curve<f32>,
+    /// Multiplies the texture.
+    pub color_over_life: Curve<[f32; 4]>,
+    /// Relative to the working directory like the other assets; white squares without.
+    pub texture: Option<PathBuf>,
+}
+
+impl ParticleEffect {
+    pub fn new(name: &str) -> Self {
+        ParticleEffect { name: name.to_string(), rate: 10.0, burst: 0, max_particles: 1000, duration: None, lifetime: [1.0, 1.0], speed: [1.0, 1.0],
+                         shape: EmitterShape::Point, gravity: Vec3::ZERO, drag: 0.0, collision: ParticleCollision::None, speed_over_life: Curve::new(1.0), size_over_life: Curve::new(0.1),
+                         color_over_life: Curve::new([1.0; 4]), texture: None }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Particle {
+    position: Vec3,
+    velocity: Vec3,
+    age: f32,
+    lifetime: f32,
+}
+
+struct Rng(u32);
+
+impl Rng {
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    fn range(&mut self, [min, max]: [f32; 2]) -> f32 { min + (max - min) * self.next() }
+
+    fn direction(&mut self) -> Vec3 {
+        let z = self.next() * 2.0 - 1.0;
+        let a = self.next() * std::f32::consts::TAU;
+        let r = (1.0 - z * z).sqrt();
+        Vec3::new(r * a.cos(), r * a.sin(), z)
+    }
+}
+
+/// Where an emitter is in its effect's run and the fraction of a particle it owes from the last
+/// frames. Shared with the gpu emitters.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Emission {
+    time: f32,
+    owed: f32,
+}
+
+impl Emission {
+    /// Particles to emit over the next `dt` seconds, the burst on the first. Clears `playing`
+    /// once past the effect's duration.
+    pub(crate) fn step(&mut self, effect: &ParticleEffect, dt: f32, playing: &mut bool) -> usize {
+        if !*playing { return 0; }
+        let mut count = 0;
+        if self.time == 0.0 { count += effect.burst as usize; }
+        let end = effect.duration.map_or(f32::INFINITY, |d| d.max(0.0));
+        //only the part of the step before the end emits
+        let emitting = (end - self.time).clamp(0.0, dt);
+        self.owed += effect.rate.max(0.0) * emitting;
+        count += self.owed as usize;
+        self.owed = self.owed.fract();
+        self.time += dt;
+        if self.time >= end { *playing = false; }
+        count
+    }
+}
+
+/// So emitters playing the same effect don't emit the same particles.
+static SEED: AtomicU32 = AtomicU32::new(0x2545_f491);
+
+/// Plays a particle effect from an entity. Particles are in world space once emitted, so they
+/// trail behind a moving entity.
+pub struct ParticleEmitter {
+    pub effect: Arc<ParticleEffect>,
+    pub texture: Arc<Texture>,
+    /// Emitting; particles already out live on either way.
+    pub playing: bool,
+    particles: Vec<Particle>,
+    emission: Emission,
+    rng: Rng,
+}
+
+impl ParticleEmitter {
+    /// Starts playing, with its burst on the first update.
+    pub fn new(effect: Arc<ParticleEffect>, texture: Arc<Texture>) -> Self {
+        let rng = Rng(SEED.fetch_add(0x9e37_79b9, Ordering::Relaxed) | 1);
+        ParticleEmitter { effect, texture, playing: true, particles: Vec::new(), emission: Emission::default(), rng }
+    }
+
+    /// From the start again, burst and all.
+    pub fn play(&mut self) {
+        self.playing = true;
+        self.emission = Emission::default();
+    }
+
+    pub fn stop(&mut self) { self.playing = false; }
+
+    /// Stopped and without particles; the entity can go.
+    pub fn is_finished(&self) -> bool { !self.playing && self.particles.is_empty() }
+
+    pub fn count(&self) -> usize { self.particles.len() }
+
+    fn emit(&mut self, transform: &Mat4) {
+        let effect = &self.effect;
+        let rng = &mut self.rng;
+        let (local, dir) = match effect.shape {
+            EmitterShape::Point => (Vec3::ZERO, rng.direction()),
+            EmitterShape::Sphere { radius } => { let d = rng.direction(); (d * radius * rng.next().cbrt(), d) }
+            EmitterShape::Cone { angle } => {
+                let (z, a) = (1.0 - rng.next() * (1.0 - angle.cos()), rng.next() * std::f32::consts::TAU);
+                let r = (1.0 - z * z).max(0.0).sqrt();
+                (Vec3::ZERO, Vec3::new(r * a.cos(), z, r * a.sin()))
+            }
+            EmitterShape::Box { half_extents } => ((Vec3::new(rng.next(), rng.next(), rng.next()) * 2.0 - Vec3::ONE) * half_extents, rng.direction()),
+        };
+        let velocity = transform.transform_vector3(dir).normalize_or_zero() * rng.range(effect.speed);
+        let lifetime = rng.range(effect.lifetime).max(1e-3);
+        self.particles.push(Particle { position: transform.transform_point3(local), velocity, age: 0.0, lifetime });
+    }
+
+    /// Ages and moves the particles and emits new ones from `transform`.
+    pub fn update(&mut self, dt: f32, transform: &Mat4) {
+        let effect = self.effect.clone();
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        //no job system to hand it to, so big emitters split across scoped threads
+        if self.particles.len() >= PARALLEL_PARTICLES && threads > 1 {
+            let (chunk, shared) = (self.particles.len().div_ceil(threads), &*effect);
+            std::thread::scope(|s| for part in self.particles.chunks_mut(chunk) { s.spawn(move || simulate(part, shared, dt)); });
+        } else {
+            simulate(&mut self.particles, &effect, dt);
+        }
+        self.particles.retain(|p| p.age < p.lifetime);
+
+        let count = self.emission.step(&effect, dt, &mut self.playing);
+        for _ in 0..count.min(effect.max_particles.saturating_sub(self.particles.len())) { self.emit(transform); }
+    }
+
+    /// A billboard per particle, for the renderer to draw with the rest.
+    pub fn billboards(&self) -> impl Iterator<Item = Billboard> + '_ {
+        self.particles.iter().map(|p| {
+            let t = p.age / p.lifetime;
+            Billboard { color: self.effect.color_over_life.sample(t), ..Billboard::new(self.texture.clone(), p.position, self.effect.size_over_life.sample(t)) }
+        })
+    }
+}
+
+fn simulate(particles: &mut [Particle], effect: &ParticleEffect, dt: f32) {
+    let damping = (1.0 - effect.drag * dt).max(0.0);
+    for p in particles {
+        p.age += dt;
+        p.velocity = (p.velocity + effect.gravity * dt) * damping;
+        p.position += p.velocity * effect.speed_over_life.sample(p.age / p.lifetime) * dt;
+    }
+}
+
+/// Steps every entity's emitter. Once a frame, after whatever moves the entities.
+#[profiling::function]
+pub fn update(time: &Time, scene: &mut Scene) {
+    for entity in &mut scene.entities {
+        if let Some(emitter) = &mut entity.particles { emitter.update(time.delta, &entity.transform); }
+    }
+}
+
+fn number(v: &Value, what: &str) -> Result<f32, String> { v.as_f64().map(|n| n as f32).ok_or_else(|| format!("{} isn't a number", what)) }
+
+/// Through the shortest text that reads back the same, so 0.1 saves as 0.1 rather than the f32's exact f64.
+fn to_number(x: f32) -> Value { Value::from(x.to_string().parse::<f64>().unwrap()) }
+
+fn to_numbers(xs: &[f32]) -> Value { xs.iter().map(|&x| to_number(x)).collect() }
+
+fn to_curve<T>(curve: &Curve<T>, value: impl Fn(&T) -> Value) -> Value {
+    curve.keys.iter().map(|k| json!({ "time": to_number(k.time), "value": value(&k.value), "ease": format!("{:?}", k.ease) })).collect()
+}
+
+/// `default` when the field isn't there.
+fn field<T>(v: &Value, default: T, read: impl Fn(&Value) -> Result<T, String>) -> Result<T, String> {
+    if v.is_null() { Ok(default) } else { read(v) }
+}
+
+fn numbers<const N: usize>(v: &Value, what: &str) -> Result<[f32; N], String> {
+    let list = v.as_array().filter(|l| l.len() == N).ok_or_else(|| format!("{} isn't {} numbers", what, N))?;
+    let mut out = [0.0; N];
+    for (o, v) in out.iter_mut().zip(list) { *o = number(v, what)?; }
+    Ok(out)
+}
+
+fn curve<T: Lerp>(v: &Value, what: &str, value: impl Fn(&Value) -> Result<T, String>) -> Result<Curve<T>, String> {
+    let mut curve = None;
+    for key in v.as_array().ok_or_else(|| format!("{} isn't a list of keys", what))? {
+        let ease = match key["ease"].as_str() {
+            Some(name) => Ease::from_name(name).ok_or_else(|| format!("{}: unknown ease {:?}", what, name))?,
+            None => Ease::Linear,
+        };
+        add_key(&mut curve, number(&key["time"], what)?, value(&key["value"])?, ease);
+    }
+    curve.ok_or_else(|| format!("{} has no keys", what))
+}
+
+/// Effects as json, a list of objects:
+///
+/// `name`, `texture` path, `rate` per second, `burst` count, `max` particles, `duration` seconds,
+/// `lifetime` and `speed` as [min, max], `shape` as {"type": "point"|"sphere"|"cone"|"box"} with
+/// its `radius`, `angle` or `half_extents`, `gravity` [x, y, z], `drag` fraction, `collision` as
+/// {"type": "none"|"kill"|"bounce"} with `restitution` and `friction`, and the over life curves
+/// `speed_over_life`, `size` and `color` as lists of {"time", "value", "ease"}.
+///
+/// Everything but `name` can be left out for `ParticleEffect::new`'s. Eases by name, see `Ease`.
+pub fn parse(text: &str) -> Result<Vec<ParticleEffect>, String> {
+    let json: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    json.as_array().ok_or("expected a list of effects")?.iter().map(|e| -> Result<ParticleEffect, String> {
+        let name = e["name"].as_str().ok_or("effect without a name")?;
+        let err = |what: String| format!("effect {:?}: {}", name, what);
+        let d = ParticleEffect::new(name);
+        let shape = match e["shape"]["type"].as_str() {
+            None => d.shape,
+            Some("point") => EmitterShape::Point,
+            Some("sphere") => EmitterShape::Sphere { radius: number(&e["shape"]["radius"], "shape.radius").map_err(err)? },
+            Some("cone") => EmitterShape::Cone { angle: number(&e["shape"]["angle"], "shape.angle").map_err(err)? },
+            Some("box") => EmitterShape::Box { half_extents: numbers::<3>(&e["shape"]["half_extents"], "shape.half_extents").map_err(err)?.into() },
+            Some(s) => return Err(err(format!("unknown shape {:?}", s))),
+        };
+        let collision = match e["collision"]["type"].as_str() {
+            None | Some("none") => ParticleCollision::None,
+            Some("kill") => ParticleCollision::Kill,
+            Some("bounce") => ParticleCollision::Bounce { restitution: number(&e["collision"]["restitution"], "collision.restitution").map_err(err)?,
+                                                          friction: number(&e["collision"]["friction"], "collision.friction").map_err(err)? },
+            Some(s) => return Err(err(format!("unknown collision {:?}", s))),
+        };
+        Ok(ParticleEffect {
+            name: name.to_string(),
+            rate: field(&e["rate"], d.rate, |v| number(v, "rate")).map_err(err)?,
+            burst: field(&e["burst"], d.burst as f32, |v| number(v, "burst")).map_err(err)? as u32,
+            max_particles: field(&e["max"], d.max_particles as f32, |v| number(v, "max")).map_err(err)? as usize,
+            duration: field(&e["duration"], d.duration, |v| number(v, "duration").map(Some)).map_err(err)?,
+            lifetime: field(&e["lifetime"], d.lifetime, |v| numbers(v, "lifetime")).map_err(err)?,
+            speed: field(&e["speed"], d.speed, |v| numbers(v, "speed")).map_err(err)?,
+            shape,
+            gravity: field(&e["gravity"], d.gravity.to_array(), |v| numbers(v, "gravity")).map_err(err)?.into(),
+            drag: field(&e["drag"], d.drag, |v| number(v, "drag")).map_err(err)?,
+            collision,
+            speed_over_life: field(&e["speed_over_life"], d.speed_over_life, |v| curve(v, "speed_over_life", |k| number(k, "speed_over_life"))).map_err(err)?,
+            size_over_life: field(&e["size"], d.size_over_life, |v| curve(v, "size", |k| number(k, "size"))).map_err(err)?,
+            color_over_life: field(&e["color"], d.color_over_life, |v| curve(v, "color", |k| numbers(k, "color"))).map_err(err)?,
+            texture: e["texture"].as_str().map(PathBuf::from),
+        })
+    }).collect()
+}
+
+/// The effects as `parse` reads them, every field written out.
+pub fn to_json(effects: &[ParticleEffect]) -> Value {
+    effects.iter().map(|e| {
+        let shape = match e.shape {
+            EmitterShape::Point => json!({ "type": "point" }),
+            EmitterShape::Sphere { radius } => json!({ "type": "sphere", "radius": to_number(radius) }),
+            EmitterShape::Cone { angle } => json!({ "type": "cone", "angle": to_number(angle) }),
+            EmitterShape::Box { half_extents } => json!({ "type": "box", "half_extents": to_numbers(&half_extents.to_array()) }),
+        };
+        let collision = match e.collision {
+            ParticleCollision::None => json!({ "type": "none" }),
+            ParticleCollision::Kill => json!({ "type": "kill" }),
+            ParticleCollision::Bounce { restitution, friction } => json!({ "type": "bounce", "restitution": to_number(restitution), "friction": to_number(friction) }),
+        };
+        let mut effect = json!({
+            "name": e.name, "rate": to_number(e.rate), "burst": e.burst, "max": e.max_particles,
+            "lifetime": to_numbers(&e.lifetime), "speed": to_numbers(&e.speed), "shape": shape,
+            "gravity": to_numbers(&e.gravity.to_array()), "drag": to_number(e.drag), "collision": collision,
+            "speed_over_life": to_curve(&e.speed_over_life, |&v| to_number(v)), "size": to_curve(&e.size_over_life, |&v| to_number(v)),
+            "color": to_curve(&e.color_over_life, |v| to_numbers(v)),
+        });
+        if let Some(duration) = e.duration { effect["duration"] = to_number(duration); }
+        if let Some(texture) = &e.texture { effect["texture"] = texture.to_string_lossy().into(); }
+        effect
+    }).collect()
+}
+
+/// A json file of effects as `parse` describes.
+pub fn load(path: impl AsRef<Path>) -> Vec<ParticleEffect> {
+    let text = std::fs::read_to_string(path.as_ref()).unwrap_or_else(|e| panic!("failed loading {:?}: {}", path.as_ref(), e));
+    parse(&text).unwrap_or_else(|e| panic!("{:?} {}", path.as_ref(), e))
+}
+
+pub fn save(effects: &[ParticleEffect], path: impl AsRef<Path>) -> std::io::Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(&to_json(effects)).unwrap())
+}
@@ -0,0 +1,312 @@
+use rapier3d::prelude::*;
+use rapier3d::na::{ Quaternion, UnitQuaternion };
+use glam::{ Mat4, Quat, Vec3 };
+use std::collections::HashMap;
+use crate::scene::{ Scene, Entity, EntityId };
+use crate::bvh::Ray as SceneRay;
+use crate::time::Time;
+use crate::debug_draw::DebugDraw;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BodyKind {
+    /// Moved by the simulation; its transform is written back to the entity.
+    Dynamic,
+    /// Follows the entity's transform and pushes dynamic bodies out of the way.
+    Kinematic,
+    /// Doesn't move unless the entity is moved by hand.
+    Fixed,
+}
+
+/// Collision shape in world units around the entity's origin. Only meshes take the entity's scale.
+#[derive(Clone, Copy, Debug)]
+pub enum ColliderShape {
+    Ball { radius: f32 },
+    Cuboid { half_extents: Vec3 },
+    /// Along y.
+    Capsule { half_height: f32, radius: f32 },
+    /// The entity's own mesh as a triangle mesh. Best left to fixed bodies.
+    Mesh,
+}
+
+/// Rigid body and collider component, see `Physics::insert`.
+#[derive(Clone, Copy, Debug)]
+pub struct PhysicsBody {
+    pub kind: BodyKind,
+    pub shape: ColliderShape,
+    pub density: f32,
+    pub friction: f32,
+    pub restitution: f32,
+}
+
+impl PhysicsBody {
+    pub fn new(kind: BodyKind, shape: ColliderShape) -> Self { PhysicsBody { kind, shape, density: 1.0, friction: 0.5, restitution: 0.0 } }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PhysicsHit {
+    pub entity: EntityId,
+    pub distance: f32,
+    /// Where the ray hit; for shape casts, where the shape's origin was at the time.
+    pub point: Vec3,
+    pub normal: Vec3,
+}
+
+/// What `Physics` keeps per entity.
+struct Registered {
+    body: PhysicsBody,
+    handle: RigidBodyHandle,
+    /// Transform last synced either way, to notice entities moved from outside.
+    synced: Mat4,
+    /// Where the body was before the last step, drawn towards where it is by `alpha`.
+    previous: Isometry<Real>,
+}
+
+const AWAKE: [f32; 4] = [0.2, 1.0, 0.4, 1.0];
+const SLEEPING: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+const KINEMATIC: [f32; 4] = [0.3, 0.6, 1.0, 1.0];
+const FIXED: [f32; 4] = [0.9, 0.9, 0.9, 0.6];
+const CONTACT: [f32; 4] = [1.0, 0.2, 0.2, 1.0];
+const JOINT: [f32; 4] = [1.0, 0.8, 0.1, 1.0];
+
+/// Rapier world mirroring the scene's entities that have a `PhysicsBody`. Steps at a fixed rate
+/// on the scaled clock, so pausing `Time` pauses the simulation. Entities that leave the scene
+/// leave the world at the next `update`.
+///
+/// Dynamic entities are drawn between their last two steps by what's left in the accumulator,
+/// so they move smoothly whatever the frame rate; that puts them up to a step behind the
+/// simulation, which queries still see exactly.
+pub struct Physics {
+    /// Colliders, contacts and joints through the debug draw.
+    pub show_debug: bool,
+    pub gravity: Vec3,
+    /// Seconds per step.
+    pub timestep: f32,
+    /// Steps a frame may take at most; beyond that the simulation slows down instead.
+    pub max_steps: u32,
+    /// Draw dynamic bodies between steps rather than where the last one left them.
+    pub interpolate: bool,
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    pipeline: PhysicsPipeline,
+    islands: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    impulse_joints: ImpulseJointSet,
+    multibody_joints: MultibodyJointSet,
+    ccd: CCDSolver,
+    queries: QueryPipeline,
+    registered: HashMap<EntityId, Registered>,
+    accumulator: f32,
+}
+
+impl Physics {
+    pub fn new() -> Self {
+        Physics { show_debug: false, gravity: Vec3::new(0.0, -9.81, 0.0), timestep: 1.0 / 60.0, max_steps: 4, interpolate: true,
+                  bodies: RigidBodySet::new(), colliders: ColliderSet::new(), pipeline: PhysicsPipeline::new(), islands: IslandManager::new(),
+                  broad_phase: BroadPhase::new(), narrow_phase: NarrowPhase::new(), impulse_joints: ImpulseJointSet::new(),
+                  multibody_joints: MultibodyJointSet::new(), ccd: CCDSolver::new(), queries: QueryPipeline::new(),
+                  registered: HashMap::new(), accumulator: 0.0 }
+    }
+
+    /// Adds or replaces `entity`'s body, starting where the entity is.
+    pub fn insert(&mut self, entity: &Entity, body: PhysicsBody) {
+        self.remove(entity.id);
+        let (scale, position) = isometry(&entity.transform);
+        let rigid_body = match body.kind {
+            BodyKind::Dynamic => RigidBodyBuilder::dynamic(),
+            BodyKind::Kinematic => RigidBodyBuilder::kinematic_position_based(),
+            BodyKind::Fixed => RigidBodyBuilder::fixed(),
+        }.position(position).build();
+        let handle = self.bodies.insert(rigid_body);
+        let shape = match body.shape {
+            ColliderShape::Mesh => match &entity.mesh {
+                Some(mesh) => {
+                    let vertices: Vec<Point<Real>> = mesh.vertices.iter().map(|v| { let p = Vec3::from(v.position) * scale; point![p.x, p.y, p.z] }).collect();
+                    let indices = (0..vertices.len() as u32 / 3).map(|t| [t * 3, t * 3 + 1, t * 3 + 2]).collect();
+                    SharedShape::trimesh(vertices, indices)
+                }
+                None => SharedShape::ball(0.5),
+            },
+            shape => shared_shape(&shape),
+        };
+        let collider = ColliderBuilder::new(shape).density(body.density).friction(body.friction).restitution(body.restitution)
+            .user_data(entity.id as u128).build();
+        self.colliders.insert_with_parent(collider, handle, &mut self.bodies);
+        self.registered.insert(entity.id, Registered { body, handle, synced: entity.transform, previous: position });
+    }
+
+    pub fn remove(&mut self, id: EntityId) {
+        if let Some(r) = self.registered.remove(&id) {
+            self.bodies.remove(r.handle, &mut self.islands, &mut self.colliders, &mut self.impulse_joints, &mut self.multibody_joints, true);
+        }
+    }
+
+    /// Ties two registered entities together, see rapier's joint builders. None unless both are.
+    pub fn add_joint(&mut self, a: EntityId, b: EntityId, joint: impl Into<GenericJoint>) -> Option<ImpulseJointHandle> {
+        let (a, b) = (self.registered.get(&a)?.handle, self.registered.get(&b)?.handle);
+        Some(self.impulse_joints.insert(a, b, joint, true))
+    }
+
+    pub fn body(&self, id: EntityId) -> Option<&PhysicsBody> { self.registered.get(&id).map(|r| &r.body) }
+
+    pub fn velocity(&self, id: EntityId) -> Vec3 {
+        self.registered.get(&id).map_or(Vec3::ZERO, |r| to_vec3(self.bodies[r.handle].linvel()))
+    }
+
+    pub fn set_velocity(&mut self, id: EntityId, velocity: Vec3) {
+        if let Some(r) = self.registered.get(&id) { self.bodies[r.handle].set_linvel(to_vector(velocity), true); }
+    }
+
+    pub fn apply_impulse(&mut self, id: EntityId, impulse: Vec3) {
+        if let Some(r) = self.registered.get(&id) { self.bodies[r.handle].apply_impulse(to_vector(impulse), true); }
+    }
+
+    /// Once a frame, before the scene's bounds are rebuilt: takes in entities moved from outside,
+    /// steps, and writes dynamic bodies back to their entities.
+    #[profiling::function]
+    pub fn update(&mut self, time: &Time, scene: &mut Scene) {
+        let gone: Vec<EntityId> = self.registered.keys().copied().filter(|&id| scene.get(id).is_none()).collect();
+        for id in gone { self.remove(id); }
+
+        for (&id, r) in self.registered.iter_mut() {
+            let entity = scene.get(id).unwrap();
+            if entity.transform == r.synced { continue; }
+            let (_, position) = isometry(&entity.transform);
+            let body = &mut self.bodies[r.handle];
+            match r.body.kind {
+                BodyKind::Kinematic => body.set_next_kinematic_position(position),
+                _ => { body.set_position(position, true); body.set_linvel(Vector::zeros(), true); body.set_angvel(Vector::zeros(), true); }
+            }
+            //moved by hand means teleported, nothing to draw in between
+            r.previous = position;
+            r.synced = entity.transform;
+        }
+
+        self.accumulator += time.delta;
+        let mut steps = 0;
+        let params = IntegrationParameters { dt: self.timestep, ..Default::default() };
+        let gravity = to_vector(self.gravity);
+        while self.accumulator >= self.timestep && steps < self.max_steps {
+            for r in self.registered.values_mut() { r.previous = *self.bodies[r.handle].position(); }
+            self.pipeline.step(&gravity, &params, &mut self.islands, &mut self.broad_phase, &mut self.narrow_phase, &mut self.bodies,
+                               &mut self.colliders, &mut self.impulse_joints, &mut self.multibody_joints, &mut self.ccd, None, &(), &());
+            self.accumulator -= self.timestep;
+            steps += 1;
+        }
+        //dropped rather than carried over, or a long hitch would keep the simulation catching up
+        if steps == self.max_steps { self.accumulator = self.accumulator.min(self.timestep); }
+        self.queries.update(&self.bodies, &self.colliders);
+        //frames between steps still move interpolated bodies along
+        if steps == 0 && !self.interpolate { return; }
+
+        let alpha = self.alpha();
+        for (&id, r) in self.registered.iter_mut().filter(|(_, r)| r.body.kind == BodyKind::Dynamic) {
+            let entity = scene.get_mut(id).unwrap();
+            let (scale, _, _) = entity.transform.to_scale_rotation_translation();
+            let current = self.bodies[r.handle].position();
+            let position = if self.interpolate { r.previous.lerp_slerp(current, alpha) } else { *current };
+            entity.transform = to_mat4(&position, scale);
+            r.synced = entity.transform;
+        }
+    }
+
+    /// How far into the next step the clock is, 0 to 1; where `interpolate` draws bodies
+    /// between the last step and the one before.
+    pub fn alpha(&self) -> f32 { (self.accumulator / self.timestep).clamp(0.0, 1.0) }
+
+    /// Nearest collider along `ray` within `max_distance`.
+    pub fn raycast(&self, ray: &SceneRay, max_distance: f32) -> Option<PhysicsHit> {
+        let r = Ray::new(point![ray.origin.x, ray.origin.y, ray.origin.z], to_vector(ray.dir));
+        let (handle, hit) = self.queries.cast_ray_and_get_normal(&self.bodies, &self.colliders, &r, max_distance, true, QueryFilter::default())?;
+        Some(PhysicsHit { entity: self.colliders[handle].user_data as EntityId, distance: hit.toi, point: ray.at(hit.toi), normal: to_vec3(&hit.normal) })
+    }
+
+    /// First collider `shape` touches when swept from `origin` along `dir`. Mesh shapes sweep nothing.
+    pub fn shape_cast(&self, shape: &ColliderShape, origin: Vec3, dir: Vec3, max_distance: f32) -> Option<PhysicsHit> {
+        if let ColliderShape::Mesh = shape { return None; }
+        let shape = shared_shape(shape);
+        let dir = dir.normalize();
+        let position = Isometry::translation(origin.x, origin.y, origin.z);
+        let (handle, toi) = self.queries.cast_shape(&self.bodies, &self.colliders, &position, &to_vector(dir), shape.as_ref(), max_distance, true,
+                                                    QueryFilter::default())?;
+        let collider = &self.colliders[handle];
+        Some(PhysicsHit { entity: collider.user_data as EntityId, distance: toi.toi, point: origin + dir * toi.toi,
+                          normal: to_vec3(&(collider.position().rotation * toi.normal1.into_inner())) })
+    }
+
+    /// Collider outlines colored by body state, contact points with their normals, and joints
+    /// from each body to its anchor.
+    pub fn debug_draw(&self, dbg: &mut DebugDraw) {
+        if !self.show_debug { return; }
+        for (_, collider) in self.colliders.iter() {
+            let color = match collider.parent().map(|h| &self.bodies[h]) {
+                Some(b) if b.is_dynamic() => if b.is_sleeping() { SLEEPING } else { AWAKE },
+                Some(b) if b.is_kinematic() => KINEMATIC,
+                _ => FIXED,
+            };
+            let iso = collider.position();
+            let shape = collider.shape();
+            if let Some(ball) = shape.as_ball() {
+                dbg.sphere(to_vec3(&iso.translation.vector), ball.radius, color);
+            } else if let Some(cuboid) = shape.as_cuboid() {
+                dbg.obb(&to_mat4(iso, to_vec3(&cuboid.half_extents) * 2.0), color);
+            } else if let Some(capsule) = shape.as_capsule() {
+                let (a, b) = (to_vec3(&(iso * capsule.segment.a).coords), to_vec3(&(iso * capsule.segment.b).coords));
+                let axis = (b - a).normalize_or_zero();
+                let (u, v) = axis.any_orthonormal_pair();
+                for end in [a, b] { dbg.sphere(end, capsule.radius, color); }
+                for side in [u, -u, v, -v] { dbg.line(a + side * capsule.radius, b + side * capsule.radius, color); }
+            } else if let Some(mesh) = shape.as_trimesh() {
+                let p = |i: u32| to_vec3(&(iso * mesh.vertices()[i as usize]).coords);
+                for t in mesh.indices() {
+                    dbg.line(p(t[0]), p(t[1]), color);
+                    dbg.line(p(t[1]), p(t[2]), color);
+                    dbg.line(p(t[2]), p(t[0]), color);
+                }
+            }
+        }
+        for pair in self.narrow_phase.contact_pairs().filter(|p| p.has_any_active_contact) {
+            for manifold in &pair.manifolds {
+                let normal = to_vec3(&manifold.data.normal);
+                for contact in &manifold.data.solver_contacts {
+                    let p = to_vec3(&contact.point.coords);
+                    dbg.sphere(p, 0.02, CONTACT);
+                    dbg.ray(p, normal * 0.25, CONTACT);
+                }
+            }
+        }
+        for (_, joint) in self.impulse_joints.iter() {
+            let (b1, b2) = (self.bodies[joint.body1].position(), self.bodies[joint.body2].position());
+            let anchor1 = to_vec3(&(b1 * joint.data.local_frame1).translation.vector);
+            let anchor2 = to_vec3(&(b2 * joint.data.local_frame2).translation.vector);
+            dbg.line(to_vec3(&b1.translation.vector), anchor1, JOINT);
+            dbg.line(to_vec3(&b2.translation.vector), anchor2, JOINT);
+            dbg.line(anchor1, anchor2, JOINT);
+            dbg.sphere(anchor1, 0.03, JOINT);
+        }
+    }
+}
+
+fn shared_shape(shape: &ColliderShape) -> SharedShape {
+    match *shape {
+        ColliderShape::Ball { radius } => SharedShape::ball(radius),
+        ColliderShape::Cuboid { half_extents: h } => SharedShape::cuboid(h.x, h.y, h.z),
+        ColliderShape::Capsule { half_height, radius } => SharedShape::capsule_y(half_height, radius),
+        ColliderShape::Mesh => unreachable!("meshes are built from their entity"),
+    }
+}
+
+fn to_vector(v: Vec3) -> Vector<Real> { vector![v.x, v.y, v.z] }
+fn to_vec3(v: &Vector<Real>) -> Vec3 { Vec3::new(v.x, v.y, v.z) }
+
+/// Rapier has no scale, so it is split off and kept on the entity's side.
+fn isometry(m: &Mat4) -> (Vec3, Isometry<Real>) {
+    let (scale, r, t) = m.to_scale_rotation_translation();
+    let rotation = UnitQuaternion::from_quaternion(Quaternion::new(r.w, r.x, r.y, r.z));
+    (scale, Isometry::from_parts(Translation::new(t.x, t.y, t.z), rotation))
+}
+
+fn to_mat4(iso: &Isometry<Real>, scale: Vec3) -> Mat4 {
+    let q = iso.rotation;
+    Mat4::from_scale_rotation_translation(scale, Quat::from_xyzw(q.i, q.j, q.k, q.w), to_vec3(&iso.translation.vector))
+}
@@ -0,0 +1,58 @@
+use vulkano::{ device::Device,
+               buffer::{ BufferUsage, CpuAccessibleBuffer },
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               image::{ AttachmentImage, ImageUsage, view::ImageView },
+               format::Format };
+use std::sync::Arc;
+use crate::scene::EntityId;
+
+pub const ID_FORMAT: Format = Format::R32_UINT;
+
+/// Owns the R32_UINT id attachment every entity writes its `EntityId` into, and a host buffer it
+/// gets copied to at the end of a frame. `pick` reads the copy from the last finished frame, so
+/// results lag the screen by one frame but never stall the gpu.
+/// The image is also storage so `SelectionOutline` can read this frame's ids on the gpu.
+pub struct Picker {
+    image: Arc<AttachmentImage>,
+    readback: Arc<CpuAccessibleBuffer<[u32]>>,
+    extent: [u32; 2],
+    pub enabled: bool,
+}
+
+impl Picker {
+    pub fn new(dev: Arc<Device>, extent: [u32; 2]) -> Self {
+        let image = AttachmentImage::with_usage(dev.clone(), extent, ID_FORMAT,
+            ImageUsage { color_attachment: true, transfer_source: true, storage: true, ..ImageUsage::none() })
+            .expect("failed id image creation");
+        let readback = CpuAccessibleBuffer::from_iter(dev, BufferUsage::transfer_destination(), true,
+            (0..extent[0] * extent[1]).map(|_| 0u32))
+            .expect("failed id readback buffer creation");
+        Picker { image, readback, extent, enabled: true }
+    }
+
+    pub fn extent(&self) -> [u32; 2] { self.extent }
+
+    pub fn view(&self) -> Arc<ImageView<AttachmentImage>> { ImageView::new_default(self.image.clone()).unwrap() }
+
+    /// Must be recorded after the render pass writing the id attachment has ended.
+    #[profiling::function]
+    pub fn record(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        if !self.enabled { return; }
+        builder.copy_image_to_buffer(self.image.clone(), self.readback.clone()).unwrap();
+    }
+
+    /// Same as `pick`, for a cursor over a `window` sized surface the ids are stretched across.
+    pub fn pick_window(&self, cursor: [u32; 2], window: [u32; 2]) -> Option<EntityId> {
+        self.pick(cursor[0] * self.extent[0] / window[0].max(1), cursor[1] * self.extent[1] / window[1].max(1))
+    }
+
+    pub fn pick(&self, x: u32, y: u32) -> Option<EntityId> {
+        if !self.enabled || x >= self.extent[0] || y >= self.extent[1] { return None; }
+        //still locked by the gpu means no frame has finished since the last resize
+        let ids = self.readback.read().ok()?;
+        match ids[(y * self.extent[0] + x) as usize] {
+            0 => None,
+            id => Some(id),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use vulkano::{ command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               image::{ AttachmentImage, view::ImageView } };
+use glam::{ Vec3, Vec4, Mat4 };
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::scene::{ Scene, EntityId };
+use crate::camera::View;
+use crate::culling::Frustum;
+use crate::renderer::{ Renderer, Target };
+
+/// Makes an entity's mesh (usually `Mesh::quad`, facing +z) show a secondary view of the scene.
+#[derive(Clone, Copy, Debug)]
+pub enum Portal {
+    Mirror,
+    /// Looking into the front of this surface shows what is in front of `destination`, as if the
+    /// viewer stepped through it.
+    Portal { destination: Mat4 },
+}
+
+fn plane(transform: &Mat4) -> Vec4 {
+    let n = transform.transform_vector3(Vec3::Z).normalize();
+    n.extend(-n.dot(transform.w_axis.truncate()))
+}
+
+/// Swaps the near plane for `clip` (view space, camera on its negative side) so geometry between
+/// the virtual camera and the surface it looks through gets clipped. Lengyel's oblique frustum,
+/// for 0..1 depth and our flipped y.
+fn oblique(proj: Mat4, clip: Vec4) -> Mat4 {
+    let q = proj.inverse() * Vec4::new(clip.x.signum(), -clip.y.signum(), 1.0, 1.0);
+    let c = clip / clip.dot(q);
+    let mut m = proj;
+    m.x_axis.z = c.x; m.y_axis.z = c.y; m.z_axis.z = c.z; m.w_axis.z = c.w;
+    m
+}
+
+impl Portal {
+    /// Secondary view seen through the surface at `transform`, or None if the viewer is behind it.
+    pub fn view(&self, transform: &Mat4, main: &View) -> Option<View> {
+        let surface = plane(transform);
+        if surface.dot(main.eye().extend(1.0)) <= 0.0 { return None; }
+        let (view, clip_plane) = match *self {
+            Portal::Mirror => {
+                let n = surface.truncate();
+                let d = surface.w;
+                //reflection about the plane n.x + d = 0
+                let reflect = Mat4::from_cols((Vec3::X - 2.0 * n.x * n).extend(0.0), (Vec3::Y - 2.0 * n.y * n).extend(0.0),
+                                              (Vec3::Z - 2.0 * n.z * n).extend(0.0), (-2.0 * d * n).extend(1.0));
+                (main.view * reflect, surface)
+            }
+            Portal::Portal { destination } => {
+                //turn around so we come out of the destination's front
+                let flip = Mat4::from_rotation_y(std::f32::consts::PI);
+                (main.view * *transform * flip * destination.inverse(), plane(&destination))
+            }
+        };
+        //plane must have the virtual eye on its negative side
+        let eye = view.inverse().w_axis;
+        let clip_plane = if clip_plane.dot(eye) > 0.0 { -clip_plane } else { clip_plane };
+        let clip_view = view.inverse().transpose() * clip_plane;
+        Some(View { view, proj: oblique(main.proj, clip_view), ..*main })
+    }
+}
+
+/// Offscreen targets for every visible portal, re-rendered each frame at full resolution so the
+/// result can be sampled in screen space.
+pub struct PortalTargets {
+    targets: HashMap<EntityId, (Target, Arc<ImageView<AttachmentImage>>)>,
+    extent: [u32; 2],
+}
+
+impl PortalTargets {
+    pub fn new() -> Self { PortalTargets { targets: HashMap::new(), extent: [0, 0] } }
+
+    /// Renders the secondary views and returns the textures the main pass should show.
+    #[profiling::function]
+    pub fn render(&mut self, renderer: &Renderer, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, scene: &Scene,
+                  main: &View, extent: [u32; 2], visible: &dyn Fn(EntityId) -> bool) -> HashMap<EntityId, Arc<ImageView<AttachmentImage>>> {
+        if extent != self.extent { self.targets.clear(); self.extent = extent; }
+        let mut out = HashMap::new();
+        let none = HashMap::new();
+        for e in scene.entities.iter().filter(|e| visible(e.id)) {
+            let portal_view = match e.portal.as_ref().and_then(|p| p.view(&e.transform, main)) { Some(v) => v, None => continue };
+            let (target, color) = self.targets.entry(e.id).or_insert_with(|| renderer.offscreen_target(extent));
+            let frustum = Frustum::from_view_proj(&portal_view.view_proj());
+            renderer.draw(builder, target, scene, &portal_view, &|o| o.portal.is_none() && frustum.intersects_aabb(&o.world_bounds()), &none, None);
+            out.insert(e.id, color.clone());
+        }
+        out
+    }
+}
@@ -0,0 +1,109 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               image::{ AttachmentImage, ImageUsage, view::ImageView },
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode, Filter },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint },
+               format::Format };
+use std::sync::Arc;
+use super::{ PostEffect, PostContext, PostImage, History, SCENE_FORMAT, extent, texel };
+
+mod down {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/bloom_down.comp", include: ["src/shaders"] }
+}
+mod up {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/bloom_up.comp", include: ["src/shaders"] }
+}
+
+/// Halvings at most; the chain also stops before a level gets smaller than 8 pixels.
+const MAX_LEVELS: usize = 6;
+
+/// Light scattered in the lens and eye, so anything bright glows. Runs in HDR, before the tonemapper.
+#[derive(Clone, Copy, Debug)]
+pub struct Bloom {
+    /// Share of the blurred image added on top. Small: with no threshold everything blooms a little, as in a real lens.
+    pub intensity: f32,
+    /// Brightness below which nothing blooms, 0 for all of it, and how softly it cuts in.
+    pub threshold: f32,
+    pub knee: f32,
+    /// 0..1, how much of each level comes from the wider ones below it. Higher spreads the glow further.
+    pub scatter: f32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self { Bloom { intensity: 0.04, threshold: 0.0, knee: 0.5, scatter: 0.7 } }
+}
+
+/// Progressive downsample to a small mip chain, then back up level by level, blurring with
+/// every step, and added onto the scene at the end.
+pub struct BloomPass {
+    dev: Arc<Device>,
+    down: Arc<ComputePipeline>,
+    up: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+    levels: Vec<PostImage>,
+    extent: [u32; 2],
+}
+
+impl BloomPass {
+    pub fn new(dev: Arc<Device>) -> Self {
+        let down = ComputePipeline::new(dev.clone(), down::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let up = ComputePipeline::new(dev.clone(), up::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo { mag_filter: Filter::Linear, min_filter: Filter::Linear,
+                                                                    address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        BloomPass { dev, down, up, sampler, levels: Vec::new(), extent: [0, 0] }
+    }
+
+    fn resize(&mut self, extent: [u32; 2]) {
+        self.extent = extent;
+        self.levels.clear();
+        let mut size = [extent[0] / 2, extent[1] / 2];
+        while self.levels.len() < MAX_LEVELS && size[0] >= 8 && size[1] >= 8 {
+            self.levels.push(ImageView::new_default(AttachmentImage::with_usage(self.dev.clone(), size, SCENE_FORMAT,
+                ImageUsage { storage: true, sampled: true, ..ImageUsage::none() }).unwrap()).unwrap());
+            size = [size[0] / 2, size[1] / 2];
+        }
+    }
+
+    fn dispatch(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, pipeline: &Arc<ComputePipeline>,
+                writes: Vec<WriteDescriptorSet>, size: [u32; 2]) {
+        let set = PersistentDescriptorSet::new(pipeline.layout().set_layouts().get(0).unwrap().clone(), writes).unwrap();
+        builder.bind_descriptor_sets(PipelineBindPoint::Compute, pipeline.layout().clone(), 0, set)
+            .dispatch([(size[0] + 7) / 8, (size[1] + 7) / 8, 1]).unwrap();
+    }
+}
+
+impl PostEffect for BloomPass {
+    fn name(&self) -> &'static str { "bloom" }
+    fn enabled(&self, ctx: &PostContext) -> bool { ctx.scene.bloom.is_some() }
+    fn output_format(&self, _input: Format) -> Format { SCENE_FORMAT }
+
+    fn record(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, ctx: &PostContext, input: &PostImage, output: &PostImage,
+              _history: Option<&History>) {
+        let bloom = ctx.scene.bloom.unwrap();
+        if self.extent != extent(input) { self.resize(extent(input)); }
+
+        builder.bind_pipeline_compute(self.down.clone());
+        let mut src = input.clone();
+        for (i, level) in self.levels.iter().enumerate() {
+            let pc = down::ty::PushConstants { texel: texel(&src), threshold: bloom.threshold, knee: bloom.knee.max(1e-4), first: (i == 0) as u32 };
+            builder.push_constants(self.down.layout().clone(), 0, pc);
+            self.dispatch(builder, &self.down, vec![WriteDescriptorSet::image_view_sampler(0, src.clone(), self.sampler.clone()),
+                                                    WriteDescriptorSet::image_view(1, level.clone())], extent(level));
+            src = level.clone();
+        }
+
+        //smallest level up into each bigger one, then the half res level onto the scene
+        builder.bind_pipeline_compute(self.up.clone());
+        let mut dsts: Vec<(PostImage, f32, u32)> = self.levels.iter().rev().skip(1).map(|l| (l.clone(), bloom.scatter, 0)).collect();
+        dsts.push((output.clone(), bloom.intensity, 1));
+        for (dst, blend, composite) in dsts {
+            let pc = up::ty::PushConstants { texel: texel(&src), blend, composite };
+            builder.push_constants(self.up.layout().clone(), 0, pc);
+            self.dispatch(builder, &self.up, vec![WriteDescriptorSet::image_view_sampler(0, src.clone(), self.sampler.clone()),
+                                                  WriteDescriptorSet::image_view(1, dst.clone()),
+                                                  WriteDescriptorSet::image_view_sampler(2, input.clone(), self.sampler.clone())], extent(&dst));
+            src = dst;
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               sampler::{ Sampler, SamplerCreateInfo },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint },
+               format::Format };
+use std::sync::Arc;
+use super::{ PostEffect, PostContext, PostImage, History, LDR_FORMAT };
+
+mod cs {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/cas.comp", include: ["src/shaders"] }
+}
+
+/// Contrast adaptive sharpening: brings back detail that TAA or upscaling softened, and holds
+/// back on edges that already have contrast so they don't ring.
+#[derive(Clone, Copy, Debug)]
+pub struct Cas {
+    /// 0..1, from subtle to as sharp as it goes.
+    pub strength: f32,
+}
+
+impl Default for Cas {
+    fn default() -> Self { Cas { strength: 0.5 } }
+}
+
+/// Runs while `scene.sharpen` is set, on display range color, last in the stack.
+pub struct CasPass {
+    pipeline: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+}
+
+impl CasPass {
+    pub fn new(dev: Arc<Device>) -> Self {
+        let pipeline = ComputePipeline::new(dev.clone(), cs::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let sampler = Sampler::new(dev, SamplerCreateInfo::default()).unwrap();
+        CasPass { pipeline, sampler }
+    }
+}
+
+impl PostEffect for CasPass {
+    fn name(&self) -> &'static str { "cas" }
+    fn enabled(&self, ctx: &PostContext) -> bool { ctx.scene.sharpen.is_some() }
+    fn output_format(&self, _input: Format) -> Format { LDR_FORMAT }
+
+    fn record(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, ctx: &PostContext, input: &PostImage, output: &PostImage,
+              _history: Option<&History>) {
+        let cas = ctx.scene.sharpen.unwrap();
+        let set = PersistentDescriptorSet::new(self.pipeline.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, input.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view(1, output.clone()),
+        ]).unwrap();
+        let extent = super::extent(output);
+        builder.bind_pipeline_compute(self.pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.pipeline.layout().clone(), 0, set)
+            .push_constants(self.pipeline.layout().clone(), 0, cs::ty::PushConstants { sharpness: cas.strength.clamp(0.0, 1.0) })
+            .dispatch([(extent[0] + 7) / 8, (extent[1] + 7) / 8, 1]).unwrap();
+    }
+}
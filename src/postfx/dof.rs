@@ -0,0 +1,134 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               image::{ AttachmentImage, ImageUsage, view::ImageView },
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode, Filter },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint },
+               shader::ShaderModule,
+               format::Format };
+use std::sync::Arc;
+use crate::camera::{ Camera, Projection };
+use super::{ PostEffect, PostContext, PostImage, History, SCENE_FORMAT };
+
+mod prepare {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/dof_prepare.comp", include: ["src/shaders"] }
+}
+mod gather {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/dof_gather.comp", include: ["src/shaders"] }
+}
+mod composite {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/dof_composite.comp", include: ["src/shaders"] }
+}
+
+/// Full frame, so the focal length falls out of the camera's field of view.
+const SENSOR_HEIGHT: f32 = 0.024;
+
+/// Thin lens depth of field for one camera: sharp at `focus_distance`, blurring away from it
+/// by how wide open the aperture is.
+#[derive(Clone, Copy, Debug)]
+pub struct DepthOfField {
+    /// Meters from the camera.
+    pub focus_distance: f32,
+    /// f-number; lower is a wider aperture and a shallower focus. A physical camera's wins.
+    pub f_stop: f32,
+    /// Largest blur radius in pixels, which bounds the gather's cost.
+    pub max_coc: f32,
+    pub samples: u32,
+}
+
+impl Default for DepthOfField {
+    fn default() -> Self { DepthOfField { focus_distance: 2.0, f_stop: 2.8, max_coc: 16.0, samples: 48 } }
+}
+
+impl DepthOfField {
+    /// Circle of confusion in pixels per unit of |z - focus| / z, for an image `height` pixels tall.
+    /// Orthographic cameras have no lens to go out of focus, so none. A physical camera's lens,
+    /// sensor and aperture replace the full frame sensor and `f_stop`.
+    fn coc_scale(&self, camera: &Camera, height: u32) -> f32 {
+        let fov_y = match camera.lens() { Projection::Perspective { fov_y } => fov_y, Projection::Orthographic { .. } => return 0.0 };
+        let (sensor_height, f_stop) = match camera.physical {
+            Some(p) => (p.sensor_height(camera.aspect) * 0.001, p.aperture),
+            None => (SENSOR_HEIGHT, self.f_stop),
+        };
+        let focal_length = sensor_height / (2.0 * (fov_y * 0.5).tan());
+        let focus = self.focus_distance.max(focal_length * 1.01);
+        focal_length * focal_length / (f_stop * (focus - focal_length)) / sensor_height * height as f32
+    }
+}
+
+/// Bokeh blur in HDR, before bloom, for cameras with `depth_of_field` set. The color goes to
+/// half res with its circle of confusion, each pixel there gathers the taps whose circles reach
+/// it, and the result is blended over the sharp image by the full res circle.
+pub struct DepthOfFieldPass {
+    dev: Arc<Device>,
+    prepare: Arc<ComputePipeline>,
+    gather: Arc<ComputePipeline>,
+    composite: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+    /// Half res color and coc, then its blur.
+    half: Vec<PostImage>,
+    extent: [u32; 2],
+}
+
+impl DepthOfFieldPass {
+    pub fn new(dev: Arc<Device>) -> Self {
+        let load = |m: Arc<ShaderModule>| ComputePipeline::new(dev.clone(), m.entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let prepare = load(prepare::load(dev.clone()).unwrap());
+        let gather = load(gather::load(dev.clone()).unwrap());
+        let composite = load(composite::load(dev.clone()).unwrap());
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo { mag_filter: Filter::Linear, min_filter: Filter::Linear,
+                                                                    address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        DepthOfFieldPass { dev, prepare, gather, composite, sampler, half: Vec::new(), extent: [0, 0] }
+    }
+}
+
+impl PostEffect for DepthOfFieldPass {
+    fn name(&self) -> &'static str { "depth of field" }
+    fn enabled(&self, ctx: &PostContext) -> bool { ctx.camera.depth_of_field.is_some() }
+    fn output_format(&self, _input: Format) -> Format { SCENE_FORMAT }
+
+    fn record(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, ctx: &PostContext, input: &PostImage, output: &PostImage,
+              _history: Option<&History>) {
+        let dof = ctx.camera.depth_of_field.unwrap();
+        let extent = super::extent(output);
+        let half = [(extent[0] + 1) / 2, (extent[1] + 1) / 2];
+        if self.extent != extent {
+            self.extent = extent;
+            self.half = (0..2).map(|_| ImageView::new_default(AttachmentImage::with_usage(self.dev.clone(), half, SCENE_FORMAT,
+                ImageUsage { storage: true, sampled: true, ..ImageUsage::none() }).unwrap()).unwrap()).collect();
+        }
+        let proj_z = [ctx.view.proj.z_axis.z, ctx.view.proj.w_axis.z];
+        let lens = [dof.focus_distance, dof.coc_scale(ctx.camera, extent[1]), dof.max_coc, 0.0];
+        let groups = |e: [u32; 2]| [(e[0] + 7) / 8, (e[1] + 7) / 8, 1];
+
+        let set = PersistentDescriptorSet::new(self.prepare.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, input.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler(1, ctx.target.depth.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view(2, self.half[0].clone()),
+        ]).unwrap();
+        builder.bind_pipeline_compute(self.prepare.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.prepare.layout().clone(), 0, set)
+            .push_constants(self.prepare.layout().clone(), 0, prepare::ty::PushConstants { lens, proj_z })
+            .dispatch(groups(half)).unwrap();
+
+        let set = PersistentDescriptorSet::new(self.gather.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, self.half[0].clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view(1, self.half[1].clone()),
+        ]).unwrap();
+        builder.bind_pipeline_compute(self.gather.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.gather.layout().clone(), 0, set)
+            .push_constants(self.gather.layout().clone(), 0, gather::ty::PushConstants { radius: dof.max_coc * 0.5, samples: dof.samples })
+            .dispatch(groups(half)).unwrap();
+
+        let set = PersistentDescriptorSet::new(self.composite.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, input.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler(1, ctx.target.depth.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler(2, self.half[1].clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view(3, output.clone()),
+        ]).unwrap();
+        builder.bind_pipeline_compute(self.composite.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.composite.layout().clone(), 0, set)
+            .push_constants(self.composite.layout().clone(), 0, composite::ty::PushConstants { lens, proj_z })
+            .dispatch(groups(extent)).unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               buffer::{ BufferUsage, DeviceLocalBuffer },
+               sampler::{ Sampler, SamplerCreateInfo },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint } };
+use std::sync::Arc;
+use super::PostImage;
+
+mod histogram {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/exposure_histogram.comp", include: ["src/shaders"] }
+}
+mod average {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/exposure_average.comp", include: ["src/shaders"] }
+}
+
+/// Eye adaptation: the tonemapper's exposure follows the scene's average brightness, so caves
+/// open up and skies don't blow out. `Tonemap::exposure` still applies on top as compensation.
+#[derive(Clone, Copy, Debug)]
+pub struct AutoExposure {
+    /// log2 luminance range measured and adapted within; darker or brighter scenes stop adapting.
+    pub min_ev: f32,
+    pub max_ev: f32,
+    /// Per second rates of adapting to a brighter and to a darker scene. Eyes are slower in the dark.
+    pub speed_up: f32,
+    pub speed_down: f32,
+}
+
+impl Default for AutoExposure {
+    fn default() -> Self { AutoExposure { min_ev: -8.0, max_ev: 8.0, speed_up: 3.0, speed_down: 1.0 } }
+}
+
+/// Histogram of the HDR image's log luminance, reduced on the gpu into a smoothed exposure that
+/// the tonemap shader reads back from `exposure` without a round trip to the cpu.
+pub struct ExposureController {
+    histogram: Arc<ComputePipeline>,
+    average: Arc<ComputePipeline>,
+    bins: Arc<DeviceLocalBuffer<[u32]>>,
+    /// Adapted luminance, then the exposure derived from it.
+    pub exposure: Arc<DeviceLocalBuffer<[f32]>>,
+    sampler: Arc<Sampler>,
+    /// Nothing adapted yet, so the first measurement is taken as is.
+    fresh: bool,
+}
+
+impl ExposureController {
+    pub fn new(dev: Arc<Device>) -> Self {
+        let histogram = ComputePipeline::new(dev.clone(), histogram::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let average = ComputePipeline::new(dev.clone(), average::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let usage = BufferUsage { storage_buffer: true, transfer_destination: true, ..BufferUsage::none() };
+        let bins = DeviceLocalBuffer::array(dev.clone(), 256, usage, dev.active_queue_families()).unwrap();
+        let exposure = DeviceLocalBuffer::array(dev.clone(), 2, usage, dev.active_queue_families()).unwrap();
+        let sampler = Sampler::new(dev, SamplerCreateInfo::default()).unwrap();
+        ExposureController { histogram, average, bins, exposure, sampler, fresh: true }
+    }
+
+    /// Measures `hdr` and moves the exposure `delta` seconds towards it.
+    pub fn record(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, hdr: &PostImage, extent: [u32; 2], settings: &AutoExposure, delta: f32) {
+        let range = (settings.max_ev - settings.min_ev).max(1e-3);
+        builder.fill_buffer(self.bins.clone(), 0).unwrap();
+        let set = PersistentDescriptorSet::new(self.histogram.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, hdr.clone(), self.sampler.clone()),
+            WriteDescriptorSet::buffer(1, self.bins.clone()),
+        ]).unwrap();
+        builder.bind_pipeline_compute(self.histogram.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.histogram.layout().clone(), 0, set)
+            .push_constants(self.histogram.layout().clone(), 0, histogram::ty::PushConstants { min_log: settings.min_ev, inv_range: 1.0 / range })
+            .dispatch([(extent[0] + 15) / 16, (extent[1] + 15) / 16, 1]).unwrap();
+
+        let adapt = |speed: f32| if self.fresh { 1.0 } else { 1.0 - (-delta * speed).exp() };
+        let set = PersistentDescriptorSet::new(self.average.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::buffer(0, self.bins.clone()),
+            WriteDescriptorSet::buffer(1, self.exposure.clone()),
+        ]).unwrap();
+        let pc = average::ty::PushConstants { min_log: settings.min_ev, range, adapt: [adapt(settings.speed_up), adapt(settings.speed_down)],
+                                              pixels: extent[0] * extent[1] };
+        builder.bind_pipeline_compute(self.average.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.average.layout().clone(), 0, set)
+            .push_constants(self.average.layout().clone(), 0, pc)
+            .dispatch([1, 1, 1]).unwrap();
+        self.fresh = false;
+    }
+}
@@ -0,0 +1,104 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               sampler::{ Sampler, SamplerCreateInfo },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint },
+               format::Format };
+use std::sync::Arc;
+use super::{ PostEffect, PostContext, PostImage, History, LDR_FORMAT, extent, storage_image };
+
+mod easu {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/fsr_easu.comp", include: ["src/shaders"] }
+}
+mod rcas {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/fsr_rcas.comp", include: ["src/shaders"] }
+}
+
+/// AMD's presets: how much smaller than the display the scene renders.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FsrQuality {
+    /// 1.3x per axis.
+    UltraQuality,
+    /// 1.5x.
+    Quality,
+    /// 1.7x.
+    Balanced,
+    /// 2x.
+    Performance,
+}
+
+impl FsrQuality {
+    /// Display size over render size, per axis.
+    pub fn ratio(self) -> f32 {
+        match self { FsrQuality::UltraQuality => 1.3, FsrQuality::Quality => 1.5, FsrQuality::Balanced => 1.7, FsrQuality::Performance => 2.0 }
+    }
+}
+
+/// FidelityFX Super Resolution 1: the scene renders smaller and is upscaled to the display with
+/// edge aware filtering, then sharpened. Wants anti-aliased input, so it goes after TAA or FXAA.
+#[derive(Clone, Copy, Debug)]
+pub struct Fsr {
+    pub quality: FsrQuality,
+    /// Stops of sharpening taken off the strongest, 0 is sharpest.
+    pub sharpness: f32,
+}
+
+impl Default for Fsr {
+    fn default() -> Self { Fsr { quality: FsrQuality::Quality, sharpness: 0.2 } }
+}
+
+/// Runs while `scene.fsr` is set, on display range color. The one effect whose output is
+/// display sized; `postfx::render_extent` makes the scene smaller to match.
+pub struct FsrPass {
+    dev: Arc<Device>,
+    easu: Arc<ComputePipeline>,
+    rcas: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+    /// Upscaled, before sharpening.
+    upscaled: Option<PostImage>,
+}
+
+impl FsrPass {
+    pub fn new(dev: Arc<Device>) -> Self {
+        let easu = ComputePipeline::new(dev.clone(), easu::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let rcas = ComputePipeline::new(dev.clone(), rcas::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo::default()).unwrap();
+        FsrPass { dev, easu, rcas, sampler, upscaled: None }
+    }
+}
+
+impl PostEffect for FsrPass {
+    fn name(&self) -> &'static str { "fsr" }
+    fn enabled(&self, ctx: &PostContext) -> bool { ctx.scene.fsr.is_some() }
+    fn output_format(&self, _input: Format) -> Format { LDR_FORMAT }
+    fn output_extent(&self, _input: [u32; 2], display: [u32; 2]) -> [u32; 2] { display }
+
+    fn record(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, ctx: &PostContext, input: &PostImage, output: &PostImage,
+              _history: Option<&History>) {
+        let fsr = ctx.scene.fsr.unwrap();
+        let size = extent(output);
+        if self.upscaled.as_ref().map_or(true, |u| extent(u) != size) { self.upscaled = Some(storage_image(&self.dev, LDR_FORMAT, size)); }
+        let upscaled = self.upscaled.clone().unwrap();
+        let groups = [(size[0] + 7) / 8, (size[1] + 7) / 8, 1];
+        let from = extent(input);
+
+        let set = PersistentDescriptorSet::new(self.easu.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, input.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view(1, upscaled.clone()),
+        ]).unwrap();
+        let pc = easu::ty::PushConstants { scale: [from[0] as f32 / size[0] as f32, from[1] as f32 / size[1] as f32] };
+        builder.bind_pipeline_compute(self.easu.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.easu.layout().clone(), 0, set)
+            .push_constants(self.easu.layout().clone(), 0, pc)
+            .dispatch(groups).unwrap();
+
+        let set = PersistentDescriptorSet::new(self.rcas.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, upscaled, self.sampler.clone()),
+            WriteDescriptorSet::image_view(1, output.clone()),
+        ]).unwrap();
+        builder.bind_pipeline_compute(self.rcas.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.rcas.layout().clone(), 0, set)
+            .push_constants(self.rcas.layout().clone(), 0, rcas::ty::PushConstants { sharpness: (-fsr.sharpness.max(0.0)).exp2() })
+            .dispatch(groups).unwrap();
+    }
+}
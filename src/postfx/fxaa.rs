@@ -0,0 +1,63 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode, Filter },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint },
+               format::Format };
+use std::sync::Arc;
+use super::{ PostEffect, PostContext, PostImage, History, LDR_FORMAT, texel };
+
+mod cs {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/fxaa.comp", include: ["src/shaders"] }
+}
+
+/// Edge smoothing from the final image alone. Cheap and works with either render path, at the
+/// cost of some softness; runs after the tonemapper, where the contrast it looks for is what shows.
+#[derive(Clone, Copy, Debug)]
+pub struct Fxaa {
+    /// 0..1, how much single pixel detail gets softened.
+    pub subpixel: f32,
+    /// Contrast, relative to the brightest neighbour, that counts as an edge.
+    pub edge_threshold: f32,
+    /// Contrast below which nothing counts, however dark.
+    pub edge_min: f32,
+}
+
+impl Default for Fxaa {
+    fn default() -> Self { Fxaa { subpixel: 0.75, edge_threshold: 0.166, edge_min: 0.0833 } }
+}
+
+pub struct FxaaPass {
+    pipeline: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+}
+
+impl FxaaPass {
+    pub fn new(dev: Arc<Device>) -> Self {
+        let pipeline = ComputePipeline::new(dev.clone(), cs::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let sampler = Sampler::new(dev, SamplerCreateInfo { mag_filter: Filter::Linear, min_filter: Filter::Linear,
+                                                            address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        FxaaPass { pipeline, sampler }
+    }
+}
+
+impl PostEffect for FxaaPass {
+    fn name(&self) -> &'static str { "fxaa" }
+    fn enabled(&self, ctx: &PostContext) -> bool { ctx.scene.fxaa.is_some() }
+    fn output_format(&self, _input: Format) -> Format { LDR_FORMAT }
+
+    fn record(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, ctx: &PostContext, input: &PostImage, output: &PostImage,
+              _history: Option<&History>) {
+        let fxaa = ctx.scene.fxaa.unwrap();
+        let set = PersistentDescriptorSet::new(self.pipeline.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, input.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view(1, output.clone()),
+        ]).unwrap();
+        let pc = cs::ty::PushConstants { texel: texel(input), subpixel: fxaa.subpixel, edge_threshold: fxaa.edge_threshold, edge_min: fxaa.edge_min };
+        let extent = super::extent(output);
+        builder.bind_pipeline_compute(self.pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.pipeline.layout().clone(), 0, set)
+            .push_constants(self.pipeline.layout().clone(), 0, pc)
+            .dispatch([(extent[0] + 7) / 8, (extent[1] + 7) / 8, 1]).unwrap();
+    }
+}
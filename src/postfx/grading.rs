@@ -0,0 +1,179 @@
+use vulkano::{ device::Queue,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               image::{ ImmutableImage, ImageDimensions, MipmapsCount, view::ImageView },
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode, Filter },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint },
+               format::Format,
+               sync::GpuFuture };
+use glam::Vec3;
+use std::path::Path;
+use std::sync::Arc;
+use crate::probe::ProbeShape;
+use super::{ PostEffect, PostContext, PostImage, History, LDR_FORMAT };
+
+mod cs {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/grading.comp", include: ["src/shaders"] }
+}
+
+/// Size of the identity lut used when none is given.
+const NEUTRAL_SIZE: u32 = 16;
+
+/// A 3D color lookup table on the GPU. Maps display encoded color to graded color, the way
+/// grading tools export them.
+pub struct Lut {
+    view: Arc<ImageView<ImmutableImage>>,
+    size: u32,
+    /// Input range covered by the table, per channel.
+    domain: [Vec3; 2],
+}
+
+impl Lut {
+    /// `size`³ entries, red varying fastest then green then blue, as .cube files store them.
+    pub fn from_entries(queue: Arc<Queue>, size: u32, entries: &[Vec3], domain: [Vec3; 2]) -> Arc<Lut> {
+        assert_eq!(entries.len(), (size * size * size) as usize, "lut needs size³ entries");
+        //10 bits a channel filters everywhere and bands far less than 8
+        let pack = |c: Vec3| {
+            let c = (c.clamp(Vec3::ZERO, Vec3::ONE) * 1023.0).round();
+            c.x as u32 | (c.y as u32) << 10 | (c.z as u32) << 20 | 3 << 30
+        };
+        let (image, future) = ImmutableImage::from_iter(entries.iter().map(|&c| pack(c)).collect::<Vec<u32>>().into_iter(),
+            ImageDimensions::Dim3d { width: size, height: size, depth: size },
+            MipmapsCount::One, Format::A2B10G10R10_UNORM_PACK32, queue).expect("failed lut upload");
+        future.then_signal_fence_and_flush().unwrap().wait(None).unwrap();
+        Arc::new(Lut { view: ImageView::new_default(image).unwrap(), size, domain })
+    }
+
+    /// Leaves color as it is.
+    pub fn neutral(queue: Arc<Queue>, size: u32) -> Arc<Lut> {
+        let n = size as usize;
+        let s = (size - 1) as f32;
+        let entries: Vec<Vec3> = (0..n * n * n).map(|i| Vec3::new((i % n) as f32, (i / n % n) as f32, (i / (n * n)) as f32) / s).collect();
+        Self::from_entries(queue, size, &entries, [Vec3::ZERO, Vec3::ONE])
+    }
+
+    /// Adobe/Resolve .cube file with a 3D table. Blocks until the upload finished, fine for load time.
+    #[profiling::function]
+    pub fn load(queue: Arc<Queue>, path: impl AsRef<Path>) -> Arc<Lut> {
+        let text = std::fs::read_to_string(path.as_ref()).unwrap_or_else(|e| panic!("failed loading {:?}: {}", path.as_ref(), e));
+        let (size, entries, domain) = parse_cube(&text).unwrap_or_else(|e| panic!("failed loading {:?}: {}", path.as_ref(), e));
+        Self::from_entries(queue, size, &entries, domain)
+    }
+
+    fn push_domain(&self) -> [[f32; 4]; 2] {
+        let [min, max] = self.domain;
+        let scale = Vec3::ONE / (max - min).max(Vec3::splat(1e-6));
+        [min.extend(self.size as f32).to_array(), scale.extend(0.0).to_array()]
+    }
+}
+
+fn parse_cube(text: &str) -> Result<(u32, Vec<Vec3>, [Vec3; 2]), String> {
+    let mut size = None;
+    let mut domain = [Vec3::ZERO, Vec3::ONE];
+    let mut entries = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        let first = match words.next() { Some(w) if !w.starts_with('#') => w, _ => continue };
+        let floats = |words: std::str::SplitWhitespace| -> Result<Vec3, String> {
+            let v: Vec<f32> = words.map(|w| w.parse::<f32>()).collect::<Result<_, _>>().map_err(|e| format!("line {}: {}", n + 1, e))?;
+            if v.len() != 3 { return Err(format!("line {}: expected three numbers", n + 1)); }
+            Ok(Vec3::new(v[0], v[1], v[2]))
+        };
+        match first {
+            "TITLE" => {}
+            "LUT_1D_SIZE" => return Err("1D luts are not supported".into()),
+            "LUT_3D_SIZE" => size = Some(words.next().and_then(|w| w.parse::<u32>().ok()).filter(|&s| s >= 2)
+                                         .ok_or_else(|| format!("line {}: bad LUT_3D_SIZE", n + 1))?),
+            "DOMAIN_MIN" => domain[0] = floats(words)?,
+            "DOMAIN_MAX" => domain[1] = floats(words)?,
+            _ if first.starts_with(|c: char| c.is_ascii_alphabetic()) => {} //keywords other tools add
+            _ => entries.push(floats(line.split_whitespace())?),
+        }
+    }
+    let size = size.ok_or("no LUT_3D_SIZE")?;
+    if entries.len() != (size * size * size) as usize {
+        return Err(format!("{} entries for a size {} lut, expected {}", entries.len(), size, size * size * size));
+    }
+    Ok((size, entries, domain))
+}
+
+/// A region with its own look, faded in towards the inside like reflection probes.
+#[derive(Clone)]
+pub struct GradingArea {
+    pub position: Vec3,
+    pub shape: ProbeShape,
+    /// Distance inside the volume over which the look fades in.
+    pub blend: f32,
+    pub lut: Arc<Lut>,
+}
+
+impl GradingArea {
+    /// 0 outside .. 1 at least `blend` deep inside.
+    pub fn weight(&self, eye: Vec3) -> f32 {
+        let depth = match self.shape {
+            ProbeShape::Sphere { radius } => radius - eye.distance(self.position),
+            ProbeShape::Box { half_extent } => (half_extent - (eye - self.position).abs()).min_element(),
+        };
+        (depth / self.blend.max(1e-4)).clamp(0.0, 1.0)
+    }
+}
+
+/// Look applied to the tonemapped image through a 3D lut.
+#[derive(Clone)]
+pub struct ColorGrading {
+    /// None grades with an identity table, leaving only the areas.
+    pub lut: Option<Arc<Lut>>,
+    /// 0..1, how much of the graded color replaces the original.
+    pub intensity: f32,
+    /// The strongest one around the camera is blended over `lut`.
+    pub areas: Vec<GradingArea>,
+}
+
+impl Default for ColorGrading {
+    fn default() -> Self { ColorGrading { lut: None, intensity: 1.0, areas: Vec::new() } }
+}
+
+/// Runs while `scene.grading` is set, after the tonemapper; reads and writes display range color.
+pub struct GradingPass {
+    pipeline: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+    neutral: Arc<Lut>,
+}
+
+impl GradingPass {
+    pub fn new(queue: Arc<Queue>) -> Self {
+        let dev = queue.device().clone();
+        let pipeline = ComputePipeline::new(dev.clone(), cs::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let sampler = Sampler::new(dev, SamplerCreateInfo { mag_filter: Filter::Linear, min_filter: Filter::Linear,
+                                                            address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        GradingPass { pipeline, sampler, neutral: Lut::neutral(queue, NEUTRAL_SIZE) }
+    }
+}
+
+impl PostEffect for GradingPass {
+    fn name(&self) -> &'static str { "grading" }
+    fn enabled(&self, ctx: &PostContext) -> bool { ctx.scene.grading.is_some() }
+    fn output_format(&self, _input: Format) -> Format { LDR_FORMAT }
+
+    fn record(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, ctx: &PostContext, input: &PostImage, output: &PostImage,
+              _history: Option<&History>) {
+        let grading = ctx.scene.grading.as_ref().unwrap();
+        let a = grading.lut.as_ref().unwrap_or(&self.neutral);
+        let eye = ctx.camera.position;
+        let area = grading.areas.iter().map(|area| (area, area.weight(eye))).filter(|(_, w)| *w > 0.0)
+            .max_by(|x, y| x.1.total_cmp(&y.1));
+        let (b, blend) = match area { Some((area, w)) => (&area.lut, w), None => (a, 0.0) };
+        let set = PersistentDescriptorSet::new(self.pipeline.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, input.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view(1, output.clone()),
+            WriteDescriptorSet::image_view_sampler(2, a.view.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler(3, b.view.clone(), self.sampler.clone()),
+        ]).unwrap();
+        let pc = cs::ty::PushConstants { domain_a: a.push_domain(), domain_b: b.push_domain(), blend, intensity: grading.intensity };
+        let extent = super::extent(output);
+        builder.bind_pipeline_compute(self.pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.pipeline.layout().clone(), 0, set)
+            .push_constants(self.pipeline.layout().clone(), 0, pc)
+            .dispatch([(extent[0] + 7) / 8, (extent[1] + 7) / 8, 1]).unwrap();
+    }
+}
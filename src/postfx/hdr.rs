@@ -0,0 +1,70 @@
+use vulkano::{ format::{ Format, NumericType }, swapchain::ColorSpace };
+
+/// How the final blit turns linear color into what the swapchain stores. The overlay and the
+/// selection outline draw onto the swapchain afterwards as they are, so on HDR displays they
+/// come out brighter than they would on SDR ones.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DisplayEncoding {
+    /// 8 bit srgb formats, encoded by the hardware on write.
+    Srgb,
+    /// Any other format in the srgb color space; the blit encodes.
+    SrgbInShader,
+    /// The PQ curve over Rec. 2020 primaries, in 10 bits.
+    Hdr10,
+    /// Linear Rec. 709 in half floats, 1 being 80 nits; values past 1 and below 0 reach the
+    /// display's wider range and gamut.
+    ScRgb,
+}
+
+impl DisplayEncoding {
+    pub fn of(format: Format, color_space: ColorSpace) -> Self {
+        match color_space {
+            ColorSpace::Hdr10St2084 => DisplayEncoding::Hdr10,
+            ColorSpace::ExtendedSrgbLinear => DisplayEncoding::ScRgb,
+            _ if format.type_color() == Some(NumericType::SRGB) => DisplayEncoding::Srgb,
+            _ => DisplayEncoding::SrgbInShader,
+        }
+    }
+
+    pub fn is_hdr(self) -> bool { matches!(self, DisplayEncoding::Hdr10 | DisplayEncoding::ScRgb) }
+
+    fn accepts(self, format: Format, color_space: ColorSpace) -> bool {
+        match self {
+            DisplayEncoding::Hdr10 => color_space == ColorSpace::Hdr10St2084
+                && matches!(format, Format::A2B10G10R10_UNORM_PACK32 | Format::A2R10G10B10_UNORM_PACK32),
+            DisplayEncoding::ScRgb => color_space == ColorSpace::ExtendedSrgbLinear && format == Format::R16G16B16A16_SFLOAT,
+            DisplayEncoding::Srgb => color_space == ColorSpace::SrgbNonLinear
+                && matches!(format, Format::B8G8R8A8_SRGB | Format::R8G8B8A8_SRGB | Format::A8B8G8R8_SRGB_PACK32),
+            DisplayEncoding::SrgbInShader => color_space == ColorSpace::SrgbNonLinear,
+        }
+    }
+}
+
+/// Brightness of an HDR display, in nits. Ignored while presenting to an SDR one.
+#[derive(Clone, Copy, Debug)]
+pub struct HdrOutput {
+    /// What diffuse white, 1.0 in the tonemapped image, comes out at. Around 200 matches SDR
+    /// content on a typical HDR monitor.
+    pub paper_white: f32,
+    /// Where the tonemapper rolls highlights off to; the display's peak.
+    pub peak: f32,
+}
+
+impl Default for HdrOutput {
+    fn default() -> Self { HdrOutput { paper_white: 200.0, peak: 1000.0 } }
+}
+
+impl HdrOutput {
+    /// Range above paper white the tonemapper may use, as a multiple of it.
+    pub fn headroom(&self) -> f32 { (self.peak / self.paper_white.max(1.0)).max(1.0) }
+}
+
+/// Surface format to present with. `hdr` asks for that encoding where the surface offers it;
+/// otherwise, or without it, an 8 bit srgb format so the hardware encodes, then anything in
+/// the srgb color space. Drivers don't list formats in any particular order, so the first one
+/// is only the last resort.
+pub fn swapchain_format(formats: &[(Format, ColorSpace)], hdr: Option<DisplayEncoding>) -> (Format, ColorSpace) {
+    hdr.into_iter().chain([DisplayEncoding::Srgb, DisplayEncoding::SrgbInShader])
+        .find_map(|encoding| formats.iter().copied().find(|&(f, c)| encoding.accepts(f, c)))
+        .unwrap_or(formats[0])
+}
@@ -0,0 +1,79 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode, Filter },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint },
+               format::Format };
+use glam::Vec3;
+use std::sync::Arc;
+use super::{ PostEffect, PostContext, PostImage, History, LDR_FORMAT, texel };
+
+mod cs {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/lens.comp", include: ["src/shaders"] }
+}
+
+/// Darkens, or tints, the edges of the image. A red one with a pulsing intensity makes a
+/// passable damage indicator.
+#[derive(Clone, Copy, Debug)]
+pub struct Vignette {
+    /// 0..1, how far the corners go towards `color`.
+    pub intensity: f32,
+    /// 0..1, 0 is a hard edge halfway out, 1 fades from the center.
+    pub smoothness: f32,
+    /// 1 is a circle, 0 stretches with the screen.
+    pub roundness: f32,
+    pub color: Vec3,
+}
+
+impl Default for Vignette {
+    fn default() -> Self { Vignette { intensity: 0.3, smoothness: 0.5, roundness: 1.0, color: Vec3::ZERO } }
+}
+
+/// Red and blue fringes that grow towards the edges, like a cheap lens.
+#[derive(Clone, Copy, Debug)]
+pub struct ChromaticAberration {
+    /// Offset of red and blue at the corners, in fractions of the screen.
+    pub intensity: f32,
+}
+
+impl Default for ChromaticAberration {
+    fn default() -> Self { ChromaticAberration { intensity: 0.004 } }
+}
+
+/// Both of the camera's lens effects in one pass, after grading. Runs while it has either.
+pub struct LensPass {
+    pipeline: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+}
+
+impl LensPass {
+    pub fn new(dev: Arc<Device>) -> Self {
+        let pipeline = ComputePipeline::new(dev.clone(), cs::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let sampler = Sampler::new(dev, SamplerCreateInfo { mag_filter: Filter::Linear, min_filter: Filter::Linear,
+                                                            address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        LensPass { pipeline, sampler }
+    }
+}
+
+impl PostEffect for LensPass {
+    fn name(&self) -> &'static str { "lens" }
+    fn enabled(&self, ctx: &PostContext) -> bool { ctx.camera.vignette.is_some() || ctx.camera.chromatic_aberration.is_some() }
+    fn output_format(&self, _input: Format) -> Format { LDR_FORMAT }
+
+    fn record(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, ctx: &PostContext, input: &PostImage, output: &PostImage,
+              _history: Option<&History>) {
+        let vignette = ctx.camera.vignette.unwrap_or(Vignette { intensity: 0.0, ..Default::default() });
+        let aberration = ctx.camera.chromatic_aberration.map_or(0.0, |c| c.intensity);
+        let set = PersistentDescriptorSet::new(self.pipeline.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, input.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view(1, output.clone()),
+        ]).unwrap();
+        let pc = cs::ty::PushConstants { vignette_color: vignette.color.extend(vignette.intensity).to_array(), texel: texel(input),
+                                         smoothness: vignette.smoothness, roundness: vignette.roundness, aberration };
+        let extent = super::extent(output);
+        builder.bind_pipeline_compute(self.pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.pipeline.layout().clone(), 0, set)
+            .push_constants(self.pipeline.layout().clone(), 0, pc)
+            .dispatch([(extent[0] + 7) / 8, (extent[1] + 7) / 8, 1]).unwrap();
+    }
+}
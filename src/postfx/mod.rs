@@ -0,0 +1,232 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, SubpassContents },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               image::{ AttachmentImage, ImageAccess, ImageUsage, SwapchainImage, view::ImageView },
+               render_pass::{ Framebuffer, FramebufferCreateInfo, RenderPass, Subpass },
+               swapchain::ColorSpace,
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode, Filter },
+               pipeline::{ GraphicsPipeline, Pipeline, PipelineBindPoint,
+                           graphics::{ input_assembly::InputAssemblyState, vertex_input::BuffersDefinition,
+                                       viewport::{ Viewport, ViewportState } } },
+               format::{ ClearValue, Format } };
+use winit::window::Window;
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::scene::Scene;
+use crate::camera::{ Camera, View };
+use crate::renderer::Target;
+use crate::time::Time;
+use hdr::DisplayEncoding;
+
+pub mod tonemap;
+pub mod exposure;
+pub mod bloom;
+pub mod dof;
+pub mod motion_blur;
+pub mod fxaa;
+pub mod taa;
+pub mod grading;
+pub mod lens;
+pub mod cas;
+pub mod fsr;
+pub mod transition;
+pub mod hdr;
+
+/// What the scene renders into before post processing, and what effects before the tonemapper write.
+pub const SCENE_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+/// What the tonemapper and the display range effects after it write: 1 is paper white, and
+/// HDR displays get headroom above it. Float, so dark gradients don't band either.
+pub const LDR_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+pub type PostImage = Arc<ImageView<AttachmentImage>>;
+
+mod blit_vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			void main() {
+				gl_Position = vec4(vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0, 0.0, 1.0);
+			}"
+    }
+}
+mod blit_fs {
+    vulkano_shaders::shader! { ty: "fragment", path: "src/shaders/post_blit.frag", include: ["src/shaders"] }
+}
+
+/// Everything an effect may read besides the previous stage's color.
+pub struct PostContext<'a> {
+    pub scene: &'a Scene,
+    /// Per camera settings such as depth of field; `view` is what it rendered with.
+    pub camera: &'a Camera,
+    pub view: &'a View,
+    /// The scene pass the chain starts from: depth, and the g-buffer when deferred.
+    pub target: &'a Target,
+    pub time: &'a Time,
+    /// How far above paper white the tonemapper may go, see `PostStack::headroom`.
+    pub headroom: f32,
+    /// The water's planar reflection, when it asked for one and the camera is above it.
+    pub water_reflection: Option<PostImage>,
+}
+
+/// An effect's images that outlive the frame: what it wrote last frame, if that is still
+/// valid, and what it writes for the next one.
+pub struct History {
+    pub previous: Option<PostImage>,
+    pub current: PostImage,
+}
+
+/// One stage of the chain. Reads `input` (sampled, any format) and writes every pixel of
+/// `output`, which the stack allocates in `output_format` and `output_extent`. Recorded outside
+/// any render pass.
+pub trait PostEffect {
+    fn name(&self) -> &'static str;
+    /// Skipped effects cost nothing; the next one reads what this one would have.
+    fn enabled(&self, _ctx: &PostContext) -> bool { true }
+    /// Compute effects write through a fixed storage format, so most override this.
+    fn output_format(&self, input: Format) -> Format { input }
+    /// Upscalers return `display`, the swapchain's size; everything else keeps the input's.
+    fn output_extent(&self, input: [u32; 2], _display: [u32; 2]) -> [u32; 2] { input }
+    /// Temporal effects get a `History` in this format.
+    fn history_format(&self) -> Option<Format> { None }
+    fn record(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, ctx: &PostContext, input: &PostImage, output: &PostImage,
+              history: Option<&History>);
+}
+
+/// A pair of images swapped every frame, and the frame the current one was last written.
+struct HistoryImages {
+    images: [PostImage; 2],
+    current: usize,
+    frame: u64,
+}
+
+/// Ordered post processing between the scene pass and the swapchain. Each enabled effect gets
+/// the previous stage's image and writes a new one; images come from a pool matched on format
+/// and size and are handed out ping-pong, never the one being read. The last image is blitted
+/// onto the swapchain image, encoded for the display, where the selection outline and overlay then go; it filters
+/// whatever is still at render resolution by then, bicubic up or box down. Effects that ask
+/// for history get an image pair of their own, which is dropped on resize and counts as empty
+/// after any frame the effect didn't run.
+pub struct PostStack {
+    dev: Arc<Device>,
+    effects: Vec<Box<dyn PostEffect>>,
+    pool: Vec<PostImage>,
+    /// By effect index.
+    histories: HashMap<usize, HistoryImages>,
+    render_pass: Arc<RenderPass>,
+    blit: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    framebuffers: Vec<Arc<Framebuffer>>,
+    display: [u32; 2],
+    encoding: DisplayEncoding,
+}
+
+impl PostStack {
+    pub fn new(dev: Arc<Device>, swapchain_format: Format, color_space: ColorSpace) -> Self {
+        let render_pass = vulkano::single_pass_renderpass!( dev.clone(),
+                                                            attachments: { color: { load: DontCare, store: Store, format: swapchain_format, samples: 1,}},
+                                                            pass: { color: [color], depth_stencil: {} }).unwrap();
+        let vs = blit_vs::load(dev.clone()).unwrap();
+        let fs = blit_fs::load(dev.clone()).unwrap();
+        let blit = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(dev.clone()).unwrap();
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo { mag_filter: Filter::Linear, min_filter: Filter::Linear,
+                                                                    address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        PostStack { dev, effects: Vec::new(), pool: Vec::new(), histories: HashMap::new(), render_pass, blit, sampler, framebuffers: Vec::new(), display: [0, 0],
+                    encoding: DisplayEncoding::of(swapchain_format, color_space) }
+    }
+
+    pub fn encoding(&self) -> DisplayEncoding { self.encoding }
+
+    /// `scene.hdr_output`'s headroom when presenting to an HDR display, 1 otherwise.
+    pub fn headroom(&self, scene: &Scene) -> f32 { if self.encoding.is_hdr() { scene.hdr_output.headroom() } else { 1.0 } }
+
+    /// Effects run in the order they were pushed.
+    pub fn push(&mut self, effect: Box<dyn PostEffect>) { self.effects.push(effect); }
+
+    pub fn resize(&mut self, images: &[Arc<SwapchainImage<Window>>]) {
+        self.framebuffers = images.iter().map(|image| {
+            Framebuffer::new(self.render_pass.clone(), FramebufferCreateInfo {
+                attachments: vec![ImageView::new_default(image.clone()).unwrap()], ..Default::default() }).unwrap()
+        }).collect();
+        self.display = images[0].dimensions().width_height();
+        self.pool.clear();
+        self.histories.clear();
+    }
+
+    /// Runs the chain over `color`, the scene target's color, and blits the result onto swapchain image `image_num`.
+    #[profiling::function]
+    pub fn record(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, image_num: usize, ctx: &PostContext, color: PostImage) {
+        let mut extent = ctx.target.extent;
+        let mut current = color;
+        let mut format = current.format().unwrap();
+        for (i, effect) in self.effects.iter_mut().enumerate().filter(|(_, e)| e.enabled(ctx)) {
+            format = effect.output_format(format);
+            extent = effect.output_extent(extent, self.display);
+            let output = acquire(&self.dev, &mut self.pool, format, extent, &current);
+            let history = effect.history_format().map(|f| {
+                let images = self.histories.entry(i).or_insert_with(|| HistoryImages {
+                    images: [storage_image(&self.dev, f, extent), storage_image(&self.dev, f, extent)], current: 0, frame: 0 });
+                //a new render resolution starts the history over
+                if self::extent(&images.images[0]) != extent {
+                    *images = HistoryImages { images: [storage_image(&self.dev, f, extent), storage_image(&self.dev, f, extent)], current: 0, frame: 0 };
+                }
+                let previous = images.images[images.current].clone();
+                let valid = images.frame > 0 && images.frame + 1 == ctx.time.frame;
+                images.current ^= 1;
+                images.frame = ctx.time.frame;
+                History { previous: if valid { Some(previous) } else { None }, current: images.images[images.current].clone() }
+            });
+            effect.record(builder, ctx, &current, &output, history.as_ref());
+            current = output;
+        }
+
+        let framebuffer = self.framebuffers[image_num].clone();
+        let size = framebuffer.extent();
+        let source = self::extent(&current);
+        let viewport = Viewport { origin: [0.0, 0.0], dimensions: [size[0] as f32, size[1] as f32], depth_range: 0.0..1.0 };
+        let set = PersistentDescriptorSet::new(self.blit.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, current, self.sampler.clone())]).unwrap();
+        builder.begin_render_pass(framebuffer, SubpassContents::Inline, vec![ClearValue::None]).unwrap()
+            .set_viewport(0, [viewport])
+            .bind_pipeline_graphics(self.blit.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, self.blit.layout().clone(), 0, set)
+            .push_constants(self.blit.layout().clone(), 0, blit_fs::ty::PushConstants { inv_extent: [1.0 / size[0] as f32, 1.0 / size[1] as f32],
+                                                                                      source_size: [source[0] as f32, source[1] as f32],
+                                                                                      encoding: self.encoding as u32, paper_white: ctx.scene.hdr_output.paper_white })
+            .draw(3, 1, 0, 0).unwrap()
+            .end_render_pass().unwrap();
+    }
+}
+
+/// Size the scene renders at for a `display` sized swapchain: `scene.render_scale` of it, less
+/// while an upscaler is on, and times `scale` from dynamic resolution on top.
+pub fn render_extent(scene: &Scene, display: [u32; 2], scale: f32) -> [u32; 2] {
+    let ratio = scene.fsr.map_or(1.0, |f| f.quality.ratio()) / (scene.render_scale.clamp(0.5, 2.0) * scale);
+    [((display[0] as f32 / ratio) as u32).max(1), ((display[1] as f32 / ratio) as u32).max(1)]
+}
+
+/// Width and height of a post image.
+pub fn extent(image: &PostImage) -> [u32; 2] { image.image().dimensions().width_height() }
+
+/// Size of one texel in uv, for effects that sample.
+pub fn texel(image: &PostImage) -> [f32; 2] { let e = extent(image); [1.0 / e[0] as f32, 1.0 / e[1] as f32] }
+
+/// A pooled image of `format` and `extent` other than `input`, made on first use.
+fn acquire(dev: &Arc<Device>, pool: &mut Vec<PostImage>, format: Format, extent: [u32; 2], input: &PostImage) -> PostImage {
+    let found = pool.iter().find(|i| i.format() == Some(format) && self::extent(i) == extent && !Arc::ptr_eq(i, input));
+    if let Some(image) = found { return image.clone(); }
+    let image = storage_image(dev, format, extent);
+    pool.push(image.clone());
+    image
+}
+
+fn storage_image(dev: &Arc<Device>, format: Format, extent: [u32; 2]) -> PostImage {
+    ImageView::new_default(AttachmentImage::with_usage(dev.clone(), extent, format,
+        ImageUsage { storage: true, sampled: true, color_attachment: true, ..ImageUsage::none() }).unwrap()).unwrap()
+}
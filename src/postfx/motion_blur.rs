@@ -0,0 +1,85 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode, Filter },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint },
+               format::Format };
+use glam::Mat4;
+use std::sync::Arc;
+use super::{ PostEffect, PostContext, PostImage, History, SCENE_FORMAT };
+
+mod cs {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/motion_blur.comp", include: ["src/shaders"] }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MotionBlurMode {
+    /// Only the camera's own movement blurs, from depth alone.
+    Camera,
+    /// Moving objects blur as well, from the velocity buffer.
+    Full,
+}
+
+/// Blur along each pixel's motion since last frame, as a camera shutter open for part of the frame would.
+#[derive(Clone, Copy, Debug)]
+pub struct MotionBlur {
+    pub mode: MotionBlurMode,
+    /// Fraction of the frame the shutter is open; 0.5 is a 180 degree shutter. A physical
+    /// camera's shutter time wins.
+    pub shutter: f32,
+    /// Longest streak in pixels.
+    pub max_blur: f32,
+    pub samples: u32,
+}
+
+impl Default for MotionBlur {
+    fn default() -> Self { MotionBlur { mode: MotionBlurMode::Full, shutter: 0.5, max_blur: 32.0, samples: 12 } }
+}
+
+/// Runs in HDR so highlights streak at full brightness. Remembers the camera it ran with for
+/// the next frame's reprojection.
+pub struct MotionBlurPass {
+    pipeline: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+    /// Camera and frame number it last ran with; older than the previous frame counts as no motion.
+    previous: Option<(Mat4, u64)>,
+}
+
+impl MotionBlurPass {
+    pub fn new(dev: Arc<Device>) -> Self {
+        let pipeline = ComputePipeline::new(dev.clone(), cs::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let sampler = Sampler::new(dev, SamplerCreateInfo { mag_filter: Filter::Linear, min_filter: Filter::Linear,
+                                                            address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        MotionBlurPass { pipeline, sampler, previous: None }
+    }
+}
+
+impl PostEffect for MotionBlurPass {
+    fn name(&self) -> &'static str { "motion blur" }
+    fn enabled(&self, ctx: &PostContext) -> bool { ctx.scene.motion_blur.is_some() }
+    fn output_format(&self, _input: Format) -> Format { SCENE_FORMAT }
+
+    fn record(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, ctx: &PostContext, input: &PostImage, output: &PostImage,
+              _history: Option<&History>) {
+        let blur = ctx.scene.motion_blur.unwrap();
+        let view_proj = ctx.view.unjittered_view_proj();
+        let previous = match self.previous.replace((view_proj, ctx.time.frame)) {
+            Some((p, frame)) if frame + 1 == ctx.time.frame => p,
+            _ => view_proj,
+        };
+        let set = PersistentDescriptorSet::new(self.pipeline.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, input.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler(1, ctx.target.depth.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler(2, ctx.target.velocity.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view(3, output.clone()),
+        ]).unwrap();
+        let shutter = ctx.camera.physical.map_or(blur.shutter, |p| p.shutter_fraction(ctx.time.real_delta));
+        let pc = cs::ty::PushConstants { reproject: (previous * view_proj.inverse()).to_cols_array_2d(),
+                                         params: [shutter, blur.max_blur, blur.samples as f32, (blur.mode == MotionBlurMode::Full) as u32 as f32] };
+        let extent = super::extent(output);
+        builder.bind_pipeline_compute(self.pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.pipeline.layout().clone(), 0, set)
+            .push_constants(self.pipeline.layout().clone(), 0, pc)
+            .dispatch([(extent[0] + 7) / 8, (extent[1] + 7) / 8, 1]).unwrap();
+    }
+}
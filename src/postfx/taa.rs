@@ -0,0 +1,113 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode, Filter },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint },
+               format::Format };
+use glam::{ Mat4, Vec2 };
+use std::sync::Arc;
+use super::{ PostEffect, PostContext, PostImage, History, SCENE_FORMAT };
+
+mod resolve {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/taa.comp", include: ["src/shaders"] }
+}
+mod sharpen {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/taa_sharpen.comp", include: ["src/shaders"] }
+}
+
+/// Frames in the jitter sequence before it repeats.
+const JITTER_PHASES: u64 = 8;
+
+/// Temporal anti-aliasing: the projection is jittered by a subpixel amount every frame and the
+/// frames are accumulated, so edges and shading get supersampled over time.
+#[derive(Clone, Copy, Debug)]
+pub struct Taa {
+    /// 0..1, share of the reprojected history kept each frame. Higher is smoother and slower to react.
+    pub feedback: f32,
+    /// Unsharp mask on the output, against the softness of accumulating.
+    pub sharpness: f32,
+}
+
+impl Default for Taa {
+    fn default() -> Self { Taa { feedback: 0.9, sharpness: 0.25 } }
+}
+
+fn halton(mut i: u64, base: u64) -> f32 {
+    let (mut f, mut r) = (1.0, 0.0);
+    while i > 0 {
+        f /= base as f32;
+        r += f * (i % base) as f32;
+        i /= base;
+    }
+    r
+}
+
+/// Clip space jitter for `frame`: Halton 2, 3 within a pixel, so the samples cover it evenly
+/// in any window of frames. Apply with `View::jittered` to the view the scene renders with.
+pub fn jitter(frame: u64, extent: [u32; 2]) -> Vec2 {
+    let i = frame % JITTER_PHASES + 1;
+    Vec2::new((halton(i, 2) - 0.5) * 2.0 / extent[0] as f32, (halton(i, 3) - 0.5) * 2.0 / extent[1] as f32)
+}
+
+/// First in the stack, on the raw HDR scene. The resolve goes to the stack's history pair and
+/// is sharpened from there into the output.
+pub struct TaaPass {
+    resolve: Arc<ComputePipeline>,
+    sharpen: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+    /// Last frame's unjittered camera.
+    previous_view_proj: Mat4,
+}
+
+impl TaaPass {
+    pub fn new(dev: Arc<Device>) -> Self {
+        let resolve = ComputePipeline::new(dev.clone(), resolve::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let sharpen = ComputePipeline::new(dev.clone(), sharpen::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let sampler = Sampler::new(dev, SamplerCreateInfo { mag_filter: Filter::Linear, min_filter: Filter::Linear,
+                                                            address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        TaaPass { resolve, sharpen, sampler, previous_view_proj: Mat4::IDENTITY }
+    }
+}
+
+impl PostEffect for TaaPass {
+    fn name(&self) -> &'static str { "taa" }
+    fn enabled(&self, ctx: &PostContext) -> bool { ctx.scene.taa.is_some() }
+    fn output_format(&self, _input: Format) -> Format { SCENE_FORMAT }
+    fn history_format(&self) -> Option<Format> { Some(SCENE_FORMAT) }
+
+    fn record(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, ctx: &PostContext, input: &PostImage, output: &PostImage,
+              history: Option<&History>) {
+        let taa = ctx.scene.taa.unwrap();
+        let history = history.unwrap();
+        let view_proj = ctx.view.unjittered_view_proj();
+        let reproject = std::mem::replace(&mut self.previous_view_proj, view_proj) * view_proj.inverse();
+        let extent = super::extent(output);
+        let groups = [(extent[0] + 7) / 8, (extent[1] + 7) / 8, 1];
+
+        //without history the previous image is never read, but the binding still wants one
+        let previous = history.previous.clone().unwrap_or_else(|| input.clone());
+        let set = PersistentDescriptorSet::new(self.resolve.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, input.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler(1, ctx.target.depth.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler(2, ctx.target.velocity.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler(3, previous, self.sampler.clone()),
+            WriteDescriptorSet::image_view(4, history.current.clone()),
+        ]).unwrap();
+        let jitter_uv = ctx.view.jitter * 0.5;
+        let pc = resolve::ty::PushConstants { reproject: reproject.to_cols_array_2d(),
+                                              params: [taa.feedback, history.previous.is_some() as u32 as f32, jitter_uv.x, jitter_uv.y] };
+        builder.bind_pipeline_compute(self.resolve.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.resolve.layout().clone(), 0, set)
+            .push_constants(self.resolve.layout().clone(), 0, pc)
+            .dispatch(groups).unwrap();
+
+        let set = PersistentDescriptorSet::new(self.sharpen.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, history.current.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view(1, output.clone()),
+        ]).unwrap();
+        builder.bind_pipeline_compute(self.sharpen.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.sharpen.layout().clone(), 0, set)
+            .push_constants(self.sharpen.layout().clone(), 0, sharpen::ty::PushConstants { amount: taa.sharpness })
+            .dispatch(groups).unwrap();
+    }
+}
@@ -0,0 +1,80 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               sampler::{ Sampler, SamplerCreateInfo },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint },
+               format::Format };
+use std::sync::Arc;
+use super::{ PostEffect, PostContext, PostImage, History, LDR_FORMAT };
+use super::exposure::{ AutoExposure, ExposureController };
+
+mod cs {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/tonemap.comp", include: ["src/shaders"] }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TonemapOperator {
+    /// Filmic, with a toe and hue shifts in bright saturated colors. SDR only, HDR displays get `Neutral`.
+    Aces,
+    /// c / (1 + c / headroom). Never quite reaches white.
+    Reinhard,
+    /// Leaves everything below 0.76 alone and only rolls off the highlights.
+    Neutral,
+}
+
+/// Maps the HDR scene into displayable range.
+#[derive(Clone, Copy, Debug)]
+pub struct Tonemap {
+    pub operator: TonemapOperator,
+    /// Stops; each one doubles the brightness going in. Compensation on top of `auto_exposure`,
+    /// or of the camera's physical exposure without it.
+    pub exposure: f32,
+    /// Adapt to the scene's brightness; None leaves exposure fixed.
+    pub auto_exposure: Option<AutoExposure>,
+}
+
+impl Default for Tonemap {
+    fn default() -> Self { Tonemap { operator: TonemapOperator::Aces, exposure: 0.0, auto_exposure: None } }
+}
+
+/// Runs while `scene.tonemap` is set; without it the HDR image is clamped on the way out.
+/// Everything after it in the stack sees 0..1 color, up to the headroom above that on HDR displays.
+pub struct TonemapPass {
+    pipeline: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+    exposure: ExposureController,
+}
+
+impl TonemapPass {
+    pub fn new(dev: Arc<Device>) -> Self {
+        let pipeline = ComputePipeline::new(dev.clone(), cs::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo::default()).unwrap();
+        TonemapPass { pipeline, sampler, exposure: ExposureController::new(dev) }
+    }
+}
+
+impl PostEffect for TonemapPass {
+    fn name(&self) -> &'static str { "tonemap" }
+    fn enabled(&self, ctx: &PostContext) -> bool { ctx.scene.tonemap.is_some() }
+    fn output_format(&self, _input: Format) -> Format { LDR_FORMAT }
+
+    fn record(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, ctx: &PostContext, input: &PostImage, output: &PostImage,
+              _history: Option<&History>) {
+        let tonemap = ctx.scene.tonemap.unwrap();
+        let extent = super::extent(output);
+        if let Some(auto) = &tonemap.auto_exposure { self.exposure.record(builder, input, extent, auto, ctx.time.delta); }
+        let set = PersistentDescriptorSet::new(self.pipeline.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, input.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view(1, output.clone()),
+            WriteDescriptorSet::buffer(2, self.exposure.exposure.clone()),
+        ]).unwrap();
+        let operator = match tonemap.operator { TonemapOperator::Aces => 0, TonemapOperator::Reinhard => 1, TonemapOperator::Neutral => 2 };
+        let physical = match (&tonemap.auto_exposure, ctx.camera.physical) { (None, Some(p)) => p.exposure(), _ => 1.0 };
+        let pc = cs::ty::PushConstants { exposure: tonemap.exposure.exp2() * physical, operator, adapted: tonemap.auto_exposure.is_some() as u32,
+                                         headroom: ctx.headroom };
+        builder.bind_pipeline_compute(self.pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.pipeline.layout().clone(), 0, set)
+            .push_constants(self.pipeline.layout().clone(), 0, pc)
+            .dispatch([(extent[0] + 7) / 8, (extent[1] + 7) / 8, 1]).unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode, Filter },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint },
+               format::Format };
+use glam::Vec3;
+use std::sync::Arc;
+use std::time::Instant;
+use super::{ PostEffect, PostContext, PostImage, History, LDR_FORMAT, extent, storage_image };
+
+mod cs {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/transition.comp", include: ["src/shaders"] }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WipePattern {
+    LeftToRight,
+    TopToBottom,
+    /// From the top left corner.
+    Diagonal,
+    /// A circle opening from the center.
+    Radial,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransitionKind {
+    /// To the color, which then stays until the next transition or `clear`.
+    FadeOut(Vec3),
+    /// From the color.
+    FadeIn(Vec3),
+    /// From the frame the transition started on to the live image.
+    Crossfade,
+    /// Same, with the live image revealed along a pattern.
+    Wipe(WipePattern),
+}
+
+/// Full screen transitions over the final image, on the real clock so they play while the
+/// game is paused. Crossfades and wipes start from the image on the frame `start` was called:
+/// start them first and change what's on screen from the next frame.
+pub struct Transitions {
+    /// Wipe edge width, in fractions of the screen.
+    pub softness: f32,
+    current: Option<(TransitionKind, Instant, f32)>,
+    /// Bumped by `start`, so the pass knows to capture.
+    generation: u64,
+}
+
+impl Transitions {
+    pub fn new() -> Self { Transitions { softness: 0.05, current: None, generation: 0 } }
+
+    /// `duration` in seconds.
+    pub fn start(&mut self, kind: TransitionKind, duration: f32) {
+        self.current = Some((kind, Instant::now(), duration.max(1e-3)));
+        self.generation += 1;
+    }
+
+    /// Drops whatever is showing, including a finished fade out.
+    pub fn clear(&mut self) { self.current = None; }
+
+    /// The transition and how far it is, 0..1, while it shows.
+    pub fn progress(&self) -> Option<(TransitionKind, f32)> {
+        let (kind, start, duration) = self.current?;
+        let t = start.elapsed().as_secs_f32() / duration;
+        match kind {
+            TransitionKind::FadeOut(_) => Some((kind, t.min(1.0))),
+            _ if t >= 1.0 => None,
+            _ => Some((kind, t)),
+        }
+    }
+
+    pub fn is_running(&self) -> bool { self.current.map_or(false, |(_, start, duration)| start.elapsed().as_secs_f32() < duration) }
+}
+
+/// Runs while `scene.transitions` shows something, last in the stack.
+pub struct TransitionPass {
+    dev: Arc<Device>,
+    pipeline: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+    /// The starting frame, and the generation it was captured for.
+    from: Option<(PostImage, u64)>,
+}
+
+impl TransitionPass {
+    pub fn new(dev: Arc<Device>) -> Self {
+        let pipeline = ComputePipeline::new(dev.clone(), cs::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo { mag_filter: Filter::Linear, min_filter: Filter::Linear,
+                                                                    address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        TransitionPass { dev, pipeline, sampler, from: None }
+    }
+
+    fn dispatch(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, to: &PostImage, output: &PostImage, from: &PostImage,
+                pc: cs::ty::PushConstants) {
+        let set = PersistentDescriptorSet::new(self.pipeline.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, to.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view(1, output.clone()),
+            WriteDescriptorSet::image_view_sampler(2, from.clone(), self.sampler.clone()),
+        ]).unwrap();
+        let size = extent(output);
+        builder.bind_pipeline_compute(self.pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.pipeline.layout().clone(), 0, set)
+            .push_constants(self.pipeline.layout().clone(), 0, pc)
+            .dispatch([(size[0] + 7) / 8, (size[1] + 7) / 8, 1]).unwrap();
+    }
+}
+
+impl PostEffect for TransitionPass {
+    fn name(&self) -> &'static str { "transition" }
+    fn enabled(&self, ctx: &PostContext) -> bool { ctx.scene.transitions.progress().is_some() }
+    fn output_format(&self, _input: Format) -> Format { LDR_FORMAT }
+
+    fn record(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, ctx: &PostContext, input: &PostImage, output: &PostImage,
+              _history: Option<&History>) {
+        let transitions = &ctx.scene.transitions;
+        let (kind, progress) = transitions.progress().unwrap();
+        let generation = transitions.generation;
+        if self.from.as_ref().map_or(true, |(image, g)| *g != generation || extent(image) != extent(input)) {
+            //a straight copy through the fade path, so any input format works
+            let image = storage_image(&self.dev, LDR_FORMAT, extent(input));
+            self.dispatch(builder, input, &image, input, cs::ty::PushConstants { color: [0.0; 4], kind: 0, progress: 0.0, softness: 0.0 });
+            self.from = Some((image, generation));
+        }
+        let (color, kind, progress) = match kind {
+            TransitionKind::FadeOut(c) => (c, 0, progress),
+            TransitionKind::FadeIn(c) => (c, 0, 1.0 - progress),
+            TransitionKind::Crossfade => (Vec3::ZERO, 1, progress),
+            TransitionKind::Wipe(pattern) => (Vec3::ZERO, 2 + pattern as u32, progress),
+        };
+        let from = self.from.as_ref().unwrap().0.clone();
+        self.dispatch(builder, input, output, &from, cs::ty::PushConstants { color: color.extend(1.0).to_array(), kind, progress, softness: transitions.softness });
+    }
+}
@@ -0,0 +1,149 @@
+use vulkano::{ device::Queue,
+               command_buffer::{ AutoCommandBufferBuilder, CommandBufferUsage, PrimaryCommandBuffer },
+               image::{ AttachmentImage, view::ImageView },
+               sync::GpuFuture };
+use glam::Vec3;
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::scene::{ Scene, EntityId };
+use crate::camera::{ Camera, Projection };
+use crate::culling::Frustum;
+use crate::ibl::Environment;
+use crate::renderer::{ Renderer, Target };
+
+pub const MAX_PROBES: usize = 4;
+
+/// Influence volume, also used as the proxy geometry for parallax correction. Boxes are axis
+/// aligned around the entity's position; its rotation and scale are ignored.
+#[derive(Clone, Copy, Debug)]
+pub enum ProbeShape {
+    Sphere { radius: f32 },
+    Box { half_extent: Vec3 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProbeRefresh {
+    /// Captured once, or again after `ReflectionProbe::invalidate`.
+    Baked,
+    /// Recaptured every n frames.
+    Every(u32),
+}
+
+/// Reflection probe component, captured from the entity's position.
+#[derive(Clone)]
+pub struct ReflectionProbe {
+    pub shape: ProbeShape,
+    /// Distance inside the volume over which the probe fades in.
+    pub blend: f32,
+    pub refresh: ProbeRefresh,
+    /// Latest capture, None until the first one.
+    pub environment: Option<Arc<Environment>>,
+    age: u32,
+    stale: bool,
+}
+
+impl ReflectionProbe {
+    pub fn new(shape: ProbeShape) -> Self { ReflectionProbe { shape, blend: 0.5, refresh: ProbeRefresh::Baked, environment: None, age: 0, stale: false } }
+
+    pub fn invalidate(&mut self) { self.environment = None; }
+
+    /// Recapture when the budget allows, keeping the current capture on screen until then.
+    pub fn mark_stale(&mut self) { self.stale = true; }
+
+    fn due(&self) -> bool {
+        match self.refresh {
+            _ if self.environment.is_none() || self.stale => true,
+            ProbeRefresh::Baked => false,
+            ProbeRefresh::Every(n) => self.age >= n,
+        }
+    }
+
+    /// Rough size, smaller probes take priority where they overlap bigger ones.
+    pub fn volume(&self) -> f32 {
+        match self.shape {
+            ProbeShape::Sphere { radius } => radius * radius * radius * 4.19,
+            ProbeShape::Box { half_extent } => half_extent.x * half_extent.y * half_extent.z * 8.0,
+        }
+    }
+}
+
+/// Captured probe as the lit shader sees it.
+pub struct ActiveProbe {
+    pub position: Vec3,
+    pub shape: ProbeShape,
+    pub blend: f32,
+    pub environment: Arc<Environment>,
+}
+
+/// Captured probes nearest `eye`, smallest first so the shader lets them override bigger ones.
+pub fn gather(scene: &Scene, eye: Vec3) -> Vec<ActiveProbe> {
+    let mut probes: Vec<_> = scene.entities.iter()
+        .filter_map(|e| { let p = e.probe.as_ref()?; Some((e.position(), p, p.environment.clone()?)) })
+        .collect();
+    probes.sort_by(|a, b| a.0.distance_squared(eye).total_cmp(&b.0.distance_squared(eye)));
+    probes.truncate(MAX_PROBES);
+    probes.sort_by(|a, b| a.1.volume().total_cmp(&b.1.volume()));
+    probes.into_iter().map(|(position, p, environment)| ActiveProbe { position, shape: p.shape, blend: p.blend, environment }).collect()
+}
+
+/// Cube face (look direction, up) in vulkan order. Rendered with x mirrored, since cubemaps
+/// are left handed, so texel uvs come out the way the samplers expect them.
+const FACES: [(Vec3, Vec3); 6] = [(Vec3::X, Vec3::Y), (Vec3::NEG_X, Vec3::Y), (Vec3::Y, Vec3::NEG_Z),
+                                  (Vec3::NEG_Y, Vec3::Z), (Vec3::Z, Vec3::Y), (Vec3::NEG_Z, Vec3::Y)];
+
+/// Captures reflection probes. Captures are submitted and waited on straight away, so call
+/// `update` before recording the frame; `budget` limits how many run per frame.
+pub struct ReflectionProbes {
+    queue: Arc<Queue>,
+    pub resolution: u32,
+    pub budget: usize,
+    faces: Vec<(Target, Arc<ImageView<AttachmentImage>>)>,
+}
+
+impl ReflectionProbes {
+    pub fn new(queue: Arc<Queue>) -> Self { ReflectionProbes { queue, resolution: 128, budget: 1, faces: Vec::new() } }
+
+    pub fn update(&mut self, renderer: &Renderer, scene: &mut Scene) {
+        for e in scene.entities.iter_mut() {
+            if let Some(p) = &mut e.probe { p.age += 1; }
+        }
+        let mut due: Vec<_> = scene.entities.iter().filter(|e| e.probe.as_ref().map_or(false, |p| p.due())).map(|e| e.id).collect();
+        due.truncate(self.budget);
+        for id in due {
+            let env = self.capture(renderer, scene, scene.get(id).unwrap().position());
+            let probe = scene.get_mut(id).unwrap().probe.as_mut().unwrap();
+            probe.environment = Some(env);
+            probe.age = 0;
+            probe.stale = false;
+        }
+    }
+
+    /// Renders the scene around `position` and prefilters it like any other environment.
+    pub fn capture(&mut self, renderer: &Renderer, scene: &Scene, position: Vec3) -> Arc<Environment> {
+        let faces = self.capture_faces(renderer, scene, position);
+        renderer.ibl.bake_faces(&faces, self.resolution)
+    }
+
+    /// The six cube face renders around `position`, finished by the time this returns. They are
+    /// reused by the next capture.
+    pub fn capture_faces(&mut self, renderer: &Renderer, scene: &Scene, position: Vec3) -> [Arc<ImageView<AttachmentImage>>; 6] {
+        let extent = [self.resolution, self.resolution];
+        if self.faces.first().map_or(true, |(t, _)| t.extent != extent) {
+            self.faces = (0..6).map(|_| renderer.offscreen_target(extent)).collect();
+        }
+        let mut builder = AutoCommandBufferBuilder::primary(self.queue.device().clone(), self.queue.family(), CommandBufferUsage::OneTimeSubmit).unwrap();
+        let none = HashMap::<EntityId, _>::new();
+        for ((target, _), (dir, up)) in self.faces.iter().zip(FACES) {
+            let mut camera = Camera::new(position, position + dir);
+            camera.up = up;
+            camera.projection = Projection::Perspective { fov_y: std::f32::consts::FRAC_PI_2 };
+            let mut view = camera.as_view();
+            view.proj.x_axis.x *= -1.0;
+            let frustum = Frustum::from_view_proj(&view.view_proj());
+            renderer.draw(&mut builder, target, scene, &view, &|e| e.portal.is_none() && frustum.intersects_aabb(&e.world_bounds()), &none, None);
+        }
+        builder.build().unwrap().execute(self.queue.clone()).unwrap()
+            .then_signal_fence_and_flush().unwrap().wait(None).unwrap();
+        [0, 1, 2, 3, 4, 5].map(|i| self.faces[i].1.clone())
+    }
+}
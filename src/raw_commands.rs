@@ -0,0 +1,86 @@
+use vulkano::{ buffer::BufferAccess,
+               command_buffer::{ SecondaryCommandBuffer, CommandBufferExecError, CommandBufferBeginInfo, CommandBufferInheritanceInfo,
+                                 CommandBufferUsage, CommandBufferLevel,
+                                 pool::{ CommandPool, CommandPoolBuilderAlloc, standard::StandardCommandPoolAlloc },
+                                 sys::{ UnsafeCommandBuffer, UnsafeCommandBufferBuilder } },
+               device::{ Device, DeviceOwned, Queue, DeviceFunctions },
+               image::{ ImageAccess, ImageLayout, ImageUninitializedSafe },
+               sync::{ AccessFlags, PipelineMemoryAccess, PipelineStages },
+               VulkanObject };
+use std::any::Any;
+use std::sync::Arc;
+
+/// Commands vulkano has no api for, recorded with ash into a secondary command buffer that an
+/// `AutoCommandBufferBuilder` executes like one of its own, outside a render pass. vulkano
+/// orders and transitions around the buffers and images listed with `buffer` and `image`, the
+/// images kept in `GENERAL` layout; anything else the commands touch they order themselves.
+pub struct RawCommands {
+    inner: UnsafeCommandBuffer,
+    inheritance: CommandBufferInheritanceInfo,
+    buffers: Vec<(Arc<dyn BufferAccess>, PipelineMemoryAccess)>,
+    images: Vec<(Arc<dyn ImageAccess>, PipelineMemoryAccess)>,
+    /// Whatever the commands use that has to live until they're done.
+    _keep: Vec<Box<dyn Any + Send + Sync>>,
+    //back to the device's pool once the command buffer executing this one is dropped
+    _alloc: StandardCommandPoolAlloc,
+}
+
+fn access(write: bool) -> PipelineMemoryAccess {
+    PipelineMemoryAccess { stages: PipelineStages { all_commands: true, ..PipelineStages::none() },
+                           access: AccessFlags { shader_read: true, shader_write: write, ..AccessFlags::none() }, exclusive: write }
+}
+
+impl RawCommands {
+    /// `record` gets the device's function pointers and the command buffer to record into.
+    /// Only `SimultaneousUse` ones can be executed more than once.
+    pub fn record(queue: &Arc<Queue>, usage: CommandBufferUsage, record: impl FnOnce(&DeviceFunctions, ash::vk::CommandBuffer)) -> Self {
+        let dev = queue.device();
+        let inheritance = CommandBufferInheritanceInfo::default();
+        let alloc = Device::standard_command_pool(dev, queue.family()).allocate(CommandBufferLevel::Secondary, 1).unwrap().next().unwrap();
+        let inner = unsafe {
+            let builder = UnsafeCommandBufferBuilder::new(alloc.inner(), CommandBufferBeginInfo {
+                usage, inheritance_info: Some(inheritance.clone()), ..Default::default() }).unwrap();
+            record(dev.fns(), builder.internal_object());
+            builder.build().unwrap()
+        };
+        RawCommands { inner, inheritance, buffers: Vec::new(), images: Vec::new(), _keep: Vec::new(), _alloc: alloc.into_alloc() }
+    }
+
+    /// `buffer` is read, or written, by the commands.
+    pub fn buffer(mut self, buffer: Arc<dyn BufferAccess>, write: bool) -> Self {
+        self.buffers.push((buffer, access(write)));
+        self
+    }
+
+    /// `image` is read, or written, by the commands, in `GENERAL` layout.
+    pub fn image(mut self, image: Arc<dyn ImageAccess>, write: bool) -> Self {
+        self.images.push((image, access(write)));
+        self
+    }
+
+    /// Holds on to `value` until the commands are done with it.
+    pub fn keep(mut self, value: impl Any + Send + Sync) -> Self {
+        self._keep.push(Box::new(value));
+        self
+    }
+}
+
+unsafe impl DeviceOwned for RawCommands {
+    fn device(&self) -> &Arc<Device> { self.inner.device() }
+}
+
+unsafe impl SecondaryCommandBuffer for RawCommands {
+    fn inner(&self) -> &UnsafeCommandBuffer { &self.inner }
+    //a one time one is only ever executed once, see record
+    fn lock_record(&self) -> Result<(), CommandBufferExecError> { Ok(()) }
+    unsafe fn unlock(&self) {}
+    fn inheritance_info(&self) -> &CommandBufferInheritanceInfo { &self.inheritance }
+    fn num_buffers(&self) -> usize { self.buffers.len() }
+    fn buffer(&self, index: usize) -> Option<(&Arc<dyn BufferAccess>, PipelineMemoryAccess)> {
+        self.buffers.get(index).map(|(b, a)| (b, *a))
+    }
+    fn num_images(&self) -> usize { self.images.len() }
+    fn image(&self, index: usize) -> Option<(&Arc<dyn ImageAccess>, PipelineMemoryAccess, ImageLayout, ImageLayout, ImageUninitializedSafe)> {
+        self.images.get(index).map(|(i, a)| (i, *a, ImageLayout::General, ImageLayout::General, ImageUninitializedSafe::Unsafe))
+    }
+}
@@ -0,0 +1,535 @@
+use vulkano::{ device::{ Device, DeviceExtensions, DeviceFunctions, Features, Queue, physical::PhysicalDevice },
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, CommandBufferUsage },
+               image::view::ImageViewAbstract,
+               sampler::Sampler,
+               shader::ShaderModule,
+               VulkanObject, Version };
+use ash::vk;
+use bytemuck::{ Pod, Zeroable };
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::mesh::{ Mesh, Vertex };
+use crate::scene::Scene;
+use crate::raw_commands::RawCommands;
+
+/// Which of the ray tracing extensions the device has, and were asked for. Opt in with
+/// `ARSE_RAY_TRACING=1`; without it nothing ray tracing related gets enabled, so devices that
+/// have it pay nothing.
+///
+/// vulkano 0.29 has no acceleration structure or ray tracing pipeline api, so `RayTracing`,
+/// `RtPipeline` and the passes on them make their objects through ash and record with
+/// `RawCommands`. Anything that would use them checks here first and keeps its raster
+/// fallback otherwise.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RayTracingSupport {
+    /// `VK_KHR_acceleration_structure` and `VK_KHR_ray_tracing_pipeline`.
+    pub pipeline: bool,
+    /// `VK_KHR_acceleration_structure` and `VK_KHR_ray_query`, for tracing from compute.
+    pub query: bool,
+}
+
+impl RayTracingSupport {
+    pub fn requested() -> bool { std::env::var("ARSE_RAY_TRACING").map_or(false, |v| v == "1") }
+
+    /// What `physical` supports, or none of it if ray tracing wasn't requested. Vulkan 1.2
+    /// brings in the spir-v 1.4 and descriptor indexing the extensions need.
+    pub fn detect(physical: PhysicalDevice) -> Self {
+        if !Self::requested() || physical.api_version() < Version::V1_2 || physical.instance().api_version() < Version::V1_2 { return Self::default(); }
+        let ext = physical.supported_extensions();
+        let features = physical.supported_features();
+        let structures = ext.khr_acceleration_structure && ext.khr_deferred_host_operations && ext.khr_buffer_device_address
+            && features.acceleration_structure && features.buffer_device_address;
+        RayTracingSupport { pipeline: structures && ext.khr_ray_tracing_pipeline && features.ray_tracing_pipeline,
+                            query: structures && ext.khr_ray_query && features.ray_query }
+    }
+
+    /// What `dev` was made with.
+    pub fn enabled(dev: &Device) -> Self {
+        let ext = dev.enabled_extensions();
+        RayTracingSupport { pipeline: ext.khr_ray_tracing_pipeline, query: ext.khr_ray_query }
+    }
+
+    pub fn any(&self) -> bool { self.pipeline || self.query }
+
+    /// To union into the device's enabled extensions.
+    pub fn extensions(&self) -> DeviceExtensions {
+        if !self.any() { return DeviceExtensions::none(); }
+        DeviceExtensions { khr_acceleration_structure: true, khr_deferred_host_operations: true, khr_buffer_device_address: true,
+                           khr_ray_tracing_pipeline: self.pipeline, khr_ray_query: self.query, ..DeviceExtensions::none() }
+    }
+
+    pub fn features(&self) -> Features {
+        if !self.any() { return Features::none(); }
+        Features { acceleration_structure: true, buffer_device_address: true, ray_tracing_pipeline: self.pipeline, ray_query: self.query,
+                   ..Features::none() }
+    }
+}
+
+fn align(value: u64, alignment: u64) -> u64 { (value + alignment - 1) / alignment * alignment }
+
+/// An execution and memory dependency from everything before it in the queue to everything after.
+pub unsafe fn barrier(fns: &DeviceFunctions, cb: vk::CommandBuffer, src: (vk::PipelineStageFlags, vk::AccessFlags), dst: (vk::PipelineStageFlags, vk::AccessFlags)) {
+    let memory = vk::MemoryBarrier { src_access_mask: src.1, dst_access_mask: dst.1, ..Default::default() };
+    (fns.v1_0.cmd_pipeline_barrier)(cb, src.0, dst.0, vk::DependencyFlags::empty(), 1, &memory, 0, std::ptr::null(), 0, std::ptr::null());
+}
+
+/// A buffer made through ash, with the device address the structure builds and the shader
+/// binding table take instead of a handle; vulkano 0.29 doesn't allocate memory that has one.
+/// Host visible ones stay mapped.
+pub struct RtBuffer {
+    dev: Arc<Device>,
+    pub handle: vk::Buffer,
+    memory: vk::DeviceMemory,
+    mapped: *mut u8,
+    pub size: u64,
+    pub address: u64,
+}
+
+//the mapping is only written through `write`, which the owner calls
+unsafe impl Send for RtBuffer {}
+unsafe impl Sync for RtBuffer {}
+
+impl RtBuffer {
+    pub fn new(dev: &Arc<Device>, size: u64, usage: vk::BufferUsageFlags, host: bool) -> Self {
+        let (d, fns) = (dev.internal_object(), dev.fns());
+        unsafe {
+            let info = vk::BufferCreateInfo { size, usage: usage | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS, sharing_mode: vk::SharingMode::EXCLUSIVE, ..Default::default() };
+            let mut handle = vk::Buffer::null();
+            (fns.v1_0.create_buffer)(d, &info, std::ptr::null(), &mut handle).result().unwrap();
+            let mut requirements = vk::MemoryRequirements::default();
+            (fns.v1_0.get_buffer_memory_requirements)(d, handle, &mut requirements);
+            let memory_type = dev.physical_device().memory_types()
+                .filter(|t| requirements.memory_type_bits & (1 << t.id()) != 0)
+                .find(|t| if host { t.is_host_visible() && t.is_host_coherent() } else { t.is_device_local() })
+                .expect("no memory type for a ray tracing buffer");
+            let flags = vk::MemoryAllocateFlagsInfo { flags: vk::MemoryAllocateFlags::DEVICE_ADDRESS, ..Default::default() };
+            let allocate = vk::MemoryAllocateInfo { p_next: &flags as *const _ as *const std::ffi::c_void, allocation_size: requirements.size,
+                                                    memory_type_index: memory_type.id(), ..Default::default() };
+            let mut memory = vk::DeviceMemory::null();
+            (fns.v1_0.allocate_memory)(d, &allocate, std::ptr::null(), &mut memory).result().unwrap();
+            (fns.v1_0.bind_buffer_memory)(d, handle, memory, 0).result().unwrap();
+            let mut mapped = std::ptr::null_mut();
+            if host { (fns.v1_0.map_memory)(d, memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty(), &mut mapped).result().unwrap(); }
+            let address = (fns.v1_2.get_buffer_device_address)(d, &vk::BufferDeviceAddressInfo { buffer: handle, ..Default::default() });
+            RtBuffer { dev: dev.clone(), handle, memory, mapped: mapped as *mut u8, size, address }
+        }
+    }
+
+    /// Host visible ones only, and nothing on the gpu may still be reading what it overwrites.
+    pub fn write(&self, offset: u64, bytes: &[u8]) {
+        assert!(!self.mapped.is_null() && offset + bytes.len() as u64 <= self.size);
+        unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), self.mapped.add(offset as usize), bytes.len()); }
+    }
+}
+
+impl Drop for RtBuffer {
+    fn drop(&mut self) {
+        let (d, fns) = (self.dev.internal_object(), self.dev.fns());
+        unsafe {
+            (fns.v1_0.destroy_buffer)(d, self.handle, std::ptr::null());
+            (fns.v1_0.free_memory)(d, self.memory, std::ptr::null());
+        }
+    }
+}
+
+/// An acceleration structure and the buffer it's in.
+struct Structure {
+    handle: vk::AccelerationStructureKHR,
+    buffer: RtBuffer,
+    address: u64,
+}
+
+impl Structure {
+    fn new(dev: &Arc<Device>, ty: vk::AccelerationStructureTypeKHR, size: u64) -> Self {
+        let (d, fns) = (dev.internal_object(), dev.fns());
+        let buffer = RtBuffer::new(dev, size, vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR, false);
+        unsafe {
+            let info = vk::AccelerationStructureCreateInfoKHR { buffer: buffer.handle, size, ty, ..Default::default() };
+            let mut handle = vk::AccelerationStructureKHR::null();
+            (fns.khr_acceleration_structure.create_acceleration_structure_khr)(d, &info, std::ptr::null(), &mut handle).result().unwrap();
+            let address = (fns.khr_acceleration_structure.get_acceleration_structure_device_address_khr)(d,
+                &vk::AccelerationStructureDeviceAddressInfoKHR { acceleration_structure: handle, ..Default::default() });
+            Structure { handle, buffer, address }
+        }
+    }
+}
+
+impl Drop for Structure {
+    fn drop(&mut self) {
+        let dev = &self.buffer.dev;
+        unsafe { (dev.fns().khr_acceleration_structure.destroy_acceleration_structure_khr)(dev.internal_object(), self.handle, std::ptr::null()); }
+    }
+}
+
+/// Records building a structure of `geometry`'s `primitives` into `cb`, into `reuse` and its
+/// scratch where they're big enough. The scratch has to live until the build is done.
+fn build(dev: &Arc<Device>, cb: vk::CommandBuffer, ty: vk::AccelerationStructureTypeKHR, geometry: &vk::AccelerationStructureGeometryKHR, primitives: u32,
+         reuse: Option<(Structure, RtBuffer)>) -> (Structure, RtBuffer) {
+    let (d, fns) = (dev.internal_object(), dev.fns());
+    let mut info = vk::AccelerationStructureBuildGeometryInfoKHR { ty, flags: vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE,
+                                                                   mode: vk::BuildAccelerationStructureModeKHR::BUILD, geometry_count: 1, p_geometries: geometry, ..Default::default() };
+    let mut sizes = vk::AccelerationStructureBuildSizesInfoKHR::default();
+    unsafe { (fns.khr_acceleration_structure.get_acceleration_structure_build_sizes_khr)(d, vk::AccelerationStructureBuildTypeKHR::DEVICE, &info, &primitives, &mut sizes); }
+    let alignment = dev.physical_device().properties().min_acceleration_structure_scratch_offset_alignment.unwrap_or(256) as u64;
+    let (structure, scratch) = match reuse {
+        Some((s, scratch)) if s.buffer.size >= sizes.acceleration_structure_size && scratch.size >= sizes.build_scratch_size + alignment => (s, scratch),
+        _ => (Structure::new(dev, ty, sizes.acceleration_structure_size),
+              RtBuffer::new(dev, sizes.build_scratch_size + alignment, vk::BufferUsageFlags::STORAGE_BUFFER, false)),
+    };
+    info.dst_acceleration_structure = structure.handle;
+    info.scratch_data = vk::DeviceOrHostAddressKHR { device_address: align(scratch.address, alignment) };
+    let range = vk::AccelerationStructureBuildRangeInfoKHR { primitive_count: primitives, ..Default::default() };
+    unsafe { (fns.khr_acceleration_structure.cmd_build_acceleration_structures_khr)(cb, 1, &info, &(&range as *const _)); }
+    (structure, scratch)
+}
+
+/// Bottom level structure over a mesh's triangles, as they are in its vertex buffer; skinned
+/// and morphed meshes are in their rest pose. Its copy of the vertices has a device address
+/// for hit shaders to read them by, see `Instance`.
+pub struct Blas {
+    structure: Structure,
+    vertices: RtBuffer,
+}
+
+impl Blas {
+    /// Returns the scratch with it, to keep until the build is done.
+    fn record(dev: &Arc<Device>, cb: vk::CommandBuffer, mesh: &Mesh) -> (Blas, RtBuffer) {
+        let bytes: &[u8] = bytemuck::cast_slice(&mesh.vertices);
+        let vertices = RtBuffer::new(dev, bytes.len() as u64,
+                                     vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR | vk::BufferUsageFlags::STORAGE_BUFFER, true);
+        vertices.write(0, bytes);
+        let triangles = vk::AccelerationStructureGeometryTrianglesDataKHR {
+            vertex_format: vk::Format::R32G32B32_SFLOAT, vertex_data: vk::DeviceOrHostAddressConstKHR { device_address: vertices.address },
+            vertex_stride: std::mem::size_of::<Vertex>() as u64, max_vertex: mesh.vertices.len() as u32 - 1, index_type: vk::IndexType::NONE_KHR, ..Default::default() };
+        let geometry = vk::AccelerationStructureGeometryKHR { geometry_type: vk::GeometryTypeKHR::TRIANGLES,
+                                                              geometry: vk::AccelerationStructureGeometryDataKHR { triangles }, flags: vk::GeometryFlagsKHR::OPAQUE, ..Default::default() };
+        let (structure, scratch) = build(dev, cb, vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL, &geometry, (mesh.vertices.len() / 3) as u32, None);
+        (Blas { structure, vertices }, scratch)
+    }
+}
+
+/// What hit shaders know of a TLAS instance, indexed by `gl_InstanceCustomIndexEXT`. Keep in
+/// step with `Instance` in rt_lighting.rchit.
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct Instance {
+    base_color: [f32; 4],
+    /// Of the mesh's vertices, a `mesh::Vertex` per triangle corner.
+    vertices: u64,
+    _pad: u64,
+}
+
+/// Top level structure over every entity with a mesh, and the `Instance` table for its hit
+/// shaders. Its instances are written on the host, so one still in flight isn't built into.
+pub struct Tlas {
+    structure: Structure,
+    scratch: RtBuffer,
+    /// `vk::AccelerationStructureInstanceKHR`s the build reads.
+    input: RtBuffer,
+    pub instances: RtBuffer,
+    capacity: usize,
+    /// The ones its instances point at, alive as long as it is.
+    _blas: Vec<Arc<Blas>>,
+}
+
+impl Tlas {
+    pub fn handle(&self) -> vk::AccelerationStructureKHR { self.structure.handle }
+
+    fn record(dev: &Arc<Device>, cb: vk::CommandBuffer, instances: Vec<(glam::Mat4, [f32; 4], Arc<Blas>)>, reuse: Option<Tlas>) -> Tlas {
+        let count = instances.len();
+        let (reuse, input, table, capacity) = match reuse {
+            Some(t) if t.capacity >= count => (Some((t.structure, t.scratch)), t.input, t.instances, t.capacity),
+            _ => {
+                let capacity = count.max(1).next_power_of_two();
+                (None, RtBuffer::new(dev, (capacity * std::mem::size_of::<vk::AccelerationStructureInstanceKHR>()) as u64,
+                                     vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR, true),
+                 RtBuffer::new(dev, (capacity * std::mem::size_of::<Instance>()) as u64, vk::BufferUsageFlags::STORAGE_BUFFER, true), capacity)
+            }
+        };
+        let flags = vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE.as_raw() as u8;
+        let structures: Vec<_> = instances.iter().enumerate().map(|(i, (transform, _, blas))| {
+            let rows = transform.transpose().to_cols_array();
+            vk::AccelerationStructureInstanceKHR {
+                transform: vk::TransformMatrixKHR { matrix: rows[..12].try_into().unwrap() },
+                instance_custom_index_and_mask: vk::Packed24_8::new(i as u32, 0xff),
+                instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(0, flags),
+                acceleration_structure_reference: vk::AccelerationStructureReferenceKHR { device_handle: blas.structure.address } }
+        }).collect();
+        let table_entries: Vec<_> = instances.iter().map(|(_, base_color, blas)| Instance { base_color: *base_color, vertices: blas.vertices.address, _pad: 0 }).collect();
+        input.write(0, unsafe { std::slice::from_raw_parts(structures.as_ptr() as *const u8, structures.len() * std::mem::size_of::<vk::AccelerationStructureInstanceKHR>()) });
+        table.write(0, bytemuck::cast_slice(&table_entries));
+        let data = vk::AccelerationStructureGeometryInstancesDataKHR { data: vk::DeviceOrHostAddressConstKHR { device_address: input.address }, ..Default::default() };
+        let geometry = vk::AccelerationStructureGeometryKHR { geometry_type: vk::GeometryTypeKHR::INSTANCES,
+                                                              geometry: vk::AccelerationStructureGeometryDataKHR { instances: data }, ..Default::default() };
+        let (structure, scratch) = build(dev, cb, vk::AccelerationStructureTypeKHR::TOP_LEVEL, &geometry, count as u32, reuse);
+        Tlas { structure, scratch, input, instances: table, capacity, _blas: instances.into_iter().map(|(_, _, b)| b).collect() }
+    }
+}
+
+/// The scene's acceleration structures: a BLAS per mesh, built the first time it's drawn and
+/// dropped with the last entity using it, and a TLAS over them rebuilt every frame.
+pub struct RayTracing {
+    queue: Arc<Queue>,
+    blas: HashMap<usize, (Arc<Mesh>, Arc<Blas>)>,
+    tlas: Option<Arc<Tlas>>,
+    /// Earlier frames' that were still in flight, built into again once they aren't.
+    spare: Vec<Arc<Tlas>>,
+}
+
+impl RayTracing {
+    pub fn new(queue: Arc<Queue>) -> Self { RayTracing { queue, blas: HashMap::new(), tlas: None, spare: Vec::new() } }
+
+    /// The last `build`'s, for passes to trace against; they keep it until they're done.
+    pub fn tlas(&self) -> Option<&Arc<Tlas>> { self.tlas.as_ref() }
+
+    /// Builds the BLAS of meshes it hasn't seen and the frame's TLAS, outside a render pass.
+    #[profiling::function]
+    pub fn build(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, scene: &Scene) {
+        let queue = self.queue.clone();
+        let dev = queue.device().clone();
+        //only the cache holds them any more
+        self.blas.retain(|_, (mesh, _)| Arc::strong_count(mesh) > 1);
+        self.spare.extend(self.tlas.take());
+        let free = self.spare.iter().position(|t| Arc::strong_count(t) == 1);
+        let reuse = free.and_then(|i| Arc::try_unwrap(self.spare.swap_remove(i)).ok());
+        let mut scratch = Vec::new();
+        let commands = RawCommands::record(&queue, CommandBufferUsage::OneTimeSubmit, |fns, cb| unsafe {
+            let mut instances = Vec::new();
+            for e in scene.entities.iter().filter(|e| e.portal.is_none()) {
+                let mesh = match &e.mesh { Some(m) if m.vertices.len() >= 3 => m, _ => continue };
+                let (_, blas) = self.blas.entry(Arc::as_ptr(mesh) as usize).or_insert_with(|| {
+                    let (blas, s) = Blas::record(&dev, cb, mesh);
+                    scratch.push(s);
+                    (mesh.clone(), Arc::new(blas))
+                });
+                instances.push((e.transform, e.material.base_color, blas.clone()));
+            }
+            let build = (vk::PipelineStageFlags::ACCELERATION_STRUCTURE_BUILD_KHR, vk::AccessFlags::ACCELERATION_STRUCTURE_WRITE_KHR);
+            //the top level reads the bottom levels built above
+            if !scratch.is_empty() {
+                barrier(fns, cb, build, (vk::PipelineStageFlags::ACCELERATION_STRUCTURE_BUILD_KHR, vk::AccessFlags::ACCELERATION_STRUCTURE_READ_KHR));
+            }
+            self.tlas = Some(Arc::new(Tlas::record(&dev, cb, instances, reuse)));
+            //traced by ray tracing pipelines and ray queries in compute later in the frame
+            barrier(fns, cb, build, (vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR | vk::PipelineStageFlags::COMPUTE_SHADER,
+                                     vk::AccessFlags::ACCELERATION_STRUCTURE_READ_KHR));
+        });
+        builder.execute_commands(commands.keep(scratch).keep(self.tlas.clone())).unwrap();
+    }
+}
+
+/// A binding of a `RawSetLayout` written.
+pub enum RawWrite<'a> {
+    Structure(&'a Tlas),
+    /// Sampled in `GENERAL` layout, see `RawCommands::image`.
+    Sampled(&'a dyn ImageViewAbstract, &'a Sampler),
+    Storage(&'a dyn ImageViewAbstract),
+    /// Handle, offset and range.
+    Buffer(vk::Buffer, u64, u64),
+    Uniform(vk::Buffer, u64, u64),
+}
+
+impl RawWrite<'_> {
+    fn ty(&self) -> vk::DescriptorType {
+        match self {
+            RawWrite::Structure(_) => vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            RawWrite::Sampled(..) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            RawWrite::Storage(_) => vk::DescriptorType::STORAGE_IMAGE,
+            RawWrite::Buffer(..) => vk::DescriptorType::STORAGE_BUFFER,
+            RawWrite::Uniform(..) => vk::DescriptorType::UNIFORM_BUFFER,
+        }
+    }
+}
+
+/// A descriptor set layout made through ash, for the acceleration structures vulkano 0.29
+/// can't bind. Binding i has the type of the i-th write sets are made with, visible to `stages`.
+pub struct RawSetLayout {
+    dev: Arc<Device>,
+    pub handle: vk::DescriptorSetLayout,
+}
+
+impl RawSetLayout {
+    pub fn new(dev: &Arc<Device>, types: &[vk::DescriptorType], stages: vk::ShaderStageFlags) -> Self {
+        let bindings: Vec<_> = types.iter().enumerate().map(|(i, &descriptor_type)| vk::DescriptorSetLayoutBinding {
+            binding: i as u32, descriptor_type, descriptor_count: 1, stage_flags: stages, ..Default::default() }).collect();
+        let info = vk::DescriptorSetLayoutCreateInfo { binding_count: bindings.len() as u32, p_bindings: bindings.as_ptr(), ..Default::default() };
+        let mut handle = vk::DescriptorSetLayout::null();
+        unsafe { (dev.fns().v1_0.create_descriptor_set_layout)(dev.internal_object(), &info, std::ptr::null(), &mut handle).result().unwrap(); }
+        RawSetLayout { dev: dev.clone(), handle }
+    }
+
+    /// A set of this layout in a pool of its own, which goes with it. Keep it with the commands
+    /// that bind it.
+    pub fn set(&self, writes: &[RawWrite]) -> RawSet {
+        let (d, fns) = (self.dev.internal_object(), self.dev.fns());
+        let sizes: Vec<_> = writes.iter().map(|w| vk::DescriptorPoolSize { ty: w.ty(), descriptor_count: 1 }).collect();
+        unsafe {
+            let mut pool = vk::DescriptorPool::null();
+            let info = vk::DescriptorPoolCreateInfo { max_sets: 1, pool_size_count: sizes.len() as u32, p_pool_sizes: sizes.as_ptr(), ..Default::default() };
+            (fns.v1_0.create_descriptor_pool)(d, &info, std::ptr::null(), &mut pool).result().unwrap();
+            let mut handle = vk::DescriptorSet::null();
+            let info = vk::DescriptorSetAllocateInfo { descriptor_pool: pool, descriptor_set_count: 1, p_set_layouts: &self.handle, ..Default::default() };
+            (fns.v1_0.allocate_descriptor_sets)(d, &info, &mut handle).result().unwrap();
+            //everything the writes point at has to stay put until the update
+            let structures: Vec<_> = writes.iter().map(|w| match w { RawWrite::Structure(t) => t.handle(), _ => vk::AccelerationStructureKHR::null() }).collect();
+            let structure_infos: Vec<_> = structures.iter().map(|s| vk::WriteDescriptorSetAccelerationStructureKHR {
+                acceleration_structure_count: 1, p_acceleration_structures: s, ..Default::default() }).collect();
+            let images: Vec<_> = writes.iter().map(|w| match w {
+                RawWrite::Sampled(view, sampler) => vk::DescriptorImageInfo { sampler: sampler.internal_object(), image_view: view.internal_object(), image_layout: vk::ImageLayout::GENERAL },
+                RawWrite::Storage(view) => vk::DescriptorImageInfo { image_view: view.internal_object(), image_layout: vk::ImageLayout::GENERAL, ..Default::default() },
+                _ => vk::DescriptorImageInfo::default(),
+            }).collect();
+            let buffers: Vec<_> = writes.iter().map(|w| match w {
+                RawWrite::Buffer(buffer, offset, range) | RawWrite::Uniform(buffer, offset, range) => vk::DescriptorBufferInfo { buffer: *buffer, offset: *offset, range: *range },
+                _ => vk::DescriptorBufferInfo::default(),
+            }).collect();
+            let updates: Vec<_> = writes.iter().enumerate().map(|(i, w)| vk::WriteDescriptorSet {
+                p_next: match w { RawWrite::Structure(_) => &structure_infos[i] as *const _ as *const std::ffi::c_void, _ => std::ptr::null() },
+                dst_set: handle, dst_binding: i as u32, descriptor_count: 1, descriptor_type: w.ty(),
+                p_image_info: &images[i], p_buffer_info: &buffers[i], ..Default::default() }).collect();
+            (fns.v1_0.update_descriptor_sets)(d, updates.len() as u32, updates.as_ptr(), 0, std::ptr::null());
+            RawSet { dev: self.dev.clone(), pool, handle }
+        }
+    }
+}
+
+impl Drop for RawSetLayout {
+    fn drop(&mut self) {
+        unsafe { (self.dev.fns().v1_0.destroy_descriptor_set_layout)(self.dev.internal_object(), self.handle, std::ptr::null()); }
+    }
+}
+
+pub struct RawSet {
+    dev: Arc<Device>,
+    pool: vk::DescriptorPool,
+    pub handle: vk::DescriptorSet,
+}
+
+impl Drop for RawSet {
+    fn drop(&mut self) {
+        unsafe { (self.dev.fns().v1_0.destroy_descriptor_pool)(self.dev.internal_object(), self.pool, std::ptr::null()); }
+    }
+}
+
+/// A pipeline made through ash and its layout: one `RawSetLayout` and `push` bytes of push
+/// constants, both visible to `stages`.
+pub struct RawPipeline {
+    dev: Arc<Device>,
+    pub handle: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    pub stages: vk::ShaderStageFlags,
+}
+
+impl RawPipeline {
+    fn layout(dev: &Arc<Device>, set: &RawSetLayout, stages: vk::ShaderStageFlags, push: u32) -> vk::PipelineLayout {
+        let range = vk::PushConstantRange { stage_flags: stages, offset: 0, size: push };
+        let info = vk::PipelineLayoutCreateInfo { set_layout_count: 1, p_set_layouts: &set.handle,
+                                                  push_constant_range_count: (push > 0) as u32, p_push_constant_ranges: &range, ..Default::default() };
+        let mut layout = vk::PipelineLayout::null();
+        unsafe { (dev.fns().v1_0.create_pipeline_layout)(dev.internal_object(), &info, std::ptr::null(), &mut layout).result().unwrap(); }
+        layout
+    }
+
+    /// For the acceleration structure bindings vulkano's own compute pipelines can't have.
+    pub fn compute(dev: &Arc<Device>, set: &RawSetLayout, push: u32, shader: &ShaderModule) -> Self {
+        let stages = vk::ShaderStageFlags::COMPUTE;
+        let layout = Self::layout(dev, set, stages, push);
+        let stage = vk::PipelineShaderStageCreateInfo { stage: stages, module: shader.internal_object(), p_name: MAIN.as_ptr() as *const _, ..Default::default() };
+        let info = vk::ComputePipelineCreateInfo { stage, layout, ..Default::default() };
+        let mut handle = vk::Pipeline::null();
+        unsafe { (dev.fns().v1_0.create_compute_pipelines)(dev.internal_object(), vk::PipelineCache::null(), 1, &info, std::ptr::null(), &mut handle).result().unwrap(); }
+        RawPipeline { dev: dev.clone(), handle, layout, stages }
+    }
+
+    /// Binds the pipeline and `set` at `bind_point`, and pushes `push`.
+    pub unsafe fn bind(&self, fns: &DeviceFunctions, cb: vk::CommandBuffer, bind_point: vk::PipelineBindPoint, set: &RawSet, push: &[u8]) {
+        (fns.v1_0.cmd_bind_pipeline)(cb, bind_point, self.handle);
+        (fns.v1_0.cmd_bind_descriptor_sets)(cb, bind_point, self.layout, 0, 1, &set.handle, 0, std::ptr::null());
+        if !push.is_empty() { (fns.v1_0.cmd_push_constants)(cb, self.layout, self.stages, 0, push.len() as u32, push.as_ptr() as *const _); }
+    }
+}
+
+impl Drop for RawPipeline {
+    fn drop(&mut self) {
+        let (d, fns) = (self.dev.internal_object(), self.dev.fns());
+        unsafe {
+            (fns.v1_0.destroy_pipeline)(d, self.handle, std::ptr::null());
+            (fns.v1_0.destroy_pipeline_layout)(d, self.layout, std::ptr::null());
+        }
+    }
+}
+
+const MAIN: &[u8] = b"main\0";
+
+/// A ray tracing pipeline and its shader binding table. Group 0 is the raygen shader, then
+/// the miss shaders in the order `traceRayEXT`'s miss index counts them, then a triangle hit
+/// group per closest hit shader, counted the same by its sbt offset. Every stage sees the set
+/// and the push constants. `recursion` can't be over the device's `max_ray_recursion_depth`.
+pub struct RtPipeline {
+    pipeline: RawPipeline,
+    _sbt: RtBuffer,
+    /// Raygen, miss and hit.
+    regions: [vk::StridedDeviceAddressRegionKHR; 3],
+}
+
+impl RtPipeline {
+    pub fn new(dev: &Arc<Device>, set: &RawSetLayout, push: u32, raygen: &ShaderModule, misses: &[&ShaderModule], hits: &[&ShaderModule], recursion: u32) -> Self {
+        let (d, fns) = (dev.internal_object(), dev.fns());
+        let properties = dev.physical_device().properties();
+        let stages = vk::ShaderStageFlags::RAYGEN_KHR | vk::ShaderStageFlags::MISS_KHR | vk::ShaderStageFlags::CLOSEST_HIT_KHR;
+        let layout = RawPipeline::layout(dev, set, stages, push);
+        let modules = std::iter::once((vk::ShaderStageFlags::RAYGEN_KHR, raygen))
+            .chain(misses.iter().map(|&m| (vk::ShaderStageFlags::MISS_KHR, m)))
+            .chain(hits.iter().map(|&m| (vk::ShaderStageFlags::CLOSEST_HIT_KHR, m)));
+        let shader_stages: Vec<_> = modules.map(|(stage, m)| vk::PipelineShaderStageCreateInfo {
+            stage, module: m.internal_object(), p_name: MAIN.as_ptr() as *const _, ..Default::default() }).collect();
+        let unused = vk::SHADER_UNUSED_KHR;
+        let groups: Vec<_> = (0..shader_stages.len() as u32).map(|i| match i as usize > misses.len() {
+            false => vk::RayTracingShaderGroupCreateInfoKHR { ty: vk::RayTracingShaderGroupTypeKHR::GENERAL, general_shader: i,
+                                                             closest_hit_shader: unused, any_hit_shader: unused, intersection_shader: unused, ..Default::default() },
+            true => vk::RayTracingShaderGroupCreateInfoKHR { ty: vk::RayTracingShaderGroupTypeKHR::TRIANGLES_HIT_GROUP, general_shader: unused,
+                                                            closest_hit_shader: i, any_hit_shader: unused, intersection_shader: unused, ..Default::default() },
+        }).collect();
+        let info = vk::RayTracingPipelineCreateInfoKHR { stage_count: shader_stages.len() as u32, p_stages: shader_stages.as_ptr(),
+                                                         group_count: groups.len() as u32, p_groups: groups.as_ptr(),
+                                                         max_pipeline_ray_recursion_depth: recursion,
+                                                         layout, ..Default::default() };
+        let mut handle = vk::Pipeline::null();
+        let handle_size = properties.shader_group_handle_size.unwrap() as u64;
+        let mut handles = vec![0u8; groups.len() * handle_size as usize];
+        unsafe {
+            (fns.khr_ray_tracing_pipeline.create_ray_tracing_pipelines_khr)(d, vk::DeferredOperationKHR::null(), vk::PipelineCache::null(), 1, &info,
+                                                                           std::ptr::null(), &mut handle).result().unwrap();
+            (fns.khr_ray_tracing_pipeline.get_ray_tracing_shader_group_handles_khr)(d, handle, 0, groups.len() as u32, handles.len(),
+                                                                                   handles.as_mut_ptr() as *mut _).result().unwrap();
+        }
+        let pipeline = RawPipeline { dev: dev.clone(), handle, layout, stages };
+
+        //records a stride apart, each region starting on the base alignment; raygen's size is its stride
+        let base_alignment = properties.shader_group_base_alignment.unwrap() as u64;
+        let stride = align(handle_size, properties.shader_group_handle_alignment.unwrap() as u64);
+        let counts = [1, misses.len() as u64, hits.len() as u64];
+        let strides = [align(stride, base_alignment), stride, stride];
+        let sizes: Vec<u64> = counts.iter().zip(strides).map(|(&n, s)| align(n * s, base_alignment)).collect();
+        let sbt = RtBuffer::new(dev, sizes.iter().sum::<u64>() + base_alignment, vk::BufferUsageFlags::SHADER_BINDING_TABLE_KHR, true);
+        let mut offset = align(sbt.address, base_alignment) - sbt.address;
+        let mut group = 0;
+        let mut regions = [vk::StridedDeviceAddressRegionKHR::default(); 3];
+        for r in 0..3 {
+            for i in 0..counts[r] {
+                sbt.write(offset + i * strides[r], &handles[group * handle_size as usize..(group + 1) * handle_size as usize]);
+                group += 1;
+            }
+            regions[r] = vk::StridedDeviceAddressRegionKHR { device_address: sbt.address + offset, stride: strides[r], size: sizes[r] };
+            offset += sizes[r];
+        }
+        RtPipeline { pipeline, _sbt: sbt, regions }
+    }
+
+    /// Binds the pipeline and `set`, pushes `push` and traces a ray from every pixel of `extent`.
+    pub unsafe fn trace(&self, fns: &DeviceFunctions, cb: vk::CommandBuffer, set: &RawSet, push: &[u8], extent: [u32; 2]) {
+        self.pipeline.bind(fns, cb, vk::PipelineBindPoint::RAY_TRACING_KHR, set, push);
+        let callable = vk::StridedDeviceAddressRegionKHR::default();
+        (fns.khr_ray_tracing_pipeline.cmd_trace_rays_khr)(cb, &self.regions[0], &self.regions[1], &self.regions[2], &callable, extent[0], extent[1], 1);
+    }
+}
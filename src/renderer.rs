@@ -0,0 +1,765 @@
+use vulkano::{ device::{ Device, Queue },
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, SubpassContents },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               image::{ AttachmentImage, ImageUsage, view::{ ImageView, ImageViewAbstract } },
+               render_pass::{ Framebuffer, FramebufferCreateInfo, RenderPass, Subpass },
+               sampler::{ Sampler, SamplerCreateInfo },
+               pipeline::{ GraphicsPipeline, Pipeline, PipelineBindPoint, graphics::{ input_assembly::{ InputAssemblyState, PrimitiveTopology },
+                                                                                      vertex_input::BuffersDefinition, tessellation::TessellationState,
+                                                                                      viewport::{ Viewport, ViewportState }, depth_stencil::DepthStencilState,
+                                                                                      rasterization::{ RasterizationState, CullMode, FrontFace },
+                                                                                      color_blend::{ ColorBlendState, ColorComponents } }, StateMode },
+               buffer::{ BufferUsage, CpuBufferPool, cpu_pool::{ CpuBufferPoolChunk, CpuBufferPoolSubbuffer } },
+               memory::pool::StdMemoryPool,
+               format::{ ClearValue, Format } };
+use glam::Mat4;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::mesh::Vertex;
+use crate::scene::{ Scene, Entity, EntityId };
+use crate::camera::View;
+use crate::picking::{ self, Picker };
+use crate::billboard::Billboards;
+use crate::tilemap::Tilemaps;
+use crate::light::SceneLights;
+use crate::material::{ Material, ShadingModel, Outline, FLAG_LIGHTMAP, FLAG_SKINNED, FLAG_MORPHED };
+use crate::skin::NoSkin;
+use crate::morph::NoMorph;
+use crate::texture::Texture;
+use crate::gpu_debug;
+use crate::gpu_memory::{ self, MemoryCategory };
+use crate::ibl::{ IblBaker, Environment };
+use crate::probe::{ self, ActiveProbe, ProbeShape, MAX_PROBES };
+use crate::shadow::{ ShadowMap, MAX_CASCADES, MAX_SHADOW_TILES };
+use crate::skybox::SkyboxRenderer;
+use crate::deferred::{ self, Resolve };
+use crate::cluster::LightClusters;
+use crate::volumetric::FogVolume;
+use crate::sdf::SdfRenderer;
+use crate::voxel_gi::VoxelClipmap;
+use crate::ssao::{ self, SsaoPass };
+use crate::ssr::{ self, SsrPass };
+use crate::raytracing::{ RayTracing, RayTracingSupport };
+use crate::rt_lighting::{ self, RtLightingPass };
+use crate::rtao::{ AoHistory, RtaoPass };
+use crate::gpu_particles::GpuParticles;
+
+pub const DEPTH_FORMAT: Format = Format::D16_UNORM;
+/// Screen space motion of moving objects since last frame, in uv. See `object_motion` in material.glsl.
+pub const VELOCITY_FORMAT: Format = Format::R16G16_SFLOAT;
+
+mod vs {
+    vulkano_shaders::shader! { ty: "vertex", path: "src/shaders/standard.vert", include: ["src/shaders"] }
+}
+mod displaced_vs {
+    vulkano_shaders::shader! { ty: "vertex", path: "src/shaders/displaced.vert", include: ["src/shaders"] }
+}
+mod displaced_tcs {
+    vulkano_shaders::shader! { ty: "tess_ctrl", path: "src/shaders/displaced.tesc", include: ["src/shaders"] }
+}
+mod displaced_tes {
+    vulkano_shaders::shader! { ty: "tess_eval", path: "src/shaders/displaced.tese", include: ["src/shaders"] }
+}
+mod fs {
+    vulkano_shaders::shader! { ty: "fragment", path: "src/shaders/standard.frag", include: ["src/shaders"],
+    types_meta: { use bytemuck::{ Pod, Zeroable }; #[derive(Clone, Copy, Zeroable, Pod)] }
+    }
+}
+mod portal_vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			layout(location = 0) in vec3 position;
+			layout(location = 0) flat out uint v_id;
+			layout(location = 1) flat out vec2 v_inv_extent;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 transform;
+				vec2 inv_extent;
+				uint id;
+			} pc;
+
+			void main() {
+				gl_Position = pc.transform * vec4(position, 1.0);
+				v_id = pc.id;
+				v_inv_extent = pc.inv_extent;
+			}"
+    }
+}
+mod portal_fs {
+    vulkano_shaders::shader! { ty: "fragment",
+    src: "#version 450
+
+			layout(set = 0, binding = 0) uniform sampler2D u_view;
+
+			layout(location = 0) flat in uint v_id;
+			layout(location = 1) flat in vec2 v_inv_extent;
+			layout(location = 0) out vec4 f_color;
+			layout(location = 1) out uint f_id;
+
+			//the secondary view was rendered with the main projection, so it lines up in screen space
+			void main() {
+				f_color = texture(u_view, gl_FragCoord.xy * v_inv_extent);
+				f_id = v_id;
+			}"
+    }
+}
+
+mod outline_vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			layout(location = 0) in vec3 position;
+			layout(location = 1) in vec3 normal;
+			layout(location = 0) flat out vec4 v_color;
+			layout(location = 1) flat out uint v_id;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 transform;
+				vec4 color;
+				vec2 width; // clip space at w 1
+				uint id;
+			} pc;
+
+			//pushed out along the normal as seen on screen, so the rim is the same width at any distance
+			void main() {
+				vec4 clip = pc.transform * vec4(position, 1.0);
+				vec2 n = (pc.transform * vec4(normal, 0.0)).xy;
+				if (dot(n, n) > 0.0) clip.xy += normalize(n) * pc.width * clip.w;
+				gl_Position = clip;
+				v_color = pc.color;
+				v_id = pc.id;
+			}"
+    }
+}
+mod outline_fs {
+    vulkano_shaders::shader! { ty: "fragment",
+    src: "#version 450
+
+			layout(location = 0) flat in vec4 v_color;
+			layout(location = 1) flat in uint v_id;
+			layout(location = 0) out vec4 f_color;
+			layout(location = 1) out uint f_id;
+
+			void main() {
+				f_color = v_color;
+				f_id = v_id;
+			}"
+    }
+}
+
+/// Framebuffer for the scene pass plus the depth view the transparent subpass and post effects read.
+pub struct Target {
+    pub framebuffer: Arc<Framebuffer>,
+    pub depth: Arc<ImageView<AttachmentImage>>,
+    /// Object motion for motion blur and temporal effects, zero wherever nothing moved on its own.
+    pub velocity: Arc<ImageView<AttachmentImage>>,
+    /// Deferred only: the g-buffer and lit image the tiled resolve reads and writes, and the
+    /// framebuffer of the composite pass after it.
+    pub gbuffer: Vec<Arc<ImageView<AttachmentImage>>>,
+    pub lit: Option<Arc<ImageView<AttachmentImage>>>,
+    /// Deferred only: ambient occlusion and its blur scratch.
+    pub ao: Vec<Arc<ImageView<AttachmentImage>>>,
+    /// Deferred with ray queries: ray traced occlusion accumulated over frames, see rtao.rs.
+    pub ao_history: Option<AoHistory>,
+    /// Deferred only: the hi-z pyramid, and screen space reflections and their blur scratch.
+    pub hiz: Option<Arc<ImageView<AttachmentImage>>>,
+    pub reflections: Vec<Arc<ImageView<AttachmentImage>>>,
+    /// Deferred only: the sun's ray traced visibility, see rt_lighting.rs.
+    pub rt_shadow: Option<Arc<ImageView<AttachmentImage>>>,
+    pub composite: Option<Arc<Framebuffer>>,
+    pub extent: [u32; 2],
+}
+
+/// Images of a target that are shared by every target of the same size.
+#[derive(Clone)]
+struct Attachments {
+    depth: Arc<ImageView<AttachmentImage>>,
+    velocity: Arc<ImageView<AttachmentImage>>,
+    gbuffer: Vec<Arc<ImageView<AttachmentImage>>>,
+    lit: Option<Arc<ImageView<AttachmentImage>>>,
+    ao: Vec<Arc<ImageView<AttachmentImage>>>,
+    hiz: Option<Arc<ImageView<AttachmentImage>>>,
+    reflections: Vec<Arc<ImageView<AttachmentImage>>>,
+    rt_shadow: Option<Arc<ImageView<AttachmentImage>>>,
+}
+
+impl Target {
+    pub fn viewport(&self) -> Viewport {
+        Viewport { origin: [0.0, 0.0], dimensions: [self.extent[0] as f32, self.extent[1] as f32], depth_range: 0.0..1.0 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderPath {
+    /// Lights shaded per object while drawing it.
+    Forward,
+    /// Materials written to a g-buffer, then lit tile by tile in compute. See deferred.rs.
+    Deferred,
+}
+
+/// Writes color and id, leaves velocity and any g-buffer attachments after them untouched, so
+/// the deferred resolve skips whatever drew with it and it only moves with the camera.
+pub fn color_and_id_only(attachments: u32) -> ColorBlendState {
+    let mut blend = ColorBlendState::new(attachments);
+    for a in blend.attachments.iter_mut().skip(2) {
+        a.color_write_mask = ColorComponents { r: false, g: false, b: false, a: false };
+    }
+    blend
+}
+
+/// Draw calls and triangles recorded, for the stats overlay.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DrawStats {
+    pub draw_calls: u32,
+    pub triangles: u64,
+}
+
+impl DrawStats {
+    pub fn add(&mut self, draw_calls: u32, triangles: u64) {
+        self.draw_calls += draw_calls;
+        self.triangles += triangles;
+    }
+}
+
+/// The scene pass: opaque entities and the scene's distance field writing color and ids, the
+/// skybox behind them, then tilemaps, billboards and gpu particles
+/// in a transparent subpass. Point lights are binned into clusters by compute just before it.
+/// Deferred splits it, lighting the g-buffer in compute before a composite pass for billboards.
+/// Volumetric fog goes over the lit scene, before billboards.
+/// The same render passes draw the main view and offscreen targets (mirrors, portals); the
+/// main view's color then goes through the post stack to the swapchain.
+pub struct Renderer {
+    dev: Arc<Device>,
+    pub path: RenderPath,
+    pub render_pass: Arc<RenderPass>,
+    composite_pass: Option<Arc<RenderPass>>,
+    pub color_format: Format,
+    depth_format: Format,
+    pipeline: Arc<GraphicsPipeline>,
+    /// `pipeline` with displacement tessellated in, when the device has tessellation shaders.
+    displaced: Option<Arc<GraphicsPipeline>>,
+    portal_pipeline: Arc<GraphicsPipeline>,
+    outline_pipeline: Arc<GraphicsPipeline>,
+    resolve: Option<Resolve>,
+    ssao: Option<SsaoPass>,
+    ssr: Option<SsrPass>,
+    /// The scene's acceleration structures, on devices made with ray tracing.
+    ray_tracing: Option<RayTracing>,
+    rt_lighting: Option<RtLightingPass>,
+    rtao: Option<RtaoPass>,
+    sdf: SdfRenderer,
+    sampler: Arc<Sampler>,
+    billboards: Billboards,
+    tilemaps: Tilemaps,
+    skybox: SkyboxRenderer,
+    frame_pool: CpuBufferPool<fs::ty::Frame>,
+    light_pool: CpuBufferPool<fs::ty::PointLight>,
+    clusters: LightClusters,
+    object_pool: CpuBufferPool<fs::ty::Object>,
+    style_pool: CpuBufferPool<fs::ty::Style>,
+    white: Arc<Texture>,
+    no_skin: NoSkin,
+    no_morph: NoMorph,
+    /// Render with `shadows.render` before any `draw` in the frame.
+    pub shadows: ShadowMap,
+    pub ibl: IblBaker,
+    /// Rebuild with `voxelize` after `shadows.render`, before any `draw` in the frame.
+    pub voxels: VoxelClipmap,
+    /// Step with `gpu_particles.update` before any `draw` in the frame.
+    pub gpu_particles: GpuParticles,
+    no_environment: Arc<Environment>,
+    stats: Cell<DrawStats>,
+}
+
+impl Renderer {
+    /// Every pipeline is built for `path`'s render pass, so the path is fixed for the renderer's lifetime.
+    pub fn new(dev: Arc<Device>, queue: Arc<Queue>, color_format: Format, path: RenderPath) -> Self {
+        let forward = || vulkano::ordered_passes_renderpass!( dev.clone(),
+                                                            attachments: { color: { load: Clear, store: Store, format: color_format, samples: 1,},
+                                                                           id: { load: Clear, store: Store, format: picking::ID_FORMAT, samples: 1,},
+                                                                           velocity: { load: Clear, store: Store, format: VELOCITY_FORMAT, samples: 1,},
+                                                                           depth: { load: Clear, store: Store, format: DEPTH_FORMAT, samples: 1,}},
+                                                            passes: [ { color: [color, id, velocity], depth_stencil: {depth}, input: [] },   //opaque
+                                                                      { color: [color], depth_stencil: {}, input: [depth] } ]    //transparent, reads depth for soft fades
+                                                            ).unwrap();
+        let (render_pass, composite_pass, depth_format, fs) = match path {
+            RenderPath::Forward => (forward(), None, DEPTH_FORMAT, fs::load(dev.clone()).unwrap()),
+            RenderPath::Deferred => (deferred::render_pass(dev.clone(), color_format), Some(deferred::composite_pass(dev.clone(), color_format)),
+                                     deferred::DEPTH_FORMAT, deferred::gbuffer_shader(dev.clone())),
+        };
+        let transparent = match &composite_pass {
+            Some(p) => Subpass::from(p.clone(), 0).unwrap(),
+            None => Subpass::from(render_pass.clone(), 1).unwrap(),
+        };
+        let opaque = Subpass::from(render_pass.clone(), 0).unwrap();
+        let vs = vs::load(dev.clone()).unwrap();
+        let pipeline = GraphicsPipeline::start().vertex_input_state(
+            BuffersDefinition::new().vertex::<Vertex>())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .depth_stencil_state(DepthStencilState::simple_depth_test())
+            .color_blend_state(ColorBlendState::new(opaque.num_color_attachments()))
+            .render_pass(opaque.clone())
+            .build(dev.clone()).unwrap();
+        //patches of three, the triangles as they are in the vertex buffer
+        let displaced = dev.enabled_features().tessellation_shader.then(|| {
+            let vs = displaced_vs::load(dev.clone()).unwrap();
+            let tcs = displaced_tcs::load(dev.clone()).unwrap();
+            let tes = displaced_tes::load(dev.clone()).unwrap();
+            GraphicsPipeline::start().vertex_input_state(
+                BuffersDefinition::new().vertex::<Vertex>())
+                .vertex_shader(vs.entry_point("main").unwrap(), ())
+                .input_assembly_state(InputAssemblyState::new().topology(PrimitiveTopology::PatchList))
+                .tessellation_shaders(tcs.entry_point("main").unwrap(), (), tes.entry_point("main").unwrap(), ())
+                .tessellation_state(TessellationState::new().patch_control_points(3))
+                .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+                .fragment_shader(fs.entry_point("main").unwrap(), ())
+                .depth_stencil_state(DepthStencilState::simple_depth_test())
+                .color_blend_state(ColorBlendState::new(opaque.num_color_attachments()))
+                .render_pass(opaque.clone())
+                .build(dev.clone()).unwrap()
+        });
+        let portal_vs = portal_vs::load(dev.clone()).unwrap();
+        let portal_fs = portal_fs::load(dev.clone()).unwrap();
+        let portal_pipeline = GraphicsPipeline::start().vertex_input_state(
+            BuffersDefinition::new().vertex::<Vertex>())
+            .vertex_shader(portal_vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(portal_fs.entry_point("main").unwrap(), ())
+            .depth_stencil_state(DepthStencilState::simple_depth_test())
+            .color_blend_state(color_and_id_only(opaque.num_color_attachments()))
+            .render_pass(opaque.clone())
+            .build(dev.clone()).unwrap();
+        let outline_vs = outline_vs::load(dev.clone()).unwrap();
+        let outline_fs = outline_fs::load(dev.clone()).unwrap();
+        //front faces culled leaves the far side of the hull showing around the silhouette; the
+        //projection flips y, so front faces wind clockwise on screen
+        let outline_pipeline = GraphicsPipeline::start().vertex_input_state(
+            BuffersDefinition::new().vertex::<Vertex>())
+            .vertex_shader(outline_vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(outline_fs.entry_point("main").unwrap(), ())
+            .rasterization_state(RasterizationState { cull_mode: StateMode::Fixed(CullMode::Front), front_face: StateMode::Fixed(FrontFace::Clockwise),
+                                                      ..RasterizationState::new() })
+            .depth_stencil_state(DepthStencilState::simple_depth_test())
+            .color_blend_state(color_and_id_only(opaque.num_color_attachments()))
+            .render_pass(opaque.clone())
+            .build(dev.clone()).unwrap();
+        gpu_debug::name(&*render_pass, "scene");
+        for (p, name) in [(&pipeline, "scene"), (&portal_pipeline, "portal"), (&outline_pipeline, "outline")] { gpu_debug::name(&**p, name); }
+        if let Some(p) = &displaced { gpu_debug::name(&**p, "scene displaced"); }
+        let resolve = match path {
+            RenderPath::Forward => None,
+            RenderPath::Deferred => Some(Resolve::new(dev.clone(), transparent.clone())),
+        };
+        let ssao = resolve.as_ref().map(|_| SsaoPass::new(dev.clone()));
+        let ssr = resolve.as_ref().map(|_| SsrPass::new(dev.clone()));
+        let rt = RayTracingSupport::enabled(&dev);
+        let ray_tracing = rt.any().then(|| RayTracing::new(queue.clone()));
+        let rt_lighting = (rt.pipeline && resolve.is_some()).then(|| RtLightingPass::new(queue.clone()));
+        let rtao = (rt.query && resolve.is_some()).then(|| RtaoPass::new(queue.clone()));
+        let sdf = SdfRenderer::new(dev.clone(), opaque.clone(), path == RenderPath::Deferred);
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo::simple_repeat_linear_no_mipmap()).unwrap();
+        let tilemaps = Tilemaps::new(dev.clone(), transparent.clone());
+        let gpu_particles = GpuParticles::new(dev.clone(), transparent.clone(), Texture::white(queue.clone()));
+        let billboards = Billboards::new(dev.clone(), transparent);
+        let skybox = SkyboxRenderer::new(dev.clone(), opaque);
+        let frame_pool = CpuBufferPool::uniform_buffer(dev.clone());
+        let light_pool = CpuBufferPool::new(dev.clone(), BufferUsage { storage_buffer: true, ..BufferUsage::none() });
+        let clusters = LightClusters::new(dev.clone());
+        let object_pool = CpuBufferPool::uniform_buffer(dev.clone());
+        let style_pool = CpuBufferPool::uniform_buffer(dev.clone());
+        let white = Texture::white(queue.clone());
+        let shadows = ShadowMap::new(dev.clone());
+        let no_skin = NoSkin::new(dev.clone());
+        let no_morph = NoMorph::new(dev.clone());
+        let ibl = IblBaker::new(dev.clone(), queue);
+        let no_environment = ibl.uniform([0.0; 3]);
+        let voxels = VoxelClipmap::new(dev.clone());
+        Renderer { dev, path, render_pass, composite_pass, color_format, depth_format, pipeline, displaced, portal_pipeline, outline_pipeline, resolve, ssao, ssr, ray_tracing, rt_lighting, rtao, sdf, sampler, billboards, tilemaps, skybox, frame_pool, light_pool, clusters, object_pool, style_pool, white, no_skin, no_morph, shadows, ibl, voxels, gpu_particles, no_environment, stats: Cell::default() }
+    }
+
+    /// What every `draw` since the last call recorded, views and reflections included. Meshes
+    /// count before tessellation; gpu particles aren't in it, their counts stay on the gpu.
+    pub fn take_stats(&self) -> DrawStats { self.stats.take() }
+
+    /// Where billboards and other things drawn over the lit scene go.
+    pub fn transparent_subpass(&self) -> Subpass {
+        match &self.composite_pass {
+            Some(p) => Subpass::from(p.clone(), 0).unwrap(),
+            None => Subpass::from(self.render_pass.clone(), 1).unwrap(),
+        }
+    }
+
+    /// The main camera's target, sized and id'd by the picker. Its color feeds the post stack.
+    pub fn scene_target(&self, picker: &Picker) -> (Target, Arc<ImageView<AttachmentImage>>) {
+        let extent = picker.extent();
+        let color = ImageView::new_default(AttachmentImage::with_usage(self.dev.clone(), extent, self.color_format,
+            ImageUsage { color_attachment: true, sampled: true, ..ImageUsage::none() }).unwrap()).unwrap();
+        (self.target(color.clone(), picker.view(), self.attachments(extent), extent), color)
+    }
+
+    /// Target whose color can be sampled once the pass has run. Ids are written but thrown away.
+    pub fn offscreen_target(&self, extent: [u32; 2]) -> (Target, Arc<ImageView<AttachmentImage>>) {
+        let color = ImageView::new_default(AttachmentImage::with_usage(self.dev.clone(), extent, self.color_format,
+            ImageUsage { color_attachment: true, sampled: true, ..ImageUsage::none() }).unwrap()).unwrap();
+        let id = ImageView::new_default(AttachmentImage::transient(self.dev.clone(), extent, picking::ID_FORMAT).unwrap()).unwrap();
+        (self.target(color.clone(), id, self.attachments(extent), extent), color)
+    }
+
+    /// Depth, plus everything the deferred passes between the g-buffer and the composite use.
+    fn attachments(&self, extent: [u32; 2]) -> Attachments {
+        let velocity = ImageView::new_default(AttachmentImage::with_usage(self.dev.clone(), extent, VELOCITY_FORMAT,
+            ImageUsage { color_attachment: true, sampled: true, ..ImageUsage::none() }).unwrap()).unwrap();
+        match self.path {
+            RenderPath::Forward => Attachments {
+                depth: ImageView::new_default(AttachmentImage::with_usage(self.dev.clone(), extent, self.depth_format,
+                    ImageUsage { depth_stencil_attachment: true, input_attachment: true, sampled: true, ..ImageUsage::none() }).unwrap()).unwrap(),
+                velocity, gbuffer: Vec::new(), lit: None, ao: Vec::new(), hiz: None, reflections: Vec::new(), rt_shadow: None },
+            RenderPath::Deferred => Attachments {
+                depth: deferred::depth_view(&self.dev, extent), velocity, gbuffer: deferred::gbuffer_views(&self.dev, extent), lit: Some(deferred::lit_view(&self.dev, extent)),
+                ao: ssao::ao_views(&self.dev, extent), hiz: Some(ssr::hiz_view(&self.dev, extent)), reflections: ssr::reflection_views(&self.dev, extent),
+                rt_shadow: Some(rt_lighting::shadow_view(&self.dev, extent)) },
+        }
+    }
+
+    fn target(&self, color: Arc<dyn ImageViewAbstract>, id: Arc<dyn ImageViewAbstract>, a: Attachments, extent: [u32; 2]) -> Target {
+        let name = |view: &dyn ImageViewAbstract, name: &str| gpu_debug::name(&**view.image().inner().image, name);
+        name(&*color, "color");
+        name(&*id, "ids");
+        name(&*a.velocity, "velocity");
+        name(&*a.depth, "depth");
+        for (i, g) in a.gbuffer.iter().enumerate() { name(&**g, &format!("gbuffer {}", i)); }
+        let mut attachments = vec![color.clone(), id, a.velocity.clone() as Arc<dyn ImageViewAbstract>, a.depth.clone() as Arc<dyn ImageViewAbstract>];
+        attachments.extend(a.gbuffer.iter().map(|g| g.clone() as Arc<dyn ImageViewAbstract>));
+        let bytes: u64 = attachments.iter().map(|a| gpu_memory::image_bytes(a.format().unwrap(), extent)).sum();
+        let framebuffer = Framebuffer::new(self.render_pass.clone(), FramebufferCreateInfo { attachments, ..Default::default() }).unwrap();
+        //for as long as the target's framebuffer; the deferred images outside it aren't counted
+        gpu_memory::track(MemoryCategory::RenderTargets, &framebuffer, bytes);
+        let composite = self.composite_pass.as_ref().map(|p| Framebuffer::new(p.clone(), FramebufferCreateInfo {
+            attachments: vec![color, a.depth.clone() as Arc<dyn ImageViewAbstract>], ..Default::default() }).unwrap());
+        //accumulated per target, targets of a size share the rest
+        let ao_history = self.rtao.as_ref().map(|_| AoHistory::new(&self.dev, extent));
+        Target { framebuffer, depth: a.depth, velocity: a.velocity, gbuffer: a.gbuffer, lit: a.lit, ao: a.ao, ao_history, hiz: a.hiz, reflections: a.reflections,
+                 rt_shadow: a.rt_shadow, composite, extent }
+    }
+
+    /// Point and spot lights for the storage buffer the clusters index into, nearest first.
+    fn light_buffer(&self, lights: &SceneLights) -> Arc<CpuBufferPoolChunk<fs::ty::PointLight, Arc<StdMemoryPool>>> {
+        let mut points: Vec<_> = lights.points.iter().map(|p| {
+            //cone cosines, -2 keeps point lights out of any cone
+            let (inner, outer) = p.cone.map_or((-1.0, -2.0), |(i, o)| (i.cos(), o.cos()));
+            let shadow = self.shadows.local.get(&p.entity).map_or(-1.0, |&t| t as f32);
+            fs::ty::PointLight { position_range: p.position.extend(p.range).into(), radiance: p.radiance.extend(p.contact_shadows as u32 as f32).into(),
+                                 direction: p.direction.extend(0.0).into(), params: [inner, outer, shadow, p.shadow.map_or(0.0, |b| b.normal_offset)] }
+        }).collect();
+        //an empty buffer can't be bound
+        if points.is_empty() { points.push(fs::ty::PointLight { position_range: [0.0; 4], radiance: [0.0; 4], direction: [0.0; 4], params: [0.0; 4] }); }
+        self.light_pool.chunk(points).unwrap()
+    }
+
+    fn frame_uniforms(&self, scene: &Scene, view: &View, lights: &SceneLights, active_probes: &[ActiveProbe], extent: [u32; 2])
+                      -> Arc<CpuBufferPoolSubbuffer<fs::ty::Frame, Arc<StdMemoryPool>>> {
+        let eye = view.eye();
+        let mut shadow_tiles = [Mat4::IDENTITY.to_cols_array_2d(); MAX_SHADOW_TILES];
+        let mut shadow_rects = [[0.0; 4]; MAX_SHADOW_TILES];
+        for (i, t) in self.shadows.tiles.iter().enumerate() {
+            shadow_tiles[i] = t.view_proj.to_cols_array_2d();
+            shadow_rects[i] = t.rect;
+        }
+        let mut probes = [fs::ty::Probe { position_blend: [0.0; 4], box_min: [0.0; 4], box_max: [0.0; 4], params: [0.0; 4] }; MAX_PROBES];
+        for (dst, p) in probes.iter_mut().zip(active_probes) {
+            let (box_min, box_max) = match p.shape {
+                ProbeShape::Sphere { radius } => ([0.0; 4], [0.0, 0.0, 0.0, radius]),
+                ProbeShape::Box { half_extent } => ((p.position - half_extent).extend(1.0).into(), (p.position + half_extent).extend(0.0).into()),
+            };
+            *dst = fs::ty::Probe { position_blend: p.position.extend(p.blend).into(), box_min, box_max,
+                                   params: [(p.environment.mip_levels - 1) as f32, 0.0, 0.0, 0.0] };
+        }
+        let (sun_dir, sun_radiance) = match lights.sun {
+            Some((d, r)) => (d.extend(lights.sun_contact_shadows as u32 as f32).into(), r.extend(1.0).into()),
+            None => ([0.0; 4], [0.0; 4]),
+        };
+        let voxels = self.voxels.uniforms(scene);
+        let mut cascade_view_proj = [Mat4::IDENTITY.to_cols_array_2d(); MAX_CASCADES];
+        let mut cascade_splits = [0.0; MAX_CASCADES];
+        let mut cascade_texels = [0.0; MAX_CASCADES];
+        for (i, c) in self.shadows.cascades.iter().enumerate() {
+            cascade_view_proj[i] = c.view_proj.to_cols_array_2d();
+            cascade_splits[i] = c.far;
+            cascade_texels[i] = c.texel;
+        }
+        self.frame_pool.next(fs::ty::Frame {
+            view_proj: view.view_proj().to_cols_array_2d(),
+            camera_pos: eye.extend(1.0).into(),
+            camera_forward: view.forward().extend(view.is_orthographic() as u32 as f32).into(),
+            ambient: lights.ambient.extend(0.0).into(),
+            sun_direction: sun_dir,
+            sun_radiance,
+            cascade_view_proj,
+            cascade_splits,
+            cascade_texels,
+            shadow_params: self.shadows.params(),
+            local_shadow_params: self.shadows.local_params(),
+            ibl_params: match &scene.environment { Some(env) => [1.0, (env.mip_levels - 1) as f32, 0.0, 1.0], None => [0.0; 4] },
+            fog_color: scene.fog.map_or([0.0; 4], |f| f.color.extend(f.density).into()),
+            fog_height: scene.fog.map_or([0.0; 4], |f| [f.height_density, f.height_falloff, f.base_height, f.start]),
+            cluster_params: [view.near, view.far, 1.0 / extent[0] as f32, 1.0 / extent[1] as f32],
+            contact_params: scene.contact_shadows.map_or([0.0; 4], |c| [c.length, c.thickness, c.steps as f32, 0.0]),
+            voxel_params: voxels.params,
+            voxel_trace: voxels.trace,
+            voxel_origins: voxels.origins,
+            counts: [lights.points.len() as u32, self.shadows.cascades.len() as u32, active_probes.len() as u32, 0],
+            probes,
+            shadow_tiles,
+            shadow_rects,
+        }).unwrap()
+    }
+
+    /// For `pipeline`, which is `displaced` when `displaced` is set.
+    fn material_set(&self, material: &Material, pipeline: &Arc<GraphicsPipeline>, displaced: bool) -> Arc<PersistentDescriptorSet> {
+        let layout = pipeline.layout().set_layouts().get(1).unwrap();
+        let view = |t: &Option<Arc<Texture>>| t.as_ref().unwrap_or(&self.white).view.clone();
+        let toon = match material.shading {
+            ShadingModel::Toon { bands, shadow_tint } => [shadow_tint[0], shadow_tint[1], shadow_tint[2], bands as f32],
+            _ => [0.0; 4],
+        };
+        let splat = material.splat.as_ref();
+        let displacement = material.displacement.as_ref();
+        let mut writes = vec![
+            WriteDescriptorSet::image_view_sampler(0, view(&material.base_color_texture), self.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler(1, view(&material.metallic_roughness_texture), self.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler(2, view(&material.normal_texture), self.sampler.clone()),
+            WriteDescriptorSet::buffer(3, self.style_pool.next(fs::ty::Style { toon, splat: [splat.map_or(1.0, |s| s.tiling), 0.0, 0.0, 0.0],
+                displacement: displacement.map_or([0.0; 4], |d| [d.scale, d.midlevel, d.edge_pixels, 0.0]) }).unwrap()),
+            WriteDescriptorSet::image_view_sampler(4, view(&splat.map(|s| s.control.clone())), self.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler_array(5, 0, (0..4).map(|i| {
+                (view(&splat.and_then(|s| s.layers[i].clone())) as Arc<dyn ImageViewAbstract>, self.sampler.clone())
+            })),
+        ];
+        if displaced { writes.push(WriteDescriptorSet::image_view_sampler(6, view(&displacement.map(|d| d.texture.clone())), self.sampler.clone())); }
+        PersistentDescriptorSet::new(layout.clone(), writes).unwrap()
+    }
+
+    /// Light probe sh at the entity's bounds center, if the scene has a baked grid, and the
+    /// entity's lightmap.
+    fn object_set(&self, scene: &Scene, entity: &Entity, pipeline: &Arc<GraphicsPipeline>) -> Arc<PersistentDescriptorSet> {
+        let layout = pipeline.layout().set_layouts().get(2).unwrap();
+        let mut sh = [[0.0; 4]; 9];
+        if let Some(probe) = scene.light_probes.as_ref().and_then(|g| g.sample(entity.world_sphere().0)) {
+            for (dst, c) in sh.iter_mut().zip(probe.coeffs) { *dst = c.extend(0.0).into(); }
+            sh[0][3] = 1.0;
+        }
+        let lightmap = entity.lightmap.as_ref().and_then(|l| l.texture.as_ref()).unwrap_or(&self.white).view.clone();
+        let mut writes = vec![
+            WriteDescriptorSet::buffer(0, self.object_pool.next(fs::ty::Object { sh, previous_model: entity.previous_transform.to_cols_array_2d() }).unwrap()),
+            WriteDescriptorSet::image_view_sampler(1, lightmap, self.sampler.clone()),
+        ];
+        //only the standard vertex shader skins and morphs
+        if Arc::ptr_eq(pipeline, &self.pipeline) {
+            let (weights, bones) = self.no_skin.or(entity.skin.as_ref());
+            let (deltas, morph_weights) = self.no_morph.or(entity.morph.as_ref());
+            writes.extend([WriteDescriptorSet::buffer(2, weights), WriteDescriptorSet::buffer(3, bones),
+                           WriteDescriptorSet::buffer(4, deltas), WriteDescriptorSet::buffer(5, morph_weights)]);
+        }
+        PersistentDescriptorSet::new(layout.clone(), writes).unwrap()
+    }
+
+    /// Draws the hull of the entity just drawn, whose vertex buffer is still bound.
+    fn draw_outline(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, target: &Target, transform: Mat4, outline: Outline,
+                    id: EntityId, vertices: u32) {
+        let pc = outline_vs::ty::PushConstants { transform: transform.to_cols_array_2d(), color: outline.color,
+                                                 width: [outline.width * 2.0 / target.extent[0] as f32, outline.width * 2.0 / target.extent[1] as f32], id };
+        builder.bind_pipeline_graphics(self.outline_pipeline.clone())
+            .push_constants(self.outline_pipeline.layout().clone(), 0, pc)
+            .draw(vertices, 1, 0, 0).unwrap();
+    }
+
+    /// Rebuilds the voxel gi clipmap around `view` for the frame's draws, if the scene has gi
+    /// and the clipmap a quality.
+    #[profiling::function]
+    pub fn voxelize(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, scene: &Scene, view: &View) {
+        let settings = match (&scene.voxel_gi, self.voxels.active(scene)) { (Some(s), true) => s, _ => return };
+        let eye = view.eye();
+        self.voxels.center(settings, eye);
+        let lights = SceneLights::gather(scene, eye);
+        let light_buffer = self.light_buffer(&lights);
+        let uniforms = self.frame_uniforms(scene, view, &lights, &[], [1, 1]);
+        let set = PersistentDescriptorSet::new(self.voxels.frame_layout(), [
+            WriteDescriptorSet::buffer(0, uniforms),
+            WriteDescriptorSet::image_view_sampler(1, self.shadows.depth.clone(), self.shadows.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler(2, self.shadows.local_depth.clone(), self.shadows.sampler.clone()),
+            WriteDescriptorSet::buffer(8, light_buffer),
+        ]).unwrap();
+        self.voxels.record(builder, set, scene);
+    }
+
+    /// Builds the acceleration structures the frame's ray traced passes trace against, before
+    /// any `draw`. Nothing without ray tracing.
+    pub fn build_acceleration_structures(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, scene: &Scene) {
+        if let Some(rt) = &mut self.ray_tracing { rt.build(builder, scene); }
+    }
+
+    /// Records the whole scene pass. `portal_views` maps portal entities to the secondary view
+    /// they show; portals without an entry are skipped, which also stops recursion. `fog` is the
+    /// view's volume for the scene's volumetric fog, if it has one.
+    #[profiling::function]
+    pub fn draw(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, target: &Target, scene: &Scene, view: &View,
+                visible: &dyn Fn(&Entity) -> bool, portal_views: &HashMap<EntityId, Arc<ImageView<AttachmentImage>>>, mut fog: Option<&mut FogVolume>) {
+        let view_proj = view.view_proj();
+        let eye = view.eye();
+        let layout = self.pipeline.layout().set_layouts().get(0).unwrap();
+        let env = scene.environment.as_ref().unwrap_or(&self.no_environment);
+        let probes = probe::gather(scene, eye);
+        let probe_env = |i: usize| probes.get(i).map_or(&self.no_environment, |p| &p.environment);
+        let probe_views = |f: fn(&Environment) -> Arc<dyn ImageViewAbstract>| (0..MAX_PROBES).map(move |i| (f(probe_env(i)), self.ibl.sampler.clone()));
+        let lights = SceneLights::gather(scene, eye);
+        let light_buffer = self.light_buffer(&lights);
+        //deferred culls per tile in the resolve instead, but fog still finds its lights by cluster
+        let fogged = fog.is_some() && scene.volumetric_fog.is_some();
+        if self.resolve.is_none() || fogged {
+            self.clusters.cull(builder, light_buffer.clone(), lights.points.len() as u32, view);
+        }
+        //deferred shades in the resolve; the g-buffer pass only needs the uniforms
+        let uniforms = self.frame_uniforms(scene, view, &lights, &probes, target.extent);
+        if let (Some(volume), Some(settings)) = (fog.as_deref_mut(), &scene.volumetric_fog) {
+            let set = PersistentDescriptorSet::new(volume.frame_layout(), [
+                WriteDescriptorSet::buffer(0, uniforms.clone()),
+                WriteDescriptorSet::image_view_sampler(1, self.shadows.depth.clone(), self.shadows.sampler.clone()),
+                WriteDescriptorSet::image_view_sampler(2, self.shadows.local_depth.clone(), self.shadows.sampler.clone()),
+                WriteDescriptorSet::buffer(8, light_buffer.clone()),
+                WriteDescriptorSet::buffer(9, self.clusters.clusters.clone()),
+            ]).unwrap();
+            volume.scatter(builder, set, settings, view);
+        }
+        let shading_layout = self.resolve.as_ref().map_or(layout.clone(), |r| r.frame_layout());
+        let shading_writes = |clusters: bool| {
+            let mut writes = vec![
+                WriteDescriptorSet::buffer(0, uniforms.clone()),
+                WriteDescriptorSet::image_view_sampler(1, self.shadows.depth.clone(), self.shadows.sampler.clone()),
+                WriteDescriptorSet::image_view_sampler(2, self.shadows.local_depth.clone(), self.shadows.sampler.clone()),
+                WriteDescriptorSet::image_view_sampler(3, env.irradiance.clone(), self.ibl.sampler.clone()),
+                WriteDescriptorSet::image_view_sampler(4, env.prefiltered.clone(), self.ibl.sampler.clone()),
+                WriteDescriptorSet::image_view_sampler(5, self.ibl.brdf_lut.clone(), self.ibl.sampler.clone()),
+                WriteDescriptorSet::image_view_sampler_array(6, 0, probe_views(|e| e.irradiance.clone() as Arc<dyn ImageViewAbstract>)),
+                WriteDescriptorSet::image_view_sampler_array(7, 0, probe_views(|e| e.prefiltered.clone() as Arc<dyn ImageViewAbstract>)),
+                WriteDescriptorSet::buffer(8, light_buffer.clone()),
+                WriteDescriptorSet::image_view_sampler_array(10, 0, self.voxels.views()),
+            ];
+            if clusters { writes.push(WriteDescriptorSet::buffer(9, self.clusters.clusters.clone())); }
+            writes
+        };
+        let shading_set = PersistentDescriptorSet::new(shading_layout, shading_writes(self.resolve.is_none())).unwrap();
+        let frame_set = match self.resolve {
+            Some(_) => PersistentDescriptorSet::new(layout.clone(), [WriteDescriptorSet::buffer(0, uniforms.clone())]).unwrap(),
+            None => shading_set.clone(),
+        };
+        let mut clear_values = vec![ [0.0, 0.0, 1.0, 1.0].into(), [0u32; 4].into(), [0.0; 4].into(), 1f32.into() ];
+        clear_values.extend(target.gbuffer.iter().map(|_| [0.0; 4].into()));
+        builder.begin_render_pass(target.framebuffer.clone(), SubpassContents::Inline, clear_values).unwrap()
+            .set_viewport(0, [target.viewport()]);
+
+        let mut stats = DrawStats::default();
+        let mut frame_billboards = scene.billboards.clone();
+        //particles leave their entity's bounds behind, so they aren't culled with it
+        for emitter in scene.entities.iter().filter_map(|e| e.particles.as_ref()) { frame_billboards.extend(emitter.billboards()); }
+        let mut displaced_frame_set = None;
+        for entity in scene.entities.iter().filter(|e| visible(e)) {
+            let mesh = match &entity.mesh { Some(m) => m, None => continue };
+            if let Some(b) = entity.impostor_lod(eye) { frame_billboards.push(b); continue; }
+            if entity.portal.is_some() {
+                let transform = (view_proj * entity.transform).to_cols_array_2d();
+                let texture = match portal_views.get(&entity.id) { Some(t) => t.clone(), None => continue };
+                let layout = self.portal_pipeline.layout().set_layouts().get(0).unwrap();
+                let set = PersistentDescriptorSet::new(layout.clone(), [WriteDescriptorSet::image_view_sampler(0, texture, self.sampler.clone())]).unwrap();
+                let pc = portal_vs::ty::PushConstants { transform, inv_extent: [1.0 / target.extent[0] as f32, 1.0 / target.extent[1] as f32], id: entity.id };
+                builder.bind_pipeline_graphics(self.portal_pipeline.clone())
+                    .bind_descriptor_sets(PipelineBindPoint::Graphics, self.portal_pipeline.layout().clone(), 0, set)
+                    .push_constants(self.portal_pipeline.layout().clone(), 0, pc);
+            } else {
+                let m = &entity.material;
+                let lightmapped = entity.lightmap.as_ref().map_or(false, |l| l.texture.is_some());
+                let pc = fs::ty::PushConstants {
+                    model: entity.transform.to_cols_array_2d(), base_color: m.base_color,
+                    emissive: [m.emissive[0], m.emissive[1], m.emissive[2], 0.0], params: [m.metallic, m.roughness, m.shininess, m.normal_scale],
+                    shading: match m.shading { ShadingModel::Pbr => 0, ShadingModel::BlinnPhong => 1, ShadingModel::Toon { .. } => 2 }, flags: m.flags() | if lightmapped { FLAG_LIGHTMAP } else { 0 } | if entity.skin.is_some() { FLAG_SKINNED } else { 0 }
+                        | if entity.morph.is_some() { FLAG_MORPHED } else { 0 }, id: entity.id };
+                //the tessellation stages see set 0 too, so it needs a set made for their layout
+                let (pipeline, frame_set) = match self.displaced.as_ref().filter(|_| m.displacement.is_some()) {
+                    Some(p) => (p, displaced_frame_set.get_or_insert_with(|| PersistentDescriptorSet::new(p.layout().set_layouts().get(0).unwrap().clone(), match self.resolve {
+                        Some(_) => vec![WriteDescriptorSet::buffer(0, uniforms.clone())],
+                        None => shading_writes(true),
+                    }).unwrap()).clone()),
+                    None => (&self.pipeline, frame_set.clone()),
+                };
+                let displaced = !Arc::ptr_eq(pipeline, &self.pipeline);
+                builder.bind_pipeline_graphics(pipeline.clone())
+                    .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 0,
+                                          vec![frame_set, self.material_set(m, pipeline, displaced), self.object_set(scene, entity, pipeline)])
+                    .push_constants(pipeline.layout().clone(), 0, pc);
+            }
+            builder.bind_vertex_buffers(0, mesh.vertex_buffer.clone())
+                .draw(mesh.vertices.len() as u32, 1, 0, 0).unwrap();
+            stats.add(1, mesh.vertices.len() as u64 / 3);
+            if let (None, Some(outline)) = (&entity.portal, entity.material.outline) {
+                self.draw_outline(builder, target, view_proj * entity.transform, outline, entity.id, mesh.vertices.len() as u32);
+                stats.add(1, mesh.vertices.len() as u64 / 3);
+            }
+        }
+        if let Some(sdf) = &scene.sdf {
+            //its own layout: the fragment shader alone reads the frame set
+            let writes = match self.resolve {
+                Some(_) => vec![WriteDescriptorSet::buffer(0, uniforms.clone())],
+                None => shading_writes(true),
+            };
+            self.sdf.draw(builder, PersistentDescriptorSet::new(self.sdf.frame_layout(), writes).unwrap(), sdf, view);
+            stats.add(1, 1);
+        }
+        if let Some(sky) = &scene.skybox {
+            self.skybox.draw(builder, sky, view, self.no_environment.cube.clone(), self.white.view.clone());
+            stats.add(1, 12);
+        }
+        match &self.resolve {
+            Some(resolve) => {
+                builder.end_render_pass().unwrap();
+                let tlas = self.ray_tracing.as_ref().and_then(|r| r.tlas());
+                match (&self.rtao, tlas, &scene.rtao, &self.ssao) {
+                    (Some(pass), Some(tlas), Some(settings), Some(ssao)) => pass.record(builder, target, tlas, settings, view, ssao),
+                    (_, _, _, Some(ssao)) => ssao.record(builder, target, scene.ssao.as_ref(), view),
+                    _ => {}
+                }
+                let reflected = match (&self.rt_lighting, tlas, &scene.rt_lighting) {
+                    (Some(pass), Some(tlas), Some(settings)) => pass.record(builder, target, tlas, settings, view, &lights),
+                    _ => {
+                        builder.clear_color_image(target.rt_shadow.clone().unwrap().image().clone(), ClearValue::Float([-1.0; 4])).unwrap();
+                        false
+                    }
+                };
+                if !reflected { if let Some(ssr) = &self.ssr { ssr.record(builder, target, scene.ssr.as_ref(), view); } }
+                resolve.light(builder, shading_set, target, view);
+                builder.begin_render_pass(target.composite.clone().unwrap(), SubpassContents::Inline, vec![ClearValue::None; 2]).unwrap()
+                    .set_viewport(0, [target.viewport()]);
+                resolve.composite(builder, target);
+            }
+            None => { builder.next_subpass(SubpassContents::Inline).unwrap(); }
+        }
+        if let (Some(volume), Some(settings)) = (&fog, &scene.volumetric_fog) {
+            volume.apply(builder, target, settings, view);
+        }
+        let tiles = self.tilemaps.draw(builder, &scene.tilemaps, view);
+        let cutouts: Vec<_> = scene.cutouts.iter().map(|c| c.mesh()).collect();
+        let sprites = self.billboards.draw(builder, &frame_billboards, &cutouts, &scene.sorting_layers, &scene.lighting_2d, target.depth.clone(), view);
+        self.gpu_particles.draw(builder, scene, target.depth.clone(), view);
+        builder.end_render_pass().unwrap();
+        for s in [tiles, sprites] { stats.add(s.draw_calls, s.triangles); }
+        let mut total = self.stats.get();
+        total.add(stats.draw_calls, stats.triangles);
+        self.stats.set(total);
+    }
+}
@@ -0,0 +1,51 @@
+/// Scales the render resolution to keep GPU frame time under a budget. Fed the GPU time every
+/// frame; `scale` multiplies both axes of the render size. A change reallocates the scene
+/// target, though never the swapchain, so the scale moves in steps and settles between them.
+pub struct DynamicResolution {
+    pub enabled: bool,
+    /// Milliseconds the GPU may spend on a frame.
+    pub budget_ms: f32,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    scale: f32,
+    /// Smoothed GPU time, so one slow frame doesn't move anything.
+    average_ms: Option<f32>,
+    /// Frames until the next change is allowed.
+    cooldown: u32,
+}
+
+/// Scales are multiples of this, so small wobbles in frame time don't reallocate.
+const STEP: f32 = 0.05;
+const COOLDOWN_FRAMES: u32 = 15;
+/// Only scale back up with this much of the budget to spare, or it would oscillate.
+const HEADROOM: f32 = 0.85;
+
+impl DynamicResolution {
+    pub fn new(budget_ms: f32) -> Self {
+        DynamicResolution { enabled: true, budget_ms, min_scale: 0.5, max_scale: 1.0, scale: 1.0, average_ms: None, cooldown: 0 }
+    }
+
+    /// Once a frame, before the render size is decided.
+    pub fn update(&mut self, gpu_ms: Option<f32>) {
+        if !self.enabled { self.scale = 1.0; return; }
+        let ms = match gpu_ms { Some(ms) => ms, None => return };
+        let average = match self.average_ms { Some(a) => a + (ms - a) * 0.1, None => ms };
+        self.average_ms = Some(average);
+        if self.cooldown > 0 { self.cooldown -= 1; return; }
+
+        //gpu time goes roughly with the pixel count, which goes with the scale squared
+        let fits = self.scale * (self.budget_ms / average.max(0.01)).sqrt();
+        let wanted = if average > self.budget_ms { (fits / STEP).floor() * STEP }
+                     else if average < self.budget_ms * HEADROOM { (self.scale + STEP).min((fits / STEP).floor() * STEP) }
+                     else { self.scale };
+        let wanted = wanted.clamp(self.min_scale, self.max_scale);
+        if (wanted - self.scale).abs() > STEP * 0.5 {
+            self.scale = wanted;
+            self.cooldown = COOLDOWN_FRAMES;
+            //the average was of the old size
+            self.average_ms = None;
+        }
+    }
+
+    pub fn scale(&self) -> f32 { if self.enabled { self.scale } else { 1.0 } }
+}
@@ -0,0 +1,110 @@
+use vulkano::{ device::{ Device, Queue },
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, CommandBufferUsage },
+               image::{ AttachmentImage, ImageUsage, view::ImageView },
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode },
+               format::{ ClearValue, Format } };
+use ash::vk;
+use std::sync::Arc;
+use crate::camera::View;
+use crate::light::SceneLights;
+use crate::raw_commands::RawCommands;
+use crate::raytracing::{ RawSetLayout, RawWrite, RtPipeline, Tlas };
+use crate::renderer::Target;
+
+mod raygen {
+    vulkano_shaders::shader! { ty: "raygen", path: "src/shaders/rt_lighting.rgen", include: ["src/shaders"], vulkan_version: "1.2", spirv_version: "1.4",
+                               types_meta: { use bytemuck::{ Pod, Zeroable }; #[derive(Clone, Copy, Zeroable, Pod)] } }
+}
+mod reflection_miss {
+    vulkano_shaders::shader! { ty: "miss", path: "src/shaders/rt_reflection.rmiss", vulkan_version: "1.2", spirv_version: "1.4" }
+}
+mod shadow_miss {
+    vulkano_shaders::shader! { ty: "miss", path: "src/shaders/rt_shadow.rmiss", vulkan_version: "1.2", spirv_version: "1.4" }
+}
+mod hit {
+    vulkano_shaders::shader! { ty: "closesthit", path: "src/shaders/rt_lighting.rchit", include: ["src/shaders"], vulkan_version: "1.2", spirv_version: "1.4" }
+}
+
+pub const SHADOW_FORMAT: Format = Format::R32_SFLOAT;
+
+/// Ray traced sun shadows and reflections, over the shadow map and screen space reflections.
+/// Deferred only, and only on devices with `RayTracingSupport::pipeline`.
+#[derive(Clone, Copy, Debug)]
+pub struct RtLighting {
+    pub shadows: bool,
+    pub reflections: bool,
+    /// Meters a reflected ray travels.
+    pub max_distance: f32,
+    /// Rougher surfaces keep the probes; reflections fade out on the way there.
+    pub max_roughness: f32,
+}
+
+impl Default for RtLighting {
+    fn default() -> Self { RtLighting { shadows: true, reflections: true, max_distance: 100.0, max_roughness: 0.6 } }
+}
+
+/// The sun's visibility, or -1 wherever the shadow map has it instead.
+pub fn shadow_view(dev: &Arc<Device>, extent: [u32; 2]) -> Arc<ImageView<AttachmentImage>> {
+    ImageView::new_default(AttachmentImage::with_usage(dev.clone(), extent, SHADOW_FORMAT,
+        ImageUsage { storage: true, sampled: true, transfer_destination: true, ..ImageUsage::none() }).unwrap()).unwrap()
+}
+
+/// Traces a shadow ray to the sun and a reflected ray from every g-buffer pixel against the
+/// frame's TLAS. Leaves `target.rt_shadow` and `target.reflections[0]` for the tiled resolve.
+/// What reflections hit is lit by the sun and ambient alone, with its entity's base color;
+/// where they miss the probes stay.
+pub struct RtLightingPass {
+    queue: Arc<Queue>,
+    layout: RawSetLayout,
+    pipeline: RtPipeline,
+    sampler: Arc<Sampler>,
+}
+
+impl RtLightingPass {
+    pub fn new(queue: Arc<Queue>) -> Self {
+        let dev = queue.device().clone();
+        let ty = vk::DescriptorType::COMBINED_IMAGE_SAMPLER;
+        let layout = RawSetLayout::new(&dev, &[vk::DescriptorType::ACCELERATION_STRUCTURE_KHR, ty, ty, vk::DescriptorType::STORAGE_IMAGE,
+                                               vk::DescriptorType::STORAGE_IMAGE, vk::DescriptorType::STORAGE_BUFFER],
+                                       vk::ShaderStageFlags::RAYGEN_KHR | vk::ShaderStageFlags::MISS_KHR | vk::ShaderStageFlags::CLOSEST_HIT_KHR);
+        //reflection rays cast shadow rays from their hits
+        let pipeline = RtPipeline::new(&dev, &layout, std::mem::size_of::<raygen::ty::PushConstants>() as u32, &raygen::load(dev.clone()).unwrap(),
+                                       &[&*reflection_miss::load(dev.clone()).unwrap(), &*shadow_miss::load(dev.clone()).unwrap()],
+                                       &[&*hit::load(dev.clone()).unwrap()], 2);
+        let sampler = Sampler::new(dev, SamplerCreateInfo { address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        RtLightingPass { queue, layout, pipeline, sampler }
+    }
+
+    /// Between the g-buffer pass and the resolve. Whether it traced reflections, ssr's to do otherwise.
+    #[profiling::function]
+    pub fn record(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, target: &Target, tlas: &Arc<Tlas>, settings: &RtLighting,
+                  view: &View, lights: &SceneLights) -> bool {
+        let shadow = target.rt_shadow.clone().unwrap();
+        if !settings.shadows { builder.clear_color_image(shadow.image().clone(), ClearValue::Float([-1.0; 4])).unwrap(); }
+        if !settings.shadows && !settings.reflections { return false; }
+        let set = self.layout.set(&[
+            RawWrite::Structure(tlas),
+            RawWrite::Sampled(&*target.depth, &self.sampler),
+            RawWrite::Sampled(&*target.gbuffer[1], &self.sampler),
+            RawWrite::Storage(&*shadow),
+            RawWrite::Storage(&*target.reflections[0]),
+            RawWrite::Buffer(tlas.instances.handle, 0, tlas.instances.size),
+        ]);
+        let (sun_direction, sun_radiance) = lights.sun.map_or(([0.0; 4], [0.0; 4]), |(d, r)| (d.extend(1.0).into(), r.extend(0.0).into()));
+        let pc = raygen::ty::PushConstants {
+            inv_view_proj: view.view_proj().inverse().to_cols_array_2d(),
+            eye: view.eye().extend(settings.max_distance).into(),
+            sun_direction,
+            sun_radiance: [sun_radiance[0], sun_radiance[1], sun_radiance[2], if settings.reflections { settings.max_roughness } else { -1.0 }],
+            ambient: lights.ambient.extend(settings.shadows as u32 as f32).into(),
+        };
+        let commands = RawCommands::record(&self.queue, CommandBufferUsage::OneTimeSubmit, |fns, cb| unsafe {
+            self.pipeline.trace(fns, cb, &set, bytemuck::bytes_of(&pc), target.extent);
+        });
+        let commands = commands.image(target.depth.image().clone(), false).image(target.gbuffer[1].image().clone(), false)
+            .image(shadow.image().clone(), settings.shadows).image(target.reflections[0].image().clone(), settings.reflections)
+            .keep(set).keep(tlas.clone());
+        builder.execute_commands(commands).unwrap();
+        settings.reflections
+    }
+}
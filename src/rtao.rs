@@ -0,0 +1,128 @@
+use vulkano::{ device::{ Device, Queue },
+               buffer::{ BufferAccess, CpuBufferPool },
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, CommandBufferUsage },
+               image::{ AttachmentImage, ImageUsage, view::ImageView },
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode },
+               format::Format,
+               VulkanObject };
+use ash::vk;
+use glam::{ Mat4, Vec3 };
+use std::cell::Cell;
+use std::sync::Arc;
+use crate::camera::View;
+use crate::raw_commands::RawCommands;
+use crate::raytracing::{ RawPipeline, RawSetLayout, RawWrite, Tlas };
+use crate::renderer::Target;
+use crate::ssao::SsaoPass;
+
+mod cs {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/rtao.comp", vulkan_version: "1.2", spirv_version: "1.4",
+                               types_meta: { use bytemuck::{ Pod, Zeroable }; #[derive(Clone, Copy, Zeroable, Pod)] } }
+}
+
+/// Occlusion, frames averaged into it and distance to the eye, per pixel.
+pub const HISTORY_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// Ray traced ambient occlusion, in place of `Ssao` on devices with `RayTracingSupport::query`.
+/// Occluders off screen and behind others count, which screen space can't see. Deferred only.
+#[derive(Clone, Copy, Debug)]
+pub struct Rtao {
+    /// Meters a ray travels before it counts as open.
+    pub radius: f32,
+    /// Power on the result; above 1 darkens.
+    pub intensity: f32,
+    /// Rays per pixel each frame.
+    pub rays: u32,
+    /// Frames averaged at most; more is smoother and slower to catch up.
+    pub frames: u32,
+    /// How hard the blur stops at depth edges, per meter.
+    pub sharpness: f32,
+}
+
+impl Default for Rtao {
+    fn default() -> Self { Rtao { radius: 1.0, intensity: 1.0, rays: 2, frames: 16, sharpness: 8.0 } }
+}
+
+/// A target's accumulated occlusion, this frame's written over the last's, and the view the
+/// last was traced from to reproject it with.
+pub struct AoHistory {
+    images: [Arc<ImageView<AttachmentImage>>; 2],
+    frame: Cell<u32>,
+    previous: Cell<Option<(Mat4, Vec3)>>,
+}
+
+impl AoHistory {
+    pub fn new(dev: &Arc<Device>, extent: [u32; 2]) -> Self {
+        let image = || ImageView::new_default(AttachmentImage::with_usage(dev.clone(), extent, HISTORY_FORMAT,
+            ImageUsage { storage: true, sampled: true, ..ImageUsage::none() }).unwrap()).unwrap();
+        AoHistory { images: [image(), image()], frame: Cell::new(0), previous: Cell::new(None) }
+    }
+}
+
+/// Queries a few rays per pixel from the g-buffer's depth and normals against the frame's TLAS,
+/// accumulates them with the target's history, then runs them through the ssao blur. Leaves
+/// `target.ao[0]` for the tiled resolve like `SsaoPass`.
+pub struct RtaoPass {
+    queue: Arc<Queue>,
+    layout: RawSetLayout,
+    pipeline: RawPipeline,
+    params: CpuBufferPool<cs::ty::Params>,
+    sampler: Arc<Sampler>,
+}
+
+impl RtaoPass {
+    pub fn new(queue: Arc<Queue>) -> Self {
+        let dev = queue.device().clone();
+        let sampled = vk::DescriptorType::COMBINED_IMAGE_SAMPLER;
+        let layout = RawSetLayout::new(&dev, &[vk::DescriptorType::ACCELERATION_STRUCTURE_KHR, sampled, sampled, sampled, sampled,
+                                               vk::DescriptorType::STORAGE_IMAGE, vk::DescriptorType::STORAGE_IMAGE, vk::DescriptorType::UNIFORM_BUFFER],
+                                       vk::ShaderStageFlags::COMPUTE);
+        let pipeline = RawPipeline::compute(&dev, &layout, 0, &cs::load(dev.clone()).unwrap());
+        let params = CpuBufferPool::uniform_buffer(dev.clone());
+        let sampler = Sampler::new(dev, SamplerCreateInfo { address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        RtaoPass { queue, layout, pipeline, params, sampler }
+    }
+
+    /// Between the g-buffer pass and the resolve, in place of `SsaoPass::record`; `ssao` for its blur.
+    #[profiling::function]
+    pub fn record(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, target: &Target, tlas: &Arc<Tlas>, rtao: &Rtao,
+                  view: &View, ssao: &SsaoPass) {
+        let history = target.ao_history.as_ref().unwrap();
+        let frame = history.frame.get();
+        history.frame.set(frame + 1);
+        let (current, last) = (&history.images[frame as usize % 2], &history.images[(frame as usize + 1) % 2]);
+        let view_proj = view.view_proj();
+        let eye = view.eye();
+        let (previous_view_proj, previous_eye) = history.previous.replace(Some((view_proj, eye))).map_or((Mat4::IDENTITY, [0.0; 4]), |(m, e)| (m, e.extend(1.0).into()));
+        let params = self.params.next(cs::ty::Params {
+            inv_view_proj: view_proj.inverse().to_cols_array_2d(),
+            previous_view_proj: previous_view_proj.to_cols_array_2d(),
+            eye: eye.extend(rtao.intensity).into(),
+            previous_eye,
+            params: [rtao.radius, rtao.rays as f32, rtao.frames.max(1) as f32, frame as f32],
+        }).unwrap();
+        let buffer = params.inner();
+        let set = self.layout.set(&[
+            RawWrite::Structure(tlas),
+            RawWrite::Sampled(&*target.depth, &self.sampler),
+            RawWrite::Sampled(&*target.gbuffer[1], &self.sampler),
+            RawWrite::Sampled(&*target.velocity, &self.sampler),
+            RawWrite::Sampled(&**last, &self.sampler),
+            RawWrite::Storage(&**current),
+            RawWrite::Storage(&*target.ao[0]),
+            RawWrite::Uniform(buffer.buffer.internal_object(), buffer.offset, params.size()),
+        ]);
+        let groups = [(target.extent[0] + 7) / 8, (target.extent[1] + 7) / 8];
+        let commands = RawCommands::record(&self.queue, CommandBufferUsage::OneTimeSubmit, |fns, cb| unsafe {
+            self.pipeline.bind(fns, cb, vk::PipelineBindPoint::COMPUTE, &set, &[]);
+            (fns.v1_0.cmd_dispatch)(cb, groups[0], groups[1], 1);
+        });
+        let commands = commands.image(target.depth.image().clone(), false).image(target.gbuffer[1].image().clone(), false)
+            .image(target.velocity.image().clone(), false).image(last.image().clone(), false)
+            .image(current.image().clone(), true).image(target.ao[0].image().clone(), true)
+            .buffer(params.clone(), false)
+            .keep(set).keep(tlas.clone());
+        builder.execute_commands(commands).unwrap();
+        ssao.blur(builder, target, rtao.sharpness, view);
+    }
+}
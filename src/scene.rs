@@ -0,0 +1,213 @@
+use glam::{ Mat4, Vec3 };
+use std::sync::Arc;
+use crate::mesh::Mesh;
+use crate::bvh::{ Aabb, Bvh, Ray, ray_triangle };
+use crate::billboard::{ Billboard, SortingLayer, SpriteSort };
+use crate::cutout::Cutout;
+use crate::impostor::Impostor;
+use crate::portal::Portal;
+use crate::light::{ Light, ContactShadows };
+use crate::material::Material;
+use crate::ibl::Environment;
+use crate::probe::ReflectionProbe;
+use crate::light_probe::LightProbeGrid;
+use crate::lightmap::Lightmap;
+use crate::audio::AudioEmitter;
+use crate::skin::Skin;
+use crate::morph::Morph;
+use crate::particles::ParticleEmitter;
+use crate::gpu_particles::GpuEmitter;
+use crate::camera_path::CameraPath;
+use crate::tilemap::Tilemap;
+use crate::light2d::Lighting2d;
+use crate::skybox::Skybox;
+use crate::volumetric::VolumetricFog;
+use crate::fog::Fog;
+use crate::sdf::SdfScene;
+use crate::voxel_gi::VoxelGi;
+use crate::ssao::Ssao;
+use crate::ssr::Ssr;
+use crate::rt_lighting::RtLighting;
+use crate::rtao::Rtao;
+use crate::water::Water;
+use crate::postfx::{ tonemap::Tonemap, bloom::Bloom, motion_blur::MotionBlur, fxaa::Fxaa, taa::Taa, grading::ColorGrading, cas::Cas, fsr::Fsr, transition::Transitions, hdr::HdrOutput };
+
+/// Entity handle. 0 is reserved for "nothing", which is also what the id buffer is cleared to.
+pub type EntityId = u32;
+
+pub struct Entity {
+    pub id: EntityId,
+    pub mesh: Option<Arc<Mesh>>,
+    pub transform: Mat4,
+    /// `transform` as it was drawn last frame, for motion vectors. See `end_frame`.
+    pub previous_transform: Mat4,
+    pub material: Material,
+    pub impostor: Option<Arc<Impostor>>,
+    pub portal: Option<Portal>,
+    pub light: Option<Light>,
+    pub probe: Option<ReflectionProbe>,
+    pub lightmap: Option<Lightmap>,
+    /// Sound playing from the entity's position.
+    pub emitter: Option<AudioEmitter>,
+    /// Bends `mesh` by an animated skeleton; its weights follow the mesh's vertex order.
+    pub skin: Option<Skin>,
+    /// Blend shapes of `mesh`, applied before the skin.
+    pub morph: Option<Morph>,
+    pub particles: Option<ParticleEmitter>,
+    /// Simulated in compute, for effects with far more particles.
+    pub gpu_particles: Option<GpuEmitter>,
+}
+
+impl Entity {
+    /// Impostor billboard to draw instead of the mesh, if the entity is far enough from `eye`.
+    pub fn impostor_lod(&self, eye: Vec3) -> Option<Billboard> {
+        let imp = self.impostor.as_ref()?;
+        let pos = self.transform.w_axis.truncate();
+        if pos.distance(eye) < imp.distance { return None; }
+        Some(imp.billboard(&self.transform, self.mesh.as_ref()?, eye))
+    }
+
+    pub fn position(&self) -> Vec3 { self.transform.w_axis.truncate() }
+
+    /// Mesh bounds in world space; entities without a mesh are a point at their position.
+    pub fn world_bounds(&self) -> Aabb {
+        match &self.mesh {
+            Some(mesh) => mesh.aabb.transformed(&self.transform),
+            None => Aabb { min: self.position(), max: self.position() },
+        }
+    }
+    /// (center, radius), radius scaled by the largest axis scale.
+    pub fn world_sphere(&self) -> (Vec3, f32) {
+        let mesh = match &self.mesh { Some(m) => m, None => return (self.position(), 0.0) };
+        let (scale, _, _) = self.transform.to_scale_rotation_translation();
+        (self.transform.transform_point3(mesh.aabb.center()), mesh.aabb.extent().length() * 0.5 * scale.abs().max_element())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    pub entity: EntityId,
+    pub distance: f32,
+    pub point: Vec3,
+}
+
+pub struct Scene {
+    pub entities: Vec<Entity>,
+    pub billboards: Vec<Billboard>,
+    /// Back to front, see `Billboard::layer`. Starts with a "default" one sorted by depth.
+    pub sorting_layers: Vec<SortingLayer>,
+    /// Drawn under the billboards, see `Tilemaps`.
+    pub tilemaps: Vec<Tilemap>,
+    /// Skeletal cutout characters, drawn with the billboards.
+    pub cutouts: Vec<Cutout>,
+    /// Lights billboards on lit sorting layers.
+    pub lighting_2d: Lighting2d,
+    pub ambient: Vec3,
+    /// Image based ambient light; falls back to the flat `ambient` when None.
+    pub environment: Option<Arc<Environment>>,
+    /// Baked diffuse lighting, overrides the environment's for every object inside it.
+    pub light_probes: Option<LightProbeGrid>,
+    /// Drawn where no geometry is; the clear color shows when None.
+    pub skybox: Option<Skybox>,
+    /// Analytic distance and height fog, applied while shading.
+    pub fog: Option<Fog>,
+    /// Lit fog in the air, drawn for views that were given a `FogVolume`.
+    pub volumetric_fog: Option<VolumetricFog>,
+    /// Raymarched distance field drawn with the opaque geometry, see sdf.rs.
+    pub sdf: Option<SdfScene>,
+    /// Voxel cone traced bounce light, drawn once the renderer's clipmap has a quality.
+    pub voxel_gi: Option<VoxelGi>,
+    /// Ambient occlusion from the depth and normals, deferred only.
+    pub ssao: Option<Ssao>,
+    /// Ray traced ambient occlusion in place of ssao, where the device can.
+    pub rtao: Option<Rtao>,
+    /// Screen space reflections over the probes, deferred only.
+    pub ssr: Option<Ssr>,
+    /// Ray traced sun shadows and reflections over the shadow map and ssr, where the device can.
+    pub rt_lighting: Option<RtLighting>,
+    /// Tuning for lights with contact shadows on; None turns them all off. Deferred only.
+    pub contact_shadows: Option<ContactShadows>,
+    /// HDR to display mapping in the post stack; None clamps.
+    pub tonemap: Option<Tonemap>,
+    /// Glow around bright pixels, added in HDR before the tonemapper.
+    pub bloom: Option<Bloom>,
+    /// Streaks along camera and object motion, before bloom.
+    pub motion_blur: Option<MotionBlur>,
+    /// Look applied after the tonemapper, through 3D luts.
+    pub grading: Option<ColorGrading>,
+    /// Post process anti-aliasing on the tonemapped image.
+    pub fxaa: Option<Fxaa>,
+    /// Temporal anti-aliasing; the main view is jittered while it is set.
+    pub taa: Option<Taa>,
+    /// Contrast adaptive sharpening, last thing before the swapchain.
+    pub sharpen: Option<Cas>,
+    /// Render below the display's resolution and upscale with FSR 1.
+    pub fsr: Option<Fsr>,
+    /// Render resolution over the display's, 0.5..2. Above 1 supersamples.
+    pub render_scale: f32,
+    /// Display brightness, used while presenting to an HDR display.
+    pub hdr_output: HdrOutput,
+    /// Fades and wipes over the final image.
+    pub transitions: Transitions,
+    /// Drawn over the scene by `WaterPass`.
+    pub water: Option<Water>,
+    /// Cutscenes and flythroughs, played by a `PathPlayer`. Saved and loaded with camera_path.rs.
+    pub camera_paths: Vec<CameraPath>,
+    pub bvh: Bvh,
+    next_id: EntityId,
+}
+
+impl Scene {
+    pub fn new() -> Self { Scene { entities: Vec::new(), billboards: Vec::new(), sorting_layers: vec![SortingLayer::new("default", SpriteSort::Depth)], tilemaps: Vec::new(), cutouts: Vec::new(), lighting_2d: Lighting2d::default(), ambient: Vec3::splat(0.05), environment: None, light_probes: None, skybox: None, fog: None, volumetric_fog: None, sdf: None, voxel_gi: None, ssao: None, rtao: None, ssr: None, rt_lighting: None, contact_shadows: None, tonemap: None, bloom: None, motion_blur: None, grading: None, fxaa: None, taa: None, sharpen: None, fsr: None, render_scale: 1.0, hdr_output: HdrOutput::default(), transitions: Transitions::new(), water: None, camera_paths: Vec::new(), bvh: Bvh::default(), next_id: 1 } }
+
+    pub fn spawn(&mut self, mesh: Arc<Mesh>, transform: Mat4) -> EntityId { self.spawn_empty(transform, Some(mesh)) }
+
+    /// Entity that may have no mesh, for lights and other component-only things.
+    pub fn spawn_empty(&mut self, transform: Mat4, mesh: Option<Arc<Mesh>>) -> EntityId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entities.push(Entity { id, mesh, transform, previous_transform: transform, material: Material::default(), impostor: None, portal: None, light: None, probe: None, lightmap: None, emitter: None, skin: None, morph: None, particles: None, gpu_particles: None });
+        id
+    }
+
+    pub fn spawn_light(&mut self, light: Light, transform: Mat4) -> EntityId {
+        let id = self.spawn_empty(transform, None);
+        self.get_mut(id).unwrap().light = Some(light);
+        id
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&Entity> { self.entities.iter().find(|e| e.id == id) }
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut Entity> { self.entities.iter_mut().find(|e| e.id == id) }
+
+    /// Rebuilds the bvh from current transforms. Call once per frame after gameplay moved things.
+    pub fn update_bounds(&mut self) {
+        self.bvh = Bvh::build(self.entities.iter().filter(|e| e.mesh.is_some()).map(|e| (e.id, e.world_bounds())).collect());
+    }
+
+    /// Remembers this frame's transforms as the previous ones. Call once per frame after recording.
+    pub fn end_frame(&mut self) {
+        for e in &mut self.entities { e.previous_transform = e.transform; }
+    }
+
+    /// Closest triangle hit, tested in world space against entities the bvh lets through.
+    pub fn raycast(&self, ray: &Ray) -> Option<RayHit> { self.raycast_filtered(ray, |_| true) }
+
+    /// `raycast` through every entity `keep` turns down.
+    pub fn raycast_filtered(&self, ray: &Ray, keep: impl Fn(&Entity) -> bool) -> Option<RayHit> {
+        let mut candidates = self.bvh.query_ray(ray);
+        candidates.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let mut best: Option<RayHit> = None;
+        for (id, entry) in candidates {
+            if best.map_or(false, |b| b.distance < entry) { break; }
+            let entity = self.get(id).unwrap();
+            let mesh = match &entity.mesh { Some(m) if keep(entity) => m, _ => continue };
+            for [a, b, c] in mesh.triangles() {
+                let t = &entity.transform;
+                if let Some(d) = ray_triangle(ray, t.transform_point3(a), t.transform_point3(b), t.transform_point3(c)) {
+                    if best.map_or(true, |b| d < b.distance) { best = Some(RayHit { entity: id, distance: d, point: ray.at(d) }); }
+                }
+            }
+        }
+        best
+    }
+}
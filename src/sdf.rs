@@ -0,0 +1,84 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, layout::DescriptorSetLayout },
+               render_pass::Subpass,
+               pipeline::{ GraphicsPipeline, Pipeline, PipelineBindPoint,
+                           graphics::{ input_assembly::InputAssemblyState, vertex_input::BuffersDefinition, viewport::ViewportState,
+                                       depth_stencil::DepthStencilState, color_blend::ColorBlendState } } };
+use std::sync::Arc;
+use crate::camera::View;
+use crate::scene::EntityId;
+
+mod vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			void main() {
+				gl_Position = vec4(vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0, 0.0, 1.0);
+			}"
+    }
+}
+mod fs {
+    vulkano_shaders::shader! { ty: "fragment", path: "src/shaders/sdf.frag", include: ["src/shaders"] }
+}
+mod gbuffer_fs {
+    vulkano_shaders::shader! { ty: "fragment", path: "src/shaders/sdf.frag", include: ["src/shaders"], define: [("DEFERRED", "")] }
+}
+
+/// Raymarched distance field drawn with the opaque geometry. The field itself is GLSL in
+/// src/shaders/sdf_scene.glsl, built into the engine with the other shaders; this is how far
+/// and how carefully to march it.
+#[derive(Clone, Copy, Debug)]
+pub struct SdfScene {
+    pub max_steps: u32,
+    /// Meters from the camera before a ray gives up.
+    pub max_distance: f32,
+    /// Hit threshold per meter of ray, so it loosens with distance.
+    pub epsilon: f32,
+    /// Handed to the field for animation. Advance it yourself.
+    pub time: f32,
+    /// Written to the id buffer so the field can be picked. 0 is nothing.
+    pub id: EntityId,
+}
+
+impl Default for SdfScene {
+    fn default() -> Self { SdfScene { max_steps: 128, max_distance: 100.0, epsilon: 1e-3, time: 0.0, id: 0 } }
+}
+
+/// Full screen pass over the opaque subpass that writes `gl_FragDepth` for every hit, so the
+/// field and rasterized meshes occlude each other, and what draws later (skybox, fog,
+/// billboards) sees it like any mesh. Forward shades it with the scene's lights; deferred
+/// writes it into the g-buffer for the tiled resolve.
+pub struct SdfRenderer {
+    pipeline: Arc<GraphicsPipeline>,
+}
+
+impl SdfRenderer {
+    pub fn new(dev: Arc<Device>, subpass: Subpass, deferred: bool) -> Self {
+        let vs = vs::load(dev.clone()).unwrap();
+        let fs = if deferred { gbuffer_fs::load(dev.clone()).unwrap() } else { fs::load(dev.clone()).unwrap() };
+        let pipeline = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .depth_stencil_state(DepthStencilState::simple_depth_test())
+            .color_blend_state(ColorBlendState::new(subpass.num_color_attachments()))
+            .render_pass(subpass)
+            .build(dev).unwrap();
+        SdfRenderer { pipeline }
+    }
+
+    /// Layout of the frame set (set 0). Forward needs all of it, deferred only the uniforms.
+    pub fn frame_layout(&self) -> Arc<DescriptorSetLayout> { self.pipeline.layout().set_layouts().get(0).unwrap().clone() }
+
+    pub fn draw(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, frame_set: Arc<PersistentDescriptorSet>, sdf: &SdfScene, view: &View) {
+        let pc = fs::ty::PushConstants { inv_view_proj: view.view_proj().inverse().to_cols_array_2d(),
+                                         march: [sdf.max_steps as f32, sdf.max_distance, sdf.epsilon, sdf.time], id: sdf.id };
+        builder.bind_pipeline_graphics(self.pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, self.pipeline.layout().clone(), 0, frame_set)
+            .push_constants(self.pipeline.layout().clone(), 0, pc)
+            .draw(3, 1, 0, 0).unwrap();
+    }
+}
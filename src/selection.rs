@@ -0,0 +1,143 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, SubpassContents },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               image::{ AttachmentImage, ImageAccess, ImageUsage, SwapchainImage, view::ImageView },
+               render_pass::{ Framebuffer, FramebufferCreateInfo, RenderPass, Subpass },
+               pipeline::{ ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint,
+                           graphics::{ input_assembly::InputAssemblyState, vertex_input::BuffersDefinition,
+                                       viewport::{ Viewport, ViewportState }, color_blend::ColorBlendState } },
+               format::{ ClearValue, Format } };
+use winit::window::Window;
+use std::sync::Arc;
+use crate::picking::Picker;
+use crate::scene::EntityId;
+
+mod seed {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/outline_seed.comp", include: ["src/shaders"] }
+}
+mod jfa {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/outline_jfa.comp", include: ["src/shaders"] }
+}
+mod composite_vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			void main() {
+				gl_Position = vec4(vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0, 0.0, 1.0);
+			}"
+    }
+}
+mod composite_fs {
+    vulkano_shaders::shader! { ty: "fragment", path: "src/shaders/outline_composite.frag", include: ["src/shaders"] }
+}
+
+/// Nearest seed pixel per pixel, -1 where there is none yet.
+const SEED_FORMAT: Format = Format::R32G32_SINT;
+
+/// Screen space outline around the selected and hovered entities, found in the picker's id
+/// image. Their pixels seed a jump flood that gives every pixel its nearest highlighted pixel,
+/// so the width costs log2 passes rather than a kernel that grows with it. Drawn straight onto
+/// the swapchain image, over the scene and under the overlay. The flood runs at the picker's
+/// resolution, which is the scene's and may be below the display's.
+pub struct SelectionOutline {
+    pub selected_color: [f32; 4],
+    pub hovered_color: [f32; 4],
+    /// Pixels outside the entity's silhouette.
+    pub width: f32,
+    pub enabled: bool,
+    dev: Arc<Device>,
+    render_pass: Arc<RenderPass>,
+    seed: Arc<ComputePipeline>,
+    jfa: Arc<ComputePipeline>,
+    composite: Arc<GraphicsPipeline>,
+    framebuffers: Vec<Arc<Framebuffer>>,
+    /// Ping-ponged by the flood, sized like the picker.
+    seeds: Vec<Arc<ImageView<AttachmentImage>>>,
+    extent: [u32; 2],
+    display: [u32; 2],
+}
+
+impl SelectionOutline {
+    pub fn new(dev: Arc<Device>, format: Format) -> Self {
+        let render_pass = vulkano::single_pass_renderpass!( dev.clone(),
+                                                            attachments: { color: { load: Load, store: Store, format: format, samples: 1,}},
+                                                            pass: { color: [color], depth_stencil: {} }).unwrap();
+        let seed = ComputePipeline::new(dev.clone(), seed::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let jfa = ComputePipeline::new(dev.clone(), jfa::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let vs = composite_vs::load(dev.clone()).unwrap();
+        let fs = composite_fs::load(dev.clone()).unwrap();
+        let composite = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .color_blend_state(ColorBlendState::new(1).blend_alpha())
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(dev.clone()).unwrap();
+        SelectionOutline { selected_color: [1.0, 0.6, 0.1, 1.0], hovered_color: [0.3, 0.7, 1.0, 0.7], width: 3.0, enabled: true,
+                           dev, render_pass, seed, jfa, composite, framebuffers: Vec::new(), seeds: Vec::new(), extent: [0, 0], display: [0, 0] }
+    }
+
+    pub fn resize(&mut self, images: &[Arc<SwapchainImage<Window>>]) {
+        self.framebuffers = images.iter().map(|image| {
+            Framebuffer::new(self.render_pass.clone(), FramebufferCreateInfo {
+                attachments: vec![ImageView::new_default(image.clone()).unwrap()], ..Default::default() }).unwrap()
+        }).collect();
+        self.display = images[0].dimensions().width_height();
+    }
+
+    /// Records the flood and the composite. After the scene, outside any render pass; the
+    /// picker's id image must be this frame's.
+    pub fn draw(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, image_num: usize, viewport: &Viewport,
+                picker: &Picker, selected: Option<EntityId>, hovered: Option<EntityId>) {
+        if !self.enabled || !picker.enabled || (selected.is_none() && hovered.is_none()) { return; }
+        if self.extent != picker.extent() {
+            self.extent = picker.extent();
+            self.seeds = (0..2).map(|_| ImageView::new_default(AttachmentImage::with_usage(self.dev.clone(), self.extent, SEED_FORMAT,
+                ImageUsage { storage: true, ..ImageUsage::none() }).unwrap()).unwrap()).collect();
+        }
+        let ids = picker.view();
+        let scale = [self.extent[0] as f32 / self.display[0] as f32, self.extent[1] as f32 / self.display[1] as f32];
+        let groups = [(self.extent[0] + 7) / 8, (self.extent[1] + 7) / 8, 1];
+
+        let set = PersistentDescriptorSet::new(self.seed.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view(0, ids.clone()),
+            WriteDescriptorSet::image_view(1, self.seeds[0].clone()),
+        ]).unwrap();
+        builder.bind_pipeline_compute(self.seed.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.seed.layout().clone(), 0, set)
+            .push_constants(self.seed.layout().clone(), 0, seed::ty::PushConstants { selected: selected.unwrap_or(0), hovered: hovered.unwrap_or(0) })
+            .dispatch(groups).unwrap();
+
+        //only seeds within the outline matter, so the flood starts at the width rather than the screen
+        let mut step = ((self.width * scale[0].max(scale[1])).ceil().max(1.0) as u32).next_power_of_two();
+        let mut current = 0;
+        builder.bind_pipeline_compute(self.jfa.clone());
+        while step >= 1 {
+            let set = PersistentDescriptorSet::new(self.jfa.layout().set_layouts().get(0).unwrap().clone(), [
+                WriteDescriptorSet::image_view(0, self.seeds[current].clone()),
+                WriteDescriptorSet::image_view(1, self.seeds[current ^ 1].clone()),
+            ]).unwrap();
+            builder.bind_descriptor_sets(PipelineBindPoint::Compute, self.jfa.layout().clone(), 0, set)
+                .push_constants(self.jfa.layout().clone(), 0, jfa::ty::PushConstants { step: step as i32 })
+                .dispatch(groups).unwrap();
+            current ^= 1;
+            step /= 2;
+        }
+
+        let set = PersistentDescriptorSet::new(self.composite.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view(0, self.seeds[current].clone()),
+            WriteDescriptorSet::image_view(1, ids),
+        ]).unwrap();
+        let pc = composite_fs::ty::PushConstants { selected_color: self.selected_color, hovered_color: self.hovered_color,
+                                                   width: self.width, selected: selected.unwrap_or(0), scale };
+        builder.begin_render_pass(self.framebuffers[image_num].clone(), SubpassContents::Inline, vec![ClearValue::None]).unwrap()
+            .set_viewport(0, [viewport.clone()])
+            .bind_pipeline_graphics(self.composite.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, self.composite.layout().clone(), 0, set)
+            .push_constants(self.composite.layout().clone(), 0, pc)
+            .draw(3, 1, 0, 0).unwrap()
+            .end_render_pass().unwrap();
+    }
+}
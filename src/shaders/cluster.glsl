@@ -0,0 +1,18 @@
+// Froxel grid shared by cluster_cull.comp and the lit shaders. Keep in step with cluster.rs.
+
+const uvec3 CLUSTER_GRID = uvec3(16, 9, 24);
+const uint CLUSTER_LIGHTS = 128; // per cluster, nearest lights first; the rest are dropped
+// each cluster is its light count followed by room for CLUSTER_LIGHTS light indices
+const uint CLUSTER_STRIDE = CLUSTER_LIGHTS + 1;
+
+// slices are spaced exponentially between near and far so froxels stay roughly cube shaped
+float slice_depth(uint slice, float near, float far) {
+	return near * pow(far / near, float(slice) / float(CLUSTER_GRID.z));
+}
+
+// cluster index, as cluster_cull.comp numbers them, of screen `uv` at `view_depth`
+uint cluster_at(vec2 uv, float view_depth, float near, float far) {
+	uvec2 tile = min(uvec2(uv * vec2(CLUSTER_GRID.xy)), CLUSTER_GRID.xy - 1u);
+	float slice = log(max(view_depth, near) / near) / log(far / near) * float(CLUSTER_GRID.z);
+	return (min(uint(slice), CLUSTER_GRID.z - 1u) * CLUSTER_GRID.y + tile.y) * CLUSTER_GRID.x + tile.x;
+}
@@ -0,0 +1,22 @@
+#version 450
+
+// object space attributes straight through to displaced.tesc; everything happens after tessellation
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec2 uv;
+layout(location = 3) in vec4 tangent;
+layout(location = 4) in vec2 lightmap_uv;
+layout(location = 0) out vec3 c_position;
+layout(location = 1) out vec3 c_normal;
+layout(location = 2) out vec2 c_uv;
+layout(location = 3) out vec4 c_tangent;
+layout(location = 4) out vec2 c_lightmap_uv;
+
+void main() {
+	c_position = position;
+	c_normal = normal;
+	c_uv = uv;
+	c_tangent = tangent;
+	c_lightmap_uv = lightmap_uv;
+}
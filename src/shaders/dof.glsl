@@ -0,0 +1,8 @@
+// shared by the depth of field passes: circle of confusion in pixels of the full res image from
+// a depth buffer value, negative in front of the focus plane
+// needs pc.proj_z and pc.lens (focus distance, pixels per unit of |z - focus| / z, max coc in pixels)
+float coc(float depth) {
+	float z = pc.proj_z.y / (depth + pc.proj_z.x);
+	float c = (z - pc.lens.x) / z * pc.lens.y;
+	return clamp(c, -pc.lens.z, pc.lens.z);
+}
@@ -0,0 +1,13 @@
+// Froxel volume layout shared by the volumetric fog passes. Slices are exponential between the
+// camera near plane and the volume's far distance, like the light clusters.
+
+const float INV_4PI = 0.07957747;
+
+float fog_depth(float w, float near, float far) { return near * pow(far / near, w); }
+float fog_slice(float depth, float near, float far) { return log(max(depth, near) / near) / log(far / near); }
+
+// henyey-greenstein, cos_theta between the light's and the view ray's direction of travel
+float fog_phase(float cos_theta, float g) {
+	float g2 = g * g;
+	return INV_4PI * (1.0 - g2) / pow(1.0 + g2 - 2.0 * g * cos_theta, 1.5);
+}
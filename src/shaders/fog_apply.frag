@@ -0,0 +1,19 @@
+#version 450
+#include "fog.glsl"
+
+layout(input_attachment_index = 0, set = 0, binding = 0) uniform subpassInput u_depth;
+layout(set = 0, binding = 1) uniform sampler3D u_fog;
+
+layout(push_constant) uniform PushConstants {
+	vec4 depth;      // x: camera near, y: camera far, z: volume far
+	vec2 inv_extent;
+} pc;
+
+layout(location = 0) out vec4 f_color;
+
+// blended as scene * a + rgb
+void main() {
+	float d = subpassLoad(u_depth).r;
+	float view_depth = pc.depth.x * pc.depth.y / (pc.depth.y - d * (pc.depth.y - pc.depth.x));
+	f_color = texture(u_fog, vec3(gl_FragCoord.xy * pc.inv_extent, fog_slice(view_depth, pc.depth.x, pc.depth.z)));
+}
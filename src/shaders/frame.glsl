@@ -0,0 +1,53 @@
+// Per frame set 0: lights, shadows and environment. Shared by everything that shades.
+
+#include "point_light.glsl"
+#include "cluster.glsl"
+
+// box_min.w is 1 for boxes; spheres keep their radius in box_max.w. params x: last prefiltered mip
+struct Probe { vec4 position_blend; vec4 box_min; vec4 box_max; vec4 params; };
+
+layout(set = 0, binding = 0) uniform Frame {
+	mat4 view_proj;
+	vec4 camera_pos;
+	vec4 camera_forward; // w > 0 for orthographic views
+	vec4 ambient;
+	vec4 sun_direction;  // w > 0 for contact shadows
+	vec4 sun_radiance;  // w > 0 if there is a sun
+	mat4 cascade_view_proj[4];
+	vec4 cascade_splits; // view depth where each cascade ends
+	vec4 cascade_texels; // world size of a texel per cascade
+	vec4 shadow_params;  // x: normal offset in texels, y: pcf radius in texels, z: atlas texel in uv, w: cascade blend band
+	vec4 local_shadow_params; // y: pcf radius in texels, z: atlas texel in uv
+	vec4 ibl_params;     // x: intensity, y: last prefiltered mip, w > 0 if there is an environment
+	vec4 fog_color;      // rgb: analytic fog color, w: extinction per meter
+	vec4 fog_height;     // x: height layer extinction at base, y: falloff, z: base height, w: start distance
+	vec4 cluster_params; // x: near, y: far, zw: 1 / target extent
+	vec4 contact_params; // x: length, 0 is off, y: thickness, z: steps
+	vec4 voxel_params;   // x: level 0 voxel size, y: levels, z: voxels per side, w: gi intensity, 0 is off
+	vec4 voxel_trace;    // x: diffuse cones, 1 or 6, y > 0: specular cone, z: max cone distance
+	vec4 voxel_origins[4]; // min corner of each clipmap level, w: its voxel size
+	uvec4 counts;        // x: point lights, y: shadow cascades, z: reflection probes
+	Probe probes[4];     // smallest first
+	mat4 shadow_tiles[64];
+	vec4 shadow_rects[64]; // atlas uv rect of each tile
+} frame;
+
+// towards the eye; orthographic views look the same way everywhere
+vec3 to_eye(vec3 world) {
+	return frame.camera_forward.w > 0.0 ? -frame.camera_forward.xyz : normalize(frame.camera_pos.xyz - world);
+}
+
+float eye_depth(vec3 world) { return dot(world - frame.camera_pos.xyz, frame.camera_forward.xyz); }
+
+layout(set = 0, binding = 1) uniform sampler2DShadow u_shadow_map; // cascade atlas, 2x2
+layout(set = 0, binding = 2) uniform sampler2DShadow u_local_shadows; // point and spot light atlas
+layout(set = 0, binding = 3) uniform samplerCube u_irradiance;
+layout(set = 0, binding = 4) uniform samplerCube u_prefiltered; // roughness over the mips
+layout(set = 0, binding = 5) uniform sampler2D u_brdf_lut;     // (n.v, roughness) -> f0 scale, bias
+layout(set = 0, binding = 6) uniform samplerCube u_probe_irradiance[4];
+layout(set = 0, binding = 7) uniform samplerCube u_probe_prefiltered[4];
+// point and spot lights nearest the camera first, binned by cluster_cull.comp into u_clusters
+layout(set = 0, binding = 8) readonly buffer Lights { PointLight points[]; } u_lights;
+layout(set = 0, binding = 9) readonly buffer Clusters { uint indices[]; } u_clusters;
+// voxel gi clipmap, radiance premultiplied by coverage in rgb and coverage in a, see voxel_gi.rs
+layout(set = 0, binding = 10) uniform sampler3D u_voxels[4];
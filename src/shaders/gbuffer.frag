@@ -0,0 +1,25 @@
+#version 450
+#include "standard.glsl"
+#include "lighting.glsl"
+#include "material.glsl"
+
+// deferred path: the standard material written out for tiled.comp to light
+layout(location = 0) out vec4 f_color;    // emissive, the resolve adds the lighting on top
+layout(location = 1) out uint f_id;
+layout(location = 2) out vec2 f_velocity;
+layout(location = 3) out vec4 g_albedo;   // rgb: albedo, a: metallic
+layout(location = 4) out vec4 g_normal;   // xyz: shading normal, w: roughness
+layout(location = 5) out vec4 g_material; // xyz: geometric normal, w: blinn-phong shininess, 0 for pbr, -bands for toon
+layout(location = 6) out vec4 g_baked;    // Surface.baked, or the toon shadow tint with w 0: toon takes no baked diffuse
+
+void main() {
+	float alpha;
+	Surface s = material_surface(alpha);
+	f_color = vec4(s.emissive, alpha);
+	f_id = v_id;
+	f_velocity = object_motion();
+	g_albedo = vec4(s.albedo, s.metallic);
+	g_normal = vec4(s.n, s.roughness);
+	g_material = vec4(s.gn, s.shading == SHADING_BLINN_PHONG ? max(s.shininess, 1e-3) : s.shading == SHADING_TOON ? -max(s.toon.w, 1.0) : 0.0);
+	g_baked = s.shading == SHADING_TOON ? vec4(s.toon.rgb, 0.0) : s.baked;
+}
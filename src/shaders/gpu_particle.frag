@@ -0,0 +1,18 @@
+#version 450
+
+layout(input_attachment_index = 0, set = 0, binding = 0) uniform subpassInput u_depth;
+layout(set = 0, binding = 1) uniform sampler2D u_texture;
+
+layout(location = 0) in vec2 v_uv;
+layout(location = 1) in vec4 v_color;
+layout(location = 2) flat in vec3 v_fade;
+layout(location = 0) out vec4 f_color;
+
+float linear_depth(float d) { return v_fade.x * v_fade.y / (v_fade.y - d * (v_fade.y - v_fade.x)); }
+
+// like the billboards: no depth test, faded out where it meets the scene
+void main() {
+	float fade = clamp((linear_depth(subpassLoad(u_depth).r) - linear_depth(gl_FragCoord.z)) / v_fade.z, 0.0, 1.0);
+	f_color = texture(u_texture, v_uv) * v_color;
+	f_color.a *= fade;
+}
@@ -0,0 +1,37 @@
+#version 450
+#include "particle.glsl"
+
+layout(set = 0, binding = 2) readonly buffer Particles { Particle particles[]; };
+layout(set = 0, binding = 3) readonly buffer Curves { vec4 curves[]; };
+
+layout(location = 0) out vec2 v_uv;
+layout(location = 1) out vec4 v_color;
+layout(location = 2) flat out vec3 v_fade;  // near, far, fade distance
+
+layout(push_constant) uniform PushConstants {
+	mat4 view_proj;
+	vec4 cam_right;
+	vec4 cam_up;
+	vec4 near_far;  // near, far, soft fade distance
+} pc;
+
+const vec2 corners[6] = vec2[](vec2(-0.5, -0.5), vec2(0.5, -0.5), vec2(0.5, 0.5),
+                               vec2(-0.5, -0.5), vec2(0.5, 0.5), vec2(-0.5, 0.5));
+
+vec4 over_life(uint base, float t) {
+	float x = clamp(t, 0.0, 1.0) * float(CURVE_SAMPLES - 1u);
+	uint i = uint(x);
+	return mix(curves[base + i], curves[base + min(i + 1u, CURVE_SAMPLES - 1u)], fract(x));
+}
+
+void main() {
+	Particle p = particles[gl_InstanceIndex];
+	float t = p.age / p.lifetime;
+	vec2 c = corners[gl_VertexIndex];
+	float size = over_life(CURVE_SAMPLES, t).x;
+	vec3 position = p.position + (pc.cam_right.xyz * c.x + pc.cam_up.xyz * c.y) * size;
+	gl_Position = pc.view_proj * vec4(position, 1.0);
+	v_uv = vec2(c.x + 0.5, 0.5 - c.y);
+	v_color = over_life(0u, t);
+	v_fade = pc.near_far.xyz;
+}
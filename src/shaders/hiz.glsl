@@ -0,0 +1,14 @@
+// Hierarchical depth: every level of the closest-depth pyramid packed into one image, level 0
+// full size at the origin, the rest stacked down a column to its right. Built by hiz.comp.
+
+// where `level` starts in the packed image and how big it is, for a level 0 of `base`
+ivec2 hiz_level(ivec2 base, int level, out ivec2 size) {
+	ivec2 offset = ivec2(0);
+	size = base;
+	for (int i = 0; i < level; i++) {
+		if (i == 0) offset = ivec2(base.x, 0);
+		else offset.y += size.y;
+		size = max(size / 2, ivec2(1));
+	}
+	return offset;
+}
@@ -0,0 +1,34 @@
+// Helpers shared by the image based lighting bake shaders.
+
+const float PI = 3.14159265359;
+
+// direction through texel uv (0..1) of a cube face, faces in vulkan order +x -x +y -y +z -z
+vec3 cube_dir(uint face, vec2 uv) {
+	vec2 p = uv * 2.0 - 1.0;
+	switch (face) {
+	case 0: return normalize(vec3(1.0, -p.y, -p.x));
+	case 1: return normalize(vec3(-1.0, -p.y, p.x));
+	case 2: return normalize(vec3(p.x, 1.0, p.y));
+	case 3: return normalize(vec3(p.x, -1.0, -p.y));
+	case 4: return normalize(vec3(p.x, -p.y, 1.0));
+	default: return normalize(vec3(-p.x, -p.y, -1.0));
+	}
+}
+
+vec2 hammersley(uint i, uint n) {
+	uint bits = bitfieldReverse(i);
+	return vec2(float(i) / float(n), float(bits) * 2.3283064365386963e-10);
+}
+
+// half vector around n, distributed like ggx with the given perceptual roughness
+vec3 importance_sample_ggx(vec2 xi, vec3 n, float roughness) {
+	float a = roughness * roughness;
+	float phi = 2.0 * PI * xi.x;
+	float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+	float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+	vec3 h = vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+	vec3 up = abs(n.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+	vec3 t = normalize(cross(up, n));
+	vec3 b = cross(n, t);
+	return normalize(t * h.x + b * h.y + n * h.z);
+}
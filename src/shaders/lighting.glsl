@@ -0,0 +1,234 @@
+// Shading shared by the forward and deferred paths. Needs frame.glsl. Fragment shaders find
+// their point lights through the clusters; tiled.comp defines TILED_LIGHTS and its own list.
+
+#include "shadows.glsl"
+#include "voxel.glsl"
+
+#define SHADING_PBR 0
+#define SHADING_BLINN_PHONG 1
+#define SHADING_TOON 2
+
+const float PI = 3.14159265359;
+
+// everything shading needs about a point, filled from the material in the forward pass or
+// from the g-buffer in the deferred resolve
+struct Surface {
+	vec3 albedo;
+	float metallic;
+	float roughness;
+	vec3 n;
+	vec3 v;
+	vec3 world;
+	vec3 gn;          // geometric normal, for shadow offsets
+	float view_depth; // distance along the view axis, picks the shadow cascade
+	uint shading;
+	float shininess;  // blinn-phong only
+	vec3 emissive;
+	vec4 baked;       // rgb: irradiance / pi replacing the ambient diffuse if w > 0; w > 1.5: lightmap, direct light included
+	vec4 toon;        // rgb: shadow tint, w: bands. Toon only
+	float occlusion;  // ambient only, from ssao in the deferred resolve
+	vec4 reflection;  // rgb premultiplied by a, how much it replaces the probes' specular. From ssr
+};
+
+float d_ggx(float ndh, float a) {
+	float a2 = a * a;
+	float d = ndh * ndh * (a2 - 1.0) + 1.0;
+	return a2 / (PI * d * d);
+}
+
+// height correlated smith, already divided by 4 ndl ndv
+float v_smith_ggx(float ndv, float ndl, float a) {
+	float a2 = a * a;
+	float gv = ndl * sqrt(ndv * ndv * (1.0 - a2) + a2);
+	float gl = ndv * sqrt(ndl * ndl * (1.0 - a2) + a2);
+	return 0.5 / max(gv + gl, 1e-5);
+}
+
+vec3 f_schlick(float vdh, vec3 f0) { return f0 + (1.0 - f0) * pow(1.0 - vdh, 5.0); }
+
+vec3 cook_torrance(Surface s, vec3 l, vec3 radiance) {
+	float ndl = max(dot(s.n, l), 0.0);
+	if (ndl <= 0.0) return vec3(0.0);
+	vec3 h = normalize(l + s.v);
+	float ndv = max(dot(s.n, s.v), 1e-4);
+	float ndh = max(dot(s.n, h), 0.0);
+	float vdh = max(dot(s.v, h), 0.0);
+	float a = s.roughness * s.roughness;
+	vec3 f0 = mix(vec3(0.04), s.albedo, s.metallic);
+	vec3 f = f_schlick(vdh, f0);
+	vec3 specular = d_ggx(ndh, a) * v_smith_ggx(ndv, ndl, a) * f;
+	vec3 diffuse = (1.0 - f) * (1.0 - s.metallic) * s.albedo / PI;
+	return (diffuse + specular) * radiance * ndl;
+}
+
+vec3 blinn_phong(Surface s, vec3 l, vec3 radiance) {
+	float ndl = max(dot(s.n, l), 0.0);
+	vec3 h = normalize(l + s.v);
+	float spec = ndl > 0.0 ? pow(max(dot(s.n, h), 0.0), s.shininess) : 0.0;
+	return (s.albedo * ndl + vec3(spec)) * radiance;
+}
+
+// diffuse in flat bands and a hard edged highlight that shrinks with roughness
+vec3 toon(Surface s, vec3 l, vec3 radiance) {
+	float ndl = max(dot(s.n, l), 0.0);
+	float bands = max(s.toon.w, 1.0);
+	float diffuse = ceil(ndl * bands) / bands;
+	vec3 h = normalize(l + s.v);
+	float spec = ndl > 0.0 ? step(1.0 - 0.1 * s.roughness, dot(s.n, h)) * (1.0 - s.roughness) : 0.0;
+	return (s.albedo * diffuse + vec3(spec)) * radiance;
+}
+
+vec3 shade(Surface s, vec3 l, vec3 radiance) {
+	if (s.shading == SHADING_TOON) return toon(s, l, radiance);
+	return s.shading == SHADING_BLINN_PHONG ? blinn_phong(s, l, radiance) : cook_torrance(s, l, radiance);
+}
+
+// 1 well inside the probe volume, fading to 0 over the blend distance at its edge
+float probe_weight(Probe p, vec3 world) {
+	float inside;
+	if (p.box_min.w > 0.5) {
+		vec3 d = min(world - p.box_min.xyz, p.box_max.xyz - world);
+		inside = min(min(d.x, d.y), d.z);
+	} else {
+		inside = p.box_max.w - distance(world, p.position_blend.xyz);
+	}
+	return clamp(inside / max(p.position_blend.w, 1e-4), 0.0, 1.0);
+}
+
+// parallax correction: where the reflection ray hits the proxy shape, seen from the capture point
+vec3 probe_dir(Probe p, vec3 world, vec3 r) {
+	vec3 hit;
+	if (p.box_min.w > 0.5) {
+		vec3 t = max((p.box_max.xyz - world) / r, (p.box_min.xyz - world) / r);
+		hit = world + r * min(min(t.x, t.y), t.z);
+	} else {
+		vec3 oc = world - p.position_blend.xyz;
+		float b = dot(oc, r);
+		float c = dot(oc, oc) - p.box_max.w * p.box_max.w;
+		hit = world + r * (-b + sqrt(max(b * b - c, 0.0)));
+	}
+	return hit - p.position_blend.xyz;
+}
+
+// each probe fills what the smaller ones before it left uncovered
+void add_probe(int i, samplerCube irr, samplerCube pre, Surface s, vec3 r, inout vec3 irradiance, inout vec3 prefiltered, inout float covered) {
+	if (i >= int(frame.counts.z)) return;
+	Probe p = frame.probes[i];
+	float w = probe_weight(p, s.world) * (1.0 - covered);
+	if (w <= 0.0) return;
+	irradiance += texture(irr, s.n).rgb * w;
+	prefiltered += textureLod(pre, probe_dir(p, s.world, r), s.roughness * p.params.x).rgb * w;
+	covered += w;
+}
+
+// split sum image based lighting from the reflection probes, then the scene environment, then
+// the flat ambient for whatever neither covers. Diffuse comes from `baked` instead when set
+vec3 ambient(Surface s) {
+	vec3 r = reflect(-s.v, s.n);
+	vec3 irradiance = vec3(0.0), prefiltered = vec3(0.0);
+	float covered = 0.0;
+	//constant indices, no dynamic indexing feature needed
+	add_probe(0, u_probe_irradiance[0], u_probe_prefiltered[0], s, r, irradiance, prefiltered, covered);
+	add_probe(1, u_probe_irradiance[1], u_probe_prefiltered[1], s, r, irradiance, prefiltered, covered);
+	add_probe(2, u_probe_irradiance[2], u_probe_prefiltered[2], s, r, irradiance, prefiltered, covered);
+	add_probe(3, u_probe_irradiance[3], u_probe_prefiltered[3], s, r, irradiance, prefiltered, covered);
+	float rest = 1.0 - covered;
+	if (frame.ibl_params.w > 0.0) {
+		irradiance += texture(u_irradiance, s.n).rgb * frame.ibl_params.x * rest;
+		prefiltered += textureLod(u_prefiltered, r, s.roughness * frame.ibl_params.y).rgb * frame.ibl_params.x * rest;
+	} else if (covered <= 0.0 && s.baked.w <= 0.0 && frame.voxel_params.w <= 0.0) {
+		return frame.ambient.rgb * s.albedo;
+	} else {
+		irradiance += frame.ambient.rgb * rest;
+		prefiltered += frame.ambient.rgb * rest;
+	}
+	//voxel gi bounces what it can see and lets the probes and sky through where its cones escape
+	if (frame.voxel_params.w > 0.0) {
+		vec3 origin = s.world + s.gn * frame.voxel_params.x * 1.5;
+		vec4 gi = voxel_diffuse(origin, s.n);
+		irradiance = gi.rgb * frame.voxel_params.w + irradiance * (1.0 - gi.a);
+		if (frame.voxel_trace.y > 0.0) {
+			gi = cone_trace(origin, r, clamp(s.roughness, 0.03, 1.0));
+			prefiltered = gi.rgb * frame.voxel_params.w + prefiltered * (1.0 - gi.a);
+		}
+	}
+	prefiltered = prefiltered * (1.0 - s.reflection.a) + s.reflection.rgb;
+	if (s.baked.w > 0.0) irradiance = s.baked.rgb;
+	if (s.shading == SHADING_BLINN_PHONG) return irradiance * s.albedo;
+	//the shadow tint is the color of the unlit side, on top of whatever ambient there is
+	if (s.shading == SHADING_TOON) return (irradiance + s.toon.rgb) * s.albedo;
+	float ndv = max(dot(s.n, s.v), 1e-4);
+	vec3 f0 = mix(vec3(0.04), s.albedo, s.metallic);
+	vec3 f = f0 + (max(vec3(1.0 - s.roughness), f0) - f0) * pow(1.0 - ndv, 5.0); //schlick with roughness
+	vec3 kd = (1.0 - f) * (1.0 - s.metallic);
+	vec2 brdf = texture(u_brdf_lut, vec2(ndv, s.roughness)).rg;
+	return kd * irradiance * s.albedo + prefiltered * (f0 * brdf.x + brdf.y);
+}
+
+// 1 unless the includer defines CONTACT_SHADOWS and its contact_shadow(world, to_light)
+float contact(Surface s, vec3 l, float enabled) {
+#ifdef CONTACT_SHADOWS
+	if (enabled > 0.0 && frame.contact_params.x > 0.0) return contact_shadow(s.world + s.gn * 0.01, l);
+#endif
+	return 1.0;
+}
+
+// the shadow map's, unless the includer defines RT_SHADOWS and its rt_shadow() traced one
+float sun_visibility(Surface s) {
+#ifdef RT_SHADOWS
+	float traced = rt_shadow();
+	if (traced >= 0.0) return traced;
+#endif
+	return sun_shadow(s.world, s.gn, s.view_depth);
+}
+
+vec3 point_light(Surface s, PointLight light) {
+	vec3 d = light.position_range.xyz - s.world;
+	float dist = length(d);
+	float range = light.position_range.w;
+	float window = pow(clamp(1.0 - pow(dist / range, 4.0), 0.0, 1.0), 2.0);
+	float cone = smoothstep(light.params.y, light.params.x, dot(-d / dist, light.direction.xyz));
+	if (window * cone <= 0.0) return vec3(0.0);
+	float shadow = local_shadow(light, s.world, s.gn) * contact(s, d / dist, light.radiance.w);
+	return shade(s, d / dist, light.radiance.rgb * window * cone * shadow / (dist * dist + 1.0));
+}
+
+#ifndef TILED_LIGHTS
+// how much of the surface at `world` makes it through the analytic fog: distance fog plus the
+// height layer's density integrated along the ray
+float fog_transmittance(vec3 world) {
+	vec3 d = world - frame.camera_pos.xyz;
+	float dist = max(length(d) - frame.fog_height.w, 0.0);
+	float falloff = frame.fog_height.y;
+	float at_camera = frame.fog_height.x * exp(-falloff * (frame.camera_pos.y - frame.fog_height.z));
+	float rise = falloff * d.y;
+	float height = at_camera * dist * (abs(rise) > 1e-4 ? (1.0 - exp(-rise)) / rise : 1.0);
+	return exp(-(frame.fog_color.w * dist + height));
+}
+
+vec3 apply_fog(vec3 color, vec3 world) { return mix(frame.fog_color.rgb, color, fog_transmittance(world)); }
+
+// froxel this fragment falls in
+uint cluster_index(float view_depth) {
+	return cluster_at(gl_FragCoord.xy * frame.cluster_params.zw, view_depth, frame.cluster_params.x, frame.cluster_params.y);
+}
+#endif
+
+// ambient, emissive, the sun and the point lights culled for this pixel's cluster or tile
+vec3 light_surface(Surface s) {
+	vec3 color = ambient(s) * s.occlusion + s.emissive;
+	if (s.baked.w > 1.5) return color; //direct light is baked in
+	if (frame.sun_radiance.w > 0.0)
+		color += shade(s, -frame.sun_direction.xyz, frame.sun_radiance.rgb * sun_visibility(s) * contact(s, -frame.sun_direction.xyz, frame.sun_direction.w));
+#ifdef TILED_LIGHTS
+	uint count = min(tile_light_count, TILE_LIGHTS);
+	for (uint j = 0; j < count; j++)
+		color += point_light(s, u_lights.points[tile_lights[j]]);
+#else
+	uint base = cluster_index(s.view_depth) * CLUSTER_STRIDE;
+	uint count = u_clusters.indices[base];
+	for (uint j = 0; j < count; j++)
+		color += point_light(s, u_lights.points[u_clusters.indices[base + 1 + j]]);
+#endif
+	return color;
+}
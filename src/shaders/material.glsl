@@ -0,0 +1,74 @@
+// Surface from the standard material, for fragment shaders behind standard.vert. Needs
+// standard.glsl and lighting.glsl.
+
+layout(location = 0) in vec3 v_world;
+layout(location = 1) in vec3 v_normal;
+layout(location = 2) in vec2 v_uv;
+layout(location = 3) in vec4 v_tangent;
+layout(location = 4) flat in uint v_id;
+layout(location = 5) in vec2 v_lightmap_uv;
+layout(location = 6) in vec4 v_clip;
+layout(location = 7) in vec4 v_previous_clip; // last frame's model, this frame's camera
+
+// how far the object itself moved on screen since last frame, in uv. The camera's share comes
+// from depth in motion_blur.comp, so everything that doesn't write this still blurs with it
+vec2 object_motion() { return (v_clip.xy / v_clip.w - v_previous_clip.xy / v_previous_clip.w) * 0.5; }
+
+// irradiance / pi from the object's light probe sh, the same units as the irradiance cubemaps.
+// cosine lobe convolution factors pi, 2pi/3, pi/4 per band
+vec3 sh_irradiance(vec3 n) {
+	const float a0 = 1.0, a1 = 2.0 / 3.0, a2 = 0.25;
+	vec3 e = object.sh[0].rgb * 0.282095 * a0;
+	e += (object.sh[1].rgb * n.y + object.sh[2].rgb * n.z + object.sh[3].rgb * n.x) * 0.488603 * a1;
+	e += (object.sh[4].rgb * n.x * n.y + object.sh[5].rgb * n.y * n.z + object.sh[7].rgb * n.x * n.z) * 1.092548 * a2;
+	e += object.sh[6].rgb * 0.315392 * (3.0 * n.z * n.z - 1.0) * a2;
+	e += object.sh[8].rgb * 0.546274 * (n.x * n.x - n.y * n.y) * a2;
+	return max(e, vec3(0.0));
+}
+
+vec3 surface_normal() {
+	vec3 n = normalize(v_normal);
+	if ((pc.flags & FLAG_NORMAL_MAP) != 0u) {
+		vec3 t = normalize(v_tangent.xyz - n * dot(n, v_tangent.xyz));
+		vec3 b = cross(n, t) * v_tangent.w;
+		vec3 m = texture(u_normal, v_uv).xyz * 2.0 - 1.0;
+		m.xy *= pc.params.w;
+		n = normalize(mat3(t, b, n) * m);
+	}
+	return gl_FrontFacing ? n : -n;
+}
+
+// terrain layers blended by the control map, see material.rs Splat
+vec4 splat_color() {
+	vec4 w = texture(u_splat_control, v_uv);
+	w /= max(dot(w, vec4(1.0)), 1e-4);
+	vec2 uv = v_uv * style.splat.x;
+	return texture(u_splat_layers[0], uv) * w.r + texture(u_splat_layers[1], uv) * w.g
+	     + texture(u_splat_layers[2], uv) * w.b + texture(u_splat_layers[3], uv) * w.a;
+}
+
+// alpha from the base color comes back separately
+Surface material_surface(out float alpha) {
+	vec4 base = pc.base_color * ((pc.flags & FLAG_SPLAT) != 0u ? splat_color() : texture(u_base_color, v_uv));
+	vec4 mr = texture(u_metallic_roughness, v_uv);
+	alpha = base.a;
+	Surface s;
+	s.albedo = base.rgb;
+	s.metallic = clamp(pc.params.x * mr.b, 0.0, 1.0);
+	s.roughness = clamp(pc.params.y * mr.g, 0.045, 1.0);
+	s.n = surface_normal();
+	s.v = to_eye(v_world);
+	s.world = v_world;
+	s.gn = normalize(v_normal) * (gl_FrontFacing ? 1.0 : -1.0);
+	s.view_depth = eye_depth(v_world);
+	s.shading = pc.shading;
+	s.shininess = pc.params.z;
+	s.emissive = pc.emissive.rgb;
+	s.toon = style.toon;
+	s.occlusion = 1.0;
+	s.reflection = vec4(0.0);
+	s.baked = vec4(0.0);
+	if ((pc.flags & FLAG_LIGHTMAP) != 0u) s.baked = vec4(texture(u_lightmap, v_lightmap_uv).rgb, 2.0);
+	else if (object.sh[0].w > 0.0) s.baked = vec4(sh_irradiance(s.n), 1.0);
+	return s;
+}
@@ -0,0 +1,27 @@
+// Morph target buffers, by default in the object set after the skin's, see morph.rs. Meshes
+// without targets get placeholders. Vertex shaders only.
+
+#ifndef MORPH_SET
+#define MORPH_SET 2
+#define MORPH_BINDING 4
+#endif
+
+struct MorphVertex { vec4 position; vec4 normal; };
+
+// every target's deltas for every vertex, target after target
+layout(set = MORPH_SET, binding = MORPH_BINDING) readonly buffer MorphDeltas { MorphVertex morph_deltas[]; };
+// this frame's weights, then last frame's
+layout(set = MORPH_SET, binding = MORPH_BINDING + 1) readonly buffer MorphWeights { float morph_weights[]; };
+
+// adds the weighted deltas of the vertex being shaded; previous takes last frame's weights
+void morph(inout vec3 position, inout vec3 previous, inout vec3 normal) {
+	uint targets = uint(morph_weights.length()) / 2u;
+	if (targets == 0u) return;
+	uint vertices = uint(morph_deltas.length()) / targets;
+	for (uint t = 0u; t < targets; t++) {
+		MorphVertex d = morph_deltas[t * vertices + uint(gl_VertexIndex)];
+		position += morph_weights[t] * d.position.xyz;
+		previous += morph_weights[targets + t] * d.position.xyz;
+		normal += morph_weights[t] * d.normal.xyz;
+	}
+}
@@ -0,0 +1,26 @@
+#version 450
+
+layout(set = 0, binding = 0, rg32i) uniform readonly iimage2D u_seeds;
+layout(set = 0, binding = 1, r32ui) uniform readonly uimage2D u_ids;
+
+layout(push_constant) uniform PushConstants {
+	vec4 selected_color;
+	vec4 hovered_color;
+	float width;
+	uint selected;
+	vec2 scale; // id pixels per display pixel
+} pc;
+
+layout(location = 0) out vec4 f_color;
+
+// outside the entity only, within `width` display pixels of its nearest pixel, with a soft last pixel
+void main() {
+	ivec2 p = ivec2(gl_FragCoord.xy * pc.scale);
+	ivec2 seed = imageLoad(u_seeds, p).xy;
+	if (seed.x < 0) discard;
+	uint id = imageLoad(u_ids, seed).r;
+	if (imageLoad(u_ids, p).r == id) discard;
+	float coverage = clamp(pc.width + 0.5 - distance(gl_FragCoord.xy, (vec2(seed) + 0.5) / pc.scale), 0.0, 1.0);
+	vec4 color = id == pc.selected ? pc.selected_color : pc.hovered_color;
+	f_color = vec4(color.rgb, color.a * coverage);
+}
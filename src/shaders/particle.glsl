@@ -0,0 +1,11 @@
+// one gpu particle, as gpu_particles::GpuParticle
+struct Particle {
+	vec3 position;
+	float age;
+	vec3 velocity;
+	float lifetime;
+};
+
+// samples per baked curve, gpu_particles::CURVE_SAMPLES. Color over life comes first, then size
+// and speed over life in x and y
+const uint CURVE_SAMPLES = 64u;
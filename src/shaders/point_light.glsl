@@ -0,0 +1,3 @@
+// spots are point lights with a cone; params x: cos inner, y: cos outer, z: first shadow tile or -1,
+// w: normal offset in texels. radiance.w > 0 for contact shadows
+struct PointLight { vec4 position_range; vec4 radiance; vec4 direction; vec4 params; };
@@ -0,0 +1,75 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D u_color; // linear filtered, clamped
+
+layout(push_constant) uniform PushConstants {
+	vec2 inv_extent;  // of the swapchain image
+	vec2 source_size; // of u_color
+	uint encoding;    // see hdr.rs DisplayEncoding
+	float paper_white; // nits
+} pc;
+
+const uint SRGB = 0u, SRGB_IN_SHADER = 1u, HDR10 = 2u, SCRGB = 3u;
+
+layout(location = 0) out vec4 f_color;
+
+// 4x4 catmull-rom in 9 bilinear taps: sharper than bilinear when stretching a smaller render
+vec3 catmull_rom(vec2 uv) {
+	vec2 pos = uv * pc.source_size;
+	vec2 tc1 = floor(pos - 0.5) + 0.5;
+	vec2 f = pos - tc1;
+	vec2 w0 = f * (-0.5 + f * (1.0 - 0.5 * f));
+	vec2 w1 = 1.0 + f * f * (-2.5 + 1.5 * f);
+	vec2 w2 = f * (0.5 + f * (2.0 - 1.5 * f));
+	vec2 w3 = f * f * (-0.5 + 0.5 * f);
+	vec2 w12 = w1 + w2;
+	vec2 tc0 = (tc1 - 1.0) / pc.source_size;
+	vec2 tc3 = (tc1 + 2.0) / pc.source_size;
+	vec2 tc12 = (tc1 + w2 / w12) / pc.source_size;
+	vec3 c = texture(u_color, vec2(tc0.x, tc0.y)).rgb * w0.x * w0.y
+	       + texture(u_color, vec2(tc12.x, tc0.y)).rgb * w12.x * w0.y
+	       + texture(u_color, vec2(tc3.x, tc0.y)).rgb * w3.x * w0.y
+	       + texture(u_color, vec2(tc0.x, tc12.y)).rgb * w0.x * w12.y
+	       + texture(u_color, vec2(tc12.x, tc12.y)).rgb * w12.x * w12.y
+	       + texture(u_color, vec2(tc3.x, tc12.y)).rgb * w3.x * w12.y
+	       + texture(u_color, vec2(tc0.x, tc3.y)).rgb * w0.x * w3.y
+	       + texture(u_color, vec2(tc12.x, tc3.y)).rgb * w12.x * w3.y
+	       + texture(u_color, vec2(tc3.x, tc3.y)).rgb * w3.x * w3.y;
+	return max(c, vec3(0.0));
+}
+
+// box over the pixel's footprint when supersampling, so uneven ratios don't alias
+vec3 box(vec2 uv, vec2 ratio) {
+	ivec2 n = ivec2(min(ceil(ratio), vec2(4.0)));
+	vec2 footprint = ratio / pc.source_size;
+	vec3 c = vec3(0.0);
+	for (int y = 0; y < n.y; y++)
+		for (int x = 0; x < n.x; x++)
+			c += texture(u_color, uv + ((vec2(x, y) + 0.5) / vec2(n) - 0.5) * footprint).rgb;
+	return c / float(n.x * n.y);
+}
+
+// SMPTE ST 2084, from absolute nits
+vec3 pq(vec3 nits) {
+	const float m1 = 0.1593017578125, m2 = 78.84375, c1 = 0.8359375, c2 = 18.8515625, c3 = 18.6875;
+	vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(m1));
+	return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+vec3 encode(vec3 c) {
+	const mat3 rec709_to_rec2020 = mat3(0.6274, 0.0691, 0.0164, 0.3293, 0.9195, 0.0880, 0.0433, 0.0114, 0.8956);
+	if (pc.encoding == HDR10) return pq(rec709_to_rec2020 * c * pc.paper_white);
+	if (pc.encoding == SCRGB) return c * pc.paper_white / 80.0;
+	if (pc.encoding == SRGB_IN_SHADER) { c = clamp(c, 0.0, 1.0); return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c)); }
+	return c;
+}
+
+void main() {
+	vec2 uv = gl_FragCoord.xy * pc.inv_extent;
+	vec2 ratio = pc.source_size * pc.inv_extent; // source texels per display pixel
+	vec3 c;
+	if (ratio == vec2(1.0)) c = texture(u_color, uv).rgb;
+	else if (ratio.x > 1.0 || ratio.y > 1.0) c = box(uv, ratio);
+	else c = catmull_rom(uv);
+	f_color = vec4(encode(c), 1.0);
+}
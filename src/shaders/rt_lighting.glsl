@@ -0,0 +1,26 @@
+// shared by the rt_lighting shaders; every stage gets the same push constants
+layout(push_constant) uniform PushConstants {
+	mat4 inv_view_proj;
+	vec4 eye;           // w: max distance of a reflected ray
+	vec4 sun_direction; // the way the light travels, w: 1 with a sun
+	vec4 sun_radiance;  // w: max roughness reflected, negative leaving reflections to ssr
+	vec4 ambient;       // w: 1 tracing shadows, 0 leaving them to the shadow map
+} pc;
+
+// miss shaders in the order RtLightingPass makes the pipeline with them
+const uint MISS_REFLECTION = 0;
+const uint MISS_SHADOW = 1;
+#define PAYLOAD_COLOR 0
+#define PAYLOAD_VISIBLE 1
+
+// meters off the surface rays start, against hitting it again
+const float NORMAL_OFFSET = 0.02;
+
+// 1 when nothing is between `origin` and the sun; the includer declares u_tlas and p_visible
+float sun_visible(vec3 origin) {
+	if (pc.sun_direction.w <= 0.0) return 1.0;
+	p_visible = 0.0;
+	traceRayEXT(u_tlas, gl_RayFlagsOpaqueEXT | gl_RayFlagsTerminateOnFirstHitEXT | gl_RayFlagsSkipClosestHitShaderEXT, 0xff, 0, 0, MISS_SHADOW,
+	            origin, 0.0, -pc.sun_direction.xyz, 1e4, PAYLOAD_VISIBLE);
+	return p_visible;
+}
@@ -0,0 +1,81 @@
+#version 450
+#include "frame.glsl"
+#include "lighting.glsl"
+#include "sdf_scene.glsl"
+
+// Raymarches the field in sdf_scene.glsl from every pixel and writes the hit's depth, so it
+// sorts against rasterized geometry in the depth test. DEFERRED writes the g-buffer instead of
+// shading.
+
+layout(push_constant) uniform PushConstants {
+	mat4 inv_view_proj;
+	vec4 march; // x: max steps, y: max distance, z: hit epsilon, w: time
+	uint id;
+} pc;
+
+layout(location = 0) out vec4 f_color;
+layout(location = 1) out uint f_id;
+layout(location = 2) out vec2 f_velocity; // the field doesn't move as an object, only with the camera
+#ifdef DEFERRED
+layout(location = 3) out vec4 g_albedo;
+layout(location = 4) out vec4 g_normal;
+layout(location = 5) out vec4 g_material;
+layout(location = 6) out vec4 g_baked;
+#endif
+
+// central differences on a tetrahedron, four taps instead of six
+vec3 scene_normal(vec3 p, float e) {
+	const vec2 k = vec2(1.0, -1.0);
+	return normalize(k.xyy * scene_distance(p + k.xyy * e, pc.march.w) + k.yyx * scene_distance(p + k.yyx * e, pc.march.w) +
+	                 k.yxy * scene_distance(p + k.yxy * e, pc.march.w) + k.xxx * scene_distance(p + k.xxx * e, pc.march.w));
+}
+
+void main() {
+	vec2 ndc = gl_FragCoord.xy * frame.cluster_params.zw * 2.0 - 1.0;
+	vec4 far = pc.inv_view_proj * vec4(ndc, 1.0, 1.0);
+	vec3 origin = frame.camera_pos.xyz;
+	vec3 dir = normalize(far.xyz / far.w - origin);
+
+	float t = frame.cluster_params.x;
+	bool hit = false;
+	for (int i = 0; i < int(pc.march.x) && t < pc.march.y; i++) {
+		float d = scene_distance(origin + dir * t, pc.march.w);
+		//the epsilon grows with distance so far surfaces don't eat the step budget
+		if (d < pc.march.z * t) { hit = true; break; }
+		t += d;
+	}
+	if (!hit) discard;
+
+	vec3 p = origin + dir * t;
+	vec4 clip = frame.view_proj * vec4(p, 1.0);
+	gl_FragDepth = clip.z / clip.w;
+
+	Surface s;
+	s.albedo = vec3(0.8);
+	s.metallic = 0.0;
+	s.roughness = 0.5;
+	s.n = scene_normal(p, max(pc.march.z * t, 1e-4));
+	s.v = -dir;
+	s.world = p;
+	s.gn = s.n;
+	s.view_depth = clip.w;
+	s.shading = SHADING_PBR;
+	s.shininess = 0.0;
+	s.emissive = vec3(0.0);
+	s.baked = vec4(0.0);
+	s.toon = vec4(0.0);
+	s.occlusion = 1.0;
+	s.reflection = vec4(0.0);
+	scene_material(p, pc.march.w, s);
+	f_id = pc.id;
+	f_velocity = vec2(0.0);
+#ifdef DEFERRED
+	f_color = vec4(s.emissive, 1.0);
+	g_albedo = vec4(s.albedo, s.metallic);
+	g_normal = vec4(s.n, s.roughness);
+	g_material = vec4(s.gn, s.shading == SHADING_BLINN_PHONG ? max(s.shininess, 1e-3) : s.shading == SHADING_TOON ? -max(s.toon.w, 1.0) : 0.0);
+	g_baked = s.shading == SHADING_TOON ? vec4(s.toon.rgb, 0.0) : s.baked;
+#else
+	f_color = vec4(apply_fog(light_surface(s), s.world), 1.0);
+#endif
+}
@@ -0,0 +1,27 @@
+// The distance field sdf.frag raymarches. Replace these two with your own shapes; they see the
+// frame uniforms, and `time` is SdfScene::time.
+//   float scene_distance(vec3 p, float time): signed distance to the nearest surface, in meters
+//   void scene_material(vec3 p, float time, inout Surface s): albedo, roughness and so on at a hit
+
+float sd_sphere(vec3 p, float r) { return length(p) - r; }
+
+float sd_torus(vec3 p, vec2 t) { return length(vec2(length(p.xz) - t.x, p.y)) - t.y; }
+
+float sd_smooth_union(float a, float b, float k) {
+	float h = clamp(0.5 + 0.5 * (b - a) / k, 0.0, 1.0);
+	return mix(b, a, h) - k * h * (1.0 - h);
+}
+
+// a sphere melting in and out of a ring
+float scene_distance(vec3 p, float time) {
+	vec3 c = p - vec3(3.0, 1.0, 0.0);
+	float ring = sd_torus(c, vec2(0.8, 0.2));
+	float blob = sd_sphere(c - vec3(0.0, sin(time) * 0.8, 0.0), 0.4);
+	return sd_smooth_union(ring, blob, 0.3);
+}
+
+void scene_material(vec3 p, float time, inout Surface s) {
+	s.albedo = vec3(0.9, 0.4, 0.2);
+	s.metallic = 0.0;
+	s.roughness = 0.35;
+}
@@ -0,0 +1,69 @@
+// Sun cascade and local light shadow lookups. Needs frame.glsl.
+
+// 3x3 pcf over the compare sampler, 1 where lit. Samples are clamped to the cascade's quadrant.
+float cascade_shadow(int i, vec3 world, vec3 n) {
+	vec4 p = frame.cascade_view_proj[i] * vec4(world + n * frame.shadow_params.x * frame.cascade_texels[i], 1.0);
+	vec3 c = p.xyz / p.w;
+	vec2 uv = c.xy * 0.5 + 0.5;
+	if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0))) || c.z > 1.0) return 1.0;
+	vec2 offset = vec2(i & 1, i >> 1) * 0.5;
+	float texel = frame.shadow_params.z;
+	vec2 lo = offset + texel * 0.5, hi = offset + 0.5 - texel * 0.5;
+	vec2 center = offset + uv * 0.5;
+	float depth = c.z;
+	float sum = 0.0;
+	for (int y = -1; y <= 1; y++)
+		for (int x = -1; x <= 1; x++)
+			sum += texture(u_shadow_map, vec3(clamp(center + vec2(x, y) * frame.shadow_params.y * texel, lo, hi), depth));
+	return sum / 9.0;
+}
+
+// picks the cascade by view depth and fades into the next one near its far end
+float sun_shadow(vec3 world, vec3 n, float depth) {
+	int count = int(frame.counts.y);
+	for (int i = 0; i < count; i++) {
+		float far = frame.cascade_splits[i];
+		if (depth > far) continue;
+		float s = cascade_shadow(i, world, n);
+		float near = i == 0 ? 0.0 : frame.cascade_splits[i - 1];
+		float band = (far - near) * frame.shadow_params.w;
+		if (band > 0.0 && i + 1 < count && far - depth < band)
+			s = mix(cascade_shadow(i + 1, world, n), s, (far - depth) / band);
+		return s;
+	}
+	return 1.0;
+}
+
+// offset is the normal offset already scaled to world units
+float tile_shadow(int tile, vec3 world, vec3 offset) {
+	vec4 p = frame.shadow_tiles[tile] * vec4(world + offset, 1.0);
+	vec3 c = p.xyz / p.w;
+	vec2 uv = c.xy * 0.5 + 0.5;
+	if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0))) || c.z < 0.0 || c.z > 1.0) return 1.0;
+	vec4 rect = frame.shadow_rects[tile];
+	float texel = frame.local_shadow_params.z;
+	vec2 lo = rect.xy + texel * 0.5, hi = rect.xy + rect.zw - texel * 0.5;
+	vec2 center = rect.xy + uv * rect.zw;
+	float depth = c.z;
+	float sum = 0.0;
+	for (int y = -1; y <= 1; y++)
+		for (int x = -1; x <= 1; x++)
+			sum += texture(u_local_shadows, vec3(clamp(center + vec2(x, y) * frame.local_shadow_params.y * texel, lo, hi), depth));
+	return sum / 9.0;
+}
+
+// spots use their one tile, point lights pick the cube face by major axis (+x -x +y -y +z -z)
+float local_shadow(PointLight light, vec3 world, vec3 n) {
+	int first = int(light.params.z);
+	if (first < 0) return 1.0;
+	vec3 d = world - light.position_range.xyz;
+	//texel footprint grows with distance: 2 * dist * tan(half fov) / tile resolution
+	bool spot = light.params.y > -1.5;
+	float tan_half = spot ? sqrt(1.0 - light.params.y * light.params.y) / light.params.y : 1.0;
+	float resolution = frame.shadow_rects[first].z / frame.local_shadow_params.z;
+	vec3 offset = n * light.params.w * 2.0 * length(d) * tan_half / resolution;
+	if (spot) return tile_shadow(first, world, offset);
+	vec3 a = abs(d);
+	int face = a.x >= a.y && a.x >= a.z ? (d.x > 0.0 ? 0 : 1) : a.y >= a.z ? (d.y > 0.0 ? 2 : 3) : (d.z > 0.0 ? 4 : 5);
+	return tile_shadow(first + face, world, offset);
+}
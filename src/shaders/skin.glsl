@@ -0,0 +1,22 @@
+// Skinning buffers, by default in the object set, see skin.rs. Meshes without a skin get
+// placeholders. Vertex shaders only.
+
+#ifndef SKIN_SET
+#define SKIN_SET 2
+#define SKIN_BINDING 2
+#endif
+
+struct SkinVertex { uvec4 joints; vec4 weights; };
+
+layout(set = SKIN_SET, binding = SKIN_BINDING) readonly buffer SkinWeights { SkinVertex skin_vertices[]; };
+// this frame's palette, then last frame's
+layout(set = SKIN_SET, binding = SKIN_BINDING + 1) readonly buffer Bones { mat4 bones[]; };
+
+// weighted palette for the vertex being shaded; previous picks last frame's. Meshes are drawn
+// unindexed, so the vertex index is the position in the weights
+mat4 skin_matrix(bool previous) {
+	SkinVertex v = skin_vertices[gl_VertexIndex];
+	uint offset = previous ? uint(bones.length()) / 2u : 0u;
+	return v.weights.x * bones[v.joints.x + offset] + v.weights.y * bones[v.joints.y + offset]
+		+ v.weights.z * bones[v.joints.z + offset] + v.weights.w * bones[v.joints.w + offset];
+}
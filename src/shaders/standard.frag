@@ -0,0 +1,16 @@
+#version 450
+#include "standard.glsl"
+#include "lighting.glsl"
+#include "material.glsl"
+
+layout(location = 0) out vec4 f_color;
+layout(location = 1) out uint f_id;
+layout(location = 2) out vec2 f_velocity;
+
+void main() {
+	float alpha;
+	Surface s = material_surface(alpha);
+	f_color = vec4(apply_fog(light_surface(s), s.world), alpha);
+	f_id = v_id;
+	f_velocity = object_motion();
+}
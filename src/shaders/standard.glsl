@@ -0,0 +1,38 @@
+// Interface shared by the standard vertex and fragment shaders.
+
+#include "frame.glsl"
+
+layout(set = 1, binding = 0) uniform sampler2D u_base_color;
+layout(set = 1, binding = 1) uniform sampler2D u_metallic_roughness; // glTF: g roughness, b metallic
+layout(set = 1, binding = 2) uniform sampler2D u_normal;
+// parameters of the shading models that don't fit the push constants
+layout(set = 1, binding = 3) uniform Style {
+	vec4 toon; // rgb: shadow tint, w: bands
+	vec4 splat; // x: layer uv tiling
+	vec4 displacement; // x: scale, y: midlevel, z: pixels per tessellated edge
+} style;
+layout(set = 1, binding = 4) uniform sampler2D u_splat_control; // layer weights in rgba
+layout(set = 1, binding = 5) uniform sampler2D u_splat_layers[4];
+
+// light probe grid sampled at the object's bounds center, radiance sh in basis order (see
+// sh_project.comp). sh[0].w is 1 when the scene has a baked grid. previous_model is last
+// frame's transform, for motion vectors
+layout(set = 2, binding = 0) uniform Object { vec4 sh[9]; mat4 previous_model; } object;
+layout(set = 2, binding = 1) uniform sampler2D u_lightmap; // irradiance / pi, see lightmap.rs
+// set 2 bindings 2 and 3 are skin.glsl's, 4 and 5 morph.glsl's, for the vertex shader only
+
+#define FLAG_NORMAL_MAP 1u
+#define FLAG_LIGHTMAP 2u
+#define FLAG_SPLAT 4u
+#define FLAG_SKINNED 8u
+#define FLAG_MORPHED 16u
+
+layout(push_constant) uniform PushConstants {
+	mat4 model;
+	vec4 base_color;
+	vec4 emissive;
+	vec4 params;     // metallic, roughness, shininess, normal scale
+	uint shading;
+	uint flags;
+	uint id;
+} pc;
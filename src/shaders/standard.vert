@@ -0,0 +1,45 @@
+#version 450
+#include "standard.glsl"
+#include "skin.glsl"
+#include "morph.glsl"
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec2 uv;
+layout(location = 3) in vec4 tangent;
+layout(location = 4) in vec2 lightmap_uv;
+layout(location = 0) out vec3 v_world;
+layout(location = 1) out vec3 v_normal;
+layout(location = 2) out vec2 v_uv;
+layout(location = 3) out vec4 v_tangent;
+layout(location = 4) flat out uint v_id;
+layout(location = 5) out vec2 v_lightmap_uv;
+layout(location = 6) out vec4 v_clip;
+layout(location = 7) out vec4 v_previous_clip;
+
+void main() {
+	vec3 p = position;
+	vec3 previous_p = position;
+	vec3 n = normal;
+	vec3 t = tangent.xyz;
+	if ((pc.flags & FLAG_MORPHED) != 0u) morph(p, previous_p, n);
+	vec4 local = vec4(p, 1.0);
+	vec4 previous = vec4(previous_p, 1.0);
+	if ((pc.flags & FLAG_SKINNED) != 0u) {
+		mat4 skin = skin_matrix(false);
+		local = skin * local;
+		previous = skin_matrix(true) * previous;
+		n = mat3(skin) * n;
+		t = mat3(skin) * t;
+	}
+	vec4 world = pc.model * local;
+	gl_Position = frame.view_proj * world;
+	v_world = world.xyz;
+	v_normal = mat3(transpose(inverse(pc.model))) * n;
+	v_uv = uv;
+	v_tangent = vec4(mat3(pc.model) * t, tangent.w);
+	v_id = pc.id;
+	v_lightmap_uv = lightmap_uv;
+	v_clip = gl_Position;
+	v_previous_clip = frame.view_proj * object.previous_model * previous;
+}
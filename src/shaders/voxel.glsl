@@ -0,0 +1,56 @@
+// Cone tracing through the voxel gi clipmap. Needs frame.glsl.
+
+// constant indices, no dynamic indexing feature needed
+vec4 voxel_fetch(int level, vec3 uvw) {
+	switch (level) {
+	case 0: return textureLod(u_voxels[0], uvw, 0.0);
+	case 1: return textureLod(u_voxels[1], uvw, 0.0);
+	case 2: return textureLod(u_voxels[2], uvw, 0.0);
+	default: return textureLod(u_voxels[3], uvw, 0.0);
+	}
+}
+
+// the level whose voxels match the cone's width at `p`, or the first coarser one that still
+// contains it; a is -1 once `p` has left the outermost level
+vec4 voxel_sample(vec3 p, float diameter) {
+	int levels = int(frame.voxel_params.y);
+	int level = min(int(log2(max(diameter / frame.voxel_params.x, 1.0))), levels - 1);
+	for (int i = level; i < levels; i++) {
+		vec3 uvw = (p - frame.voxel_origins[i].xyz) / (frame.voxel_origins[i].w * frame.voxel_params.z);
+		if (all(greaterThanEqual(uvw, vec3(0.0))) && all(lessThan(uvw, vec3(1.0)))) return voxel_fetch(i, uvw);
+	}
+	return vec4(0.0, 0.0, 0.0, -1.0);
+}
+
+// radiance gathered front to back along a cone, and how much of it was blocked
+vec4 cone_trace(vec3 origin, vec3 dir, float tan_half) {
+	float voxel = frame.voxel_params.x;
+	vec3 color = vec3(0.0);
+	float occlusion = 0.0;
+	float t = voxel;
+	while (t < frame.voxel_trace.z && occlusion < 0.95) {
+		float diameter = max(voxel, 2.0 * tan_half * t);
+		vec4 v = voxel_sample(origin + dir * t, diameter);
+		if (v.a < 0.0) break;
+		color += (1.0 - occlusion) * v.rgb;
+		occlusion += (1.0 - occlusion) * v.a;
+		t += diameter * 0.5;
+	}
+	return vec4(color, occlusion);
+}
+
+// irradiance / pi over the hemisphere, from a cone along the normal and, on the better tiers,
+// five more around it at 60 degrees, weighted by their share of the cosine lobe
+vec4 voxel_diffuse(vec3 origin, vec3 n) {
+	const float tan30 = 0.577;
+	if (frame.voxel_trace.x < 2.0) return cone_trace(origin, n, tan30);
+	vec3 t = normalize(cross(n, abs(n.y) < 0.99 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0)));
+	vec3 b = cross(n, t);
+	vec4 sum = cone_trace(origin, n, tan30) * 0.25;
+	for (int i = 0; i < 5; i++) {
+		float a = float(i) * 1.2566371; // 2pi / 5
+		vec3 d = normalize(n * 0.5 + (t * cos(a) + b * sin(a)) * 0.866);
+		sum += cone_trace(origin, d, tan30) * 0.15;
+	}
+	return sum;
+}
@@ -0,0 +1,362 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, SubpassContents },
+               image::{ AttachmentImage, ImageUsage, view::ImageView },
+               render_pass::{ Framebuffer, FramebufferCreateInfo, RenderPass, Subpass },
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode, BorderColor, Filter },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               pipeline::{ GraphicsPipeline, Pipeline, PipelineBindPoint, StateMode, graphics::{ input_assembly::InputAssemblyState, vertex_input::BuffersDefinition,
+                                                                       viewport::{ Viewport, ViewportState }, depth_stencil::{ DepthStencilState, CompareOp },
+                                                                       rasterization::{ RasterizationState, CullMode, FrontFace, DepthBiasState } } },
+               format::Format };
+use glam::{ Vec3, Vec4, Mat4 };
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::mesh::Vertex;
+use crate::scene::{ Scene, EntityId };
+use crate::camera::View;
+use crate::light::{ SceneLights, PointLightData, ShadowBias };
+use crate::culling::Frustum;
+use crate::skin::NoSkin;
+use crate::morph::NoMorph;
+
+pub const SHADOW_FORMAT: Format = Format::D32_SFLOAT;
+
+mod vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+			#define SKIN_SET 0
+			#define SKIN_BINDING 0
+			#include \"skin.glsl\"
+			#define MORPH_SET 0
+			#define MORPH_BINDING 2
+			#include \"morph.glsl\"
+
+			layout(location = 0) in vec3 position;
+			layout(push_constant) uniform PushConstants { mat4 mvp; uint skinned; uint morphed; } pc;
+
+			void main() {
+				vec3 m = position, previous = position, normal = vec3(0.0);
+				if (pc.morphed != 0u) morph(m, previous, normal);
+				vec4 p = vec4(m, 1.0);
+				gl_Position = pc.mvp * (pc.skinned != 0u ? skin_matrix(false) * p : p);
+			}",
+    include: ["src/shaders"]
+    }
+}
+mod fs {
+    vulkano_shaders::shader! { ty: "fragment",
+    src: "#version 450
+
+			void main() {}"
+    }
+}
+
+pub const MAX_CASCADES: usize = 4;
+/// Atlas tiles for point and spot lights; a point light takes six.
+pub const MAX_SHADOW_TILES: usize = 64;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    /// Per cascade; the atlas is twice this on each side.
+    pub resolution: u32,
+    /// PCF kernel spacing in texels; 0 gives hard shadows.
+    pub pcf_radius: f32,
+    /// How far from the camera shadows are drawn.
+    pub distance: f32,
+    /// 1 to `MAX_CASCADES`.
+    pub cascades: usize,
+    /// Blend between uniform (0) and logarithmic (1) splits.
+    pub split_lambda: f32,
+    /// Fraction at the far end of each cascade that fades into the next one.
+    pub blend: f32,
+    /// Side of the point/spot light atlas.
+    pub atlas_size: u32,
+    /// Tile size for spot lights; point light faces get half.
+    pub local_resolution: u32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        ShadowSettings { enabled: true, resolution: 1024, pcf_radius: 1.0, distance: 60.0, cascades: 4, split_lambda: 0.75, blend: 0.1,
+                         atlas_size: 4096, local_resolution: 512 }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Cascade {
+    pub view_proj: Mat4,
+    /// View space depth where this cascade ends.
+    pub far: f32,
+    /// World size of one texel, to scale the normal offset.
+    pub texel: f32,
+}
+
+/// Square power of two tiles out of a power of two atlas, buddy style: a free block is split into
+/// quarters until it is the requested size. Rebuilt every frame, so nothing is ever freed.
+pub struct ShadowAtlas {
+    size: u32,
+    free: Vec<Vec<[u32; 2]>>, //free block origins per level, level 0 is the whole atlas
+}
+
+impl ShadowAtlas {
+    pub fn new(size: u32) -> Self { ShadowAtlas { size, free: vec![vec![[0, 0]]] } }
+
+    pub fn clear(&mut self) { self.free = vec![vec![[0, 0]]]; }
+
+    /// Pixel rect (x, y, size) of a free tile, or None if the atlas is full.
+    pub fn alloc(&mut self, size: u32) -> Option<[u32; 3]> {
+        let size = size.next_power_of_two().min(self.size);
+        let level = (self.size / size).trailing_zeros() as usize;
+        if self.free.len() <= level { self.free.resize(level + 1, Vec::new()); }
+        let from = (0..=level).rev().find(|&l| !self.free[l].is_empty())?;
+        let mut origin = self.free[from].pop().unwrap();
+        for l in from..level {
+            let half = self.size >> (l + 1);
+            let [x, y] = origin;
+            self.free[l + 1].extend([[x + half, y], [x, y + half], [x + half, y + half]]);
+            origin = [x, y];
+        }
+        Some([origin[0], origin[1], size])
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowTile {
+    pub view_proj: Mat4,
+    /// x, y, w, h in atlas uv.
+    pub rect: [f32; 4],
+    pub bias: ShadowBias,
+}
+
+/// +x, -x, +y, -y, +z, -z, matching the face pick in the shader.
+const CUBE_FACES: [(Vec3, Vec3); 6] = [(Vec3::X, Vec3::NEG_Y), (Vec3::NEG_X, Vec3::NEG_Y), (Vec3::Y, Vec3::Z),
+                                       (Vec3::NEG_Y, Vec3::NEG_Z), (Vec3::Z, Vec3::NEG_Y), (Vec3::NEG_Z, Vec3::NEG_Y)];
+const LOCAL_NEAR: f32 = 0.05;
+
+/// View projections for a point or spot light: six cube faces or one cone.
+fn local_views(light: &PointLightData) -> Vec<Mat4> {
+    match light.cone {
+        Some((_, outer)) => {
+            let up = if light.direction.y.abs() > 0.99 { Vec3::Z } else { Vec3::Y };
+            let proj = Mat4::perspective_rh((outer * 2.0).min(3.1), 1.0, LOCAL_NEAR, light.range);
+            vec![proj * Mat4::look_at_rh(light.position, light.position + light.direction, up)]
+        }
+        None => {
+            let proj = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, LOCAL_NEAR, light.range);
+            CUBE_FACES.iter().map(|&(dir, up)| proj * Mat4::look_at_rh(light.position, light.position + dir, up)).collect()
+        }
+    }
+}
+
+/// Cascaded depth from the sun, rendered before the scene pass and sampled by the standard shader.
+/// Cascades live in the quadrants of one atlas, cascade i at (i % 2, i / 2). Shadowed point and
+/// spot lights get tiles in a second atlas, six per point light (a cube unrolled) and one per spot.
+pub struct ShadowMap {
+    dev: Arc<Device>,
+    pub settings: ShadowSettings,
+    render_pass: Arc<RenderPass>,
+    pipelines: [Arc<GraphicsPipeline>; 2], //culling nothing, culling front faces
+    pub sampler: Arc<Sampler>,
+    pub depth: Arc<ImageView<AttachmentImage>>,
+    framebuffer: Arc<Framebuffer>,
+    resolution: u32,
+    /// Empty when the last `render` had no sun to draw from.
+    pub cascades: Vec<Cascade>,
+    sun_bias: ShadowBias,
+    pub local_depth: Arc<ImageView<AttachmentImage>>,
+    local_framebuffer: Arc<Framebuffer>,
+    atlas: ShadowAtlas,
+    pub tiles: Vec<ShadowTile>,
+    /// First tile of each light that got shadows this frame.
+    pub local: HashMap<EntityId, usize>,
+    no_skin: NoSkin,
+    no_morph: NoMorph,
+}
+
+/// Extra depth kept behind each fitted sphere so casters outside the view still shadow into it.
+const CASTER_MARGIN: f32 = 50.0;
+
+fn targets(dev: &Arc<Device>, render_pass: &Arc<RenderPass>, size: u32) -> (Arc<ImageView<AttachmentImage>>, Arc<Framebuffer>) {
+    let depth = ImageView::new_default(AttachmentImage::with_usage(dev.clone(), [size, size], SHADOW_FORMAT,
+        ImageUsage { depth_stencil_attachment: true, sampled: true, ..ImageUsage::none() }).unwrap()).unwrap();
+    let framebuffer = Framebuffer::new(render_pass.clone(), FramebufferCreateInfo { attachments: vec![depth.clone()], ..Default::default() }).unwrap();
+    (depth, framebuffer)
+}
+
+/// World space corners of the view frustum between depths `near` and `far`.
+pub fn frustum_slice(view: &View, near: f32, far: f32) -> [Vec3; 8] {
+    let inv_proj = view.proj.inverse();
+    let inv_view = view.view.inverse();
+    let mut corners = [Vec3::ZERO; 8];
+    for (i, (x, y)) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter().enumerate() {
+        let p = inv_proj * Vec4::new(*x, *y, 1.0, 1.0);
+        let dir = p.truncate() / p.w;
+        let dir = dir / -dir.z; //view space, one unit deep
+        corners[i] = inv_view.transform_point3(dir * near);
+        corners[i + 4] = inv_view.transform_point3(dir * far);
+    }
+    corners
+}
+
+/// View space depths where each cascade ends, the practical split scheme.
+pub fn cascade_splits(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+    (1..=count).map(|i| {
+        let f = i as f32 / count as f32;
+        let log = near * (far / near).powf(f);
+        let uniform = near + (far - near) * f;
+        lambda * log + (1.0 - lambda) * uniform
+    }).collect()
+}
+
+/// Orthographic light matrix looking along `dir` containing the sphere around `corners`, and the
+/// world size of a texel. Sphere fitting keeps the size constant as the camera turns, and snapping
+/// the center to whole texels stops the edges shimmering as it moves.
+pub fn fit_light(dir: Vec3, corners: &[Vec3; 8], resolution: u32) -> (Mat4, f32) {
+    let center = corners.iter().fold(Vec3::ZERO, |a, &c| a + c) / 8.0;
+    let radius = corners.iter().map(|c| c.distance(center)).fold(0.0, f32::max);
+    let radius = (radius * 16.0).ceil() / 16.0;
+    let up = if dir.y.abs() > 0.99 { Vec3::Z } else { Vec3::Y };
+    let view = Mat4::look_at_rh(Vec3::ZERO, dir, up);
+    let texel = radius * 2.0 / resolution as f32;
+    let c = view.transform_point3(center);
+    let (x, y) = ((c.x / texel).floor() * texel, (c.y / texel).floor() * texel);
+    let proj = Mat4::orthographic_rh(x - radius, x + radius, y - radius, y + radius, -c.z - radius - CASTER_MARGIN, -c.z + radius);
+    (proj * view, texel)
+}
+
+impl ShadowMap {
+    pub fn new(dev: Arc<Device>) -> Self {
+        let settings = ShadowSettings::default();
+        let render_pass = vulkano::single_pass_renderpass!( dev.clone(),
+                                                            attachments: { depth: { load: Clear, store: Store, format: SHADOW_FORMAT, samples: 1,}},
+                                                            pass: { color: [], depth_stencil: {depth} }).unwrap();
+        let vs = vs::load(dev.clone()).unwrap();
+        let fs = fs::load(dev.clone()).unwrap();
+        //light matrices aren't y flipped like the camera's, so world ccw faces come out clockwise
+        let pipelines = [CullMode::None, CullMode::Front].map(|cull| GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .rasterization_state(RasterizationState { cull_mode: StateMode::Fixed(cull), front_face: StateMode::Fixed(FrontFace::Clockwise),
+                                                      depth_bias: Some(DepthBiasState { enable_dynamic: false, bias: StateMode::Dynamic }),
+                                                      ..RasterizationState::new() })
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .depth_stencil_state(DepthStencilState::simple_depth_test())
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(dev.clone()).unwrap());
+        //compare sampler for sampler2DShadow, outside the map counts as lit
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo {
+            mag_filter: Filter::Linear, min_filter: Filter::Linear,
+            address_mode: [SamplerAddressMode::ClampToBorder; 3], border_color: BorderColor::FloatOpaqueWhite,
+            compare: Some(CompareOp::LessOrEqual), ..Default::default() }).unwrap();
+        let (depth, framebuffer) = targets(&dev, &render_pass, settings.resolution * 2);
+        let (local_depth, local_framebuffer) = targets(&dev, &render_pass, settings.atlas_size);
+        let no_skin = NoSkin::new(dev.clone());
+        let no_morph = NoMorph::new(dev.clone());
+        ShadowMap { dev, settings, render_pass, pipelines, sampler, depth, framebuffer, resolution: settings.resolution, cascades: Vec::new(), sun_bias: ShadowBias::default(),
+                    local_depth, local_framebuffer, atlas: ShadowAtlas::new(settings.atlas_size), tiles: Vec::new(), local: HashMap::new(), no_skin, no_morph }
+    }
+
+    /// x: sun normal offset in texels, y: pcf radius in texels, z: atlas texel size in uv, w: cascade blend band.
+    pub fn params(&self) -> [f32; 4] {
+        [self.sun_bias.normal_offset, self.settings.pcf_radius, 1.0 / (self.resolution * 2) as f32, self.settings.blend]
+    }
+
+    /// y: pcf radius in texels, z: local atlas texel size in uv. Normal offsets are per light.
+    pub fn local_params(&self) -> [f32; 4] {
+        [0.0, self.settings.pcf_radius, 1.0 / self.atlas.size as f32, 0.0]
+    }
+
+    /// Hands out atlas tiles to shadowed lights, nearest first as `SceneLights` sorts them,
+    /// until the atlas or `MAX_SHADOW_TILES` runs out.
+    fn allocate_local(&mut self, lights: &SceneLights) {
+        self.atlas.clear();
+        self.tiles.clear();
+        self.local.clear();
+        let size = self.atlas.size as f32;
+        for (light, bias) in lights.points.iter().filter_map(|l| Some((l, l.shadow?))) {
+            let views = local_views(light);
+            let resolution = if views.len() == 1 { self.settings.local_resolution } else { self.settings.local_resolution / 2 };
+            if self.tiles.len() + views.len() > MAX_SHADOW_TILES { break; }
+            let rects: Option<Vec<_>> = views.iter().map(|_| self.atlas.alloc(resolution)).collect();
+            let rects = match rects { Some(r) => r, None => break };
+            self.local.insert(light.entity, self.tiles.len());
+            for (view_proj, [x, y, s]) in views.into_iter().zip(rects) {
+                self.tiles.push(ShadowTile { view_proj, rect: [x as f32 / size, y as f32 / size, s as f32 / size, s as f32 / size], bias });
+            }
+        }
+    }
+
+    fn draw_casters(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, scene: &Scene, view_proj: &Mat4, bias: &ShadowBias) {
+        let pipeline = &self.pipelines[bias.cull_front as usize];
+        builder.bind_pipeline_graphics(pipeline.clone())
+            .set_depth_bias(bias.constant, 0.0, bias.slope);
+        let frustum = Frustum::from_view_proj(view_proj);
+        for id in scene.bvh.query(|b| frustum.intersects_aabb(b)) {
+            let entity = scene.get(id).unwrap();
+            let mesh = match &entity.mesh { Some(m) if entity.portal.is_none() => m, _ => continue };
+            let (weights, bones) = self.no_skin.or(entity.skin.as_ref());
+            let (deltas, morph_weights) = self.no_morph.or(entity.morph.as_ref());
+            let set = PersistentDescriptorSet::new(pipeline.layout().set_layouts().get(0).unwrap().clone(),
+                                                   [WriteDescriptorSet::buffer(0, weights), WriteDescriptorSet::buffer(1, bones),
+                                                    WriteDescriptorSet::buffer(2, deltas), WriteDescriptorSet::buffer(3, morph_weights)]).unwrap();
+            let pc = vs::ty::PushConstants { mvp: (*view_proj * entity.transform).to_cols_array_2d(), skinned: entity.skin.is_some() as u32,
+                                             morphed: entity.morph.is_some() as u32 };
+            builder.bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 0, set)
+                .push_constants(pipeline.layout().clone(), 0, pc)
+                .bind_vertex_buffers(0, mesh.vertex_buffer.clone())
+                .draw(mesh.vertices.len() as u32, 1, 0, 0).unwrap();
+        }
+    }
+
+    /// Renders the cascades and local light tiles. Always clears both atlases, so the shader can bind
+    /// them even with nothing to shadow. Expects `scene.update_bounds` to have been called this frame.
+    #[profiling::function]
+    pub fn render(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, scene: &Scene, view: &View) {
+        if self.settings.resolution != self.resolution {
+            self.resolution = self.settings.resolution;
+            let (depth, framebuffer) = targets(&self.dev, &self.render_pass, self.resolution * 2);
+            self.depth = depth;
+            self.framebuffer = framebuffer;
+        }
+        if self.settings.atlas_size != self.atlas.size {
+            self.atlas = ShadowAtlas::new(self.settings.atlas_size);
+            let (depth, framebuffer) = targets(&self.dev, &self.render_pass, self.atlas.size);
+            self.local_depth = depth;
+            self.local_framebuffer = framebuffer;
+        }
+        let lights = SceneLights::gather(scene, view.eye());
+        self.cascades.clear();
+        if let (Some((dir, _)), Some(bias), true) = (lights.sun, lights.sun_shadow, self.settings.enabled) {
+            self.sun_bias = bias;
+            let count = self.settings.cascades.clamp(1, MAX_CASCADES);
+            let mut near = view.near;
+            for far in cascade_splits(view.near, self.settings.distance, count, self.settings.split_lambda) {
+                let (view_proj, texel) = fit_light(dir, &frustum_slice(view, near, far), self.resolution);
+                self.cascades.push(Cascade { view_proj, far, texel });
+                near = far;
+            }
+        }
+
+        builder.begin_render_pass(self.framebuffer.clone(), SubpassContents::Inline, vec![1f32.into()]).unwrap();
+        let size = self.resolution as f32;
+        for (i, cascade) in self.cascades.iter().enumerate() {
+            let origin = [(i % 2) as f32 * size, (i / 2) as f32 * size];
+            builder.set_viewport(0, [Viewport { origin, dimensions: [size, size], depth_range: 0.0..1.0 }]);
+            self.draw_casters(builder, scene, &cascade.view_proj, &self.sun_bias);
+        }
+        builder.end_render_pass().unwrap();
+
+        if self.settings.enabled { self.allocate_local(&lights); } else { self.tiles.clear(); self.local.clear(); }
+        builder.begin_render_pass(self.local_framebuffer.clone(), SubpassContents::Inline, vec![1f32.into()]).unwrap();
+        let size = self.atlas.size as f32;
+        for tile in &self.tiles {
+            let r = tile.rect;
+            builder.set_viewport(0, [Viewport { origin: [r[0] * size, r[1] * size], dimensions: [r[2] * size, r[3] * size], depth_range: 0.0..1.0 }]);
+            self.draw_casters(builder, scene, &tile.view_proj, &tile.bias);
+        }
+        builder.end_render_pass().unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+use vulkano::{ device::Device,
+               buffer::{ BufferAccess, BufferUsage, CpuAccessibleBuffer, CpuBufferPool } };
+use bytemuck::{ Pod, Zeroable };
+use glam::Mat4;
+use std::sync::Arc;
+use crate::animation::{ AnimationPlayer, Pose, Skeleton };
+use crate::animation_graph::AnimationGraph;
+use crate::ik::{ self, IkConstraint };
+use crate::scene::Scene;
+use crate::time::Time;
+
+/// Joints and weights of one mesh vertex, in the mesh's vertex order. Unused slots have weight 0.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct SkinVertex { pub joints: [u32; 4], pub weights: [f32; 4] }
+
+/// Bends the entity's mesh by a skeleton in the vertex shader. The mesh is drawn, shadowed and
+/// picked skinned, and so are morph targets; culling, ray casts, outlines and the passes that read the mesh themselves
+/// (voxel gi, impostors, tessellation) still see the bind pose.
+pub struct Skin {
+    pub skeleton: Arc<Skeleton>,
+    /// Per vertex of the entity's mesh, read by the vertex shader as a storage buffer.
+    pub weights: Arc<CpuAccessibleBuffer<[SkinVertex]>>,
+    /// Drives `pose` while it plays anything and there's no graph; set `pose` yourself otherwise.
+    pub player: AnimationPlayer,
+    /// Drives `pose` when set.
+    pub graph: Option<AnimationGraph>,
+    /// The animated pose, before `constraints`.
+    pub pose: Pose,
+    /// Solved over `pose` in order each frame, for the palette only; foot placement, look-at.
+    pub constraints: Vec<IkConstraint>,
+    palette: Vec<Mat4>,
+    /// This frame's palette followed by last frame's, for motion vectors.
+    bones: Option<Arc<dyn BufferAccess>>,
+}
+
+impl Skin {
+    pub fn new(dev: Arc<Device>, skeleton: Arc<Skeleton>, weights: &[SkinVertex]) -> Self {
+        let weights = CpuAccessibleBuffer::from_iter(dev, BufferUsage::storage_buffer(), false, weights.iter().cloned()).expect("failed skin upload");
+        Skin { pose: skeleton.rest_pose(), skeleton, weights, player: AnimationPlayer::default(), graph: None, constraints: Vec::new(), palette: Vec::new(), bones: None }
+    }
+
+    /// Weights and bone palette to bind, once `Skinning::update` has run.
+    pub fn buffers(&self) -> Option<(Arc<dyn BufferAccess>, Arc<dyn BufferAccess>)> {
+        Some((self.weights.clone(), self.bones.clone()?))
+    }
+}
+
+/// Bound in place of a skin's buffers for meshes without one, so skinned and unskinned draws
+/// share a layout.
+pub struct NoSkin {
+    pub weights: Arc<dyn BufferAccess>,
+    pub bones: Arc<dyn BufferAccess>,
+}
+
+impl NoSkin {
+    pub fn new(dev: Arc<Device>) -> Self {
+        let weights = CpuAccessibleBuffer::from_iter(dev.clone(), BufferUsage::storage_buffer(), false, [SkinVertex::default()]).unwrap();
+        let bones = CpuAccessibleBuffer::from_iter(dev, BufferUsage::storage_buffer(), false, [Mat4::IDENTITY.to_cols_array_2d(); 2]).unwrap();
+        NoSkin { weights, bones }
+    }
+
+    /// `skin`'s buffers, or these.
+    pub fn or(&self, skin: Option<&Skin>) -> (Arc<dyn BufferAccess>, Arc<dyn BufferAccess>) {
+        skin.and_then(|s| s.buffers()).unwrap_or_else(|| (self.weights.clone(), self.bones.clone()))
+    }
+}
+
+/// Poses every skinned entity and uploads its palette. Once a frame, before drawing.
+pub struct Skinning {
+    pool: CpuBufferPool<[[f32; 4]; 4]>,
+}
+
+impl Skinning {
+    pub fn new(dev: Arc<Device>) -> Self { Skinning { pool: CpuBufferPool::new(dev, BufferUsage::storage_buffer()) } }
+
+    #[profiling::function]
+    pub fn update(&self, time: &Time, scene: &mut Scene) {
+        for entity in &mut scene.entities {
+            let skin = match &mut entity.skin { Some(s) => s, None => continue };
+            if let Some(graph) = &mut skin.graph {
+                graph.update(time.delta);
+                skin.pose = graph.pose(&skin.skeleton);
+            } else if skin.player.is_playing() {
+                skin.player.update(time.delta);
+                skin.pose = skin.player.pose(&skin.skeleton);
+            }
+            let palette = if skin.constraints.is_empty() { skin.skeleton.palette(&skin.pose) } else {
+                skin.skeleton.palette(&ik::solve(&skin.skeleton, &skin.pose, &skin.constraints, entity.transform.inverse()))
+            };
+            //the first frame has nothing to move from
+            let previous = if skin.palette.len() == palette.len() { std::mem::replace(&mut skin.palette, palette) } else { skin.palette = palette; skin.palette.clone() };
+            let bones = self.pool.chunk(skin.palette.iter().chain(&previous).map(|m| m.to_cols_array_2d())).unwrap();
+            skin.bones = Some(bones);
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use glam::Vec3;
+
+/// Rayleigh and Mie scattering sky, baked by `IblBaker::bake_sky` into an environment that can
+/// serve as both skybox and image based light.
+#[derive(Clone, Copy, Debug)]
+pub struct ProceduralSky {
+    /// Towards the sun.
+    pub sun_direction: Vec3,
+    pub sun_intensity: f32,
+    /// Haze, 1 for a clear day; scales the Mie coefficient.
+    pub turbidity: f32,
+    /// Multiplies the result, the atmosphere comes out very bright in physical units.
+    pub exposure: f32,
+    /// Mie phase anisotropy, how strongly haze glows around the sun.
+    pub mie_g: f32,
+    pub ground_albedo: Vec3,
+}
+
+impl Default for ProceduralSky {
+    fn default() -> Self {
+        ProceduralSky { sun_direction: Vec3::new(0.3, 0.6, 0.5).normalize(), sun_intensity: 20.0, turbidity: 2.0,
+                        exposure: 0.5, mie_g: 0.76, ground_albedo: Vec3::splat(0.3) }
+    }
+}
+
+impl ProceduralSky {
+    /// Color of direct sunlight after the trip through the atmosphere, for the directional light
+    /// that goes with the sky. Same coefficients as sky.comp, with Kasten-Young air mass.
+    pub fn sun_color(&self) -> Vec3 {
+        let rayleigh = Vec3::new(5.8e-6, 13.5e-6, 33.1e-6);
+        let mie = 21e-6;
+        let elevation = self.sun_direction.normalize().y.clamp(-1.0, 1.0).asin().to_degrees();
+        if elevation < -2.0 { return Vec3::ZERO; }
+        let zenith = 90.0 - elevation.max(0.0);
+        let air_mass = 1.0 / (zenith.to_radians().cos() + 0.50572 * (96.07995 - zenith).powf(-1.6364));
+        let tau = (rayleigh * 7994.0 + Vec3::splat(mie * 1.1 * self.turbidity * 1200.0)) * air_mass;
+        let fade = ((elevation + 2.0) / 2.0).clamp(0.0, 1.0); //below the horizon the disk is gone
+        Vec3::new((-tau.x).exp(), (-tau.y).exp(), (-tau.z).exp()) * fade
+    }
+}
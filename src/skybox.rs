@@ -0,0 +1,137 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               image::view::ImageViewAbstract,
+               render_pass::Subpass,
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode, SamplerMipmapMode, Filter },
+               pipeline::{ GraphicsPipeline, Pipeline, PipelineBindPoint, StateMode,
+                           graphics::{ input_assembly::InputAssemblyState, vertex_input::BuffersDefinition, viewport::ViewportState,
+                                       depth_stencil::{ DepthStencilState, DepthState, CompareOp } } } };
+use glam::Vec4;
+use std::sync::Arc;
+use crate::texture::Texture;
+use crate::camera::View;
+use crate::renderer::color_and_id_only;
+
+mod vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			layout(location = 0) out vec3 v_dir;
+			layout(location = 1) flat out vec2 v_params;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 inv_view_proj; //without the camera translation
+				vec2 params;        //x: mode, y: intensity
+			} pc;
+
+			//one triangle covering the screen, on the far plane
+			void main() {
+				vec2 p = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+				gl_Position = vec4(p, 1.0, 1.0);
+				vec4 d = pc.inv_view_proj * vec4(p, 0.0, 1.0);
+				v_dir = d.xyz / d.w;
+				v_params = pc.params;
+			}"
+    }
+}
+mod fs {
+    vulkano_shaders::shader! { ty: "fragment",
+    src: "#version 450
+
+			layout(set = 0, binding = 0) uniform samplerCube u_cube;
+			layout(set = 0, binding = 1) uniform sampler2D u_equirect;
+
+			layout(location = 0) in vec3 v_dir;
+			layout(location = 1) flat in vec2 v_params;
+			layout(location = 0) out vec4 f_color;
+			layout(location = 1) out uint f_id;
+
+			const float PI = 3.14159265359;
+
+			void main() {
+				vec3 d = normalize(v_dir);
+				vec3 c;
+				if (v_params.x > 0.5) {
+					//same mapping as equirect.comp, so both forms of a panorama line up
+					vec2 uv = vec2(atan(d.z, d.x) / (2.0 * PI) + 0.5, acos(clamp(d.y, -1.0, 1.0)) / PI);
+					c = textureLod(u_equirect, uv, 0.0).rgb;
+				} else {
+					c = textureLod(u_cube, d, 0.0).rgb;
+				}
+				f_color = vec4(c * v_params.y, 1.0);
+				f_id = 0;
+			}"
+    }
+}
+
+#[derive(Clone)]
+pub enum SkyboxSource {
+    /// Cube view, such as `Environment::cube`.
+    Cube(Arc<dyn ImageViewAbstract>),
+    /// Lat-long panorama, as `Texture::load_hdr` loads it.
+    Equirect(Arc<Texture>),
+}
+
+/// Per scene backdrop, drawn behind everything at infinite distance.
+#[derive(Clone)]
+pub struct Skybox {
+    pub source: SkyboxSource,
+    pub intensity: f32,
+}
+
+impl Skybox {
+    pub fn new(source: SkyboxSource) -> Self { Skybox { source, intensity: 1.0 } }
+}
+
+/// Draws the scene's skybox as a full screen triangle on the far plane. Run it after the opaque
+/// geometry so the depth test throws away every covered pixel.
+pub struct SkyboxRenderer {
+    pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+}
+
+impl SkyboxRenderer {
+    pub fn new(dev: Arc<Device>, subpass: Subpass) -> Self {
+        let vs = vs::load(dev.clone()).unwrap();
+        let fs = fs::load(dev.clone()).unwrap();
+        let pipeline = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            //the depth buffer is cleared to 1, so less-or-equal passes wherever nothing was drawn
+            .depth_stencil_state(DepthStencilState { depth: Some(DepthState { enable_dynamic: false, write_enable: StateMode::Fixed(false),
+                                                                              compare_op: StateMode::Fixed(CompareOp::LessOrEqual) }),
+                                                     ..DepthStencilState::disabled() })
+            .color_blend_state(color_and_id_only(subpass.num_color_attachments()))
+            .render_pass(subpass)
+            .build(dev.clone()).unwrap();
+        let sampler = Sampler::new(dev, SamplerCreateInfo {
+            mag_filter: Filter::Linear, min_filter: Filter::Linear, mipmap_mode: SamplerMipmapMode::Linear,
+            address_mode: [SamplerAddressMode::Repeat, SamplerAddressMode::ClampToEdge, SamplerAddressMode::ClampToEdge], ..Default::default() }).unwrap();
+        SkyboxRenderer { pipeline, sampler }
+    }
+
+    /// `cube` and `equirect` fill whichever binding the skybox doesn't use.
+    pub fn draw(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, skybox: &Skybox, view: &View,
+                cube: Arc<dyn ImageViewAbstract>, equirect: Arc<dyn ImageViewAbstract>) {
+        let (mode, cube, equirect) = match &skybox.source {
+            SkyboxSource::Cube(c) => (0.0, c.clone(), equirect),
+            SkyboxSource::Equirect(t) => (1.0, cube, t.view.clone()),
+        };
+        let mut rotation = view.view;
+        rotation.w_axis = Vec4::W;
+        let pc = vs::ty::PushConstants { inv_view_proj: (view.proj * rotation).inverse().to_cols_array_2d(), params: [mode, skybox.intensity] };
+        let layout = self.pipeline.layout().set_layouts().get(0).unwrap();
+        let set = PersistentDescriptorSet::new(layout.clone(), [
+            WriteDescriptorSet::image_view_sampler(0, cube, self.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler(1, equirect, self.sampler.clone()),
+        ]).unwrap();
+        builder.bind_pipeline_graphics(self.pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, self.pipeline.layout().clone(), 0, set)
+            .push_constants(self.pipeline.layout().clone(), 0, pc)
+            .draw(3, 1, 0, 0).unwrap();
+    }
+}
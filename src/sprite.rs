@@ -0,0 +1,211 @@
+use serde_json::Value;
+use std::path::{ Path, PathBuf };
+use std::sync::Arc;
+use crate::billboard::Billboard;
+use crate::time::Time;
+
+/// One cell of a sprite sheet.
+#[derive(Clone, Copy, Debug)]
+pub struct SpriteFrame {
+    /// min.xy, max.xy, as `Billboard::uv` takes it.
+    pub uv: [f32; 4],
+    /// Pixels, for sizing the billboard.
+    pub size: [u32; 2],
+    /// Seconds.
+    pub duration: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Forward,
+    Reverse,
+    /// Forward then back, without showing the end frames twice.
+    PingPong,
+}
+
+/// A named run of frames, an Aseprite tag.
+#[derive(Clone, Debug)]
+pub struct SpriteClip {
+    pub name: String,
+    pub from: usize,
+    /// Inclusive.
+    pub to: usize,
+    pub direction: Direction,
+}
+
+/// Frame regions and timings of one sprite sheet image.
+#[derive(Clone, Debug, Default)]
+pub struct SpriteSheet {
+    /// The image the sheet was exported with, next to the json.
+    pub image: Option<PathBuf>,
+    pub frames: Vec<SpriteFrame>,
+    pub clips: Vec<SpriteClip>,
+}
+
+/// Aseprite's ms default for exports without durations, TexturePacker's for one.
+const DEFAULT_DURATION: f32 = 0.1;
+
+impl SpriteSheet {
+    /// `count` equal cells of a `columns` by `rows` grid, row by row from the top left.
+    pub fn grid(columns: u32, rows: u32, count: usize, duration: f32) -> Self {
+        let (w, h) = (1.0 / columns as f32, 1.0 / rows as f32);
+        let frames = (0..count.min((columns * rows) as usize) as u32).map(|i| {
+            let (x, y) = ((i % columns) as f32 * w, (i / columns) as f32 * h);
+            SpriteFrame { uv: [x, y, x + w, y + h], size: [0, 0], duration }
+        }).collect();
+        SpriteSheet { image: None, frames, clips: Vec::new() }
+    }
+
+    /// Aseprite's json export, as a hash or an array; TexturePacker's json too, minus rotated
+    /// frames. Tags become clips.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let json: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        let meta = &json["meta"];
+        let number = |v: &Value, what: &str| v.as_f64().map(|n| n as f32).ok_or_else(|| format!("missing {}", what));
+        let sheet = [number(&meta["size"]["w"], "meta.size.w")?, number(&meta["size"]["h"], "meta.size.h")?];
+        //the hash keeps file order, which is frame order
+        let frames: Vec<&Value> = match &json["frames"] {
+            Value::Array(frames) => frames.iter().collect(),
+            Value::Object(frames) => frames.values().collect(),
+            _ => return Err("missing frames".into()),
+        };
+        let frames = frames.into_iter().enumerate().map(|(i, f)| {
+            if f["rotated"].as_bool() == Some(true) { return Err(format!("frame {} is rotated, export without rotation", i)); }
+            let rect = &f["frame"];
+            let [x, y, w, h] = ["x", "y", "w", "h"].map(|k| number(&rect[k], &format!("frames[{}].frame.{}", i, k)));
+            let (x, y, w, h) = (x?, y?, w?, h?);
+            let duration = f["duration"].as_f64().map_or(DEFAULT_DURATION, |ms| ms as f32 / 1000.0);
+            Ok(SpriteFrame { uv: [x / sheet[0], y / sheet[1], (x + w) / sheet[0], (y + h) / sheet[1]], size: [w as u32, h as u32], duration })
+        }).collect::<Result<Vec<_>, String>>()?;
+        let clips = meta["frameTags"].as_array().map_or(&[][..], |t| t).iter().map(|t| {
+            let name = t["name"].as_str().unwrap_or("").to_string();
+            let (from, to) = (t["from"].as_u64().unwrap_or(0) as usize, t["to"].as_u64().unwrap_or(0) as usize);
+            if from > to || to >= frames.len() { return Err(format!("tag {:?} runs past the frames", name)); }
+            let direction = match t["direction"].as_str().unwrap_or("forward") {
+                "forward" => Direction::Forward,
+                "reverse" => Direction::Reverse,
+                //pingpong_reverse starts from the front too
+                "pingpong" | "pingpong_reverse" => Direction::PingPong,
+                d => return Err(format!("tag {:?} has unknown direction {:?}", name, d)),
+            };
+            Ok(SpriteClip { name, from, to, direction })
+        }).collect::<Result<Vec<_>, String>>()?;
+        let image = meta["image"].as_str().map(PathBuf::from);
+        Ok(SpriteSheet { image, frames, clips })
+    }
+
+    /// An Aseprite or TexturePacker json export on disk, see `parse`. `image` comes back
+    /// relative to where the json is.
+    #[profiling::function]
+    pub fn load(path: impl AsRef<Path>) -> Arc<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("failed loading {:?}: {}", path, e));
+        let mut sheet = Self::parse(&text).unwrap_or_else(|e| panic!("{:?} {}", path, e));
+        sheet.image = sheet.image.map(|i| path.parent().map_or(i.clone(), |dir| dir.join(i)));
+        Arc::new(sheet)
+    }
+
+    pub fn clip(&self, name: &str) -> Option<usize> { self.clips.iter().position(|c| c.name == name) }
+}
+
+/// Steps through a sheet's frames; put it on a billboard and `animate` keeps its uv current.
+#[derive(Clone)]
+pub struct SpriteAnimator {
+    pub sheet: Arc<SpriteSheet>,
+    /// Playback rate, 1 as authored.
+    pub speed: f32,
+    /// Round and round, or stop on the last frame.
+    pub looping: bool,
+    /// Index into the sheet's clips; the whole sheet forward when None.
+    clip: Option<usize>,
+    frame: usize,
+    /// +1 or -1, ping pong turns it around.
+    step: isize,
+    elapsed: f32,
+    playing: bool,
+}
+
+impl SpriteAnimator {
+    /// Playing the whole sheet.
+    pub fn new(sheet: Arc<SpriteSheet>) -> Self {
+        let mut animator = SpriteAnimator { sheet, speed: 1.0, looping: true, clip: None, frame: 0, step: 1, elapsed: 0.0, playing: false };
+        animator.restart();
+        animator
+    }
+
+    /// From the clip's first frame. False, and nothing changes, for a name the sheet hasn't.
+    pub fn play(&mut self, name: &str) -> bool {
+        let clip = match self.sheet.clip(name) { Some(c) => c, None => return false };
+        self.clip = Some(clip);
+        self.restart();
+        true
+    }
+
+    /// Keeps going from wherever it is if it's already playing `name`.
+    pub fn play_if_not(&mut self, name: &str) -> bool {
+        if self.playing && self.clip.map(|c| self.sheet.clips[c].name.as_str()) == Some(name) { return true; }
+        self.play(name)
+    }
+
+    pub fn stop(&mut self) { self.playing = false; }
+
+    pub fn is_playing(&self) -> bool { self.playing }
+
+    pub fn frame(&self) -> &SpriteFrame { &self.sheet.frames[self.frame] }
+
+    fn range(&self) -> (usize, usize, Direction) {
+        match self.clip.map(|c| &self.sheet.clips[c]) {
+            Some(c) => (c.from, c.to, c.direction),
+            None => (0, self.sheet.frames.len().saturating_sub(1), Direction::Forward),
+        }
+    }
+
+    fn restart(&mut self) {
+        let (from, to, direction) = self.range();
+        (self.frame, self.step) = if direction == Direction::Reverse { (to, -1) } else { (from, 1) };
+        self.elapsed = 0.0;
+        self.playing = !self.sheet.frames.is_empty();
+    }
+
+    /// To the next frame; false off the end of a clip that doesn't loop.
+    fn advance(&mut self) -> bool {
+        let (from, to, direction) = self.range();
+        let next = self.frame as isize + self.step;
+        if next >= from as isize && next <= to as isize { self.frame = next as usize; return true; }
+        if direction == Direction::PingPong && self.step == 1 && from < to {
+            self.step = -1;
+            self.frame -= 1;
+            return true;
+        }
+        if !self.looping { return false; }
+        match direction {
+            Direction::Forward => self.frame = from,
+            Direction::Reverse => self.frame = to,
+            Direction::PingPong => { self.step = 1; self.frame = (from + 1).min(to); }
+        }
+        true
+    }
+
+    /// `dt` in scaled seconds. Long frames skip as many frames as they cover.
+    pub fn update(&mut self, dt: f32) {
+        if !self.playing { return; }
+        self.elapsed += dt * self.speed;
+        loop {
+            //zero length frames would never let go
+            let duration = self.frame().duration.max(1e-3);
+            if self.elapsed < duration { break; }
+            self.elapsed -= duration;
+            if !self.advance() { self.elapsed = 0.0; self.playing = false; break; }
+        }
+    }
+}
+
+/// Advances every animated billboard and points its uv at the current frame. Once a frame.
+pub fn animate(time: &Time, billboards: &mut [Billboard]) {
+    for b in billboards {
+        if let Some(animation) = &mut b.animation {
+            animation.update(time.delta);
+            b.uv = animation.frame().uv;
+        }
+    }
+}
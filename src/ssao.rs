@@ -0,0 +1,104 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               image::{ AttachmentImage, ImageUsage, view::ImageView },
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint },
+               format::{ ClearValue, Format } };
+use std::sync::Arc;
+use crate::camera::View;
+use crate::renderer::Target;
+
+mod occlusion {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/ssao.comp", include: ["src/shaders"] }
+}
+mod blur {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/ssao_blur.comp", include: ["src/shaders"] }
+}
+
+pub const AO_FORMAT: Format = Format::R32_SFLOAT;
+
+/// Screen space ambient occlusion, darkening the ambient light in creases and contact points.
+/// Deferred only: forward shades before there is a depth or normal buffer to read.
+#[derive(Clone, Copy, Debug)]
+pub struct Ssao {
+    /// Meters around each point that can occlude it.
+    pub radius: f32,
+    /// Power on the result; above 1 darkens.
+    pub intensity: f32,
+    /// Meters an occluder must be in front by, against self occlusion.
+    pub bias: f32,
+    pub samples: u32,
+    /// How hard the blur stops at depth edges, per meter.
+    pub sharpness: f32,
+}
+
+impl Default for Ssao {
+    fn default() -> Self { Ssao { radius: 0.5, intensity: 1.5, bias: 0.025, samples: 16, sharpness: 8.0 } }
+}
+
+/// Result and blur scratch, shared by targets of the same size like the g-buffer.
+pub fn ao_views(dev: &Arc<Device>, extent: [u32; 2]) -> Vec<Arc<ImageView<AttachmentImage>>> {
+    (0..2).map(|_| ImageView::new_default(AttachmentImage::with_usage(dev.clone(), extent, AO_FORMAT,
+        ImageUsage { storage: true, sampled: true, transfer_destination: true, ..ImageUsage::none() }).unwrap()).unwrap()).collect()
+}
+
+/// Hemisphere sampling over the g-buffer's depth and normals, then a bilateral blur across
+/// and down. Leaves `target.ao[0]` for the tiled resolve to multiply the ambient term by.
+pub struct SsaoPass {
+    occlusion: Arc<ComputePipeline>,
+    blur: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+}
+
+impl SsaoPass {
+    pub fn new(dev: Arc<Device>) -> Self {
+        let occlusion = ComputePipeline::new(dev.clone(), occlusion::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let blur = ComputePipeline::new(dev.clone(), blur::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let sampler = Sampler::new(dev, SamplerCreateInfo { address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        SsaoPass { occlusion, blur, sampler }
+    }
+
+    /// Between the g-buffer pass and the resolve. None leaves the ambient unoccluded.
+    #[profiling::function]
+    pub fn record(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, target: &Target, ssao: Option<&Ssao>, view: &View) {
+        let ssao = match ssao {
+            Some(s) => s,
+            None => {
+                builder.clear_color_image(target.ao[0].image().clone(), ClearValue::Float([1.0; 4])).unwrap();
+                return;
+            }
+        };
+        let rows = view.view.transpose();
+        let set = PersistentDescriptorSet::new(self.occlusion.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, target.depth.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler(1, target.gbuffer[1].clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view(2, target.ao[0].clone()),
+        ]).unwrap();
+        let pc = occlusion::ty::PushConstants { proj: view.proj.to_cols_array_2d(),
+                                                view_rows: [rows.x_axis.into(), rows.y_axis.into(), rows.z_axis.into()],
+                                                params: [ssao.radius, ssao.intensity, ssao.bias, ssao.samples as f32] };
+        builder.bind_pipeline_compute(self.occlusion.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.occlusion.layout().clone(), 0, set)
+            .push_constants(self.occlusion.layout().clone(), 0, pc)
+            .dispatch([(target.extent[0] + 7) / 8, (target.extent[1] + 7) / 8, 1]).unwrap();
+        self.blur(builder, target, ssao.sharpness, view);
+    }
+
+    /// The bilateral blur across and down, `target.ao[0]` back into itself. Ray traced AO's too.
+    pub fn blur(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, target: &Target, sharpness: f32, view: &View) {
+        let groups = [(target.extent[0] + 7) / 8, (target.extent[1] + 7) / 8, 1];
+        builder.bind_pipeline_compute(self.blur.clone());
+        for (direction, src, dst) in [([1, 0], 0, 1), ([0, 1], 1, 0)] {
+            let set = PersistentDescriptorSet::new(self.blur.layout().set_layouts().get(0).unwrap().clone(), [
+                WriteDescriptorSet::image_view_sampler(0, target.depth.clone(), self.sampler.clone()),
+                WriteDescriptorSet::image_view(1, target.ao[src].clone()),
+                WriteDescriptorSet::image_view(2, target.ao[dst].clone()),
+            ]).unwrap();
+            let pc = blur::ty::PushConstants { direction, proj_z: [view.proj.z_axis.z, view.proj.w_axis.z], sharpness };
+            builder.bind_descriptor_sets(PipelineBindPoint::Compute, self.blur.layout().clone(), 0, set)
+                .push_constants(self.blur.layout().clone(), 0, pc)
+                .dispatch(groups).unwrap();
+        }
+    }
+}
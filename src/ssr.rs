@@ -0,0 +1,134 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               image::{ AttachmentImage, ImageUsage, view::ImageView },
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode, Filter },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint },
+               format::{ ClearValue, Format } };
+use std::sync::Arc;
+use crate::camera::View;
+use crate::renderer::Target;
+
+mod hiz {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/hiz.comp", include: ["src/shaders"] }
+}
+mod trace {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/ssr.comp", include: ["src/shaders"] }
+}
+mod blur {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/ssr_blur.comp", include: ["src/shaders"] }
+}
+
+pub const HIZ_FORMAT: Format = Format::R32_SFLOAT;
+pub const REFLECTION_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+/// Hi-z levels built past level 0. Keep in step with MAX_LEVEL in ssr.comp.
+const HIZ_LEVELS: i32 = 6;
+
+/// Screen space reflections, taking over from the reflection probes and sky wherever the
+/// reflected ray finds something on screen. Deferred only.
+#[derive(Clone, Copy, Debug)]
+pub struct Ssr {
+    /// Meters a ray travels.
+    pub max_distance: f32,
+    /// Meters behind the depth buffer a ray still counts as hitting.
+    pub thickness: f32,
+    pub max_steps: u32,
+    /// Rougher surfaces keep the probes; reflections fade out on the way there.
+    pub max_roughness: f32,
+    /// Blur width in pixels at roughness 1.
+    pub blur: f32,
+}
+
+impl Default for Ssr {
+    fn default() -> Self { Ssr { max_distance: 30.0, thickness: 0.3, max_steps: 96, max_roughness: 0.6, blur: 12.0 } }
+}
+
+/// The packed hi-z pyramid, see hiz.glsl: level 0 and a column half as wide beside it.
+pub fn hiz_view(dev: &Arc<Device>, extent: [u32; 2]) -> Arc<ImageView<AttachmentImage>> {
+    ImageView::new_default(AttachmentImage::with_usage(dev.clone(), [extent[0] + (extent[0] / 2).max(1), extent[1]], HIZ_FORMAT,
+        ImageUsage { storage: true, ..ImageUsage::none() }).unwrap()).unwrap()
+}
+
+/// Reflections and blur scratch.
+pub fn reflection_views(dev: &Arc<Device>, extent: [u32; 2]) -> Vec<Arc<ImageView<AttachmentImage>>> {
+    (0..2).map(|_| ImageView::new_default(AttachmentImage::with_usage(dev.clone(), extent, REFLECTION_FORMAT,
+        ImageUsage { storage: true, sampled: true, transfer_destination: true, ..ImageUsage::none() }).unwrap()).unwrap()).collect()
+}
+
+/// Builds the hi-z pyramid from the g-buffer's depth, traces reflections through it against the
+/// last frame's lit image, then blurs them by roughness. Leaves `target.reflections[0]` for the
+/// tiled resolve to use in place of the probes' specular. Last frame's image isn't reprojected,
+/// so reflections trail a fast moving camera by a frame.
+pub struct SsrPass {
+    hiz: Arc<ComputePipeline>,
+    trace: Arc<ComputePipeline>,
+    blur: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+    linear: Arc<Sampler>,
+}
+
+impl SsrPass {
+    pub fn new(dev: Arc<Device>) -> Self {
+        let hiz = ComputePipeline::new(dev.clone(), hiz::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let trace = ComputePipeline::new(dev.clone(), trace::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let blur = ComputePipeline::new(dev.clone(), blur::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo { address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        let linear = Sampler::new(dev, SamplerCreateInfo { mag_filter: Filter::Linear, min_filter: Filter::Linear,
+                                                           address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        SsrPass { hiz, trace, blur, sampler, linear }
+    }
+
+    /// Between the g-buffer pass and the resolve. None leaves reflections to the probes.
+    #[profiling::function]
+    pub fn record(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, target: &Target, ssr: Option<&Ssr>, view: &View) {
+        let ssr = match ssr {
+            Some(s) => s,
+            None => {
+                builder.clear_color_image(target.reflections[0].image().clone(), ClearValue::Float([0.0; 4])).unwrap();
+                return;
+            }
+        };
+        let hiz = target.hiz.clone().unwrap();
+        let base = [target.extent[0] as i32, target.extent[1] as i32];
+        let set = PersistentDescriptorSet::new(self.hiz.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, target.depth.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view(1, hiz.clone()),
+        ]).unwrap();
+        builder.bind_pipeline_compute(self.hiz.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.hiz.layout().clone(), 0, set);
+        for level in 0..=HIZ_LEVELS {
+            let size = [(target.extent[0] >> level).max(1), (target.extent[1] >> level).max(1)];
+            builder.push_constants(self.hiz.layout().clone(), 0, hiz::ty::PushConstants { base, level })
+                .dispatch([(size[0] + 7) / 8, (size[1] + 7) / 8, 1]).unwrap();
+        }
+
+        let groups = [(target.extent[0] + 7) / 8, (target.extent[1] + 7) / 8, 1];
+        let rows = view.view.transpose();
+        let set = PersistentDescriptorSet::new(self.trace.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, target.depth.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler(1, target.gbuffer[1].clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view(2, hiz),
+            WriteDescriptorSet::image_view_sampler(3, target.lit.clone().unwrap(), self.linear.clone()),
+            WriteDescriptorSet::image_view(4, target.reflections[0].clone()),
+        ]).unwrap();
+        let pc = trace::ty::PushConstants { proj: view.proj.to_cols_array_2d(),
+                                            view_rows: [rows.x_axis.into(), rows.y_axis.into(), rows.z_axis.into()],
+                                            params: [ssr.max_distance, ssr.thickness, ssr.max_steps as f32, ssr.max_roughness] };
+        builder.bind_pipeline_compute(self.trace.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.trace.layout().clone(), 0, set)
+            .push_constants(self.trace.layout().clone(), 0, pc)
+            .dispatch(groups).unwrap();
+
+        builder.bind_pipeline_compute(self.blur.clone());
+        for (direction, src, dst) in [([1, 0], 0, 1), ([0, 1], 1, 0)] {
+            let set = PersistentDescriptorSet::new(self.blur.layout().set_layouts().get(0).unwrap().clone(), [
+                WriteDescriptorSet::image_view_sampler(0, target.gbuffer[1].clone(), self.sampler.clone()),
+                WriteDescriptorSet::image_view(1, target.reflections[src].clone()),
+                WriteDescriptorSet::image_view(2, target.reflections[dst].clone()),
+            ]).unwrap();
+            builder.bind_descriptor_sets(PipelineBindPoint::Compute, self.blur.layout().clone(), 0, set)
+                .push_constants(self.blur.layout().clone(), 0, blur::ty::PushConstants { direction, max_radius: ssr.blur })
+                .dispatch(groups).unwrap();
+        }
+    }
+}
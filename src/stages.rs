@@ -0,0 +1,24 @@
+use vulkano::device::{ Features, physical::PhysicalDevice };
+
+/// Optional shader stages the device has. They are enabled whenever present; pipelines that
+/// need one check the device's enabled features and are only built when it's there, and
+/// whatever would draw with them keeps a plain pipeline to fall back on.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ShaderStageSupport {
+    /// Tessellation control and evaluation shaders, for `Material::displacement`.
+    pub tessellation: bool,
+    /// Geometry shaders, for `Overlay::line_width`.
+    pub geometry: bool,
+}
+
+impl ShaderStageSupport {
+    pub fn detect(physical: PhysicalDevice) -> Self {
+        let features = physical.supported_features();
+        ShaderStageSupport { tessellation: features.tessellation_shader, geometry: features.geometry_shader }
+    }
+
+    /// `features` with the supported stages' added.
+    pub fn enable(&self, features: Features) -> Features {
+        Features { tessellation_shader: self.tessellation, geometry_shader: self.geometry, ..features }
+    }
+}
@@ -0,0 +1,82 @@
+use vulkano::swapchain::Swapchain;
+use winit::window::Window;
+use std::collections::VecDeque;
+use crate::renderer::DrawStats;
+use crate::gpu_memory::{ GpuMemory, MemoryCategory };
+use crate::text::{ Align, Font, TextStyle };
+use crate::time::Time;
+use crate::ui::Ui;
+use crate::vector::{ self, Stroke };
+
+/// Frames the graph spans.
+const HISTORY: usize = 120;
+/// Milliseconds at the top of the graph.
+const GRAPH_MS: f32 = 33.3;
+/// Graph size in points.
+const GRAPH: [f32; 2] = [240.0, 64.0];
+
+/// Frame telemetry in the top right corner: fps, frame and gpu times with a graph of the
+/// last couple of seconds, draw calls and triangles, gpu memory, how the swapchain presents
+/// and the gpu time of every pass.
+pub struct StatsOverlay {
+    pub visible: bool,
+    frame: VecDeque<f32>,
+    gpu: VecDeque<f32>,
+    passes: Vec<(&'static str, f32)>,
+    draws: DrawStats,
+    memory: GpuMemory,
+}
+
+impl StatsOverlay {
+    pub fn new() -> Self { StatsOverlay { visible: true, frame: VecDeque::new(), gpu: VecDeque::new(), passes: Vec::new(), draws: DrawStats::default(), memory: GpuMemory::new() } }
+
+    /// Once a frame. `gpu_ms` and `passes` are the `GpuTimer`'s and `draws` the renderer's
+    /// stats of the frame before, all a little behind the frame time.
+    pub fn update(&mut self, time: &Time, gpu_ms: Option<f32>, passes: &[(&'static str, f32)], draws: DrawStats, memory: &GpuMemory) {
+        for (history, ms) in [(&mut self.frame, Some(time.real_delta * 1000.0)), (&mut self.gpu, gpu_ms)] {
+            let ms = match ms { Some(ms) => ms, None => continue };
+            if history.len() == HISTORY { history.pop_front(); }
+            history.push_back(ms);
+        }
+        self.passes.clear();
+        self.passes.extend_from_slice(passes);
+        self.draws = draws;
+        self.memory = memory.clone();
+    }
+
+    /// Text in `font` right aligned against the top right of a `screen` pixels across, `scale`
+    /// pixels to the point.
+    pub fn draw(&self, ui: &mut Ui, font: &mut Font, swapchain: &Swapchain<Window>, screen: [f32; 2], scale: f32) {
+        if !self.visible { return; }
+        let last = |history: &VecDeque<f32>| history.back().map_or("-".to_string(), |ms| format!("{:.2}", ms));
+        let mean = self.frame.iter().sum::<f32>() / self.frame.len().max(1) as f32;
+        let extent = swapchain.image_extent();
+        let mb = |bytes: u64| bytes as f32 / (1024.0 * 1024.0);
+        let (used, budget) = self.memory.device_local();
+        let vram = match self.memory.has_budget {
+            true => format!("{:.0} of {:.0} MB vram{}", mb(used), mb(budget), if self.memory.near_budget() { ", near budget" } else { "" }),
+            false => format!("{:.0} MB vram", mb(budget)),
+        };
+        let categories: Vec<String> = MemoryCategory::ALL.iter().map(|c| format!("{} {:.0}", c.name(), mb(self.memory.categories[*c as usize]))).collect();
+        let text = format!("{:.0} fps\n{} ms frame, {} ms gpu\n{} draws, {} triangles\n{}\n{} MB\n{}x{} {:?}\n{:?}, {} images",
+                           1000.0 / mean.max(1e-3), last(&self.frame), last(&self.gpu), self.draws.draw_calls, self.draws.triangles,
+                           vram, categories.join(", "), extent[0], extent[1], swapchain.image_format(), swapchain.present_mode(), swapchain.image_count());
+        let style = TextStyle { align: Align::Right, ..TextStyle::new(14.0 * scale) };
+        let margin = 12.0 * scale;
+        ui.text(font, &text, [screen[0] - margin, margin], &style);
+
+        let at = [screen[0] - margin - GRAPH[0] * scale, margin * 1.5 + font.line_height(style.size) * 7.0];
+        ui.fill(&vector::rounded_rect([0.0, 0.0, GRAPH[0], GRAPH[1]], 4.0), at, scale, [0.0, 0.0, 0.0, 0.6]);
+        let y = |ms: f32| GRAPH[1] - (ms / GRAPH_MS).min(1.0) * GRAPH[1];
+        //60 fps
+        ui.stroke(&vector::polyline(&[[0.0, y(1000.0 / 60.0)], [GRAPH[0], y(1000.0 / 60.0)]], false), &Stroke::new(1.0), at, scale, [1.0, 1.0, 1.0, 0.3]);
+        for (history, color) in [(&self.gpu, [1.0, 0.6, 0.2, 1.0]), (&self.frame, [0.4, 1.0, 0.4, 1.0])] {
+            let points: Vec<[f32; 2]> = history.iter().enumerate().map(|(i, &ms)| [i as f32 * GRAPH[0] / (HISTORY - 1) as f32, y(ms)]).collect();
+            ui.stroke(&vector::polyline(&points, false), &Stroke::new(1.5), at, scale, color);
+        }
+        if !self.passes.is_empty() {
+            let passes: Vec<String> = self.passes.iter().map(|(name, ms)| format!("{} {:.2} ms", name, ms)).collect();
+            ui.text(font, &passes.join("\n"), [screen[0] - margin, at[1] + GRAPH[1] * scale + margin * 0.5], &style);
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use vulkano::device::Device;
+use glam::{ Mat4, Vec2, Vec3 };
+use std::path::Path;
+use std::sync::Arc;
+use crate::mesh::{ Mesh, Vertex };
+use crate::bvh::Aabb;
+use crate::material::Material;
+use crate::scene::{ Scene, EntityId };
+
+/// Heights in 0..1, row major starting at the terrain's -x -z corner.
+pub struct Heightmap {
+    size: [u32; 2],
+    heights: Vec<f32>,
+}
+
+impl Heightmap {
+    pub fn from_fn(size: [u32; 2], f: impl Fn(u32, u32) -> f32) -> Self {
+        assert!(size[0] >= 2 && size[1] >= 2, "heightmap needs at least 2x2 samples");
+        let heights = (0..size[1]).flat_map(|y| (0..size[0]).map(move |x| (x, y))).map(|(x, y)| f(x, y)).collect();
+        Heightmap { size, heights }
+    }
+
+    /// Grayscale image; 16 bit pngs keep their precision, anything else is converted to luma.
+    #[profiling::function]
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let img = image::open(path.as_ref()).unwrap_or_else(|e| panic!("failed loading {:?}: {}", path.as_ref(), e)).to_luma16();
+        Self::from_fn([img.width(), img.height()], |x, y| img.get_pixel(x, y).0[0] as f32 / 65535.0)
+    }
+
+    fn texel(&self, x: i64, y: i64) -> f32 {
+        let x = x.clamp(0, self.size[0] as i64 - 1) as usize;
+        let y = y.clamp(0, self.size[1] as i64 - 1) as usize;
+        self.heights[y * self.size[0] as usize + x]
+    }
+
+    /// Bilinear, `uv` 0..1 across the map.
+    pub fn sample(&self, uv: Vec2) -> f32 {
+        let p = uv.clamp(Vec2::ZERO, Vec2::ONE) * (Vec2::new(self.size[0] as f32, self.size[1] as f32) - 1.0);
+        let (x, y, f) = (p.x.floor() as i64, p.y.floor() as i64, p.fract());
+        let top = self.texel(x, y) + (self.texel(x + 1, y) - self.texel(x, y)) * f.x;
+        let bottom = self.texel(x, y + 1) + (self.texel(x + 1, y + 1) - self.texel(x, y + 1)) * f.x;
+        top + (bottom - top) * f.y
+    }
+
+    /// Lowest and highest texel under a uv rectangle, one texel of margin for the filtering.
+    fn range(&self, min: Vec2, max: Vec2) -> (f32, f32) {
+        let last = Vec2::new(self.size[0] as f32, self.size[1] as f32) - 1.0;
+        let (a, b) = ((min * last).floor(), (max * last).ceil());
+        let mut range = (f32::MAX, f32::MIN);
+        for y in a.y as i64..=b.y as i64 {
+            for x in a.x as i64..=b.x as i64 {
+                let h = self.texel(x, y);
+                range = (range.0.min(h), range.1.max(h));
+            }
+        }
+        range
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TerrainSettings {
+    /// World size of the square the heightmap is stretched over, centered on the terrain's position.
+    pub size: f32,
+    /// World height of a heightmap value of 1.
+    pub height: f32,
+    /// Quads along a chunk's side, the same at every level, so deeper chunks are finer.
+    pub chunk_cells: u32,
+    /// Quadtree depth; the finest chunks are `size / 2^(levels - 1)` wide.
+    pub levels: u32,
+    /// A chunk splits while the eye is closer than this many times its width.
+    pub lod_distance: f32,
+    /// How far the skirts hang below chunk edges. Has to cover the largest step between
+    /// neighbouring levels, a few percent of `height` is usually plenty.
+    pub skirt: f32,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self { TerrainSettings { size: 256.0, height: 32.0, chunk_cells: 32, levels: 5, lod_distance: 1.5, skirt: 2.0 } }
+}
+
+struct Node {
+    /// Corner and side in heightmap uv.
+    min: Vec2,
+    side: f32,
+    children: Option<[usize; 4]>,
+    /// Local to the terrain.
+    bounds: Aabb,
+    entity: EntityId,
+    /// Built the first time the node is selected.
+    mesh: Option<Arc<Mesh>>,
+}
+
+/// Heightmap terrain split into a quadtree of chunks. Every node is an entity of its own that
+/// gets its mesh only while selected, so culling, picking and materials treat chunks like any
+/// other mesh. Neighbours at different levels don't share edge vertices; instead every chunk
+/// hangs a skirt down from its edges to hide the cracks. Normals come from the heightmap
+/// rather than the chunk's triangles, so they match across levels.
+pub struct Terrain {
+    pub settings: TerrainSettings,
+    dev: Arc<Device>,
+    heightmap: Heightmap,
+    position: Vec3,
+    nodes: Vec<Node>,
+}
+
+impl Terrain {
+    /// Spawns the chunk entities, all with `material`; give it a `Splat` for layered texturing.
+    /// Chunk uvs run 0..1 across the whole terrain.
+    pub fn new(dev: Arc<Device>, scene: &mut Scene, heightmap: Heightmap, settings: TerrainSettings, material: Material, position: Vec3) -> Self {
+        let mut terrain = Terrain { settings, dev, heightmap, position, nodes: Vec::new() };
+        terrain.build(scene, &material, Vec2::ZERO, 1.0, settings.levels.max(1) - 1);
+        terrain
+    }
+
+    fn build(&mut self, scene: &mut Scene, material: &Material, min: Vec2, side: f32, depth: u32) -> usize {
+        let index = self.nodes.len();
+        let entity = scene.spawn_empty(Mat4::from_translation(self.position), None);
+        scene.get_mut(entity).unwrap().material = material.clone();
+        let (low, high) = self.heightmap.range(min, min + side);
+        let s = &self.settings;
+        let corner = |uv: Vec2, h: f32| Vec3::new((uv.x - 0.5) * s.size, h * s.height, (uv.y - 0.5) * s.size);
+        let bounds = Aabb { min: corner(min, low) - Vec3::Y * s.skirt, max: corner(min + side, high) };
+        self.nodes.push(Node { min, side, children: None, bounds, entity, mesh: None });
+        if depth > 0 {
+            let half = side * 0.5;
+            let children = [Vec2::ZERO, Vec2::X, Vec2::Y, Vec2::ONE].map(|o| self.build(scene, material, min + o * half, half, depth - 1));
+            self.nodes[index].children = Some(children);
+        }
+        index
+    }
+
+    /// Picks the chunks to draw for `eye` and gives them their meshes. Call before `scene.update_bounds`.
+    pub fn update(&mut self, scene: &mut Scene, eye: Vec3) {
+        let eye = eye - self.position;
+        let mut selected = vec![false; self.nodes.len()];
+        let mut stack = vec![0];
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            let distance = (eye - eye.clamp(node.bounds.min, node.bounds.max)).length();
+            match node.children {
+                Some(children) if distance < self.settings.lod_distance * node.side * self.settings.size => stack.extend(children),
+                _ => selected[i] = true,
+            }
+        }
+        for i in 0..self.nodes.len() {
+            let mesh = if selected[i] { Some(self.mesh(i)) } else { None };
+            if let Some(entity) = scene.get_mut(self.nodes[i].entity) { entity.mesh = mesh; }
+        }
+    }
+
+    fn mesh(&mut self, i: usize) -> Arc<Mesh> {
+        if let Some(mesh) = &self.nodes[i].mesh { return mesh.clone(); }
+        let mesh = self.chunk_mesh(self.nodes[i].min, self.nodes[i].side);
+        self.nodes[i].mesh = Some(mesh.clone());
+        mesh
+    }
+
+    /// Central differences one heightmap texel apart, whatever the chunk's own spacing.
+    fn normal(&self, uv: Vec2) -> Vec3 {
+        let s = &self.settings;
+        let texel = Vec2::ONE / (Vec2::new(self.heightmap.size[0] as f32, self.heightmap.size[1] as f32) - 1.0);
+        let dx = (self.heightmap.sample(uv + Vec2::X * texel) - self.heightmap.sample(uv - Vec2::X * texel)) * s.height / (2.0 * texel.x * s.size);
+        let dz = (self.heightmap.sample(uv + Vec2::Y * texel) - self.heightmap.sample(uv - Vec2::Y * texel)) * s.height / (2.0 * texel.y * s.size);
+        Vec3::new(-dx, 1.0, -dz).normalize()
+    }
+
+    fn vertex(&self, uv: Vec2, drop: f32) -> Vertex {
+        let s = &self.settings;
+        let position = [(uv.x - 0.5) * s.size, self.heightmap.sample(uv) * s.height - drop, (uv.y - 0.5) * s.size];
+        Vertex { position, normal: self.normal(uv).into(), uv: uv.into(), tangent: [0.0; 4], lightmap_uv: [0.0; 2] }
+    }
+
+    fn chunk_mesh(&self, min: Vec2, side: f32) -> Arc<Mesh> {
+        let n = self.settings.chunk_cells.max(1);
+        let uv = |x: u32, z: u32| min + Vec2::new(x as f32, z as f32) * side / n as f32;
+        let mut vertices = Vec::with_capacity((n * n * 6 + n * 24) as usize);
+        for z in 0..n {
+            for x in 0..n {
+                let [a, b, c, d] = [uv(x, z), uv(x + 1, z), uv(x, z + 1), uv(x + 1, z + 1)].map(|p| self.vertex(p, 0.0));
+                vertices.extend([a, c, d, a, d, b]);
+            }
+        }
+        //a strip along each edge, facing out
+        let edges = [(Vec3::NEG_Z, (0..=n).map(|i| uv(i, 0)).collect::<Vec<_>>()), (Vec3::Z, (0..=n).map(|i| uv(i, n)).collect()),
+                     (Vec3::NEG_X, (0..=n).map(|i| uv(0, i)).collect()), (Vec3::X, (0..=n).map(|i| uv(n, i)).collect())];
+        for (outward, edge) in edges {
+            for pair in edge.windows(2) {
+                let (a, b) = (self.vertex(pair[0], 0.0), self.vertex(pair[1], 0.0));
+                let along = Vec3::from(b.position) - Vec3::from(a.position);
+                let (a, b) = if along.cross(Vec3::NEG_Y).dot(outward) > 0.0 { (a, b) } else { (b, a) };
+                let drop = |v: Vertex| Vertex { position: [v.position[0], v.position[1] - self.settings.skirt, v.position[2]], ..v };
+                vertices.extend([a, b, drop(a), b, drop(b), drop(a)]);
+            }
+        }
+        Mesh::new(self.dev.clone(), vertices)
+    }
+
+    /// Terrain height under a world position, None outside its square.
+    pub fn height(&self, world: Vec3) -> Option<f32> {
+        let uv = Vec2::new(world.x - self.position.x, world.z - self.position.z) / self.settings.size + 0.5;
+        if uv.min_element() < 0.0 || uv.max_element() > 1.0 { return None; }
+        Some(self.position.y + self.heightmap.sample(uv) * self.settings.height)
+    }
+
+    /// The chunk entities, coarsest first.
+    pub fn entities(&self) -> impl Iterator<Item = EntityId> + '_ { self.nodes.iter().map(|n| n.entity) }
+}
@@ -0,0 +1,288 @@
+use vulkano::device::Queue;
+use fontdue::{ Font as FontFace, FontSettings };
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use crate::texture::Texture;
+use crate::ui::{ Ui, UiQuad, SdfEffects, SdfQuad };
+use crate::text_layout::{ LaidGlyph, TextLayout };
+
+/// Width of the glyph atlas in pixels; it grows down as glyphs fill it.
+const ATLAS_WIDTH: u32 = 512;
+/// Between glyphs so linear filtering doesn't pick up the neighbours.
+const PADDING: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align { Left, Center, Right }
+
+#[derive(Clone, Copy, Debug)]
+pub struct TextStyle {
+    /// Pixels per em.
+    pub size: f32,
+    pub color: [f32; 4],
+    /// Of every line against the position's x.
+    pub align: Align,
+    /// Only distance field fonts have them.
+    pub effects: SdfEffects,
+}
+
+impl TextStyle {
+    pub fn new(size: f32) -> Self { TextStyle { size, color: [1.0; 4], align: Align::Left, effects: SdfEffects::default() } }
+}
+
+/// How far left of the position a line `width` wide starts.
+pub fn align(align: Align, width: f32) -> f32 { match align { Align::Left => 0.0, Align::Center => width * 0.5, Align::Right => width } }
+
+#[derive(Clone, Copy, Debug)]
+struct Glyph {
+    /// x, y, w, h in the atlas.
+    rect: [u32; 4],
+    /// Left edge from the pen and bottom edge above the baseline.
+    offset: [f32; 2],
+    advance: f32,
+}
+
+/// A TTF or OTF font and the atlas of the glyphs drawn with it so far, rasterized the first
+/// time they're asked for at a size.
+pub struct Font {
+    face: FontFace,
+    /// The file, for shaping.
+    data: Vec<u8>,
+    queue: Arc<Queue>,
+    /// By glyph index and pixel size.
+    glyphs: HashMap<(u16, u32), Glyph>,
+    /// White with coverage in alpha, ATLAS_WIDTH wide.
+    pixels: Vec<u8>,
+    height: u32,
+    /// Where the next glyph goes: x and y of the current shelf, and its height so far.
+    shelf: [u32; 3],
+    /// Remade when glyphs were added since; quads already out keep the old one, which has
+    /// everything they need.
+    texture: Option<Arc<Texture>>,
+}
+
+impl Font {
+    pub fn from_bytes(queue: Arc<Queue>, bytes: &[u8]) -> Result<Self, String> {
+        let face = FontFace::from_bytes(bytes, FontSettings::default()).map_err(|e| e.to_string())?;
+        Ok(Font { face, data: bytes.to_vec(), queue, glyphs: HashMap::new(), pixels: vec![0; (ATLAS_WIDTH * ATLAS_WIDTH * 4) as usize], height: ATLAS_WIDTH,
+                  shelf: [0, 0, 0], texture: None })
+    }
+
+    /// A .ttf or .otf; glyphs go into the atlas the first time they're drawn.
+    #[profiling::function]
+    pub fn load(queue: Arc<Queue>, path: impl AsRef<Path>) -> Self {
+        let bytes = std::fs::read(path.as_ref()).unwrap_or_else(|e| panic!("failed loading {:?}: {}", path.as_ref(), e));
+        Self::from_bytes(queue, &bytes).unwrap_or_else(|e| panic!("{:?} {}", path.as_ref(), e))
+    }
+
+    pub fn data(&self) -> &[u8] { &self.data }
+
+    /// Whether it draws `c` itself, rather than as the missing glyph box.
+    pub fn has_glyph(&self, c: char) -> bool { self.face.lookup_glyph_index(c) != 0 }
+
+    fn glyph(&mut self, c: char, size: f32) -> Glyph { self.glyph_indexed(self.face.lookup_glyph_index(c), size) }
+
+    fn glyph_indexed(&mut self, index: u16, size: f32) -> Glyph {
+        let key = (index, size.round() as u32);
+        if let Some(g) = self.glyphs.get(&key) { return *g; }
+        let (metrics, coverage) = self.face.rasterize_indexed(index, key.1 as f32);
+        let (w, h) = (metrics.width as u32, metrics.height as u32);
+        //next shelf when this one's full, a taller atlas when they all are
+        if self.shelf[0] + w + PADDING > ATLAS_WIDTH { self.shelf = [0, self.shelf[1] + self.shelf[2], 0]; }
+        while self.shelf[1] + h + PADDING > self.height {
+            self.height *= 2;
+            self.pixels.resize((ATLAS_WIDTH * self.height * 4) as usize, 0);
+        }
+        let [x, y, _] = self.shelf;
+        for row in 0..h {
+            for column in 0..w.min(ATLAS_WIDTH) {
+                let i = (((y + row) * ATLAS_WIDTH + x + column) * 4) as usize;
+                self.pixels[i..i + 4].copy_from_slice(&[255, 255, 255, coverage[(row * w + column) as usize]]);
+            }
+        }
+        self.shelf = [x + w + PADDING, y, self.shelf[2].max(h + PADDING)];
+        if w * h > 0 { self.texture = None; }
+        let glyph = Glyph { rect: [x, y, w, h], offset: [metrics.xmin as f32, metrics.ymin as f32], advance: metrics.advance_width };
+        self.glyphs.insert(key, glyph);
+        glyph
+    }
+
+    fn texture(&mut self) -> Arc<Texture> {
+        let (queue, pixels, height) = (&self.queue, &self.pixels, self.height);
+        self.texture.get_or_insert_with(|| Texture::from_rgba(queue.clone(), [ATLAS_WIDTH, height], pixels.clone())).clone()
+    }
+
+    /// Pixels from one baseline to the next.
+    pub fn line_height(&self, size: f32) -> f32 { self.face.horizontal_line_metrics(size).map_or(size * 1.2, |m| m.new_line_size) }
+
+    /// Pixels from the top of a line to its baseline.
+    pub fn ascent(&self, size: f32) -> f32 { self.face.horizontal_line_metrics(size).map_or(size, |m| m.ascent) }
+
+    /// Glyphs of one line with their pen positions, kerned.
+    fn line(&mut self, line: &str, size: f32) -> (Vec<(f32, Glyph)>, f32) {
+        let mut pen = 0.0;
+        let mut previous = None;
+        let glyphs = line.chars().map(|c| {
+            if let Some(p) = previous { pen += self.face.horizontal_kern(p, c, size).unwrap_or(0.0); }
+            previous = Some(c);
+            let g = self.glyph(c, size);
+            let at = pen;
+            pen += g.advance;
+            (at, g)
+        }).collect();
+        (glyphs, pen)
+    }
+
+    /// Width of the longest line and height of all of them, in pixels.
+    pub fn measure(&mut self, text: &str, size: f32) -> [f32; 2] {
+        let lines: Vec<f32> = text.lines().map(|l| self.line(l, size).1).collect();
+        [lines.iter().copied().fold(0.0, f32::max), lines.len() as f32 * self.line_height(size)]
+    }
+
+    /// Quads for `text` with its first line's top at `position`, in pixels from the top left
+    /// of the window. Lines break on newlines only.
+    pub fn quads(&mut self, text: &str, position: [f32; 2], style: &TextStyle) -> Vec<UiQuad> {
+        let lines: Vec<_> = text.lines().map(|l| self.line(l, style.size)).collect();
+        let texture = self.texture();
+        let atlas = [ATLAS_WIDTH as f32, self.height as f32];
+        let (ascent, line_height) = (self.ascent(style.size), self.line_height(style.size));
+        let mut quads = Vec::new();
+        for (n, (glyphs, width)) in lines.into_iter().enumerate() {
+            let left = position[0] - align(style.align, width);
+            let baseline = (position[1] + ascent + n as f32 * line_height).round();
+            for (pen, g) in glyphs.into_iter().filter(|(_, g)| g.rect[2] * g.rect[3] > 0) {
+                let [x, y, w, h] = g.rect.map(|v| v as f32);
+                //whole pixels keep the glyphs as sharp as they were rasterized
+                let rect = [(left + pen + g.offset[0]).round(), baseline - g.offset[1] - h, w, h];
+                quads.push(UiQuad { texture: texture.clone(), rect, uv: [x / atlas[0], y / atlas[1], (x + w) / atlas[0], (y + h) / atlas[1]],
+                                    color: style.color, sdf: None });
+            }
+        }
+        quads
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct SdfGlyph {
+    /// Ems.
+    advance: f32,
+    /// Left, bottom, right, top around the pen on the baseline in ems, y up; None for spaces.
+    plane: Option<[f32; 4]>,
+    uv: [f32; 4],
+}
+
+/// A distance field font baked ahead by msdf-atlas-gen, sdf, psdf, msdf or mtsdf: crisp at any
+/// size from one atlas, with outlines and glow from `TextStyle::effects`.
+pub struct SdfFont {
+    texture: Arc<Texture>,
+    glyphs: HashMap<char, SdfGlyph>,
+    kerning: HashMap<(char, char), f32>,
+    /// Atlas pixels per em, and the distance range in atlas pixels.
+    em_size: f32,
+    distance_range: f32,
+    /// Ems.
+    line_height: f32,
+    ascender: f32,
+}
+
+impl SdfFont {
+    /// msdf-atlas-gen's json and the atlas image it wrote with it, which is loaded linear.
+    pub fn parse(texture: Arc<Texture>, json: &str) -> Result<Self, String> {
+        let json: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let (atlas, metrics) = (&json["atlas"], &json["metrics"]);
+        let number = |v: &Value, what: &str| v.as_f64().map(|n| n as f32).ok_or_else(|| format!("missing {}", what));
+        let size = [number(&atlas["width"], "atlas.width")?, number(&atlas["height"], "atlas.height")?];
+        let flip = atlas["yOrigin"].as_str() != Some("top");
+        let bounds = |b: &Value| -> Option<[f32; 4]> { Some([b["left"].as_f64()? as f32, b["bottom"].as_f64()? as f32, b["right"].as_f64()? as f32, b["top"].as_f64()? as f32]) };
+        let mut glyphs = HashMap::new();
+        for g in json["glyphs"].as_array().ok_or("missing glyphs")? {
+            let c = match g["unicode"].as_u64().and_then(|u| char::from_u32(u as u32)) { Some(c) => c, None => continue };
+            let uv = bounds(&g["atlasBounds"]).map(|[l, b, r, t]| {
+                //bottom up atlases count from the last row
+                let (top, bottom) = if flip { (size[1] - t, size[1] - b) } else { (b, t) };
+                [l / size[0], top / size[1], r / size[0], bottom / size[1]]
+            });
+            glyphs.insert(c, SdfGlyph { advance: number(&g["advance"], "glyph advance")?, plane: bounds(&g["planeBounds"]).filter(|_| uv.is_some()),
+                                        uv: uv.unwrap_or_default() });
+        }
+        let kerning = json["kerning"].as_array().map_or(&[][..], |k| k).iter().filter_map(|k| {
+            let c = |key: &str| k[key].as_u64().and_then(|u| char::from_u32(u as u32));
+            Some(((c("unicode1")?, c("unicode2")?), k["advance"].as_f64()? as f32))
+        }).collect();
+        Ok(SdfFont { texture, glyphs, kerning, em_size: number(&atlas["size"], "atlas.size")?, distance_range: number(&atlas["distanceRange"], "atlas.distanceRange")?,
+                     line_height: number(&metrics["lineHeight"], "metrics.lineHeight")?, ascender: number(&metrics["ascender"], "metrics.ascender")? })
+    }
+
+    /// What msdf-atlas-gen wrote for `-json` and `-imageout`, with an msdf or mtsdf atlas type.
+    #[profiling::function]
+    pub fn load(queue: Arc<Queue>, json: impl AsRef<Path>, image: impl AsRef<Path>) -> Self {
+        let text = std::fs::read_to_string(json.as_ref()).unwrap_or_else(|e| panic!("failed loading {:?}: {}", json.as_ref(), e));
+        Self::parse(Texture::load_linear(queue, image), &text).unwrap_or_else(|e| panic!("{:?} {}", json.as_ref(), e))
+    }
+
+    /// Glyphs of one line with their pen positions, and its width, in ems.
+    fn line(&self, line: &str) -> (Vec<(f32, SdfGlyph)>, f32) {
+        let mut pen = 0.0;
+        let mut previous = None;
+        let glyphs = line.chars().filter_map(|c| {
+            if let Some(p) = previous { pen += self.kerning.get(&(p, c)).copied().unwrap_or(0.0); }
+            previous = Some(c);
+            let g = *self.glyphs.get(&c).or_else(|| self.glyphs.get(&'?'))?;
+            let at = pen;
+            pen += g.advance;
+            Some((at, g))
+        }).collect();
+        (glyphs, pen)
+    }
+
+    pub fn line_height(&self, size: f32) -> f32 { self.line_height * size }
+
+    /// Width of the longest line and height of all of them, in pixels.
+    pub fn measure(&self, text: &str, size: f32) -> [f32; 2] {
+        let width = text.lines().map(|l| self.line(l).1).fold(0.0, f32::max);
+        [width * size, text.lines().count() as f32 * self.line_height(size)]
+    }
+
+    /// Like `Font::quads`. Positions aren't snapped to pixels, there's nothing to keep sharp.
+    pub fn quads(&self, text: &str, position: [f32; 2], style: &TextStyle) -> Vec<UiQuad> {
+        let sdf = Some(SdfQuad { px_range: self.distance_range * style.size / self.em_size, effects: style.effects });
+        let mut quads = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let (glyphs, width) = self.line(line);
+            let left = position[0] - align(style.align, width * style.size);
+            let baseline = position[1] + (self.ascender + n as f32 * self.line_height) * style.size;
+            for (pen, g) in glyphs {
+                let [l, b, r, t] = match g.plane { Some(p) => p, None => continue };
+                let rect = [left + (pen + l) * style.size, baseline - t * style.size, (r - l) * style.size, (t - b) * style.size];
+                quads.push(UiQuad { texture: self.texture.clone(), rect, uv: g.uv, color: style.color, sdf });
+            }
+        }
+        quads
+    }
+}
+
+impl Ui {
+    pub fn text(&mut self, font: &mut Font, text: &str, position: [f32; 2], style: &TextStyle) {
+        for q in font.quads(text, position, style) { self.quad(q); }
+    }
+
+    pub fn sdf_text(&mut self, font: &SdfFont, text: &str, position: [f32; 2], style: &TextStyle) {
+        for q in font.quads(text, position, style) { self.quad(q); }
+    }
+
+    /// A laid out `layout` with its top left at `position`; `fonts` as it was laid out with.
+    pub fn text_layout(&mut self, fonts: &mut [Font], layout: &TextLayout, position: [f32; 2]) {
+        let glyphs: Vec<(usize, Glyph, &LaidGlyph)> = layout.glyphs().iter().map(|l| (l.font, fonts[l.font].glyph_indexed(l.glyph, l.size), l)).collect();
+        //after rasterizing everything, so each font's atlas is made once
+        let mut textures: Vec<Option<Arc<Texture>>> = vec![None; fonts.len()];
+        for (font, g, laid) in glyphs.into_iter().filter(|(_, g, _)| g.rect[2] * g.rect[3] > 0) {
+            let texture = textures[font].get_or_insert_with(|| fonts[font].texture()).clone();
+            let atlas = [ATLAS_WIDTH as f32, fonts[font].height as f32];
+            let [x, y, w, h] = g.rect.map(|v| v as f32);
+            let pen = [position[0] + laid.position[0], (position[1] + laid.position[1]).round()];
+            let rect = [(pen[0] + g.offset[0]).round(), pen[1] - g.offset[1] - h, w, h];
+            self.quad(UiQuad { texture, rect, uv: [x / atlas[0], y / atlas[1], (x + w) / atlas[0], (y + h) / atlas[1]], color: laid.color, sdf: None });
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use rustybuzz::{ Direction, Face, UnicodeBuffer };
+use unicode_bidi::BidiInfo;
+use unicode_linebreak::{ linebreaks, BreakOpportunity };
+use std::ops::Range;
+use crate::text::{ align, Align, Font };
+
+/// How one run of a `TextLayout` looks. Bold or italic is another font.
+#[derive(Clone, Copy, Debug)]
+pub struct SpanStyle {
+    /// Index into the fonts the layout is laid out and drawn with.
+    pub font: usize,
+    /// Pixels per em.
+    pub size: f32,
+    pub color: [f32; 4],
+}
+
+impl SpanStyle {
+    pub fn new(font: usize, size: f32) -> Self { SpanStyle { font, size, color: [1.0; 4] } }
+}
+
+/// One glyph where `layout` put it.
+#[derive(Clone, Copy, Debug)]
+pub struct LaidGlyph {
+    pub font: usize,
+    /// Index into the font, not a char; shaping can turn several chars into one or the reverse.
+    pub glyph: u16,
+    pub size: f32,
+    pub color: [f32; 4],
+    /// Pen on the baseline, pixels from the layout's top left.
+    pub position: [f32; 2],
+}
+
+/// Shaped, wrapped, mixed direction text in runs of their own style, for `Ui::text_layout`.
+/// Chars the span's font lacks come from the first of the others that has them, so put an
+/// emoji or symbol font last; color emoji (bitmap tables) aren't drawn.
+pub struct TextLayout {
+    /// Wraps at break opportunities past this many pixels; words longer than it overflow.
+    pub max_width: Option<f32>,
+    pub align: Align,
+    /// Times each line's own height.
+    pub line_spacing: f32,
+    text: String,
+    spans: Vec<(Range<usize>, SpanStyle)>,
+    glyphs: Vec<LaidGlyph>,
+    size: [f32; 2],
+}
+
+struct Shaped { glyph: u16, cluster: usize, advance: f32, offset: [f32; 2] }
+
+fn shape(font: &Font, text: &str, range: Range<usize>, size: f32, rtl: bool) -> Vec<Shaped> {
+    let face = match Face::from_slice(font.data(), 0) { Some(f) => f, None => return Vec::new() };
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(&text[range.clone()]);
+    buffer.guess_segment_properties();
+    buffer.set_direction(if rtl { Direction::RightToLeft } else { Direction::LeftToRight });
+    let shaped = rustybuzz::shape(&face, &[], buffer);
+    let scale = size / face.units_per_em() as f32;
+    shaped.glyph_infos().iter().zip(shaped.glyph_positions()).map(|(i, p)| Shaped {
+        glyph: i.glyph_id as u16, cluster: range.start + i.cluster as usize, advance: p.x_advance as f32 * scale,
+        offset: [p.x_offset as f32 * scale, p.y_offset as f32 * scale],
+    }).collect()
+}
+
+impl TextLayout {
+    pub fn new() -> Self {
+        TextLayout { max_width: None, align: Align::Left, line_spacing: 1.0, text: String::new(), spans: Vec::new(), glyphs: Vec::new(), size: [0.0; 2] }
+    }
+
+    /// Appends a run; `layout` again after.
+    pub fn push(&mut self, text: &str, style: SpanStyle) -> &mut Self {
+        let start = self.text.len();
+        self.text.push_str(text);
+        self.spans.push((start..self.text.len(), style));
+        self
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.spans.clear();
+        self.glyphs.clear();
+        self.size = [0.0; 2];
+    }
+
+    pub fn glyphs(&self) -> &[LaidGlyph] { &self.glyphs }
+
+    /// Width of the widest line and height of them all, once laid out.
+    pub fn size(&self) -> [f32; 2] { self.size }
+
+    /// Runs of the same span, font and direction in `range`, in logical order.
+    fn items(&self, fonts: &[Font], bidi: &BidiInfo, range: Range<usize>) -> Vec<(Range<usize>, usize, SpanStyle, bool)> {
+        let mut items: Vec<(Range<usize>, usize, SpanStyle, bool)> = Vec::new();
+        for (span_range, style) in self.spans.iter().filter(|(r, _)| r.start < range.end && r.end > range.start) {
+            let (start, end) = (span_range.start.max(range.start), span_range.end.min(range.end));
+            for (i, c) in self.text[start..end].char_indices() {
+                let at = start + i;
+                let rtl = bidi.levels[at].is_rtl();
+                //a fallback run keeps what it can draw, so joiners and variation selectors stay with their emoji
+                let previous = items.last().filter(|(r, _, _, _)| r.end == at && r.start >= start).map(|i| i.1);
+                let font = match previous {
+                    Some(f) if f != style.font && fonts[f].has_glyph(c) => f,
+                    _ if fonts[style.font].has_glyph(c) || c.is_whitespace() => style.font,
+                    _ => (0..fonts.len()).find(|&f| fonts[f].has_glyph(c)).unwrap_or(style.font),
+                };
+                match items.last_mut() {
+                    Some((r, f, s, d)) if r.end == at && *f == font && *d == rtl && s.size == style.size && s.color == style.color => r.end = at + c.len_utf8(),
+                    _ => items.push((at..at + c.len_utf8(), font, *style, rtl)),
+                }
+            }
+        }
+        items
+    }
+
+    /// Shapes, wraps and places every glyph. Again after changing the text, fonts or width.
+    pub fn layout(&mut self, fonts: &[Font]) {
+        self.glyphs.clear();
+        self.size = [0.0; 2];
+        if self.text.is_empty() || fonts.is_empty() { return; }
+        let text = self.text.clone();
+        let bidi = BidiInfo::new(&text, None);
+
+        //advance of every cluster, by its first byte, for wrapping before the lines are reordered
+        let mut advance = vec![0.0f32; text.len() + 1];
+        for (range, font, style, rtl) in self.items(fonts, &bidi, 0..text.len()) {
+            for s in shape(&fonts[font], &text, range, style.size, rtl) { advance[s.cluster] += s.advance; }
+        }
+        let width = |r: Range<usize>| -> f32 { let end = r.start + text[r.clone()].trim_end().len(); advance[r.start..end].iter().sum() };
+
+        let mut lines: Vec<Range<usize>> = Vec::new();
+        let (mut start, mut last) = (0, None);
+        for (at, kind) in linebreaks(&text) {
+            if let (Some(max), Some(ok)) = (self.max_width, last) {
+                if width(start..at) > max { lines.push(start..ok); start = ok; last = None; }
+            }
+            if kind == BreakOpportunity::Mandatory {
+                if at > start || at < text.len() { lines.push(start..at); }
+                start = at;
+                last = None;
+            } else { last = Some(at); }
+        }
+
+        let mut top = 0.0;
+        for line in lines {
+            let visible = line.start..line.start + text[line.clone()].trim_end().len();
+            //an empty line is as tall as the span it's in
+            let styles: Vec<SpanStyle> = self.spans.iter().filter(|(r, _)| r.start < line.end.max(line.start + 1) && r.end > line.start).map(|s| s.1).collect();
+            let styles = if styles.is_empty() { vec![self.spans.last().unwrap().1] } else { styles };
+            let ascent = styles.iter().map(|s| fonts[s.font].ascent(s.size)).fold(0.0, f32::max);
+            let height = styles.iter().map(|s| fonts[s.font].line_height(s.size)).fold(0.0, f32::max) * self.line_spacing;
+            let baseline = top + ascent;
+            let first = self.glyphs.len();
+            let mut pen = 0.0;
+            if !visible.is_empty() {
+                let paragraph = bidi.paragraphs.iter().find(|p| p.range.contains(&visible.start)).unwrap();
+                let (levels, runs) = bidi.visual_runs(paragraph, visible.clone());
+                for run in runs {
+                    let rtl = levels[run.start].is_rtl();
+                    let mut items = self.items(fonts, &bidi, run);
+                    if rtl { items.reverse(); }
+                    for (range, font, style, _) in items {
+                        for s in shape(&fonts[font], &text, range, style.size, rtl) {
+                            self.glyphs.push(LaidGlyph { font, glyph: s.glyph, size: style.size, color: style.color,
+                                                         position: [pen + s.offset[0], baseline - s.offset[1]] });
+                            pen += s.advance;
+                        }
+                    }
+                }
+            }
+            let shift = match self.max_width { Some(max) => align(self.align, pen) - align(self.align, max), None => align(self.align, pen) };
+            for g in &mut self.glyphs[first..] { g.position[0] -= shift; }
+            self.size = [self.size[0].max(pen), top + height];
+            top += height;
+        }
+    }
+}
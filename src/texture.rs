@@ -0,0 +1,66 @@
+use vulkano::{ device::Queue,
+               image::{ ImmutableImage, ImageDimensions, MipmapsCount, view::{ ImageView, ImageViewAbstract } },
+               format::Format,
+               sync::GpuFuture };
+use std::sync::Arc;
+use std::path::Path;
+use crate::gpu_debug;
+use crate::gpu_memory::{ self, MemoryCategory };
+
+/// Any sampled image, loaded from disk or rendered by the engine.
+pub struct Texture {
+    pub view: Arc<dyn ImageViewAbstract>,
+    pub extent: [u32; 2],
+}
+
+impl Texture {
+    /// Srgb encoded color, decoded to linear by the sampler. Blocks until the upload finished,
+    /// fine for load time.
+    pub fn from_rgba(queue: Arc<Queue>, extent: [u32; 2], data: Vec<u8>) -> Arc<Texture> {
+        Self::from_rgba_format(queue, extent, data, Format::R8G8B8A8_SRGB)
+    }
+
+    pub fn from_rgba_format(queue: Arc<Queue>, extent: [u32; 2], data: Vec<u8>, format: Format) -> Arc<Texture> {
+        let bytes = data.len() as u64;
+        let (image, future) = ImmutableImage::from_iter(data.into_iter(),
+            ImageDimensions::Dim2d { width: extent[0], height: extent[1], array_layers: 1 },
+            MipmapsCount::One, format, queue).expect("failed texture upload");
+        future.then_signal_fence_and_flush().unwrap().wait(None).unwrap();
+        let view = ImageView::new_default(image).unwrap();
+        gpu_memory::track(MemoryCategory::Textures, &view, bytes);
+        Arc::new(Texture { view, extent })
+    }
+
+    /// Srgb color: base color, emissive, splat layers. Data textures, normals and
+    /// metallic-roughness, have to use `load_linear` instead.
+    #[profiling::function]
+    pub fn load(queue: Arc<Queue>, path: impl AsRef<Path>) -> Arc<Texture> {
+        let img = image::open(path.as_ref()).unwrap_or_else(|e| panic!("failed loading {:?}: {}", path.as_ref(), e)).to_rgba8();
+        let extent = [img.width(), img.height()];
+        Self::from_rgba(queue, extent, img.into_raw()).named(path.as_ref())
+    }
+
+    /// For data rather than color, normal maps and such, which must not be srgb decoded.
+    #[profiling::function]
+    pub fn load_linear(queue: Arc<Queue>, path: impl AsRef<Path>) -> Arc<Texture> {
+        let img = image::open(path.as_ref()).unwrap_or_else(|e| panic!("failed loading {:?}: {}", path.as_ref(), e)).to_rgba8();
+        let extent = [img.width(), img.height()];
+        Self::from_rgba_format(queue, extent, img.into_raw(), Format::R8G8B8A8_UNORM).named(path.as_ref())
+    }
+
+    /// Float rgba, for hdr panoramas and other data outside 0..1.
+    #[profiling::function]
+    pub fn load_hdr(queue: Arc<Queue>, path: impl AsRef<Path>) -> Arc<Texture> {
+        let img = image::open(path.as_ref()).unwrap_or_else(|e| panic!("failed loading {:?}: {}", path.as_ref(), e)).to_rgba32f();
+        let extent = [img.width(), img.height()];
+        Self::from_rgba_format(queue, extent, bytemuck::cast_slice(img.as_raw()).to_vec(), Format::R32G32B32A32_SFLOAT).named(path.as_ref())
+    }
+
+    pub fn white(queue: Arc<Queue>) -> Arc<Texture> { Self::from_rgba(queue, [1, 1], vec![255; 4]) }
+
+    /// The image named after the file it came from, for graphics debuggers.
+    fn named(self: Arc<Self>, path: &Path) -> Arc<Self> {
+        gpu_debug::name(&**self.view.image().inner().image, &path.to_string_lossy());
+        self
+    }
+}
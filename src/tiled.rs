@@ -0,0 +1,203 @@
+use roxmltree::{ Document, Node };
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{ Path, PathBuf };
+
+/// Flip bits Tiled keeps in the top of a gid.
+pub const FLIP_HORIZONTAL: u32 = 0x8000_0000;
+pub const FLIP_VERTICAL: u32 = 0x4000_0000;
+pub const FLIP_DIAGONAL: u32 = 0x2000_0000;
+/// Hexagonal maps' 120 degree rotation, cleared along with the flips.
+const ROTATE_HEX: u32 = 0x1000_0000;
+pub const GID_MASK: u32 = !(FLIP_HORIZONTAL | FLIP_VERTICAL | FLIP_DIAGONAL | ROTATE_HEX);
+
+/// One image cut into a grid of tiles.
+#[derive(Clone, Debug)]
+pub struct Tileset {
+    /// Gid of its tile 0 in the map.
+    pub first_gid: u32,
+    pub image: PathBuf,
+    pub image_size: [u32; 2],
+    pub tile_size: [u32; 2],
+    pub columns: u32,
+    pub count: u32,
+    pub margin: u32,
+    pub spacing: u32,
+    /// Frames of animated tiles by tile id, as tile id and seconds.
+    pub animations: HashMap<u32, Vec<(u32, f32)>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TileLayer {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    /// Gids row by row from the top left with their flip bits, 0 for empty.
+    pub tiles: Vec<u32>,
+    pub visible: bool,
+    pub opacity: f32,
+    /// Pixels, y down.
+    pub offset: [f32; 2],
+}
+
+/// An orthogonal, finite Tiled map. Groups are flattened into their layers; object and image
+/// layers are skipped.
+#[derive(Clone, Debug)]
+pub struct TileMap {
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: [u32; 2],
+    pub tilesets: Vec<Tileset>,
+    /// Back to front.
+    pub layers: Vec<TileLayer>,
+}
+
+impl TileMap {
+    /// The tileset a gid comes from, and its id in there.
+    pub fn tile(&self, gid: u32) -> Option<(usize, u32)> {
+        let gid = gid & GID_MASK;
+        if gid == 0 { return None; }
+        let set = self.tilesets.iter().rposition(|t| t.first_gid <= gid)?;
+        let id = gid - self.tilesets[set].first_gid;
+        (id < self.tilesets[set].count).then(|| (set, id))
+    }
+}
+
+fn attribute<T: std::str::FromStr>(node: Node, name: &str) -> Result<T, String> {
+    let value = node.attribute(name).ok_or_else(|| format!("<{}> without {}", node.tag_name().name(), name))?;
+    value.parse().map_err(|_| format!("<{}> has bad {} {:?}", node.tag_name().name(), name, value))
+}
+
+fn attribute_or<T: std::str::FromStr>(node: Node, name: &str, default: T) -> Result<T, String> {
+    if node.has_attribute(name) { attribute(node, name) } else { Ok(default) }
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+    let mut bytes = Vec::new();
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=') {
+        bits = bits << 6 | value(c).ok_or("bad base64")? as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+fn tile_data(data: Node) -> Result<Vec<u32>, String> {
+    if data.has_attribute("compression") { return Err("compressed layers aren't supported, save them uncompressed".into()); }
+    let text = data.text().unwrap_or("");
+    match data.attribute("encoding") {
+        Some("csv") => text.split(',').map(|t| t.trim()).filter(|t| !t.is_empty())
+            .map(|t| t.parse::<u32>().map_err(|_| format!("bad tile {:?}", t))).collect(),
+        Some("base64") => Ok(decode_base64(text)?.chunks_exact(4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()),
+        None => data.children().filter(|n| n.has_tag_name("tile")).map(|n| attribute_or(n, "gid", 0)).collect(),
+        Some(e) => Err(format!("unknown encoding {:?}", e)),
+    }
+}
+
+/// An embedded <tileset> or the root of a .tsx, images relative to `dir`.
+fn tileset_xml(node: Node, first_gid: u32, dir: &Path) -> Result<Tileset, String> {
+    let image = node.children().find(|n| n.has_tag_name("image")).ok_or("tilesets of separate images aren't supported")?;
+    let mut animations = HashMap::new();
+    for tile in node.children().filter(|n| n.has_tag_name("tile")) {
+        if let Some(animation) = tile.children().find(|n| n.has_tag_name("animation")) {
+            let frames = animation.children().filter(|n| n.has_tag_name("frame"))
+                .map(|f| Ok((attribute(f, "tileid")?, attribute::<f32>(f, "duration")? / 1000.0))).collect::<Result<Vec<_>, String>>()?;
+            animations.insert(attribute(tile, "id")?, frames);
+        }
+    }
+    Ok(Tileset { first_gid, image: dir.join(attribute::<String>(image, "source")?), image_size: [attribute(image, "width")?, attribute(image, "height")?],
+                 tile_size: [attribute(node, "tilewidth")?, attribute(node, "tileheight")?], columns: attribute(node, "columns")?,
+                 count: attribute(node, "tilecount")?, margin: attribute_or(node, "margin", 0)?, spacing: attribute_or(node, "spacing", 0)?, animations })
+}
+
+/// A .tsj, images relative to `dir`.
+fn tileset_json(text: &str, first_gid: u32, dir: &Path) -> Result<Tileset, String> {
+    let json: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let number = |key: &str| json[key].as_u64().map(|n| n as u32).ok_or_else(|| format!("missing {}", key));
+    let image = json["image"].as_str().ok_or("tilesets of separate images aren't supported")?;
+    let mut animations = HashMap::new();
+    for tile in json["tiles"].as_array().map_or(&[][..], |t| t) {
+        if let (Some(id), Some(frames)) = (tile["id"].as_u64(), tile["animation"].as_array()) {
+            let frames = frames.iter().map(|f| match (f["tileid"].as_u64(), f["duration"].as_f64()) {
+                (Some(t), Some(d)) => Ok((t as u32, d as f32 / 1000.0)),
+                _ => Err(format!("tile {} has a bad animation frame", id)),
+            }).collect::<Result<Vec<_>, String>>()?;
+            animations.insert(id as u32, frames);
+        }
+    }
+    Ok(Tileset { first_gid, image: dir.join(image), image_size: [number("imagewidth")?, number("imageheight")?],
+                 tile_size: [number("tilewidth")?, number("tileheight")?], columns: number("columns")?, count: number("tilecount")?,
+                 margin: number("margin").unwrap_or(0), spacing: number("spacing").unwrap_or(0), animations })
+}
+
+fn layers(node: Node, offset: [f32; 2], opacity: f32, visible: bool, out: &mut Vec<TileLayer>) -> Result<(), String> {
+    for child in node.children().filter(|n| n.is_element()) {
+        //groups pass their offset, opacity and visibility down
+        let offset = [offset[0] + attribute_or(child, "offsetx", 0.0)?, offset[1] + attribute_or(child, "offsety", 0.0)?];
+        let opacity = opacity * attribute_or(child, "opacity", 1.0)?;
+        let visible = visible && attribute_or(child, "visible", 1u32)? != 0;
+        match child.tag_name().name() {
+            "group" => layers(child, offset, opacity, visible, out)?,
+            "layer" => {
+                let data = child.children().find(|n| n.has_tag_name("data")).ok_or("<layer> without <data>")?;
+                if data.children().any(|n| n.has_tag_name("chunk")) { return Err("infinite maps aren't supported".into()); }
+                let (width, height): (u32, u32) = (attribute(child, "width")?, attribute(child, "height")?);
+                let tiles = tile_data(data)?;
+                if tiles.len() != (width * height) as usize { return Err(format!("layer {:?} has {} tiles for {}x{}", child.attribute("name"), tiles.len(), width, height)); }
+                out.push(TileLayer { name: child.attribute("name").unwrap_or("").to_string(), width, height, tiles, visible, opacity, offset });
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// A .tmx; external tilesets, .tsx or .tsj, and images are found relative to `dir`.
+pub fn parse_tmx(text: &str, dir: &Path) -> Result<TileMap, String> {
+    let doc = Document::parse(text).map_err(|e| e.to_string())?;
+    let map = doc.root_element();
+    if !map.has_tag_name("map") { return Err("not a map".into()); }
+    if map.attribute("orientation") != Some("orthogonal") { return Err(format!("{:?} maps aren't supported", map.attribute("orientation"))); }
+    if attribute_or(map, "infinite", 0u32)? != 0 { return Err("infinite maps aren't supported".into()); }
+    let mut tilesets = Vec::new();
+    for node in map.children().filter(|n| n.has_tag_name("tileset")) {
+        let first_gid = attribute(node, "firstgid")?;
+        tilesets.push(match node.attribute("source") {
+            Some(source) => {
+                let path = dir.join(source);
+                let text = std::fs::read_to_string(&path).map_err(|e| format!("{:?} {}", path, e))?;
+                let dir = path.parent().unwrap_or(dir);
+                if path.extension().map_or(false, |e| e == "tsj" || e == "json") { tileset_json(&text, first_gid, dir)? } else {
+                    let doc = Document::parse(&text).map_err(|e| format!("{:?} {}", path, e))?;
+                    tileset_xml(doc.root_element(), first_gid, dir)?
+                }
+            }
+            None => tileset_xml(node, first_gid, dir)?,
+        });
+    }
+    tilesets.sort_by_key(|t| t.first_gid);
+    let mut out = Vec::new();
+    layers(map, [0.0, 0.0], 1.0, true, &mut out)?;
+    Ok(TileMap { width: attribute(map, "width")?, height: attribute(map, "height")?, tile_size: [attribute(map, "tilewidth")?, attribute(map, "tileheight")?],
+                 tilesets, layers: out })
+}
+
+/// A .tmx on disk, its external tilesets and images where the map's relative paths point.
+#[profiling::function]
+pub fn load_tmx(path: impl AsRef<Path>) -> TileMap {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("failed loading {:?}: {}", path, e));
+    parse_tmx(&text, path.parent().unwrap_or(Path::new(""))).unwrap_or_else(|e| panic!("{:?} {}", path, e))
+}
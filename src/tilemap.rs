@@ -0,0 +1,224 @@
+use vulkano::{ device::{ Device, Queue },
+               buffer::{ BufferUsage, CpuAccessibleBuffer, CpuBufferPool },
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               render_pass::Subpass,
+               sampler::{ Filter, Sampler, SamplerAddressMode, SamplerCreateInfo },
+               pipeline::{ GraphicsPipeline, Pipeline, PipelineBindPoint, graphics::{ input_assembly::InputAssemblyState, vertex_input::BuffersDefinition,
+                                                                                      viewport::ViewportState, color_blend::ColorBlendState } },
+               impl_vertex };
+use bytemuck::{ Pod, Zeroable };
+use glam::{ Mat4, Vec3 };
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::bvh::Aabb;
+use crate::camera::View;
+use crate::culling::Frustum;
+use crate::texture::Texture;
+use crate::tiled::{ TileMap, FLIP_HORIZONTAL, FLIP_VERTICAL, FLIP_DIAGONAL };
+use crate::time::Time;
+use crate::renderer::DrawStats;
+
+/// Cells a side per chunk.
+const CHUNK: u32 = 16;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+struct TileVertex { position: [f32; 3], corner: [f32; 2], tile: u32, flags: u32 }
+impl_vertex!(TileVertex, position, corner, tile, flags);
+
+/// The tiles of one layer from one tileset in one chunk of the map.
+struct Chunk {
+    layer: usize,
+    tileset: usize,
+    /// Map space.
+    bounds: Aabb,
+    vertices: Arc<CpuAccessibleBuffer<[TileVertex]>>,
+}
+
+/// A Tiled map on the gpu. Map space has a pixel per unit, x right and y up from the top left
+/// corner; `transform` places it in the world.
+pub struct Tilemap {
+    pub map: TileMap,
+    pub transform: Mat4,
+    textures: Vec<Arc<Texture>>,
+    chunks: Vec<Chunk>,
+    /// The tile every tile shows right now, by tileset; animated ones move along.
+    frames: Vec<Vec<u32>>,
+    clock: f32,
+}
+
+impl Tilemap {
+    /// A tile a world unit wide at the origin. Panics on tileset images it can't load.
+    pub fn new(dev: Arc<Device>, queue: Arc<Queue>, map: TileMap) -> Self {
+        let textures = map.tilesets.iter().map(|t| Texture::load(queue.clone(), &t.image)).collect();
+        let frames = map.tilesets.iter().map(|t| (0..t.count).collect()).collect();
+        let transform = Mat4::from_scale(Vec3::splat(1.0 / map.tile_size[0] as f32));
+        let mut tilemap = Tilemap { map, transform, textures, chunks: Vec::new(), frames, clock: 0.0 };
+        tilemap.rebuild(dev);
+        tilemap
+    }
+
+    /// Remakes the chunks from `map`, after its tiles change.
+    pub fn rebuild(&mut self, dev: Arc<Device>) {
+        const CORNERS: [[f32; 2]; 6] = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let map = &self.map;
+        let mut chunks: HashMap<(usize, [u32; 2], usize), Vec<TileVertex>> = HashMap::new();
+        for (l, layer) in map.layers.iter().enumerate() {
+            for (i, &gid) in layer.tiles.iter().enumerate() {
+                let (set, tile) = match map.tile(gid) { Some(t) => t, None => continue };
+                let (x, y) = (i as u32 % layer.width, i as u32 / layer.width);
+                let size = map.tilesets[set].tile_size.map(|s| s as f32);
+                //tiles bigger than a cell stick out up and right from its bottom left, like in Tiled
+                let left = (x * map.tile_size[0]) as f32 + layer.offset[0];
+                let top = ((y + 1) * map.tile_size[1]) as f32 + layer.offset[1] - size[1];
+                let flags = (gid & FLIP_HORIZONTAL != 0) as u32 | ((gid & FLIP_VERTICAL != 0) as u32) << 1 | ((gid & FLIP_DIAGONAL != 0) as u32) << 2;
+                let vertices = chunks.entry((l, [x / CHUNK, y / CHUNK], set)).or_default();
+                vertices.extend(CORNERS.iter().map(|&c| TileVertex {
+                    position: [left + c[0] * size[0], -(top + c[1] * size[1]), 0.0], corner: c, tile, flags }));
+            }
+        }
+        let mut keys: Vec<_> = chunks.keys().copied().collect();
+        keys.sort();
+        self.chunks = keys.into_iter().map(|key| {
+            let vertices = &chunks[&key];
+            let bounds = Aabb::from_points(vertices.iter().map(|v| Vec3::from(v.position)));
+            let vertices = CpuAccessibleBuffer::from_iter(dev.clone(), BufferUsage::vertex_buffer(), false, vertices.iter().cloned()).unwrap();
+            Chunk { layer: key.0, tileset: key.2, bounds, vertices }
+        }).collect();
+    }
+
+    /// Moves animated tiles along on the scaled clock. Once a frame.
+    pub fn update(&mut self, time: &Time) {
+        self.clock += time.delta;
+        for (set, frames) in self.map.tilesets.iter().zip(&mut self.frames) {
+            for (&tile, animation) in &set.animations {
+                let total: f32 = animation.iter().map(|f| f.1).sum();
+                if total <= 0.0 || tile >= set.count { continue; }
+                let mut t = self.clock % total;
+                frames[tile as usize] = animation.iter().find(|f| { t -= f.1; t < 0.0 }).map_or(tile, |f| f.0);
+            }
+        }
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			layout(location = 0) in vec3 position;
+			layout(location = 1) in vec2 corner;
+			layout(location = 2) in uint tile;
+			layout(location = 3) in uint flags;
+
+			layout(set = 0, binding = 1) readonly buffer Frames { uint frames[]; };
+
+			layout(location = 0) out vec2 v_uv;
+			layout(location = 1) flat out float v_opacity;
+
+			layout(push_constant) uniform PushConstants {
+				mat4 mvp;
+				vec4 grid;    //uv of tile 0's corner, uv from one tile to the next
+				vec2 size;    //one tile in uv
+				uint columns;
+				float opacity;
+			} pc;
+
+			void main() {
+				gl_Position = pc.mvp * vec4(position, 1.0);
+				uint t = frames[tile];
+				//undoes Tiled's diagonal, horizontal then vertical flip
+				vec2 c = corner;
+				if ((flags & 2u) != 0u) c.y = 1.0 - c.y;
+				if ((flags & 1u) != 0u) c.x = 1.0 - c.x;
+				if ((flags & 4u) != 0u) c = c.yx;
+				v_uv = pc.grid.xy + vec2(t % pc.columns, t / pc.columns) * pc.grid.zw + c * pc.size;
+				v_opacity = pc.opacity;
+			}"
+    }
+}
+mod fs {
+    vulkano_shaders::shader! { ty: "fragment",
+    src: "#version 450
+
+			layout(set = 0, binding = 0) uniform sampler2D u_texture;
+
+			layout(location = 0) in vec2 v_uv;
+			layout(location = 1) flat in float v_opacity;
+			layout(location = 0) out vec4 f_color;
+
+			void main() {
+				f_color = texture(u_texture, v_uv);
+				f_color.a *= v_opacity;
+			}"
+    }
+}
+
+/// Draws the scene's tilemaps layer by layer in the transparent subpass, before billboards,
+/// skipping chunks outside the camera. There is no depth test, maps are backdrops for 2d scenes
+/// under an orthographic camera.
+pub struct Tilemaps {
+    pipeline: Arc<GraphicsPipeline>,
+    pool: CpuBufferPool<u32>,
+    sampler: Arc<Sampler>,
+}
+
+impl Tilemaps {
+    pub fn new(dev: Arc<Device>, subpass: Subpass) -> Self {
+        let vs = vs::load(dev.clone()).unwrap();
+        let fs = fs::load(dev.clone()).unwrap();
+        let pipeline = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new().vertex::<TileVertex>())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .color_blend_state(ColorBlendState::new(subpass.num_color_attachments()).blend_alpha())
+            .render_pass(subpass)
+            .build(dev.clone()).unwrap();
+        //pixel art stays crisp, and neighbouring tiles don't bleed in
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo { mag_filter: Filter::Nearest, min_filter: Filter::Nearest,
+                                                                    address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        Tilemaps { pipeline, pool: CpuBufferPool::new(dev, BufferUsage::storage_buffer()), sampler }
+    }
+
+    pub fn draw(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, tilemaps: &[Tilemap], view: &View) -> DrawStats {
+        let mut stats = DrawStats::default();
+        if tilemaps.is_empty() { return stats; }
+        let layout = self.pipeline.layout().set_layouts().get(0).unwrap();
+        let view_proj = view.view_proj();
+        let frustum = Frustum::from_view_proj(&view_proj);
+        builder.bind_pipeline_graphics(self.pipeline.clone());
+        for tilemap in tilemaps {
+            let mut sets = vec![None; tilemap.map.tilesets.len()];
+            //chunks come sorted by layer, so this draws back to front
+            for chunk in &tilemap.chunks {
+                let layer = &tilemap.map.layers[chunk.layer];
+                if !layer.visible || !frustum.intersects_aabb(&chunk.bounds.transformed(&tilemap.transform)) { continue; }
+                let set = sets[chunk.tileset].get_or_insert_with(|| {
+                    let frames = self.pool.chunk(tilemap.frames[chunk.tileset].iter().copied()).unwrap();
+                    PersistentDescriptorSet::new(layout.clone(), [
+                        WriteDescriptorSet::image_view_sampler(0, tilemap.textures[chunk.tileset].view.clone(), self.sampler.clone()),
+                        WriteDescriptorSet::buffer(1, frames),
+                    ]).unwrap()
+                }).clone();
+                let t = &tilemap.map.tilesets[chunk.tileset];
+                let image = t.image_size.map(|s| s as f32);
+                let pc = vs::ty::PushConstants {
+                    mvp: (view_proj * tilemap.transform).to_cols_array_2d(),
+                    grid: [t.margin as f32 / image[0], t.margin as f32 / image[1],
+                           (t.tile_size[0] + t.spacing) as f32 / image[0], (t.tile_size[1] + t.spacing) as f32 / image[1]],
+                    size: [t.tile_size[0] as f32 / image[0], t.tile_size[1] as f32 / image[1]],
+                    columns: t.columns.max(1),
+                    opacity: layer.opacity,
+                };
+                builder.push_constants(self.pipeline.layout().clone(), 0, pc)
+                    .bind_descriptor_sets(PipelineBindPoint::Graphics, self.pipeline.layout().clone(), 0, set)
+                    .bind_vertex_buffers(0, chunk.vertices.clone())
+                    .draw(chunk.vertices.len() as u32, 1, 0, 0).unwrap();
+                stats.add(1, chunk.vertices.len() as u64 / 3);
+            }
+        }
+        stats
+    }
+}
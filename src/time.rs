@@ -0,0 +1,37 @@
+use std::time::Instant;
+
+/// Frame clock. `tick` once at the top of every frame; everything that animates reads `delta`
+/// from here so pausing and slow motion work everywhere at once.
+pub struct Time {
+    last: Instant,
+    /// Scaled seconds since the previous tick.
+    pub delta: f32,
+    /// The same unscaled, for whatever keeps going while paused, like the camera.
+    pub real_delta: f32,
+    /// Scaled seconds since start.
+    pub elapsed: f64,
+    /// 0 pauses, 1 is real time.
+    pub scale: f32,
+    pub frame: u64,
+}
+
+impl Time {
+    pub fn new() -> Self { Time { last: Instant::now(), delta: 0.0, real_delta: 0.0, elapsed: 0.0, scale: 1.0, frame: 0 } }
+
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        //clamped so a breakpoint or a window drag doesn't fling everything forward
+        let real = now.duration_since(self.last).as_secs_f32().min(0.25);
+        self.tick_fixed(real);
+    }
+
+    /// `tick` as if exactly `real` seconds went by, for runs that have to play out the same
+    /// every time.
+    pub fn tick_fixed(&mut self, real: f32) {
+        self.last = Instant::now();
+        self.real_delta = real;
+        self.delta = real * self.scale;
+        self.elapsed += self.delta as f64;
+        self.frame += 1;
+    }
+}
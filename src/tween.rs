@@ -0,0 +1,308 @@
+use glam::{ Mat4, Quat, Vec2, Vec3, Vec4 };
+use std::f32::consts::PI;
+use std::fmt::Write as _;
+use crate::camera::{ Camera, Projection };
+use crate::scene::{ Scene, EntityId };
+
+/// Shape of the progress from one key to the next.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ease {
+    Linear,
+    /// Holds the previous value until the key.
+    Step,
+    QuadIn, QuadOut, QuadInOut,
+    CubicIn, CubicOut, CubicInOut,
+    SineInOut,
+    ExpoOut,
+    /// Overshoots a little and settles back.
+    BackOut,
+    ElasticOut,
+    BounceOut,
+}
+
+impl Ease {
+    pub const ALL: [Ease; 13] = [Ease::Linear, Ease::Step, Ease::QuadIn, Ease::QuadOut, Ease::QuadInOut, Ease::CubicIn, Ease::CubicOut,
+                                 Ease::CubicInOut, Ease::SineInOut, Ease::ExpoOut, Ease::BackOut, Ease::ElasticOut, Ease::BounceOut];
+
+    /// By its name as `Debug` prints it.
+    pub fn from_name(name: &str) -> Option<Ease> { Ease::ALL.into_iter().find(|e| format!("{:?}", e) == name) }
+
+    /// `t` in 0..1 to eased progress, 0 at 0 and 1 at 1.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Ease::Linear => t,
+            Ease::Step => if t < 1.0 { 0.0 } else { 1.0 },
+            Ease::QuadIn => t * t,
+            Ease::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Ease::QuadInOut => if t < 0.5 { 2.0 * t * t } else { 1.0 - (-2.0 * t + 2.0).powi(2) / 2.0 },
+            Ease::CubicIn => t * t * t,
+            Ease::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Ease::CubicInOut => if t < 0.5 { 4.0 * t * t * t } else { 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0 },
+            Ease::SineInOut => -((PI * t).cos() - 1.0) / 2.0,
+            Ease::ExpoOut => if t >= 1.0 { 1.0 } else { 1.0 - 2f32.powf(-10.0 * t) },
+            Ease::BackOut => {
+                let (c1, c3) = (1.70158, 2.70158);
+                1.0 + c3 * (t - 1.0).powi(3) + c1 * (t - 1.0).powi(2)
+            }
+            Ease::ElasticOut => {
+                if t <= 0.0 || t >= 1.0 { return t; }
+                2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * 2.0 * PI / 3.0).sin() + 1.0
+            }
+            Ease::BounceOut => {
+                let (n, d) = (7.5625, 2.75);
+                if t < 1.0 / d { n * t * t }
+                else if t < 2.0 / d { let t = t - 1.5 / d; n * t * t + 0.75 }
+                else if t < 2.5 / d { let t = t - 2.25 / d; n * t * t + 0.9375 }
+                else { let t = t - 2.625 / d; n * t * t + 0.984375 }
+            }
+        }
+    }
+}
+
+/// Values a curve can interpolate. `t` can leave 0..1 for eases that overshoot.
+pub trait Lerp: Clone {
+    fn lerp(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 { fn lerp(&self, other: &Self, t: f32) -> Self { self + (other - self) * t } }
+impl Lerp for Vec2 { fn lerp(&self, other: &Self, t: f32) -> Self { Vec2::lerp(*self, *other, t) } }
+impl Lerp for Vec3 { fn lerp(&self, other: &Self, t: f32) -> Self { Vec3::lerp(*self, *other, t) } }
+impl Lerp for Vec4 { fn lerp(&self, other: &Self, t: f32) -> Self { Vec4::lerp(*self, *other, t) } }
+/// The shorter way round.
+impl Lerp for Quat { fn lerp(&self, other: &Self, t: f32) -> Self { self.slerp(*other, t) } }
+impl<const N: usize> Lerp for [f32; N] {
+    fn lerp(&self, other: &Self, t: f32) -> Self { std::array::from_fn(|i| self[i] + (other[i] - self[i]) * t) }
+}
+/// Decomposed into scale, rotation and translation, so rotations don't shrink halfway.
+impl Lerp for Mat4 {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        let (s0, r0, t0) = self.to_scale_rotation_translation();
+        let (s1, r1, t1) = other.to_scale_rotation_translation();
+        Mat4::from_scale_rotation_translation(s0.lerp(s1, t), r0.slerp(r1, t), t0.lerp(t1, t))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Key<T> {
+    /// Seconds.
+    pub time: f32,
+    pub value: T,
+    /// How the curve gets here from the key before.
+    pub ease: Ease,
+}
+
+/// Keyed values over time. Holds the first key's value before it and the last's after.
+#[derive(Clone, Debug)]
+pub struct Curve<T> {
+    /// Ascending by time.
+    pub keys: Vec<Key<T>>,
+}
+
+impl<T: Lerp> Curve<T> {
+    /// Starts at `value` at time 0.
+    pub fn new(value: T) -> Self { Curve { keys: vec![Key { time: 0.0, value, ease: Ease::Linear }] } }
+
+    /// `from` to `to` over `duration` seconds.
+    pub fn between(from: T, to: T, duration: f32, ease: Ease) -> Self { Curve::new(from).key(duration, to, ease) }
+
+    /// Adds a key, kept in time order.
+    pub fn key(mut self, time: f32, value: T, ease: Ease) -> Self {
+        let at = self.keys.partition_point(|k| k.time <= time);
+        self.keys.insert(at, Key { time, value, ease });
+        self
+    }
+
+    /// Time of the last key.
+    pub fn duration(&self) -> f32 { self.keys.last().map_or(0.0, |k| k.time) }
+
+    pub fn sample(&self, time: f32) -> T {
+        let next = self.keys.partition_point(|k| k.time <= time);
+        if next == 0 { return self.keys[0].value.clone(); }
+        if next == self.keys.len() { return self.keys[next - 1].value.clone(); }
+        let (a, b) = (&self.keys[next - 1], &self.keys[next]);
+        let t = (time - a.time) / (b.time - a.time).max(1e-6);
+        a.value.lerp(&b.value, b.ease.apply(t))
+    }
+}
+
+/// Writes a `name time values... ease` line per key, for the camera path format.
+pub fn write_curve<T, const N: usize>(out: &mut String, name: &str, curve: &Curve<T>, values: impl Fn(&T) -> [f32; N]) {
+    for key in &curve.keys {
+        let v: Vec<String> = values(&key.value).iter().map(|v| v.to_string()).collect();
+        writeln!(out, "{} {} {} {:?}", name, key.time, v.join(" "), key.ease).unwrap();
+    }
+}
+
+/// Adds a parsed key to a curve that may not have started yet.
+pub fn add_key<T: Lerp>(curve: &mut Option<Curve<T>>, time: f32, value: T, ease: Ease) {
+    *curve = Some(match curve.take() {
+        Some(c) => c.key(time, value, ease),
+        //the first key at its own time, not 0
+        None => { let mut c = Curve::new(value); c.keys[0].time = time; c }
+    });
+}
+
+/// Something that plays over time against the scene and camera.
+pub trait Animate {
+    fn duration(&self) -> f32;
+    /// Sets whatever it animates to how it is `time` seconds in, 0..duration.
+    fn apply(&mut self, time: f32, scene: &mut Scene, camera: &mut Camera);
+}
+
+/// A curve and where its value goes.
+pub struct Tween<T> {
+    pub curve: Curve<T>,
+    set: Box<dyn FnMut(&mut Scene, &mut Camera, T)>,
+}
+
+impl<T: Lerp> Tween<T> {
+    pub fn new(curve: Curve<T>, set: impl FnMut(&mut Scene, &mut Camera, T) + 'static) -> Self { Tween { curve, set: Box::new(set) } }
+}
+
+impl<T: Lerp> Animate for Tween<T> {
+    fn duration(&self) -> f32 { self.curve.duration() }
+    fn apply(&mut self, time: f32, scene: &mut Scene, camera: &mut Camera) { (self.set)(scene, camera, self.curve.sample(time)) }
+}
+
+/// The entity's whole transform.
+pub fn transform(id: EntityId, curve: Curve<Mat4>) -> Tween<Mat4> {
+    Tween::new(curve, move |scene, _, m| if let Some(e) = scene.get_mut(id) { e.transform = m; })
+}
+
+/// The entity's position, leaving rotation and scale be.
+pub fn position(id: EntityId, curve: Curve<Vec3>) -> Tween<Vec3> {
+    Tween::new(curve, move |scene, _, p| if let Some(e) = scene.get_mut(id) { e.transform.w_axis = p.extend(1.0); })
+}
+
+pub fn rotation(id: EntityId, curve: Curve<Quat>) -> Tween<Quat> {
+    Tween::new(curve, move |scene, _, r| if let Some(e) = scene.get_mut(id) {
+        let (scale, _, translation) = e.transform.to_scale_rotation_translation();
+        e.transform = Mat4::from_scale_rotation_translation(scale, r, translation);
+    })
+}
+
+pub fn base_color(id: EntityId, curve: Curve<[f32; 4]>) -> Tween<[f32; 4]> {
+    Tween::new(curve, move |scene, _, c| if let Some(e) = scene.get_mut(id) { e.material.base_color = c; })
+}
+
+pub fn emissive(id: EntityId, curve: Curve<[f32; 3]>) -> Tween<[f32; 3]> {
+    Tween::new(curve, move |scene, _, c| if let Some(e) = scene.get_mut(id) { e.material.emissive = c; })
+}
+
+pub fn roughness(id: EntityId, curve: Curve<f32>) -> Tween<f32> {
+    Tween::new(curve, move |scene, _, r| if let Some(e) = scene.get_mut(id) { e.material.roughness = r; })
+}
+
+pub fn metallic(id: EntityId, curve: Curve<f32>) -> Tween<f32> {
+    Tween::new(curve, move |scene, _, m| if let Some(e) = scene.get_mut(id) { e.material.metallic = m; })
+}
+
+pub fn camera_position(curve: Curve<Vec3>) -> Tween<Vec3> { Tween::new(curve, |_, camera, p| camera.position = p) }
+
+pub fn camera_target(curve: Curve<Vec3>) -> Tween<Vec3> { Tween::new(curve, |_, camera, t| camera.target = t) }
+
+/// Vertical field of view in radians; does nothing to an orthographic camera.
+pub fn camera_fov(curve: Curve<f32>) -> Tween<f32> {
+    Tween::new(curve, |_, camera, fov| if let Projection::Perspective { fov_y } = &mut camera.projection { *fov_y = fov; })
+}
+
+/// Does nothing for a while, to space out a sequence.
+pub struct Wait(pub f32);
+
+impl Animate for Wait {
+    fn duration(&self) -> f32 { self.0 }
+    fn apply(&mut self, _: f32, _: &mut Scene, _: &mut Camera) {}
+}
+
+/// Plays its parts one after another. Parts skipped over in one step still get their end.
+pub struct Sequence {
+    pub parts: Vec<Box<dyn Animate>>,
+    current: usize,
+    /// When `current` started.
+    start: f32,
+}
+
+impl Sequence {
+    pub fn new() -> Self { Sequence { parts: Vec::new(), current: 0, start: 0.0 } }
+    pub fn then(mut self, part: impl Animate + 'static) -> Self { self.parts.push(Box::new(part)); self }
+    pub fn wait(self, seconds: f32) -> Self { self.then(Wait(seconds)) }
+}
+
+impl Animate for Sequence {
+    fn duration(&self) -> f32 { self.parts.iter().map(|p| p.duration()).sum() }
+
+    fn apply(&mut self, time: f32, scene: &mut Scene, camera: &mut Camera) {
+        //started over, when looping
+        if time < self.start { self.current = 0; self.start = 0.0; }
+        while let Some(part) = self.parts.get_mut(self.current) {
+            let end = self.start + part.duration();
+            if time < end || self.current + 1 == self.parts.len() {
+                part.apply((time - self.start).min(part.duration()), scene, camera);
+                return;
+            }
+            part.apply(part.duration(), scene, camera);
+            self.current += 1;
+            self.start = end;
+        }
+    }
+}
+
+/// Plays its parts together, for as long as the longest.
+pub struct Parallel(pub Vec<Box<dyn Animate>>);
+
+impl Animate for Parallel {
+    fn duration(&self) -> f32 { self.0.iter().map(|p| p.duration()).fold(0.0, f32::max) }
+
+    fn apply(&mut self, time: f32, scene: &mut Scene, camera: &mut Camera) {
+        for part in &mut self.0 { part.apply(time.min(part.duration()), scene, camera); }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TweenId(u64);
+
+struct Playing {
+    id: TweenId,
+    animation: Box<dyn Animate>,
+    time: f32,
+    looping: bool,
+}
+
+/// Runs tweens from the update loop, each until its end or for good if it loops. Whatever
+/// they animate is theirs while they play; later ones win over earlier ones.
+pub struct Tweener {
+    playing: Vec<Playing>,
+    next: u64,
+}
+
+impl Tweener {
+    pub fn new() -> Self { Tweener { playing: Vec::new(), next: 0 } }
+
+    pub fn play(&mut self, animation: impl Animate + 'static) -> TweenId { self.start(Box::new(animation), false) }
+
+    pub fn play_looping(&mut self, animation: impl Animate + 'static) -> TweenId { self.start(Box::new(animation), true) }
+
+    fn start(&mut self, animation: Box<dyn Animate>, looping: bool) -> TweenId {
+        let id = TweenId(self.next);
+        self.next += 1;
+        self.playing.push(Playing { id, animation, time: 0.0, looping });
+        id
+    }
+
+    /// Leaves what it animated as it is now.
+    pub fn stop(&mut self, id: TweenId) { self.playing.retain(|p| p.id != id); }
+
+    pub fn is_playing(&self, id: TweenId) -> bool { self.playing.iter().any(|p| p.id == id) }
+
+    /// `dt` in scaled seconds, so tweens pause along with the scene.
+    pub fn update(&mut self, dt: f32, scene: &mut Scene, camera: &mut Camera) {
+        for p in &mut self.playing {
+            let duration = p.animation.duration();
+            p.time += dt;
+            if p.looping && duration > 0.0 { p.time %= duration; }
+            p.animation.apply(p.time.min(duration), scene, camera);
+        }
+        self.playing.retain(|p| p.looping || p.time < p.animation.duration());
+    }
+}
@@ -0,0 +1,107 @@
+use std::sync::Arc;
+use crate::texture::Texture;
+use crate::overlay::ShapeVertex;
+
+/// A textured screen rect, drawn by the overlay over the scene.
+#[derive(Clone)]
+pub struct UiQuad {
+    pub texture: Arc<Texture>,
+    /// x, y, w, h in pixels from the top left of the window.
+    pub rect: [f32; 4],
+    /// min.xy, max.xy.
+    pub uv: [f32; 4],
+    pub color: [f32; 4],
+    /// Set for glyphs of distance field fonts.
+    pub sdf: Option<SdfQuad>,
+}
+
+/// Outline and glow around distance field text, in screen pixels; widths of 0 leave them off.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SdfEffects {
+    pub outline: [f32; 4],
+    pub outline_width: f32,
+    pub glow: [f32; 4],
+    pub glow_width: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SdfQuad {
+    /// Screen pixels the atlas's distance range spans at this size.
+    pub px_range: f32,
+    pub effects: SdfEffects,
+}
+
+/// A panel or button skin that scales without stretching its borders: corners stay their size,
+/// edges stretch along themselves and the middle both ways.
+#[derive(Clone)]
+pub struct NineSlice {
+    pub texture: Arc<Texture>,
+    /// The part of `texture` holding the skin, x, y, w, h in its pixels; all of it by default.
+    pub region: [u32; 4],
+    /// Border widths in texture pixels: left, top, right, bottom.
+    pub margins: [u32; 4],
+    /// Screen pixels per texture pixel of border.
+    pub scale: f32,
+}
+
+impl NineSlice {
+    pub fn new(texture: Arc<Texture>, margins: [u32; 4]) -> Self {
+        NineSlice { region: [0, 0, texture.extent[0], texture.extent[1]], texture, margins, scale: 1.0 }
+    }
+
+    /// Up to nine quads covering `rect`, in pixels. Rects smaller than the borders shrink them
+    /// evenly rather than overlap.
+    pub fn quads(&self, rect: [f32; 4], color: [f32; 4]) -> Vec<UiQuad> {
+        let [l, t, r, b] = self.margins.map(|m| m as f32 * self.scale);
+        let fit = [(rect[2] / (l + r)).min(1.0), (rect[3] / (t + b)).min(1.0)].map(|f| if f.is_finite() { f } else { 1.0 });
+        let xs = [rect[0], rect[0] + l * fit[0], rect[0] + rect[2] - r * fit[0], rect[0] + rect[2]];
+        let ys = [rect[1], rect[1] + t * fit[1], rect[1] + rect[3] - b * fit[1], rect[1] + rect[3]];
+        let [rx, ry, rw, rh] = self.region.map(|v| v as f32);
+        let [ml, mt, mr, mb] = self.margins.map(|m| m as f32);
+        let size = self.texture.extent.map(|e| e as f32);
+        let us = [rx, rx + ml, rx + rw - mr, rx + rw].map(|u| u / size[0]);
+        let vs = [ry, ry + mt, ry + rh - mb, ry + rh].map(|v| v / size[1]);
+        let mut quads = Vec::with_capacity(9);
+        for row in 0..3 {
+            for column in 0..3 {
+                let (w, h) = (xs[column + 1] - xs[column], ys[row + 1] - ys[row]);
+                if w <= 0.0 || h <= 0.0 { continue; }
+                quads.push(UiQuad { texture: self.texture.clone(), rect: [xs[column], ys[row], w, h],
+                                    uv: [us[column], vs[row], us[column + 1], vs[row + 1]], color, sdf: None });
+            }
+        }
+        quads
+    }
+}
+
+/// Screen space quads for the overlay, gathered anew every frame like `DebugDraw`'s lines.
+pub struct Ui {
+    quads: Vec<UiQuad>,
+    shapes: Vec<ShapeVertex>,
+}
+
+impl Ui {
+    pub fn new() -> Self { Ui { quads: Vec::new(), shapes: Vec::new() } }
+
+    pub fn begin_frame(&mut self) {
+        self.quads.clear();
+        self.shapes.clear();
+    }
+
+    pub fn quads(&self) -> &[UiQuad] { &self.quads }
+
+    pub fn shapes(&self) -> &[ShapeVertex] { &self.shapes }
+
+    /// Untextured triangles in pixels, three vertices apiece, drawn under the quads. See
+    /// `vector` for filling and stroking paths into them.
+    pub fn triangles(&mut self, vertices: impl IntoIterator<Item = ShapeVertex>) { self.shapes.extend(vertices); }
+
+    /// `uv` of `texture` over `rect`, in pixels from the top left.
+    pub fn image(&mut self, texture: Arc<Texture>, rect: [f32; 4], uv: [f32; 4], color: [f32; 4]) {
+        self.quads.push(UiQuad { texture, rect, uv, color, sdf: None });
+    }
+
+    pub fn quad(&mut self, quad: UiQuad) { self.quads.push(quad); }
+
+    pub fn nine_slice(&mut self, slice: &NineSlice, rect: [f32; 4], color: [f32; 4]) { self.quads.extend(slice.quads(rect, color)); }
+}
@@ -0,0 +1,84 @@
+use lyon::math::{ point, Box2D, Point };
+use lyon::path::{ builder::BorderRadii, Winding };
+use lyon::tessellation::{ BuffersBuilder, FillOptions, FillTessellator, FillVertex, StrokeOptions, StrokeTessellator, StrokeVertex, VertexBuffers };
+use crate::overlay::ShapeVertex;
+use crate::ui::Ui;
+
+pub use lyon::path::Path;
+pub use lyon::tessellation::{ LineCap, LineJoin };
+
+/// Screen pixels a tessellated curve may stray from the real one. Paths tessellate every frame
+/// at the scale they're drawn, so they stay smooth however big or dense the screen.
+const TOLERANCE: f32 = 0.1;
+
+/// How a path's outline is drawn. Widths are in the path's units and scale with it.
+#[derive(Clone, Copy, Debug)]
+pub struct Stroke {
+    pub width: f32,
+    pub join: LineJoin,
+    pub cap: LineCap,
+}
+
+impl Stroke {
+    pub fn new(width: f32) -> Self { Stroke { width, join: LineJoin::Round, cap: LineCap::Round } }
+}
+
+/// x, y, w, h.
+pub fn rect(rect: [f32; 4]) -> Path {
+    let mut builder = Path::builder();
+    builder.add_rectangle(&Box2D::new(point(rect[0], rect[1]), point(rect[0] + rect[2], rect[1] + rect[3])), Winding::Positive);
+    builder.build()
+}
+
+/// x, y, w, h with corners of `radius`, at most half the shorter side.
+pub fn rounded_rect(rect: [f32; 4], radius: f32) -> Path {
+    let radius = radius.min(rect[2].min(rect[3]) * 0.5).max(0.0);
+    let mut builder = Path::builder();
+    builder.add_rounded_rectangle(&Box2D::new(point(rect[0], rect[1]), point(rect[0] + rect[2], rect[1] + rect[3])),
+                                  &BorderRadii::new(radius), Winding::Positive);
+    builder.build()
+}
+
+pub fn circle(center: [f32; 2], radius: f32) -> Path {
+    let mut builder = Path::builder();
+    builder.add_circle(point(center[0], center[1]), radius, Winding::Positive);
+    builder.build()
+}
+
+/// Through `points`, back to the first when `closed`. Lines of charts and graphs.
+pub fn polyline(points: &[[f32; 2]], closed: bool) -> Path {
+    let mut builder = Path::builder();
+    if let Some((first, rest)) = points.split_first() {
+        builder.begin(point(first[0], first[1]));
+        for p in rest { builder.line_to(point(p[0], p[1])); }
+        builder.end(closed);
+    }
+    builder.build()
+}
+
+/// Indexed output as a triangle list, `scale` pixels to the unit from `at`.
+fn place(buffers: &VertexBuffers<Point, u32>, at: [f32; 2], scale: f32, color: [f32; 4]) -> impl Iterator<Item = ShapeVertex> + '_ {
+    buffers.indices.iter().map(move |&i| {
+        let p = buffers.vertices[i as usize];
+        ShapeVertex { position: [at[0] + p.x * scale, at[1] + p.y * scale], color }
+    })
+}
+
+impl Ui {
+    /// Fills `path`, given in its own units, `scale` pixels to the unit from `at` pixels off the
+    /// top left. Shapes go under the quads: panels and charts behind their text.
+    pub fn fill(&mut self, path: &Path, at: [f32; 2], scale: f32, color: [f32; 4]) {
+        let mut buffers = VertexBuffers::new();
+        let options = FillOptions::tolerance(TOLERANCE / scale);
+        //a path the tessellator chokes on goes missing for the frame rather than take it down
+        if FillTessellator::new().tessellate_path(path, &options, &mut BuffersBuilder::new(&mut buffers, |v: FillVertex| v.position())).is_err() { return; }
+        self.triangles(place(&buffers, at, scale, color));
+    }
+
+    pub fn stroke(&mut self, path: &Path, stroke: &Stroke, at: [f32; 2], scale: f32, color: [f32; 4]) {
+        let mut buffers = VertexBuffers::new();
+        let options = StrokeOptions::tolerance(TOLERANCE / scale).with_line_width(stroke.width).with_line_join(stroke.join).with_line_cap(stroke.cap);
+        if StrokeTessellator::new().tessellate_path(path, &options, &mut BuffersBuilder::new(&mut buffers, |v: StrokeVertex| v.position())).is_err() { return; }
+        self.triangles(place(&buffers, at, scale, color));
+    }
+}
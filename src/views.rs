@@ -0,0 +1,133 @@
+use vulkano::{ command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               image::{ AttachmentImage, view::{ ImageView, ImageViewAbstract } } };
+use std::sync::Arc;
+use crate::scene::Scene;
+use crate::camera::Camera;
+use crate::culling::Frustum;
+use crate::renderer::{ Renderer, Target };
+use crate::texture::Texture;
+
+pub type ViewId = usize;
+
+/// Passes a view runs for itself before drawing the scene. Whatever it leaves off it shares
+/// with the view rendered before it, last frame's main camera for the first; fine for views
+/// looking at the same place, wrong for a camera across the level.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ViewPasses {
+    /// Renders the sun's cascades and the local light shadows around this camera.
+    pub shadows: bool,
+    /// Rebuilds the voxel gi clipmap around this camera.
+    pub voxel_gi: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ViewSize {
+    Fixed([u32; 2]),
+    /// Its `rect` of the window in pixels, kept up by `resize`. Split screen.
+    Window,
+}
+
+/// Extra camera rendered to its own target and composited into `rect` (x, y, w, h as fractions
+/// of the window, origin top left). Minimaps, rear view mirrors, security monitors, split screen.
+pub struct SecondaryView {
+    pub camera: Camera,
+    pub rect: [f32; 4],
+    pub enabled: bool,
+    /// Set `texture` on a material instead to show it in the world, and `rect` to zero size.
+    pub composite: bool,
+    pub passes: ViewPasses,
+    /// Views whose textures show up in this one, a monitor in sight of a mirror; they render
+    /// first. A cycle leaves one of them a frame behind.
+    pub depends_on: Vec<ViewId>,
+    pub size: ViewSize,
+    target: Target,
+    color: Arc<ImageView<AttachmentImage>>,
+    extent: [u32; 2],
+}
+
+impl SecondaryView {
+    /// The view's image, for a material.
+    pub fn texture(&self) -> Arc<Texture> { Arc::new(Texture { view: self.color.clone(), extent: self.extent }) }
+}
+
+fn extent(size: ViewSize, rect: [f32; 4], display: [u32; 2]) -> [u32; 2] {
+    match size {
+        ViewSize::Fixed(extent) => extent,
+        ViewSize::Window => [((display[0] as f32 * rect[2]) as u32).max(1), ((display[1] as f32 * rect[3]) as u32).max(1)],
+    }
+}
+
+pub struct Views { views: Vec<SecondaryView>, }
+
+impl Views {
+    pub fn new() -> Self { Views { views: Vec::new() } }
+
+    pub fn add(&mut self, renderer: &Renderer, camera: Camera, rect: [f32; 4], extent: [u32; 2]) -> ViewId {
+        self.push(renderer, camera, rect, ViewSize::Fixed(extent), extent)
+    }
+
+    /// A view as big as its `rect` of the `display`, for split screen. Give it `shadows` in its
+    /// passes if it goes its own way.
+    pub fn add_split(&mut self, renderer: &Renderer, camera: Camera, rect: [f32; 4], display: [u32; 2]) -> ViewId {
+        self.push(renderer, camera, rect, ViewSize::Window, extent(ViewSize::Window, rect, display))
+    }
+
+    fn push(&mut self, renderer: &Renderer, mut camera: Camera, rect: [f32; 4], size: ViewSize, extent: [u32; 2]) -> ViewId {
+        camera.aspect = extent[0] as f32 / extent[1] as f32;
+        let (target, color) = renderer.offscreen_target(extent);
+        self.views.push(SecondaryView { camera, rect, enabled: true, composite: true, passes: ViewPasses::default(), depends_on: Vec::new(), size,
+                                        target, color, extent });
+        self.views.len() - 1
+    }
+
+    pub fn get(&self, id: ViewId) -> &SecondaryView { &self.views[id] }
+
+    pub fn get_mut(&mut self, id: ViewId) -> &mut SecondaryView { &mut self.views[id] }
+
+    /// Remakes the targets of views sized by the window. Materials showing them need `texture`
+    /// again after.
+    pub fn resize(&mut self, renderer: &Renderer, display: [u32; 2]) {
+        for v in self.views.iter_mut().filter(|v| v.size == ViewSize::Window) {
+            let extent = extent(v.size, v.rect, display);
+            if extent == v.extent { continue; }
+            let (target, color) = renderer.offscreen_target(extent);
+            v.camera.aspect = extent[0] as f32 / extent[1] as f32;
+            (v.target, v.color, v.extent) = (target, color, extent);
+        }
+    }
+
+    /// Enabled views, each after the ones it depends on.
+    fn schedule(&self) -> Vec<ViewId> {
+        fn visit(views: &[SecondaryView], id: ViewId, state: &mut [u8], order: &mut Vec<ViewId>) {
+            //0 not seen, 1 on the way down, 2 placed
+            if state[id] != 0 { return; }
+            state[id] = 1;
+            for &d in views[id].depends_on.iter().filter(|&&d| d < views.len() && views[d].enabled) { visit(views, d, state, order); }
+            state[id] = 2;
+            order.push(id);
+        }
+        let mut state = vec![0; self.views.len()];
+        let mut order = Vec::new();
+        for id in (0..self.views.len()).filter(|&i| self.views[i].enabled) { visit(&self.views, id, &mut state, &mut order); }
+        order
+    }
+
+    /// Records every enabled view with its passes. Call before the main camera's own shadows
+    /// and gi each frame, which the views' passes would otherwise overwrite.
+    #[profiling::function]
+    pub fn render(&self, renderer: &mut Renderer, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, scene: &Scene) {
+        let none = Default::default();
+        for v in self.schedule().into_iter().map(|id| &self.views[id]) {
+            let view = v.camera.as_view();
+            if v.passes.shadows { renderer.shadows.render(builder, scene, &view); }
+            if v.passes.voxel_gi { renderer.voxelize(builder, scene, &view); }
+            let frustum = Frustum::from_view_proj(&view.view_proj());
+            renderer.draw(builder, &v.target, scene, &view, &|e| e.portal.is_none() && frustum.intersects_aabb(&e.world_bounds()), &none, None);
+        }
+    }
+
+    /// Textures and rects for the overlay to composite.
+    pub fn composites(&self) -> Vec<(Arc<dyn ImageViewAbstract>, [f32; 4])> {
+        self.views.iter().filter(|v| v.enabled && v.composite).map(|v| (v.color.clone() as Arc<dyn ImageViewAbstract>, v.rect)).collect()
+    }
+}
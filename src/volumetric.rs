@@ -0,0 +1,177 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet, layout::DescriptorSetLayout },
+               image::{ StorageImage, ImageDimensions, ImageUsage, ImageCreateFlags, view::ImageView },
+               render_pass::Subpass,
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode, Filter },
+               pipeline::{ ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint,
+                           graphics::{ input_assembly::InputAssemblyState, vertex_input::BuffersDefinition, viewport::ViewportState,
+                                       color_blend::{ ColorBlendState, AttachmentBlend, BlendOp, BlendFactor } } },
+               buffer::CpuBufferPool,
+               format::Format };
+use glam::{ Mat4, Vec3 };
+use std::sync::Arc;
+use crate::camera::View;
+use crate::renderer::Target;
+
+mod scatter {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/fog_scatter.comp", include: ["src/shaders"] }
+}
+mod integrate {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/fog_integrate.comp", include: ["src/shaders"] }
+}
+mod apply_vs {
+    vulkano_shaders::shader! { ty: "vertex",
+    src: "#version 450
+
+			void main() {
+				gl_Position = vec4(vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0, 0.0, 1.0);
+			}"
+    }
+}
+mod apply_fs {
+    vulkano_shaders::shader! { ty: "fragment", path: "src/shaders/fog_apply.frag", include: ["src/shaders"] }
+}
+
+/// Froxels across, down and deep.
+const RESOLUTION: [u32; 3] = [160, 90, 64];
+const FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// Participating medium around the camera, lit by the sun and the point lights with their
+/// shadows, which is where the light shafts come from. Drawn by `FogVolume`.
+#[derive(Clone, Copy, Debug)]
+pub struct VolumetricFog {
+    /// Extinction per meter at and below `base_height`.
+    pub density: f32,
+    /// How fast density falls off above `base_height`, per meter. 0 is uniform.
+    pub height_falloff: f32,
+    pub base_height: f32,
+    /// 0..1, breaks the density up with noise.
+    pub noise: f32,
+    /// Noise features per meter.
+    pub noise_frequency: f32,
+    /// Scroll it for wind.
+    pub noise_offset: Vec3,
+    /// Scattering color; white fog loses no light to absorption.
+    pub albedo: Vec3,
+    /// Henyey-Greenstein g. Above 0 scatters forward, brightening the fog towards lights.
+    pub anisotropy: f32,
+    /// How much of the scene's flat ambient the fog picks up.
+    pub ambient: f32,
+    /// Meters the volume reaches from the camera; anything further gets the fog of its last slice.
+    pub distance: f32,
+    /// 0..1 weight of last frame's volume, steadies the jittered slices.
+    pub temporal: f32,
+}
+
+impl Default for VolumetricFog {
+    fn default() -> Self {
+        VolumetricFog { density: 0.02, height_falloff: 0.2, base_height: 0.0, noise: 0.3, noise_frequency: 0.5, noise_offset: Vec3::ZERO,
+                        albedo: Vec3::ONE, anisotropy: 0.6, ambient: 1.0, distance: 64.0, temporal: 0.9 }
+    }
+}
+
+/// Froxel volume for `VolumetricFog`. `scatter` lights every froxel and blends it with last
+/// frame's, reprojected, then integrates front to back; `apply` fogs the scene with the result
+/// before anything transparent or post processing. Keeps history, so one per view.
+pub struct FogVolume {
+    scatter: Arc<ComputePipeline>,
+    integrate: Arc<ComputePipeline>,
+    apply: Arc<GraphicsPipeline>,
+    uniforms: CpuBufferPool<scatter::ty::Fog>,
+    sampler: Arc<Sampler>,
+    /// Scattering and extinction; this frame's and last frame's swap every `scatter`.
+    volumes: [Arc<ImageView<StorageImage>>; 2],
+    integrated: Arc<ImageView<StorageImage>>,
+    current: usize,
+    prev_view_proj: Option<Mat4>,
+    frame: u32,
+}
+
+impl FogVolume {
+    /// `subpass` is the renderer's transparent subpass, see `Renderer::transparent_subpass`.
+    pub fn new(dev: Arc<Device>, subpass: Subpass) -> Self {
+        let scatter = ComputePipeline::new(dev.clone(), scatter::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let integrate = ComputePipeline::new(dev.clone(), integrate::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let vs = apply_vs::load(dev.clone()).unwrap();
+        let fs = apply_fs::load(dev.clone()).unwrap();
+        let apply = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            //scene * transmittance + in-scattering, scene alpha kept
+            .color_blend_state(ColorBlendState::new(1).blend(AttachmentBlend {
+                color_op: BlendOp::Add, color_source: BlendFactor::One, color_destination: BlendFactor::SrcAlpha,
+                alpha_op: BlendOp::Add, alpha_source: BlendFactor::Zero, alpha_destination: BlendFactor::One }))
+            .render_pass(subpass)
+            .build(dev.clone()).unwrap();
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo { mag_filter: Filter::Linear, min_filter: Filter::Linear,
+                                                                    address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        let volume = || {
+            let image = StorageImage::with_usage(dev.clone(), ImageDimensions::Dim3d { width: RESOLUTION[0], height: RESOLUTION[1], depth: RESOLUTION[2] },
+                FORMAT, ImageUsage { storage: true, sampled: true, ..ImageUsage::none() }, ImageCreateFlags::none(), dev.active_queue_families()).unwrap();
+            ImageView::new_default(image).unwrap()
+        };
+        FogVolume { scatter, integrate, apply, uniforms: CpuBufferPool::uniform_buffer(dev.clone()), sampler,
+                    volumes: [volume(), volume()], integrated: volume(), current: 0, prev_view_proj: None, frame: 0 }
+    }
+
+    /// Layout of the frame set (set 0) scattering reads lights and shadows from.
+    pub fn frame_layout(&self) -> Arc<DescriptorSetLayout> { self.scatter.layout().set_layouts().get(0).unwrap().clone() }
+
+    /// Records the volume for `view`. Compute, so outside any render pass, after the shadow maps
+    /// and light clusters of the frame.
+    pub fn scatter(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, frame_set: Arc<PersistentDescriptorSet>,
+                   fog: &VolumetricFog, view: &View) {
+        self.current ^= 1;
+        self.frame = self.frame.wrapping_add(1);
+        let view_proj = view.view_proj();
+        let forward = view.view.inverse().transform_vector3(-Vec3::Z).normalize();
+        //golden ratio steps cover the slice depth evenly over a few frames
+        let jitter = (self.frame as f32 * 0.618034).fract();
+        let uniforms = self.uniforms.next(scatter::ty::Fog {
+            inv_view_proj: view_proj.inverse().to_cols_array_2d(),
+            prev_view_proj: self.prev_view_proj.unwrap_or(view_proj).to_cols_array_2d(),
+            forward: forward.extend(view.near).into(),
+            medium: [fog.density, fog.height_falloff, fog.base_height, fog.noise],
+            scattering: fog.albedo.extend(fog.anisotropy).into(),
+            params: [fog.distance, if self.prev_view_proj.is_some() { fog.temporal } else { 0.0 }, jitter, fog.ambient],
+            noise: [fog.noise_frequency, fog.noise_offset.x, fog.noise_offset.y, fog.noise_offset.z],
+        }).unwrap();
+        let (volume, history) = (self.volumes[self.current].clone(), self.volumes[self.current ^ 1].clone());
+        let set = PersistentDescriptorSet::new(self.scatter.layout().set_layouts().get(1).unwrap().clone(), [
+            WriteDescriptorSet::buffer(0, uniforms),
+            WriteDescriptorSet::image_view(1, volume.clone()),
+            WriteDescriptorSet::image_view_sampler(2, history, self.sampler.clone()),
+        ]).unwrap();
+        builder.bind_pipeline_compute(self.scatter.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.scatter.layout().clone(), 0, vec![frame_set, set])
+            .dispatch([(RESOLUTION[0] + 3) / 4, (RESOLUTION[1] + 3) / 4, RESOLUTION[2] / 4]).unwrap();
+
+        let set = PersistentDescriptorSet::new(self.integrate.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view(0, volume),
+            WriteDescriptorSet::image_view(1, self.integrated.clone()),
+        ]).unwrap();
+        builder.bind_pipeline_compute(self.integrate.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.integrate.layout().clone(), 0, set)
+            .push_constants(self.integrate.layout().clone(), 0, integrate::ty::PushConstants { depth: [view.near, fog.distance] })
+            .dispatch([(RESOLUTION[0] + 7) / 8, (RESOLUTION[1] + 7) / 8, 1]).unwrap();
+        self.prev_view_proj = Some(view_proj);
+    }
+
+    /// Fogs what is in color so far. Records into the transparent subpass, before billboards.
+    pub fn apply(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, target: &Target, fog: &VolumetricFog, view: &View) {
+        let set = PersistentDescriptorSet::new(self.apply.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view(0, target.depth.clone()),
+            WriteDescriptorSet::image_view_sampler(1, self.integrated.clone(), self.sampler.clone()),
+        ]).unwrap();
+        let pc = apply_fs::ty::PushConstants { depth: [view.near, view.far, fog.distance, 0.0],
+                                               inv_extent: [1.0 / target.extent[0] as f32, 1.0 / target.extent[1] as f32] };
+        builder.bind_pipeline_graphics(self.apply.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, self.apply.layout().clone(), 0, set)
+            .push_constants(self.apply.layout().clone(), 0, pc)
+            .draw(3, 1, 0, 0).unwrap();
+    }
+}
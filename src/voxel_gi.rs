@@ -0,0 +1,187 @@
+use vulkano::{ device::Device,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet, layout::DescriptorSetLayout },
+               image::{ StorageImage, ImageDimensions, ImageUsage, ImageCreateFlags, view::{ ImageView, ImageViewAbstract } },
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode, Filter },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint },
+               format::{ ClearValue, Format } };
+use glam::Vec3;
+use std::sync::Arc;
+use crate::scene::Scene;
+
+mod voxelize {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/voxelize.comp", include: ["src/shaders"] }
+}
+mod inject {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/voxel_inject.comp", include: ["src/shaders"] }
+}
+
+/// Clipmap levels the frame set has room for. Keep in step with frame.glsl.
+pub const MAX_LEVELS: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GiQuality {
+    /// 32 voxels a side, 3 levels, one diffuse cone and no specular.
+    Low,
+    /// 64 a side, 4 levels, six diffuse cones and a specular one.
+    Medium,
+    /// 128 a side, 4 levels, six diffuse cones and a specular one.
+    High,
+}
+
+impl GiQuality {
+    fn resolution(self) -> u32 { match self { GiQuality::Low => 32, GiQuality::Medium => 64, GiQuality::High => 128 } }
+    fn levels(self) -> usize { match self { GiQuality::Low => 3, _ => 4 } }
+    fn full_cones(self) -> bool { self != GiQuality::Low }
+}
+
+/// Scene settings for voxel global illumination. Only shows once the renderer's clipmap has a
+/// quality, see `VoxelClipmap::set_quality`.
+#[derive(Clone, Copy, Debug)]
+pub struct VoxelGi {
+    pub intensity: f32,
+    /// Meters per voxel in the innermost level; every level after doubles it.
+    pub voxel_size: f32,
+    /// Meters a cone travels before it gives up.
+    pub max_distance: f32,
+}
+
+impl Default for VoxelGi {
+    fn default() -> Self { VoxelGi { intensity: 1.0, voxel_size: 0.125, max_distance: 20.0 } }
+}
+
+/// What the shading frame set gets about the clipmap.
+pub struct VoxelUniforms {
+    pub params: [f32; 4],
+    pub trace: [f32; 4],
+    pub origins: [[f32; 4]; MAX_LEVELS],
+}
+
+struct Level {
+    albedo: Arc<ImageView<StorageImage>>,
+    normal: Arc<ImageView<StorageImage>>,
+    emissive: Arc<ImageView<StorageImage>>,
+    radiance: Arc<ImageView<StorageImage>>,
+}
+
+/// Voxel cone tracing. Every frame `Renderer::voxelize` rebuilds the clipmap around the
+/// camera, a few nested grids each twice as coarse as the one inside it: meshes are voxelized
+/// triangle by triangle in compute, then the sun and point lights are injected with their
+/// shadows. Shading traces cones through it for one bounce of diffuse and, on the better
+/// tiers, glossy reflections, falling back to the probes and sky where the cones get out.
+pub struct VoxelClipmap {
+    dev: Arc<Device>,
+    quality: Option<GiQuality>,
+    voxelize: Arc<ComputePipeline>,
+    inject: Arc<ComputePipeline>,
+    levels: Vec<Level>,
+    /// Bound in place of the levels when gi is off or the quality has fewer.
+    empty: Arc<ImageView<StorageImage>>,
+    pub sampler: Arc<Sampler>,
+    origins: [[f32; 4]; MAX_LEVELS],
+}
+
+impl VoxelClipmap {
+    pub fn new(dev: Arc<Device>) -> Self {
+        let voxelize = ComputePipeline::new(dev.clone(), voxelize::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let inject = ComputePipeline::new(dev.clone(), inject::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo { mag_filter: Filter::Linear, min_filter: Filter::Linear,
+                                                                    address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        let empty = volume(&dev, 1, Format::R16G16B16A16_SFLOAT);
+        VoxelClipmap { dev, quality: None, voxelize, inject, levels: Vec::new(), empty, sampler, origins: [[0.0; 4]; MAX_LEVELS] }
+    }
+
+    /// None frees the clipmap and turns gi off whatever the scene says.
+    pub fn set_quality(&mut self, quality: Option<GiQuality>) {
+        if quality == self.quality { return; }
+        self.quality = quality;
+        let (resolution, levels) = quality.map_or((0, 0), |q| (q.resolution(), q.levels()));
+        self.levels = (0..levels).map(|_| Level {
+            albedo: volume(&self.dev, resolution, Format::R8G8B8A8_UNORM),
+            normal: volume(&self.dev, resolution, Format::R8G8B8A8_UNORM),
+            emissive: volume(&self.dev, resolution, Format::R16G16B16A16_SFLOAT),
+            radiance: volume(&self.dev, resolution, Format::R16G16B16A16_SFLOAT),
+        }).collect();
+    }
+
+    pub fn active(&self, scene: &Scene) -> bool { self.quality.is_some() && scene.voxel_gi.is_some() }
+
+    /// Layout of the frame set (set 0) injection reads lights and shadows from.
+    pub fn frame_layout(&self) -> Arc<DescriptorSetLayout> { self.inject.layout().set_layouts().get(0).unwrap().clone() }
+
+    /// Snaps each level to its own voxel size around `eye`, so voxels don't swim as the camera moves.
+    pub fn center(&mut self, settings: &VoxelGi, eye: Vec3) {
+        let resolution = match self.quality { Some(q) => q.resolution() as f32, None => return };
+        for (i, origin) in self.origins.iter_mut().enumerate().take(self.levels.len()) {
+            let size = settings.voxel_size * (1 << i) as f32;
+            let corner = (eye / size).floor() * size - Vec3::splat(size * resolution * 0.5);
+            *origin = corner.extend(size).into();
+        }
+    }
+
+    pub fn uniforms(&self, scene: &Scene) -> VoxelUniforms {
+        match (self.quality, &scene.voxel_gi) {
+            (Some(q), Some(gi)) => VoxelUniforms {
+                params: [gi.voxel_size, self.levels.len() as f32, q.resolution() as f32, gi.intensity],
+                trace: [if q.full_cones() { 6.0 } else { 1.0 }, if q.full_cones() { 1.0 } else { 0.0 }, gi.max_distance, 0.0],
+                origins: self.origins,
+            },
+            _ => VoxelUniforms { params: [0.0; 4], trace: [0.0; 4], origins: [[0.0; 4]; MAX_LEVELS] },
+        }
+    }
+
+    /// Radiance of every level, padded with the empty volume, for frame set binding 10.
+    pub fn views(&self) -> impl Iterator<Item = (Arc<dyn ImageViewAbstract>, Arc<Sampler>)> + '_ {
+        (0..MAX_LEVELS).map(move |i| {
+            let view = self.levels.get(i).map_or(self.empty.clone(), |l| l.radiance.clone());
+            (view as Arc<dyn ImageViewAbstract>, self.sampler.clone())
+        })
+    }
+
+    /// Voxelizes every mesh entity into every level and lights the result. Compute, so outside
+    /// any render pass, after the shadow maps of the frame. `center` first.
+    pub fn record(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, frame_set: Arc<PersistentDescriptorSet>, scene: &Scene) {
+        let resolution = match self.quality { Some(q) => q.resolution(), None => return };
+        for (level, origin) in self.levels.iter().zip(self.origins) {
+            for image in [&level.albedo, &level.normal, &level.emissive] {
+                builder.clear_color_image(image.image().clone(), ClearValue::Float([0.0; 4])).unwrap();
+            }
+            let set = PersistentDescriptorSet::new(self.voxelize.layout().set_layouts().get(0).unwrap().clone(), [
+                WriteDescriptorSet::image_view(1, level.albedo.clone()),
+                WriteDescriptorSet::image_view(2, level.normal.clone()),
+                WriteDescriptorSet::image_view(3, level.emissive.clone()),
+            ]).unwrap();
+            builder.bind_pipeline_compute(self.voxelize.clone());
+            for entity in scene.entities.iter().filter(|e| e.portal.is_none()) {
+                let mesh = match &entity.mesh { Some(m) => m, None => continue };
+                let m = &entity.material;
+                let triangles = (mesh.vertices.len() / 3) as u32;
+                let vertices = PersistentDescriptorSet::new(self.voxelize.layout().set_layouts().get(1).unwrap().clone(), [
+                    WriteDescriptorSet::buffer(0, mesh.vertex_buffer.clone())]).unwrap();
+                let pc = voxelize::ty::PushConstants { model: entity.transform.to_cols_array_2d(), albedo: m.base_color,
+                                                       emissive: [m.emissive[0], m.emissive[1], m.emissive[2], 0.0],
+                                                       origin, triangles, resolution };
+                builder.bind_descriptor_sets(PipelineBindPoint::Compute, self.voxelize.layout().clone(), 0, vec![set.clone(), vertices])
+                    .push_constants(self.voxelize.layout().clone(), 0, pc)
+                    .dispatch([(triangles + 63) / 64, 1, 1]).unwrap();
+            }
+
+            let set = PersistentDescriptorSet::new(self.inject.layout().set_layouts().get(1).unwrap().clone(), [
+                WriteDescriptorSet::image_view(0, level.albedo.clone()),
+                WriteDescriptorSet::image_view(1, level.normal.clone()),
+                WriteDescriptorSet::image_view(2, level.emissive.clone()),
+                WriteDescriptorSet::image_view(3, level.radiance.clone()),
+            ]).unwrap();
+            builder.bind_pipeline_compute(self.inject.clone())
+                .bind_descriptor_sets(PipelineBindPoint::Compute, self.inject.layout().clone(), 0, vec![frame_set.clone(), set])
+                .push_constants(self.inject.layout().clone(), 0, inject::ty::PushConstants { origin })
+                .dispatch([resolution / 4; 3]).unwrap();
+        }
+    }
+}
+
+fn volume(dev: &Arc<Device>, side: u32, format: Format) -> Arc<ImageView<StorageImage>> {
+    let image = StorageImage::with_usage(dev.clone(), ImageDimensions::Dim3d { width: side, height: side, depth: side }, format,
+        ImageUsage { storage: true, sampled: true, transfer_destination: true, ..ImageUsage::none() }, ImageCreateFlags::none(), dev.active_queue_families()).unwrap();
+    ImageView::new_default(image).unwrap()
+}
@@ -0,0 +1,198 @@
+use vulkano::{ device::Device,
+               buffer::CpuBufferPool,
+               command_buffer::{ AutoCommandBufferBuilder, PrimaryAutoCommandBuffer },
+               descriptor_set::{ PersistentDescriptorSet, WriteDescriptorSet },
+               sampler::{ Sampler, SamplerCreateInfo, SamplerAddressMode, Filter },
+               pipeline::{ ComputePipeline, Pipeline, PipelineBindPoint },
+               format::Format };
+use glam::{ Mat4, Vec2, Vec3 };
+use std::collections::HashMap;
+use std::f32::consts::{ FRAC_PI_2, TAU };
+use std::sync::Arc;
+use crate::scene::Scene;
+use crate::camera::View;
+use crate::culling::Frustum;
+use crate::ibl::Environment;
+use crate::light::SceneLights;
+use crate::portal::Portal;
+use crate::renderer::{ Renderer, Target };
+use crate::postfx::{ self, PostEffect, PostContext, PostImage, History, SCENE_FORMAT };
+
+mod cs {
+    vulkano_shaders::shader! { ty: "compute", path: "src/shaders/water.comp", include: ["src/shaders"] }
+}
+
+/// Waves past this many in either list are ignored.
+pub const MAX_WAVES: usize = 4;
+
+/// One sine travelling across the surface.
+#[derive(Clone, Copy, Debug)]
+pub struct Wave {
+    /// Along the water, xz.
+    pub direction: Vec2,
+    pub wavelength: f32,
+    pub amplitude: f32,
+    /// Meters per second.
+    pub speed: f32,
+}
+
+impl Wave {
+    fn packed(&self) -> [f32; 4] {
+        let k = self.direction.normalize_or_zero() * TAU / self.wavelength.max(1e-3);
+        [k.x, k.y, self.amplitude, self.speed]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WaterReflections {
+    /// The sky or the scene's environment map only.
+    Environment,
+    /// The scene rendered again mirrored about the water's level, see `PlanarReflection`. Exact
+    /// but costs a second scene pass.
+    Planar,
+    /// Whatever is on screen along the reflected ray, the environment elsewhere.
+    ScreenSpace { max_distance: f32, thickness: f32 },
+}
+
+/// A water surface at `level`, drawn over the scene after it rendered: the view ray is
+/// intersected with the waves, and what lies under the surface is refracted and absorbed
+/// by the depth of water in between. The surface writes no depth, so effects after it still
+/// see the bottom.
+#[derive(Clone, Debug)]
+pub struct Water {
+    pub level: f32,
+    /// Min and max corner of the xz rectangle it covers; None reaches the horizon.
+    pub bounds: Option<[Vec2; 2]>,
+    /// Displace the surface, so they show up in its silhouette against the shore.
+    pub waves: Vec<Wave>,
+    /// Only bend the normals; small and fast.
+    pub ripples: Vec<Wave>,
+    pub reflections: WaterReflections,
+    /// Per meter looked through, by channel. Red going first turns deep water blue green.
+    pub absorption: Vec3,
+    /// What deep water looks like, multiplied by the sky's light.
+    pub scatter: Vec3,
+    /// How far the bottom shifts in uv with the surface's slope.
+    pub refraction: f32,
+    pub roughness: f32,
+    pub foam_color: Vec3,
+    /// Meters of depth over which shoreline foam fades out; 0 for none.
+    pub foam_width: f32,
+}
+
+impl Default for Water {
+    fn default() -> Self {
+        let wave = |x: f32, z: f32, wavelength: f32, amplitude: f32, speed: f32| Wave { direction: Vec2::new(x, z), wavelength, amplitude, speed };
+        Water { level: 0.0, bounds: None,
+                waves: vec![wave(1.0, 0.3, 8.0, 0.08, 1.6), wave(-0.4, 1.0, 5.0, 0.05, 1.2), wave(0.7, -0.8, 3.0, 0.03, 1.0), wave(-1.0, -0.2, 1.7, 0.015, 0.8)],
+                ripples: vec![wave(0.8, 0.6, 0.6, 0.01, 0.5), wave(-0.6, 0.9, 0.35, 0.006, 0.4)],
+                reflections: WaterReflections::ScreenSpace { max_distance: 30.0, thickness: 0.5 },
+                absorption: Vec3::new(0.45, 0.09, 0.06), scatter: Vec3::new(0.01, 0.05, 0.06), refraction: 0.03, roughness: 0.08,
+                foam_color: Vec3::splat(0.9), foam_width: 0.3 }
+    }
+}
+
+/// Offscreen view mirrored about the water's level, for `WaterReflections::Planar`. Half
+/// resolution, the waves blur it anyway.
+pub struct PlanarReflection {
+    target: Option<(Target, PostImage)>,
+}
+
+impl PlanarReflection {
+    pub fn new() -> Self { PlanarReflection { target: None } }
+
+    /// Renders the reflection if the scene's water asks for one and `view` is above it. Recorded
+    /// outside any render pass; the result goes to `PostContext::water_reflection`.
+    #[profiling::function]
+    pub fn render(&mut self, renderer: &Renderer, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, scene: &Scene, view: &View,
+                  extent: [u32; 2]) -> Option<PostImage> {
+        let water = scene.water.as_ref().filter(|w| w.reflections == WaterReflections::Planar)?;
+        let surface = Mat4::from_translation(Vec3::Y * water.level) * Mat4::from_rotation_x(-FRAC_PI_2);
+        let mirrored = Portal::Mirror.view(&surface, view)?;
+        let extent = [(extent[0] / 2).max(1), (extent[1] / 2).max(1)];
+        if self.target.as_ref().map_or(true, |(t, _)| t.extent != extent) { self.target = Some(renderer.offscreen_target(extent)); }
+        let (target, color) = self.target.as_ref().unwrap();
+        let frustum = Frustum::from_view_proj(&mirrored.view_proj());
+        renderer.draw(builder, target, scene, &mirrored, &|o| o.portal.is_none() && frustum.intersects_aabb(&o.world_bounds()), &HashMap::new(), None);
+        Some(color.clone())
+    }
+}
+
+/// Runs while `scene.water` is set, first in the post stack so temporal and lens effects
+/// treat the water like the rest of the scene.
+pub struct WaterPass {
+    pipeline: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+    environment_sampler: Arc<Sampler>,
+    uniforms: CpuBufferPool<cs::ty::Water>,
+    /// Reflected while the scene has no environment of its own.
+    no_environment: Arc<Environment>,
+}
+
+impl WaterPass {
+    pub fn new(dev: Arc<Device>, no_environment: Arc<Environment>) -> Self {
+        let pipeline = ComputePipeline::new(dev.clone(), cs::load(dev.clone()).unwrap().entry_point("main").unwrap(), &(), None, |_| {}).unwrap();
+        let sampler = Sampler::new(dev.clone(), SamplerCreateInfo { mag_filter: Filter::Linear, min_filter: Filter::Linear,
+                                                                    address_mode: [SamplerAddressMode::ClampToEdge; 3], ..Default::default() }).unwrap();
+        let environment_sampler = Sampler::new(dev.clone(), SamplerCreateInfo::simple_repeat_linear()).unwrap();
+        WaterPass { pipeline, sampler, environment_sampler, uniforms: CpuBufferPool::uniform_buffer(dev), no_environment }
+    }
+}
+
+fn pack(waves: &[Wave]) -> [[f32; 4]; MAX_WAVES] {
+    let mut packed = [[0.0; 4]; MAX_WAVES];
+    for (p, w) in packed.iter_mut().zip(waves) { *p = w.packed(); }
+    packed
+}
+
+impl PostEffect for WaterPass {
+    fn name(&self) -> &'static str { "water" }
+    fn enabled(&self, ctx: &PostContext) -> bool { ctx.scene.water.is_some() }
+    fn output_format(&self, _input: Format) -> Format { SCENE_FORMAT }
+
+    fn record(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, ctx: &PostContext, input: &PostImage, output: &PostImage,
+              _history: Option<&History>) {
+        let water = ctx.scene.water.as_ref().unwrap();
+        let env = ctx.scene.environment.as_ref().unwrap_or(&self.no_environment);
+        let eye = ctx.view.eye();
+        let view_proj = ctx.view.view_proj();
+        let (reflections, reflection, ray) = match (water.reflections, &ctx.water_reflection) {
+            (WaterReflections::Planar, Some(image)) => (1.0, image.clone(), [0.0; 2]),
+            (WaterReflections::ScreenSpace { max_distance, thickness }, _) => (2.0, input.clone(), [max_distance, thickness]),
+            _ => (0.0, input.clone(), [0.0; 2]),
+        };
+        let (sun_direction, sun_radiance) = match SceneLights::gather(ctx.scene, eye).sun {
+            Some((direction, radiance)) => ((-direction).extend(0.0), radiance.extend(1.0)),
+            None => (Vec3::Y.extend(0.0), Vec3::ZERO.extend(0.0)),
+        };
+        let bounds = water.bounds.map_or([1.0, 1.0, -1.0, -1.0], |[min, max]| [min.x, min.y, max.x, max.y]);
+        let amplitude: f32 = water.waves.iter().take(MAX_WAVES).map(|w| w.amplitude.abs()).sum();
+        let uniforms = self.uniforms.next(cs::ty::Water {
+            view_proj: view_proj.to_cols_array_2d(),
+            inv_view_proj: view_proj.inverse().to_cols_array_2d(),
+            eye: eye.extend(ctx.time.elapsed as f32).into(),
+            surface: [water.level, amplitude, reflections, env.mip_levels.saturating_sub(1) as f32],
+            bounds,
+            absorption: water.absorption.extend(water.refraction).into(),
+            scatter: water.scatter.extend(water.roughness).into(),
+            foam: water.foam_color.extend(water.foam_width).into(),
+            sun_direction: sun_direction.into(),
+            sun_radiance: sun_radiance.into(),
+            depth: [ctx.view.near, ctx.view.far, ray[0], ray[1]],
+            waves: pack(&water.waves),
+            ripples: pack(&water.ripples),
+        }).unwrap();
+        let set = PersistentDescriptorSet::new(self.pipeline.layout().set_layouts().get(0).unwrap().clone(), [
+            WriteDescriptorSet::image_view_sampler(0, input.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler(1, ctx.target.depth.clone(), self.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler(2, reflection, self.sampler.clone()),
+            WriteDescriptorSet::image_view_sampler(3, env.prefiltered.clone(), self.environment_sampler.clone()),
+            WriteDescriptorSet::image_view(4, output.clone()),
+            WriteDescriptorSet::buffer(5, uniforms),
+        ]).unwrap();
+        let extent = postfx::extent(output);
+        builder.bind_pipeline_compute(self.pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.pipeline.layout().clone(), 0, set)
+            .dispatch([(extent[0] + 7) / 8, (extent[1] + 7) / 8, 1]).unwrap();
+    }
+}